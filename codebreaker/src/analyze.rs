@@ -1,10 +1,18 @@
 use anyhow::Result;
-use hypercube::cube::{analyze_data, CubeConfig};
+use hypercube::cube::{
+    analyze_data, block_layout, recommend_block_size, BlockLayout, CubeConfig, CubePreset,
+    CubeRegistry,
+};
 use hypercube::header::Compression;
 use std::path::Path;
 
-/// Suggest a Hypercube configuration for an input file.
-pub fn analyze_file(path: &Path, compression: Compression, dimension: usize) -> Result<String> {
+/// Suggest a Hypercube configuration and block size for an input file.
+pub fn analyze_file(
+    path: &Path,
+    compression: Compression,
+    dimension: usize,
+    mac_bits: usize,
+) -> Result<String> {
     let data = std::fs::read(path)?;
     let cube = CubeConfig::hypercube(dimension);
     let analysis = analyze_data(&data, compression, cube)?;
@@ -36,7 +44,7 @@ pub fn analyze_file(path: &Path, compression: Compression, dimension: usize) ->
         analysis.block_size_bytes * 8
     ));
     output.push_str(&format!(
-        "Per-compartment capacity: {}\n",
+        "Per-partition capacity: {}\n",
         format_size(analysis.capacity_bytes as u64)
     ));
     output.push_str(&format!(
@@ -44,9 +52,93 @@ pub fn analyze_file(path: &Path, compression: Compression, dimension: usize) ->
         format_size(analysis.headroom_bytes() as u64)
     ));
 
+    // Padding waste against per-block overhead, for a few cube sizes
+    let payload = analysis.payload_bytes;
+    let recommended = recommend_block_size(payload, mac_bits);
+    let mut dimensions = vec![8, 16, 32, 64, 128, 256, dimension, recommended.dimension];
+    dimensions.sort_unstable();
+    dimensions.dedup();
+    output.push_str(&format!(
+        "\nBlock size trade-off ({}-bit MAC, {} B per block):\n",
+        mac_bits,
+        16 + mac_bits / 8
+    ));
+    output.push_str("  Dimension   Block   Overhead    Padding  Partition on disk\n");
+    for dimension in dimensions {
+        let layout = block_layout(payload, dimension, mac_bits);
+        output.push_str(&layout_row(&layout, payload, layout == recommended));
+    }
+    output.push_str(&format!(
+        "\nRecommended: dimension {} with {}-byte blocks ({} per partition)\n",
+        recommended.dimension,
+        recommended.block_size,
+        format_size(recommended.partition_bytes(payload) as u64)
+    ));
+    output.push_str(&format!(
+        "  hypercube add --partitions {} --blocks-per-partition {} --block-size {} {}\n\n",
+        recommended.dimension,
+        recommended.dimension,
+        recommended.block_size,
+        path.display()
+    ));
+    output.push_str(
+        "  Every block pays a 16 B sequence and a MAC, so many small blocks add overhead;\n\
+         \x20 few large blocks waste more padding and spend a whole block on the AONT key.\n\
+         \x20 The dimension above is blocks per partition. The partition count does not change\n\
+         \x20 a partition's size, only how many a sealed file holds: set it with --partitions.\n\
+         \x20 `hypercube add --apply-recommendation` sizes a new file the same way.\n",
+    );
+
+    // What the built-in presets hold, and whether this input fits each
+    output.push_str("\nCube presets (hypercube add --cube NAME):\n");
+    output.push_str(&format!(
+        "  {:>6}  {:<12} {:>7} {:>8} {:>16} {:>12}\n",
+        "Preset", "Description", "Cube", "Block", "Partition holds", "Sealed size"
+    ));
+    for (name, preset) in CubeRegistry::builtin().iter() {
+        output.push_str(&preset_row(name, preset, analysis.compressed_bytes));
+    }
+
     Ok(output)
 }
 
+/// One preset's shape and capacity; `compressed` is the input's compressed size
+fn preset_row(name: &str, preset: &CubePreset, compressed: usize) -> String {
+    let shape = format!("{}×{}", preset.dimension, preset.dimension);
+    let (block, capacity, sealed, fits) = match preset.partition_capacity() {
+        Some(capacity) => (
+            format_size(preset.block_size.unwrap_or_default() as u64),
+            format_size(capacity as u64),
+            format_size(preset.sealed_bytes().unwrap_or_default()),
+            compressed <= capacity,
+        ),
+        // Blocks are sized to the first input, so anything fits
+        None => ("input".into(), "any".into(), "-".into(), true),
+    };
+    format!(
+        "  {:>6}  {:<12} {:>7} {:>8} {:>16} {:>12}{}\n",
+        name,
+        preset.description.as_deref().unwrap_or(""),
+        shape,
+        block,
+        capacity,
+        sealed,
+        if fits { "" } else { "  too small" }
+    )
+}
+
+fn layout_row(layout: &BlockLayout, payload: usize, recommended: bool) -> String {
+    format!(
+        "  {:>9} {:>7} {:>10} {:>10} {:>18}{}\n",
+        layout.dimension,
+        layout.block_size,
+        format_size(layout.overhead_bytes as u64),
+        format_size(layout.padding_bytes as u64),
+        format_size(layout.partition_bytes(payload) as u64),
+        if recommended { "  <- recommended" } else { "" }
+    )
+}
+
 fn format_size(bytes: u64) -> String {
     if bytes < 1024 {
         format!("{} B", bytes)
@@ -69,7 +161,18 @@ mod tests {
         let dir = tempdir().unwrap();
         let input = dir.path().join("data.bin");
         std::fs::write(&input, b"hello world").unwrap();
-        let report = analyze_file(&input, Compression::Zstd, 32).unwrap();
+        let report = analyze_file(&input, Compression::Zstd, 32, 256).unwrap();
         assert!(report.contains("Cube 32")); // dimension = 32, now shows "partitions"
+        assert!(report.contains("Recommended: dimension 8 with 32-byte blocks"));
+        assert!(report.contains("<- recommended"));
+        // Every built-in preset is listed; this input fits even the smallest
+        assert!(report.contains("Cube presets"));
+        assert!(report.contains("small"));
+        assert!(report.contains("7.5 KB"));
+        assert!(!report.contains("too small"));
+        assert!(report.contains(&format!(
+            "hypercube add --partitions 8 --blocks-per-partition 8 --block-size 32 {}",
+            input.display()
+        )));
     }
 }
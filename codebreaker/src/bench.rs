@@ -0,0 +1,207 @@
+use crate::stats::{statistics, Complexity, Statistic};
+use anyhow::{bail, Result};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use std::time::{Duration, Instant};
+
+pub struct BenchOptions {
+    /// Sample sizes in bytes
+    pub sizes: Vec<usize>,
+    /// Largest sample fed to O(n²) statistics; bigger sizes are extrapolated
+    pub quadratic_limit: usize,
+    /// Input size the timings are projected onto (e.g. a whole container)
+    pub project_bytes: u64,
+    /// Seed for the random sample data
+    pub seed: u64,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            sizes: vec![256, 4096, 65536],
+            quadratic_limit: 16384,
+            project_bytes: 1 << 30,
+            seed: 0,
+        }
+    }
+}
+
+/// Keep repeating small runs until this much time has elapsed
+const MIN_SAMPLE_TIME: Duration = Duration::from_millis(20);
+const MAX_REPEATS: u32 = 1000;
+
+/// Time every dashboard statistic on random samples of each size.
+pub fn run(options: &BenchOptions) -> Result<String> {
+    if options.sizes.is_empty() || options.sizes.contains(&0) {
+        bail!("Sample sizes must be positive");
+    }
+
+    let mut sizes = options.sizes.clone();
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut sample = vec![0u8; *sizes.last().unwrap()];
+    rng.fill_bytes(&mut sample);
+
+    let mut output = String::new();
+    output.push_str("Codebreaker Statistic Benchmark\n");
+    output.push_str("===============================\n\n");
+    output.push_str(&format!(
+        "Sample sizes: {}\n",
+        sizes
+            .iter()
+            .map(|&s| format_size(s as u64))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    output.push_str(&format!(
+        "Projection target: {}\n\n",
+        format_size(options.project_bytes)
+    ));
+
+    let name_width = statistics()
+        .iter()
+        .map(|s| s.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    output.push_str(&format!("{:<name_width$}  {:<10}", "Statistic", "Cost"));
+    for &size in &sizes {
+        output.push_str(&format!("  {:>10}", format_size(size as u64)));
+    }
+    output.push_str(&format!(
+        "  {:>12}\n",
+        format!("@ {}", format_size(options.project_bytes))
+    ));
+
+    for stat in statistics() {
+        let mut measured: Option<(usize, Duration)> = None;
+        output.push_str(&format!(
+            "{:<name_width$}  {:<10}",
+            stat.name,
+            stat.complexity.label()
+        ));
+        for &size in &sizes {
+            if stat.complexity == Complexity::Quadratic && size > options.quadratic_limit {
+                output.push_str(&format!("  {:>10}", "skipped"));
+                continue;
+            }
+            let elapsed = time_statistic(&stat, &sample[..size]);
+            measured = Some((size, elapsed));
+            output.push_str(&format!("  {:>10}", format_duration(elapsed)));
+        }
+        let projected = measured
+            .map(|(size, elapsed)| {
+                format_duration(project(
+                    stat.complexity,
+                    size,
+                    elapsed,
+                    options.project_bytes,
+                ))
+            })
+            .unwrap_or_else(|| "n/a".to_string());
+        output.push_str(&format!("  {:>12}\n", projected));
+    }
+
+    output.push_str(
+        "\nProjections extrapolate the largest measured size by each statistic's cost class.\n",
+    );
+    output.push_str(
+        "O(n²) statistics are only practical per block; run them with `stats --block N`.\n",
+    );
+
+    Ok(output)
+}
+
+/// Average wall time of one run of the statistic
+fn time_statistic(stat: &Statistic, data: &[u8]) -> Duration {
+    let start = Instant::now();
+    let mut repeats = 0u32;
+    while repeats == 0 || (start.elapsed() < MIN_SAMPLE_TIME && repeats < MAX_REPEATS) {
+        (stat.run)(data);
+        repeats += 1;
+    }
+    start.elapsed() / repeats
+}
+
+/// Scale a measured timing from `size` bytes to `target` bytes
+fn project(complexity: Complexity, size: usize, elapsed: Duration, target: u64) -> Duration {
+    let target = usize::try_from(target).unwrap_or(usize::MAX);
+    let ratio = complexity.cost(target) / complexity.cost(size);
+    Duration::try_from_secs_f64(elapsed.as_secs_f64() * ratio).unwrap_or(Duration::MAX)
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs < 1e-3 {
+        format!("{:.1} µs", secs * 1e6)
+    } else if secs < 1.0 {
+        format!("{:.1} ms", secs * 1e3)
+    } else if secs < 120.0 {
+        format!("{:.1} s", secs)
+    } else if secs < 7200.0 {
+        format!("{:.1} min", secs / 60.0)
+    } else if secs < 172800.0 {
+        format!("{:.1} h", secs / 3600.0)
+    } else {
+        format!("{:.1} days", secs / 86400.0)
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.0} KB", bytes as f64 / 1024.0)
+    } else if bytes < 1024 * 1024 * 1024 {
+        format!("{:.0} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.0} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_lists_every_statistic() {
+        let options = BenchOptions {
+            sizes: vec![64, 128],
+            quadratic_limit: 64,
+            ..Default::default()
+        };
+        let report = run(&options).unwrap();
+        for stat in statistics() {
+            assert!(report.contains(stat.name), "missing {}", stat.name);
+        }
+        assert!(report.contains("skipped"));
+    }
+
+    #[test]
+    fn test_projection_scales_by_complexity() {
+        let second = Duration::from_secs(1);
+        assert_eq!(
+            project(Complexity::Linear, 10, second, 100),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            project(Complexity::Quadratic, 10, second, 100),
+            Duration::from_secs(100)
+        );
+        // Projections past what a Duration holds saturate rather than panic
+        assert_eq!(
+            project(Complexity::Quadratic, 64, second, 10_995_116_277_760),
+            Duration::MAX
+        );
+        assert_eq!(project(Complexity::Quadratic, 1, second, u64::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn test_rejects_empty_sizes() {
+        let options = BenchOptions {
+            sizes: vec![],
+            ..Default::default()
+        };
+        assert!(run(&options).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::stats::{append_block_stats, population_report};
+use anyhow::{anyhow, bail, Result};
+use hypercube::partition::find_partition_blocks;
+use hypercube::vhc::VhcReader;
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Bytes of chaff, from randomly chosen blocks, fed to the single-sample dashboard
+const CHAFF_SAMPLE_BYTES: usize = 64 * 1024;
+
+#[derive(Default)]
+pub struct ChaffOptions {
+    /// Secrets of every real partition; their blocks are left out
+    pub secrets: Vec<String>,
+    /// Chaff block pairs sampled for linkability
+    pub pairs: usize,
+    /// Seed for pair sampling and the block sample (reproducible reports)
+    pub seed: Option<u64>,
+}
+
+/// Run the statistical battery on the blocks no given secret authenticates.
+/// With every partition's secret given, what remains is the container's chaff.
+pub fn run(path: &Path, options: &ChaffOptions) -> Result<String> {
+    if options.secrets.is_empty() {
+        bail!("Give the secret of every real partition with --secret");
+    }
+    let mut rng = match options.seed {
+        Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+        None => ChaCha20Rng::from_entropy(),
+    };
+    let vhc = VhcReader::open(path)?;
+    let header = vhc.header();
+    if header.dedup {
+        // Shared chunks are keyed by their content, not by any partition secret
+        bail!("Deduplicating containers are not supported: shared chunks would count as chaff");
+    }
+    let blocks: Vec<&[u8]> = vhc.blocks().collect();
+    if blocks.is_empty() {
+        return Err(anyhow!("No blocks in {}", path.display()));
+    }
+
+    let mut payload_blocks = BTreeSet::new();
+    for (i, secret) in options.secrets.iter().enumerate() {
+        let key = header.partition_key(secret.as_bytes())?;
+        let positions = find_partition_blocks(&blocks, &key, header);
+        if positions.is_empty() {
+            bail!("Secret #{} authenticates no blocks", i + 1);
+        }
+        payload_blocks.extend(positions);
+    }
+    let mut chaff: Vec<&[u8]> = vhc
+        .block_refs()
+        .filter(|block| !payload_blocks.contains(&block.index))
+        .map(|block| block.payload)
+        .collect();
+    if chaff.is_empty() {
+        bail!("Every block belongs to a partition; there is no chaff to test");
+    }
+
+    let mut output = String::new();
+    output.push_str("Hypercube Chaff Cryptanalysis\n");
+    output.push_str("=============================\n\n");
+    output.push_str(&format!("File: {}\n", path.display()));
+    output.push_str(&format!(
+        "Blocks: {} total, {} in {} partition(s), {} chaff\n",
+        blocks.len(),
+        payload_blocks.len(),
+        options.secrets.len(),
+        chaff.len()
+    ));
+    output.push_str(&format!(
+        "Block size: {} bytes (data only, excluding 16B seq + {}B MAC)\n\n",
+        header.block_size,
+        header.mac_bytes()
+    ));
+    output.push_str(&population_report(&chaff, options.pairs, &mut rng));
+
+    // The full dashboard on a sample of chaff, as one stream
+    chaff.shuffle(&mut rng);
+    let sample: Vec<u8> = chaff
+        .iter()
+        .flat_map(|payload| payload.iter().copied())
+        .take(CHAFF_SAMPLE_BYTES)
+        .collect();
+    output.push_str(&format!(
+        "\nChaff sample: {} bytes from randomly chosen blocks\n\n",
+        sample.len()
+    ));
+    append_block_stats(&mut output, &sample)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hypercube::cli::{add_partition, AddOptions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_chaff_excludes_partitions() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let path = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"real partition data").unwrap();
+        for secret in ["alpha", "beta"] {
+            let options = AddOptions {
+                secret: secret.into(),
+                dimension: 8,
+                kdf: None,
+                seal: secret == "beta",
+                ..Default::default()
+            };
+            add_partition(&input, &path, &options).unwrap();
+        }
+
+        let options = ChaffOptions {
+            secrets: vec!["alpha".into(), "beta".into()],
+            pairs: 16,
+            seed: Some(1),
+        };
+        let report = run(&path, &options).unwrap();
+        assert!(report.contains("Blocks: 64 total, 16 in 2 partition(s), 48 chaff"));
+        assert!(report.contains("Second-Order Uniformity"));
+        assert!(report.contains("Chaff sample:"));
+
+        // A secret that matches nothing, or none at all, is a mistake
+        let wrong = ChaffOptions {
+            secrets: vec!["alpha".into(), "gamma".into()],
+            ..Default::default()
+        };
+        let err = run(&path, &wrong).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Secret #2 authenticates no blocks"));
+        assert!(run(&path, &ChaffOptions::default()).is_err());
+    }
+}
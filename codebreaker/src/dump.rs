@@ -0,0 +1,213 @@
+use crate::stats::hexdump;
+use anyhow::{bail, Result};
+use hypercube::vhc::VhcReader;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub struct DumpOptions {
+    /// Block index to dump from (VHC containers)
+    pub block: Option<usize>,
+    /// Start offset within the stored block (sequence + data + MAC)
+    pub offset: usize,
+    /// Number of bytes to dump
+    pub len: usize,
+    /// Dump from this absolute file offset instead of a block
+    pub raw_offset: Option<u64>,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            block: None,
+            offset: 0,
+            len: 256,
+            raw_offset: None,
+        }
+    }
+}
+
+/// Hexdump a chosen block region or raw byte range of a file.
+pub fn run(path: &Path, options: &DumpOptions) -> Result<String> {
+    if options.len == 0 {
+        bail!("Dump length must be positive");
+    }
+    match (options.block, options.raw_offset) {
+        (Some(block), None) => dump_block(path, block, options.offset, options.len),
+        (None, Some(raw_offset)) => dump_raw(path, raw_offset, options.len),
+        (Some(_), Some(_)) => bail!("--block and --raw-offset are mutually exclusive"),
+        (None, None) => bail!("Specify --block N or --raw-offset N"),
+    }
+}
+
+fn dump_block(path: &Path, block: usize, offset: usize, len: usize) -> Result<String> {
+    let vhc = VhcReader::open(path)?;
+    let Some(fields) = vhc.block_ref(block) else {
+        let count = vhc.block_count();
+        if count == 0 {
+            bail!("No blocks in {}", path.display());
+        }
+        bail!("Block index {} out of bounds (0..{})", block, count - 1);
+    };
+    let data = vhc.block(block).unwrap_or_default();
+    if offset >= data.len() {
+        bail!(
+            "Offset {} is past the end of block {} ({} bytes)",
+            offset,
+            block,
+            data.len()
+        );
+    }
+    let end = offset.saturating_add(len).min(data.len());
+    let mac_start = fields.mac_offset();
+
+    let mut output = String::new();
+    output.push_str("Hypercube Block Dump\n");
+    output.push_str("====================\n\n");
+    output.push_str(&format!("File: {}\n", path.display()));
+    output.push_str(&format!(
+        "Block: {} ({} bytes at file offset 0x{:X})\n",
+        block,
+        data.len(),
+        fields.offset
+    ));
+    output.push_str(&format!(
+        "Layout: sequence 0x0000-0x{:04X}, data 0x{:04X}-0x{:04X}, MAC 0x{:04X}-0x{:04X}\n",
+        fields.sequence.len() - 1,
+        fields.sequence.len(),
+        mac_start - 1,
+        mac_start,
+        data.len() - 1
+    ));
+    output.push_str(&format!(
+        "Range: 0x{:04X}-0x{:04X} ({} bytes)\n\n",
+        offset,
+        end - 1,
+        end - offset
+    ));
+    output.push_str(&hexdump(&data[offset..end], offset));
+    Ok(output)
+}
+
+fn dump_raw(path: &Path, raw_offset: u64, len: usize) -> Result<String> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    if raw_offset >= file_len {
+        bail!(
+            "Offset {} is past the end of {} ({} bytes)",
+            raw_offset,
+            path.display(),
+            file_len
+        );
+    }
+    file.seek(SeekFrom::Start(raw_offset))?;
+    let len = (len as u64).min(file_len - raw_offset);
+    let mut data = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut data)?;
+
+    let mut output = String::new();
+    output.push_str("Raw Byte Dump\n");
+    output.push_str("=============\n\n");
+    output.push_str(&format!("File: {} ({} bytes)\n", path.display(), file_len));
+    output.push_str(&format!(
+        "Range: 0x{:X}-0x{:X} ({} bytes)\n\n",
+        raw_offset,
+        raw_offset + data.len() as u64 - 1,
+        data.len()
+    ));
+    output.push_str(&hexdump(&data, raw_offset as usize));
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hypercube::header::VhcHeader;
+    use hypercube::vhc::{write_vhc_file, VhcFile};
+    use tempfile::tempdir;
+
+    fn write_container(path: &Path) -> usize {
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let block_size = header.total_block_size();
+        let mut vhc = VhcFile::new(header);
+        vhc.add_blocks((0..4u8).map(|i| vec![i; block_size]).collect());
+        write_vhc_file(path, &vhc).unwrap();
+        block_size
+    }
+
+    #[test]
+    fn test_dump_block_region() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("vault.vhc");
+        write_container(&path);
+
+        let options = DumpOptions {
+            block: Some(2),
+            offset: 16,
+            len: 32,
+            ..Default::default()
+        };
+        let report = run(&path, &options).unwrap();
+        assert!(report.contains("Block: 2"));
+        assert!(report.contains("00000010  02 02"));
+        assert!(report.contains("00000020  02 02"));
+        assert!(!report.contains("00000030"));
+    }
+
+    #[test]
+    fn test_dump_clamps_to_block_end() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("vault.vhc");
+        let block_size = write_container(&path);
+
+        let options = DumpOptions {
+            block: Some(0),
+            offset: block_size - 8,
+            len: 512,
+            ..Default::default()
+        };
+        let report = run(&path, &options).unwrap();
+        assert!(report.contains("(8 bytes)"));
+        let huge = DumpOptions {
+            len: usize::MAX,
+            ..options
+        };
+        assert!(run(&path, &huge).unwrap().contains("(8 bytes)"));
+
+        let past_end = DumpOptions {
+            block: Some(0),
+            offset: block_size,
+            ..Default::default()
+        };
+        assert!(run(&path, &past_end).is_err());
+        let missing = DumpOptions {
+            block: Some(4),
+            ..Default::default()
+        };
+        assert!(run(&path, &missing).is_err());
+    }
+
+    #[test]
+    fn test_dump_raw_range() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, b"0123456789abcdefHello, World!").unwrap();
+
+        let options = DumpOptions {
+            raw_offset: Some(16),
+            len: 5,
+            ..Default::default()
+        };
+        let report = run(&path, &options).unwrap();
+        assert!(report.contains("00000010  48 65 6C 6C 6F"));
+        assert!(report.contains("|Hello|"));
+        // A length past the end of the file reads to the end, without allocating for it
+        let huge = DumpOptions {
+            raw_offset: Some(16),
+            len: usize::MAX,
+            ..Default::default()
+        };
+        assert!(run(&path, &huge).unwrap().contains("(13 bytes)"));
+        assert!(run(&path, &DumpOptions::default()).is_err());
+    }
+}
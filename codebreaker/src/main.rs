@@ -1,8 +1,15 @@
 mod analyze;
+mod bench;
+mod chaff;
+mod dump;
+mod prng;
 mod stats;
 
 use analyze::analyze_file;
+use bench::{run as run_bench, BenchOptions};
+use chaff::{run as run_chaff, ChaffOptions};
 use clap::{Parser, Subcommand};
+use dump::{run as run_dump, DumpOptions};
 use hypercube::header::Compression;
 use stats::{run as run_stats, StatsOptions};
 use std::path::PathBuf;
@@ -29,6 +36,10 @@ enum Commands {
         /// Hypercube dimension (N×N blocks, must be multiple of 8)
         #[arg(long, default_value_t = 32)]
         dimension: usize,
+
+        /// MAC size in bits, for the block size recommendation
+        #[arg(long, default_value_t = 256)]
+        mac_bits: usize,
     },
 
     /// Run cryptanalysis on a VHC block or raw file
@@ -43,6 +54,85 @@ enum Commands {
         /// Treat input as raw bytes instead of a VHC container
         #[arg(long)]
         raw: bool,
+
+        /// Test every block and check the per-block p-values for uniformity
+        #[arg(long, conflicts_with_all = ["block", "raw"])]
+        all_blocks: bool,
+
+        /// Test whether the file can be told apart from random bytes
+        #[arg(long, conflicts_with_all = ["block", "raw", "all_blocks"])]
+        distinguish: bool,
+
+        /// Test whether sequence fields set some blocks (such as chaff) apart
+        #[arg(long, conflicts_with_all = ["block", "raw", "all_blocks", "distinguish"])]
+        sequences: bool,
+
+        /// Block pairs sampled for inter-block linkability (with --all-blocks)
+        #[arg(long, default_value_t = 256, requires = "all_blocks")]
+        pairs: usize,
+
+        /// Seed for random block selection, so a report can be regenerated
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+
+    /// Run the statistics on a container's chaff alone, leaving out every partition
+    ChaffStats {
+        /// VHC container to analyze
+        file: PathBuf,
+
+        /// Secret of a real partition (repeat for each one)
+        #[arg(long = "secret", value_name = "SECRET", required = true)]
+        secrets: Vec<String>,
+
+        /// Chaff block pairs sampled for inter-block linkability
+        #[arg(long, default_value_t = 256)]
+        pairs: usize,
+
+        /// Seed for pair sampling and the byte sample, so a report can be regenerated
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+
+    /// Hexdump a block region or raw byte range
+    Dump {
+        /// File to dump (VHC container for --block, any file for --raw-offset)
+        file: PathBuf,
+
+        /// Block index to dump from
+        #[arg(long, required_unless_present = "raw_offset")]
+        block: Option<usize>,
+
+        /// Start offset within the block (sequence + data + MAC)
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Number of bytes to dump
+        #[arg(long, default_value_t = 256)]
+        len: usize,
+
+        /// Dump from an absolute file offset instead of a block
+        #[arg(long, conflicts_with_all = ["block", "offset"])]
+        raw_offset: Option<u64>,
+    },
+
+    /// Time each statistic to see which scale to whole containers
+    Bench {
+        /// Sample sizes in bytes (comma separated)
+        #[arg(long, value_delimiter = ',', default_value = "256,4096,65536")]
+        sizes: Vec<usize>,
+
+        /// Largest sample fed to O(n²) statistics
+        #[arg(long, default_value_t = 16384)]
+        quadratic_limit: usize,
+
+        /// Input size to project timings onto, in bytes (default 1 GiB)
+        #[arg(long, default_value_t = 1 << 30)]
+        project: u64,
+
+        /// Seed for the random sample data
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
     },
 }
 
@@ -57,15 +147,78 @@ fn main() -> anyhow::Result<()> {
             file,
             compression,
             dimension,
+            mac_bits,
         } => {
-            let report = analyze_file(&file, compression, dimension)?;
+            let report = analyze_file(&file, compression, dimension, mac_bits)?;
             print!("{}", report);
         }
-        Commands::Stats { file, block, raw } => {
-            let options = StatsOptions { raw, block };
+        Commands::Stats {
+            file,
+            block,
+            raw,
+            all_blocks,
+            distinguish,
+            sequences,
+            pairs,
+            seed,
+        } => {
+            let options = StatsOptions {
+                raw,
+                block,
+                all_blocks,
+                pairs,
+                seed,
+                distinguish,
+                sequences,
+            };
             let report = run_stats(&file, &options)?;
             print!("{}", report);
         }
+        Commands::ChaffStats {
+            file,
+            secrets,
+            pairs,
+            seed,
+        } => {
+            let options = ChaffOptions {
+                secrets,
+                pairs,
+                seed,
+            };
+            let report = run_chaff(&file, &options)?;
+            print!("{}", report);
+        }
+        Commands::Dump {
+            file,
+            block,
+            offset,
+            len,
+            raw_offset,
+        } => {
+            let options = DumpOptions {
+                block,
+                offset,
+                len,
+                raw_offset,
+            };
+            let report = run_dump(&file, &options)?;
+            print!("{}", report);
+        }
+        Commands::Bench {
+            sizes,
+            quadratic_limit,
+            project,
+            seed,
+        } => {
+            let options = BenchOptions {
+                sizes,
+                quadratic_limit,
+                project_bytes: project,
+                seed,
+            };
+            let report = run_bench(&options)?;
+            print!("{}", report);
+        }
     }
 
     Ok(())
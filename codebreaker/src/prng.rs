@@ -0,0 +1,282 @@
+//! Tests that try to fit simple generators to the data.
+//! Weak chaff (an LCG or xorshift seeded once) passes most entropy metrics
+//! but falls apart as soon as its algebraic structure is probed directly.
+
+use crate::stats::berlekamp_massey;
+use std::f64::consts::TAU;
+
+/// Consecutive words used to recover an LCG modulus
+const LCG_WORDS: usize = 64;
+
+/// Recovered moduli below this are indistinguishable from chance gcds
+const MIN_LCG_MODULUS: u64 = 1 << 16;
+
+/// Lowest word bits checked for short periods
+const LOW_BITS: u32 = 8;
+
+/// Tuples fed to the spectral test
+const SPECTRAL_TUPLES: usize = 1024;
+
+/// Largest coefficient searched per dimension in the spectral test
+const SPECTRAL_RANGE_2D: i64 = 32;
+const SPECTRAL_RANGE_3D: i64 = 9;
+
+/// Words per bit lane fed to Berlekamp–Massey (quadratic cost)
+const LANE_WORDS: usize = 512;
+
+/// Split data into little-endian 32-bit words, dropping a trailing partial word
+pub fn words(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+pub struct LcgFit {
+    /// Modulus (or a small multiple of it) recovered from the words, if any
+    pub modulus: Option<u64>,
+    /// Number of low word bits with period ≤ 2^(bit+1)
+    pub periodic_low_bits: u32,
+    pub words: usize,
+}
+
+/// Try to recover an LCG x' = a·x + c (mod m) from consecutive 32-bit outputs.
+/// With t_n = x_{n+1} − x_n, every t_{n+2}·t_n − t_{n+1}² is a multiple of m,
+/// so their gcd converges on the modulus; random words give a tiny gcd.
+pub fn lcg_fit(data: &[u8]) -> LcgFit {
+    let words = words(data);
+    let sample = &words[..words.len().min(LCG_WORDS)];
+
+    let mut modulus = 0u128;
+    if sample.len() >= 6 {
+        let diffs: Vec<i128> = sample
+            .windows(2)
+            .map(|w| w[1] as i128 - w[0] as i128)
+            .collect();
+        for t in diffs.windows(3) {
+            let u = (t[2] * t[0] - t[1] * t[1]).unsigned_abs();
+            modulus = gcd_u128(modulus, u);
+        }
+    }
+    let max_word = sample.iter().copied().max().unwrap_or(0) as u128;
+    let modulus =
+        (modulus >= MIN_LCG_MODULUS as u128 && modulus > max_word && modulus <= u64::MAX as u128)
+            .then_some(modulus as u64);
+
+    // Power-of-two LCGs: bit k of each output has period dividing 2^(k+1)
+    let mut periodic_low_bits = 0;
+    for bit in 0..LOW_BITS {
+        let period = 2usize << bit;
+        if words.len() < 4 * period {
+            break;
+        }
+        let lane = |i: usize| (words[i] >> bit) & 1;
+        if (period..words.len()).all(|i| lane(i) == lane(i - period)) {
+            periodic_low_bits += 1;
+        } else {
+            break;
+        }
+    }
+
+    LcgFit {
+        modulus,
+        periodic_low_bits,
+        words: words.len(),
+    }
+}
+
+fn gcd_u128(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd_u128(b, a % b)
+    }
+}
+
+pub struct SpectralFit {
+    /// Dual-lattice vector h with the strongest Fourier coefficient
+    pub vector: Vec<i64>,
+    /// n·|ĉ(h)|², ≈Exp(1) per vector for random tuples
+    pub score: f64,
+    /// Chance of a score this large across all searched vectors
+    pub p_value: f64,
+    pub tuples: usize,
+}
+
+/// Empirical spectral test on overlapping `dim`-tuples of 32-bit words.
+/// Lattice generators put their tuples on a few parallel hyperplanes h·u ≡ k;
+/// the Fourier coefficient of the point set at such an h is then close to 1,
+/// while for random points it is O(1/√n).
+pub fn spectral_fit(data: &[u8], dim: usize) -> Option<SpectralFit> {
+    let words = words(data);
+    if words.len() < dim + 16 {
+        return None;
+    }
+    let range = if dim == 2 {
+        SPECTRAL_RANGE_2D
+    } else {
+        SPECTRAL_RANGE_3D
+    };
+    let tuples = (words.len() + 1 - dim).min(SPECTRAL_TUPLES);
+    let span = (2 * range + 1) as usize;
+
+    // powers[t][j][k] = exp(2πi·k·u_{t+j}) for k in -range..=range
+    let unit: Vec<f64> = words.iter().map(|&w| w as f64 / 4_294_967_296.0).collect();
+    let powers: Vec<Vec<(f64, f64)>> = unit[..tuples + dim - 1]
+        .iter()
+        .map(|&u| {
+            (-range..=range)
+                .map(|k| {
+                    let angle = TAU * k as f64 * u;
+                    (angle.cos(), angle.sin())
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut best = (Vec::new(), 0.0f64);
+    let mut searched = 0usize;
+    let mut h = vec![-range; dim];
+    loop {
+        // Only one of ±h is needed: keep vectors whose first non-zero entry is positive
+        if h.iter().find(|&&x| x != 0).is_some_and(|&x| x > 0) {
+            let (mut re, mut im) = (0.0, 0.0);
+            for t in 0..tuples {
+                let (mut pr, mut pi) = (1.0, 0.0);
+                for (j, &hj) in h.iter().enumerate() {
+                    let (cr, ci) = powers[t + j][(hj + range) as usize];
+                    (pr, pi) = (pr * cr - pi * ci, pr * ci + pi * cr);
+                }
+                re += pr;
+                im += pi;
+            }
+            let score = (re * re + im * im) / tuples as f64;
+            if score > best.1 {
+                best = (h.clone(), score);
+            }
+            searched += 1;
+        }
+
+        // Advance h like an odometer over [-range, range]^dim
+        let mut pos = 0;
+        while pos < dim && h[pos] == range {
+            h[pos] = -range;
+            pos += 1;
+        }
+        if pos == dim {
+            break;
+        }
+        h[pos] += 1;
+    }
+    debug_assert_eq!(searched, (span.pow(dim as u32) - 1) / 2);
+
+    let p_value = 1.0 - (1.0 - (-best.1).exp()).powf(searched as f64);
+    Some(SpectralFit {
+        vector: best.0,
+        score: best.1,
+        p_value: p_value.clamp(0.0, 1.0),
+        tuples,
+    })
+}
+
+pub struct XorshiftFit {
+    /// Smallest Berlekamp–Massey complexity over the 32 bit lanes
+    pub min_lane_complexity: usize,
+    /// Bit lane with that complexity
+    pub lane: u32,
+    pub words: usize,
+}
+
+/// Xorshift, LFSR and other GF(2)-linear generators make every bit lane of
+/// their output words obey a short linear recurrence (≤ state size), whereas
+/// random lanes have linear complexity ≈ n/2.
+pub fn xorshift_fit(data: &[u8]) -> Option<XorshiftFit> {
+    let words = words(data);
+    let words = &words[..words.len().min(LANE_WORDS)];
+    if words.len() < 64 {
+        return None;
+    }
+
+    (0..32u32)
+        .map(|lane| {
+            let bits: Vec<u8> = words.iter().map(|&w| ((w >> lane) & 1) as u8).collect();
+            (berlekamp_massey(&bits), lane)
+        })
+        .min()
+        .map(|(complexity, lane)| XorshiftFit {
+            min_lane_complexity: complexity,
+            lane,
+            words: words.len(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    fn random_data(len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        StdRng::seed_from_u64(3).fill_bytes(&mut data);
+        data
+    }
+
+    fn to_bytes(words: impl Iterator<Item = u32>) -> Vec<u8> {
+        words.flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    /// RANDU's multiplier mod 2^32: x_{n+2} = 6·x_{n+1} − 9·x_n, so 3D tuples lie on few planes
+    fn randu(count: usize) -> Vec<u8> {
+        let mut x = 1u32;
+        to_bytes((0..count).map(move |_| {
+            x = x.wrapping_mul(65539);
+            x
+        }))
+    }
+
+    fn xorshift32(count: usize) -> Vec<u8> {
+        let mut x = 2463534242u32;
+        to_bytes((0..count).map(move |_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x
+        }))
+    }
+
+    #[test]
+    fn test_random_data_fits_no_generator() {
+        let data = random_data(4096);
+        let lcg = lcg_fit(&data);
+        assert_eq!(lcg.modulus, None);
+        assert_eq!(lcg.periodic_low_bits, 0);
+        for dim in [2, 3] {
+            assert!(spectral_fit(&data, dim).unwrap().p_value > 0.001);
+        }
+        let xs = xorshift_fit(&data).unwrap();
+        assert!(xs.min_lane_complexity > xs.words / 2 - 16);
+    }
+
+    #[test]
+    fn test_lcg_modulus_recovered() {
+        let lcg = lcg_fit(&randu(256));
+        assert_eq!(lcg.modulus.unwrap() % (1 << 32), 0);
+        // RANDU outputs are always odd
+        assert!(lcg.periodic_low_bits >= 1);
+    }
+
+    #[test]
+    fn test_spectral_finds_randu_planes() {
+        let fit = spectral_fit(&randu(1024), 3).unwrap();
+        assert!(fit.p_value < 1e-6);
+        // 9·x_n − 6·x_{n+1} + x_{n+2} ≡ 0 (mod 2^32)
+        let h = fit.vector;
+        assert_eq!(h[0] * -6, h[1] * 9);
+        assert_eq!(h[2] * 9, h[0]);
+    }
+
+    #[test]
+    fn test_xorshift_lanes_are_linear() {
+        let fit = xorshift_fit(&xorshift32(512)).unwrap();
+        assert!(fit.min_lane_complexity <= 32);
+    }
+}
@@ -1,30 +1,44 @@
+use crate::prng::{lcg_fit, spectral_fit, xorshift_fit};
 use anyhow::{anyhow, bail, Result};
-use hypercube::vhc::read_vhc_file;
-use rand::Rng;
+use hypercube::vhc::{read_vhc_header, VhcReader};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use std::cmp::min;
 use std::collections::HashMap;
 use std::path::Path;
 
+#[derive(Default)]
 pub struct StatsOptions {
     pub raw: bool,
     pub block: Option<usize>,
-}
-
-impl Default for StatsOptions {
-    fn default() -> Self {
-        Self {
-            raw: false,
-            block: None,
-        }
-    }
+    pub all_blocks: bool,
+    /// Block pairs sampled for linkability in `all_blocks` mode
+    pub pairs: usize,
+    /// Seed for random block selection and pair sampling (reproducible reports)
+    pub seed: Option<u64>,
+    /// Test whether the whole file can be told apart from random bytes
+    pub distinguish: bool,
+    /// Test whether the sequence fields set some blocks apart
+    pub sequences: bool,
 }
 
 /// Run cryptanalysis on either a raw file or a Hypercube VHC block.
 pub fn run(path: &Path, options: &StatsOptions) -> Result<String> {
+    // ChaCha20 rather than StdRng, whose stream may change with the rand version
+    let mut rng = match options.seed {
+        Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+        None => ChaCha20Rng::from_entropy(),
+    };
     if options.raw {
         analyze_raw_file(path)
+    } else if options.distinguish {
+        analyze_distinguisher(path)
+    } else if options.sequences {
+        analyze_sequences(path)
+    } else if options.all_blocks {
+        analyze_all_blocks(path, options.pairs, &mut rng)
     } else {
-        analyze_vhc_file(path, options.block)
+        analyze_vhc_file(path, options.block, &mut rng)
     }
 }
 
@@ -38,43 +52,36 @@ fn analyze_raw_file(path: &Path) -> Result<String> {
     output.push_str("Codebreaker Cryptanalysis\n");
     output.push_str("========================\n\n");
     output.push_str(&format!("File: {}\n", path.display()));
-    output.push_str(&format!("Mode: Raw bytes\n"));
+    output.push_str("Mode: Raw bytes\n");
     output.push_str(&format!("Bytes analyzed: {}\n\n", data.len()));
     append_block_stats(&mut output, &data)?;
     Ok(output)
 }
 
-fn analyze_vhc_file(path: &Path, block: Option<usize>) -> Result<String> {
-    let vhc = read_vhc_file(path)?;
+fn analyze_vhc_file(path: &Path, block: Option<usize>, rng: &mut ChaCha20Rng) -> Result<String> {
+    let vhc = VhcReader::open(path)?;
+    let block_count = vhc.block_count();
 
-    if vhc.blocks.is_empty() {
+    if block_count == 0 {
         return Err(anyhow!("No blocks in {}", path.display()));
     }
 
-    let sequence_size = 16;
-    let mac_size = vhc.header.mac_bytes();
+    let mac_size = vhc.header().mac_bytes();
 
     let block_idx = match block {
         Some(idx) => {
-            if idx >= vhc.blocks.len() {
-                bail!(
-                    "Block index {} out of bounds (0..{})",
-                    idx,
-                    vhc.blocks.len() - 1
-                );
+            if idx >= block_count {
+                bail!("Block index {} out of bounds (0..{})", idx, block_count - 1);
             }
             idx
         }
-        None => rand::thread_rng().gen_range(0..vhc.blocks.len()),
+        None => rng.gen_range(0..block_count),
     };
 
-    let full_block = &vhc.blocks[block_idx];
-    if full_block.len() < sequence_size + mac_size {
-        bail!("Block {} is too small to contain sequence+MAC", block_idx);
-    }
-    let data_start = sequence_size;
-    let data_end = full_block.len() - mac_size;
-    let block_data = &full_block[data_start..data_end];
+    let block_data = vhc
+        .block_ref(block_idx)
+        .ok_or_else(|| anyhow!("Block {} could not be read", block_idx))?
+        .payload;
 
     let mut output = String::new();
     output.push_str("Hypercube Block Cryptanalysis\n");
@@ -82,8 +89,7 @@ fn analyze_vhc_file(path: &Path, block: Option<usize>) -> Result<String> {
     output.push_str(&format!("File: {}\n", path.display()));
     output.push_str(&format!(
         "Block: {} (of {} total)\n",
-        block_idx,
-        vhc.blocks.len()
+        block_idx, block_count
     ));
     output.push_str(&format!(
         "Block size: {} bytes (data only, excluding 16B seq + {}B MAC)\n\n",
@@ -95,7 +101,346 @@ fn analyze_vhc_file(path: &Path, block: Option<usize>) -> Result<String> {
     Ok(output)
 }
 
-fn append_block_stats(output: &mut String, block_data: &[u8]) -> Result<()> {
+/// Run the per-block tests on every block and check that their p-values are uniform.
+fn analyze_all_blocks(path: &Path, pairs: usize, rng: &mut ChaCha20Rng) -> Result<String> {
+    // SAFETY: analysis only reads; a container must not be written while it is analyzed
+    let vhc = unsafe { VhcReader::map(path)? };
+    let block_count = vhc.block_count();
+
+    if block_count == 0 {
+        return Err(anyhow!("No blocks in {}", path.display()));
+    }
+
+    let mac_size = vhc.header().mac_bytes();
+    let mut payloads = Vec::with_capacity(block_count);
+    for block in vhc.block_refs() {
+        if block.payload.is_empty() {
+            bail!("Block {} has no data bytes", block.index);
+        }
+        payloads.push(block.payload);
+    }
+
+    let mut output = String::new();
+    output.push_str("Hypercube Container Cryptanalysis\n");
+    output.push_str("=================================\n\n");
+    output.push_str(&format!("File: {}\n", path.display()));
+    output.push_str(&format!("Blocks analyzed: {}\n", block_count));
+    output.push_str(&format!(
+        "Block size: {} bytes (data only, excluding 16B seq + {}B MAC)\n\n",
+        vhc.header().block_size,
+        mac_size
+    ));
+
+    output.push_str(&population_report(&payloads, pairs, rng));
+    Ok(output)
+}
+
+/// Per-block p-value uniformity and pairwise linkability over a population of payloads
+pub fn population_report(payloads: &[&[u8]], pairs: usize, rng: &mut ChaCha20Rng) -> String {
+    let mut output = String::new();
+    let mut dashboard = Dashboard::new("P-Value Aggregation");
+    let mut section = dashboard.section("Second-Order Uniformity");
+    for (name, test) in BLOCK_P_VALUE_TESTS {
+        let p_values: Vec<f64> = payloads.iter().map(|data| test(data)).collect();
+        let agg = aggregate_p_values(&p_values);
+        section = section.metric(
+            name,
+            format!(
+                "χ²={:.2}, p={}, {}/{} pass",
+                agg.chi_square,
+                format_p_value(agg.uniformity_p),
+                agg.passed,
+                agg.count
+            ),
+            format_deciles(&agg),
+            severity_aggregate(&agg),
+        );
+    }
+    dashboard.add_section(section);
+
+    let pair_indices = sample_block_pairs(payloads.len(), pairs, rng);
+    if !pair_indices.is_empty() {
+        let links: Vec<PairLink> = pair_indices
+            .iter()
+            .map(|&(a, b)| pair_link(payloads[a], payloads[b]))
+            .collect();
+        let corr_agg =
+            aggregate_p_values(&links.iter().map(|l| l.correlation_p).collect::<Vec<_>>());
+        let mi_agg = aggregate_p_values(&links.iter().map(|l| l.mutual_p).collect::<Vec<_>>());
+        let max_corr = links
+            .iter()
+            .map(|l| l.correlation.abs())
+            .fold(0.0, f64::max);
+        let mean_mi = links.iter().map(|l| l.mutual_information).sum::<f64>() / links.len() as f64;
+        let max_mi = links
+            .iter()
+            .map(|l| l.mutual_information)
+            .fold(0.0, f64::max);
+
+        let mut link_section = dashboard.section("Inter-Block Linkability");
+        link_section = link_section
+            .metric(
+                "Pairs sampled",
+                format!("{}", links.len()),
+                "Random block pairs, bytes aligned by offset",
+                Severity::Pass,
+            )
+            .metric(
+                "Cross-correlation",
+                format!(
+                    "max |r|={:.4}, p={}, {}/{} pass",
+                    max_corr,
+                    format_p_value(corr_agg.uniformity_p),
+                    corr_agg.passed,
+                    corr_agg.count
+                ),
+                format_deciles(&corr_agg),
+                severity_aggregate(&corr_agg),
+            )
+            .metric(
+                "Mutual information (nibbles)",
+                format!(
+                    "mean {:.5}, max {:.5} bits, p={}",
+                    mean_mi,
+                    max_mi,
+                    format_p_value(mi_agg.uniformity_p)
+                ),
+                format_deciles(&mi_agg),
+                severity_aggregate(&mi_agg),
+            );
+        dashboard.add_section(link_section);
+    }
+    output.push_str(&dashboard.render());
+
+    output.push_str("\nPer-block p-values should be uniform on [0,1]. A skewed decile histogram\n");
+    output
+        .push_str("or too few passing blocks (α=0.01) reveals bias shared across the container\n");
+    output.push_str("that no single-block dashboard can show. The same check over block pairs\n");
+    output.push_str("scores whether blocks are statistically linkable to one another.\n");
+    if payloads.len() < MIN_AGGREGATE_BLOCKS {
+        output.push_str(&format!(
+            "Note: fewer than {} blocks; the uniformity test has little power.\n",
+            MIN_AGGREGATE_BLOCKS
+        ));
+    }
+    output
+}
+
+/// Chunk size for the distinguisher's p-values (64 chunks in the smallest envelope)
+const DISTINGUISH_CHUNK: usize = 1024;
+
+/// Decide whether a file can be told apart from random bytes of the same size.
+/// Checks for container signatures, then aggregates per-chunk p-values; a
+/// deniable envelope should pass every row.
+fn analyze_distinguisher(path: &Path) -> Result<String> {
+    let data = std::fs::read(path)?;
+    if data.is_empty() {
+        bail!("File is empty");
+    }
+
+    let mut output = String::new();
+    output.push_str("Random-File Distinguisher\n");
+    output.push_str("=========================\n\n");
+    output.push_str(&format!("File: {}\n", path.display()));
+    output.push_str(&format!(
+        "Chunks analyzed: {} × {} bytes\n\n",
+        data.len() / DISTINGUISH_CHUNK,
+        DISTINGUISH_CHUNK
+    ));
+
+    let mut dashboard = Dashboard::new("Distinguisher");
+    let magic = data.starts_with(b"VHC\x01");
+    let header = read_vhc_header(path).is_ok();
+    let size_note = if data.len().is_power_of_two() {
+        format!("2^{}", data.len().trailing_zeros())
+    } else {
+        "not a power of two".to_string()
+    };
+    let section = dashboard
+        .section("Signatures")
+        .metric(
+            "VHC magic",
+            if magic { "present" } else { "absent" }.to_string(),
+            "Leading VHC\\x01 bytes",
+            if magic {
+                Severity::Fail
+            } else {
+                Severity::Pass
+            },
+        )
+        .metric(
+            "Plaintext header",
+            if header { "parses" } else { "none" }.to_string(),
+            "JSON container header after the prefix",
+            if header {
+                Severity::Fail
+            } else {
+                Severity::Pass
+            },
+        )
+        .metric(
+            "File size",
+            format!("{} bytes ({})", data.len(), size_note),
+            "Standard sizes hide the payload length",
+            if data.len().is_power_of_two() {
+                Severity::Pass
+            } else {
+                Severity::Warn
+            },
+        );
+    dashboard.add_section(section);
+
+    let chunks: Vec<&[u8]> = data.chunks_exact(DISTINGUISH_CHUNK).collect();
+    let mut section = dashboard.section("Chunk Uniformity");
+    for (name, test) in BLOCK_P_VALUE_TESTS {
+        let p_values: Vec<f64> = chunks.iter().map(|chunk| test(chunk)).collect();
+        let agg = aggregate_p_values(&p_values);
+        section = section.metric(
+            name,
+            format!(
+                "χ²={:.2}, p={}, {}/{} pass",
+                agg.chi_square,
+                format_p_value(agg.uniformity_p),
+                agg.passed,
+                agg.count
+            ),
+            format_deciles(&agg),
+            severity_aggregate(&agg),
+        );
+    }
+    dashboard.add_section(section);
+
+    let verdict = match dashboard.status {
+        Severity::Fail => "distinguishable from random bytes",
+        Severity::Warn => "inconclusive",
+        Severity::Pass => "indistinguishable from random bytes",
+    };
+    output.push_str(&dashboard.render());
+    output.push_str(&format!("\nVerdict: {}\n", verdict));
+    if chunks.len() < MIN_AGGREGATE_BLOCKS {
+        output.push_str(&format!(
+            "Note: fewer than {} chunks; the uniformity tests have little power.\n",
+            MIN_AGGREGATE_BLOCKS
+        ));
+    }
+    Ok(output)
+}
+
+/// Decide whether the sequence field sets some blocks apart from the rest.
+/// Payload sequences count up from a random base, so each partition is a run of
+/// consecutive values. Chaff with fully random sequence bytes would show up as
+/// isolated blocks, and runs of an unusual length stand out from the others.
+fn analyze_sequences(path: &Path) -> Result<String> {
+    // SAFETY: as in `analyze_all_blocks`
+    let vhc = unsafe { VhcReader::map(path)? };
+    if vhc.block_count() == 0 {
+        return Err(anyhow!("No blocks in {}", path.display()));
+    }
+    let sequences: Vec<u128> = vhc
+        .block_refs()
+        .map(|block| block.sequence_number())
+        .collect();
+    let runs = sequence_runs(&sequences);
+
+    let mut output = String::new();
+    output.push_str("Sequence Run Analysis\n");
+    output.push_str("=====================\n\n");
+    output.push_str(&format!("File: {}\n", path.display()));
+    output.push_str(&format!("Blocks analyzed: {}\n\n", sequences.len()));
+
+    let mut lengths: Vec<(usize, usize)> = Vec::new();
+    for &len in &runs {
+        match lengths.iter_mut().find(|(l, _)| *l == len) {
+            Some((_, count)) => *count += 1,
+            None => lengths.push((len, 1)),
+        }
+    }
+    lengths.sort_unstable();
+    let isolated = lengths
+        .iter()
+        .find(|(len, _)| *len == 1)
+        .map_or(0, |&(_, count)| count);
+    let histogram = lengths
+        .iter()
+        .map(|(len, count)| format!("{}×{}", count, len))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut dashboard = Dashboard::new("Sequences");
+    let section = dashboard
+        .section("Sequence Runs")
+        .metric(
+            "Runs",
+            format!("{} runs over {} blocks", runs.len(), sequences.len()),
+            "Consecutive values link the blocks of one partition",
+            Severity::Pass,
+        )
+        .metric(
+            "Run lengths",
+            histogram,
+            "count×length; one length means every run has the same shape",
+            if lengths.len() > 1 {
+                Severity::Warn
+            } else {
+                Severity::Pass
+            },
+        )
+        .metric(
+            "Isolated blocks",
+            isolated.to_string(),
+            "Blocks outside any run, as random sequence bytes would be",
+            if isolated > 0 && isolated < runs.len() {
+                Severity::Fail
+            } else {
+                Severity::Pass
+            },
+        );
+    dashboard.add_section(section);
+
+    let verdict = match dashboard.status {
+        Severity::Fail => "sequences separate some blocks from the rest",
+        Severity::Warn => "runs of an uncommon length stand apart",
+        Severity::Pass => "sequences do not separate blocks",
+    };
+    output.push_str(&dashboard.render());
+    output.push_str(&format!("\nVerdict: {}\n", verdict));
+    Ok(output)
+}
+
+/// Lengths of the runs of consecutive values among `sequences`, in any order
+fn sequence_runs(sequences: &[u128]) -> Vec<usize> {
+    let mut sorted = sequences.to_vec();
+    sorted.sort_unstable();
+    let mut runs = Vec::new();
+    let mut len = 0;
+    for (i, &seq) in sorted.iter().enumerate() {
+        if i > 0 && sorted[i - 1].wrapping_add(1) == seq {
+            len += 1;
+        } else {
+            if len > 0 {
+                runs.push(len);
+            }
+            len = 1;
+        }
+    }
+    if len > 0 {
+        runs.push(len);
+    }
+    runs
+}
+
+fn format_deciles(agg: &PValueAggregate) -> String {
+    format!(
+        "deciles {}",
+        agg.bins
+            .iter()
+            .map(|b| b.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+pub fn append_block_stats(output: &mut String, block_data: &[u8]) -> Result<()> {
     if block_data.is_empty() {
         bail!("Not enough bytes to analyze");
     }
@@ -389,6 +734,78 @@ fn append_block_stats(output: &mut String, block_data: &[u8]) -> Result<()> {
     );
     dashboard.add_section(lin_section);
 
+    // Generator fitting
+    let lcg = lcg_fit(block_data);
+    let mut prng_section = dashboard.section("Generator Fitting");
+    prng_section = prng_section
+        .metric(
+            "LCG modulus recovery",
+            match lcg.modulus {
+                Some(m) => format!("m = {} (0x{:X})", m, m),
+                None => format!("none over {} words", lcg.words.min(64)),
+            },
+            "gcd of t₂t₀ − t₁² over word differences",
+            if lcg.modulus.is_some() {
+                Severity::Fail
+            } else {
+                Severity::Pass
+            },
+        )
+        .metric(
+            "LCG low-bit periods",
+            format!("{} of 8 low bits periodic", lcg.periodic_low_bits),
+            "Power-of-two LCGs repeat bit k every 2^(k+1) words",
+            if lcg.periodic_low_bits > 0 {
+                Severity::Fail
+            } else {
+                Severity::Pass
+            },
+        );
+    for dim in [2, 3] {
+        let label = format!("Spectral test ({}D)", dim);
+        prng_section = match spectral_fit(block_data, dim) {
+            Some(fit) => prng_section.metric(
+                &label,
+                format!(
+                    "h=({}), n|ĉ|²={:.1}, p={}",
+                    fit.vector
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    fit.score,
+                    format_p_value(fit.p_value)
+                ),
+                format!("Strongest lattice plane over {} word tuples", fit.tuples),
+                severity_generator_p(fit.p_value),
+            ),
+            None => prng_section.metric(
+                &label,
+                "n/a".to_string(),
+                "Too few 32-bit words",
+                Severity::Pass,
+            ),
+        };
+    }
+    prng_section = match xorshift_fit(block_data) {
+        Some(fit) => prng_section.metric(
+            "Xorshift/LFSR lanes",
+            format!(
+                "min L = {} over {} words (bit {})",
+                fit.min_lane_complexity, fit.words, fit.lane
+            ),
+            "GF(2)-linear generators keep L ≤ state bits; random ≈ n/2",
+            severity_lane_complexity(fit.min_lane_complexity, fit.words),
+        ),
+        None => prng_section.metric(
+            "Xorshift/LFSR lanes",
+            "n/a".to_string(),
+            "Too few 32-bit words",
+            Severity::Pass,
+        ),
+    };
+    dashboard.add_section(prng_section);
+
     // Multivariate/TVLA-style
     let t_value = welch_t_test(block_data);
     let mut multi_section = dashboard.section("Multivariate/TVLA");
@@ -420,11 +837,12 @@ fn append_block_stats(output: &mut String, block_data: &[u8]) -> Result<()> {
     dashboard.add_section(special_section);
 
     output.push_str(&dashboard.render());
+    output.push_str(&render_charts(block_data));
 
     let dump_size = block_data.len().min(256);
     output.push_str(&format!("\nHexdump (first {} bytes)\n", dump_size));
     output.push_str("------------------------\n");
-    output.push_str(&hexdump(&block_data[..dump_size]));
+    output.push_str(&hexdump(&block_data[..dump_size], 0));
 
     output.push_str("\nTests Requiring Larger Samples:\n");
     output.push_str("  - NIST SP 800-22 suite\n");
@@ -434,6 +852,255 @@ fn append_block_stats(output: &mut String, block_data: &[u8]) -> Result<()> {
     Ok(())
 }
 
+/// Growth of a statistic's running time with sample length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Complexity {
+    /// Works on a capped prefix, so cost stops growing with input size
+    Constant,
+    Linear,
+    Linearithmic,
+    Quadratic,
+}
+
+impl Complexity {
+    /// Relative cost of running on `n` bytes (arbitrary units)
+    pub fn cost(&self, n: usize) -> f64 {
+        let n = n.max(2) as f64;
+        match self {
+            Complexity::Constant => 1.0,
+            Complexity::Linear => n,
+            Complexity::Linearithmic => n * n.log2(),
+            Complexity::Quadratic => n * n,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Complexity::Constant => "O(1)",
+            Complexity::Linear => "O(n)",
+            Complexity::Linearithmic => "O(n log n)",
+            Complexity::Quadratic => "O(n²)",
+        }
+    }
+}
+
+/// A dashboard statistic that can be run in isolation
+pub struct Statistic {
+    pub name: &'static str,
+    pub complexity: Complexity,
+    pub run: fn(&[u8]),
+}
+
+/// Every statistic rendered by the dashboard, in dashboard order
+pub fn statistics() -> Vec<Statistic> {
+    use std::hint::black_box;
+    vec![
+        Statistic {
+            name: "Byte frequency",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(byte_frequency_analysis(d));
+            },
+        },
+        Statistic {
+            name: "Index of coincidence",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(index_of_coincidence(d));
+            },
+        },
+        Statistic {
+            name: "Top n-grams",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(top_ngrams(d, 3, 3));
+            },
+        },
+        Statistic {
+            name: "Kasiski distances",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(kasiski_analysis(d).summary);
+            },
+        },
+        Statistic {
+            name: "Crib coincidence",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(crib_coincidence(d).matches);
+            },
+        },
+        Statistic {
+            name: "Shannon entropy",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_entropy(d));
+            },
+        },
+        Statistic {
+            name: "Min-entropy",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_min_entropy(d));
+            },
+        },
+        Statistic {
+            name: "Rényi entropy",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_renyi_entropy(d));
+            },
+        },
+        Statistic {
+            name: "Sliding entropy (32B)",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(shingled_entropy(d, 32).average);
+            },
+        },
+        Statistic {
+            name: "Chi-square",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_chi_square(d));
+            },
+        },
+        Statistic {
+            name: "Kolmogorov–Smirnov",
+            complexity: Complexity::Linearithmic,
+            run: |d| {
+                black_box(kolmogorov_smirnov_uniform(d).p_value);
+            },
+        },
+        Statistic {
+            name: "Anderson–Darling",
+            complexity: Complexity::Linearithmic,
+            run: |d| {
+                black_box(anderson_darling_uniform(d));
+            },
+        },
+        Statistic {
+            name: "Kuiper",
+            complexity: Complexity::Linearithmic,
+            run: |d| {
+                black_box(kuiper_uniform(d));
+            },
+        },
+        Statistic {
+            name: "ASCII ratio",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_ascii_ratio(d));
+            },
+        },
+        Statistic {
+            name: "Runs test",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_runs_test(d));
+            },
+        },
+        Statistic {
+            name: "Serial correlation",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_serial_correlation(d));
+            },
+        },
+        Statistic {
+            name: "Autocorrelation",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(calculate_autocorrelation(d, &[1, 2, 4, 8, 16]));
+            },
+        },
+        Statistic {
+            name: "Cross-correlation",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(cross_correlation(d, 16));
+            },
+        },
+        Statistic {
+            name: "Bit-plane uniformity",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(bit_plane_stats(d).len());
+            },
+        },
+        Statistic {
+            name: "XOR Δ bias",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(xor_bias(d).frequency);
+            },
+        },
+        Statistic {
+            name: "Bit correlation matrix",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(bit_correlation_stats(d).max_abs);
+            },
+        },
+        Statistic {
+            name: "DFT spectrum",
+            complexity: Complexity::Quadratic,
+            run: |d| {
+                black_box(spectral_stats(d).peak);
+            },
+        },
+        Statistic {
+            name: "Berlekamp–Massey",
+            complexity: Complexity::Quadratic,
+            run: |d| {
+                black_box(linear_complexity(d).length);
+            },
+        },
+        Statistic {
+            name: "Welch t-test",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(welch_t_test(d));
+            },
+        },
+        Statistic {
+            name: "Hamming weight",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(hamming_weight_stats(d).mean);
+            },
+        },
+        Statistic {
+            name: "Bit run lengths",
+            complexity: Complexity::Linear,
+            run: |d| {
+                black_box(run_length_stats_bits(d).longest);
+            },
+        },
+        Statistic {
+            name: "LCG fit",
+            complexity: Complexity::Constant,
+            run: |d| {
+                black_box(lcg_fit(d).modulus);
+            },
+        },
+        Statistic {
+            name: "Spectral test (3D)",
+            complexity: Complexity::Constant,
+            run: |d| {
+                black_box(spectral_fit(d, 3).map(|fit| fit.p_value));
+            },
+        },
+        Statistic {
+            name: "Xorshift lanes",
+            complexity: Complexity::Constant,
+            run: |d| {
+                black_box(xorshift_fit(d).map(|fit| fit.min_lane_complexity));
+            },
+        },
+    ]
+}
+
 #[derive(Clone, Copy)]
 enum Severity {
     Pass,
@@ -610,9 +1277,9 @@ fn severity_autocorr(values: &[(usize, f64)]) -> Severity {
 }
 
 fn severity_p_value(p: f64) -> Severity {
-    if p < 0.01 || p > 0.99 {
+    if !(0.01..=0.99).contains(&p) {
         Severity::Fail
-    } else if p < 0.05 || p > 0.95 {
+    } else if !(0.05..=0.95).contains(&p) {
         Severity::Warn
     } else {
         Severity::Pass
@@ -731,6 +1398,38 @@ fn severity_run_length(longest: usize) -> Severity {
     }
 }
 
+fn severity_generator_p(p: f64) -> Severity {
+    if p < 0.001 {
+        Severity::Fail
+    } else if p < 0.01 {
+        Severity::Warn
+    } else {
+        Severity::Pass
+    }
+}
+
+fn severity_lane_complexity(complexity: usize, words: usize) -> Severity {
+    if complexity < words / 4 {
+        Severity::Fail
+    } else if complexity + 16 < words / 2 {
+        Severity::Warn
+    } else {
+        Severity::Pass
+    }
+}
+
+fn severity_aggregate(agg: &PValueAggregate) -> Severity {
+    if agg.count < MIN_AGGREGATE_BLOCKS {
+        Severity::Warn
+    } else if agg.uniformity_p < 0.0001 || agg.passed < agg.min_passing() {
+        Severity::Fail
+    } else if agg.uniformity_p < 0.01 {
+        Severity::Warn
+    } else {
+        Severity::Pass
+    }
+}
+
 const RESET: &str = "\x1b[0m";
 const BOLD: &str = "\x1b[1m";
 const FG_GREEN: &str = "\x1b[32m";
@@ -1025,9 +1724,11 @@ fn calculate_chi_square(data: &[u8]) -> f64 {
     chi_square
 }
 
-/// Approximate p-value for chi-square (normal approximation)
+/// Approximate upper-tail p-value for chi-square (Wilson–Hilferty)
 fn chi_square_p_value(chi_square: f64, df: usize) -> f64 {
-    let z = ((2.0 * chi_square).sqrt() - (2.0 * df as f64 - 1.0).sqrt()) / std::f64::consts::SQRT_2;
+    let k = df as f64;
+    let h = 2.0 / (9.0 * k);
+    let z = ((chi_square / k).cbrt() - (1.0 - h)) / h.sqrt();
     0.5 * (1.0 - erf(z / std::f64::consts::SQRT_2))
 }
 
@@ -1047,9 +1748,9 @@ fn erf(x: f64) -> f64 {
 }
 
 fn interpret_chi_square(p: f64) -> &'static str {
-    if p < 0.01 || p > 0.99 {
+    if !(0.01..=0.99).contains(&p) {
         "SUSPECT - significant deviation from uniform"
-    } else if p < 0.05 || p > 0.95 {
+    } else if !(0.05..=0.95).contains(&p) {
         "Marginal - slight deviation from uniform"
     } else {
         "PASS - consistent with random data"
@@ -1555,7 +2256,8 @@ fn top_ngrams(data: &[u8], n: usize, limit: usize) -> Vec<(Vec<u8>, usize)> {
         *map.entry(window.to_vec()).or_insert(0) += 1;
     }
     let mut items: Vec<_> = map.into_iter().collect();
-    items.sort_by(|a, b| b.1.cmp(&a.1));
+    // Break count ties by byte value so reports are reproducible
+    items.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
     items.truncate(limit);
     items
 }
@@ -1699,7 +2401,7 @@ fn kolmogorov_smirnov_uniform(data: &[u8]) -> KsResult {
         let mut sum = 0.0;
         for k in 1..100 {
             let term = (-2.0 * (k as f64).powi(2) * lambda * lambda).exp();
-            sum += (-1.0f64).powi(k as i32 - 1) * term;
+            sum += (-1.0f64).powi(k - 1) * term;
         }
         (2.0 * sum).clamp(0.0, 1.0)
     } else {
@@ -1728,12 +2430,12 @@ fn anderson_darling_uniform(data: &[u8]) -> f64 {
     let n = sorted.len() as f64;
     let mut sum = 0.0;
     for (i, x) in sorted.iter().enumerate() {
-        let xi = x.max(1e-12).min(1.0 - 1e-12);
+        let xi = x.clamp(1e-12, 1.0 - 1e-12);
         let term = (2.0 * (i as f64 + 1.0) - 1.0) * (xi.ln() + (1.0 - xi).ln());
         sum += term;
     }
-    let a2 = -n - (sum / n);
-    a2
+
+    -n - (sum / n)
 }
 
 fn kuiper_uniform(data: &[u8]) -> f64 {
@@ -1768,10 +2470,7 @@ fn shingled_entropy(data: &[u8], window: usize) -> ShingleEntropy {
         };
     }
 
-    let mut entropies = Vec::new();
-    for chunk in data.windows(window) {
-        entropies.push(calculate_entropy(chunk));
-    }
+    let entropies = sliding_entropies(data, window);
 
     let sum: f64 = entropies.iter().sum();
     let average = sum / entropies.len() as f64;
@@ -1781,6 +2480,11 @@ fn shingled_entropy(data: &[u8], window: usize) -> ShingleEntropy {
     ShingleEntropy { average, min, max }
 }
 
+/// Shannon entropy of every `window`-byte window
+fn sliding_entropies(data: &[u8], window: usize) -> Vec<f64> {
+    data.windows(window).map(calculate_entropy).collect()
+}
+
 struct LinearComplexity {
     length: usize,
     total_bits: usize,
@@ -1794,6 +2498,17 @@ fn linear_complexity(data: &[u8]) -> LinearComplexity {
         }
     }
 
+    LinearComplexity {
+        length: berlekamp_massey(&bits),
+        total_bits: bits.len(),
+    }
+}
+
+/// Length of the shortest LFSR generating a 0/1 bit sequence
+pub fn berlekamp_massey(bits: &[u8]) -> usize {
+    if bits.is_empty() {
+        return 0;
+    }
     let mut c = vec![0u8; bits.len()];
     let mut b = vec![0u8; bits.len()];
     c[0] = 1;
@@ -1810,8 +2525,8 @@ fn linear_complexity(data: &[u8]) -> LinearComplexity {
         if d == 1 {
             let temp = c.clone();
             let shift = n as isize - m;
-            for j in 0..b.len() {
-                if b[j] == 1 {
+            for (j, &bit) in b.iter().enumerate() {
+                if bit == 1 {
                     let idx = j as isize + shift;
                     if idx >= 0 && (idx as usize) < c.len() {
                         c[idx as usize] ^= 1;
@@ -1826,17 +2541,310 @@ fn linear_complexity(data: &[u8]) -> LinearComplexity {
         }
     }
 
-    LinearComplexity {
-        length: l,
-        total_bits: bits.len(),
+    l
+}
+
+/// Per-block tests whose p-values are aggregated container-wide
+type PValueTest = fn(&[u8]) -> f64;
+
+const BLOCK_P_VALUE_TESTS: &[(&str, PValueTest)] = &[
+    ("Byte χ² (df=255)", |d| {
+        chi_square_p_value(calculate_chi_square(d), 255)
+    }),
+    ("Monobit frequency", monobit_p_value),
+    ("Runs (bits)", runs_p_value),
+];
+
+/// NIST SP 800-22 recommends at least 55 samples for the p-value histogram
+const MIN_AGGREGATE_BLOCKS: usize = 55;
+
+/// Significance level used for the pass proportion
+const AGGREGATE_ALPHA: f64 = 0.01;
+
+struct PValueAggregate {
+    count: usize,
+    passed: usize,
+    bins: [usize; 10],
+    chi_square: f64,
+    uniformity_p: f64,
+}
+
+impl PValueAggregate {
+    /// Lower bound of the NIST confidence interval on the pass proportion
+    fn min_passing(&self) -> usize {
+        let m = self.count as f64;
+        let p = 1.0 - AGGREGATE_ALPHA;
+        let bound = p - 3.0 * (p * AGGREGATE_ALPHA / m).sqrt();
+        (bound * m).ceil().max(0.0) as usize
+    }
+}
+
+/// Chi-square of p-values over ten equal bins (df=9) plus the α=0.01 pass count
+fn aggregate_p_values(p_values: &[f64]) -> PValueAggregate {
+    let mut bins = [0usize; 10];
+    for &p in p_values {
+        bins[((p * 10.0) as usize).min(9)] += 1;
+    }
+    let expected = p_values.len() as f64 / 10.0;
+    let chi_square = if expected > 0.0 {
+        bins.iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    } else {
+        0.0
+    };
+
+    PValueAggregate {
+        count: p_values.len(),
+        passed: p_values.iter().filter(|&&p| p >= AGGREGATE_ALPHA).count(),
+        bins,
+        chi_square,
+        uniformity_p: chi_square_p_value(chi_square, 9),
+    }
+}
+
+/// Pick up to `samples` distinct block pairs (all pairs if there are fewer)
+fn sample_block_pairs(count: usize, samples: usize, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+    let total = count * count.saturating_sub(1) / 2;
+    if total <= samples {
+        return (0..count)
+            .flat_map(|a| (a + 1..count).map(move |b| (a, b)))
+            .collect();
+    }
+
+    let mut pairs = std::collections::HashSet::with_capacity(samples);
+    while pairs.len() < samples {
+        let a = rng.gen_range(0..count);
+        let b = rng.gen_range(0..count);
+        if a != b {
+            pairs.insert((a.min(b), a.max(b)));
+        }
     }
+    let mut pairs: Vec<_> = pairs.into_iter().collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+struct PairLink {
+    correlation: f64,
+    correlation_p: f64,
+    mutual_information: f64,
+    mutual_p: f64,
 }
 
-fn hexdump(data: &[u8]) -> String {
+/// Pearson correlation and nibble mutual information between two blocks
+fn pair_link(a: &[u8], b: &[u8]) -> PairLink {
+    let n = a.len().min(b.len());
+    let (a, b) = (&a[..n], &b[..n]);
+
+    let mean_a = a.iter().map(|&x| x as f64).sum::<f64>() / n as f64;
+    let mean_b = b.iter().map(|&x| x as f64).sum::<f64>() / n as f64;
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (&x, &y) in a.iter().zip(b) {
+        let dx = x as f64 - mean_a;
+        let dy = y as f64 - mean_b;
+        cov += dx * dy;
+        var_a += dx * dx;
+        var_b += dy * dy;
+    }
+    let correlation = if var_a == 0.0 || var_b == 0.0 {
+        0.0
+    } else {
+        cov / (var_a * var_b).sqrt()
+    };
+    // r·√n is approximately standard normal for independent blocks
+    let z = correlation.abs() * (n as f64).sqrt();
+    let correlation_p = 1.0 - erf(z / std::f64::consts::SQRT_2);
+
+    // Joint histogram of high nibbles keeps the plug-in estimate nearly unbiased
+    let mut joint = [[0u64; 16]; 16];
+    let mut marg_a = [0u64; 16];
+    let mut marg_b = [0u64; 16];
+    for (&x, &y) in a.iter().zip(b) {
+        joint[(x >> 4) as usize][(y >> 4) as usize] += 1;
+        marg_a[(x >> 4) as usize] += 1;
+        marg_b[(y >> 4) as usize] += 1;
+    }
+    let total = n as f64;
+    let mut mutual_information = 0.0;
+    for (i, row) in joint.iter().enumerate() {
+        for (j, &count) in row.iter().enumerate() {
+            if count > 0 {
+                let pxy = count as f64 / total;
+                let px = marg_a[i] as f64 / total;
+                let py = marg_b[j] as f64 / total;
+                mutual_information += pxy * (pxy / (px * py)).log2();
+            }
+        }
+    }
+    // G-test: 2n·ln2·I ~ χ²((16-1)²) under independence
+    let g = 2.0 * total * std::f64::consts::LN_2 * mutual_information;
+    let mutual_p = chi_square_p_value(g, 225);
+
+    PairLink {
+        correlation,
+        correlation_p,
+        mutual_information,
+        mutual_p,
+    }
+}
+
+/// NIST frequency (monobit) test
+fn monobit_p_value(data: &[u8]) -> f64 {
+    let n = (data.len() * 8) as f64;
+    let ones: u64 = data.iter().map(|b| b.count_ones() as u64).sum();
+    let s = (2.0 * ones as f64 - n).abs();
+    1.0 - erf(s / (2.0 * n).sqrt())
+}
+
+/// Wald–Wolfowitz runs test over the bit stream (two-sided)
+fn runs_p_value(data: &[u8]) -> f64 {
+    let (runs, expected) = calculate_runs_test(data);
+    let n = (data.len() * 8) as f64;
+    let variance = (expected - 1.0) * (expected - 2.0) / (n - 1.0);
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    let z = (runs as f64 - expected) / variance.sqrt();
+    1.0 - erf(z.abs() / std::f64::consts::SQRT_2)
+}
+
+/// Columns used by the histogram and sparkline
+const CHART_WIDTH: usize = 64;
+const HISTOGRAM_HEIGHT: usize = 6;
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Byte-frequency histogram and sliding-entropy sparkline
+fn render_charts(data: &[u8]) -> String {
+    let mut output = String::new();
+
+    let mut freq = [0u64; 256];
+    for &byte in data {
+        freq[byte as usize] += 1;
+    }
+    let per_bin = 256 / CHART_WIDTH;
+    let bins: Vec<f64> = freq
+        .chunks(per_bin)
+        .map(|chunk| chunk.iter().sum::<u64>() as f64)
+        .collect();
+    let peak = bins.iter().cloned().fold(0.0, f64::max);
+    output.push_str(&format!(
+        "\nByte Frequency ({} bins × {} values, peak {}, expected {:.1})\n",
+        CHART_WIDTH,
+        per_bin,
+        peak,
+        data.len() as f64 / CHART_WIDTH as f64
+    ));
+    for row in bar_chart(&bins, HISTOGRAM_HEIGHT) {
+        output.push_str(&format!("  {}\n", row));
+    }
+    let step = CHART_WIDTH / 4;
+    let mut axis = String::new();
+    for i in 0..4 {
+        axis.push_str(&format!(
+            "{:<width$}",
+            format!("{:02X}", i * step * per_bin),
+            width = step
+        ));
+    }
+    output.push_str(&format!("  {}FF\n", &axis[..axis.len() - 2]));
+
+    let window = 32;
+    let entropies = sliding_entropies(data, window);
+    if !entropies.is_empty() {
+        let ceiling = (window as f64).log2();
+        let columns = downsample_min(&entropies, CHART_WIDTH);
+        let (low_at, low) =
+            entropies
+                .iter()
+                .enumerate()
+                .fold(
+                    (0, f64::INFINITY),
+                    |acc, (i, &e)| if e < acc.1 { (i, e) } else { acc },
+                );
+        output.push_str(&format!(
+            "\nSliding Entropy ({}B windows, column minimum, scale 0–{:.0} bits)\n",
+            window, ceiling
+        ));
+        output.push_str(&format!("  {}\n", sparkline(&columns, ceiling)));
+        output.push_str(&format!(
+            "  lowest {:.3} bits at offset 0x{:X}\n",
+            low, low_at
+        ));
+    }
+
+    output
+}
+
+/// One-line chart with eighth-block resolution, scaled to `max`
+fn sparkline(values: &[f64], max: f64) -> String {
+    values
+        .iter()
+        .map(|&v| {
+            let level = if max > 0.0 {
+                (v / max).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            SPARK_LEVELS[((level * 7.0).round() as usize).min(7)]
+        })
+        .collect()
+}
+
+/// Vertical bar chart, top row first, scaled so the tallest bar fills `height` rows
+fn bar_chart(values: &[f64], height: usize) -> Vec<String> {
+    let peak = values.iter().cloned().fold(0.0, f64::max);
+    let eighths: Vec<usize> = values
+        .iter()
+        .map(|&v| {
+            if peak > 0.0 {
+                ((v / peak) * (height * 8) as f64).round() as usize
+            } else {
+                0
+            }
+        })
+        .collect();
+
+    (0..height)
+        .rev()
+        .map(|row| {
+            eighths
+                .iter()
+                .map(|&e| match e.saturating_sub(row * 8) {
+                    0 => ' ',
+                    n if n >= 8 => '█',
+                    n => SPARK_LEVELS[n - 1],
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Shrink a series to `width` columns, keeping each column's minimum so dips stay visible
+fn downsample_min(values: &[f64], width: usize) -> Vec<f64> {
+    if values.len() <= width {
+        return values.to_vec();
+    }
+    (0..width)
+        .map(|col| {
+            let start = col * values.len() / width;
+            let end = (col + 1) * values.len() / width;
+            values[start..end]
+                .iter()
+                .cloned()
+                .fold(f64::INFINITY, f64::min)
+        })
+        .collect()
+}
+
+/// Canonical hex + ASCII dump; offsets start at `base`
+pub fn hexdump(data: &[u8], base: usize) -> String {
     let mut output = String::new();
 
     for (i, chunk) in data.chunks(16).enumerate() {
-        output.push_str(&format!("{:08X}  ", i * 16));
+        output.push_str(&format!("{:08X}  ", base + i * 16));
 
         for (j, byte) in chunk.iter().enumerate() {
             output.push_str(&format!("{:02X} ", byte));
@@ -1856,7 +2864,7 @@ fn hexdump(data: &[u8]) -> String {
 
         output.push_str(" |");
         for &byte in chunk {
-            if byte >= 0x20 && byte <= 0x7E {
+            if (0x20..=0x7E).contains(&byte) {
                 output.push(byte as char);
             } else {
                 output.push('.');
@@ -1889,15 +2897,144 @@ mod tests {
     #[test]
     fn test_hexdump() {
         let data = b"Hello, World!";
-        let dump = hexdump(data);
+        let dump = hexdump(data, 0);
         assert!(dump.contains("48 65 6C 6C"));
         assert!(dump.contains("|Hello, World!|"));
     }
 
+    #[test]
+    fn test_sequence_runs() {
+        assert_eq!(sequence_runs(&[7, 3, 5, 4, 9, 8, 20]), vec![3, 3, 1]);
+        assert!(sequence_runs(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_sealed_chaff_shares_sequence_runs() {
+        use hypercube::cli::add::{add_partition, AddOptions};
+        use hypercube::cli::seal::seal_file;
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"seed data").unwrap();
+        let opts = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &opts).unwrap();
+        seal_file(&vhc).unwrap();
+
+        let options = StatsOptions {
+            sequences: true,
+            ..Default::default()
+        };
+        let report = run(&vhc, &options).unwrap();
+        assert!(report.contains("8 runs over 64 blocks"), "{}", report);
+        assert!(report.contains("Verdict: sequences do not separate blocks"));
+    }
+
     #[test]
     fn test_chi_square() {
         let data: Vec<u8> = (0..256).map(|i| i as u8).collect();
         let chi = calculate_chi_square(&data);
         assert!(chi < 1.0);
     }
+
+    #[test]
+    fn test_aggregate_uniform_p_values_pass() {
+        let p_values: Vec<f64> = (0..1000).map(|i| (i as f64 + 0.5) / 1000.0).collect();
+        let agg = aggregate_p_values(&p_values);
+        assert_eq!(agg.bins, [100; 10]);
+        assert!(agg.uniformity_p > 0.99);
+        assert!(matches!(severity_aggregate(&agg), Severity::Pass));
+    }
+
+    #[test]
+    fn test_aggregate_flags_skewed_p_values() {
+        let p_values: Vec<f64> = (0..1000).map(|i| (i as f64 + 0.5) / 4000.0).collect();
+        let agg = aggregate_p_values(&p_values);
+        assert!(agg.uniformity_p < 0.0001);
+        assert!(matches!(severity_aggregate(&agg), Severity::Fail));
+    }
+
+    #[test]
+    fn test_block_p_values_are_calibrated() {
+        use rand::{rngs::StdRng, RngCore, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(7);
+        let blocks: Vec<Vec<u8>> = (0..500)
+            .map(|_| {
+                let mut block = vec![0u8; 1024];
+                rng.fill_bytes(&mut block);
+                block
+            })
+            .collect();
+        for (name, test) in BLOCK_P_VALUE_TESTS {
+            let p_values: Vec<f64> = blocks.iter().map(|b| test(b)).collect();
+            let agg = aggregate_p_values(&p_values);
+            assert!(agg.uniformity_p > 0.0001, "{} p-values skewed", name);
+            assert!(agg.passed >= agg.min_passing(), "{} pass rate low", name);
+        }
+    }
+
+    #[test]
+    fn test_pair_link_detects_shared_blocks() {
+        use rand::{rngs::StdRng, RngCore, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut a = vec![0u8; 4096];
+        let mut b = vec![0u8; 4096];
+        rng.fill_bytes(&mut a);
+        rng.fill_bytes(&mut b);
+
+        let independent = pair_link(&a, &b);
+        assert!(independent.correlation.abs() < 0.08);
+        assert!(independent.mutual_p > 0.0001);
+
+        let linked = pair_link(&a, &a);
+        assert!((linked.correlation - 1.0).abs() < 1e-9);
+        assert!(linked.mutual_information > 3.9);
+        assert!(linked.mutual_p < 0.001);
+    }
+
+    #[test]
+    fn test_sample_block_pairs() {
+        let mut rng = rand::thread_rng();
+        assert_eq!(sample_block_pairs(1, 10, &mut rng), vec![]);
+        assert_eq!(sample_block_pairs(3, 10, &mut rng).len(), 3);
+        let sampled = sample_block_pairs(100, 50, &mut rng);
+        assert_eq!(sampled.len(), 50);
+        assert!(sampled.iter().all(|&(a, b)| a < b && b < 100));
+    }
+
+    #[test]
+    fn test_sparkline_levels() {
+        assert_eq!(sparkline(&[0.0, 0.5, 1.0, 2.0], 1.0), "▁▅██");
+    }
+
+    #[test]
+    fn test_bar_chart_scales_to_peak() {
+        let rows = bar_chart(&[8.0, 4.0, 0.0, 1.0], 2);
+        assert_eq!(rows, vec!["█   ", "██ ▂"]);
+    }
+
+    #[test]
+    fn test_downsample_keeps_dips() {
+        let mut values = vec![5.0; 100];
+        values[37] = 1.0;
+        let columns = downsample_min(&values, 10);
+        assert_eq!(columns.len(), 10);
+        assert_eq!(columns[3], 1.0);
+        assert!(columns.iter().filter(|&&v| v == 5.0).count() == 9);
+    }
+
+    #[test]
+    fn test_charts_render_for_block() {
+        let data: Vec<u8> = (0..4096).map(|i| (i * 7 % 256) as u8).collect();
+        let charts = render_charts(&data);
+        assert!(charts.contains("Byte Frequency"));
+        assert!(charts.contains("Sliding Entropy"));
+        assert!(
+            charts.contains("  00              40              80              C0            FF")
+        );
+    }
 }
@@ -53,3 +53,175 @@ fn stats_command_reads_vhc_files() -> Result<(), Box<dyn Error>> {
     assert!(stdout.contains("Hypercube Block Cryptanalysis"));
     Ok(())
 }
+
+#[test]
+fn bench_command_times_statistics() -> Result<(), Box<dyn Error>> {
+    let output = run(&["bench", "--sizes", "64,256", "--quadratic-limit", "64"])?;
+    assert!(
+        output.status.success(),
+        "bench failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Codebreaker Statistic Benchmark"));
+    assert!(stdout.contains("Shannon entropy"));
+    assert!(stdout.contains("skipped"));
+    Ok(())
+}
+
+#[test]
+fn stats_all_blocks_aggregates_p_values() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("payload.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"payload data for aggregation")?;
+
+    let opts = AddOptions {
+        secret: "codebreaker-secret".into(),
+        ..Default::default()
+    };
+    add_partition(&input, &vault, &opts).expect("failed to create VHC");
+
+    let output = run(&["stats", "--all-blocks", vault.to_str().unwrap()])?;
+    assert!(
+        output.status.success(),
+        "stats failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Hypercube Container Cryptanalysis"));
+    assert!(stdout.contains("Second-Order Uniformity"));
+    assert!(stdout.contains("Inter-Block Linkability"));
+    Ok(())
+}
+
+#[test]
+fn chaff_stats_leaves_out_partitions() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("payload.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"payload data beside chaff")?;
+
+    let opts = AddOptions {
+        secret: "codebreaker-secret".into(),
+        dimension: 8,
+        kdf: None,
+        seal: true,
+        ..Default::default()
+    };
+    add_partition(&input, &vault, &opts).expect("failed to create VHC");
+
+    let vault = vault.to_str().unwrap();
+    let output = run(&["chaff-stats", "--secret", "codebreaker-secret", vault])?;
+    assert!(
+        output.status.success(),
+        "chaff-stats failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Hypercube Chaff Cryptanalysis"));
+    assert!(stdout.contains("Blocks: 64 total, 8 in 1 partition(s), 56 chaff"));
+    assert!(!run(&["chaff-stats", vault])?.status.success());
+    Ok(())
+}
+
+#[test]
+fn dump_command_prints_block_region() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("payload.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"payload data for dump")?;
+
+    let opts = AddOptions {
+        secret: "codebreaker-secret".into(),
+        ..Default::default()
+    };
+    add_partition(&input, &vault, &opts).expect("failed to create VHC");
+
+    let vault = vault.to_str().unwrap();
+    let output = run(&[
+        "dump", vault, "--block", "1", "--offset", "16", "--len", "32",
+    ])?;
+    assert!(
+        output.status.success(),
+        "dump failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Hypercube Block Dump"));
+    assert!(stdout.contains("00000010  "));
+
+    let output = run(&["dump", vault, "--raw-offset", "0", "--len", "4"])?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("|VHC.|"));
+    Ok(())
+}
+
+#[test]
+fn stats_seed_makes_reports_reproducible() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("payload.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"payload data for seeded stats")?;
+
+    let opts = AddOptions {
+        secret: "codebreaker-secret".into(),
+        ..Default::default()
+    };
+    add_partition(&input, &vault, &opts).expect("failed to create VHC");
+
+    let vault = vault.to_str().unwrap();
+    let first = run(&["stats", vault, "--seed", "42"])?;
+    let second = run(&["stats", vault, "--seed", "42"])?;
+    assert!(
+        first.status.success(),
+        "stats failed: {}",
+        String::from_utf8_lossy(&first.stderr)
+    );
+    assert_eq!(first.stdout, second.stdout);
+    Ok(())
+}
+
+#[test]
+fn stats_distinguish_separates_envelopes_from_containers() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("payload.txt");
+    let vault = dir.path().join("vault.vhc");
+    let envelope = dir.path().join("noise.bin");
+    fs::write(&input, b"payload data for the distinguisher")?;
+
+    let opts = AddOptions {
+        secret: "codebreaker-secret".into(),
+        ..Default::default()
+    };
+    add_partition(&input, &vault, &opts).expect("failed to create VHC");
+    let deniable = AddOptions {
+        passphrase: Some("outer".into()),
+        deniable: true,
+        ..opts
+    };
+    add_partition(&input, &envelope, &deniable).expect("failed to create envelope");
+
+    let output = run(&["stats", "--distinguish", vault.to_str().unwrap()])?;
+    assert!(
+        output.status.success(),
+        "stats failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Verdict: distinguishable from random bytes"));
+
+    let output = run(&["stats", "--distinguish", envelope.to_str().unwrap()])?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let row = |label: &str| {
+        stdout
+            .lines()
+            .find(|line| line.contains(label))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert!(row("VHC magic").contains("absent"));
+    assert!(row("Plaintext header").contains("none"));
+    assert!(row("File size").contains("(2^16)"));
+    Ok(())
+}
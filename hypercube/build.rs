@@ -3,7 +3,7 @@ use std::path::Path;
 use std::process::Command;
 
 fn main() {
-    // Read and increment build number
+    // Read the build number; it is bumped by hand, so building never dirties the tree
     let build_file = Path::new("BUILD_NUMBER");
     let build_number: u64 = if build_file.exists() {
         fs::read_to_string(build_file)
@@ -15,9 +15,6 @@ fn main() {
         0
     };
 
-    let new_build = build_number + 1;
-    fs::write(build_file, new_build.to_string()).expect("Failed to write build number");
-
     // Detect if this is a release build
     let profile = std::env::var("PROFILE").unwrap_or_else(|_| "debug".to_string());
     let is_release = profile == "release";
@@ -44,7 +41,7 @@ fn main() {
 
     // Set environment variables for compilation
     println!("cargo:rustc-env=HYPERCUBE_VERSION={}", version);
-    println!("cargo:rustc-env=HYPERCUBE_BUILD={}", new_build);
+    println!("cargo:rustc-env=HYPERCUBE_BUILD={}", build_number);
     println!(
         "cargo:rustc-env=HYPERCUBE_PROFILE={}",
         if is_release { "release" } else { "development" }
@@ -0,0 +1,1135 @@
+//! Multi-file archives stored as one partition's payload.
+//!
+//! `hypercube add --archive dir/` packs a directory tree into an archive, which is then
+//! compressed and sealed like any other payload. `hypercube ls` lists a partition's
+//! entries and `hypercube extract --archive` unpacks them into a directory.
+//!
+//! Layout: the magic `HCAR`, a format version byte and a `u32 LE` entry count, then
+//! each entry as `kind: u8`, `mode: u32 LE`, `mtime: i64 LE` (seconds since the Unix
+//! epoch), `path_len: u32 LE`, the path, `data_len: u64 LE` and the data. Paths are
+//! UTF-8, relative and `/`-separated. A file's data is its contents and a symlink's is
+//! its target; directories have none. In version 2 each entry then has an
+//! `attribute_count: u32 LE` and its attributes, each as `name_len: u32 LE`, the UTF-8
+//! name, `value_len: u32 LE` and the value. An archive with no attributes is written
+//! as version 1, which older builds read too.
+//!
+//! Entries are regular files, directories and symlinks; sockets, FIFOs and devices
+//! are skipped. Symlinks are stored, never followed, and are only recreated on Unix.
+//!
+//! Attributes are captured only when asked for (see `ArchiveOptions`): extended
+//! attributes under their own names, such as `user.comment`, the SELinux label as
+//! `security.selinux` and POSIX ACLs as `system.posix_acl_*`. They are read from and
+//! written to symlinks themselves, never their targets. Extended attributes are
+//! supported on Linux and macOS. On Windows the DACL is stored as SDDL text under
+//! `windows.acl`, and each alternate data stream under `windows.stream.` and its name.
+//! Attributes of another platform are skipped when unpacking, and ACLs are restored
+//! best effort, since the users and groups they name may not exist here.
+
+use crate::error::{HypercubeError, Result};
+use crate::file_mode::create_private_file;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Magic at the start of an archive payload
+pub const ARCHIVE_MAGIC: &[u8; 4] = b"HCAR";
+
+/// Archive format version
+pub const ARCHIVE_VERSION: u8 = 2;
+
+/// Version of archives without attributes, which builds before attributes also read
+const PLAIN_ARCHIVE_VERSION: u8 = 1;
+
+/// Extended attribute holding a file's SELinux label
+pub const SELINUX_LABEL: &str = "security.selinux";
+
+/// Extended attributes holding a file's POSIX ACLs on Linux
+pub const POSIX_ACLS: [&str; 2] = ["system.posix_acl_access", "system.posix_acl_default"];
+
+/// Attribute holding a Windows file's DACL as SDDL text, such as `D:(A;;FA;;;SY)`
+pub const WINDOWS_ACL: &str = "windows.acl";
+
+/// Prefix of the attributes holding a Windows file's alternate data streams, by name
+pub const WINDOWS_STREAM: &str = "windows.stream.";
+
+/// Extended metadata captured when packing an archive and restored when unpacking it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveOptions {
+    /// Extended attributes other than the SELinux label, like `tar --xattrs`
+    pub xattrs: bool,
+    /// The SELinux label, like `tar --selinux`
+    pub selinux: bool,
+    /// POSIX ACLs, or the DACL on Windows, like `tar --acls`
+    pub acls: bool,
+}
+
+impl ArchiveOptions {
+    /// Whether the attribute `name` is captured and restored
+    fn wants(&self, name: &str) -> bool {
+        match name {
+            SELINUX_LABEL => self.selinux,
+            _ if is_acl(name) => self.acls,
+            _ => self.xattrs,
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.xattrs || self.selinux || self.acls
+    }
+}
+
+fn is_acl(name: &str) -> bool {
+    name == WINDOWS_ACL || POSIX_ACLS.contains(&name)
+}
+
+/// What an archive entry is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl EntryKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::File => 0,
+            Self::Directory => 1,
+            Self::Symlink => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::File),
+            1 => Some(Self::Directory),
+            2 => Some(Self::Symlink),
+            _ => None,
+        }
+    }
+}
+
+/// One file, directory or symlink of an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Relative, `/`-separated path
+    pub path: String,
+    pub kind: EntryKind,
+    /// Permission bits (`0o7777`)
+    pub mode: u32,
+    /// Modification time, in seconds since the Unix epoch
+    pub mtime: i64,
+    /// File contents or symlink target
+    pub data: Vec<u8>,
+    /// Extended metadata, sorted by name
+    pub attributes: Vec<Attribute>,
+}
+
+/// A named piece of an entry's extended metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    /// The platform's name for it, such as `user.comment` or `security.selinux`
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+impl std::fmt::Display for ArchiveEntry {
+    /// One `ls -l` style line: mode, size, UTC modification time and path
+    /// As on macOS, `@` after the mode marks attributes other than ACLs (such as
+    /// alternate data streams), and otherwise `+` marks an ACL
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            EntryKind::File => '-',
+            EntryKind::Directory => 'd',
+            EntryKind::Symlink => 'l',
+        };
+        let permissions: String = (0..9)
+            .map(|bit| match self.mode & (0o400 >> bit) != 0 {
+                true => ['r', 'w', 'x'][bit % 3],
+                false => '-',
+            })
+            .collect();
+        let names = || self.attributes.iter().map(|attribute| attribute.name.as_str());
+        let marker = match (names().any(|name| !is_acl(name)), names().any(is_acl)) {
+            (true, _) => "@",
+            (false, true) => "+",
+            (false, false) => "",
+        };
+        write!(
+            f,
+            "{}{}{} {:>12} {} {}",
+            kind,
+            permissions,
+            marker,
+            self.data.len(),
+            format_mtime(self.mtime),
+            self.path
+        )?;
+        if self.kind == EntryKind::Symlink {
+            write!(f, " -> {}", String::from_utf8_lossy(&self.data))?;
+        }
+        Ok(())
+    }
+}
+
+/// Named files, directories and symlinks packed into one payload
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Archive {
+    pub entries: Vec<ArchiveEntry>,
+}
+
+impl Archive {
+    /// Pack the tree under `root`, or the single file `root`, in path order, with the
+    /// attributes `options` asks for
+    pub fn from_path(root: &Path, options: &ArchiveOptions) -> Result<Self> {
+        let mut archive = Self::default();
+        if fs::symlink_metadata(root)?.is_dir() {
+            archive.add_dir(root, "", options)?;
+        } else {
+            let name = root.file_name().and_then(|name| name.to_str());
+            let name = name.ok_or_else(|| invalid_path(&root.to_string_lossy()))?;
+            archive.add_path(root, name.to_string(), options)?;
+        }
+        Ok(archive)
+    }
+
+    fn add_dir(&mut self, dir: &Path, prefix: &str, options: &ArchiveOptions) -> Result<()> {
+        let mut children: Vec<_> = fs::read_dir(dir)?.collect::<std::io::Result<_>>()?;
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            let name = child.file_name();
+            let name = name
+                .to_str()
+                .ok_or_else(|| invalid_path(&child.path().to_string_lossy()))?;
+            self.add_path(&child.path(), format!("{}{}", prefix, name), options)?;
+        }
+        Ok(())
+    }
+
+    fn add_path(&mut self, path: &Path, name: String, options: &ArchiveOptions) -> Result<()> {
+        let metadata = fs::symlink_metadata(path)?;
+        let kind = if metadata.is_dir() {
+            EntryKind::Directory
+        } else if metadata.is_symlink() {
+            EntryKind::Symlink
+        } else if metadata.is_file() {
+            EntryKind::File
+        } else {
+            return Ok(());
+        };
+        let data = match kind {
+            EntryKind::File => fs::read(path)?,
+            EntryKind::Directory => Vec::new(),
+            EntryKind::Symlink => {
+                let target = fs::read_link(path)?;
+                let target = target.to_str().ok_or_else(|| invalid_path(&name))?;
+                target.as_bytes().to_vec()
+            }
+        };
+        let mtime = metadata
+            .modified()
+            .map_or(0, |time| match time.duration_since(UNIX_EPOCH) {
+                Ok(after) => after.as_secs() as i64,
+                Err(before) => -(before.duration().as_secs() as i64),
+            });
+        check_path(&name)?;
+        self.entries.push(ArchiveEntry {
+            path: name.clone(),
+            kind,
+            mode: mode_of(&metadata, kind),
+            mtime,
+            data,
+            attributes: read_attributes(path, kind, options)?,
+        });
+        if kind == EntryKind::Directory {
+            self.add_dir(path, &format!("{}/", name), options)?;
+        }
+        Ok(())
+    }
+
+    /// The payload starts like an archive
+    pub fn is_archive(data: &[u8]) -> bool {
+        data.starts_with(ARCHIVE_MAGIC)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let attributes = self.entries.iter().any(|entry| !entry.attributes.is_empty());
+        let mut bytes = ARCHIVE_MAGIC.to_vec();
+        bytes.push(match attributes {
+            true => ARCHIVE_VERSION,
+            false => PLAIN_ARCHIVE_VERSION,
+        });
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            bytes.push(entry.kind.to_byte());
+            bytes.extend_from_slice(&entry.mode.to_le_bytes());
+            bytes.extend_from_slice(&entry.mtime.to_le_bytes());
+            bytes.extend_from_slice(&(entry.path.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(entry.path.as_bytes());
+            bytes.extend_from_slice(&(entry.data.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&entry.data);
+            if attributes {
+                bytes.extend_from_slice(&(entry.attributes.len() as u32).to_le_bytes());
+                for attribute in &entry.attributes {
+                    bytes.extend_from_slice(&(attribute.name.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(attribute.name.as_bytes());
+                    bytes.extend_from_slice(&(attribute.value.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(&attribute.value);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Parse an archive, refusing paths that could leave the directory it unpacks into
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if !Self::is_archive(data) {
+            return Err(HypercubeError::InvalidFormat(
+                "Partition is not an archive".into(),
+            ));
+        }
+        let mut reader = Reader(&data[ARCHIVE_MAGIC.len()..]);
+        let version = reader.take(1)?[0];
+        if !(PLAIN_ARCHIVE_VERSION..=ARCHIVE_VERSION).contains(&version) {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "Unsupported archive version {}",
+                version
+            )));
+        }
+        let count = reader.u32()?;
+        let mut archive = Self::default();
+        let mut seen = HashSet::new();
+        for _ in 0..count {
+            let kind = EntryKind::from_byte(reader.take(1)?[0])
+                .ok_or_else(|| HypercubeError::InvalidFormat("Unknown archive entry".into()))?;
+            let mode = reader.u32()? & 0o7777;
+            let mtime = i64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+            let length = reader.u32()? as usize;
+            let path = std::str::from_utf8(reader.take(length)?)
+                .map_err(|_| HypercubeError::InvalidFormat("Archive path is not UTF-8".into()))?
+                .to_string();
+            check_path(&path)?;
+            if !seen.insert(path.clone()) {
+                return Err(HypercubeError::InvalidFormat(format!(
+                    "Archive holds {} twice",
+                    path
+                )));
+            }
+            let length = usize::try_from(reader.u64()?).map_err(|_| truncated())?;
+            let data = reader.take(length)?.to_vec();
+            let mut attributes = Vec::new();
+            if version >= ARCHIVE_VERSION {
+                for _ in 0..reader.u32()? {
+                    let length = reader.u32()? as usize;
+                    let name = std::str::from_utf8(reader.take(length)?)
+                        .ok()
+                        .filter(|name| !name.is_empty() && !name.contains('\0'))
+                        .ok_or_else(|| {
+                            HypercubeError::InvalidFormat(format!("Bad attribute name on {}", path))
+                        })?
+                        .to_string();
+                    let length = reader.u32()? as usize;
+                    let value = reader.take(length)?.to_vec();
+                    attributes.push(Attribute { name, value });
+                }
+            }
+            archive.entries.push(ArchiveEntry {
+                path,
+                kind,
+                mode,
+                mtime,
+                data,
+                attributes,
+            });
+        }
+        if !reader.0.is_empty() {
+            return Err(HypercubeError::InvalidFormat(
+                "Trailing bytes after the last archive entry".into(),
+            ));
+        }
+        Ok(archive)
+    }
+
+    /// Recreate the entries under `dir`, which is created if missing, with the attributes
+    /// `options` asks for
+    /// Files are overwritten. Symlinks come last, so no entry is written through one, and
+    /// directory times and permissions are set once their contents are in place. An
+    /// attribute the file system refuses fails the unpacking.
+    pub fn unpack(&self, dir: &Path, options: &ArchiveOptions) -> Result<()> {
+        // A drive letter or stream name would escape `dir` on Windows
+        if let Some(entry) = self
+            .entries
+            .iter()
+            .find(|entry| cfg!(windows) && entry.path.contains(':'))
+        {
+            return Err(invalid_path(&entry.path));
+        }
+        fs::create_dir_all(dir)?;
+        let target = |entry: &ArchiveEntry| dir.join(&entry.path);
+        for entry in &self.entries {
+            let path = target(entry);
+            match entry.kind {
+                EntryKind::Directory => fs::create_dir_all(&path)?,
+                EntryKind::File => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    let file = create_private_file(&path)?;
+                    std::io::Write::write_all(&mut &file, &entry.data)?;
+                    file.set_modified(entry_time(entry))?;
+                    // Before the mode, which may take away the write access they need
+                    write_attributes(&path, entry, options)?;
+                    set_mode(&path, entry.mode)?;
+                }
+                EntryKind::Symlink => {}
+            }
+        }
+        for entry in self.entries.iter().rev() {
+            if entry.kind == EntryKind::Directory {
+                let path = target(entry);
+                write_attributes(&path, entry, options)?;
+                // Best effort: not every platform can open a directory to set its time
+                let _ = fs::File::open(&path).and_then(|file| file.set_modified(entry_time(entry)));
+                set_mode(&path, entry.mode)?;
+            }
+        }
+        for entry in &self.entries {
+            if entry.kind == EntryKind::Symlink {
+                let path = target(entry);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                make_symlink(&entry.data, &path)?;
+                if cfg!(unix) {
+                    write_attributes(&path, entry, options)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads an archive front to back
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.0.len() < length {
+            return Err(truncated());
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+fn truncated() -> HypercubeError {
+    HypercubeError::InvalidFormat("Truncated archive".into())
+}
+
+fn invalid_path(path: &str) -> HypercubeError {
+    HypercubeError::InvalidFormat(format!("Archive path {} is not allowed", path))
+}
+
+/// Refuse absolute paths, `.` and `..`, empty components and separators other than `/`
+fn check_path(path: &str) -> Result<()> {
+    let bad_component = |part: &str| part.is_empty() || part == "." || part == "..";
+    if path.split('/').any(bad_component) || path.contains(['\\', '\0']) {
+        return Err(invalid_path(path));
+    }
+    Ok(())
+}
+
+/// The attributes of `path` that `options` asks for, sorted by name
+fn read_attributes(path: &Path, kind: EntryKind, options: &ArchiveOptions) -> Result<Vec<Attribute>> {
+    // Symlinks are only recreated on Unix, so their attributes are not kept elsewhere
+    if !options.any() || (kind == EntryKind::Symlink && !cfg!(unix)) {
+        return Ok(Vec::new());
+    }
+    let mut attributes = Vec::new();
+    for name in native::list(path)? {
+        let name = String::from_utf8(name).map_err(|name| {
+            HypercubeError::InvalidFormat(format!(
+                "Extended attribute {} on {} is not UTF-8",
+                String::from_utf8_lossy(name.as_bytes()),
+                path.display()
+            ))
+        })?;
+        if options.wants(&name) {
+            let value = native::get(path, &name)?;
+            attributes.push(Attribute { name, value });
+        }
+    }
+    attributes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(attributes)
+}
+
+/// Set the attributes of `entry` that `options` asks for and this platform can hold
+/// An ACL the file system refuses is skipped; any other attribute fails the unpacking
+fn write_attributes(path: &Path, entry: &ArchiveEntry, options: &ArchiveOptions) -> Result<()> {
+    for attribute in &entry.attributes {
+        if !options.wants(&attribute.name) || !native::holds(&attribute.name) {
+            continue;
+        }
+        match native::set(path, &attribute.name, &attribute.value) {
+            Err(_) if is_acl(&attribute.name) => {}
+            Err(e) => {
+                return Err(HypercubeError::Io(std::io::Error::new(
+                    e.kind(),
+                    format!("{} on {}: {}", attribute.name, path.display(), e),
+                )))
+            }
+            Ok(()) => {}
+        }
+    }
+    Ok(())
+}
+
+fn entry_time(entry: &ArchiveEntry) -> SystemTime {
+    let seconds = Duration::from_secs(entry.mtime.unsigned_abs());
+    match entry.mtime >= 0 {
+        true => UNIX_EPOCH + seconds,
+        false => UNIX_EPOCH - seconds,
+    }
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC
+fn format_mtime(mtime: i64) -> String {
+    let (days, seconds) = (mtime.div_euclid(86400), mtime.rem_euclid(86400));
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata, _kind: EntryKind) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata, kind: EntryKind) -> u32 {
+    let base = match kind {
+        EntryKind::Directory => 0o755,
+        _ => 0o644,
+    };
+    match metadata.permissions().readonly() {
+        true => base & 0o555,
+        false => base,
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::set_permissions(path, fs::Permissions::from_mode(mode))?)
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    Ok(fs::set_permissions(path, permissions)?)
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &[u8], path: &Path) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let target = PathBuf::from(std::ffi::OsStr::from_bytes(target));
+    Ok(std::os::unix::fs::symlink(target, path)?)
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_target: &[u8], _path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Extended attributes of a path itself, not of a symlink's target
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+mod native {
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Names of the attributes on `path`
+    pub fn list(path: &Path) -> io::Result<Vec<Vec<u8>>> {
+        let path = c_string(path.as_os_str().as_bytes())?;
+        let names = read_sized(|buf, size| unsafe { sys::list(&path, buf, size) })?;
+        Ok(names
+            .split(|&byte| byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect())
+    }
+
+    pub fn get(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+        let (path, name) = (c_string(path.as_os_str().as_bytes())?, c_string(name.as_bytes())?);
+        read_sized(|buf, size| unsafe { sys::get(&path, &name, buf, size) })
+    }
+
+    pub fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let (path, name) = (c_string(path.as_os_str().as_bytes())?, c_string(name.as_bytes())?);
+        match unsafe { sys::set(&path, &name, value) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Linux only has namespaced names; the rest come from other platforms
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn holds(name: &str) -> bool {
+        ["user.", "trusted.", "security.", "system."]
+            .iter()
+            .any(|namespace| name.starts_with(namespace))
+    }
+
+    /// Any name but a Windows one
+    #[cfg(target_os = "macos")]
+    pub fn holds(name: &str) -> bool {
+        !name.starts_with("windows.")
+    }
+
+    fn c_string(bytes: &[u8]) -> io::Result<CString> {
+        CString::new(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Ask `read` for the size with an empty buffer, then read into one that size, again
+    /// if the value grew in between
+    fn read_sized(read: impl Fn(*mut c_char, usize) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let size = read(std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buf = vec![0u8; size as usize];
+            let read_len = read(buf.as_mut_ptr().cast(), buf.len());
+            if read_len >= 0 {
+                buf.truncate(read_len as usize);
+                return Ok(buf);
+            }
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(libc::ERANGE) {
+                return Err(e);
+            }
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    mod sys {
+        use super::*;
+
+        pub unsafe fn list(path: &CStr, buf: *mut c_char, size: usize) -> isize {
+            libc::llistxattr(path.as_ptr(), buf, size)
+        }
+
+        pub unsafe fn get(path: &CStr, name: &CStr, buf: *mut c_char, size: usize) -> isize {
+            libc::lgetxattr(path.as_ptr(), name.as_ptr(), buf.cast(), size)
+        }
+
+        pub unsafe fn set(path: &CStr, name: &CStr, value: &[u8]) -> i32 {
+            let (value, size) = (value.as_ptr().cast(), value.len());
+            libc::lsetxattr(path.as_ptr(), name.as_ptr(), value, size, 0)
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod sys {
+        use super::*;
+        use libc::XATTR_NOFOLLOW;
+
+        pub unsafe fn list(path: &CStr, buf: *mut c_char, size: usize) -> isize {
+            libc::listxattr(path.as_ptr(), buf, size, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn get(path: &CStr, name: &CStr, buf: *mut c_char, size: usize) -> isize {
+            libc::getxattr(path.as_ptr(), name.as_ptr(), buf.cast(), size, 0, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn set(path: &CStr, name: &CStr, value: &[u8]) -> i32 {
+            let (value, size) = (value.as_ptr().cast(), value.len());
+            libc::setxattr(path.as_ptr(), name.as_ptr(), value, size, 0, XATTR_NOFOLLOW)
+        }
+    }
+}
+
+/// The DACL and alternate data streams of a path, under their attribute names
+#[cfg(windows)]
+mod native {
+    use super::{WINDOWS_ACL, WINDOWS_STREAM};
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::Foundation::{LocalFree, ERROR_HANDLE_EOF, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertSecurityDescriptorToStringSecurityDescriptorW,
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, GetNamedSecurityInfoW,
+        SetNamedSecurityInfoW, SDDL_REVISION_1, SE_FILE_OBJECT,
+    };
+    use windows_sys::Win32::Security::{
+        GetSecurityDescriptorDacl, ACL, DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+        WIN32_FIND_STREAM_DATA,
+    };
+
+    /// The DACL, then each alternate data stream
+    pub fn list(path: &Path) -> io::Result<Vec<Vec<u8>>> {
+        let mut names = vec![WINDOWS_ACL.as_bytes().to_vec()];
+        for stream in streams(path)? {
+            names.push(format!("{}{}", WINDOWS_STREAM, stream).into_bytes());
+        }
+        Ok(names)
+    }
+
+    pub fn get(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+        match name.strip_prefix(WINDOWS_STREAM) {
+            Some(stream) => std::fs::read(stream_path(path, stream)),
+            None => dacl(path).map(String::into_bytes),
+        }
+    }
+
+    pub fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        match name.strip_prefix(WINDOWS_STREAM) {
+            Some(stream) => std::fs::write(stream_path(path, stream), value),
+            None => set_dacl(path, value),
+        }
+    }
+
+    pub fn holds(name: &str) -> bool {
+        name == WINDOWS_ACL || name.starts_with(WINDOWS_STREAM)
+    }
+
+    fn wide(text: &OsStr) -> Vec<u16> {
+        text.encode_wide().chain([0]).collect()
+    }
+
+    /// `path:stream`, which opens the stream like a file
+    fn stream_path(path: &Path, stream: &str) -> PathBuf {
+        let mut joined = path.as_os_str().to_owned();
+        joined.push(":");
+        joined.push(stream);
+        PathBuf::from(joined)
+    }
+
+    /// Names of the alternate data streams, without the unnamed main stream
+    fn streams(path: &Path) -> io::Result<Vec<String>> {
+        let path = wide(path.as_os_str());
+        let mut data = WIN32_FIND_STREAM_DATA::default();
+        let data_ptr = (&mut data as *mut WIN32_FIND_STREAM_DATA).cast();
+        let find = unsafe { FindFirstStreamW(path.as_ptr(), FindStreamInfoStandard, data_ptr, 0) };
+        if find == INVALID_HANDLE_VALUE {
+            return no_more(io::Error::last_os_error(), Vec::new());
+        }
+        let mut names = Vec::new();
+        loop {
+            let name = &data.cStreamName;
+            let len = name.iter().position(|&unit| unit == 0).unwrap_or(name.len());
+            // Stream names look like `:Zone.Identifier:$DATA`; the main stream is `::$DATA`
+            let name = String::from_utf16_lossy(&name[..len]);
+            let stream = name.strip_prefix(':').and_then(|name| name.strip_suffix(":$DATA"));
+            if let Some(stream) = stream.filter(|stream| !stream.is_empty()) {
+                names.push(stream.to_string());
+            }
+            if unsafe { FindNextStreamW(find, data_ptr) } == 0 {
+                break;
+            }
+        }
+        let e = io::Error::last_os_error();
+        unsafe { FindClose(find) };
+        no_more(e, names)
+    }
+
+    /// `names` if `e` only says there are no more streams
+    fn no_more(e: io::Error, names: Vec<String>) -> io::Result<Vec<String>> {
+        match e.raw_os_error() {
+            Some(code) if code as u32 == ERROR_HANDLE_EOF => Ok(names),
+            _ => Err(e),
+        }
+    }
+
+    fn status(code: u32) -> io::Result<()> {
+        match code {
+            0 => Ok(()),
+            _ => Err(io::Error::from_raw_os_error(code as i32)),
+        }
+    }
+
+    /// The DACL of `path` as SDDL text
+    fn dacl(path: &Path) -> io::Result<String> {
+        let path = wide(path.as_os_str());
+        let mut descriptor: PSECURITY_DESCRIPTOR = null_mut();
+        status(unsafe {
+            GetNamedSecurityInfoW(
+                path.as_ptr(),
+                SE_FILE_OBJECT,
+                DACL_SECURITY_INFORMATION,
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                &mut descriptor,
+            )
+        })?;
+        let (mut text, mut len) = (null_mut(), 0u32);
+        let converted = unsafe {
+            ConvertSecurityDescriptorToStringSecurityDescriptorW(
+                descriptor,
+                SDDL_REVISION_1,
+                DACL_SECURITY_INFORMATION,
+                &mut text,
+                &mut len,
+            )
+        };
+        let sddl = match converted {
+            0 => Err(io::Error::last_os_error()),
+            _ => {
+                let units = unsafe { std::slice::from_raw_parts(text, len as usize) };
+                let units = units.split(|&unit| unit == 0).next().unwrap_or_default();
+                let sddl = String::from_utf16_lossy(units);
+                unsafe { LocalFree(text.cast()) };
+                Ok(sddl)
+            }
+        };
+        unsafe { LocalFree(descriptor) };
+        sddl
+    }
+
+    /// Replace the DACL of `path` with the one in SDDL text `sddl`
+    fn set_dacl(path: &Path, sddl: &[u8]) -> io::Result<()> {
+        let sddl = std::str::from_utf8(sddl)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let sddl = wide(OsStr::new(sddl));
+        let mut descriptor: PSECURITY_DESCRIPTOR = null_mut();
+        let parsed = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                null_mut(),
+            )
+        };
+        if parsed == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (mut present, mut defaulted, mut dacl) = (0, 0, null_mut::<ACL>());
+        let found = unsafe {
+            GetSecurityDescriptorDacl(descriptor, &mut present, &mut dacl, &mut defaulted)
+        };
+        let path = wide(path.as_os_str());
+        let result = match found {
+            0 => Err(io::Error::last_os_error()),
+            _ => status(unsafe {
+                SetNamedSecurityInfoW(
+                    path.as_ptr(),
+                    SE_FILE_OBJECT,
+                    DACL_SECURITY_INFORMATION,
+                    null_mut(),
+                    null_mut(),
+                    dacl,
+                    null(),
+                )
+            }),
+        };
+        unsafe { LocalFree(descriptor) };
+        result
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    windows
+)))]
+mod native {
+    use std::io;
+    use std::path::Path;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "extended attributes are not supported on this platform",
+        )
+    }
+
+    pub fn list(_path: &Path) -> io::Result<Vec<Vec<u8>>> {
+        Err(unsupported())
+    }
+
+    pub fn get(_path: &Path, _name: &str) -> io::Result<Vec<u8>> {
+        Err(unsupported())
+    }
+
+    pub fn set(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn holds(_name: &str) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join("docs/empty")).unwrap();
+        fs::write(root.join("a.txt"), b"alpha").unwrap();
+        fs::write(root.join("docs/b.txt"), b"beta").unwrap();
+        let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(root.join("a.txt"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            set_mode(&root.join("docs/b.txt"), 0o600).unwrap();
+            std::os::unix::fs::symlink("../a.txt", root.join("docs/link")).unwrap();
+        }
+
+        let archive = Archive::from_path(&root, &ArchiveOptions::default()).unwrap();
+        let paths: Vec<&str> = archive.entries.iter().map(|e| e.path.as_str()).collect();
+        #[cfg(unix)]
+        assert_eq!(
+            paths,
+            ["a.txt", "docs", "docs/b.txt", "docs/empty", "docs/link"]
+        );
+        assert_eq!(archive.entries[0].mtime, 1_000_000_000);
+        let line = archive.entries[0].to_string();
+        assert!(line.starts_with("-rw"), "{}", line);
+        assert!(line.ends_with(" 5 2001-09-09 01:46 a.txt"), "{}", line);
+
+        let bytes = archive.to_bytes();
+        assert!(Archive::is_archive(&bytes));
+        let parsed = Archive::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, archive);
+
+        let out = dir.path().join("out");
+        parsed.unpack(&out, &ArchiveOptions::default()).unwrap();
+        assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"alpha");
+        assert_eq!(fs::read(out.join("docs/b.txt")).unwrap(), b"beta");
+        assert!(out.join("docs/empty").is_dir());
+        let modified = fs::metadata(out.join("a.txt")).unwrap().modified().unwrap();
+        assert_eq!(modified, old);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(out.join("docs/b.txt"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+            assert_eq!(fs::read(out.join("docs/link")).unwrap(), b"alpha");
+            assert!(parsed.entries[4]
+                .to_string()
+                .ends_with("docs/link -> ../a.txt"));
+        }
+
+        // A single file is archived under its own name
+        let single = Archive::from_path(&root.join("a.txt"), &ArchiveOptions::default()).unwrap();
+        assert_eq!(single.entries.len(), 1);
+        assert_eq!(single.entries[0].path, "a.txt");
+    }
+
+    #[test]
+    fn test_archive_rejects_bad_input() {
+        let entry = |path: &str| ArchiveEntry {
+            path: path.into(),
+            kind: EntryKind::File,
+            mode: 0o644,
+            mtime: 0,
+            data: b"x".to_vec(),
+            attributes: Vec::new(),
+        };
+        for bad in [
+            "",
+            "/etc/passwd",
+            "../x",
+            "a/../../x",
+            "a//b",
+            "./a",
+            "a\\b",
+        ] {
+            let archive = Archive {
+                entries: vec![entry(bad)],
+            };
+            assert!(Archive::from_bytes(&archive.to_bytes()).is_err(), "{}", bad);
+        }
+        let twice = Archive {
+            entries: vec![entry("a"), entry("a")],
+        };
+        assert!(Archive::from_bytes(&twice.to_bytes()).is_err());
+
+        let good = Archive {
+            entries: vec![entry("a/b")],
+        }
+        .to_bytes();
+        assert!(Archive::from_bytes(&good).is_ok());
+        assert!(Archive::from_bytes(&good[..good.len() - 1]).is_err());
+        assert!(Archive::from_bytes(&[good.as_slice(), b"!"].concat()).is_err());
+        assert!(Archive::from_bytes(b"plain file").is_err());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn test_archive_xattrs() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("a.txt"), b"alpha").unwrap();
+        // Not every file system takes user attributes
+        if native::set(&root.join("a.txt"), "user.comment", b"first draft").is_err() {
+            return;
+        }
+        native::set(&root.join("docs"), "user.tag", b"").unwrap();
+        set_mode(&root.join("a.txt"), 0o400).unwrap();
+
+        // Left out unless asked for, so the archive stays readable by older builds
+        let plain = Archive::from_path(&root, &ArchiveOptions::default()).unwrap();
+        assert_eq!(plain.to_bytes()[4], PLAIN_ARCHIVE_VERSION);
+        let options = ArchiveOptions {
+            xattrs: true,
+            ..Default::default()
+        };
+        let archive = Archive::from_path(&root, &options).unwrap();
+        let attribute = |entry: &ArchiveEntry, name: &str| {
+            let found = entry.attributes.iter().find(|attribute| attribute.name == name);
+            found.map(|attribute| attribute.value.clone())
+        };
+        assert_eq!(attribute(&archive.entries[0], "user.comment").unwrap(), b"first draft");
+        assert_eq!(attribute(&archive.entries[1], "user.tag").unwrap(), b"");
+        assert!(attribute(&archive.entries[0], SELINUX_LABEL).is_none());
+        let bytes = archive.to_bytes();
+        assert_eq!(bytes[4], ARCHIVE_VERSION);
+        let parsed = Archive::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, archive);
+        assert!(Archive::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // Restored only when asked for, and before a read-only mode is set
+        let out = dir.path().join("out");
+        parsed.unpack(&out, &ArchiveOptions::default()).unwrap();
+        assert!(native::get(&out.join("a.txt"), "user.comment").is_err());
+        let out = dir.path().join("restored");
+        parsed.unpack(&out, &options).unwrap();
+        assert_eq!(native::get(&out.join("a.txt"), "user.comment").unwrap(), b"first draft");
+        assert_eq!(native::get(&out.join("docs"), "user.tag").unwrap(), b"");
+
+        let selinux = ArchiveOptions {
+            selinux: true,
+            ..Default::default()
+        };
+        assert!(selinux.wants(SELINUX_LABEL) && !selinux.wants("user.comment"));
+        assert!(!options.wants(SELINUX_LABEL));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_archive_acls() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), b"alpha").unwrap();
+        // Version 2, then tag, permissions and id per entry: the owner, user 1000,
+        // the group, the mask and others
+        let mut acl = 2u32.to_le_bytes().to_vec();
+        for (tag, perm, id) in [(1u16, 6u16, u32::MAX), (2, 4, 1000), (4, 4, u32::MAX)]
+            .into_iter()
+            .chain([(0x10, 4, u32::MAX), (0x20, 0, u32::MAX)])
+        {
+            acl.extend(tag.to_le_bytes());
+            acl.extend(perm.to_le_bytes());
+            acl.extend(id.to_le_bytes());
+        }
+        // Not every file system takes ACLs
+        if native::set(&root.join("a.txt"), POSIX_ACLS[0], &acl).is_err() {
+            return;
+        }
+
+        let xattrs = ArchiveOptions {
+            xattrs: true,
+            ..Default::default()
+        };
+        let archive = Archive::from_path(&root, &xattrs).unwrap();
+        assert!(archive.entries[0].attributes.is_empty());
+        let acls = ArchiveOptions {
+            acls: true,
+            ..Default::default()
+        };
+        let mut archive = Archive::from_path(&root, &acls).unwrap();
+        assert_eq!(archive.entries[0].attributes[0].name, POSIX_ACLS[0]);
+        assert!(archive.entries[0].to_string().starts_with("-rw-r-----+ "));
+        let out = dir.path().join("out");
+        archive.unpack(&out, &acls).unwrap();
+        assert_eq!(native::get(&out.join("a.txt"), POSIX_ACLS[0]).unwrap(), acl);
+
+        // A malformed ACL is skipped, and Windows attributes never reach the file
+        archive.entries[0].attributes = vec![
+            Attribute {
+                name: POSIX_ACLS[0].to_string(),
+                value: b"not an acl".to_vec(),
+            },
+            Attribute {
+                name: WINDOWS_ACL.to_string(),
+                value: b"D:(A;;FA;;;SY)".to_vec(),
+            },
+            Attribute {
+                name: format!("{}Zone.Identifier", WINDOWS_STREAM),
+                value: b"[ZoneTransfer]".to_vec(),
+            },
+        ];
+        assert!(archive.entries[0].to_string().starts_with("-rw-r-----@ "));
+        let all = ArchiveOptions {
+            xattrs: true,
+            selinux: false,
+            acls: true,
+        };
+        let out = dir.path().join("skipped");
+        archive.unpack(&out, &all).unwrap();
+        assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"alpha");
+        assert!(native::list(&out.join("a.txt")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_format_mtime() {
+        assert_eq!(format_mtime(0), "1970-01-01 00:00");
+        assert_eq!(format_mtime(951_782_400), "2000-02-29 00:00");
+        assert_eq!(format_mtime(-60), "1969-12-31 23:59");
+    }
+}
@@ -0,0 +1,202 @@
+//! Local record of failed extraction attempts, for containers with an attempt policy.
+//! Kept at `$XDG_STATE_HOME/hypercube/attempts.json`, then `~/.local/state/hypercube/`.
+//! Entries are keyed by a hash of the container id, so the file lists no ids itself.
+
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::file_mode::write_private_file;
+use crate::header::{AttemptPolicy, VhcHeader};
+use crate::vhc::replace_file;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Failed attempts against one container since its last successful extraction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Failures {
+    pub count: u32,
+    /// Milliseconds since the Unix epoch of the latest failure
+    pub last_ms: u64,
+}
+
+/// The attempt log file, loaded whole
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AttemptLog {
+    #[serde(default)]
+    pub containers: BTreeMap<String, Failures>,
+}
+
+impl AttemptLog {
+    /// Load the log; a missing file is an empty log
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_vec_pretty(self)?;
+        replace_file(path, |temp| write_private_file(temp, &data))
+    }
+}
+
+/// `$XDG_STATE_HOME/hypercube/attempts.json`, falling back to `~/.local/state`
+pub fn default_attempt_log_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(base.join("hypercube").join("attempts.json"))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Run an extraction attempt under the container's attempt policy, recorded in `log_path`
+/// The attempt is refused while the backoff from earlier failures runs, and `derive`
+/// (the key derivation) is padded to the policy's minimum time. A secret that opens no
+/// blocks, and other integrity errors, count as failures; success clears the record.
+pub fn with_attempt_policy<K, T>(
+    log_path: &Path,
+    header: &VhcHeader,
+    derive: impl FnOnce() -> Result<K>,
+    attempt: impl FnOnce(K) -> Result<T>,
+) -> Result<T> {
+    let Some(policy) = header.attempt_policy else {
+        return attempt(derive()?);
+    };
+    // Containers without an id cannot be told apart, so only the minimum time applies
+    let entry = header
+        .uuid
+        .map(|id| hex::encode(&blake3::derive_key(domains::ATTEMPT_LOG, &id.0)[..16]));
+    if let Some(entry) = &entry {
+        let failures = AttemptLog::load(log_path)?
+            .containers
+            .get(entry)
+            .copied()
+            .unwrap_or_default();
+        check_backoff(&policy, failures, now_ms())?;
+    }
+
+    let started = Instant::now();
+    let key = derive();
+    let minimum = Duration::from_millis(policy.min_kdf_ms.into());
+    if let Some(rest) = minimum.checked_sub(started.elapsed()) {
+        std::thread::sleep(rest);
+    }
+    let result = key.and_then(attempt);
+
+    let Some(entry) = entry else {
+        return result;
+    };
+    let mut log = AttemptLog::load(log_path)?;
+    match &result {
+        Err(HypercubeError::IntegrityError(_) | HypercubeError::NoAuthenticatedBlocks) => {
+            let failures = log.containers.entry(entry).or_default();
+            failures.count = failures.count.saturating_add(1);
+            failures.last_ms = now_ms();
+        }
+        Ok(_) if log.containers.remove(&entry).is_some() => {}
+        _ => return result,
+    }
+    log.save(log_path)?;
+    result
+}
+
+/// Refuse an attempt made before the backoff after `failures` has run out
+fn check_backoff(policy: &AttemptPolicy, failures: Failures, now_ms: u64) -> Result<()> {
+    let wait = policy.backoff(failures.count).as_millis() as u64;
+    let ready_ms = failures.last_ms.saturating_add(wait);
+    if now_ms < ready_ms {
+        return Err(HypercubeError::AttemptBackoff {
+            failures: failures.count,
+            retry_secs: (ready_ms - now_ms).div_ceil(1000),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::ContainerId;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_attempt_policy() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("state/attempts.json");
+        let mut header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+        header.uuid = Some(ContainerId([7; 16]));
+        header.attempt_policy = Some(AttemptPolicy {
+            min_kdf_ms: 20,
+            backoff_ms: 60_000,
+        });
+        let wrong = || Err::<(), _>(HypercubeError::NoAuthenticatedBlocks);
+
+        // The derivation is padded to the minimum
+        let started = Instant::now();
+        with_attempt_policy(&log, &header, || Ok(()), |_| Ok(())).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert!(!log.exists());
+
+        // A failure is recorded, and the next attempt has to wait out the backoff
+        assert!(with_attempt_policy(&log, &header, || Ok(()), |_| wrong()).is_err());
+        let entries = AttemptLog::load(&log).unwrap().containers;
+        assert_eq!(entries.values().next().unwrap().count, 1);
+        assert!(!std::fs::read_to_string(&log)
+            .unwrap()
+            .contains(&hex::encode([7; 16])));
+        let refused = with_attempt_policy(&log, &header, || Ok(()), |_| Ok(()));
+        assert!(matches!(
+            refused,
+            Err(HypercubeError::AttemptBackoff {
+                failures: 1,
+                retry_secs: 60
+            })
+        ));
+
+        // The backoff doubles with each failure, up to a day
+        let policy = header.attempt_policy.unwrap();
+        assert_eq!(policy.backoff(3), Duration::from_secs(240));
+        assert_eq!(
+            policy.backoff(40).as_millis() as u64,
+            AttemptPolicy::MAX_BACKOFF_MS
+        );
+        let failures = Failures {
+            count: 2,
+            last_ms: 1_000,
+        };
+        assert!(check_backoff(&policy, failures, 120_999).is_err());
+        check_backoff(&policy, failures, 121_000).unwrap();
+
+        // Other errors are not failed guesses, and a success clears the record
+        std::fs::write(&log, r#"{"containers": {}}"#).unwrap();
+        let io = || Err::<(), _>(HypercubeError::Io(std::io::ErrorKind::NotFound.into()));
+        assert!(with_attempt_policy(&log, &header, || Ok(()), |_| io()).is_err());
+        assert!(with_attempt_policy(&log, &header, || Ok(()), |_| wrong()).is_err());
+        let mut cleared = AttemptLog::load(&log).unwrap();
+        assert_eq!(cleared.containers.values().next().unwrap().count, 1);
+        cleared
+            .containers
+            .values_mut()
+            .for_each(|failures| failures.last_ms = 0);
+        cleared.save(&log).unwrap();
+        with_attempt_policy(&log, &header, || Ok(()), |_| Ok(())).unwrap();
+        assert!(AttemptLog::load(&log).unwrap().containers.is_empty());
+
+        // Without a policy nothing is checked or recorded
+        header.attempt_policy = None;
+        with_attempt_policy(&log, &header, || Ok(()), |_| Ok(())).unwrap();
+        assert!(with_attempt_policy(&log, &header, || Ok(()), |_| wrong()).is_err());
+        assert!(AttemptLog::load(&log).unwrap().containers.is_empty());
+    }
+}
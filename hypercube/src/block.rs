@@ -0,0 +1,81 @@
+//! Typed view of a stored block.
+//!
+//! On disk every block is `sequence || payload || MAC`, with a 16-byte sequence, a
+//! `block_size` payload and a `mac_bits / 8` MAC. `BlockRef` splits a block along
+//! those lines once, so callers name fields instead of slicing at offsets.
+
+use crate::header::VhcHeader;
+use crate::pipeline::{SequenceNumber, SEQUENCE_SIZE};
+
+/// A stored block split into its fields, borrowed from a mapping or buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRef<'a> {
+    /// Position in the container's block table
+    pub index: usize,
+    /// Byte offset of the block from the start of the file
+    pub offset: u64,
+    /// Sequence field (masked generation tag included)
+    pub sequence: &'a [u8; SEQUENCE_SIZE],
+    /// Transformed data the MAC covers after the sequence
+    pub payload: &'a [u8],
+    /// Authentication tag
+    pub mac: &'a [u8],
+}
+
+impl<'a> BlockRef<'a> {
+    /// Split `bytes` with the header's block and MAC sizes
+    /// Returns None unless `bytes` is exactly one block long
+    pub fn parse(bytes: &'a [u8], index: usize, offset: u64, header: &VhcHeader) -> Option<Self> {
+        if bytes.len() != header.total_block_size() {
+            return None;
+        }
+        let (sequence, rest) = bytes.split_at(SEQUENCE_SIZE);
+        let (payload, mac) = rest.split_at(header.block_size);
+        Some(Self {
+            index,
+            offset,
+            sequence: sequence.try_into().ok()?,
+            payload,
+            mac,
+        })
+    }
+
+    /// The sequence field as a number
+    pub fn sequence_number(&self) -> u128 {
+        SequenceNumber::from_bytes(*self.sequence).to_u128()
+    }
+
+    /// Whole block length: sequence, payload and MAC
+    pub fn size(&self) -> usize {
+        SEQUENCE_SIZE + self.payload.len() + self.mac.len()
+    }
+
+    /// Offset of the MAC within the block
+    pub fn mac_offset(&self) -> usize {
+        SEQUENCE_SIZE + self.payload.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block_ref() {
+        let header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+        let mut bytes = vec![0xAAu8; header.total_block_size()];
+        bytes[..SEQUENCE_SIZE].copy_from_slice(&7u128.to_le_bytes());
+        bytes[SEQUENCE_SIZE + 64..].fill(0xCC);
+
+        let block = BlockRef::parse(&bytes, 3, 1000, &header).unwrap();
+        assert_eq!((block.index, block.offset), (3, 1000));
+        assert_eq!(block.sequence_number(), 7);
+        assert_eq!(block.payload, &[0xAA; 64][..]);
+        assert_eq!(block.mac, &[0xCC; 32][..]);
+        assert_eq!(block.size(), bytes.len());
+        assert_eq!(block.mac_offset(), SEQUENCE_SIZE + 64);
+
+        assert!(BlockRef::parse(&bytes[1..], 3, 1000, &header).is_none());
+        assert!(BlockRef::parse(&[], 0, 0, &header).is_none());
+    }
+}
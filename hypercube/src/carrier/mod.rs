@@ -0,0 +1,56 @@
+//! Carrier formats that smuggle a container inside an ordinary file.
+//! The output still opens normally in standard tools; `dig` recovers the payload.
+
+pub mod pdf;
+pub mod zip;
+
+use crate::error::{HypercubeError, Result};
+
+/// A file format that can carry an opaque payload
+pub trait Carrier: Sync {
+    /// Short name used on the command line
+    fn name(&self) -> &'static str;
+
+    /// Whether `data` looks like a file of this format
+    fn detect(&self, data: &[u8]) -> bool;
+
+    /// A copy of `carrier` that also carries `payload`
+    fn embed(&self, carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>>;
+
+    /// The payload embedded by `embed`, or None if there is none
+    fn dig(&self, data: &[u8]) -> Result<Option<Vec<u8>>>;
+}
+
+/// All registered carriers; new formats only need an entry here
+pub static CARRIERS: &[&dyn Carrier] = &[&zip::ZipCarrier, &pdf::PdfCarrier];
+
+/// Look up a carrier by name
+pub fn carrier_by_name(name: &str) -> Result<&'static dyn Carrier> {
+    CARRIERS
+        .iter()
+        .copied()
+        .find(|carrier| carrier.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| HypercubeError::UnsupportedAlgorithm(format!("carrier: {}", name)))
+}
+
+/// The carrier whose format matches `data`
+pub fn detect_carrier(data: &[u8]) -> Option<&'static dyn Carrier> {
+    CARRIERS
+        .iter()
+        .copied()
+        .find(|carrier| carrier.detect(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_carrier_lookup() {
+        assert_eq!(carrier_by_name("ZIP").unwrap().name(), "zip");
+        assert_eq!(carrier_by_name("pdf").unwrap().name(), "pdf");
+        assert!(carrier_by_name("png").is_err());
+        assert_eq!(detect_carrier(b"%PDF-1.4\n").unwrap().name(), "pdf");
+        assert!(detect_carrier(b"plain text").is_none());
+    }
+}
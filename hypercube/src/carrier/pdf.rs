@@ -0,0 +1,235 @@
+use super::Carrier;
+use crate::error::{HypercubeError, Result};
+
+/// PDF headers may be preceded by junk, but only within the first KiB
+const HEADER_WINDOW: usize = 1024;
+
+/// Trailer keys carried over into the update's trailer
+const INHERITED_REFS: &[&[u8]] = &[b"/Root", b"/Info", b"/Encrypt"];
+
+/// PDF documents: the payload becomes an unreferenced stream object added in an
+/// incremental update (new object, one-entry xref section and trailer with
+/// /Prev), which every conforming reader accepts and ignores
+/// Layout: [original PDF][N 0 obj << /Length L >> stream...][xref][trailer]
+pub struct PdfCarrier;
+
+impl Carrier for PdfCarrier {
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        find(&data[..data.len().min(HEADER_WINDOW)], b"%PDF-").is_some()
+    }
+
+    fn embed(&self, carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+        let (startxref, prev) = last_startxref(carrier)
+            .ok_or_else(|| HypercubeError::InvalidFormat("No PDF startxref".into()))?;
+        let trailer = trailer_dict(carrier, prev, startxref)
+            .ok_or_else(|| HypercubeError::InvalidFormat("No PDF trailer".into()))?;
+        let size = dict_int(trailer, b"/Size")
+            .ok_or_else(|| HypercubeError::InvalidFormat("PDF trailer has no /Size".into()))?;
+
+        let mut output = carrier.to_vec();
+        if !output.ends_with(b"\n") {
+            output.push(b'\n');
+        }
+
+        let object = output.len();
+        output.extend_from_slice(
+            format!("{} 0 obj\n<< /Length {} >>\nstream\n", size, payload.len()).as_bytes(),
+        );
+        output.extend_from_slice(payload);
+        output.extend_from_slice(b"\nendstream\nendobj\n");
+
+        let xref = output.len();
+        let mut new_trailer = format!("/Size {} /Prev {}", size + 1, prev);
+        for key in INHERITED_REFS {
+            if let Some(value) = dict_ref(trailer, key) {
+                new_trailer.push_str(&format!(" {} {}", String::from_utf8_lossy(key), value));
+            }
+        }
+        if let Some(id) = dict_array(trailer, b"/ID") {
+            new_trailer.push_str(&format!(" /ID {}", String::from_utf8_lossy(id)));
+        }
+        output.extend_from_slice(
+            format!(
+                "xref\n{} 1\n{:010} 00000 n \ntrailer\n<< {} >>\nstartxref\n{}\n%%EOF\n",
+                size, object, new_trailer, xref
+            )
+            .as_bytes(),
+        );
+        Ok(output)
+    }
+
+    fn dig(&self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(last_startxref(data).and_then(|(_, xref)| dig_update(data, xref)))
+    }
+}
+
+/// Payload of the stream object listed in a one-entry xref section at `xref`
+fn dig_update(data: &[u8], xref: usize) -> Option<Vec<u8>> {
+    let section = data.get(xref..)?.strip_prefix(b"xref\n")?;
+    let (number, rest) = read_int(section)?;
+    let rest = rest.strip_prefix(b" 1\n")?;
+    let (object, rest) = read_int(rest)?;
+    rest.strip_prefix(b" 00000 n \ntrailer")?;
+
+    let header = format!("{} 0 obj\n<< /Length ", number);
+    let body = data.get(object..)?.strip_prefix(header.as_bytes())?;
+    let (len, body) = read_int(body)?;
+    let body = body.strip_prefix(b" >>\nstream\n")?;
+    let payload = body.get(..len)?;
+    body[len..]
+        .starts_with(b"\nendstream")
+        .then(|| payload.to_vec())
+}
+
+/// Position of the last `startxref` keyword and the offset it records
+fn last_startxref(data: &[u8]) -> Option<(usize, usize)> {
+    let pos = rfind(data, b"startxref")?;
+    let (offset, _) = read_int(skip_whitespace(&data[pos + b"startxref".len()..]))?;
+    Some((pos, offset))
+}
+
+/// Trailer dictionary text of the xref section (or xref stream) at `xref`
+fn trailer_dict(data: &[u8], xref: usize, end: usize) -> Option<&[u8]> {
+    let section = data.get(xref..end)?;
+    let start = if section.starts_with(b"xref") {
+        find(section, b"trailer")?
+    } else {
+        // Cross-reference stream: its dictionary holds the trailer keys
+        find(section, b"obj")?
+    };
+    Some(&section[start..])
+}
+
+/// Integer value of `key` in a dictionary
+fn dict_int(dict: &[u8], key: &[u8]) -> Option<usize> {
+    let value = dict_value(dict, key)?;
+    read_int(value).map(|(n, _)| n)
+}
+
+/// `N G R` reference value of `key`, as text
+fn dict_ref(dict: &[u8], key: &[u8]) -> Option<String> {
+    let value = dict_value(dict, key)?;
+    let (number, rest) = read_int(value)?;
+    let (generation, rest) = read_int(skip_whitespace(rest))?;
+    skip_whitespace(rest)
+        .starts_with(b"R")
+        .then(|| format!("{} {} R", number, generation))
+}
+
+/// `[...]` array value of `key`, verbatim
+fn dict_array<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let value = dict_value(dict, key)?;
+    if !value.starts_with(b"[") {
+        return None;
+    }
+    let end = value.iter().position(|&b| b == b']')?;
+    Some(&value[..=end])
+}
+
+/// Text following `key` (a whole name, not a prefix of a longer one)
+fn dict_value<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let mut from = 0;
+    while let Some(pos) = find(&dict[from..], key) {
+        let after = from + pos + key.len();
+        match dict.get(after) {
+            Some(b) if b.is_ascii_alphanumeric() => from = after,
+            _ => return Some(skip_whitespace(&dict[after..])),
+        }
+    }
+    None
+}
+
+fn read_int(data: &[u8]) -> Option<(usize, &[u8])> {
+    let digits = data.iter().take_while(|b| b.is_ascii_digit()).count();
+    let value = std::str::from_utf8(&data[..digits]).ok()?.parse().ok()?;
+    Some((value, &data[digits..]))
+}
+
+fn skip_whitespace(data: &[u8]) -> &[u8] {
+    let n = data.iter().take_while(|b| b.is_ascii_whitespace()).count();
+    &data[n..]
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal one-page document with a classic xref table
+    fn minimal_pdf() -> Vec<u8> {
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>",
+        ];
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(b"xref\n0 4\n0000000000 65535 f \n");
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<< /Size 4 /Root 1 0 R /ID [<00ff><00ff>] >>\nstartxref\n{}\n%%EOF\n",
+                xref
+            )
+            .as_bytes(),
+        );
+        pdf
+    }
+
+    #[test]
+    fn test_pdf_embed_and_dig() {
+        let pdf = minimal_pdf();
+        assert!(PdfCarrier.detect(&pdf));
+        assert_eq!(PdfCarrier.dig(&pdf).unwrap(), None);
+
+        let payload = b"binary\nendstream\x00\xffpayload".to_vec();
+        let polyglot = PdfCarrier.embed(&pdf, &payload).unwrap();
+        assert!(polyglot.starts_with(&pdf));
+        assert_eq!(PdfCarrier.dig(&polyglot).unwrap(), Some(payload));
+
+        let tail = String::from_utf8_lossy(&polyglot[polyglot.len() - 120..]).into_owned();
+        assert!(tail.contains("/Size 5"));
+        assert!(tail.contains("/Root 1 0 R"));
+        assert!(tail.contains("/ID [<00ff><00ff>]"));
+        assert!(tail.ends_with("%%EOF\n"));
+    }
+
+    #[test]
+    fn test_pdf_xref_offsets_are_exact() {
+        let polyglot = PdfCarrier.embed(&minimal_pdf(), b"payload").unwrap();
+        let (_, xref) = last_startxref(&polyglot).unwrap();
+        assert!(polyglot[xref..].starts_with(b"xref\n4 1\n"));
+        let (object, _) = read_int(&polyglot[xref + 9..]).unwrap();
+        assert!(polyglot[object..].starts_with(b"4 0 obj"));
+        let (_, prev) = last_startxref(&minimal_pdf()).unwrap();
+        assert!(find(&polyglot[xref..], format!("/Prev {}", prev).as_bytes()).is_some());
+    }
+
+    #[test]
+    fn test_dict_lookups() {
+        let dict = b"<< /Size 12 /Root 3 0 R /RootX 9 /Info 7 0 R >>";
+        assert_eq!(dict_int(dict, b"/Size"), Some(12));
+        assert_eq!(dict_ref(dict, b"/Root").as_deref(), Some("3 0 R"));
+        assert_eq!(dict_ref(dict, b"/Info").as_deref(), Some("7 0 R"));
+        assert_eq!(dict_ref(dict, b"/Encrypt"), None);
+        assert!(PdfCarrier.embed(b"%PDF-1.4\nno xref", b"x").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use super::Carrier;
+use crate::error::{HypercubeError, Result};
+
+/// End of central directory record signature and fixed size
+const EOCD_SIGNATURE: &[u8; 4] = b"PK\x05\x06";
+const EOCD_SIZE: usize = 22;
+
+/// ZIP64 end of central directory locator signature
+const ZIP64_LOCATOR_SIGNATURE: &[u8; 4] = b"PK\x06\x07";
+
+const LOCAL_HEADER_SIGNATURE: &[u8; 4] = b"PK\x03\x04";
+
+/// ZIP archives: the payload is appended after the central directory,
+/// followed by a relocated copy of the directory and end record.
+/// Readers locate the archive through the final end record, so the original
+/// directory and the payload become ignored slack between file data and directory
+/// Layout: [original archive][payload][central directory copy][end record]
+pub struct ZipCarrier;
+
+impl Carrier for ZipCarrier {
+    fn name(&self) -> &'static str {
+        "zip"
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        (data.starts_with(LOCAL_HEADER_SIGNATURE) || data.starts_with(EOCD_SIGNATURE))
+            && find_eocd(data).is_some()
+    }
+
+    fn embed(&self, carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+        let eocd = find_eocd(carrier)
+            .ok_or_else(|| HypercubeError::InvalidFormat("No ZIP end record".into()))?;
+        eocd.check_supported(carrier)?;
+        let directory = &carrier[eocd.cd_offset..eocd.cd_offset + eocd.cd_size];
+
+        let new_offset = u32::try_from(carrier.len() + payload.len())
+            .map_err(|_| HypercubeError::InvalidFormat("ZIP carrier would exceed 4 GiB".into()))?;
+
+        let mut output =
+            Vec::with_capacity(carrier.len() + payload.len() + directory.len() + EOCD_SIZE);
+        output.extend_from_slice(carrier);
+        output.extend_from_slice(payload);
+        output.extend_from_slice(directory);
+        let mut record = carrier[eocd.pos..].to_vec();
+        record[16..20].copy_from_slice(&new_offset.to_le_bytes());
+        output.extend_from_slice(&record);
+        Ok(output)
+    }
+
+    fn dig(&self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(last) = find_eocd(data) else {
+            return Ok(None);
+        };
+        let directory = &data[last.cd_offset..last.cd_offset + last.cd_size];
+        let comment = &data[last.pos + EOCD_SIZE..];
+
+        // The original end record points at an identical directory and ends
+        // right where the payload starts
+        let original = (0..last.cd_offset.saturating_sub(EOCD_SIZE - 1))
+            .filter(|&pos| data[pos..].starts_with(EOCD_SIGNATURE))
+            .filter_map(|pos| parse_eocd(data, pos))
+            .find(|eocd| {
+                eocd.cd_offset + eocd.cd_size == eocd.pos
+                    && &data[eocd.cd_offset..eocd.pos] == directory
+                    && data[eocd.pos + EOCD_SIZE..].starts_with(comment)
+            });
+
+        Ok(original.map(|eocd| {
+            let start = eocd.pos + EOCD_SIZE + comment.len();
+            data[start.min(last.cd_offset)..last.cd_offset].to_vec()
+        }))
+    }
+}
+
+/// Fields of an end of central directory record
+struct Eocd {
+    pos: usize,
+    entries: u16,
+    cd_size: usize,
+    cd_offset: usize,
+}
+
+impl Eocd {
+    /// Multi-disk and ZIP64 archives keep directory data elsewhere
+    fn check_supported(&self, data: &[u8]) -> Result<()> {
+        let zip64 = self.entries == u16::MAX
+            || self.cd_size == u32::MAX as usize
+            || self.cd_offset == u32::MAX as usize
+            || (self.pos >= 20 && data[self.pos - 20..].starts_with(ZIP64_LOCATOR_SIGNATURE));
+        if zip64 {
+            return Err(HypercubeError::UnsupportedAlgorithm("ZIP64 carrier".into()));
+        }
+        if data[self.pos + 4..self.pos + 8] != [0, 0, 0, 0] {
+            return Err(HypercubeError::UnsupportedAlgorithm(
+                "multi-disk ZIP carrier".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parse the end record at `pos` if its directory lies within the data
+fn parse_eocd(data: &[u8], pos: usize) -> Option<Eocd> {
+    let record = data.get(pos..pos + EOCD_SIZE)?;
+    let u16_at = |i: usize| u16::from_le_bytes([record[i], record[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes(record[i..i + 4].try_into().unwrap()) as usize;
+
+    let eocd = Eocd {
+        pos,
+        entries: u16_at(10),
+        cd_size: u32_at(12),
+        cd_offset: u32_at(16),
+    };
+    (eocd.cd_offset.checked_add(eocd.cd_size)? <= pos).then_some(eocd)
+}
+
+/// Find the end record whose comment runs exactly to the end of the data
+fn find_eocd(data: &[u8]) -> Option<Eocd> {
+    let last = data.len().checked_sub(EOCD_SIZE)?;
+    let first = last.saturating_sub(u16::MAX as usize);
+    (first..=last).rev().find_map(|pos| {
+        if !data[pos..].starts_with(EOCD_SIGNATURE) {
+            return None;
+        }
+        let comment_len = u16::from_le_bytes([data[pos + 20], data[pos + 21]]) as usize;
+        if pos + EOCD_SIZE + comment_len != data.len() {
+            return None;
+        }
+        parse_eocd(data, pos)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single stored file, as written by `zip -0`
+    fn stored_zip(name: &str, contents: &[u8], comment: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let fields = |buf: &mut Vec<u8>| {
+            buf.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]); // flags, method, time, date
+            buf.extend_from_slice(&[0, 0, 0, 0]); // crc (unchecked here)
+            buf.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            buf.extend_from_slice(&[0, 0]); // extra length
+        };
+
+        data.extend_from_slice(LOCAL_HEADER_SIGNATURE);
+        data.extend_from_slice(&[20, 0]);
+        fields(&mut data);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(contents);
+
+        let cd_offset = data.len();
+        data.extend_from_slice(b"PK\x01\x02");
+        data.extend_from_slice(&[20, 0, 20, 0]);
+        fields(&mut data);
+        data.extend_from_slice(&[0; 12]); // comment, disk, attributes, local offset 0
+        data.extend_from_slice(name.as_bytes());
+        let cd_size = data.len() - cd_offset;
+
+        data.extend_from_slice(EOCD_SIGNATURE);
+        data.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+        data.extend_from_slice(&(cd_size as u32).to_le_bytes());
+        data.extend_from_slice(&(cd_offset as u32).to_le_bytes());
+        data.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+        data.extend_from_slice(comment);
+        data
+    }
+
+    #[test]
+    fn test_zip_embed_and_dig() {
+        let archive = stored_zip("hello.txt", b"Hello, World!", b"");
+        assert!(ZipCarrier.detect(&archive));
+        assert_eq!(ZipCarrier.dig(&archive).unwrap(), None);
+
+        let payload = b"VHC\x01 payload bytes PK\x05\x06".to_vec();
+        let polyglot = ZipCarrier.embed(&archive, &payload).unwrap();
+        assert!(ZipCarrier.detect(&polyglot));
+        assert_eq!(ZipCarrier.dig(&polyglot).unwrap(), Some(payload));
+
+        // The live directory sits right before the final end record
+        let eocd = find_eocd(&polyglot).unwrap();
+        assert_eq!(eocd.cd_offset + eocd.cd_size, eocd.pos);
+        assert_eq!(&polyglot[..archive.len()], &archive[..]);
+    }
+
+    #[test]
+    fn test_zip_comment_preserved() {
+        let archive = stored_zip("a", b"a", b"archive comment");
+        let polyglot = ZipCarrier.embed(&archive, b"payload").unwrap();
+        assert!(polyglot.ends_with(b"archive comment"));
+        assert_eq!(ZipCarrier.dig(&polyglot).unwrap().unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_zip_rejects_non_archives() {
+        assert!(!ZipCarrier.detect(b"PK\x03\x04 truncated"));
+        assert!(ZipCarrier.embed(b"not a zip", b"payload").is_err());
+    }
+}
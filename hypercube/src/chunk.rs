@@ -0,0 +1,932 @@
+//! Content-defined chunking inside a partition.
+//!
+//! A chunked partition is a set of block groups. Each group is sealed like a small
+//! partition of its own: AONT, fragment shuffle, a fresh sequence base and MACs under the
+//! partition secret. There is one group per chunk, a manifest group that lists the chunks
+//! in order, and padding groups that fill the partition out to `blocks_per_partition`.
+//! Padding is sealed as groups of one data block each, separate from the manifest, so an
+//! update whose chunks need more or fewer blocks only adds or drops that many groups.
+//!
+//! Chunk boundaries come from a gear rolling hash keyed by the partition secret, so an
+//! edit only moves the boundaries near it. `update` re-seals the chunks that changed and
+//! the manifest, and leaves every other group's blocks where they are.
+//!
+//! In a deduplicating container the gear is keyed by a container-wide dedup key instead,
+//! and every chunk group is sealed under a key derived from the chunk and that dedup key.
+//! A partition that meets a chunk some partition already stored lists the existing group
+//! rather than sealing it again. The manifest carries each chunk's key, so reading needs
+//! only the partition secret. The shared groups a partition stored (its held groups) count
+//! towards its size for good: another partition may list them, so neither `update` nor
+//! `wipe` frees them.
+
+use crate::domains::Domains;
+use crate::error::{HypercubeError, Result};
+use crate::header::VhcHeader;
+use crate::metrics::{self, Stage};
+use crate::partition::{authenticate_block, decode_blocks, encode_blocks, CreatePartitionResult};
+use crate::pipeline::{codec_for, AuthenticatedBlock, SequenceNumber, SEQUENCE_SIZE};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const KIND_CHUNK: u8 = 0;
+const KIND_MANIFEST: u8 = 1;
+const KIND_PADDING: u8 = 2;
+
+/// Kind byte and u32 payload length in front of every group's payload
+const GROUP_PREFIX: usize = 5;
+
+/// Manifest prefix: u64 original size and u32 chunk count
+const MANIFEST_PREFIX: usize = 12;
+
+/// Manifest entry: group base, block count, chunk length and hash
+const ENTRY_SIZE: usize = SEQUENCE_SIZE + 4 + 4 + 32;
+
+/// A deduplicating manifest's entries also carry the key of their shared group
+const SHARED_ENTRY_SIZE: usize = ENTRY_SIZE + 32;
+
+/// One chunk as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkEntry {
+    /// Sequence number of the group's first block; identifies the group
+    pub base: u128,
+    /// Blocks the group occupies
+    pub blocks: usize,
+    /// Uncompressed chunk length
+    pub length: usize,
+    /// BLAKE3 of the uncompressed chunk, to recognise it on update
+    pub hash: [u8; 32],
+    /// Key a shared group is sealed under; None when it is under the partition secret
+    pub key: Option<[u8; 32]>,
+}
+
+/// A chunked partition as found in a container
+#[derive(Debug, Clone)]
+pub struct ChunkedPartition {
+    pub original_size: usize,
+    /// Chunks in data order; a repeated chunk may list the same group twice
+    pub chunks: Vec<ChunkEntry>,
+    /// Shared groups this partition stored, whether or not it still lists them
+    pub held: Vec<ChunkEntry>,
+    /// Block positions of every group under the partition secret, by base
+    pub groups: BTreeMap<u128, Vec<usize>>,
+    /// Bases of the padding groups
+    pub padding: Vec<u128>,
+    /// Compressed payload of every chunk group, by base
+    payloads: HashMap<u128, Vec<u8>>,
+}
+
+/// Container-wide key behind shared chunks, derived from the dedup secret
+#[derive(Clone)]
+pub struct DedupKey([u8; 32]);
+
+impl DedupKey {
+    /// `secret` is the dedup secret after the header's hardening (see `dedup_key`)
+    pub fn new(secret: &[u8], domains: &Domains) -> Self {
+        Self(blake3::derive_key(domains.chunk_dedup, secret))
+    }
+
+    /// Key the shared group of `chunk` is sealed under
+    fn chunk_key(&self, chunk: &[u8]) -> [u8; 32] {
+        *blake3::keyed_hash(&self.0, chunk).as_bytes()
+    }
+}
+
+/// Dedup key of a container from the dedup secret that every write to it must supply
+pub fn dedup_key(header: &VhcHeader, dedup_secret: Option<&str>) -> Result<DedupKey> {
+    let secret = dedup_secret.ok_or_else(missing_dedup_key)?;
+    Ok(DedupKey::new(
+        &header.partition_key(secret.as_bytes())?,
+        header.domains()?,
+    ))
+}
+
+fn missing_dedup_key() -> HypercubeError {
+    HypercubeError::InvalidFormat("A deduplicating container needs its dedup secret".into())
+}
+
+/// The dedup key to use for a header: required when it deduplicates, ignored otherwise
+fn require_dedup<'a>(
+    header: &VhcHeader,
+    dedup: Option<&'a DedupKey>,
+) -> Result<Option<&'a DedupKey>> {
+    match (header.dedup, dedup) {
+        (false, _) => Ok(None),
+        (true, Some(dedup)) => Ok(Some(dedup)),
+        (true, None) => Err(missing_dedup_key()),
+    }
+}
+
+/// Boundary rule for a block size: chunks of about four blocks, two at least, eight at most
+#[derive(Debug, Clone, Copy)]
+struct ChunkParams {
+    min: usize,
+    max: usize,
+    /// A boundary falls where the top `bits` bits of the rolling hash are zero
+    bits: u32,
+}
+
+impl ChunkParams {
+    fn new(block_size: usize) -> Self {
+        // Chunk lengths are stored as u32 in the manifest
+        let max = (8 * block_size).min(u32::MAX as usize);
+        Self {
+            min: (2 * block_size).min(max),
+            max,
+            bits: (2 * block_size).ilog2(),
+        }
+    }
+}
+
+/// Gear table keyed by a secret, so boundaries do not reveal content
+fn gear_table(secret: &[u8], domains: &Domains) -> [u64; 256] {
+    let mut bytes = [0u8; 256 * 8];
+    blake3::Hasher::new_derive_key(domains.chunk_gear)
+        .update(secret)
+        .finalize_xof()
+        .fill(&mut bytes);
+    std::array::from_fn(|i| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap()))
+}
+
+/// Gear table for a partition; shared chunks need the same boundaries in every partition
+fn partition_gear(
+    secret: &[u8],
+    dedup: Option<&DedupKey>,
+    header: &VhcHeader,
+) -> Result<[u64; 256]> {
+    let key = dedup.map_or(secret, |dedup| dedup.0.as_slice());
+    Ok(gear_table(key, header.domains()?))
+}
+
+/// Split data at content-defined boundaries; empty data is one empty chunk
+fn split_chunks<'a>(data: &'a [u8], gear: &[u64; 256], params: ChunkParams) -> Vec<&'a [u8]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut hash = 0u64;
+    for (i, &byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(gear[byte as usize]);
+        let len = i + 1 - start;
+        if len >= params.max || (len >= params.min && hash >> (64 - params.bits) == 0) {
+            chunks.push(&data[start..=i]);
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    if start < data.len() || chunks.is_empty() {
+        chunks.push(&data[start..]);
+    }
+    chunks
+}
+
+/// Blocks taken by the AONT key of every group
+fn key_blocks(header: &VhcHeader) -> usize {
+    header.blocks_per_partition() - header.data_blocks_per_partition()
+}
+
+/// Data blocks a group with a payload of `len` bytes needs
+fn data_blocks(len: usize, header: &VhcHeader) -> usize {
+    (GROUP_PREFIX + len).div_ceil(header.block_size)
+}
+
+fn entry_size(header: &VhcHeader) -> usize {
+    if header.dedup {
+        SHARED_ENTRY_SIZE
+    } else {
+        ENTRY_SIZE
+    }
+}
+
+/// Seal one group, padded to `data_blocks` data blocks
+fn encode_group(
+    kind: u8,
+    payload: &[u8],
+    data_blocks: usize,
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Result<Vec<Vec<u8>>> {
+    let len = u32::try_from(payload.len()).map_err(|_| HypercubeError::DataTooLarge {
+        data_size: payload.len(),
+        max_size: u32::MAX as usize,
+    })?;
+    let mut data = Vec::with_capacity(data_blocks * header.block_size);
+    data.push(kind);
+    data.extend_from_slice(&len.to_le_bytes());
+    data.extend_from_slice(payload);
+    data.resize(data_blocks * header.block_size, 0);
+    encode_blocks(&data, secret, header, None)
+}
+
+fn group_base(blocks: &[Vec<u8>]) -> u128 {
+    let mut bytes = [0u8; SEQUENCE_SIZE];
+    bytes.copy_from_slice(&blocks[0][..SEQUENCE_SIZE]);
+    SequenceNumber::from_bytes(bytes).to_u128()
+}
+
+/// Compress and seal one chunk, under its shared key if it has one
+/// Returns its entry, its group and its compressed size
+fn seal_chunk(
+    chunk: &[u8],
+    hash: [u8; 32],
+    secret: &[u8],
+    key: Option<[u8; 32]>,
+    header: &VhcHeader,
+) -> Result<(ChunkEntry, Vec<Vec<u8>>, usize)> {
+    let codec = codec_for(header)?;
+    let compressed = metrics::time(Stage::Compress, || codec.compress(chunk))?;
+    let group = encode_group(
+        KIND_CHUNK,
+        &compressed,
+        data_blocks(compressed.len(), header),
+        key.as_ref().map_or(secret, |key| key.as_slice()),
+        header,
+    )?;
+    let entry = ChunkEntry {
+        base: group_base(&group),
+        blocks: group.len(),
+        length: chunk.len(),
+        hash,
+        key,
+    };
+    Ok((entry, group, compressed.len()))
+}
+
+/// A group found in a container, opened
+struct Group {
+    base: u128,
+    positions: Vec<usize>,
+    kind: u8,
+    payload: Vec<u8>,
+}
+
+/// Find and open every group sealed under `secret`
+fn open_groups(
+    all_blocks: &[impl AsRef<[u8]>],
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Result<Vec<Group>> {
+    let mut authenticated: Vec<_> = metrics::time(Stage::Mac, || {
+        all_blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(position, block)| {
+                let block = authenticate_block(block.as_ref(), secret, header)?;
+                let sequence = SequenceNumber::from_bytes(block.sequence_bytes).to_u128();
+                Some((sequence, position, block))
+            })
+            .collect()
+    });
+
+    // Groups are runs of consecutive sequence numbers
+    authenticated.sort_by_key(|&(sequence, position, _)| (sequence, position));
+    let mut runs: Vec<Vec<(u128, usize, AuthenticatedBlock)>> = Vec::new();
+    for block in authenticated {
+        match runs.last_mut() {
+            Some(run) if run.last().unwrap().0.wrapping_add(1) == block.0 => run.push(block),
+            _ => runs.push(vec![block]),
+        }
+    }
+
+    runs.into_iter()
+        .map(|run| {
+            let base = run[0].0;
+            let positions = run.iter().map(|&(_, position, _)| position).collect();
+            let plain = decode_blocks(
+                run.into_iter().map(|(_, _, block)| block).collect(),
+                secret,
+                header,
+            )?;
+            let len = plain
+                .get(1..GROUP_PREFIX)
+                .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+                .ok_or_else(|| HypercubeError::IntegrityError("Chunk group too short".into()))?;
+            let payload = plain
+                .get(GROUP_PREFIX..GROUP_PREFIX + len)
+                .ok_or_else(|| HypercubeError::IntegrityError("Invalid chunk length".into()))?
+                .to_vec();
+            Ok(Group {
+                base,
+                positions,
+                kind: plain[0],
+                payload,
+            })
+        })
+        .collect()
+}
+
+/// A shared group that already holds `chunk`, sealed by any partition
+fn find_shared(
+    all_blocks: &[Vec<u8>],
+    chunk: &[u8],
+    hash: [u8; 32],
+    key: [u8; 32],
+    header: &VhcHeader,
+) -> Result<Option<ChunkEntry>> {
+    let codec = codec_for(header)?;
+    for group in open_groups(all_blocks, &key, header)? {
+        if group.kind != KIND_CHUNK {
+            continue;
+        }
+        let plain = codec.decompress(&group.payload, chunk.len());
+        if plain.is_ok_and(|plain| plain == chunk) {
+            return Ok(Some(ChunkEntry {
+                base: group.base,
+                blocks: group.positions.len(),
+                length: chunk.len(),
+                hash,
+                key: Some(key),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+fn encode_entry(manifest: &mut Vec<u8>, chunk: &ChunkEntry, header: &VhcHeader) {
+    manifest.extend_from_slice(&chunk.base.to_le_bytes());
+    manifest.extend_from_slice(&(chunk.blocks as u32).to_le_bytes());
+    manifest.extend_from_slice(&(chunk.length as u32).to_le_bytes());
+    manifest.extend_from_slice(&chunk.hash);
+    if header.dedup {
+        manifest.extend_from_slice(&chunk.key.unwrap_or_default());
+    }
+}
+
+fn decode_entry(entry: &[u8]) -> ChunkEntry {
+    ChunkEntry {
+        base: u128::from_le_bytes(entry[..SEQUENCE_SIZE].try_into().unwrap()),
+        blocks: u32::from_le_bytes(entry[16..20].try_into().unwrap()) as usize,
+        length: u32::from_le_bytes(entry[20..24].try_into().unwrap()) as usize,
+        hash: entry[24..ENTRY_SIZE].try_into().unwrap(),
+        key: (entry.len() == SHARED_ENTRY_SIZE).then(|| entry[ENTRY_SIZE..].try_into().unwrap()),
+    }
+}
+
+/// Original size, chunks in order and, when deduplicating, a count and list of held groups
+fn encode_manifest(
+    original_size: usize,
+    chunks: &[ChunkEntry],
+    held: &[ChunkEntry],
+    header: &VhcHeader,
+) -> Vec<u8> {
+    let mut manifest = Vec::with_capacity(manifest_size(chunks.len(), held.len(), header));
+    manifest.extend_from_slice(&(original_size as u64).to_le_bytes());
+    manifest.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+    for chunk in chunks {
+        encode_entry(&mut manifest, chunk, header);
+    }
+    if header.dedup {
+        manifest.extend_from_slice(&(held.len() as u32).to_le_bytes());
+        for group in held {
+            encode_entry(&mut manifest, group, header);
+        }
+    }
+    manifest
+}
+
+fn manifest_size(chunks: usize, held: usize, header: &VhcHeader) -> usize {
+    let held = if header.dedup {
+        4 + held * entry_size(header)
+    } else {
+        0
+    };
+    MANIFEST_PREFIX + chunks * entry_size(header) + held
+}
+
+/// The partition's original size, its chunks and its held groups
+type Manifest = (usize, Vec<ChunkEntry>, Vec<ChunkEntry>);
+
+fn decode_manifest(manifest: &[u8], header: &VhcHeader) -> Result<Manifest> {
+    let invalid = || HypercubeError::IntegrityError("Invalid chunk manifest".into());
+    let size = manifest.get(..8).ok_or_else(invalid)?;
+    let original_size =
+        usize::try_from(u64::from_le_bytes(size.try_into().unwrap())).map_err(|_| invalid())?;
+    let mut at = 8;
+    let mut entries = || -> Result<Vec<ChunkEntry>> {
+        let count = manifest.get(at..at + 4).ok_or_else(invalid)?;
+        let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+        let end = count
+            .checked_mul(entry_size(header))
+            .and_then(|len| len.checked_add(at + 4))
+            .ok_or_else(invalid)?;
+        let entries = manifest.get(at + 4..end).ok_or_else(invalid)?;
+        at = end;
+        Ok(entries
+            .chunks_exact(entry_size(header))
+            .map(decode_entry)
+            .collect())
+    };
+    let chunks = entries()?;
+    let held = if header.dedup { entries()? } else { Vec::new() };
+    if at != manifest.len() {
+        return Err(invalid());
+    }
+    Ok((original_size, chunks, held))
+}
+
+/// A chunked partition's new blocks, and the groups it keeps from before
+struct ChunkPlan {
+    blocks: Vec<Vec<u8>>,
+    reused: Vec<u128>,
+    /// Compressed bytes of the chunks sealed into `blocks`
+    compressed: usize,
+}
+
+impl ChunkPlan {
+    fn into_result(self, data: &[u8]) -> CreatePartitionResult {
+        CreatePartitionResult {
+            blocks: self.blocks,
+            original_size: data.len(),
+            compressed_size: self.compressed,
+        }
+    }
+}
+
+/// Chunk `data` and seal every chunk not already stored in a group of `previous` (or, when
+/// deduplicating, in a shared group anywhere in `all_blocks`), then seal a manifest and
+/// padding that fill the rest of the partition
+fn plan_chunks(
+    all_blocks: &[Vec<u8>],
+    data: &[u8],
+    secret: &[u8],
+    dedup: Option<&DedupKey>,
+    header: &VhcHeader,
+    previous: Option<&ChunkedPartition>,
+) -> Result<ChunkPlan> {
+    let dedup = require_dedup(header, dedup)?;
+    let gear = partition_gear(secret, dedup, header)?;
+    let previous_chunks = previous.map_or(&[][..], |partition| &partition.chunks);
+    let mut held = previous.map_or_else(Vec::new, |partition| partition.held.clone());
+    let mut stored: HashMap<[u8; 32], ChunkEntry> = previous_chunks
+        .iter()
+        .chain(&held)
+        .map(|chunk| (chunk.hash, chunk.clone()))
+        .collect();
+
+    let mut blocks = Vec::new();
+    let mut reused = Vec::new();
+    let mut chunks = Vec::new();
+    let mut compressed = 0;
+    // Held groups are never freed
+    let mut used: usize = held.iter().map(|group| group.blocks).sum();
+    for chunk in split_chunks(data, &gear, ChunkParams::new(header.block_size)) {
+        let hash = *blake3::hash(chunk).as_bytes();
+        let entry = match stored.get(&hash) {
+            Some(entry) if entry.length == chunk.len() => {
+                let own = entry.key.is_none() && previous_chunks.contains(entry);
+                if own && !reused.contains(&entry.base) {
+                    reused.push(entry.base);
+                    used += entry.blocks;
+                }
+                entry.clone()
+            }
+            _ => {
+                let key = dedup.map(|dedup| dedup.chunk_key(chunk));
+                let shared = match key {
+                    Some(key) => find_shared(all_blocks, chunk, hash, key, header)?,
+                    None => None,
+                };
+                let entry = match shared {
+                    Some(entry) => entry,
+                    None => {
+                        let (entry, group, size) = seal_chunk(chunk, hash, secret, key, header)?;
+                        compressed += size;
+                        if key.is_some() {
+                            held.push(entry.clone());
+                        }
+                        used += group.len();
+                        blocks.extend(group);
+                        entry
+                    }
+                };
+                stored.insert(hash, entry.clone());
+                entry
+            }
+        };
+        chunks.push(entry);
+    }
+
+    let manifest = encode_manifest(data.len(), &chunks, &held, header);
+    let needed = data_blocks(manifest.len(), header) + key_blocks(header);
+    let total = header.blocks_per_partition();
+    if used + needed > total {
+        return Err(HypercubeError::DataTooLarge {
+            data_size: (used + needed) * header.block_size,
+            max_size: total * header.block_size,
+        });
+    }
+
+    // Keep as much of the old padding as still fits, largest groups first (a partition
+    // sealed before padding was split has one group), and pad the rest with the smallest
+    // groups there are; a gap too small for a group of its own goes to the manifest
+    let mut manifest_blocks = needed - key_blocks(header);
+    let mut fill = total - used - needed;
+    if let Some(partition) = previous {
+        let mut padding: Vec<(usize, u128)> = partition
+            .padding
+            .iter()
+            .map(|base| (partition.groups[base].len(), *base))
+            .collect();
+        padding.sort_unstable_by(|a, b| b.cmp(a));
+        for (size, base) in padding {
+            if size <= fill {
+                fill -= size;
+                reused.push(base);
+            }
+        }
+    }
+    let group_blocks = key_blocks(header) + 1;
+    for _ in 0..fill / group_blocks {
+        blocks.extend(encode_group(KIND_PADDING, &[], 1, secret, header)?);
+    }
+    manifest_blocks += fill % group_blocks;
+    blocks.extend(encode_group(
+        KIND_MANIFEST,
+        &manifest,
+        manifest_blocks,
+        secret,
+        header,
+    )?);
+    Ok(ChunkPlan {
+        blocks,
+        reused,
+        compressed,
+    })
+}
+
+/// Blocks a chunked partition of `data` needs at the header's block size, manifest included
+/// Used to size a new container; the manifest counts at its smallest
+pub fn chunked_block_count(
+    data: &[u8],
+    secret: &[u8],
+    dedup: Option<&DedupKey>,
+    header: &VhcHeader,
+) -> Result<usize> {
+    let gear = partition_gear(secret, require_dedup(header, dedup)?, header)?;
+    let codec = codec_for(header)?;
+    let mut seen = Vec::new();
+    let mut blocks = 0;
+    let mut count = 0;
+    for chunk in split_chunks(data, &gear, ChunkParams::new(header.block_size)) {
+        count += 1;
+        let hash = blake3::hash(chunk);
+        if !seen.contains(&hash) {
+            seen.push(hash);
+            let compressed = codec.compress(chunk)?;
+            blocks += data_blocks(compressed.len(), header) + key_blocks(header);
+        }
+    }
+    let manifest = manifest_size(count, seen.len(), header);
+    Ok(blocks + data_blocks(manifest, header) + key_blocks(header))
+}
+
+/// Seal `data` as a chunked partition of exactly `blocks_per_partition` blocks
+pub fn create_chunked_partition(
+    data: &[u8],
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Result<CreatePartitionResult> {
+    let plan = plan_chunks(&[], data, secret, None, header, None)?;
+    Ok(plan.into_result(data))
+}
+
+/// Seal `data` as a partition of a deduplicating container, listing the shared groups
+/// already in `all_blocks` instead of storing their chunks again
+/// Returns exactly `blocks_per_partition` blocks: the shared groups it stored, its
+/// manifest and padding
+pub fn create_dedup_partition(
+    all_blocks: &[Vec<u8>],
+    data: &[u8],
+    secret: &[u8],
+    dedup: &DedupKey,
+    header: &VhcHeader,
+) -> Result<CreatePartitionResult> {
+    let plan = plan_chunks(all_blocks, data, secret, Some(dedup), header, None)?;
+    Ok(plan.into_result(data))
+}
+
+/// Find and open the groups of a chunked partition
+pub fn read_chunked_partition(
+    all_blocks: &[impl AsRef<[u8]>],
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Result<ChunkedPartition> {
+    let own = open_groups(all_blocks, secret, header)?;
+    if own.is_empty() {
+        return Err(HypercubeError::NoAuthenticatedBlocks);
+    }
+
+    let mut groups = BTreeMap::new();
+    let mut payloads = HashMap::new();
+    let mut manifest = None;
+    let mut padding = Vec::new();
+    for group in own {
+        match group.kind {
+            KIND_CHUNK => {
+                payloads.insert(group.base, group.payload);
+            }
+            KIND_MANIFEST if manifest.is_none() => manifest = Some(group.payload),
+            KIND_PADDING => padding.push(group.base),
+            KIND_MANIFEST => {
+                return Err(HypercubeError::IntegrityError(
+                    "Several chunk manifests".into(),
+                ))
+            }
+            kind => {
+                return Err(HypercubeError::IntegrityError(format!(
+                    "Unknown chunk group kind {}",
+                    kind
+                )))
+            }
+        }
+        groups.insert(group.base, group.positions);
+    }
+
+    let manifest =
+        manifest.ok_or_else(|| HypercubeError::IntegrityError("No chunk manifest found".into()))?;
+    let (original_size, chunks, held) = decode_manifest(&manifest, header)?;
+
+    // Shared groups are sealed under their chunk keys; open each key's groups once
+    let mut sizes: HashMap<u128, usize> = groups.iter().map(|(&b, p)| (b, p.len())).collect();
+    let mut opened = HashSet::new();
+    for key in chunks.iter().filter_map(|chunk| chunk.key) {
+        if !opened.insert(key) {
+            continue;
+        }
+        for group in open_groups(all_blocks, &key, header)? {
+            if group.kind == KIND_CHUNK {
+                sizes.insert(group.base, group.positions.len());
+                payloads.insert(group.base, group.payload);
+            }
+        }
+    }
+    for chunk in &chunks {
+        if sizes.get(&chunk.base) != Some(&chunk.blocks) || !payloads.contains_key(&chunk.base) {
+            return Err(HypercubeError::IntegrityError(
+                "Chunk listed in the manifest is missing".into(),
+            ));
+        }
+    }
+
+    Ok(ChunkedPartition {
+        original_size,
+        chunks,
+        held,
+        groups,
+        padding,
+        payloads,
+    })
+}
+
+/// Extract a chunked partition, refusing one that declares more than `max_size` bytes
+pub fn extract_chunked_partition(
+    all_blocks: &[impl AsRef<[u8]>],
+    secret: &[u8],
+    header: &VhcHeader,
+    max_size: usize,
+) -> Result<Vec<u8>> {
+    let codec = codec_for(header)?;
+    let partition = read_chunked_partition(all_blocks, secret, header)?;
+    if partition.original_size > max_size {
+        return Err(HypercubeError::DecompressionError(format!(
+            "Declared size {} exceeds the {} byte limit",
+            partition.original_size, max_size
+        )));
+    }
+
+    let mut data = Vec::with_capacity(partition.original_size);
+    for chunk in &partition.chunks {
+        if data.len() + chunk.length > partition.original_size {
+            return Err(HypercubeError::IntegrityError(
+                "Chunks exceed the partition size".into(),
+            ));
+        }
+        let payload = &partition.payloads[&chunk.base];
+        let plain = metrics::time(Stage::Decompress, || codec.decompress(payload, chunk.length))?;
+        if plain.len() != chunk.length || blake3::hash(&plain).as_bytes() != &chunk.hash {
+            return Err(HypercubeError::IntegrityError(
+                "Chunk does not match its manifest entry".into(),
+            ));
+        }
+        data.extend_from_slice(&plain);
+    }
+    if data.len() != partition.original_size {
+        return Err(HypercubeError::IntegrityError(
+            "Original size mismatch after reassembly".into(),
+        ));
+    }
+    Ok(data)
+}
+
+/// Re-seal a chunked partition for new data, keeping the groups of unchanged chunks
+/// (`dedup` is needed for a deduplicating container; held groups are never freed)
+/// Returns the positions to overwrite and as many blocks to write there
+pub fn update_chunked_partition(
+    all_blocks: &[Vec<u8>],
+    data: &[u8],
+    secret: &[u8],
+    dedup: Option<&DedupKey>,
+    header: &VhcHeader,
+) -> Result<(Vec<usize>, Vec<Vec<u8>>)> {
+    let current = read_chunked_partition(all_blocks, secret, header)?;
+    let plan = plan_chunks(all_blocks, data, secret, dedup, header, Some(&current))?;
+    let freed: Vec<usize> = current
+        .groups
+        .iter()
+        .filter(|(base, _)| !plan.reused.contains(base))
+        .flat_map(|(_, positions)| positions.iter().copied())
+        .collect();
+    if freed.len() != plan.blocks.len() {
+        return Err(HypercubeError::IntegrityError(format!(
+            "Partition holds {} blocks, expected {}",
+            all_blocks.len() - freed.len() + plan.blocks.len(),
+            header.blocks_per_partition()
+        )));
+    }
+    Ok((freed, plan.blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domains::V1;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    fn chunked_header() -> VhcHeader {
+        let mut header = VhcHeader::new(32, 32, 32, 512, 256).unwrap();
+        header.chunked = true;
+        header
+    }
+
+    /// Incompressible but repeatable data, so chunk boundaries are the same every run
+    fn random_data(len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        StdRng::seed_from_u64(len as u64).fill_bytes(&mut data);
+        data
+    }
+
+    #[test]
+    fn test_split_chunks() {
+        let data = random_data(20_000);
+        let params = ChunkParams::new(512);
+        let gear = gear_table(b"secret", &V1);
+        let chunks = split_chunks(&data, &gear, params);
+        assert_eq!(chunks.concat(), data);
+        assert!(chunks.len() > 4);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() >= params.min && chunk.len() <= params.max);
+        }
+
+        // An edit near the start leaves later boundaries where they were
+        let mut edited = data.clone();
+        edited[100] ^= 0xFF;
+        let after = split_chunks(&edited, &gear, params);
+        let same = after.iter().filter(|chunk| chunks.contains(chunk)).count();
+        assert!(same >= chunks.len() - 2, "{} of {}", same, chunks.len());
+
+        // Boundaries depend on the secret
+        let other = split_chunks(&data, &gear_table(b"other", &V1), params);
+        assert_ne!(
+            other.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            chunks.iter().map(|c| c.len()).collect::<Vec<_>>()
+        );
+
+        assert_eq!(split_chunks(&[], &gear, params), vec![&[] as &[u8]]);
+    }
+
+    #[test]
+    fn test_chunked_roundtrip() {
+        let header = chunked_header();
+        let data = random_data(8_000);
+        let blocks = create_chunked_partition(&data, b"secret", &header)
+            .unwrap()
+            .blocks;
+        assert_eq!(blocks.len(), header.blocks_per_partition());
+        assert!(chunked_block_count(&data, b"secret", None, &header).unwrap() <= blocks.len());
+
+        let partition = read_chunked_partition(&blocks, b"secret", &header).unwrap();
+        assert!(partition.chunks.len() > 1);
+        // One group per chunk, the manifest and (unless the chunks fill it) padding
+        let padding = partition.padding.len();
+        assert_eq!(partition.groups.len(), partition.chunks.len() + 1 + padding);
+        let max = usize::MAX;
+        assert_eq!(
+            extract_chunked_partition(&blocks, b"secret", &header, max).unwrap(),
+            data
+        );
+        assert!(extract_chunked_partition(&blocks, b"secret", &header, 100).is_err());
+        assert!(extract_chunked_partition(&blocks, b"wrong", &header, max).is_err());
+    }
+
+    #[test]
+    fn test_chunked_update_reuses_blocks() {
+        let header = chunked_header();
+        let data = random_data(8_000);
+        let mut blocks = create_chunked_partition(&data, b"secret", &header)
+            .unwrap()
+            .blocks;
+
+        let mut edited = data.clone();
+        edited[7_900] ^= 0xFF;
+        let (positions, fresh) =
+            update_chunked_partition(&blocks, &edited, b"secret", None, &header).unwrap();
+        assert_eq!(positions.len(), fresh.len());
+        assert!(
+            fresh.len() < blocks.len() / 2,
+            "rewrote {} blocks",
+            fresh.len()
+        );
+        for (position, block) in positions.into_iter().zip(fresh) {
+            blocks[position] = block;
+        }
+        let max = usize::MAX;
+        assert_eq!(
+            extract_chunked_partition(&blocks, b"secret", &header, max).unwrap(),
+            edited
+        );
+
+        // Growing by a chunk drops only as many padding groups as it needs blocks
+        let before = read_chunked_partition(&blocks, b"secret", &header).unwrap();
+        let mut grown = edited.clone();
+        grown.extend(random_data(1_000));
+        let (positions, fresh) =
+            update_chunked_partition(&blocks, &grown, b"secret", None, &header).unwrap();
+        for (position, block) in positions.into_iter().zip(fresh.iter().cloned()) {
+            blocks[position] = block;
+        }
+        let after = read_chunked_partition(&blocks, b"secret", &header).unwrap();
+        let kept = before.padding.iter().filter(|base| after.padding.contains(base));
+        assert_eq!(kept.count(), after.padding.len());
+        assert!(after.padding.len() < before.padding.len());
+        assert!(!after.padding.is_empty());
+        assert_eq!(
+            extract_chunked_partition(&blocks, b"secret", &header, max).unwrap(),
+            grown
+        );
+
+        // Data that no longer fits is refused
+        let too_big = random_data(64_000);
+        assert!(update_chunked_partition(&blocks, &too_big, b"secret", None, &header).is_err());
+    }
+
+    #[test]
+    fn test_repeated_chunks_share_a_group() {
+        let header = chunked_header();
+        // Constant data splits into identical chunks
+        let data = vec![7u8; 16_384];
+        let blocks = create_chunked_partition(&data, b"secret", &header)
+            .unwrap()
+            .blocks;
+        let partition = read_chunked_partition(&blocks, b"secret", &header).unwrap();
+        assert!(partition.chunks.len() >= 4);
+        let padding = partition.padding.len();
+        assert_eq!(partition.groups.len(), 1 + 1 + padding);
+        let max = usize::MAX;
+        assert_eq!(
+            extract_chunked_partition(&blocks, b"secret", &header, max).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_dedup_shares_chunks_across_partitions() {
+        let mut header = chunked_header();
+        header.dedup = true;
+        let dedup = DedupKey::new(b"dedup", &V1);
+        let max = usize::MAX;
+        let data = random_data(8_000);
+        let mut all = create_dedup_partition(&[], &data, b"alice", &dedup, &header)
+            .unwrap()
+            .blocks;
+        assert_eq!(all.len(), header.blocks_per_partition());
+        let alice = read_chunked_partition(&all, b"alice", &header).unwrap();
+        assert!(alice.chunks.iter().all(|chunk| chunk.key.is_some()));
+        assert_eq!(alice.held.len(), alice.chunks.len());
+
+        // Bob's copy differs near the end: he lists Alice's groups and stores the rest
+        let mut edited = data.clone();
+        edited[7_900] ^= 0xFF;
+        let bob = create_dedup_partition(&all, &edited, b"bob", &dedup, &header).unwrap();
+        all.extend(bob.blocks);
+        let bob = read_chunked_partition(&all, b"bob", &header).unwrap();
+        assert!(bob.held.len() <= 2, "{} held", bob.held.len());
+        assert!(bob.chunks.iter().any(|chunk| alice.held.contains(chunk)));
+        assert_eq!(
+            extract_chunked_partition(&all, b"bob", &header, max).unwrap(),
+            edited
+        );
+
+        // Alice's update never frees a group Bob may list
+        let (positions, fresh) =
+            update_chunked_partition(&all, b"new", b"alice", Some(&dedup), &header).unwrap();
+        for (position, block) in positions.into_iter().zip(fresh) {
+            all[position] = block;
+        }
+        assert_eq!(
+            extract_chunked_partition(&all, b"alice", &header, max).unwrap(),
+            b"new"
+        );
+        assert_eq!(
+            extract_chunked_partition(&all, b"bob", &header, max).unwrap(),
+            edited
+        );
+
+        assert!(create_chunked_partition(&data, b"carol", &header).is_err());
+    }
+}
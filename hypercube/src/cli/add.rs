@@ -1,10 +1,31 @@
-use crate::cli::seal::seal_file;
-use crate::partition::create_partition;
-use crate::cube::{analyze_data, CubeConfig};
+use crate::archive::{Archive, ArchiveOptions};
+use crate::chunk::{chunked_block_count, create_dedup_partition, dedup_key};
+use crate::cli::extract::{read_from_vhc, ExtractOptions};
+use crate::cli::seal::{keyed_chaff_blocks, seal_file_with_progress};
+use crate::partition::create_partition_with_trailer;
+use crate::cube::{
+    analyze_framed, recommend_block_size, required_block_size, CubeConfig, CubePreset,
+};
+use crate::envelope::with_envelope;
 use crate::error::{HypercubeError, Result};
-use crate::header::{Aont, Compression, HashAlgorithm, VhcHeader};
-use crate::vhc::{append_blocks_to_vhc, get_block_count, read_vhc_header, write_vhc_file, VhcFile};
-use std::path::Path;
+use crate::header::{
+    Aont, AttemptPolicy, Compression, HashAlgorithm, PipelineOrder, ReshufflePolicy, Shuffle,
+    StageIds, VhcHeader,
+};
+use crate::hint::BloomHint;
+use crate::honeypot;
+use crate::index::record_partition;
+use crate::kdf::{Kdf, KdfParams};
+use crate::metrics::{self, Stage, BYTES_ADDED};
+use crate::partition::find_partition_blocks;
+use crate::pipeline::{codec_for, codec_named, ErasureCode, SEQUENCE_NONCE_SIZE};
+use crate::progress::{Progress, ProgressSink};
+use crate::vhc::{
+    append_blocks_to_vhc, get_block_count, read_vhc_file, read_vhc_header, read_vhc_layout,
+    replace_blocks_in_vhc, rewrite_vhc_header, with_detached_header, write_vhc_file, VhcFile,
+    VhcLayout,
+};
+use std::path::{Path, PathBuf};
 
 /// Options for the add command
 #[derive(Debug, Clone)]
@@ -15,8 +36,77 @@ pub struct AddOptions {
     pub hash: HashAlgorithm,
     /// Hypercube dimension (N partitions × N blocks). Must be multiple of 8.
     pub dimension: usize,
+    /// Fixed block payload size for a new container; sized to the input when None
+    pub block_size: Option<usize>,
+    /// Partitions in a new container; the dimension when None
+    pub partitions: Option<usize>,
+    /// Blocks per partition in a new container; the dimension when None
+    pub blocks_per_partition: Option<usize>,
     pub mac_bits: usize,
     pub seal: bool,
+    /// Record this partition's block positions in the encrypted `.vhcidx` sidecar
+    pub index: bool,
+    /// Embed a keyed bloom filter over this partition's blocks in the header
+    pub hint: bool,
+    /// Create the container without magic bytes, keyed to `passphrase`
+    pub magicless: bool,
+    /// Container passphrase checked against a magicless container's verifier
+    pub passphrase: Option<String>,
+    /// Wrap the container in a random-looking envelope keyed to `passphrase`
+    pub deniable: bool,
+    /// Keep the header in this file, so the container itself starts with its first block
+    pub detached_header: Option<PathBuf>,
+    /// Maintain a Merkle tree over the blocks in the container trailer
+    pub merkle: bool,
+    /// Shuffle layers for a new container (an existing header keeps its own)
+    pub shuffle: Shuffle,
+    /// Reshuffle policy to record in the header; None keeps the existing one
+    pub reshuffle: Option<ReshufflePolicy>,
+    /// Secret hardening for a new container (an existing header keeps its own);
+    /// Argon2id by default, None to use secrets as given
+    pub kdf: Option<Kdf>,
+    /// Attempt policy for a new container (an existing header keeps its own)
+    pub attempt_policy: Option<AttemptPolicy>,
+    /// Create an append-only container that journals every write
+    pub append_only: bool,
+    /// Create a container that every write must leave at full capacity
+    pub always_full: bool,
+    /// Keys the top-up chaff of an always-full container so later adds can replace it
+    pub chaff_secret: Option<String>,
+    /// Size a new container with `recommend_block_size`, overriding dimension and block size
+    pub apply_recommendation: bool,
+    /// Compress a new container's partitions in seekable frames of this many bytes
+    pub frame_size: Option<u32>,
+    /// Split a new container's partitions into content-defined chunks for `update`
+    pub chunked: bool,
+    /// Store chunks shared by a new chunked container's partitions once
+    pub dedup: bool,
+    /// Derive a new container's sequence bases with HKDF from the key and a per-write
+    /// nonce from the OS, rather than from the thread RNG
+    pub derived_sequence: bool,
+    /// Keys a deduplicating container's shared chunks; needed for every add to it
+    pub dedup_secret: Option<String>,
+    /// Registered stage implementations for a new container (see `pipeline::registry`)
+    pub stages: StageIds,
+    /// Stage order for a new container (an existing header keeps its own)
+    pub pipeline_order: PipelineOrder,
+    /// Reed–Solomon parity for a new container's partitions
+    pub erasure: Option<ErasureCode>,
+    /// Pack the input, a directory or a single file, as an archive (see `archive`)
+    pub archive: bool,
+    /// Extended metadata the archive captures
+    pub archive_options: ArchiveOptions,
+    /// Receives bytes encoded and blocks written as the partition is added
+    pub progress: Progress,
+    /// Read the partition back from the written container and compare it with the input
+    /// before reporting success
+    pub verify: bool,
+    /// Add partitions to an existing container that the input does not fit, instead of
+    /// failing with `FileFull`
+    pub grow: bool,
+    /// Plant the partition as a honeypot with this label: opening it reports to the audit
+    /// hook (see `honeypot`), while its data reads back unchanged
+    pub honeypot: Option<String>,
 }
 
 impl Default for AddOptions {
@@ -27,61 +117,267 @@ impl Default for AddOptions {
             aont: Aont::default(),
             hash: HashAlgorithm::default(),
             dimension: 32,
+            block_size: None,
+            partitions: None,
+            blocks_per_partition: None,
             mac_bits: 256,
             seal: false,
+            index: false,
+            hint: false,
+            magicless: false,
+            passphrase: None,
+            deniable: false,
+            detached_header: None,
+            merkle: false,
+            shuffle: Shuffle::default(),
+            reshuffle: None,
+            kdf: Some(Kdf::argon2id()),
+            attempt_policy: None,
+            append_only: false,
+            always_full: false,
+            chaff_secret: None,
+            apply_recommendation: false,
+            frame_size: None,
+            chunked: false,
+            dedup: false,
+            derived_sequence: false,
+            dedup_secret: None,
+            stages: StageIds::default(),
+            pipeline_order: PipelineOrder::default(),
+            erasure: None,
+            archive: false,
+            archive_options: ArchiveOptions::default(),
+            progress: Progress::default(),
+            verify: false,
+            grow: false,
+            honeypot: None,
+        }
+    }
+}
+
+impl AddOptions {
+    /// Take geometry, algorithms and sealing from a cube preset, keeping fields it leaves unset
+    pub fn with_preset(self, preset: &CubePreset) -> Self {
+        Self {
+            dimension: preset.dimension,
+            block_size: preset.block_size.or(self.block_size),
+            mac_bits: preset.mac_bits.unwrap_or(self.mac_bits),
+            compression: preset.compression.unwrap_or(self.compression),
+            aont: preset.aont.unwrap_or(self.aont),
+            hash: preset.hash.unwrap_or(self.hash),
+            seal: preset.seal.unwrap_or(self.seal),
+            ..self
+        }
+    }
+}
+
+/// What an add stored, for judging the compression choice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddReport {
+    /// Input bytes
+    pub original_size: u64,
+    /// Compressed bytes the partition carries (see `CreatePartitionResult::compressed_size`)
+    pub compressed_size: u64,
+    /// Blocks the partition consumed, padding and AONT key blocks included
+    pub blocks: usize,
+    /// Bytes those blocks take on disk (sequence and MAC included)
+    pub stored_size: u64,
+}
+
+impl AddReport {
+    /// Percentage of the input that compression saved; None for empty input
+    pub fn savings_percent(&self) -> Option<f64> {
+        (self.original_size > 0)
+            .then(|| 100.0 * (1.0 - self.compressed_size as f64 / self.original_size as f64))
+    }
+
+    /// Bytes on disk beyond the input, as a percentage of it; None for empty input
+    pub fn overhead_percent(&self) -> Option<f64> {
+        (self.original_size > 0)
+            .then(|| 100.0 * (self.stored_size as f64 / self.original_size as f64 - 1.0))
+    }
+}
+
+impl std::fmt::Display for AddReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Original size: {} bytes", self.original_size)?;
+        write!(f, "Compressed size: {} bytes", self.compressed_size)?;
+        match self.savings_percent() {
+            Some(savings) => writeln!(f, " ({:.1}% saved)", savings)?,
+            None => writeln!(f)?,
+        }
+        writeln!(
+            f,
+            "Blocks consumed: {} ({} bytes on disk)",
+            self.blocks, self.stored_size
+        )?;
+        match self.overhead_percent() {
+            Some(overhead) => writeln!(f, "Overhead: {:.1}% of the original size", overhead),
+            None => writeln!(f, "Overhead: n/a (empty input)"),
         }
     }
 }
 
 /// Add a partition to a VHC file
-/// Returns the number of blocks added
+/// Returns what was stored: sizes before and after compression, and the blocks used
 pub fn add_partition(
     input_path: &Path,
     output_path: &Path,
     options: &AddOptions,
-) -> Result<usize> {
-    let input_data = std::fs::read(input_path)?;
+) -> Result<AddReport> {
+    let report = if options.deniable {
+        add_to_envelope(input_path, output_path, options)?
+    } else if let Some(header_path) = &options.detached_header {
+        add_detached(input_path, output_path, header_path, options)?
+    } else {
+        add_to_container(input_path, output_path, options)?
+    };
+    if options.verify {
+        verify_added(input_path, output_path, options)?;
+    }
+    Ok(report)
+}
+
+/// Extract the partition just added, as stored, and check it against the input
+/// Envelopes and detached headers are opened as `extract` would open them
+fn verify_added(input_path: &Path, output_path: &Path, options: &AddOptions) -> Result<()> {
+    let expected = match options.archive {
+        true => Archive::from_path(input_path, &options.archive_options)?.to_bytes(),
+        false => std::fs::read(input_path)?,
+    };
+    let extract = ExtractOptions {
+        secret: options.secret.clone(),
+        passphrase: options.passphrase.clone(),
+        deniable: options.deniable,
+        detached_header: options.detached_header.clone(),
+        max_size: Some(expected.len()),
+        ..Default::default()
+    };
+    // Reading back a honeypot just planted is no intrusion
+    let data = honeypot::unobserved(|| read_from_vhc(output_path, &extract)).map_err(|e| {
+        HypercubeError::IntegrityError(format!("Added partition does not read back: {}", e))
+    })?;
+    if data != expected {
+        return Err(HypercubeError::IntegrityError(
+            "Added partition reads back different data than the input".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Add a partition to a plain container, where the header is at the start of the file
+fn add_to_container(
+    input_path: &Path,
+    output_path: &Path,
+    options: &AddOptions,
+) -> Result<AddReport> {
+
+    let input_data = metrics::time(Stage::Io, || match options.archive {
+        true => Archive::from_path(input_path, &options.archive_options)
+            .map(|archive| archive.to_bytes()),
+        false => Ok(std::fs::read(input_path)?),
+    })?;
     let effective_compression = options.compression;
+    let trailer = match &options.honeypot {
+        Some(label) => honeypot::record(label)?,
+        None => Vec::new(),
+    };
+    // Chunked partitions are packed into their blocks, with no padding to keep a record in
+    let no_padding = |chunked: bool| match chunked && !trailer.is_empty() {
+        true => Err(HypercubeError::InvalidFormat(
+            "A chunked container cannot hold a honeypot partition".into(),
+        )),
+        false => Ok(()),
+    };
+    no_padding(options.chunked)?;
 
     // Load existing header or create new file
     let (header, current_blocks, mut pad_blocks) = if output_path.exists() {
-        let header = read_vhc_header(output_path)?;
+        if let Some(passphrase) = &options.passphrase {
+            verify_passphrase(output_path, passphrase)?;
+        }
+        let mut header = read_vhc_header(output_path)?;
+        header.check_unlocked()?;
+        no_padding(header.chunked)?;
         let blocks = get_block_count(output_path)?;
+        let reshuffle = options.reshuffle.filter(|&policy| policy != header.reshuffle);
+        if reshuffle.is_some() {
+            header.check_mutable("change the reshuffle policy")?;
+        }
+        if (options.merkle && !header.merkle) || reshuffle.is_some() {
+            header.merkle |= options.merkle;
+            if let Some(policy) = reshuffle {
+                header.reshuffle = policy;
+                header.pending_appends = 0;
+            }
+            rewrite_vhc_header(output_path, &header)?;
+        }
         
         // Check if new data can fit in existing cube's block size
-        let compressed = crate::pipeline::compress(&input_data, header.compression)?;
-        let payload_size = crate::header::PartitionMeta::SIZE + compressed.len();
-        let max_payload = header.block_size * header.data_blocks_per_partition();
-        if payload_size > max_payload {
-            return Err(HypercubeError::DataTooLarge {
-                data_size: payload_size,
-                max_size: max_payload,
-            });
+        // (chunks are sized as they are sealed, and shared ones may not need room at all)
+        if !header.chunked {
+            let compressed = crate::pipeline::compress_framed(
+                &input_data,
+                &*codec_for(&header)?,
+                header.frame_size,
+            )?;
+            let payload_size = header.meta_size() + compressed.len() + trailer.len();
+            let max_payload = header.block_size * header.data_blocks_per_partition();
+            if payload_size > max_payload {
+                return Err(HypercubeError::DataTooLarge {
+                    data_size: payload_size,
+                    max_size: max_payload,
+                });
+            }
         }
         
         (header, blocks, None)
     } else {
-        // Validate dimension is multiple of 8
-        if options.dimension < 8 || options.dimension % 8 != 0 {
-            return Err(HypercubeError::InvalidDimension(options.dimension));
+        // Create cube config from dimension (N×N hypercube), or the explicit geometry
+        let partitions = options.partitions.unwrap_or(options.dimension);
+        let blocks_per_partition = options.blocks_per_partition.unwrap_or(options.dimension);
+        // Checked before the input is analysed for this geometry; VhcHeader::new checks again
+        if partitions < 8 || !partitions.is_multiple_of(8) {
+            return Err(HypercubeError::InvalidDimension(partitions));
         }
-
-        // Create cube config from dimension (N×N hypercube)
-        let cube_cfg = CubeConfig {
-            id: options.dimension,
-            partitions: options.dimension,
-            blocks_per_partition: options.dimension,
-        };
-        let analysis = analyze_data(&input_data, effective_compression, cube_cfg)?;
-        let mut block_bytes = analysis.block_size_bytes;
-
-        // Ensure block size is even and at least 32 bytes (for AONT key)
-        if block_bytes < 32 {
-            block_bytes = 32;
+        if blocks_per_partition < 8 || !blocks_per_partition.is_multiple_of(8) {
+            return Err(HypercubeError::InvalidBlocksPerPartition(blocks_per_partition));
         }
-        if block_bytes % 2 != 0 {
-            block_bytes += 1;
+        let mut cube_cfg = CubeConfig {
+            id: partitions,
+            partitions,
+            blocks_per_partition,
+        };
+        let frame_size = options.frame_size.unwrap_or(0);
+        let codec = codec_named(options.stages.compression.as_deref(), effective_compression)?;
+        let mut analysis = analyze_framed(&input_data, &*codec, frame_size, cube_cfg)?;
+        if options.derived_sequence {
+            // The sequence nonce is stored after the partition metadata
+            analysis = analysis.with_extra_payload(SEQUENCE_NONCE_SIZE);
         }
+        // A honeypot record is stored in the padding, so it needs room there
+        analysis = analysis.with_extra_payload(trailer.len());
+        let block_bytes = match options.block_size {
+            // A block size given outright is used as is, and refused by VhcHeader::new
+            Some(block_size) if !options.apply_recommendation => block_size,
+            _ => {
+                let mut block_bytes = analysis.block_size_bytes;
+                if options.apply_recommendation {
+                    let layout = recommend_block_size(analysis.payload_bytes, options.mac_bits);
+                    cube_cfg = CubeConfig::hypercube(layout.dimension);
+                    block_bytes = layout.block_size;
+                }
+
+                // Ensure block size is even and at least 32 bytes (for AONT key)
+                if block_bytes < 32 {
+                    block_bytes = 32;
+                }
+                if !block_bytes.is_multiple_of(2) {
+                    block_bytes += 1;
+                }
+                block_bytes
+            }
+        };
 
         // Create new VHC file with header
         let mut header = VhcHeader::new(
@@ -92,10 +388,86 @@ pub fn add_partition(
             options.mac_bits,
         )?;
         header.compression = effective_compression;
+        header.frame_size = frame_size;
         header.aont = options.aont;
         header.hash = options.hash;
+        header.stages = options.stages.clone();
+        header.pipeline_order = options.pipeline_order.clone();
+        header.merkle = options.merkle;
+        header.shuffle = options.shuffle;
+        header.reshuffle = options.reshuffle.unwrap_or_default();
+        header.kdf = options.kdf.map(KdfParams::new);
+        header.attempt_policy = options.attempt_policy;
+        header.append_only = options.append_only;
+        header.derived_sequence = options.derived_sequence;
+        if options.append_only {
+            // Existing blocks never move, so there is nothing to reshuffle
+            if options.reshuffle.is_some_and(|policy| policy != ReshufflePolicy::Never) {
+                return Err(HypercubeError::AppendOnly("reshuffles are disabled".into()));
+            }
+            header.reshuffle = ReshufflePolicy::Never;
+        }
+        if options.erasure.is_some() {
+            if options.chunked {
+                return Err(HypercubeError::InvalidFormat(
+                    "A chunked container cannot also be erasure-coded".into(),
+                ));
+            }
+            header.set_erasure(options.erasure)?;
+            // Parity takes blocks from the data, so blocks sized to the input must grow
+            if options.block_size.is_none() {
+                let blocks = header.data_blocks_per_partition();
+                let block_size = required_block_size(analysis.payload_bytes, blocks).max(32);
+                header.set_block_size(block_size + block_size % 2)?;
+            }
+        }
+        if options.dedup && !options.chunked {
+            return Err(HypercubeError::InvalidFormat(
+                "Deduplication needs a chunked container".into(),
+            ));
+        }
+        if options.chunked {
+            // Each chunk is sealed under a base of its own, with no metadata to hold a nonce
+            if options.derived_sequence {
+                return Err(HypercubeError::InvalidFormat(
+                    "A chunked container cannot derive its sequence bases".into(),
+                ));
+            }
+            // Chunks are compressed one by one, so frames have nothing to add
+            if options.frame_size.is_some() {
+                return Err(HypercubeError::InvalidFormat(
+                    "A chunked container cannot also use compression frames".into(),
+                ));
+            }
+            header.chunked = true;
+            header.dedup = options.dedup;
+            fit_chunked(&input_data, options, &mut header)?;
+        }
+        if options.always_full {
+            // Topping up swaps chaff for data, which an append-only journal cannot record
+            if options.append_only {
+                return Err(HypercubeError::AppendOnly("cannot also be always-full".into()));
+            }
+            chaff_secret(options)?;
+            header.always_full = true;
+        }
+
+        // A preset block size is fixed, so the first input must fit it too
+        let max_payload = header.block_size * header.data_blocks_per_partition();
+        if analysis.payload_bytes > max_payload {
+            return Err(HypercubeError::DataTooLarge {
+                data_size: analysis.payload_bytes,
+                max_size: max_payload,
+            });
+        }
         // Write empty file with just header
-        let vhc = VhcFile::new(header.clone());
+        let mut vhc = VhcFile::new(header.clone());
+        if options.magicless {
+            let passphrase = options.passphrase.as_deref().ok_or_else(|| {
+                HypercubeError::InvalidFormat("A magicless container needs a passphrase".into())
+            })?;
+            vhc.layout = VhcLayout::keyed(passphrase, &header)?;
+        }
         write_vhc_file(output_path, &vhc)?;
         let blocks_per = header.data_blocks_per_partition();
         (header, 0, Some(blocks_per))
@@ -106,23 +478,209 @@ pub fn add_partition(
     let capacity = header.theoretical_block_count();
 
     // Create the partition - returns serialized blocks
-    let result = create_partition(&input_data, options.secret.as_bytes(), &header, pad_blocks)?;
+    let key = header.partition_key(options.secret.as_bytes())?;
+    let partition = if header.dedup {
+        // Chunks some partition already stored are listed, not stored again
+        let dedup = dedup_key(&header, options.dedup_secret.as_deref())?;
+        let existing = if current_blocks > 0 {
+            read_vhc_file(output_path)?.blocks
+        } else {
+            Vec::new()
+        };
+        create_dedup_partition(&existing, &input_data, &key, &dedup, &header)?
+    } else {
+        create_partition_with_trailer(&input_data, &key, &header, pad_blocks, &trailer)?
+    };
+    let blocks = partition.blocks;
+    let input_size = input_data.len() as u64;
+    options.progress.bytes_processed(input_size, input_size);
 
-    let block_count = result.blocks.len();
-    let remaining = capacity.saturating_sub(current_blocks);
-    if block_count > remaining {
-        return Err(HypercubeError::FileFull(capacity));
-    }
+    let block_count = blocks.len();
+    let mut positions = if header.always_full {
+        add_topped_up(output_path, &header, &blocks, options)?
+    } else {
+        let remaining = capacity.saturating_sub(current_blocks);
+        if block_count > remaining {
+            if !options.grow {
+                return Err(HypercubeError::FileFull(capacity));
+            }
+            grow_to_fit(output_path, &mut header.clone(), current_blocks + block_count)?;
+        }
+        append_blocks_to_vhc(output_path, &blocks)?
+    };
+    options
+        .progress
+        .blocks_written(block_count as u64, block_count as u64);
 
-    // Append blocks to VHC file
-    append_blocks_to_vhc(output_path, &result.blocks)?;
+    if options.hint {
+        let mut header = read_vhc_header(output_path)?;
+        let hint = BloomHint::build(&key, &blocks, &header)?;
+        header.hints.push(hint);
+        rewrite_vhc_header(output_path, &header)?;
+    }
 
     // Handle --seal option: add chaff partitions
     if options.seal {
-        seal_file(output_path)?;
+        seal_file_with_progress(output_path, &options.progress)?;
+        if options.index {
+            // Sealing reshuffled the container, so locate our blocks again
+            let vhc = read_vhc_file(output_path)?;
+            positions = find_partition_blocks(&vhc.blocks, &key, &vhc.header);
+        }
+    }
+
+    if options.index {
+        record_partition(output_path, &key, &positions)?;
+    }
+
+    metrics::counter(BYTES_ADDED, input_data.len() as u64);
+    Ok(AddReport {
+        original_size: partition.original_size as u64,
+        compressed_size: partition.compressed_size as u64,
+        blocks: block_count,
+        stored_size: block_count as u64 * header.total_block_size() as u64,
+    })
+}
+
+/// Add blocks to an always-full container: evict as much of the operator's keyed chaff as
+/// the new blocks need, then top the cube back up to capacity with fresh keyed chaff
+/// Returns the positions of the new blocks
+fn add_topped_up(
+    path: &Path,
+    header: &VhcHeader,
+    new_blocks: &[Vec<u8>],
+    options: &AddOptions,
+) -> Result<Vec<usize>> {
+    let chaff_key = header.partition_key(chaff_secret(options)?.as_bytes())?;
+    let vhc = read_vhc_file(path)?;
+    let mut capacity = header.theoretical_block_count();
+    let chaff = find_partition_blocks(&vhc.blocks, &chaff_key, header);
+    if new_blocks.len() > capacity.saturating_sub(vhc.blocks.len()) + chaff.len() {
+        if !options.grow {
+            return Err(HypercubeError::FileFull(capacity));
+        }
+        // Room for every block but the chaff, which is topped up again below
+        let needed = vhc.blocks.len() - chaff.len() + new_blocks.len();
+        capacity = grow_to_fit(path, &mut header.clone(), needed)?;
     }
+    let free = capacity.saturating_sub(vhc.blocks.len());
 
-    Ok(block_count)
+    let evicted = &chaff[..new_blocks.len().saturating_sub(free)];
+    let kept = vhc.blocks.len() - evicted.len() + new_blocks.len();
+    let mut blocks = new_blocks.to_vec();
+    blocks.extend(keyed_chaff_blocks(header, &chaff_key, capacity - kept)?);
+    let mut positions = replace_blocks_in_vhc(path, evicted, &blocks)?;
+    positions.truncate(new_blocks.len());
+    Ok(positions)
+}
+
+/// Add partitions to the container at `path` until it holds `needed` blocks, rounding
+/// up to the next multiple of 8. Blocks are opaque, so none is read or re-authenticated:
+/// only the header changes, and the new room is left free (`seal` fills it)
+/// Returns the new capacity
+fn grow_to_fit(path: &Path, header: &mut VhcHeader, needed: usize) -> Result<usize> {
+    let partitions = needed.div_ceil(header.blocks_per_partition()).next_multiple_of(8);
+    header.set_dimension(partitions.max(header.dimension))?;
+    rewrite_vhc_header(path, header)?;
+    Ok(header.theoretical_block_count())
+}
+
+/// Grow the block size of a new chunked container until its first input fits
+/// Each chunk rounds up to whole blocks and carries its own AONT key block; a quarter of
+/// the partition stays free so an update can seal changed chunks before freeing old ones
+fn fit_chunked(data: &[u8], options: &AddOptions, header: &mut VhcHeader) -> Result<()> {
+    let key = header.partition_key(options.secret.as_bytes())?;
+    let dedup = header
+        .dedup
+        .then(|| dedup_key(header, options.dedup_secret.as_deref()))
+        .transpose()?;
+    loop {
+        let needed = chunked_block_count(data, &key, dedup.as_ref(), header)?;
+        let total = header.blocks_per_partition();
+        if needed + total / 4 <= total {
+            return Ok(());
+        }
+        // A preset block size is fixed
+        if options.block_size.is_some() {
+            return Err(HypercubeError::DataTooLarge {
+                data_size: needed * header.block_size,
+                max_size: header.blocks_per_partition() * header.block_size,
+            });
+        }
+        let grown = header.block_size + (header.block_size / 8).max(2);
+        header.set_block_size(grown + grown % 2)?;
+    }
+}
+
+fn chaff_secret(options: &AddOptions) -> Result<&str> {
+    options.chaff_secret.as_deref().ok_or_else(|| {
+        HypercubeError::InvalidFormat("An always-full container needs a chaff secret".into())
+    })
+}
+
+/// Add a partition to the container inside a deniable envelope
+fn add_to_envelope(
+    input_path: &Path,
+    output_path: &Path,
+    options: &AddOptions,
+) -> Result<AddReport> {
+    let passphrase = options.passphrase.as_deref().ok_or_else(|| {
+        HypercubeError::InvalidFormat("A deniable container needs a passphrase".into())
+    })?;
+    if options.index {
+        // A sidecar next to the envelope would give it away
+        return Err(HypercubeError::InvalidFormat(
+            "A deniable container cannot have an index sidecar".into(),
+        ));
+    }
+
+    let inner = AddOptions {
+        magicless: false,
+        passphrase: None,
+        deniable: false,
+        // The envelope is opened and checked once it is sealed again
+        verify: false,
+        ..options.clone()
+    };
+    with_envelope(output_path, passphrase, true, |container| {
+        add_partition(input_path, container, &inner)
+    })
+}
+
+/// Add a partition to a container whose header is kept in `header_path`
+fn add_detached(
+    input_path: &Path,
+    output_path: &Path,
+    header_path: &Path,
+    options: &AddOptions,
+) -> Result<AddReport> {
+    if options.deniable || options.index {
+        // The sidecar is named after the container, which only exists joined
+        return Err(HypercubeError::InvalidFormat(
+            "A detached header cannot be combined with --deniable or an index sidecar".into(),
+        ));
+    }
+    let inner = AddOptions {
+        detached_header: None,
+        verify: false,
+        ..options.clone()
+    };
+    with_detached_header(output_path, header_path, true, |container| {
+        add_partition(input_path, container, &inner)
+    })
+}
+
+/// Check a container passphrase against the file's keyed verifier
+pub fn verify_passphrase(path: &Path, passphrase: &str) -> Result<()> {
+    match read_vhc_layout(path)? {
+        VhcLayout::Magic => Err(HypercubeError::InvalidFormat(
+            "Container has magic bytes and no passphrase".into(),
+        )),
+        layout if layout.verify(passphrase, &read_vhc_header(path)?)? => Ok(()),
+        _ => Err(HypercubeError::IntegrityError(
+            "Container passphrase does not match".into(),
+        )),
+    }
 }
 
 #[cfg(test)]
@@ -144,7 +702,8 @@ mod tests {
             ..Default::default()
         };
 
-        let block_count = add_partition(&input_path, &output_path, &options).unwrap();
+        let report = add_partition(&input_path, &output_path, &options).unwrap();
+        let block_count = report.blocks;
         assert!(block_count > 0);
         assert!(output_path.exists());
 
@@ -153,6 +712,169 @@ mod tests {
         let header = read_vhc_header(&output_path).unwrap();
         assert_eq!(block_count, header.blocks_per_partition());
         assert_eq!(file_blocks, block_count);
+        assert_eq!(report.original_size, 13);
+        // Secrets are hardened by default
+        assert_eq!(header.kdf.map(|params| params.kdf), Some(Kdf::argon2id()));
+        let stored = (block_count * header.total_block_size()) as u64;
+        assert_eq!(report.stored_size, stored);
+    }
+
+    #[test]
+    fn test_add_with_verify() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, b"about to be deleted").unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            kdf: None,
+            verify: true,
+            ..Default::default()
+        };
+        add_partition(&input, &output, &options).unwrap();
+
+        // Envelopes are checked once sealed again
+        let deniable = AddOptions {
+            passphrase: Some("envelope".into()),
+            deniable: true,
+            ..options.clone()
+        };
+        add_partition(&input, &dir.path().join("deniable.vhc"), &deniable).unwrap();
+
+        // A container that no longer reads back, or holds other data, fails the check
+        let mut vhc = read_vhc_file(&output).unwrap();
+        vhc.blocks.truncate(5);
+        write_vhc_file(&output, &vhc).unwrap();
+        let error = verify_added(&input, &output, &options).unwrap_err();
+        assert!(error.to_string().contains("does not read back"), "{}", error);
+        let other = dir.path().join("other.vhc");
+        add_partition(&input, &other, &options).unwrap();
+        std::fs::write(&input, b"about to be DELETED").unwrap();
+        let error = verify_added(&input, &other, &options).unwrap_err();
+        assert!(error.to_string().contains("different data"), "{}", error);
+    }
+
+    #[test]
+    fn test_add_derived_sequence() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        // Incompressible, so the first partition's block size is fitted tightly
+        let data: Vec<u8> = (0..3000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        std::fs::write(&input, &data).unwrap();
+
+        let options = AddOptions {
+            secret: "first".into(),
+            dimension: 8,
+            derived_sequence: true,
+            seal: true,
+            ..Default::default()
+        };
+        add_partition(&input, &output, &options).unwrap();
+        assert!(read_vhc_header(&output).unwrap().derived_sequence);
+        let extracted = dir.path().join("extracted");
+        let extract = crate::cli::extract::ExtractOptions {
+            secret: "first".into(),
+            ..Default::default()
+        };
+        crate::cli::extract::extract_from_vhc(&output, &extracted, &extract).unwrap();
+        assert_eq!(std::fs::read(&extracted).unwrap(), data);
+
+        let chunked = AddOptions {
+            chunked: true,
+            ..options
+        };
+        let result = add_partition(&input, &dir.path().join("chunked.vhc"), &chunked);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_add_erasure_coded() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        let data = crate::partition::generate_chaff(3000);
+        std::fs::write(&input, &data).unwrap();
+
+        let options = AddOptions {
+            secret: "first".into(),
+            dimension: 8,
+            kdf: None,
+            erasure: Some("4+2".parse().unwrap()),
+            seal: true,
+            ..Default::default()
+        };
+        add_partition(&input, &output, &options).unwrap();
+        let mut vhc = read_vhc_file(&output).unwrap();
+        assert_eq!(vhc.header.erasure, options.erasure);
+        // Blocks were sized for the data left after parity
+        assert!(vhc.header.block_size * vhc.header.data_blocks_per_partition() >= 3000);
+
+        // Two of the partition's blocks rot
+        for position in &find_partition_blocks(&vhc.blocks, b"first", &vhc.header)[..2] {
+            vhc.blocks[*position][40] ^= 0xff;
+        }
+        write_vhc_file(&output, &vhc).unwrap();
+        let extracted = dir.path().join("extracted");
+        let extract = crate::cli::extract::ExtractOptions {
+            secret: "first".into(),
+            ..Default::default()
+        };
+        crate::cli::extract::extract_from_vhc(&output, &extracted, &extract).unwrap();
+        assert_eq!(std::fs::read(&extracted).unwrap(), data);
+
+        let chunked = AddOptions {
+            chunked: true,
+            ..options
+        };
+        let result = add_partition(&input, &dir.path().join("chunked.vhc"), &chunked);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_add_report_sizes() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, b"compressible ".repeat(4096)).unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        let report = add_partition(&input, &output, &options).unwrap();
+        assert_eq!(report.original_size, 13 * 4096);
+        assert!(report.compressed_size < report.original_size / 20);
+        assert!(report.savings_percent().unwrap() > 95.0);
+        // A whole padded partition of blocks still outweighs the compressed payload
+        assert!(report.stored_size > report.compressed_size);
+        let text = report.to_string();
+        assert!(text.contains("Original size: 53248 bytes"), "{}", text);
+        assert!(text.contains("% saved)"), "{}", text);
+        assert!(text.contains("Overhead: "), "{}", text);
+
+        let chunked = AddOptions {
+            secret: "secret".into(),
+            chunked: true,
+            ..Default::default()
+        };
+        let chunked_output = dir.path().join("chunked.vhc");
+        let report = add_partition(&input, &chunked_output, &chunked).unwrap();
+        assert!(report.compressed_size > 0);
+        assert!(report.compressed_size < report.original_size);
+
+        let empty = dir.path().join("empty.txt");
+        std::fs::write(&empty, b"").unwrap();
+        let other = AddOptions {
+            secret: "other".into(),
+            ..Default::default()
+        };
+        let report = add_partition(&empty, &output, &other).unwrap();
+        assert_eq!(report.overhead_percent(), None);
+        assert!(report.to_string().contains("n/a"));
     }
 
     #[test]
@@ -175,8 +897,8 @@ mod tests {
             ..Default::default()
         };
 
-        let count1 = add_partition(&input1, &output, &options1).unwrap();
-        let count2 = add_partition(&input2, &output, &options2).unwrap();
+        let count1 = add_partition(&input1, &output, &options1).unwrap().blocks;
+        let count2 = add_partition(&input2, &output, &options2).unwrap().blocks;
 
         // Verify total blocks
         let total_blocks = get_block_count(&output).unwrap();
@@ -196,7 +918,359 @@ mod tests {
             ..Default::default()
         };
 
-        let block_count = add_partition(&input, &output, &options).unwrap();
+        let block_count = add_partition(&input, &output, &options).unwrap().blocks;
         assert!(block_count > 0);
     }
+
+    #[test]
+    fn test_add_magicless_container() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, b"Test data").unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            magicless: true,
+            ..Default::default()
+        };
+        assert!(add_partition(&input, &output, &options).is_err());
+
+        let options = AddOptions {
+            passphrase: Some("open sesame".into()),
+            ..options
+        };
+        add_partition(&input, &output, &options).unwrap();
+        assert_ne!(&std::fs::read(&output).unwrap()[..4], b"VHC\x01");
+        assert!(verify_passphrase(&output, "open sesame").is_ok());
+
+        let wrong = AddOptions {
+            secret: "secret2".into(),
+            passphrase: Some("guess".into()),
+            ..Default::default()
+        };
+        assert!(add_partition(&input, &output, &wrong).is_err());
+
+        let second = AddOptions {
+            secret: "secret2".into(),
+            passphrase: Some("open sesame".into()),
+            ..Default::default()
+        };
+        add_partition(&input, &output, &second).unwrap();
+        assert_eq!(
+            get_block_count(&output).unwrap(),
+            2 * read_vhc_header(&output).unwrap().blocks_per_partition()
+        );
+    }
+
+    #[test]
+    fn test_add_with_preset() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, b"Test data").unwrap();
+
+        let preset = CubePreset {
+            block_size: Some(64),
+            hash: Some(HashAlgorithm::Blake3),
+            ..CubePreset::hypercube(16)
+        };
+        let options = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        }
+        .with_preset(&preset);
+        add_partition(&input, &output, &options).unwrap();
+
+        let header = read_vhc_header(&output).unwrap();
+        assert_eq!(header.dimension, 16);
+        assert_eq!(header.block_size, 64);
+        assert_eq!(header.hash, HashAlgorithm::Blake3);
+        assert_eq!(header.mac_bits, 256);
+
+        // A fixed block size that cannot hold the input is refused
+        let tiny = CubePreset {
+            block_size: Some(32),
+            compression: Some(Compression::None),
+            ..CubePreset::hypercube(8)
+        };
+        std::fs::write(&input, vec![0xA5u8; 4096]).unwrap();
+        let options = options.with_preset(&tiny);
+        let result = add_partition(&input, &dir.path().join("tiny.vhc"), &options);
+        assert!(matches!(result, Err(HypercubeError::DataTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_add_with_explicit_geometry() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, b"Test data").unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            kdf: None,
+            partitions: Some(16),
+            blocks_per_partition: Some(8),
+            block_size: Some(96),
+            ..Default::default()
+        };
+        add_partition(&input, &output, &options).unwrap();
+        let header = read_vhc_header(&output).unwrap();
+        assert_eq!(header.dimension, 16);
+        assert_eq!(header.blocks_per_partition(), 8);
+        assert_eq!(header.block_size, 96);
+        assert_eq!(header.theoretical_block_count(), 128);
+
+        // Invalid values are refused, not rounded
+        let refused = |options: AddOptions| {
+            add_partition(&input, &dir.path().join("refused.vhc"), &options).unwrap_err()
+        };
+        let err = refused(AddOptions {
+            partitions: Some(12),
+            ..options.clone()
+        });
+        assert!(matches!(err, HypercubeError::InvalidDimension(12)));
+        let err = refused(AddOptions {
+            blocks_per_partition: Some(0),
+            ..options.clone()
+        });
+        assert!(matches!(err, HypercubeError::InvalidBlocksPerPartition(0)));
+        let err = refused(AddOptions {
+            block_size: Some(33),
+            ..options.clone()
+        });
+        assert!(matches!(err, HypercubeError::InvalidBlockSize(33)));
+        assert!(!dir.path().join("refused.vhc").exists());
+    }
+
+    #[test]
+    fn test_add_deniable_envelope() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.bin");
+        std::fs::write(&input, b"Test data").unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            deniable: true,
+            passphrase: Some("outer".into()),
+            ..Default::default()
+        };
+        let count = add_partition(&input, &output, &options).unwrap().blocks;
+        add_partition(
+            &input,
+            &output,
+            &AddOptions {
+                secret: "secret2".into(),
+                ..options.clone()
+            },
+        )
+        .unwrap();
+
+        let size = std::fs::metadata(&output).unwrap().len() as usize;
+        assert_eq!(size, crate::envelope::MIN_BUCKET_SIZE);
+        assert!(read_vhc_header(&output).is_err());
+        let blocks = with_envelope(&output, "outer", false, get_block_count).unwrap();
+        assert_eq!(blocks, 2 * count);
+
+        let indexed = AddOptions {
+            index: true,
+            ..options
+        };
+        assert!(add_partition(&input, &output, &indexed).is_err());
+    }
+
+    #[test]
+    fn test_add_always_full() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, b"always full").unwrap();
+
+        let options = AddOptions {
+            secret: "first".into(),
+            dimension: 8,
+            always_full: true,
+            chaff_secret: Some("chaff".into()),
+            ..Default::default()
+        };
+        let missing = AddOptions {
+            chaff_secret: None,
+            ..options.clone()
+        };
+        assert!(add_partition(&input, &output, &missing).is_err());
+        assert!(!output.exists());
+
+        add_partition(&input, &output, &options).unwrap();
+        let header = read_vhc_header(&output).unwrap();
+        assert!(header.always_full);
+        let capacity = header.theoretical_block_count();
+        assert_eq!(get_block_count(&output).unwrap(), capacity);
+
+        let second = AddOptions {
+            secret: "second".into(),
+            ..options.clone()
+        };
+        add_partition(&input, &output, &second).unwrap();
+        assert_eq!(get_block_count(&output).unwrap(), capacity);
+        for secret in ["first", "second"] {
+            let extracted = dir.path().join(secret);
+            let extract = crate::cli::extract::ExtractOptions {
+                secret: secret.into(),
+                ..Default::default()
+            };
+            crate::cli::extract::extract_from_vhc(&output, &extracted, &extract).unwrap();
+            assert_eq!(std::fs::read(&extracted).unwrap(), b"always full");
+        }
+
+        // Without the chaff secret there is nothing to swap out
+        let wrong = AddOptions {
+            secret: "third".into(),
+            chaff_secret: Some("wrong".into()),
+            ..options
+        };
+        let before = std::fs::read(&output).unwrap();
+        let result = add_partition(&input, &output, &wrong);
+        assert!(matches!(result, Err(HypercubeError::FileFull(_))));
+        let block = vec![0u8; header.total_block_size()];
+        let result = append_blocks_to_vhc(&output, &[block]);
+        assert!(matches!(result, Err(HypercubeError::NotFull { .. })));
+        assert_eq!(std::fs::read(&output).unwrap(), before);
+
+        // Grown, the cube gets new partitions and is topped up to its new capacity
+        let grow = AddOptions { grow: true, ..wrong };
+        add_partition(&input, &output, &grow).unwrap();
+        let grown = read_vhc_header(&output).unwrap();
+        assert_eq!(grown.dimension, 2 * header.dimension);
+        assert_eq!(get_block_count(&output).unwrap(), grown.theoretical_block_count());
+    }
+
+    #[test]
+    fn test_add_grows_full_container() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, b"one more").unwrap();
+        let options = AddOptions {
+            secret: "first".into(),
+            dimension: 8,
+            kdf: None,
+            seal: true,
+            ..Default::default()
+        };
+        add_partition(&input, &output, &options).unwrap();
+        let before = read_vhc_file(&output).unwrap();
+
+        // A sealed cube is full, unless it may grow
+        let second = AddOptions {
+            secret: "second".into(),
+            seal: false,
+            ..options.clone()
+        };
+        let result = add_partition(&input, &output, &second);
+        assert!(matches!(result, Err(HypercubeError::FileFull(64))));
+        let grow = AddOptions {
+            grow: true,
+            ..second
+        };
+        add_partition(&input, &output, &grow).unwrap();
+
+        // One partition more is rounded up to 8, and every old block is kept as it was
+        let after = read_vhc_file(&output).unwrap();
+        assert_eq!(after.header.dimension, 16);
+        assert_eq!(after.header.cube_id, 16);
+        assert_eq!(after.header.blocks_per_partition(), 8);
+        assert_eq!(after.blocks.len(), 72);
+        assert!(before.blocks.iter().all(|block| after.blocks.contains(block)));
+        for secret in ["first", "second"] {
+            let extract = ExtractOptions {
+                secret: secret.into(),
+                ..Default::default()
+            };
+            assert_eq!(read_from_vhc(&output, &extract).unwrap(), b"one more");
+        }
+
+        // Room that is already there is used without growing
+        let third = AddOptions {
+            secret: "third".into(),
+            ..grow
+        };
+        add_partition(&input, &output, &third).unwrap();
+        assert_eq!(read_vhc_header(&output).unwrap().dimension, 16);
+    }
+
+    #[test]
+    fn test_add_applies_recommendation() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.vhc");
+        std::fs::write(&input, vec![0x5Au8; 200]).unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            compression: Compression::None,
+            apply_recommendation: true,
+            ..Default::default()
+        };
+        add_partition(&input, &output, &options).unwrap();
+
+        let payload = crate::header::PartitionMeta::HASHED_SIZE + 200;
+        let layout = recommend_block_size(payload, options.mac_bits);
+        let header = read_vhc_header(&output).unwrap();
+        assert_eq!(header.dimension, layout.dimension);
+        assert_eq!(header.block_size, layout.block_size);
+        assert_ne!(header.dimension, options.dimension);
+    }
+
+    #[test]
+    fn test_add_dedup_partitions() {
+        use crate::chunk::read_chunked_partition;
+        use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.bin");
+        let output = dir.path().join("output.vhc");
+        let mut data = vec![0u8; 10_000];
+        StdRng::seed_from_u64(7).fill_bytes(&mut data);
+        std::fs::write(&input, &data).unwrap();
+
+        let options = AddOptions {
+            secret: "first".into(),
+            chunked: true,
+            dedup: true,
+            dedup_secret: Some("shared".into()),
+            ..Default::default()
+        };
+        let missing = AddOptions {
+            dedup_secret: None,
+            ..options.clone()
+        };
+        assert!(add_partition(&input, &output, &missing).is_err());
+        assert!(!output.exists());
+
+        add_partition(&input, &output, &options).unwrap();
+        assert!(read_vhc_header(&output).unwrap().dedup);
+        let second = AddOptions {
+            secret: "second".into(),
+            ..options.clone()
+        };
+        add_partition(&input, &output, &second).unwrap();
+        assert!(add_partition(&input, &output, &missing).is_err());
+
+        // The second copy lists the first one's chunks and stores none of its own
+        let vhc = read_vhc_file(&output).unwrap();
+        let key = vhc.header.partition_key(b"second").unwrap();
+        let partition = read_chunked_partition(&vhc.blocks, &key, &vhc.header).unwrap();
+        assert!(partition.held.is_empty());
+        for secret in ["first", "second"] {
+            let extracted = dir.path().join(secret);
+            let extract = crate::cli::extract::ExtractOptions {
+                secret: secret.into(),
+                ..Default::default()
+            };
+            crate::cli::extract::extract_from_vhc(&output, &extracted, &extract).unwrap();
+            assert_eq!(std::fs::read(&extracted).unwrap(), data);
+        }
+    }
 }
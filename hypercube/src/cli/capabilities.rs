@@ -0,0 +1,136 @@
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::header::VhcHeader;
+use crate::pipeline::registry::{registered_stages, StageNames};
+use serde::Serialize;
+
+/// Cargo features this binary may be built with, and whether it was
+const FEATURES: [(&str, bool); 4] = [
+    ("metrics", cfg!(feature = "metrics")),
+    ("kms-aws", cfg!(feature = "kms-aws")),
+    ("kms-gcp", cfg!(feature = "kms-gcp")),
+    ("kms-azure", cfg!(feature = "kms-azure")),
+];
+
+/// What this build can read and write, for `hypercube capabilities`
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub version: String,
+    /// Oldest and newest header versions read; new containers use the newest
+    pub format_versions: [u32; 2],
+    pub layouts: Vec<&'static str>,
+    /// Stage implementations by name, registered ones included
+    pub stages: StageNames,
+    pub kdfs: Vec<&'static str>,
+    pub mac_bits: Vec<usize>,
+    /// Optional cargo features compiled in
+    pub features: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").into(),
+            format_versions: [1, domains::LATEST_VERSION],
+            layouts: vec!["magic", "magicless", "deniable", "detached"],
+            stages: registered_stages(),
+            kdfs: vec!["argon2id", "scrypt", "pbkdf2"],
+            mac_bits: vec![128, 256, 512],
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| *name)
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |names: &[String]| names.join(", ");
+        writeln!(f, "hypercube {}", self.version)?;
+        let [oldest, newest] = self.format_versions;
+        writeln!(f, "Format versions: {}-{}", oldest, newest)?;
+        writeln!(f, "Layouts: {}", self.layouts.join(", "))?;
+        writeln!(f, "Compression: {}", list(&self.stages.compression))?;
+        writeln!(f, "Whiteners: {}", list(&self.stages.whitener))?;
+        writeln!(f, "AONTs: {}", list(&self.stages.aont))?;
+        writeln!(f, "MACs: {}", list(&self.stages.mac))?;
+        writeln!(f, "KDFs: {}", self.kdfs.join(", "))?;
+        let bits: Vec<String> = self.mac_bits.iter().map(usize::to_string).collect();
+        writeln!(f, "MAC bits: {}", bits.join(", "))?;
+        match self.features.is_empty() {
+            true => writeln!(f, "Features: none"),
+            false => writeln!(f, "Features: {}", self.features.join(", ")),
+        }
+    }
+}
+
+/// What a container needs that this build lacks: stage implementations it names that
+/// are not registered
+/// Built-in algorithms and format versions are checked when the header is parsed
+pub fn missing_capabilities(header: &VhcHeader) -> Vec<String> {
+    let registered = registered_stages();
+    let stages = &header.stages;
+    [
+        ("compression", &stages.compression, &registered.compression),
+        ("whitener", &stages.whitener, &registered.whitener),
+        ("aont", &stages.aont, &registered.aont),
+        ("mac", &stages.mac, &registered.mac),
+    ]
+    .into_iter()
+    .filter_map(|(stage, name, names)| {
+        let name = name.as_ref()?;
+        (!names.contains(name)).then(|| format!("{} stage `{}`", stage, name))
+    })
+    .collect()
+}
+
+/// Refuse a container that needs something this build lacks, naming what
+pub fn check_capabilities(header: &VhcHeader) -> Result<()> {
+    let missing = missing_capabilities(header);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(HypercubeError::MissingCapability(missing.join(" and ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = Capabilities::current();
+        assert_eq!(capabilities.format_versions, [1, domains::LATEST_VERSION]);
+        assert!(capabilities
+            .stages
+            .compression
+            .contains(&"zstd".to_string()));
+        let json = serde_json::to_string(&capabilities).unwrap();
+        assert!(
+            json.contains(r#""mac":["blake3","sha256","sha3"]"#),
+            "{}",
+            json
+        );
+        assert_eq!(
+            capabilities.features.contains(&"metrics"),
+            cfg!(feature = "metrics")
+        );
+
+        let mut header = VhcHeader::default();
+        check_capabilities(&header).unwrap();
+        header.stages.whitener = Some("unheard-of".into());
+        header.stages.mac = Some("sha3".into());
+        assert_eq!(
+            missing_capabilities(&header),
+            ["whitener stage `unheard-of`"]
+        );
+        let error = check_capabilities(&header).unwrap_err().to_string();
+        assert!(
+            error.contains("requires whitener stage `unheard-of`"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::cli::seal::keyed_chaff_blocks;
+use crate::error::{HypercubeError, Result};
+use crate::partition::find_partition_blocks;
+use crate::vhc::{read_vhc_file, replace_blocks_in_vhc};
+use std::path::Path;
+use std::time::Duration;
+
+/// Options for the daemon command
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    /// Time between chaff rotations
+    pub rotate_chaff: Duration,
+    /// Secret the container's chaff is keyed under (see `AddOptions::chaff_secret`)
+    pub chaff_secret: String,
+    /// Stop after this many rotations; run until an error when None
+    pub rotations: Option<usize>,
+}
+
+impl Default for DaemonOptions {
+    fn default() -> Self {
+        Self {
+            rotate_chaff: Duration::from_secs(24 * 60 * 60),
+            chaff_secret: String::new(),
+            rotations: None,
+        }
+    }
+}
+
+/// One rotation done by `run_daemon`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    /// Rotations so far, this one included
+    pub number: usize,
+    /// Chaff blocks replaced
+    pub replaced: usize,
+}
+
+/// Parse an interval such as `90s`, `30m`, `24h` or `7d`
+pub fn parse_interval(s: &str) -> Result<Duration> {
+    let invalid = || HypercubeError::InvalidFormat(format!("Invalid interval: {}", s));
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (count, unit) = s.split_at(split);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let secs = count
+        .checked_mul(unit)
+        .filter(|&secs| secs > 0)
+        .ok_or_else(invalid)?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Replace every chaff block keyed under `chaff_secret` with fresh chaff and reshuffle
+/// Returns the number of blocks replaced
+pub fn rotate_chaff(path: &Path, chaff_secret: &str) -> Result<usize> {
+    let vhc = read_vhc_file(path)?;
+    vhc.header.check_unlocked()?;
+    vhc.header.check_mutable("rotate chaff")?;
+    let key = vhc.header.partition_key(chaff_secret.as_bytes())?;
+    let positions = find_partition_blocks(&vhc.blocks, &key, &vhc.header);
+    if positions.is_empty() {
+        return Err(HypercubeError::IntegrityError(
+            "No chaff authenticated with this chaff secret".into(),
+        ));
+    }
+    let fresh = keyed_chaff_blocks(&vhc.header, &key, positions.len())?;
+    replace_blocks_in_vhc(path, &positions, &fresh)?;
+    Ok(positions.len())
+}
+
+/// Rotate the container's chaff now and then once every interval, passing each rotation
+/// to `on_rotation` as it completes
+/// Returns the number of rotations once `rotations` is reached
+pub fn run_daemon(
+    path: &Path,
+    options: &DaemonOptions,
+    mut on_rotation: impl FnMut(Rotation),
+) -> Result<usize> {
+    let mut rotations = 0;
+    loop {
+        let replaced = rotate_chaff(path, &options.chaff_secret)?;
+        rotations += 1;
+        on_rotation(Rotation {
+            number: rotations,
+            replaced,
+        });
+        if options.rotations.is_some_and(|limit| rotations >= limit) {
+            return Ok(rotations);
+        }
+        std::thread::sleep(options.rotate_chaff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use crate::vhc::get_block_count;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_interval("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_interval("24h").unwrap(), Duration::from_secs(86400));
+        assert_eq!(parse_interval("7d").unwrap(), Duration::from_secs(604800));
+        for bad in ["", "24", "h", "0h", "1.5h", "-1h", "3w"] {
+            assert!(parse_interval(bad).is_err(), "{}", bad);
+        }
+        assert!(parse_interval("999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_rotate_chaff() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"quiet data").unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            always_full: true,
+            chaff_secret: Some("chaff".into()),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        let before = read_vhc_file(&vhc).unwrap().blocks;
+
+        let daemon = DaemonOptions {
+            rotate_chaff: Duration::from_secs(1),
+            chaff_secret: "chaff".into(),
+            rotations: Some(1),
+        };
+        let mut reported = Vec::new();
+        assert_eq!(run_daemon(&vhc, &daemon, |rotation| reported.push(rotation)).unwrap(), 1);
+        let rotation = Rotation {
+            number: 1,
+            replaced: 56,
+        };
+        assert_eq!(reported, [rotation]);
+        let after = read_vhc_file(&vhc).unwrap().blocks;
+        assert_eq!(after.len(), before.len());
+        let kept = after.iter().filter(|block| before.contains(block)).count();
+        assert_eq!(kept, before.len() - 56, "only the data blocks survive");
+        assert_eq!(get_block_count(&vhc).unwrap(), 64);
+
+        let output = dir.path().join("output.txt");
+        let extract = ExtractOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc, &output, &extract).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"quiet data");
+
+        assert!(rotate_chaff(&vhc, "wrong").is_err());
+    }
+}
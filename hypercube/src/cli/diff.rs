@@ -0,0 +1,278 @@
+use crate::cli::extract::{read_from_vhc, ExtractOptions};
+use crate::error::{HypercubeError, Result};
+use crate::vhc::VhcReader;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Header values longer than this are shown by length and digest instead
+const MAX_VALUE_CHARS: usize = 64;
+
+/// Options for the diff command
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Partition secrets whose contents are compared between the containers
+    pub secrets: Vec<String>,
+}
+
+/// A header field that differs; None where the field is absent (at its default)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderChange {
+    pub field: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// Size and BLAKE3 digest of a partition's data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionDigest {
+    pub size: usize,
+    pub hash: blake3::Hash,
+}
+
+/// One secret's partition in each container; None where the secret opens nothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionChange {
+    pub left: Option<PartitionDigest>,
+    pub right: Option<PartitionDigest>,
+}
+
+impl PartitionChange {
+    pub fn is_unchanged(&self) -> bool {
+        self.left == self.right
+    }
+}
+
+/// Differences between two containers, in a stable order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerDiff {
+    pub left_path: PathBuf,
+    pub right_path: PathBuf,
+    /// Changed header fields, by field name
+    pub header: Vec<HeaderChange>,
+    pub left_blocks: usize,
+    pub right_blocks: usize,
+    /// Blocks stored byte for byte in both, wherever they sit
+    pub shared_blocks: usize,
+    /// One entry per secret, in the order given
+    pub partitions: Vec<PartitionChange>,
+}
+
+impl ContainerDiff {
+    /// The containers hold the same header, blocks and partitions
+    /// Block order is not compared: every add or seal reshuffles it
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self.left_blocks == self.right_blocks
+            && self.shared_blocks == self.left_blocks
+            && self.partitions.iter().all(PartitionChange::is_unchanged)
+    }
+}
+
+impl std::fmt::Display for ContainerDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "--- {}", self.left_path.display())?;
+        writeln!(f, "+++ {}", self.right_path.display())?;
+        for change in &self.header {
+            let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "(none)".into());
+            writeln!(
+                f,
+                "Header {}: {} -> {}",
+                change.field,
+                show(&change.left),
+                show(&change.right)
+            )?;
+        }
+        writeln!(
+            f,
+            "Blocks: {} -> {} ({:+}), {} shared, {} removed, {} added",
+            self.left_blocks,
+            self.right_blocks,
+            self.right_blocks as i64 - self.left_blocks as i64,
+            self.shared_blocks,
+            self.left_blocks - self.shared_blocks,
+            self.right_blocks - self.shared_blocks
+        )?;
+        for (i, change) in self.partitions.iter().enumerate() {
+            let status = match (change.left, change.right) {
+                (None, None) => "not found in either".to_string(),
+                (Some(left), None) => format!("removed ({} bytes)", left.size),
+                (None, Some(right)) => format!("added ({} bytes)", right.size),
+                (Some(left), Some(right)) if left == right => {
+                    format!("unchanged ({} bytes)", left.size)
+                }
+                (Some(left), Some(right)) => {
+                    format!("changed ({} -> {} bytes)", left.size, right.size)
+                }
+            };
+            writeln!(f, "Partition #{}: {}", i + 1, status)?;
+        }
+        if self.is_empty() {
+            writeln!(f, "No differences")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two containers for change review
+///
+/// Headers are compared field by field, blocks as a multiset of their bytes (so a
+/// reshuffle alone is no difference), and, for each secret, the partition's data by
+/// digest. Secrets are reported by position, never by value. The output depends only
+/// on the two files and the secrets.
+pub fn diff_containers(left: &Path, right: &Path, options: &DiffOptions) -> Result<ContainerDiff> {
+    let left_vhc = VhcReader::open(left)?;
+    let right_vhc = VhcReader::open(right)?;
+    let header = header_changes(
+        &serde_json::to_value(left_vhc.header())?,
+        &serde_json::to_value(right_vhc.header())?,
+    );
+
+    let mut remaining: HashMap<&[u8], usize> = HashMap::new();
+    for block in left_vhc.blocks() {
+        *remaining.entry(block).or_default() += 1;
+    }
+    let shared_blocks = right_vhc
+        .blocks()
+        .filter(|block| match remaining.get_mut(block) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        })
+        .count();
+
+    let partitions = options
+        .secrets
+        .iter()
+        .map(|secret| {
+            Ok(PartitionChange {
+                left: partition_digest(left, secret)?,
+                right: partition_digest(right, secret)?,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(ContainerDiff {
+        left_path: left.to_path_buf(),
+        right_path: right.to_path_buf(),
+        header,
+        left_blocks: left_vhc.block_count(),
+        right_blocks: right_vhc.block_count(),
+        shared_blocks,
+        partitions,
+    })
+}
+
+/// Fields whose values differ, in field name order
+fn header_changes(left: &serde_json::Value, right: &serde_json::Value) -> Vec<HeaderChange> {
+    let (Some(left), Some(right)) = (left.as_object(), right.as_object()) else {
+        return Vec::new();
+    };
+    let fields: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+    fields
+        .into_iter()
+        .filter(|field| left.get(*field) != right.get(*field))
+        .map(|field| HeaderChange {
+            field: field.clone(),
+            left: left.get(field).map(show_value),
+            right: right.get(field).map(show_value),
+        })
+        .collect()
+}
+
+/// Compact JSON, with long values (salts, hints) shortened to a length and digest
+fn show_value(value: &serde_json::Value) -> String {
+    let text = value.to_string();
+    if text.len() <= MAX_VALUE_CHARS {
+        return text;
+    }
+    let digest = blake3::hash(text.as_bytes()).to_hex();
+    format!("<{} chars, blake3 {}>", text.len(), &digest[..16])
+}
+
+/// The partition a secret opens, or None if it opens nothing in this container
+fn partition_digest(path: &Path, secret: &str) -> Result<Option<PartitionDigest>> {
+    let options = ExtractOptions {
+        secret: secret.to_string(),
+        ..Default::default()
+    };
+    match read_from_vhc(path, &options) {
+        Ok(data) => Ok(Some(PartitionDigest {
+            size: data.len(),
+            hash: blake3::hash(&data),
+        })),
+        Err(HypercubeError::NoAuthenticatedBlocks | HypercubeError::IntegrityError(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_diff_containers() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let before = dir.path().join("before.vhc");
+        let after = dir.path().join("after.vhc");
+        std::fs::write(&input, b"first partition").unwrap();
+        let add = |secret: &str, path: &Path| {
+            let options = AddOptions {
+                secret: secret.into(),
+                dimension: 8,
+                kdf: None,
+                ..Default::default()
+            };
+            add_partition(&input, path, &options).unwrap();
+        };
+        add("alpha", &before);
+        std::fs::copy(&before, &after).unwrap();
+
+        // A copy differs in nothing
+        let options = DiffOptions {
+            secrets: vec!["alpha".into(), "beta".into()],
+        };
+        let same = diff_containers(&before, &after, &options).unwrap();
+        assert!(same.is_empty(), "{}", same);
+        assert!(same
+            .to_string()
+            .contains("Partition #2: not found in either"));
+
+        add("beta", &after);
+        let diff = diff_containers(&before, &after, &options).unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!((diff.left_blocks, diff.right_blocks), (8, 16));
+        assert_eq!(diff.shared_blocks, 8);
+        assert!(diff.partitions[0].is_unchanged());
+        assert!(diff.partitions[1].left.is_none() && diff.partitions[1].right.is_some());
+        assert!(diff
+            .header
+            .iter()
+            .any(|change| change.field == "generation"));
+
+        let text = diff.to_string();
+        assert!(
+            text.contains("Blocks: 8 -> 16 (+8), 8 shared, 0 removed, 8 added"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("Partition #1: unchanged (15 bytes)"),
+            "{}",
+            text
+        );
+        assert!(text.contains("Partition #2: added (15 bytes)"), "{}", text);
+        assert!(!text.contains("alpha") && !text.contains("beta"));
+        // Deterministic, so it can be committed next to the containers
+        assert_eq!(
+            text,
+            diff_containers(&before, &after, &options)
+                .unwrap()
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use crate::error::Result;
+use crate::header::VhcHeader;
+use crate::journal::verify_journal;
+use crate::pipeline::SEQUENCE_SIZE;
+use crate::trailer::VhcTrailer;
+use crate::vhc::{
+    platform_size, read_vhc_file, read_vhc_header, read_vhc_trailer, vhc_block_region,
+};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+/// Chi-square limit for the byte histogram of the block bodies
+/// Uniform bytes give 255 ± 23 (255 degrees of freedom); this is about ten deviations out
+const CHI_SQUARE_LIMIT: f64 = 480.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Outcome of one diagnostic check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: &'static str,
+    /// What was found and, for problems, what to do about it
+    pub message: String,
+}
+
+/// Everything `diagnose` found, in the order the checks ran
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    pub findings: Vec<Finding>,
+}
+
+impl DoctorReport {
+    fn push(&mut self, severity: Severity, check: &'static str, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            check,
+            message: message.into(),
+        });
+    }
+
+    pub fn worst(&self) -> Severity {
+        self.findings
+            .iter()
+            .map(|finding| finding.severity)
+            .max()
+            .unwrap_or(Severity::Ok)
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            let label = match finding.severity {
+                Severity::Ok => "ok",
+                Severity::Warning => "warn",
+                Severity::Error => "error",
+            };
+            writeln!(f, "[{}] {}: {}", label, finding.check, finding.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check a container for common problems without any secret
+/// Only an unreadable file is an `Err`; everything else is a finding
+pub fn diagnose(path: &Path) -> Result<DoctorReport> {
+    let mut report = DoctorReport::default();
+    let file_size = std::fs::metadata(path)?.len();
+    check_build(&mut report);
+
+    let header = match read_vhc_header(path) {
+        Ok(header) => header,
+        Err(e) => {
+            let advice = if e.to_string().contains("Unsupported") {
+                "written by a newer hypercube; upgrade to read it"
+            } else if file_size < 64 {
+                "the file is too short to be a container, or was truncated"
+            } else {
+                "not a container, a truncated header, or a deniable envelope \
+                 (open those with --deniable --passphrase)"
+            };
+            report.push(Severity::Error, "header", format!("{} ({})", e, advice));
+            return Ok(report);
+        }
+    };
+    report.push(
+        Severity::Ok,
+        "header",
+        format!(
+            "version {}, {} byte blocks",
+            header.version,
+            header.total_block_size()
+        ),
+    );
+
+    let trailer = match read_vhc_trailer(path) {
+        Ok(trailer) => trailer,
+        Err(e) => {
+            report.push(
+                Severity::Error,
+                "trailer",
+                format!("{} (the file was truncated or has bytes appended)", e),
+            );
+            return Ok(report);
+        }
+    };
+
+    let (_, region) = vhc_block_region(path)?;
+    let block_size = header.total_block_size() as u64;
+    let blocks = platform_size(region / block_size)?;
+    if region % block_size == 0 {
+        report.push(
+            Severity::Ok,
+            "alignment",
+            format!("{} whole blocks", blocks),
+        );
+    } else {
+        report.push(
+            Severity::Error,
+            "alignment",
+            format!(
+                "{} stray bytes after block {} (interrupted write or edited block_size; \
+                 restore from a replica)",
+                region % block_size,
+                blocks
+            ),
+        );
+    }
+
+    let vhc = read_vhc_file(path)?;
+    check_trailer(&mut report, &header, trailer.as_ref(), &vhc.blocks);
+    check_capacity(&mut report, &header, blocks);
+    check_chaff(&mut report, &vhc.blocks);
+
+    if header.locked {
+        report.push(
+            Severity::Warning,
+            "locked",
+            "add, seal and wipe will fail; there is no unlock, so copy the blocks into a new \
+             container to write again",
+        );
+    }
+    Ok(report)
+}
+
+fn check_build(report: &mut DoctorReport) {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    let severity = if cfg!(debug_assertions) {
+        Severity::Warning
+    } else {
+        Severity::Ok
+    };
+    let mut message = format!(
+        "hypercube {} ({} build, {}-bit); reads format versions 1-{}",
+        env!("CARGO_PKG_VERSION"),
+        profile,
+        usize::BITS,
+        crate::domains::LATEST_VERSION
+    );
+    if cfg!(debug_assertions) {
+        message.push_str("; KDFs and large containers are slow, use a release build");
+    }
+    report.push(severity, "build", message);
+}
+
+fn check_trailer(
+    report: &mut DoctorReport,
+    header: &VhcHeader,
+    trailer: Option<&VhcTrailer>,
+    blocks: &[Vec<u8>],
+) {
+    let leaves = trailer.and_then(|trailer| trailer.merkle_leaves.as_ref());
+    match (header.merkle, leaves) {
+        (true, None) => report.push(
+            Severity::Error,
+            "merkle",
+            "header enables Merkle but the trailer has no leaves (the next add rebuilds them)",
+        ),
+        (true, Some(leaves)) if leaves.len() != blocks.len() => report.push(
+            Severity::Error,
+            "merkle",
+            format!(
+                "{} leaves for {} blocks (the next add rebuilds them; compare with a \
+                 replica first)",
+                leaves.len(),
+                blocks.len()
+            ),
+        ),
+        (true, Some(_)) => report.push(Severity::Ok, "merkle", "one leaf per block"),
+        (false, Some(_)) => report.push(
+            Severity::Warning,
+            "merkle",
+            "trailer has leaves but the header does not enable Merkle (they are dropped on the \
+             next write)",
+        ),
+        (false, None) => {}
+    }
+
+    let journal = trailer.map_or(&[][..], |trailer| &trailer.journal[..]);
+    if header.append_only {
+        match verify_journal(journal, blocks) {
+            Ok(()) => report.push(
+                Severity::Ok,
+                "journal",
+                format!("{} generations verified", journal.len()),
+            ),
+            Err(e) => report.push(
+                Severity::Error,
+                "journal",
+                format!("{} (blocks were replaced or the trailer was cut)", e),
+            ),
+        }
+    } else if !journal.is_empty() {
+        report.push(
+            Severity::Warning,
+            "journal",
+            "trailer has a journal but the header is not append-only (it was edited)",
+        );
+    }
+}
+
+fn check_capacity(report: &mut DoctorReport, header: &VhcHeader, blocks: usize) {
+    let capacity = header.theoretical_block_count();
+    let (severity, message) = if blocks > capacity {
+        (
+            Severity::Error,
+            format!(
+                "{} blocks exceed the cube's {} (written by a broken tool; extract and rebuild)",
+                blocks, capacity
+            ),
+        )
+    } else if blocks == capacity {
+        (
+            Severity::Warning,
+            format!(
+                "no free capacity ({} blocks): further adds fail, which is expected once sealed",
+                capacity
+            ),
+        )
+    } else if header.always_full {
+        (
+            Severity::Error,
+            format!(
+                "always-full container holds {} of {} blocks (run `hypercube seal`)",
+                blocks, capacity
+            ),
+        )
+    } else {
+        (
+            Severity::Warning,
+            format!(
+                "{} of {} blocks used; the file size shows how full it is \
+                 (run `hypercube seal`)",
+                blocks, capacity
+            ),
+        )
+    };
+    report.push(severity, "capacity", message);
+}
+
+/// What the randomness battery found in a set of block bodies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RandomnessCheck {
+    /// Bodies identical to an earlier one
+    pub duplicates: usize,
+    /// Bodies of a single repeated byte
+    pub constant: usize,
+    /// Chi-square of the byte histogram over all bodies (255 degrees of freedom)
+    pub chi_square: f64,
+    /// Expected count per histogram bucket; below about five the chi-square means nothing
+    pub expected: f64,
+}
+
+impl RandomnessCheck {
+    /// Every way the bodies fall short of random bytes
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.duplicates > 0 {
+            problems.push(format!("{} duplicate blocks", self.duplicates));
+        }
+        if self.constant > 0 {
+            problems.push(format!("{} constant blocks", self.constant));
+        }
+        if self.expected >= 5.0 && self.chi_square > CHI_SQUARE_LIMIT {
+            problems.push(format!("byte distribution chi-square {:.0}", self.chi_square));
+        }
+        problems
+    }
+}
+
+/// The battery chaff and transformed payload blocks must both pass: no repeats, no
+/// constant bodies, and a flat byte histogram
+pub fn check_randomness<'a>(bodies: impl IntoIterator<Item = &'a [u8]>) -> RandomnessCheck {
+    let mut seen = HashSet::new();
+    let mut check = RandomnessCheck {
+        duplicates: 0,
+        constant: 0,
+        chi_square: 0.0,
+        expected: 0.0,
+    };
+    let mut histogram = [0u64; 256];
+    let mut total = 0;
+    for body in bodies {
+        if !seen.insert(body) {
+            check.duplicates += 1;
+        }
+        if body.iter().all(|&byte| byte == body[0]) {
+            check.constant += 1;
+        }
+        for &byte in body {
+            histogram[byte as usize] += 1;
+        }
+        total += body.len();
+    }
+    check.expected = total as f64 / 256.0;
+    check.chi_square = histogram
+        .iter()
+        .map(|&count| (count as f64 - check.expected).powi(2) / check.expected)
+        .sum();
+    check
+}
+
+/// Chaff should be indistinguishable from random bytes: no repeats, no constant blocks,
+/// and a flat byte histogram over the block bodies
+fn check_chaff(report: &mut DoctorReport, blocks: &[Vec<u8>]) {
+    if blocks.is_empty() {
+        return;
+    }
+    let check = check_randomness(blocks.iter().map(|block| &block[SEQUENCE_SIZE..]));
+    let (chi_square, problems) = (check.chi_square, check.problems());
+    if problems.is_empty() {
+        report.push(
+            Severity::Ok,
+            "entropy",
+            format!("block bodies look random (chi-square {:.0})", chi_square),
+        );
+    } else {
+        report.push(
+            Severity::Warning,
+            "entropy",
+            format!(
+                "{} (a weak chaff generator, a foreign tool, or a build before random \
+                 AONT key padding; re-create the container)",
+                problems.join(", ")
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::seal::seal_file;
+    use crate::vhc::write_vhc_file;
+    use tempfile::tempdir;
+
+    fn severity_of(report: &DoctorReport, check: &str) -> Option<Severity> {
+        report
+            .findings
+            .iter()
+            .find(|finding| finding.check == check)
+            .map(|finding| finding.severity)
+    }
+
+    #[test]
+    fn test_healthy_container() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"checked daily").unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            merkle: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        seal_file(&vhc).unwrap();
+
+        let report = diagnose(&vhc).unwrap();
+        assert_eq!(report.count(Severity::Error), 0, "{}", report);
+        assert_eq!(
+            severity_of(&report, "entropy"),
+            Some(Severity::Ok),
+            "{}",
+            report
+        );
+        assert_eq!(severity_of(&report, "merkle"), Some(Severity::Ok));
+        assert_eq!(severity_of(&report, "capacity"), Some(Severity::Warning));
+    }
+
+    #[test]
+    fn test_problems_are_reported() {
+        let dir = tempdir().unwrap();
+        let vhc = dir.path().join("vault.vhc");
+
+        // Zero-filled "chaff", and a Merkle flag whose leaves were swapped for an empty trailer
+        let mut header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+        header.merkle = true;
+        let mut file = crate::vhc::VhcFile::new(header);
+        let block_size = file.header.total_block_size();
+        file.blocks = vec![vec![0u8; block_size]; 8];
+        write_vhc_file(&vhc, &file).unwrap();
+        let mut bytes = std::fs::read(&vhc).unwrap();
+        bytes.truncate(bytes.len() - (5 + 8 * 32 + 4));
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        std::fs::write(&vhc, &bytes).unwrap();
+
+        let report = diagnose(&vhc).unwrap();
+        assert_eq!(severity_of(&report, "merkle"), Some(Severity::Error));
+        let entropy = report
+            .findings
+            .iter()
+            .find(|f| f.check == "entropy")
+            .unwrap();
+        assert!(entropy.message.contains("7 duplicate blocks"), "{}", report);
+        assert!(entropy.message.contains("8 constant blocks"), "{}", report);
+
+        // Cut into the last block: the trailer length is now read from block bytes
+        let bytes = std::fs::read(&vhc).unwrap();
+        std::fs::write(&vhc, &bytes[..bytes.len() - 10]).unwrap();
+        let report = diagnose(&vhc).unwrap();
+        assert_eq!(report.worst(), Severity::Error, "{}", report);
+
+        std::fs::write(&vhc, b"not a container").unwrap();
+        let report = diagnose(&vhc).unwrap();
+        assert_eq!(severity_of(&report, "header"), Some(Severity::Error));
+        assert!(diagnose(&dir.path().join("missing.vhc")).is_err());
+    }
+}
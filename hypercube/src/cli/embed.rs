@@ -0,0 +1,114 @@
+use crate::carrier::{carrier_by_name, detect_carrier, Carrier, CARRIERS};
+use crate::error::{HypercubeError, Result};
+use crate::file_mode::write_file;
+use std::path::Path;
+
+/// Options for the embed command
+#[derive(Debug, Clone, Default)]
+pub struct EmbedOptions {
+    /// Carrier format name; detected from the carrier file when None
+    pub carrier: Option<String>,
+}
+
+/// Hide a container inside a carrier file, writing the polyglot to `output_path`
+/// Returns the name of the carrier format used
+pub fn embed_container(
+    container_path: &Path,
+    carrier_path: &Path,
+    output_path: &Path,
+    options: &EmbedOptions,
+) -> Result<&'static str> {
+    let container = std::fs::read(container_path)?;
+    let carrier_data = std::fs::read(carrier_path)?;
+
+    let carrier: &dyn Carrier = match &options.carrier {
+        Some(name) => carrier_by_name(name)?,
+        None => detect_carrier(&carrier_data).ok_or_else(|| {
+            HypercubeError::InvalidFormat(format!(
+                "Unrecognised carrier {} (supported: {})",
+                carrier_path.display(),
+                carrier_names()
+            ))
+        })?,
+    };
+
+    let polyglot = carrier.embed(&carrier_data, &container)?;
+    write_file(output_path, &polyglot)?;
+    Ok(carrier.name())
+}
+
+/// Recover an embedded container from a carrier file
+/// Returns the carrier format and the container size in bytes
+pub fn dig_container(input_path: &Path, output_path: &Path) -> Result<(&'static str, usize)> {
+    let data = std::fs::read(input_path)?;
+
+    for carrier in CARRIERS.iter().filter(|carrier| carrier.detect(&data)) {
+        if let Some(container) = carrier.dig(&data)? {
+            write_file(output_path, &container)?;
+            return Ok((carrier.name(), container.len()));
+        }
+    }
+    Err(HypercubeError::InvalidFormat(format!(
+        "No embedded container found in {}",
+        input_path.display()
+    )))
+}
+
+fn carrier_names() -> String {
+    CARRIERS
+        .iter()
+        .map(|carrier| carrier.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_embed_dig_extract() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        let carrier = dir.path().join("doc.pdf");
+        let polyglot = dir.path().join("out.pdf");
+        let dug = dir.path().join("dug.vhc");
+        let output = dir.path().join("output.txt");
+
+        std::fs::write(&input, b"hidden in a document").unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        std::fs::write(
+            &carrier,
+            b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog >>\nendobj\nxref\n0 2\n\
+              0000000000 65535 f \n0000000009 00000 n \n\
+              trailer\n<< /Size 2 /Root 1 0 R >>\nstartxref\n45\n%%EOF\n",
+        )
+        .unwrap();
+
+        let name = embed_container(&vhc, &carrier, &polyglot, &EmbedOptions::default()).unwrap();
+        assert_eq!(name, "pdf");
+        assert_eq!(dig_container(&polyglot, &dug).unwrap().0, "pdf");
+        assert_eq!(std::fs::read(&dug).unwrap(), std::fs::read(&vhc).unwrap());
+
+        let extract = ExtractOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&dug, &output, &extract).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"hidden in a document");
+
+        assert!(dig_container(&carrier, &dug).is_err());
+        let forced = EmbedOptions {
+            carrier: Some("zip".into()),
+        };
+        assert!(embed_container(&vhc, &carrier, &polyglot, &forced).is_err());
+    }
+}
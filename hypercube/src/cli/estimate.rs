@@ -0,0 +1,198 @@
+use crate::error::Result;
+use crate::partition::{find_partition_blocks, generate_chaff};
+use crate::vhc::read_vhc_file;
+use std::path::Path;
+use std::time::Instant;
+
+/// Blocks whose MAC checks are timed when no sample size is given
+pub const DEFAULT_SAMPLE_BLOCKS: usize = 1024;
+
+/// The sample is checked repeatedly until this much time has passed, so the
+/// per-block figure is not lost in timer noise on small containers
+const MIN_SAMPLE_SECONDS: f64 = 0.05;
+
+/// Options for the estimate-extract command
+#[derive(Debug, Clone, Default)]
+pub struct EstimateOptions {
+    /// Blocks to benchmark MAC checks on (default `DEFAULT_SAMPLE_BLOCKS`)
+    pub sample: Option<usize>,
+}
+
+/// Projected cost of a full extraction scan, measured on this machine
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtractEstimate {
+    /// Blocks stored in the container
+    pub blocks: usize,
+    /// Blocks the container can hold
+    pub capacity: usize,
+    /// Size of the container file
+    pub file_size: u64,
+    /// Time to read and parse the whole container
+    pub read_seconds: f64,
+    /// Time to stretch one secret with the container KDF (0 without one)
+    pub kdf_seconds: f64,
+    /// Average time to check one block's MAC
+    pub mac_seconds_per_block: f64,
+    /// Distinct blocks the MAC figure was measured on
+    pub sampled_blocks: usize,
+    /// MAC checks timed in all
+    pub checks: usize,
+    /// The header carries bloom hints, so hinted partitions check fewer blocks
+    pub hinted: bool,
+}
+
+impl ExtractEstimate {
+    /// Time to MAC-check every stored block
+    pub fn scan_seconds(&self) -> f64 {
+        self.mac_seconds_per_block * self.blocks as f64
+    }
+
+    /// Read, key derivation and scan together
+    pub fn total_seconds(&self) -> f64 {
+        self.read_seconds + self.kdf_seconds + self.scan_seconds()
+    }
+
+    /// The same extraction once the container is full, assuming reads grow with its size
+    pub fn full_seconds(&self) -> f64 {
+        let growth = if self.blocks > 0 {
+            self.capacity as f64 / self.blocks as f64
+        } else {
+            1.0
+        };
+        self.read_seconds * growth
+            + self.kdf_seconds
+            + self.mac_seconds_per_block * self.capacity as f64
+    }
+}
+
+impl std::fmt::Display for ExtractEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Blocks: {} of {} ({} bytes)", self.blocks, self.capacity, self.file_size)?;
+        writeln!(f, "Read: {}", format_seconds(self.read_seconds))?;
+        if self.kdf_seconds > 0.0 {
+            writeln!(f, "Key derivation: {}", format_seconds(self.kdf_seconds))?;
+        } else {
+            writeln!(f, "Key derivation: none")?;
+        }
+        writeln!(
+            f,
+            "MAC check: {:.3} µs per block ({} checks over {} blocks)",
+            self.mac_seconds_per_block * 1e6,
+            self.checks,
+            self.sampled_blocks
+        )?;
+        writeln!(f, "Scan: {}", format_seconds(self.scan_seconds()))?;
+        writeln!(f, "Estimated extraction: {}", format_seconds(self.total_seconds()))?;
+        if self.capacity > self.blocks {
+            writeln!(
+                f,
+                "Estimated extraction when full: {}",
+                format_seconds(self.full_seconds())
+            )?;
+        }
+        if self.hinted {
+            writeln!(
+                f,
+                "Bloom hints: present (a hinted partition checks only its candidates; \
+                 these figures are the worst case)"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimate how long a full extraction scan of a container takes here, without a secret
+///
+/// The container is read once for real, the KDF is run once on a random secret, and
+/// MAC checks under a random key are timed on a sample of blocks. None of them verify,
+/// which is what a scan does for every block outside the wanted partition. Decoding the
+/// partition itself is left out: it touches only one partition's worth of blocks. An
+/// index sidecar skips the scan entirely.
+pub fn estimate_extract(path: &Path, options: &EstimateOptions) -> Result<ExtractEstimate> {
+    let start = Instant::now();
+    let vhc = read_vhc_file(path)?;
+    let read_seconds = start.elapsed().as_secs_f64();
+    let file_size = std::fs::metadata(path)?.len();
+
+    let secret = generate_chaff(32);
+    let start = Instant::now();
+    let key = vhc.header.partition_key(&secret)?;
+    let kdf_seconds = if vhc.header.kdf.is_some() {
+        start.elapsed().as_secs_f64()
+    } else {
+        0.0
+    };
+
+    let sample_size = options.sample.unwrap_or(DEFAULT_SAMPLE_BLOCKS);
+    let sample = &vhc.blocks[..vhc.blocks.len().min(sample_size)];
+    let mut checks = 0;
+    let mut elapsed = 0.0;
+    if !sample.is_empty() {
+        let start = Instant::now();
+        while elapsed < MIN_SAMPLE_SECONDS {
+            find_partition_blocks(sample, &key, &vhc.header);
+            checks += sample.len();
+            elapsed = start.elapsed().as_secs_f64();
+        }
+    }
+
+    Ok(ExtractEstimate {
+        blocks: vhc.blocks.len(),
+        capacity: vhc.header.theoretical_block_count(),
+        file_size,
+        read_seconds,
+        kdf_seconds,
+        mac_seconds_per_block: if checks > 0 { elapsed / checks as f64 } else { 0.0 },
+        sampled_blocks: sample.len(),
+        checks,
+        hinted: !vhc.header.hints.is_empty(),
+    })
+}
+
+fn format_seconds(seconds: f64) -> String {
+    if seconds < 1.0 {
+        format!("{:.3} ms", seconds * 1000.0)
+    } else if seconds < 120.0 {
+        format!("{:.2} s", seconds)
+    } else {
+        format!("{:.1} min", seconds / 60.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::kdf::Kdf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_estimate_extract() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"estimate me").unwrap();
+        let opts = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            kdf: Some(Kdf::pbkdf2(Some(1000))),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &opts).unwrap();
+
+        let options = EstimateOptions { sample: Some(4) };
+        let estimate = estimate_extract(&vhc, &options).unwrap();
+        assert_eq!(estimate.blocks, 8);
+        assert_eq!(estimate.capacity, 64);
+        assert_eq!(estimate.sampled_blocks, 4);
+        assert!(estimate.checks >= 4 && estimate.checks.is_multiple_of(4));
+        assert!(estimate.mac_seconds_per_block > 0.0);
+        assert!(estimate.kdf_seconds > 0.0);
+        assert!(estimate.total_seconds() >= estimate.scan_seconds());
+        assert!(estimate.full_seconds() > estimate.total_seconds());
+
+        let text = estimate.to_string();
+        assert!(text.contains("Blocks: 8 of 64"), "{}", text);
+        assert!(text.contains("Estimated extraction when full: "), "{}", text);
+    }
+}
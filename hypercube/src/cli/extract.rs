@@ -1,12 +1,50 @@
-use crate::partition::extract_partition;
-use crate::error::Result;
-use crate::vhc::read_vhc_file;
-use std::path::Path;
+use crate::archive::{Archive, ArchiveOptions};
+use crate::attempts::with_attempt_policy;
+use crate::cli::add::verify_passphrase;
+use crate::cli::capabilities::check_capabilities;
+use crate::partition::{extract_partition_range, find_partition_blocks, ByteRange};
+use crate::envelope::with_envelope;
+use crate::error::{HypercubeError, Result};
+use crate::file_mode::write_private_file;
+use crate::hint::candidate_blocks;
+use crate::index::lookup_partition;
+use crate::metrics::{self, Stage, BYTES_EXTRACTED};
+use crate::pipeline::DEFAULT_MAX_DECOMPRESSED_SIZE;
+use crate::progress::{Progress, ProgressSink};
+use crate::header::VhcHeader;
+use crate::vhc::{read_vhc_blocks_at, read_vhc_header, with_detached_header, VhcReader};
+use rand::{rngs::OsRng, seq::SliceRandom};
+use std::path::{Path, PathBuf};
+
+/// Blocks MAC-checked between progress reports during a full scan
+const SCAN_SLICE_BLOCKS: usize = 4096;
 
 /// Options for the extract command
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ExtractOptions {
     pub secret: String,
+    /// Container passphrase checked against a magicless container's verifier
+    pub passphrase: Option<String>,
+    /// The container is wrapped in a deniable envelope keyed to `passphrase`
+    pub deniable: bool,
+    /// The container's header is kept in this file rather than at its start
+    pub detached_header: Option<PathBuf>,
+    /// Refuse partitions that decompress to more than this many bytes (default 1 GiB)
+    pub max_size: Option<usize>,
+    /// Recover only this part of the original data
+    pub range: ByteRange,
+    /// Scan blocks in a fresh random order instead of file order, so the disk access
+    /// pattern of a full scan says nothing about block positions
+    pub random_scan: bool,
+    /// The partition is an archive: unpack its entries into the output directory
+    pub archive: bool,
+    /// Extended metadata restored when unpacking an archive
+    pub archive_options: ArchiveOptions,
+    /// Honor the container's attempt policy, recording failed attempts in this log
+    /// (see `attempts`); the policy is ignored when None
+    pub attempt_log: Option<PathBuf>,
+    /// Receives blocks scanned and bytes decoded as the partition is recovered
+    pub progress: Progress,
 }
 
 /// Extract a partition from a VHC file
@@ -17,26 +55,186 @@ pub fn extract_from_vhc(
     output_path: &Path,
     options: &ExtractOptions,
 ) -> Result<usize> {
-    // Read VHC file (all blocks)
-    let vhc = read_vhc_file(input_path)?;
+    let (data, blocks_used) = recover_from_vhc(input_path, options)?;
+    metrics::time(Stage::Io, || match options.archive {
+        true => Archive::from_bytes(&data)?.unpack(output_path, &options.archive_options),
+        false => write_private_file(output_path, &data),
+    })?;
+    metrics::counter(BYTES_EXTRACTED, data.len() as u64);
+    Ok(blocks_used)
+}
 
-    // Extract partition by scanning all blocks
-    // The extract function tries to authenticate each block with the secret
-    let data = extract_partition(&vhc.blocks, options.secret.as_bytes(), &vhc.header)?;
+/// Recover a partition (or `options.range` of it) into memory, for `cat`
+pub fn read_from_vhc(input_path: &Path, options: &ExtractOptions) -> Result<Vec<u8>> {
+    let (data, _) = recover_from_vhc(input_path, options)?;
+    metrics::counter(BYTES_EXTRACTED, data.len() as u64);
+    Ok(data)
+}
 
-    // Write extracted data to output
-    std::fs::write(output_path, &data)?;
+/// Recover a partition's data and the number of blocks that matched
+fn recover_from_vhc(input_path: &Path, options: &ExtractOptions) -> Result<(Vec<u8>, usize)> {
+    if options.deniable {
+        let passphrase = options.passphrase.as_deref().ok_or_else(|| {
+            HypercubeError::InvalidFormat("A deniable container needs a passphrase".into())
+        })?;
+        let inner = ExtractOptions {
+            secret: options.secret.clone(),
+            max_size: options.max_size,
+            range: options.range,
+            random_scan: options.random_scan,
+            attempt_log: options.attempt_log.clone(),
+            progress: options.progress.clone(),
+            ..Default::default()
+        };
+        return with_envelope(input_path, passphrase, false, |container| {
+            recover_from_vhc(container, &inner)
+        });
+    }
+    if let Some(header_path) = &options.detached_header {
+        let inner = ExtractOptions {
+            detached_header: None,
+            ..options.clone()
+        };
+        return with_detached_header(input_path, header_path, false, |container| {
+            recover_from_vhc(container, &inner)
+        });
+    }
+
+    let max_size = options.max_size.unwrap_or(DEFAULT_MAX_DECOMPRESSED_SIZE);
+    if let Some(passphrase) = &options.passphrase {
+        verify_passphrase(input_path, passphrase)?;
+    }
+    let header = read_vhc_header(input_path)?;
+    check_capabilities(&header)?;
+    let derive = || header.partition_key(options.secret.as_bytes());
+    match &options.attempt_log {
+        Some(log) => with_attempt_policy(log, &header, derive, |key| {
+            recover_with_key(input_path, &key, max_size, options)
+        }),
+        None => recover_with_key(input_path, &derive()?, max_size, options),
+    }
+}
+
+/// Recover a partition's data with its derived key
+fn recover_with_key(
+    input_path: &Path,
+    secret: &[u8],
+    max_size: usize,
+    options: &ExtractOptions,
+) -> Result<(Vec<u8>, usize)> {
+    // Fast path: an index sidecar entry lets us read only our own blocks
+    // A stale one is left as it is, as extraction never writes; `update` refreshes it
+    if let Some(indices) = lookup_partition(input_path, secret)? {
+        if let Some(data) = extract_indexed(input_path, &indices, secret, max_size, options.range)? {
+            return Ok((data, indices.len()));
+        }
+    }
+
+    // Map the VHC file; blocks are read from disk only as the scan reaches them, in
+    // file order or in `order` when randomized
+    // SAFETY: extraction never writes the container, and the mapping is dropped on return;
+    // writing it from elsewhere meanwhile is unsupported, as the README says
+    let vhc = unsafe { VhcReader::map(input_path)? };
+    let mut order: Vec<usize> = (0..vhc.block_count()).collect();
+    if options.random_scan {
+        order.shuffle(&mut OsRng);
+    }
+    let blocks: Vec<&[u8]> = order.iter().filter_map(|&i| vhc.block(i)).collect();
+    let header = vhc.header();
+
+    // Bloom hints let us MAC-check only the blocks matching our filter;
+    // otherwise scan all blocks, authenticating each with the secret
+    let data = match extract_hinted(header, &blocks, secret, max_size, options.range)? {
+        Some(data) => data,
+        // Chunked partitions need the shared chunks as well as their own blocks
+        None if options.progress.is_enabled() && !header.chunked => {
+            let own = scan_with_progress(&blocks, secret, header, &options.progress);
+            extract_partition_range(&own, secret, header, max_size, options.range)?
+        }
+        None => extract_partition_range(&blocks, secret, header, max_size, options.range)?,
+    };
+    let size = data.len() as u64;
+    options.progress.bytes_processed(size, size);
 
     // Return number of blocks that were authenticated
     // (We don't have direct access to this, but we can estimate from data size)
-    let blocks_used = (data.len() / vhc.header.block_size) + 1;
-    Ok(blocks_used)
+    let blocks_used = (data.len() / header.block_size) + 1;
+    Ok((data, blocks_used))
+}
+
+/// Find the partition's blocks a slice at a time, reporting each slice to `progress`
+fn scan_with_progress<'a>(
+    blocks: &[&'a [u8]],
+    secret: &[u8],
+    header: &VhcHeader,
+    progress: &Progress,
+) -> Vec<&'a [u8]> {
+    let total = blocks.len() as u64;
+    let mut own = Vec::new();
+    let mut scanned = 0;
+    for slice in blocks.chunks(SCAN_SLICE_BLOCKS) {
+        own.extend(find_partition_blocks(slice, secret, header).into_iter().map(|i| slice[i]));
+        scanned += slice.len() as u64;
+        progress.blocks_scanned(scanned, total);
+    }
+    own
+}
+
+/// Try extracting using only the blocks matching the header's bloom hints
+/// Returns None when this secret's partition was not hinted
+fn extract_hinted(
+    header: &VhcHeader,
+    blocks: &[&[u8]],
+    secret: &[u8],
+    max_size: usize,
+    range: ByteRange,
+) -> Result<Option<Vec<u8>>> {
+    if header.hints.is_empty() {
+        return Ok(None);
+    }
+
+    let candidates = candidate_blocks(&header.hints, secret, blocks, header.domains()?);
+    // Partitions always fill blocks_per_partition blocks; fewer matches
+    // are false positives from other partitions' filters
+    let matched = find_partition_blocks(&candidates, secret, header).len();
+    if matched != header.blocks_per_partition() {
+        return Ok(None);
+    }
+
+    extract_partition_range(&candidates, secret, header, max_size, range).map(Some)
+}
+
+/// Try extracting using only the blocks listed in the index
+/// Returns None when the entry is stale and a full scan is needed
+fn extract_indexed(
+    input_path: &Path,
+    indices: &[usize],
+    secret: &[u8],
+    max_size: usize,
+    range: ByteRange,
+) -> Result<Option<Vec<u8>>> {
+    let header = read_vhc_header(input_path)?;
+    let blocks = match read_vhc_blocks_at(input_path, indices)? {
+        Some(blocks) => blocks,
+        None => return Ok(None),
+    };
+
+    // Every listed block must still be ours, otherwise positions have moved
+    if find_partition_blocks(&blocks, secret, &header).len() != blocks.len() {
+        return Ok(None);
+    }
+
+    Ok(extract_partition_range(&blocks, secret, &header, max_size, range).ok())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::update::{update_partition, UpdateOptions};
+    use crate::index::index_path;
+    use crate::kdf::Kdf;
+    use crate::vhc::read_vhc_file;
     use tempfile::tempdir;
 
     #[test]
@@ -60,6 +258,7 @@ mod tests {
         // Extract partition
         let extract_options = ExtractOptions {
             secret: "my_secret".into(),
+            ..Default::default()
         };
         extract_from_vhc(&vhc_path, &output_path, &extract_options).unwrap();
 
@@ -68,6 +267,50 @@ mod tests {
         assert_eq!(original_data, extracted);
     }
 
+    #[test]
+    fn test_extract_framed_range() {
+        let dir = tempdir().unwrap();
+        let input_path = dir.path().join("input.txt");
+        let vhc_path = dir.path().join("test.vhc");
+        let output_path = dir.path().join("output.txt");
+        let original_data: Vec<u8> = (0..20_000).map(|i| ((i * 7 + 13) % 251) as u8).collect();
+        std::fs::write(&input_path, &original_data).unwrap();
+
+        let add_options = AddOptions {
+            secret: "my_secret".into(),
+            frame_size: Some(1024),
+            ..Default::default()
+        };
+        add_partition(&input_path, &vhc_path, &add_options).unwrap();
+        assert_eq!(read_vhc_header(&vhc_path).unwrap().frame_size, 1024);
+
+        // A second partition is framed the same way, as the header says
+        let second = dir.path().join("second.txt");
+        std::fs::write(&second, b"short").unwrap();
+        let second_options = AddOptions {
+            secret: "other".into(),
+            ..Default::default()
+        };
+        add_partition(&second, &vhc_path, &second_options).unwrap();
+
+        let mut options = ExtractOptions {
+            secret: "my_secret".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc_path, &output_path, &options).unwrap();
+        assert_eq!(std::fs::read(&output_path).unwrap(), original_data);
+
+        options.range = ByteRange {
+            offset: 3000,
+            length: Some(2500),
+        };
+        assert_eq!(read_from_vhc(&vhc_path, &options).unwrap(), &original_data[3000..5500]);
+
+        options.secret = "other".into();
+        options.range = ByteRange::default();
+        assert_eq!(read_from_vhc(&vhc_path, &options).unwrap(), b"short");
+    }
+
     #[test]
     fn test_extract_wrong_secret() {
         let dir = tempdir().unwrap();
@@ -85,6 +328,7 @@ mod tests {
 
         let extract_options = ExtractOptions {
             secret: "wrong_secret".into(),
+            ..Default::default()
         };
         let result = extract_from_vhc(&vhc_path, &output_path, &extract_options);
         assert!(result.is_err());
@@ -122,6 +366,7 @@ mod tests {
         // Extract first partition
         let extract1 = ExtractOptions {
             secret: "secret1".into(),
+            ..Default::default()
         };
         extract_from_vhc(&vhc_path, &output, &extract1).unwrap();
         assert_eq!(std::fs::read(&output).unwrap(), data1);
@@ -129,8 +374,219 @@ mod tests {
         // Extract second partition
         let extract2 = ExtractOptions {
             secret: "secret2".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc_path, &output, &extract2).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), data2);
+    }
+
+    #[test]
+    fn test_extract_random_scan_chunked() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc_path = dir.path().join("test.vhc");
+        let data: Vec<u8> = (0..6000).map(|i| ((i * 13 + 5) % 251) as u8).collect();
+        std::fs::write(&input, &data).unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            chunked: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc_path, &options).unwrap();
+
+        for _ in 0..3 {
+            let options = ExtractOptions {
+                secret: "secret".into(),
+                random_scan: true,
+                range: ByteRange {
+                    offset: 100,
+                    length: Some(4000),
+                },
+                ..Default::default()
+            };
+            assert_eq!(read_from_vhc(&vhc_path, &options).unwrap(), data[100..4100]);
+        }
+    }
+
+    #[test]
+    fn test_extract_with_index_sidecar() {
+        let dir = tempdir().unwrap();
+        let input1 = dir.path().join("input1.txt");
+        let input2 = dir.path().join("input2.txt");
+        let vhc_path = dir.path().join("test.vhc");
+        let output = dir.path().join("output.txt");
+
+        let data1: Vec<u8> = (0..3000).map(|i| ((i * 7 + 13) % 256) as u8).collect();
+        let data2: Vec<u8> = (0..3000).map(|i| ((i * 11 + 29) % 256) as u8).collect();
+        std::fs::write(&input1, &data1).unwrap();
+        std::fs::write(&input2, &data2).unwrap();
+
+        let options1 = AddOptions {
+            secret: "secret1".into(),
+            index: true,
+            ..Default::default()
+        };
+        add_partition(&input1, &vhc_path, &options1).unwrap();
+        let key = read_vhc_header(&vhc_path).unwrap().partition_key(b"secret1").unwrap();
+        let indexed = lookup_partition(&vhc_path, &key).unwrap().unwrap();
+
+        // Second add reshuffles the container, leaving the first entry stale
+        let options2 = AddOptions {
+            secret: "secret2".into(),
+            index: true,
+            ..Default::default()
+        };
+        add_partition(&input2, &vhc_path, &options2).unwrap();
+
+        // The stale entry falls back to a scan, which leaves the sidecar alone
+        let sidecar = std::fs::read(index_path(&vhc_path)).unwrap();
+        let extract1 = ExtractOptions {
+            secret: "secret1".into(),
+            random_scan: true,
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc_path, &output, &extract1).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), data1);
+        assert_eq!(std::fs::read(index_path(&vhc_path)).unwrap(), sidecar);
+
+        // An update records the partition's current positions
+        let update = UpdateOptions {
+            secret: "secret1".into(),
+            ..Default::default()
+        };
+        update_partition(&input1, &vhc_path, &update).unwrap();
+        let vhc = read_vhc_file(&vhc_path).unwrap();
+        let refreshed = lookup_partition(&vhc_path, &key).unwrap().unwrap();
+        assert_eq!(refreshed.len(), indexed.len());
+        assert_eq!(refreshed, find_partition_blocks(&vhc.blocks, &key, &vhc.header));
+
+        let extract2 = ExtractOptions {
+            secret: "secret2".into(),
+            ..Default::default()
         };
         extract_from_vhc(&vhc_path, &output, &extract2).unwrap();
         assert_eq!(std::fs::read(&output).unwrap(), data2);
     }
+
+    #[test]
+    fn test_extract_with_bloom_hints() {
+        let dir = tempdir().unwrap();
+        let hinted = dir.path().join("hinted.txt");
+        let plain = dir.path().join("plain.txt");
+        let vhc_path = dir.path().join("test.vhc");
+        let output = dir.path().join("output.txt");
+
+        let data1: Vec<u8> = (0..3000).map(|i| ((i * 7 + 13) % 256) as u8).collect();
+        let data2: Vec<u8> = (0..3000).map(|i| ((i * 11 + 29) % 256) as u8).collect();
+        std::fs::write(&hinted, &data1).unwrap();
+        std::fs::write(&plain, &data2).unwrap();
+
+        let options1 = AddOptions {
+            secret: "hinted".into(),
+            hint: true,
+            ..Default::default()
+        };
+        add_partition(&hinted, &vhc_path, &options1).unwrap();
+        let options2 = AddOptions {
+            secret: "plain".into(),
+            ..Default::default()
+        };
+        add_partition(&plain, &vhc_path, &options2).unwrap();
+        assert_eq!(read_vhc_header(&vhc_path).unwrap().hints.len(), 1);
+
+        let extract1 = ExtractOptions {
+            secret: "hinted".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc_path, &output, &extract1).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), data1);
+
+        // Partitions without a hint still extract via the full scan
+        let extract2 = ExtractOptions {
+            secret: "plain".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc_path, &output, &extract2).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), data2);
+    }
+
+    #[test]
+    fn test_extract_from_deniable_envelope() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc_path = dir.path().join("noise.bin");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, b"hidden in plain noise").unwrap();
+
+        let add_options = AddOptions {
+            secret: "secret".into(),
+            passphrase: Some("outer".into()),
+            deniable: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc_path, &add_options).unwrap();
+
+        let plain = ExtractOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        assert!(extract_from_vhc(&vhc_path, &output, &plain).is_err());
+
+        let deniable = ExtractOptions {
+            secret: "secret".into(),
+            passphrase: Some("outer".into()),
+            deniable: true,
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc_path, &output, &deniable).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"hidden in plain noise");
+    }
+
+    #[test]
+    fn test_extract_with_kdf() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, b"hardened").unwrap();
+
+        let kdfs = [
+            Kdf::Argon2id {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            },
+            Kdf::Scrypt {
+                log_n: 4,
+                r: 8,
+                p: 1,
+            },
+        ];
+        for (i, kdf) in kdfs.into_iter().enumerate() {
+            let vhc = dir.path().join(format!("vault{}.vhc", i));
+            let add = AddOptions {
+                secret: "passphrase".into(),
+                kdf: Some(kdf),
+                index: true,
+                ..Default::default()
+            };
+            add_partition(&input, &vhc, &add).unwrap();
+            assert_eq!(read_vhc_header(&vhc).unwrap().kdf.unwrap().kdf, kdf);
+
+            // The raw secret no longer authenticates any block
+            let raw = read_vhc_file(&vhc).unwrap();
+            assert!(find_partition_blocks(&raw.blocks, b"passphrase", &raw.header).is_empty());
+
+            let extract = ExtractOptions {
+                secret: "passphrase".into(),
+                ..Default::default()
+            };
+            extract_from_vhc(&vhc, &output, &extract).unwrap();
+            assert_eq!(std::fs::read(&output).unwrap(), b"hardened");
+            let wrong = ExtractOptions {
+                secret: "guess".into(),
+                ..Default::default()
+            };
+            assert!(extract_from_vhc(&vhc, &output, &wrong).is_err());
+        }
+    }
 }
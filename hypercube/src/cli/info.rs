@@ -1,79 +1,293 @@
+use crate::cli::capabilities::missing_capabilities;
+use crate::domains;
 use crate::error::Result;
-use crate::vhc::{get_block_count, read_vhc_header};
+use crate::header::VhcHeader;
+use crate::journal::verify_journal;
+use crate::pipeline::WideTag;
+use crate::signature::key_fingerprint;
+use crate::vhc::{
+    get_block_count, read_vhc_header, read_vhc_layout, read_vhc_trailer, VhcLayout, VhcReader,
+};
 use std::fs;
 use std::path::Path;
 
+/// Block slot accounting for a container
+/// Blocks cannot be attributed to partitions without their secrets, so the
+/// partition count is a lower bound: every partition has at most
+/// `blocks_per_partition` blocks (exactly that many when added via the CLI)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotUsage {
+    pub used_blocks: usize,
+    pub total_blocks: usize,
+    pub free_blocks: usize,
+    /// Fewest partitions that could hold the used blocks
+    pub min_partitions: usize,
+    /// Used blocks beyond the last whole partition's worth
+    pub partial_blocks: usize,
+    /// Full-size partitions that still fit
+    pub addable_partitions: usize,
+}
+
+impl SlotUsage {
+    pub fn new(header: &VhcHeader, block_count: usize) -> Self {
+        let per_partition = header.blocks_per_partition().max(1);
+        let total_blocks = header.theoretical_block_count();
+        let free_blocks = total_blocks.saturating_sub(block_count);
+        Self {
+            used_blocks: block_count,
+            total_blocks,
+            free_blocks,
+            min_partitions: block_count.div_ceil(per_partition),
+            partial_blocks: block_count % per_partition,
+            addable_partitions: free_blocks / per_partition,
+        }
+    }
+}
+
+/// Options for the info command
+#[derive(Debug, Clone, Default)]
+pub struct InfoOptions {
+    /// Show only format and algorithm details, leaving out anything that reveals how
+    /// full the container is or which file it is (path, id, size, generation, block and
+    /// partition counts, hints, journal, Merkle root, signer)
+    pub redacted: bool,
+}
+
 /// Display information about a VHC file
-pub fn show_info(path: &Path) -> Result<String> {
+pub fn show_info(path: &Path, options: &InfoOptions) -> Result<String> {
+    let redacted = options.redacted;
     let header = read_vhc_header(path)?;
+    let layout = read_vhc_layout(path)?;
     let block_count = get_block_count(path)?;
-    let file_size = fs::metadata(path)?.len() as usize;
+    let file_size = fs::metadata(path)?.len();
 
     let cube = header.cube();
     let block_bits = header.block_bits();
     let block_payload_bytes = header.block_size;
     let per_partition_blocks = header.blocks_per_partition();
-    let partition_capacity = block_payload_bytes * per_partition_blocks;
-    let theoretical_blocks = header.theoretical_block_count();
+    let partition_capacity = block_payload_bytes as u64 * per_partition_blocks as u64;
     let payload_capacity_bytes = header.payload_capacity_bytes();
-    let payload_capacity_bits = block_bits * theoretical_blocks;
-    let per_block_overhead = 16 + header.mac_bytes();
-    let theoretical_overhead_bytes = per_block_overhead * theoretical_blocks;
+    let payload_capacity_bits = payload_capacity_bytes.saturating_mul(8);
+    let theoretical_overhead_bytes = header.full_data_size() - payload_capacity_bytes;
     let header_bytes = header.to_bytes()?.len();
-    let header_overhead = 4 + 4 + header_bytes;
-    let theoretical_total_bytes = header_overhead + header.total_block_size() * theoretical_blocks;
+    let header_overhead = (layout.prefix_len() + 4 + header_bytes) as u64;
+    let theoretical_total_bytes = header_overhead.saturating_add(header.full_data_size());
 
     let mut output = String::new();
 
-    output.push_str(&format!("Hypercube VHC File Information\n"));
-    output.push_str(&format!("==============================\n\n"));
+    output.push_str("Hypercube VHC File Information\n");
+    output.push_str("==============================\n\n");
 
-    output.push_str(&format!("File: {}\n", path.display()));
-    output.push_str(&format!("Actual size: {}\n", format_size(file_size as u64)));
+    if redacted {
+        output.push_str("Redacted: file, usage and history details omitted\n");
+    } else {
+        output.push_str(&format!("File: {}\n", path.display()));
+        if let Some(id) = header.uuid {
+            output.push_str(&format!("Container id: {}\n", id));
+        }
+        output.push_str(&format!("Actual size: {}\n", format_size(file_size)));
+    }
     output.push_str(&format!("Version: {}\n", header.version));
-    output.push_str(&format!("\n"));
+    output.push_str(match layout {
+        VhcLayout::Magic => "Layout: magic\n",
+        VhcLayout::Keyed { .. } => "Layout: magicless (keyed verifier)\n",
+    });
+    let encoding = match header.version >= domains::BINARY_HEADER_VERSION {
+        true => "binary",
+        false => "JSON",
+    };
+    output.push_str(&format!("Header: {} ({} bytes)\n", encoding, header_bytes));
+    if header.locked {
+        output.push_str("Locked: yes (no further writes)\n");
+    }
+    if header.always_full {
+        output.push_str("Always full: yes (every write tops up chaff)\n");
+    }
+    if header.append_only {
+        output.push_str("Append-only: yes (journaled)\n");
+    }
+    if !redacted {
+        output.push_str(&format!("Generation: {}\n", header.generation));
+    }
+    output.push('\n');
 
-    output.push_str(&format!("Cube Geometry:\n"));
+    output.push_str("Cube Geometry:\n");
     output.push_str(&format!("  Cube id: {}\n", cube));
     output.push_str(&format!("  Partitions: {}\n", header.dimension));
     output.push_str(&format!(
         "  Blocks per partition: {}\n",
         per_partition_blocks
     ));
-    let partitions_used =
-        (block_count + per_partition_blocks - 1) / per_partition_blocks.max(1);
-    output.push_str(&format!(
-        "  Partitions in use: {} / {}\n",
-        partitions_used, header.dimension
-    ));
     output.push_str(&format!(
         "  Block payload: {} bytes ({} bits)\n",
         block_payload_bytes, block_bits
     ));
     output.push_str(&format!(
         "  Capacity per partition: {}\n",
-        format_size(partition_capacity as u64)
+        format_size(partition_capacity)
     ));
     output.push_str(&format!(
         "  Fragment size: {} bytes ({} fragments per block)\n",
         header.fragment_size,
         header.fragments_per_block()
     ));
-    output.push_str(&format!("\n"));
+    output.push('\n');
 
-    output.push_str(&format!("Algorithms:\n"));
+    output.push_str("Algorithms:\n");
     output.push_str(&format!("  Compression: {:?}\n", header.compression));
+    if header.frame_size != 0 {
+        output.push_str(&format!("  Frames: {} bytes (seekable)\n", header.frame_size));
+    }
+    if header.chunked {
+        output.push_str("  Chunking: content-defined\n");
+    }
+    if !header.pipeline_order.is_standard() {
+        output.push_str(&format!("  Pipeline order: {}\n", header.pipeline_order));
+    }
+    if header.derived_sequence {
+        output.push_str("  Sequence bases: derived (HKDF-SHA256)\n");
+    }
+    if let Some(code) = header.erasure {
+        output.push_str(&format!(
+            "  Erasure code: Reed–Solomon {}+{} per stripe\n",
+            code.data, code.parity
+        ));
+    }
+    if header.dedup {
+        output.push_str("  Dedup: shared chunks (convergent)\n");
+    }
     output.push_str(&format!("  AONT: {:?}\n", header.aont));
+    output.push_str(&format!("  Shuffle: {:?}\n", header.shuffle));
+    output.push_str(&format!("  Reshuffle: {}\n", header.reshuffle));
+    if let Some(kdf) = &header.kdf {
+        output.push_str(&format!("  KDF: {}\n", kdf.kdf));
+    }
+    if let Some(policy) = header.attempt_policy {
+        output.push_str(&format!(
+            "  Attempt policy: KDF at least {} ms, backoff from {} ms\n",
+            policy.min_kdf_ms, policy.backoff_ms
+        ));
+    }
     output.push_str(&format!("  Hash: {:?}\n", header.hash));
     output.push_str(&format!("  MAC bits: {}\n", header.mac_bits));
-    output.push_str(&format!("\n"));
+    if header.mac_bits > 256 && header.wide_tag() == WideTag::Expanded {
+        output.push_str("  MAC strength: 256 bits (expanded tags; new partitions are refused)\n");
+    }
+    let stages = &header.stages;
+    let registered: Vec<String> = [
+        ("compression", &stages.compression),
+        ("whitener", &stages.whitener),
+        ("aont", &stages.aont),
+        ("mac", &stages.mac),
+    ]
+    .into_iter()
+    .filter_map(|(stage, name)| Some(format!("{}={}", stage, name.as_deref()?)))
+    .collect();
+    if !registered.is_empty() {
+        // These replace the built-ins named above
+        output.push_str(&format!("  Registered stages: {}\n", registered.join(", ")));
+    }
+    // Reading the container fails until these are registered
+    for missing in missing_capabilities(&header) {
+        output.push_str(&format!("  Missing from this build: {}\n", missing));
+    }
+    if !header.hints.is_empty() && !redacted {
+        output.push_str(&format!("  Bloom hints: {}\n", header.hints.len()));
+    }
+    let trailer = read_vhc_trailer(path)?.unwrap_or_default();
+    if let Some(tree) = trailer.merkle_tree() {
+        if redacted {
+            output.push_str("  Merkle tree: stored\n");
+        } else {
+            output.push_str(&format!("  Merkle root: {}\n", hex::encode(tree.root())));
+        }
+    }
+    if redacted && trailer.signature.is_some() {
+        output.push_str("  Signature: embedded\n");
+    } else if let Some(signature) = &trailer.signature {
+        output.push_str(&format!(
+            "  Signature: embedded, key {}\n",
+            key_fingerprint(&signature.public_key)
+        ));
+    }
+    output.push('\n');
+
+    if header.append_only && !redacted {
+        // Replaying the chain needs every block, so only append-only files pay for it
+        output.push_str("Append-only Journal:\n");
+        let journal = &trailer.journal;
+        let verified = VhcReader::open(path)
+            .and_then(|vhc| verify_journal(journal, &vhc.blocks().collect::<Vec<_>>()));
+        match verified {
+            Ok(()) => output.push_str(&format!(
+                "  Status: verified ({} generations, no block replaced)\n",
+                journal.len()
+            )),
+            Err(e) => output.push_str(&format!("  Status: FAILED ({})\n", e)),
+        }
+        for entry in journal {
+            output.push_str(&format!(
+                "  Generation {}: blocks {}..{} appended at {} (unix), chain {}\n",
+                entry.generation,
+                entry.first_block,
+                entry.first_block + entry.block_count,
+                entry.timestamp,
+                hex::encode(&entry.chain[..8])
+            ));
+        }
+        if let Some(head) = journal.last() {
+            output.push_str(&format!("  Head: {}\n", hex::encode(head.chain)));
+        }
+        output.push('\n');
+    }
+
+    if !redacted {
+        output.push_str(&usage_info(&header, block_count));
+    }
+
+    output.push_str("Capacity (Full Cube):\n");
+    output.push_str(&format!(
+        "  Payload capacity: {} ({})\n",
+        format_size(payload_capacity_bytes),
+        format_bits(payload_capacity_bits),
+    ));
+    output.push_str(&format!(
+        "  Overhead (sequence + MAC): {}\n",
+        format_size(theoretical_overhead_bytes)
+    ));
+    output.push_str(&format!(
+        "  Header overhead: {}\n",
+        format_size(header_overhead)
+    ));
+    output.push_str(&format!(
+        "  Full cube file size: {}\n",
+        format_size(theoretical_total_bytes)
+    ));
+    output.push('\n');
+
+    // Security note
+    output.push_str("Security Model:\n");
+    output.push_str("  Blocks are not tracked by partition.\n");
+    output.push_str("  To extract, provide your secret key.\n");
+    output.push_str("  Only blocks matching your key will be recovered.\n");
+
+    Ok(output)
+}
+
+/// Storage and slot usage: what `--redacted` leaves out
+fn usage_info(header: &VhcHeader, block_count: usize) -> String {
+    let block_payload_bytes = header.block_size;
+    let per_block_overhead = 16 + header.mac_bytes();
+    let per_partition_blocks = header.blocks_per_partition();
+    let theoretical_blocks = header.theoretical_block_count();
+    let mut output = String::new();
 
     // Current block statistics
     let total_block_size = header.total_block_size();
     let current_payload = block_count * block_payload_bytes;
     let current_overhead = block_count * per_block_overhead;
     let current_storage = block_count * total_block_size;
-    output.push_str(&format!("Current Storage:\n"));
+    output.push_str("Current Storage:\n");
     output.push_str(&format!("  Total blocks written: {}\n", block_count));
     output.push_str(&format!(
         "  Block size (with MAC): {} bytes\n",
@@ -91,44 +305,45 @@ pub fn show_info(path: &Path) -> Result<String> {
         "  Data region usage: {}\n",
         format_size(current_storage as u64)
     ));
-    output.push_str(&format!("\n"));
+    output.push('\n');
 
-    if block_count > theoretical_blocks {
-        output.push_str(&format!(
-            "Warning: cube stores {} blocks but capacity is {}. Rebuild with a larger cube.\n\n",
-            block_count, theoretical_blocks
-        ));
-    }
-
-    output.push_str(&format!("Capacity (Full Cube):\n"));
+    let slots = SlotUsage::new(header, block_count);
+    output.push_str("Partition Slots:\n");
     output.push_str(&format!(
-        "  Payload capacity: {} ({})\n",
-        format_size(payload_capacity_bytes as u64),
-        format_bits(payload_capacity_bits as u64),
+        "  Block slots used: {} / {}\n",
+        slots.used_blocks, slots.total_blocks
     ));
     output.push_str(&format!(
-        "  Overhead (sequence + MAC): {}\n",
-        format_size(theoretical_overhead_bytes as u64)
+        "  Per-partition maximum: {} blocks\n",
+        per_partition_blocks
     ));
     output.push_str(&format!(
-        "  Header overhead: {}\n",
-        format_size(header_overhead as u64)
+        "  Partitions in use: at least {} / {}\n",
+        slots.min_partitions, header.dimension
     ));
+    if slots.partial_blocks > 0 {
+        output.push_str(&format!(
+            "  Blocks outside whole partitions: {}\n",
+            slots.partial_blocks
+        ));
+    }
     output.push_str(&format!(
-        "  Full cube file size: {}\n",
-        format_size(theoretical_total_bytes as u64)
+        "  Free block slots: {}\n",
+        slots.free_blocks
     ));
-    output.push_str(&format!("\n"));
-
-    // Security note
-    output.push_str(&format!("Security Model:\n"));
-    output.push_str(&format!("  Blocks are not tracked by partition.\n"));
-    output.push_str(&format!("  To extract, provide your secret key.\n"));
     output.push_str(&format!(
-        "  Only blocks matching your key will be recovered.\n"
+        "  Whole partitions addable: {}\n",
+        slots.addable_partitions
     ));
+    output.push('\n');
 
-    Ok(output)
+    if block_count > theoretical_blocks {
+        output.push_str(&format!(
+            "Warning: cube stores {} blocks but capacity is {}. Rebuild with a larger cube.\n\n",
+            block_count, theoretical_blocks
+        ));
+    }
+    output
 }
 
 fn format_size(bytes: u64) -> String {
@@ -175,12 +390,64 @@ mod tests {
         };
         add_partition(&input_path, &vhc_path, &options).unwrap();
 
-        let info = show_info(&vhc_path).unwrap();
+        let info = show_info(&vhc_path, &InfoOptions::default()).unwrap();
 
-        assert!(info.contains("Version: 1"));
+        assert!(info.contains("Version: 5"));
+        assert!(info.contains("Header: binary"));
+        let id = read_vhc_header(&vhc_path).unwrap().uuid.unwrap();
+        assert!(info.contains(&format!("Container id: {}\n", id)));
         assert!(info.contains("Cube id: 32")); // Cube id equals dimension
         assert!(info.contains("Blocks per partition:"));
         assert!(info.contains("Total blocks written:"));
+        assert!(info.contains("Block slots used: 32 / 1024"));
+        assert!(info.contains("Partitions in use: at least 1 / 32"));
+        assert!(info.contains("Whole partitions addable: 31"));
+    }
+
+    #[test]
+    fn test_redacted_info_omits_usage() {
+        let dir = tempdir().unwrap();
+        let input_path = dir.path().join("input.txt");
+        let vhc_path = dir.path().join("test.vhc");
+        std::fs::write(&input_path, b"Test data").unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            hint: true,
+            merkle: true,
+            ..Default::default()
+        };
+        add_partition(&input_path, &vhc_path, &options).unwrap();
+
+        let info = show_info(&vhc_path, &InfoOptions { redacted: true }).unwrap();
+        assert!(info.contains("Cube id: 32"));
+        assert!(info.contains("Hash:"));
+        assert!(info.contains("Merkle tree: stored"));
+        for hidden in [
+            "test.vhc",
+            "Container id",
+            "Actual size",
+            "Generation",
+            "Bloom hints",
+            "Merkle root",
+            "Total blocks written",
+            "Partitions in use",
+        ] {
+            assert!(!info.contains(hidden), "{} in {}", hidden, info);
+        }
+    }
+
+    #[test]
+    fn test_slot_usage_with_short_partitions() {
+        let header = VhcHeader::new(32, 32, 32, 32, 256).unwrap();
+        // One full partition plus one padded to only 10 blocks
+        let slots = SlotUsage::new(&header, 42);
+        assert_eq!(slots.min_partitions, 2);
+        assert_eq!(slots.partial_blocks, 10);
+        assert_eq!(slots.free_blocks, 1024 - 42);
+        assert_eq!(slots.addable_partitions, 30);
+
+        let full = SlotUsage::new(&header, 1024);
+        assert_eq!((full.free_blocks, full.addable_partitions), (0, 0));
     }
 
     #[test]
@@ -0,0 +1,264 @@
+//! Known-answer test vectors, for checking that this build reads containers written by
+//! other implementations and earlier versions.
+//!
+//! A vector file is JSON in the `hypercube-kat` format:
+//!
+//! ```text
+//! {
+//!   "format": "hypercube-kat",
+//!   "version": 1,
+//!   "vectors": [
+//!     {
+//!       "name": "v3-rivest-sha3",
+//!       "producer": "hypercube-go 0.4",
+//!       "container": "<hex>",
+//!       "partitions": [{ "secret": "alpha", "plaintext": "<hex>" }]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! A vector gives its container inline as hex (`container`) or as a path relative to the
+//! vector file (`container_file`). `passphrase` and `deniable` open magicless and enveloped
+//! containers. Every listed partition must extract to exactly its plaintext.
+
+use crate::cli::extract::{read_from_vhc, ExtractOptions};
+use crate::envelope::ScratchFile;
+use crate::error::{HypercubeError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// `format` of a vector file
+pub const KAT_FORMAT: &str = "hypercube-kat";
+
+/// Newest vector file version this build reads
+pub const KAT_VERSION: u32 = 1;
+
+/// A vector file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KatFile {
+    pub format: String,
+    pub version: u32,
+    pub vectors: Vec<KatVector>,
+}
+
+/// One container and the partitions it must yield
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KatVector {
+    pub name: String,
+    /// Implementation and version that wrote the container, for the report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub producer: Option<String>,
+    /// The container, hex-encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// The container's file, relative to the vector file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_file: Option<PathBuf>,
+    /// Passphrase of a magicless or deniable container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deniable: bool,
+    pub partitions: Vec<KatPartition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KatPartition {
+    pub secret: String,
+    #[serde(with = "hex")]
+    pub plaintext: Vec<u8>,
+}
+
+/// How one vector fared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KatOutcome {
+    pub name: String,
+    pub producer: Option<String>,
+    /// Why the vector failed; None when every partition extracted as expected
+    pub failure: Option<String>,
+}
+
+/// Every vector's outcome, in file order
+#[derive(Debug, Clone, Default)]
+pub struct KatReport {
+    pub outcomes: Vec<KatOutcome>,
+}
+
+impl KatReport {
+    pub fn failures(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.failure.is_some())
+            .count()
+    }
+}
+
+impl fmt::Display for KatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for outcome in &self.outcomes {
+            let label = if outcome.failure.is_some() {
+                "fail"
+            } else {
+                "pass"
+            };
+            write!(f, "[{}] {}", label, outcome.name)?;
+            if let Some(producer) = &outcome.producer {
+                write!(f, " ({})", producer)?;
+            }
+            if let Some(failure) = &outcome.failure {
+                write!(f, ": {}", failure)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check every vector in a vector file
+/// Only an unreadable or malformed vector file is an `Err`; failing vectors are reported
+pub fn verify_vectors(path: &Path) -> Result<KatReport> {
+    let file: KatFile = serde_json::from_slice(&std::fs::read(path)?)?;
+    if file.format != KAT_FORMAT {
+        return Err(HypercubeError::InvalidFormat(format!(
+            "Not a {} vector file: format is {:?}",
+            KAT_FORMAT, file.format
+        )));
+    }
+    if file.version == 0 || file.version > KAT_VERSION {
+        return Err(HypercubeError::InvalidFormat(format!(
+            "Unsupported vector file version {} (this build reads 1-{})",
+            file.version, KAT_VERSION
+        )));
+    }
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    let outcomes = file
+        .vectors
+        .into_iter()
+        .map(|vector| KatOutcome {
+            failure: verify_vector(&vector, base).err(),
+            name: vector.name,
+            producer: vector.producer,
+        })
+        .collect();
+    Ok(KatReport { outcomes })
+}
+
+/// Extract every partition of a vector; the reason on failure
+fn verify_vector(vector: &KatVector, base: &Path) -> std::result::Result<(), String> {
+    let scratch;
+    let container = match (&vector.container, &vector.container_file) {
+        (Some(container), None) => {
+            let bytes = hex::decode(container.trim())
+                .map_err(|e| format!("container is not hex: {}", e))?;
+            scratch = ScratchFile::new();
+            std::fs::write(&scratch.0, bytes).map_err(|e| e.to_string())?;
+            scratch.0.clone()
+        }
+        (None, Some(file)) => base.join(file),
+        _ => return Err("needs exactly one of container and container_file".into()),
+    };
+    if vector.partitions.is_empty() {
+        return Err("lists no partitions".into());
+    }
+
+    for (i, partition) in vector.partitions.iter().enumerate() {
+        let options = ExtractOptions {
+            secret: partition.secret.clone(),
+            passphrase: vector.passphrase.clone(),
+            deniable: vector.deniable,
+            ..Default::default()
+        };
+        // Secrets stay out of the report; partitions are numbered as listed
+        let data = read_from_vhc(&container, &options)
+            .map_err(|e| format!("partition {}: {}", i + 1, e))?;
+        if data != partition.plaintext {
+            return Err(format!(
+                "partition {}: extracted {} bytes that differ from the expected {}",
+                i + 1,
+                data.len(),
+                partition.plaintext.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_verify_vectors() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vault = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"known answer").unwrap();
+        for secret in ["alpha", "beta"] {
+            let options = AddOptions {
+                secret: secret.into(),
+                ..Default::default()
+            };
+            add_partition(&input, &vault, &options).unwrap();
+        }
+
+        let partition = |secret: &str, plaintext: &[u8]| KatPartition {
+            secret: secret.into(),
+            plaintext: plaintext.to_vec(),
+        };
+        let vectors = KatFile {
+            format: KAT_FORMAT.into(),
+            version: KAT_VERSION,
+            vectors: vec![
+                KatVector {
+                    name: "inline".into(),
+                    producer: Some("test".into()),
+                    container: Some(hex::encode(std::fs::read(&vault).unwrap())),
+                    partitions: vec![
+                        partition("alpha", b"known answer"),
+                        partition("beta", b"known answer"),
+                    ],
+                    ..Default::default()
+                },
+                KatVector {
+                    name: "file".into(),
+                    container_file: Some("vault.vhc".into()),
+                    partitions: vec![partition("alpha", b"known answer")],
+                    ..Default::default()
+                },
+                KatVector {
+                    name: "wrong-plaintext".into(),
+                    container_file: Some("vault.vhc".into()),
+                    partitions: vec![partition("beta", b"other answer")],
+                    ..Default::default()
+                },
+                KatVector {
+                    name: "wrong-secret".into(),
+                    container_file: Some("vault.vhc".into()),
+                    partitions: vec![partition("gamma", b"known answer")],
+                    ..Default::default()
+                },
+            ],
+        };
+        let path = dir.path().join("vectors.json");
+        std::fs::write(&path, serde_json::to_vec(&vectors).unwrap()).unwrap();
+
+        let report = verify_vectors(&path).unwrap();
+        assert_eq!(report.failures(), 2);
+        let failed: Vec<_> = report
+            .outcomes
+            .iter()
+            .filter(|outcome| outcome.failure.is_some())
+            .map(|outcome| outcome.name.as_str())
+            .collect();
+        assert_eq!(failed, ["wrong-plaintext", "wrong-secret"]);
+        assert!(report.to_string().starts_with("[pass] inline (test)\n"));
+
+        std::fs::write(&path, r#"{"format": "other", "version": 1, "vectors": []}"#).unwrap();
+        assert!(verify_vectors(&path).is_err());
+    }
+}
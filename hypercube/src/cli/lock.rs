@@ -0,0 +1,72 @@
+use crate::error::Result;
+use crate::vhc::{read_vhc_header, rewrite_vhc_header};
+use std::path::Path;
+
+/// Mark a container write-once: later add, seal and wipe fail with `HypercubeError::Locked`
+/// Returns false if it was already locked
+pub fn lock_container(path: &Path) -> Result<bool> {
+    let mut header = read_vhc_header(path)?;
+    if header.locked {
+        return Ok(false);
+    }
+    header.locked = true;
+    rewrite_vhc_header(path, &header)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use crate::cli::seal::seal_file;
+    use crate::cli::wipe::{wipe_partition, WipeOptions};
+    use crate::error::HypercubeError;
+    use crate::vhc::append_blocks_to_vhc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_locked_container_refuses_writes() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("archive.vhc");
+        std::fs::write(&input, b"final version").unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        assert!(lock_container(&vhc).unwrap());
+        assert!(!lock_container(&vhc).unwrap());
+        let before = std::fs::read(&vhc).unwrap();
+
+        let other = AddOptions {
+            secret: "other".into(),
+            ..Default::default()
+        };
+        let wipe = WipeOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        let block_size = read_vhc_header(&vhc).unwrap().total_block_size();
+        for result in [
+            add_partition(&input, &vhc, &other).map(drop),
+            seal_file(&vhc).map(drop),
+            wipe_partition(&vhc, &wipe).map(drop),
+            append_blocks_to_vhc(&vhc, &[vec![0u8; block_size]]).map(drop),
+        ] {
+            assert!(matches!(result, Err(HypercubeError::Locked)));
+        }
+        assert_eq!(std::fs::read(&vhc).unwrap(), before);
+
+        // Reading is unaffected
+        let output = dir.path().join("output.txt");
+        let extract = ExtractOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc, &output, &extract).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"final version");
+    }
+}
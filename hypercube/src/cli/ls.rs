@@ -0,0 +1,77 @@
+use crate::archive::Archive;
+use crate::cli::extract::{read_from_vhc, ExtractOptions};
+use crate::error::Result;
+use std::path::Path;
+
+/// The entries of an archive partition (see `archive`), read without unpacking them
+pub fn list_archive(path: &Path, options: &ExtractOptions) -> Result<Archive> {
+    let options = ExtractOptions {
+        archive: false,
+        ..options.clone()
+    };
+    Archive::from_bytes(&read_from_vhc(path, &options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::EntryKind;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::extract_from_vhc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_partition() {
+        let dir = tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::create_dir_all(tree.join("sub")).unwrap();
+        std::fs::write(tree.join("one.txt"), b"first file").unwrap();
+        std::fs::write(tree.join("sub/two.txt"), b"second file").unwrap();
+        std::fs::write(dir.path().join("plain.txt"), b"not an archive").unwrap();
+
+        let add = |input: &Path, secret: &str, archive: bool| {
+            let options = AddOptions {
+                secret: secret.into(),
+                dimension: 8,
+                kdf: None,
+                archive,
+                ..Default::default()
+            };
+            add_partition(input, &vhc, &options).unwrap();
+        };
+        add(&tree, "archived", true);
+        add(&dir.path().join("plain.txt"), "plain", false);
+
+        let options = |secret: &str| ExtractOptions {
+            secret: secret.into(),
+            ..Default::default()
+        };
+        let archive = list_archive(&vhc, &options("archived")).unwrap();
+        let listed: Vec<_> = archive
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.kind, entry.data.len()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("one.txt", EntryKind::File, 10),
+                ("sub", EntryKind::Directory, 0),
+                ("sub/two.txt", EntryKind::File, 11),
+            ]
+        );
+        assert!(list_archive(&vhc, &options("plain")).is_err());
+
+        let out = dir.path().join("out");
+        let unpack = ExtractOptions {
+            archive: true,
+            ..options("archived")
+        };
+        extract_from_vhc(&vhc, &out, &unpack).unwrap();
+        assert_eq!(
+            std::fs::read(out.join("sub/two.txt")).unwrap(),
+            b"second file"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::header::{Aont, Compression, ContainerId, HashAlgorithm};
+use crate::partition::{extract_partition_bounded, find_partition_blocks};
+use crate::pipeline::DEFAULT_MAX_DECOMPRESSED_SIZE;
+use crate::vhc::{read_vhc_header, VhcReader};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Version of the manifest JSON format
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Options for the manifest command
+#[derive(Debug, Clone, Default)]
+pub struct ManifestOptions {
+    pub secret: String,
+    /// Name to record for the partition; partitions carry none of their own
+    pub label: Option<String>,
+}
+
+/// What a partition held when its manifest was exported, MACed under the partition key
+/// Anyone holding the secret can later check the manifest, and a copy of the data against
+/// it, without scanning the container again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionManifest {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<ContainerId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Bytes of original data
+    pub original_size: u64,
+    /// BLAKE3 of the original data, hex encoded
+    pub content_hash: String,
+    /// Blocks the partition occupies
+    pub blocks: usize,
+    /// Bytes those blocks take on disk
+    pub stored_size: u64,
+    pub block_size: usize,
+    pub compression: Compression,
+    pub aont: Aont,
+    pub hash: HashAlgorithm,
+    pub mac_bits: usize,
+    /// Keyed BLAKE3 over the manifest without this field, hex encoded
+    #[serde(default)]
+    pub mac: String,
+}
+
+impl PartitionManifest {
+    /// The MAC over every other field, under a key derived from the partition key
+    fn compute_mac(&self, key: &[u8]) -> Result<String> {
+        let unsigned = Self {
+            mac: String::new(),
+            ..self.clone()
+        };
+        let mac_key = blake3::derive_key(domains::MANIFEST, key);
+        Ok(
+            blake3::keyed_hash(&mac_key, &serde_json::to_vec(&unsigned)?)
+                .to_hex()
+                .to_string(),
+        )
+    }
+
+    /// Check the MAC, then that `data` is what the manifest describes
+    pub fn verify(&self, key: &[u8], data: &[u8]) -> Result<()> {
+        // Hex digests compare in constant time as hashes
+        let expected = blake3::Hash::from_hex(self.compute_mac(key)?).unwrap();
+        if blake3::Hash::from_hex(&self.mac).ok() != Some(expected) {
+            return Err(HypercubeError::IntegrityError(
+                "Manifest MAC does not match (wrong secret or altered manifest)".into(),
+            ));
+        }
+        if data.len() as u64 != self.original_size
+            || blake3::hash(data).to_hex().as_str() != self.content_hash
+        {
+            return Err(HypercubeError::IntegrityError(
+                "Copy does not match the manifest".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Describe a partition and MAC the description under its key
+pub fn export_manifest(path: &Path, options: &ManifestOptions) -> Result<PartitionManifest> {
+    let vhc = VhcReader::open(path)?;
+    let header = vhc.header();
+    let key = header.partition_key(options.secret.as_bytes())?;
+    let blocks: Vec<&[u8]> = (0..vhc.block_count())
+        .filter_map(|i| vhc.block(i))
+        .collect();
+    let own: Vec<&[u8]> = find_partition_blocks(&blocks, &key, header)
+        .into_iter()
+        .map(|position| blocks[position])
+        .collect();
+    let data = extract_partition_bounded(&own, &key, header, DEFAULT_MAX_DECOMPRESSED_SIZE)?;
+
+    let mut manifest = PartitionManifest {
+        version: MANIFEST_VERSION,
+        container_id: header.uuid,
+        label: options.label.clone(),
+        original_size: data.len() as u64,
+        content_hash: blake3::hash(&data).to_hex().to_string(),
+        blocks: own.len(),
+        stored_size: own.len() as u64 * header.total_block_size() as u64,
+        block_size: header.block_size,
+        compression: header.compression,
+        aont: header.aont,
+        hash: header.hash,
+        mac_bits: header.mac_bits,
+        mac: String::new(),
+    };
+    manifest.mac = manifest.compute_mac(&key)?;
+    Ok(manifest)
+}
+
+/// Check an extracted `copy` against a manifest exported from the container at `path`
+/// Only the container's header is read, for the secret's key derivation
+pub fn check_manifest(
+    path: &Path,
+    manifest: &PartitionManifest,
+    secret: &str,
+    copy: &Path,
+) -> Result<()> {
+    let header = read_vhc_header(path)?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(HypercubeError::InvalidFormat(format!(
+            "Unsupported manifest version {}",
+            manifest.version
+        )));
+    }
+    if manifest.container_id.is_some() && manifest.container_id != header.uuid {
+        return Err(HypercubeError::IntegrityError(
+            "Manifest was exported from another container".into(),
+        ));
+    }
+    let key = header.partition_key(secret.as_bytes())?;
+    manifest.verify(&key, &std::fs::read(copy)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"manifest me").unwrap();
+        let add = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            kdf: None,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &add).unwrap();
+
+        let options = ManifestOptions {
+            secret: "secret".into(),
+            label: Some("notes".into()),
+        };
+        let manifest = export_manifest(&vhc, &options).unwrap();
+        assert_eq!(manifest.original_size, 11);
+        assert_eq!(manifest.blocks, 8);
+        assert_eq!(manifest.label.as_deref(), Some("notes"));
+        assert_eq!(manifest.container_id, read_vhc_header(&vhc).unwrap().uuid);
+
+        // Survives JSON and checks a faithful copy
+        let json = serde_json::to_string_pretty(&manifest).unwrap();
+        let parsed: PartitionManifest = serde_json::from_str(&json).unwrap();
+        check_manifest(&vhc, &parsed, "secret", &input).unwrap();
+
+        // A changed copy, a wrong secret and an edited manifest are all refused
+        let changed = dir.path().join("changed.txt");
+        std::fs::write(&changed, b"manifest me!").unwrap();
+        assert!(check_manifest(&vhc, &parsed, "secret", &changed).is_err());
+        assert!(check_manifest(&vhc, &parsed, "wrong", &input).is_err());
+        let edited = PartitionManifest {
+            label: Some("other".into()),
+            ..parsed.clone()
+        };
+        assert!(check_manifest(&vhc, &edited, "secret", &input).is_err());
+        assert!(export_manifest(
+            &vhc,
+            &ManifestOptions {
+                secret: "wrong".into(),
+                label: None,
+            }
+        )
+        .is_err());
+    }
+}
@@ -1,9 +1,47 @@
 pub mod add;
+pub mod capabilities;
+pub mod daemon;
+pub mod diff;
+pub mod doctor;
+pub mod embed;
+pub mod estimate;
 pub mod extract;
 pub mod info;
+pub mod kat;
+pub mod lock;
+pub mod ls;
+pub mod manifest;
+pub mod plugin;
+pub mod proof;
+pub mod rekey;
+pub mod replicate;
 pub mod seal;
+pub mod secret;
+pub mod sign;
+pub mod update;
+pub mod verify;
+pub mod wipe;
 
 pub use add::*;
+pub use capabilities::*;
+pub use daemon::*;
+pub use diff::*;
+pub use doctor::*;
+pub use embed::*;
+pub use estimate::*;
 pub use extract::*;
 pub use info::*;
+pub use kat::*;
+pub use lock::*;
+pub use ls::*;
+pub use manifest::*;
+pub use plugin::*;
+pub use proof::*;
+pub use rekey::*;
+pub use replicate::*;
 pub use seal::*;
+pub use secret::*;
+pub use sign::*;
+pub use update::*;
+pub use verify::*;
+pub use wipe::*;
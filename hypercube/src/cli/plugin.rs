@@ -0,0 +1,112 @@
+use crate::concurrency::Concurrency;
+use crate::error::{HypercubeError, Result};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Prefix of plugin executables: `hypercube foo` runs `hypercube-foo`
+pub const PLUGIN_PREFIX: &str = "hypercube-";
+
+/// Path of the running `hypercube` binary, for plugins that call back into it
+pub const BIN_ENV: &str = "HYPERCUBE";
+
+/// Secret helper given with the global `--secret-helper`
+pub const SECRET_HELPER_ENV: &str = "HYPERCUBE_SECRET_HELPER";
+
+/// Thread setting given with the global `--threads`
+pub const THREADS_ENV: &str = "HYPERCUBE_THREADS";
+
+/// File mode given with the global `--mode`, in octal
+pub const MODE_ENV: &str = "HYPERCUBE_MODE";
+
+/// Global flags handed to a plugin through its environment
+/// (`--config` goes in `HYPERCUBE_CONFIG`, which `Config::load_default` already reads)
+#[derive(Debug, Clone, Default)]
+pub struct PluginEnv {
+    pub config: Option<PathBuf>,
+    pub secret_helper: Option<String>,
+    pub threads: Option<Concurrency>,
+    pub mode: Option<u32>,
+}
+
+/// The `hypercube-<name>` executable in `search_path` (a `PATH`-style list), if any
+pub fn find_plugin(name: &str, search_path: &OsStr) -> Option<PathBuf> {
+    // A name with a separator would escape the search path
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return None;
+    }
+    let file = format!("{}{}{}", PLUGIN_PREFIX, name, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(search_path)
+        .map(|dir| dir.join(&file))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    path.is_file()
+}
+
+/// Run the plugin for an unknown subcommand, cargo-style
+/// `args` starts with the subcommand name; the rest is passed through untouched
+/// Returns the plugin's exit code
+pub fn run_plugin(args: &[OsString], env: &PluginEnv) -> Result<i32> {
+    let (name, rest) = args
+        .split_first()
+        .ok_or_else(|| HypercubeError::UnknownCommand(String::new()))?;
+    let name = name.to_string_lossy();
+    let search_path = std::env::var_os("PATH").unwrap_or_default();
+    let plugin = find_plugin(&name, &search_path)
+        .ok_or_else(|| HypercubeError::UnknownCommand(name.into_owned()))?;
+
+    let mut command = Command::new(plugin);
+    command.args(rest);
+    if let Ok(bin) = std::env::current_exe() {
+        command.env(BIN_ENV, bin);
+    }
+    if let Some(config) = &env.config {
+        command.env(crate::config::CONFIG_ENV, config);
+    }
+    if let Some(helper) = &env.secret_helper {
+        command.env(SECRET_HELPER_ENV, helper);
+    }
+    if let Some(threads) = env.threads {
+        command.env(THREADS_ENV, threads.to_string());
+    }
+    if let Some(mode) = env.mode {
+        command.env(MODE_ENV, format!("{:04o}", mode));
+    }
+    let status = command.status()?;
+    // A plugin killed by a signal has no exit code; report a plain failure
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_plugin() {
+        let first = tempdir().unwrap();
+        let second = tempdir().unwrap();
+        let plugin = second.path().join("hypercube-hello");
+        std::fs::write(&plugin, "#!/bin/sh\necho hello\n").unwrap();
+        std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        // Not executable, so skipped
+        let shadow = first.path().join("hypercube-hello");
+        std::fs::write(&shadow, "").unwrap();
+
+        let search = std::env::join_paths([first.path(), second.path()]).unwrap();
+        assert_eq!(find_plugin("hello", &search), Some(plugin));
+        assert_eq!(find_plugin("missing", &search), None);
+        assert_eq!(find_plugin("../hello", &search), None);
+        assert_eq!(find_plugin("", &search), None);
+    }
+}
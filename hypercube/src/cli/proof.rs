@@ -0,0 +1,200 @@
+use crate::error::{HypercubeError, Result};
+use crate::merkle::{leaf_hash, Hash, MerkleProof, MerkleTree};
+use crate::vhc::{read_vhc_blocks_at, read_vhc_file, read_vhc_trailer};
+use std::path::Path;
+
+/// Options for the proof command
+#[derive(Debug, Clone, Default)]
+pub struct ProofOptions {
+    /// Block to prove; None checks every block against the stored tree
+    pub block: Option<usize>,
+    /// Root to verify the proof against (e.g. a remote replica's), hex encoded
+    pub root: Option<String>,
+}
+
+/// The Merkle tree stored in a container's trailer
+pub fn stored_merkle_tree(path: &Path) -> Result<MerkleTree> {
+    read_vhc_trailer(path)?
+        .and_then(|trailer| trailer.merkle_tree())
+        .ok_or_else(|| {
+            HypercubeError::InvalidFormat(
+                "Container has no Merkle tree (create it with --merkle)".into(),
+            )
+        })
+}
+
+/// Audit path for one block, plus the block itself
+pub fn merkle_proof(path: &Path, block: usize) -> Result<(MerkleProof, Vec<u8>)> {
+    let tree = stored_merkle_tree(path)?;
+    let proof = tree.proof(block).ok_or_else(|| {
+        HypercubeError::InvalidFormat(format!(
+            "Block {} out of range ({} blocks)",
+            block,
+            tree.leaves().len()
+        ))
+    })?;
+    let data = read_vhc_blocks_at(path, &[block])?
+        .and_then(|mut blocks| blocks.pop())
+        .ok_or_else(|| HypercubeError::InvalidFormat("Block missing from container".into()))?;
+    Ok((proof, data))
+}
+
+/// Blocks whose contents no longer match their stored leaf hash
+pub fn corrupted_blocks(path: &Path) -> Result<Vec<usize>> {
+    let tree = stored_merkle_tree(path)?;
+    let vhc = read_vhc_file(path)?;
+    if vhc.blocks.len() != tree.leaves().len() {
+        return Err(HypercubeError::IntegrityError(format!(
+            "Merkle tree covers {} blocks, container has {}",
+            tree.leaves().len(),
+            vhc.blocks.len()
+        )));
+    }
+    Ok(vhc
+        .blocks
+        .iter()
+        .zip(tree.leaves())
+        .enumerate()
+        .filter(|(_, (block, leaf))| leaf_hash(block) != **leaf)
+        .map(|(index, _)| index)
+        .collect())
+}
+
+/// Show the Merkle root, or an audit path for one block
+pub fn show_proof(path: &Path, options: &ProofOptions) -> Result<String> {
+    let tree = stored_merkle_tree(path)?;
+    let root = match &options.root {
+        Some(root) => parse_hash(root)?,
+        None => tree.root(),
+    };
+
+    let mut output = String::new();
+    let Some(block) = options.block else {
+        let corrupted = corrupted_blocks(path)?;
+        output.push_str(&format!("Merkle root: {}\n", hex::encode(tree.root())));
+        output.push_str(&format!("Blocks: {}\n", tree.leaves().len()));
+        if corrupted.is_empty() {
+            output.push_str("Corrupted blocks: none\n");
+        } else {
+            let list: Vec<String> = corrupted.iter().map(|i| i.to_string()).collect();
+            output.push_str(&format!("Corrupted blocks: {}\n", list.join(", ")));
+        }
+        if options.root.is_some() {
+            output.push_str(&format!("Matches root: {}\n", yes_no(tree.root() == root)));
+        }
+        return Ok(output);
+    };
+
+    let (proof, data) = merkle_proof(path, block)?;
+    output.push_str(&format!(
+        "Merkle proof for block {} of {}\n",
+        proof.index, proof.leaf_count
+    ));
+    output.push_str(&format!("Root: {}\n", hex::encode(root)));
+    output.push_str(&format!("Leaf: {}\n", hex::encode(leaf_hash(&data))));
+    output.push_str(&format!("Path ({} hashes):\n", proof.siblings.len()));
+    for sibling in &proof.siblings {
+        output.push_str(&format!("  {}\n", hex::encode(sibling)));
+    }
+    output.push_str(&format!(
+        "Block matches stored leaf: {}\n",
+        yes_no(leaf_hash(&data) == tree.leaves()[block])
+    ));
+    output.push_str(&format!(
+        "Proof verifies: {}\n",
+        yes_no(proof.verify(&data, &root))
+    ));
+    Ok(output)
+}
+
+fn parse_hash(text: &str) -> Result<Hash> {
+    hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| HypercubeError::InvalidFormat(format!("Invalid Merkle root: {}", text)))
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::vhc::vhc_data_offset;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_proof_detects_corruption() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"replicated data").unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            merkle: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        let root = hex::encode(stored_merkle_tree(&vhc).unwrap().root());
+        assert!(corrupted_blocks(&vhc).unwrap().is_empty());
+
+        let (proof, data) = merkle_proof(&vhc, 3).unwrap();
+        assert!(proof.verify(&data, &stored_merkle_tree(&vhc).unwrap().root()));
+
+        // Flip a byte of block 3 in place
+        let block_size = crate::vhc::read_vhc_header(&vhc)
+            .unwrap()
+            .total_block_size();
+        let offset = vhc_data_offset(&vhc).unwrap() + 3 * block_size as u64;
+        let mut file = std::fs::OpenOptions::new().write(true).open(&vhc).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[!data[0]]).unwrap();
+        drop(file);
+
+        assert_eq!(corrupted_blocks(&vhc).unwrap(), vec![3]);
+        let report = show_proof(
+            &vhc,
+            &ProofOptions {
+                block: Some(3),
+                root: Some(root),
+            },
+        )
+        .unwrap();
+        assert!(report.contains("Block matches stored leaf: no"));
+        assert!(report.contains("Proof verifies: no"));
+    }
+
+    #[test]
+    fn test_proof_requires_merkle_tree() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"no tree").unwrap();
+
+        let options = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        assert!(show_proof(&vhc, &ProofOptions::default()).is_err());
+
+        // Enabling the tree on an existing container
+        let options = AddOptions {
+            secret: "other".into(),
+            merkle: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        let report = show_proof(&vhc, &ProofOptions::default()).unwrap();
+        assert!(report.contains("Corrupted blocks: none"));
+        assert!(merkle_proof(&vhc, 10_000).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::error::Result;
+use crate::hint::{BloomHint, HintKey};
+use crate::index::{forget_partition, record_partition};
+use crate::vhc::{read_vhc_file, replace_vhc_file};
+use rand::{seq::SliceRandom, thread_rng};
+use std::path::Path;
+
+/// Options for the rekey command
+#[derive(Debug, Clone, Default)]
+pub struct RekeyOptions {
+    /// Secret that opens the partition now
+    pub secret: String,
+    /// Secret that opens it afterwards
+    pub new_secret: String,
+}
+
+/// Re-seal a partition under a new secret without re-adding its data
+/// The partition keeps its block slots, its bloom hint and its index entry, all moved to
+/// the new secret; the container is written beside the old one and renamed into place
+/// Returns the number of blocks rewritten
+pub fn rekey_partition(path: &Path, options: &RekeyOptions) -> Result<usize> {
+    let mut vhc = read_vhc_file(path)?;
+    vhc.header.check_unlocked()?;
+    vhc.header.check_mutable("re-key a partition")?;
+    let old_key = vhc.header.partition_key(options.secret.as_bytes())?;
+    let new_key = vhc.header.partition_key(options.new_secret.as_bytes())?;
+
+    let (partition, blocks) =
+        crate::partition::rekey_partition(&vhc.blocks, &old_key, &new_key, &vhc.header)?;
+    let mut positions = partition.clone();
+    positions.shuffle(&mut thread_rng());
+
+    // A hint over the old blocks is rebuilt over the new ones
+    let domains = vhc.header.domains()?;
+    let hint_key = HintKey::new(&old_key, domains);
+    let hinted = vhc.header.hints.iter().position(|hint| {
+        partition
+            .iter()
+            .all(|&position| hint.may_contain(&hint_key, &vhc.blocks[position]))
+    });
+
+    let rewritten = blocks.len();
+    for (position, block) in positions.into_iter().zip(blocks) {
+        vhc.blocks[position] = block;
+    }
+    if let Some(index) = hinted {
+        let blocks: Vec<Vec<u8>> = partition.iter().map(|&p| vhc.blocks[p].clone()).collect();
+        vhc.header.hints[index] = BloomHint::build(&new_key, &blocks, &vhc.header)?;
+    }
+    vhc.header.bump_generation();
+    replace_vhc_file(path, &vhc)?;
+
+    if forget_partition(path, &old_key)? {
+        record_partition(path, &new_key, &partition)?;
+    }
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use crate::error::HypercubeError;
+    use crate::index::lookup_partition;
+    use crate::partition::find_partition_blocks;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+    use tempfile::tempdir;
+
+    fn extract(vhc: &Path, secret: &str) -> Result<Vec<u8>> {
+        let output = vhc.with_extension("out");
+        let options = ExtractOptions {
+            secret: secret.into(),
+            ..Default::default()
+        };
+        extract_from_vhc(vhc, &output, &options)?;
+        Ok(std::fs::read(&output)?)
+    }
+
+    fn rekey(vhc: &Path, secret: &str, new_secret: &str) -> Result<usize> {
+        let options = RekeyOptions {
+            secret: secret.into(),
+            new_secret: new_secret.into(),
+        };
+        rekey_partition(vhc, &options)
+    }
+
+    #[test]
+    fn test_rekey_partition() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let other = dir.path().join("other.txt");
+        let vhc = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"moving to a new key").unwrap();
+        std::fs::write(&other, b"neighbour").unwrap();
+
+        let options = AddOptions {
+            secret: "old".into(),
+            hint: true,
+            index: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        let neighbour = AddOptions {
+            secret: "other".into(),
+            ..Default::default()
+        };
+        add_partition(&other, &vhc, &neighbour).unwrap();
+        let before = read_vhc_file(&vhc).unwrap();
+        let old_key = before.header.partition_key(b"old").unwrap();
+        let positions = find_partition_blocks(&before.blocks, &old_key, &before.header);
+
+        let rewritten = rekey(&vhc, "old", "new").unwrap();
+        assert_eq!(rewritten, positions.len());
+        assert_eq!(extract(&vhc, "new").unwrap(), b"moving to a new key");
+        assert!(extract(&vhc, "old").is_err());
+        assert_eq!(extract(&vhc, "other").unwrap(), b"neighbour");
+
+        // Same slots, same hint slot, index entry moved over
+        let after = read_vhc_file(&vhc).unwrap();
+        let new_key = after.header.partition_key(b"new").unwrap();
+        assert_eq!(
+            find_partition_blocks(&after.blocks, &new_key, &after.header),
+            positions
+        );
+        assert_eq!(after.header.hints.len(), before.header.hints.len());
+        assert!(after.header.generation > before.header.generation);
+        assert!(lookup_partition(&vhc, &old_key).unwrap().is_none());
+        assert_eq!(lookup_partition(&vhc, &new_key).unwrap(), Some(positions));
+        // No temp file is left beside it
+        let mut names = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name());
+        assert!(names.all(|name| !name.to_string_lossy().starts_with(".tmp")));
+
+        // Unknown secret, or a new secret that already opens a partition
+        assert!(matches!(
+            rekey(&vhc, "old", "newer"),
+            Err(HypercubeError::NoAuthenticatedBlocks)
+        ));
+        assert!(matches!(
+            rekey(&vhc, "new", "other"),
+            Err(HypercubeError::InvalidFormat(_))
+        ));
+        assert_eq!(extract(&vhc, "new").unwrap(), b"moving to a new key");
+    }
+
+    #[test]
+    fn test_rekey_chunked_partition() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.bin");
+        let vhc = dir.path().join("vault.vhc");
+        let mut data = vec![0u8; 20_000];
+        StdRng::seed_from_u64(7).fill_bytes(&mut data);
+        std::fs::write(&input, &data).unwrap();
+
+        let options = AddOptions {
+            secret: "old".into(),
+            chunked: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        assert!(read_vhc_file(&vhc).unwrap().header.chunked);
+
+        rekey(&vhc, "old", "new").unwrap();
+        assert_eq!(extract(&vhc, "new").unwrap(), data);
+        assert!(extract(&vhc, "old").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::error::Result;
+use crate::file_mode::create_file;
+use crate::header::VhcHeader;
+use crate::merkle::{leaf_hash, Hash};
+use crate::vhc::{
+    iter_vhc_blocks, platform_size, read_vhc_header, read_vhc_trailer, replace_file,
+    vhc_block_region,
+};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Options for the replicate command
+#[derive(Debug, Clone, Default)]
+pub struct ReplicateOptions {
+    /// Hash every block on both sides instead of trusting their Merkle trailers
+    pub rehash: bool,
+    /// Work out what would be copied without writing anything
+    pub dry_run: bool,
+}
+
+/// What a replication copied (or would copy, on a dry run)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplicateReport {
+    /// Blocks in the source
+    pub blocks: usize,
+    /// Blocks written to the destination
+    pub copied: usize,
+    /// The header or trailer differed and was rewritten
+    pub metadata: bool,
+    /// The destination was missing or laid out differently, so the whole file was copied
+    pub full_copy: bool,
+}
+
+/// Bring `destination` up to date with `source`, writing only what differs
+///
+/// Blocks are compared by their Merkle leaf hashes, taken from the trailer when a side
+/// has one and hashed from the blocks otherwise; neither file is loaded whole. Changed
+/// blocks are written in place and synced before the trailer and header that describe
+/// them, then the file is synced again. A missing destination, or one whose header
+/// length or block size differs, is copied whole through a temp file. An interrupted
+/// in-place run leaves a mixed copy that the next run repairs: its trailer still holds
+/// the old leaves, so the blocks not yet written still differ.
+pub fn replicate_container(
+    source: &Path,
+    destination: &Path,
+    options: &ReplicateOptions,
+) -> Result<ReplicateReport> {
+    let src = Extent::read(source)?;
+    let blocks = src.blocks;
+    let full = ReplicateReport {
+        blocks,
+        copied: blocks,
+        metadata: true,
+        full_copy: true,
+    };
+    if !destination.exists() {
+        return copy_whole(source, destination, options).map(|()| full);
+    }
+
+    // Refuses to overwrite anything that is not a container
+    let dst = Extent::read(destination)?;
+    let size = src.header.total_block_size() as u64;
+    let data_start = src.data_start;
+    if dst.data_start != data_start || dst.header.total_block_size() as u64 != size {
+        return copy_whole(source, destination, options).map(|()| full);
+    }
+
+    let src_leaves = block_leaves(source, blocks, options.rehash)?;
+    let dst_leaves = block_leaves(destination, dst.blocks, options.rehash)?;
+    let changed: Vec<usize> = (0..blocks)
+        .filter(|&i| dst_leaves.get(i) != Some(&src_leaves[i]))
+        .collect();
+    let mut src_file = File::open(source)?;
+    let mut dst_file = File::open(destination)?;
+    let data_end = src.data_end();
+    let header = read_range(&mut src_file, 0, data_start)?;
+    let trailer = read_range(&mut src_file, data_end, src.len)?;
+    let header_changed = header != read_range(&mut dst_file, 0, data_start)?;
+    let metadata =
+        header_changed || trailer != read_range(&mut dst_file, dst.data_end(), dst.len)?;
+    let report = ReplicateReport {
+        blocks,
+        copied: changed.len(),
+        metadata,
+        full_copy: false,
+    };
+    drop(dst_file);
+    if options.dry_run || (changed.is_empty() && !metadata) {
+        return Ok(report);
+    }
+
+    let mut file = OpenOptions::new().write(true).open(destination)?;
+    let mut block = vec![0u8; platform_size(size)?];
+    for &index in &changed {
+        let offset = data_start + index as u64 * size;
+        src_file.seek(SeekFrom::Start(offset))?;
+        src_file.read_exact(&mut block)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&block)?;
+    }
+    // The new trailer's leaves vouch for these blocks, so they must reach the disk first
+    file.sync_data()?;
+    file.seek(SeekFrom::Start(data_end))?;
+    file.write_all(&trailer)?;
+    file.set_len(src.len)?;
+    if header_changed {
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+    }
+    file.sync_all()?;
+    Ok(report)
+}
+
+/// Where a container's parts lie, found without reading its blocks
+struct Extent {
+    header: VhcHeader,
+    data_start: u64,
+    blocks: usize,
+    len: u64,
+}
+
+impl Extent {
+    fn read(path: &Path) -> Result<Self> {
+        let header = read_vhc_header(path)?;
+        let (data_start, data_size) = vhc_block_region(path)?;
+        let blocks = platform_size(data_size / header.total_block_size() as u64)?;
+        let len = std::fs::metadata(path)?.len();
+        Ok(Self {
+            header,
+            data_start,
+            blocks,
+            len,
+        })
+    }
+
+    /// Offset just past the last block: where the trailer starts
+    fn data_end(&self) -> u64 {
+        self.data_start + self.blocks as u64 * self.header.total_block_size() as u64
+    }
+}
+
+fn read_range(file: &mut File, start: u64, end: u64) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; platform_size(end - start)?];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Leaf hash of every block, from the Merkle trailer unless `rehash` is set
+fn block_leaves(path: &Path, blocks: usize, rehash: bool) -> Result<Vec<Hash>> {
+    if !rehash {
+        let leaves = read_vhc_trailer(path)?.and_then(|trailer| trailer.merkle_leaves);
+        if let Some(leaves) = leaves.filter(|leaves| leaves.len() == blocks) {
+            return Ok(leaves);
+        }
+    }
+    iter_vhc_blocks(path)?
+        .map(|block| block.map(|block| leaf_hash(&block)))
+        .collect()
+}
+
+fn copy_whole(source: &Path, destination: &Path, options: &ReplicateOptions) -> Result<()> {
+    if options.dry_run {
+        return Ok(());
+    }
+    replace_file(destination, |temp| {
+        std::io::copy(&mut File::open(source)?, &mut create_file(temp)?)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::update::{update_partition, UpdateOptions};
+    use crate::error::HypercubeError;
+    use tempfile::tempdir;
+
+    fn read(path: &Path) -> Vec<u8> {
+        std::fs::read(path).unwrap()
+    }
+
+    fn replicate(source: &Path, destination: &Path, rehash: bool) -> ReplicateReport {
+        let options = ReplicateOptions {
+            rehash,
+            ..Default::default()
+        };
+        replicate_container(source, destination, &options).unwrap()
+    }
+
+    #[test]
+    fn test_replicate_container() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let source = dir.path().join("vault.vhc");
+        let mirror = dir.path().join("mirror.vhc");
+        std::fs::write(&input, b"first version").unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            merkle: true,
+            ..Default::default()
+        };
+        add_partition(&input, &source, &options).unwrap();
+
+        // First run copies everything, a second one nothing
+        assert!(replicate(&source, &mirror, false).full_copy);
+        assert_eq!(read(&mirror), read(&source));
+        let report = replicate(&source, &mirror, false);
+        assert_eq!((report.copied, report.metadata), (0, false));
+
+        // An update moves only the partition's blocks, plus the header and trailer
+        std::fs::write(&input, b"second version").unwrap();
+        let update = UpdateOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        let rewritten = update_partition(&input, &source, &update).unwrap();
+        let dry_run = ReplicateOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let before = read(&mirror);
+        let planned = replicate_container(&source, &mirror, &dry_run).unwrap();
+        assert_eq!(read(&mirror), before);
+        let report = replicate(&source, &mirror, false);
+        assert_eq!(report, planned);
+        assert_eq!(report.copied, rewritten);
+        assert!(report.metadata && !report.full_copy);
+        assert_eq!(read(&mirror), read(&source));
+
+        // A block damaged behind the trailer's back is only seen when rehashing
+        let mut damaged = read(&mirror);
+        let (first, _) = vhc_block_region(&mirror).unwrap();
+        damaged[first as usize + 20] ^= 1;
+        std::fs::write(&mirror, &damaged).unwrap();
+        assert_eq!(replicate(&source, &mirror, false).copied, 0);
+        assert_eq!(replicate(&source, &mirror, true).copied, 1);
+        assert_eq!(read(&mirror), read(&source));
+    }
+
+    #[test]
+    fn test_replicate_refuses_non_container() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let source = dir.path().join("vault.vhc");
+        let other = dir.path().join("notes.txt");
+        std::fs::write(&input, b"data").unwrap();
+        std::fs::write(&other, b"not a container").unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        add_partition(&input, &source, &options).unwrap();
+
+        let result = replicate_container(&source, &other, &ReplicateOptions::default());
+        assert!(matches!(result, Err(HypercubeError::InvalidFormat(_))));
+        assert_eq!(std::fs::read(&other).unwrap(), b"not a container");
+    }
+}
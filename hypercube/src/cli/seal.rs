@@ -1,6 +1,12 @@
-use crate::partition::create_partition;
+use crate::partition::{
+    chunked_chaff_partition, create_partition, generate_chaff, generate_chaff_blocks,
+};
 use crate::error::{HypercubeError, Result};
-use crate::vhc::{append_blocks_to_vhc, get_block_count, read_vhc_header};
+use crate::header::VhcHeader;
+use crate::progress::{Progress, ProgressSink};
+use crate::vhc::{
+    append_and_reshuffle_vhc, get_block_count, platform_size, read_vhc_header, vhc_block_region,
+};
 use rand::rngs::OsRng;
 use rand::RngCore;
 use std::cmp;
@@ -10,7 +16,14 @@ use std::time::Instant;
 /// Fill the remaining cube capacity with random chaff blocks
 /// Returns the number of blocks added
 pub fn seal_file(path: &Path) -> Result<usize> {
+    seal_file_with_progress(path, &Progress::default())
+}
+
+/// `seal_file`, reporting the chaff blocks generated to `progress`
+/// The per-round summary on stdout is left out while a sink is attached
+pub fn seal_file_with_progress(path: &Path, progress: &Progress) -> Result<usize> {
     let header = read_vhc_header(path)?;
+    header.check_unlocked()?;
     let current_blocks = get_block_count(path)?;
     let capacity = header.theoretical_block_count();
 
@@ -27,44 +40,110 @@ pub fn seal_file(path: &Path) -> Result<usize> {
     let mut remaining = capacity - current_blocks;
     let total = remaining;
     let mut new_blocks: Vec<Vec<u8>> = Vec::with_capacity(remaining);
-    let mut rng = OsRng;
 
     while remaining > 0 {
         let iter_start = Instant::now();
-        let data_blocks = header.data_blocks_per_partition();
-        // Generate less data to ensure it fits after metadata overhead
-        let max_payload = header.block_size * data_blocks;
-        let data_size = max_payload.saturating_sub(crate::header::PartitionMeta::SIZE + 64);
-        let chunk_bytes = cmp::max(1, data_size);
-        let mut random_data = vec![0u8; chunk_bytes];
-        rng.fill_bytes(&mut random_data);
-
-        let mut secret = vec![0u8; 32];
-        rng.fill_bytes(&mut secret);
-
-        let partition = create_partition(&random_data, &secret, &header, Some(data_blocks))?;
-        let produced = partition.blocks.len();
-        if produced == 0 {
-            continue;
-        }
-
-        let take = remaining.min(produced);
-        new_blocks.extend(partition.blocks.into_iter().take(take));
+        let take = remaining.min(header.blocks_per_partition());
+        new_blocks.extend(generate_chaff_blocks(&header, take)?);
         remaining -= take;
         let processed = total - remaining;
         let elapsed = iter_start.elapsed();
         let per_block = elapsed / (take as u32);
-        println!(
-            "Sealing: added {} blocks ({}/{}); avg {:?} per block",
-            take, processed, total, per_block
-        );
+        if progress.is_enabled() {
+            progress.blocks_written(processed as u64, total as u64);
+        } else {
+            println!(
+                "Sealing: added {} blocks ({}/{}); avg {:?} per block",
+                take, processed, total, per_block
+            );
+        }
     }
 
     let added = new_blocks.len();
-    append_blocks_to_vhc(path, &new_blocks)?;
+    append_and_reshuffle_vhc(path, &new_blocks)?;
     Ok(added)
 }
 
+/// Outcome of `check_sealed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SealCheck {
+    pub blocks: usize,
+    pub capacity: usize,
+    /// On-disk size of every block (sequence + data + MAC)
+    pub block_size: usize,
+    /// Bytes in the block region that do not make up a whole block
+    pub stray_bytes: usize,
+}
+
+impl SealCheck {
+    /// A full cube of equally sized blocks: its size says nothing about how much data it holds
+    pub fn is_sealed(&self) -> bool {
+        self.blocks == self.capacity && self.stray_bytes == 0
+    }
+}
+
+impl std::fmt::Display for SealCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} / {} blocks of {} bytes",
+            self.blocks, self.capacity, self.block_size
+        )?;
+        if self.stray_bytes > 0 {
+            write!(f, ", {} stray bytes after the last block", self.stray_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that a container holds exactly its theoretical block count in whole blocks
+pub fn check_sealed(path: &Path) -> Result<SealCheck> {
+    let header = read_vhc_header(path)?;
+    let (_, region) = vhc_block_region(path)?;
+    let block_size = header.total_block_size();
+    Ok(SealCheck {
+        blocks: platform_size(region / block_size as u64)?,
+        capacity: header.theoretical_block_count(),
+        block_size,
+        stray_bytes: (region % block_size as u64) as usize,
+    })
+}
+
+/// Blocks of one partition of random data under a random, discarded secret
+/// Indistinguishable from a real partition without the secret
+pub fn chaff_partition(header: &VhcHeader) -> Result<Vec<Vec<u8>>> {
+    let mut secret = vec![0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    keyed_chaff_partition(header, &secret)
+}
+
+/// Chaff partition under a known key, so its holder can find and replace it later
+pub fn keyed_chaff_partition(header: &VhcHeader, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let data_blocks = header.data_blocks_per_partition();
+    // Generate less data to ensure it fits after metadata overhead
+    let max_payload = header.block_size * data_blocks;
+    let data_size = max_payload.saturating_sub(header.meta_size() + 64);
+    let chunk_bytes = cmp::max(1, data_size);
+    if header.chunked {
+        // Chunked like payload, so its sequence runs take the same shapes
+        return chunked_chaff_partition(header, key);
+    }
+    let random_data = generate_chaff(chunk_bytes);
+
+    Ok(create_partition(&random_data, key, header, Some(data_blocks))?.blocks)
+}
+
+/// Exactly `count` chaff blocks under `key`, the last partition cut short if needed
+pub fn keyed_chaff_blocks(header: &VhcHeader, key: &[u8], count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut blocks = Vec::with_capacity(count);
+    while blocks.len() < count {
+        let partition = keyed_chaff_partition(header, key)?;
+        let take = partition.len().min(count - blocks.len());
+        blocks.extend(partition.into_iter().take(take));
+    }
+    Ok(blocks)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,4 +174,56 @@ mod tests {
         let second = seal_file(&vhc).unwrap();
         assert_eq!(second, 0);
     }
+
+    #[test]
+    fn test_seal_chunked_file() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"seed data").unwrap();
+
+        let opts = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            chunked: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &opts).unwrap();
+        assert_eq!(seal_file(&vhc).unwrap(), 56);
+        assert!(check_sealed(&vhc).unwrap().is_sealed());
+    }
+
+    #[test]
+    fn test_check_sealed() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"seed data").unwrap();
+
+        let opts = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &opts).unwrap();
+        let check = check_sealed(&vhc).unwrap();
+        assert_eq!((check.blocks, check.capacity), (8, 64));
+        assert!(!check.is_sealed());
+
+        seal_file(&vhc).unwrap();
+        let check = check_sealed(&vhc).unwrap();
+        assert!(check.is_sealed(), "{}", check);
+
+        // A v1 file ends with its blocks, so stray bytes land in the block region
+        let mut sealed = crate::vhc::read_vhc_file(&vhc).unwrap();
+        sealed.header.version = 1;
+        crate::vhc::write_vhc_file(&vhc, &sealed).unwrap();
+        let mut bytes = std::fs::read(&vhc).unwrap();
+        bytes.extend_from_slice(&[0u8; 3]);
+        std::fs::write(&vhc, bytes).unwrap();
+        let check = check_sealed(&vhc).unwrap();
+        assert_eq!(check.stray_bytes, 3);
+        assert!(!check.is_sealed());
+        assert!(check.to_string().contains("3 stray bytes"));
+    }
 }
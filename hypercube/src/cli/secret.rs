@@ -0,0 +1,151 @@
+use crate::domains::{KEYFILE, KMS};
+use crate::error::{HypercubeError, Result};
+use crate::kms::KmsKey;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+/// Where a partition secret comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// A passphrase, used as given
+    Passphrase(String),
+    /// The contents of a key file, which may be binary
+    Keyfile(PathBuf),
+    /// A passphrase and a key file, both needed
+    Combined {
+        passphrase: String,
+        keyfile: PathBuf,
+    },
+    /// Key material from a KMS key, with a passphrase if one is given
+    Kms {
+        key: KmsKey,
+        passphrase: Option<String>,
+    },
+}
+
+impl SecretSource {
+    /// The source for an optional passphrase and key file, None if neither is given
+    pub fn new(passphrase: Option<String>, keyfile: Option<PathBuf>) -> Option<Self> {
+        match (passphrase, keyfile) {
+            (Some(passphrase), Some(keyfile)) => Some(Self::Combined {
+                passphrase,
+                keyfile,
+            }),
+            (Some(passphrase), None) => Some(Self::Passphrase(passphrase)),
+            (None, Some(keyfile)) => Some(Self::Keyfile(keyfile)),
+            (None, None) => None,
+        }
+    }
+
+    /// The source for a KMS key named by `key_id`, combined with a passphrase if given
+    pub fn kms(key_id: &str, passphrase: Option<String>) -> Result<Self> {
+        Ok(Self::Kms {
+            key: KmsKey::parse(key_id)?,
+            passphrase,
+        })
+    }
+
+    /// The secret to put in `AddOptions::secret`, `ExtractOptions::secret` and the like
+    ///
+    /// A passphrase is returned unchanged, so it opens the same partitions as before. A
+    /// key file is hashed with BLAKE3, together with the passphrase if there is one,
+    /// into a 64-digit hex secret; the key material itself never reaches the options.
+    /// KMS key material is hashed the same way under a context of its own.
+    pub fn resolve(&self) -> Result<String> {
+        let (passphrase, keyfile) = match self {
+            Self::Passphrase(passphrase) => return Ok(passphrase.clone()),
+            Self::Keyfile(keyfile) => (None, keyfile),
+            Self::Combined {
+                passphrase,
+                keyfile,
+            } => (Some(passphrase), keyfile),
+            Self::Kms { key, passphrase } => {
+                let material = key.key_material()?;
+                return Ok(derive_secret(KMS, passphrase.as_deref(), &material));
+            }
+        };
+        let contents = Zeroizing::new(std::fs::read(keyfile)?);
+        if contents.is_empty() {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "Key file {} is empty",
+                keyfile.display()
+            )));
+        }
+        Ok(derive_secret(KEYFILE, passphrase.map(String::as_str), &contents))
+    }
+}
+
+/// Hex secret from key material and an optional passphrase, under a BLAKE3 context
+fn derive_secret(context: &str, passphrase: Option<&str>, material: &[u8]) -> String {
+    let mut hasher = blake3::Hasher::new_derive_key(context);
+    match passphrase {
+        Some(passphrase) => {
+            hasher.update(&[1]);
+            hasher.update(&(passphrase.len() as u64).to_le_bytes());
+            hasher.update(passphrase.as_bytes());
+        }
+        None => {
+            hasher.update(&[0]);
+        }
+    }
+    hasher.update(material);
+    hasher.finalize().to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_secret_sources() {
+        let dir = tempdir().unwrap();
+        let keyfile = dir.path().join("key.bin");
+        std::fs::write(&keyfile, [0u8, 0xff, 0x10, 0x80]).unwrap();
+
+        assert_eq!(SecretSource::new(None, None), None);
+        let passphrase = SecretSource::new(Some("pass".into()), None).unwrap();
+        assert_eq!(passphrase.resolve().unwrap(), "pass");
+
+        let file_only = SecretSource::new(None, Some(keyfile.clone())).unwrap();
+        let combined = SecretSource::new(Some("pass".into()), Some(keyfile.clone())).unwrap();
+        let empty_pass = SecretSource::new(Some(String::new()), Some(keyfile.clone())).unwrap();
+        let secrets = [
+            file_only.resolve().unwrap(),
+            combined.resolve().unwrap(),
+            empty_pass.resolve().unwrap(),
+        ];
+        assert_eq!(secrets[0].len(), 64);
+        assert_eq!(secrets[0], file_only.resolve().unwrap());
+        assert_ne!(secrets[0], secrets[1]);
+        assert_ne!(secrets[0], secrets[2]);
+        assert_ne!(secrets[1], secrets[2]);
+
+        // A different key file, a missing one and an empty one
+        std::fs::write(&keyfile, [0u8, 0xff, 0x10, 0x81]).unwrap();
+        assert_ne!(file_only.resolve().unwrap(), secrets[0]);
+        std::fs::write(&keyfile, []).unwrap();
+        assert!(file_only.resolve().is_err());
+        std::fs::remove_file(&keyfile).unwrap();
+        assert!(matches!(file_only.resolve(), Err(HypercubeError::Io(_))));
+    }
+
+    #[test]
+    fn test_kms_secret_source() {
+        let arn = "arn:aws:kms:eu-west-1:123456789012:key/k";
+        let source = SecretSource::kms(arn, Some("pin".into())).unwrap();
+        assert!(matches!(
+            source,
+            SecretSource::Kms {
+                passphrase: Some(_),
+                ..
+            }
+        ));
+        assert!(SecretSource::kms("not-a-key", None).is_err());
+
+        // The same material is a different secret from a key file, with or without a pin
+        let kms = derive_secret(KMS, None, b"material");
+        assert_ne!(kms, derive_secret(KEYFILE, None, b"material"));
+        assert_ne!(kms, derive_secret(KMS, Some("pin"), b"material"));
+    }
+}
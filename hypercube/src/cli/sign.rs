@@ -0,0 +1,203 @@
+use crate::error::{HypercubeError, Result};
+use crate::signature::{
+    read_signature, read_signing_key, read_verifying_key, write_signature, ContainerSignature,
+};
+use crate::vhc::{read_vhc_file, read_vhc_trailer, replace_vhc_file, signed_region_digest};
+use std::path::{Path, PathBuf};
+
+/// Options for the sign command
+#[derive(Debug, Clone, Default)]
+pub struct SignOptions {
+    /// Store the signature in the container trailer instead of a detached file
+    pub embed: bool,
+    /// Detached signature path (defaults to `<file>.sig`)
+    pub signature: Option<PathBuf>,
+}
+
+/// Options for the verify-signature command
+#[derive(Debug, Clone, Default)]
+pub struct VerifySignatureOptions {
+    /// Detached signature to check; defaults to the embedded one, then `<file>.sig`
+    pub signature: Option<PathBuf>,
+}
+
+/// Where a detached signature for `path` lives by default
+pub fn default_signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Sign a container's layout, header and block region with an Ed25519 key
+/// Any later write to the container invalidates (and drops) an embedded signature
+pub fn sign_container(
+    path: &Path,
+    signing_key_path: &Path,
+    options: &SignOptions,
+) -> Result<ContainerSignature> {
+    let key = read_signing_key(signing_key_path)?;
+
+    if !options.embed {
+        let signature = ContainerSignature::sign(&key, &signed_region_digest(path)?);
+        let signature_path = options
+            .signature
+            .clone()
+            .unwrap_or_else(|| default_signature_path(path));
+        write_signature(&signature_path, &signature)?;
+        return Ok(signature);
+    }
+
+    // Adding a trailer can change the header version, so sign the file as it will be
+    // laid out: write with a placeholder, digest, then write the real signature
+    let mut vhc = read_vhc_file(path)?;
+    vhc.signature = Some(ContainerSignature {
+        public_key: [0; 32],
+        signature: [0; 64],
+    });
+    replace_vhc_file(path, &vhc)?;
+    let signature = ContainerSignature::sign(&key, &signed_region_digest(path)?);
+    vhc.signature = Some(signature.clone());
+    replace_vhc_file(path, &vhc)?;
+    Ok(signature)
+}
+
+/// Check a container's signature against a trusted public key
+pub fn verify_container_signature(
+    path: &Path,
+    public_key_path: &Path,
+    options: &VerifySignatureOptions,
+) -> Result<ContainerSignature> {
+    let public_key = read_verifying_key(public_key_path)?;
+    let embedded = read_vhc_trailer(path)?.and_then(|trailer| trailer.signature);
+
+    let signature = match (&options.signature, embedded) {
+        (Some(detached), _) => read_signature(detached)?,
+        (None, Some(embedded)) => embedded,
+        (None, None) => {
+            let detached = default_signature_path(path);
+            if !detached.exists() {
+                return Err(HypercubeError::IntegrityError(format!(
+                    "No embedded signature and no {}",
+                    detached.display()
+                )));
+            }
+            read_signature(&detached)?
+        }
+    };
+
+    signature.verify(&signed_region_digest(path)?, &public_key)?;
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use crate::signature::{generate_signing_key, public_key_path};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sign_detached_and_embedded() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        let output = dir.path().join("output.txt");
+        let key = dir.path().join("signer");
+        std::fs::write(&input, b"signed payload").unwrap();
+        let public = generate_signing_key(&key).unwrap();
+        assert_eq!(public, public_key_path(&key));
+
+        let add = AddOptions {
+            secret: "secret".into(),
+            merkle: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &add).unwrap();
+
+        // Detached
+        sign_container(&vhc, &key, &SignOptions::default()).unwrap();
+        assert!(default_signature_path(&vhc).exists());
+        verify_container_signature(&vhc, &public, &VerifySignatureOptions::default()).unwrap();
+
+        // Embedded; the Merkle section survives and partitions still extract
+        let embed = SignOptions {
+            embed: true,
+            ..Default::default()
+        };
+        std::fs::remove_file(default_signature_path(&vhc)).unwrap();
+        sign_container(&vhc, &key, &embed).unwrap();
+        verify_container_signature(&vhc, &public, &VerifySignatureOptions::default()).unwrap();
+        assert!(read_vhc_trailer(&vhc)
+            .unwrap()
+            .unwrap()
+            .merkle_leaves
+            .is_some());
+        let extract = ExtractOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc, &output, &extract).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"signed payload");
+
+        // Another key does not verify
+        let other = dir.path().join("other");
+        let other_public = generate_signing_key(&other).unwrap();
+        assert!(verify_container_signature(
+            &vhc,
+            &other_public,
+            &VerifySignatureOptions::default()
+        )
+        .is_err());
+
+        // Adding a partition drops the embedded signature
+        add_partition(
+            &input,
+            &vhc,
+            &AddOptions {
+                secret: "second".into(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(
+            verify_container_signature(&vhc, &public, &VerifySignatureOptions::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_detached_signature_detects_tampering() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        let key = dir.path().join("signer");
+        std::fs::write(&input, b"payload").unwrap();
+        let public = generate_signing_key(&key).unwrap();
+
+        let add = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &add).unwrap();
+        let options = SignOptions {
+            signature: Some(dir.path().join("custom.sig")),
+            ..Default::default()
+        };
+        sign_container(&vhc, &key, &options).unwrap();
+
+        let verify = VerifySignatureOptions {
+            signature: options.signature.clone(),
+        };
+        verify_container_signature(&vhc, &public, &verify).unwrap();
+        // Not at the default location
+        assert!(
+            verify_container_signature(&vhc, &public, &VerifySignatureOptions::default()).is_err()
+        );
+
+        let mut data = std::fs::read(&vhc).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        std::fs::write(&vhc, data).unwrap();
+        assert!(verify_container_signature(&vhc, &public, &verify).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::chunk::{dedup_key, update_chunked_partition};
+use crate::error::{HypercubeError, Result};
+use crate::hint::{BloomHint, HintKey};
+use crate::index::{lookup_partition, record_partition};
+use crate::metrics::{self, BYTES_ADDED};
+use crate::partition::{create_partition, find_partition_blocks};
+use crate::vhc::{read_vhc_file, replace_vhc_file};
+use rand::{seq::SliceRandom, thread_rng};
+use std::path::Path;
+
+/// Options for the update command
+#[derive(Debug, Clone, Default)]
+pub struct UpdateOptions {
+    pub secret: String,
+    /// Dedup secret of a deduplicating container
+    pub dedup_secret: Option<String>,
+}
+
+/// Replace a partition's data in place, keeping its block positions
+/// A chunked container only rewrites the chunks that changed (plus the manifest);
+/// any other container rewrites the whole partition
+/// Returns the number of blocks rewritten
+pub fn update_partition(input_path: &Path, path: &Path, options: &UpdateOptions) -> Result<usize> {
+    let data = std::fs::read(input_path)?;
+    let mut vhc = read_vhc_file(path)?;
+    vhc.header.check_unlocked()?;
+    vhc.header.check_mutable("update a partition")?;
+    let key = vhc.header.partition_key(options.secret.as_bytes())?;
+    let secret = key.as_slice();
+    let partition = find_partition_blocks(&vhc.blocks, secret, &vhc.header);
+    if partition.is_empty() {
+        return Err(HypercubeError::NoAuthenticatedBlocks);
+    }
+
+    let (mut positions, blocks) = if vhc.header.chunked {
+        let dedup = vhc
+            .header
+            .dedup
+            .then(|| dedup_key(&vhc.header, options.dedup_secret.as_deref()))
+            .transpose()?;
+        update_chunked_partition(&vhc.blocks, &data, secret, dedup.as_ref(), &vhc.header)?
+    } else {
+        let pad = vhc.header.data_blocks_per_partition();
+        let blocks = create_partition(&data, secret, &vhc.header, Some(pad))?.blocks;
+        if blocks.len() != partition.len() {
+            return Err(HypercubeError::IntegrityError(format!(
+                "Partition holds {} blocks, expected {}",
+                partition.len(),
+                blocks.len()
+            )));
+        }
+        (partition.clone(), blocks)
+    };
+    // New blocks land in the freed slots in no particular order
+    positions.shuffle(&mut thread_rng());
+
+    // A hint over the old blocks is rebuilt over the new ones
+    let domains = vhc.header.domains()?;
+    let hint_key = HintKey::new(secret, domains);
+    let hinted = vhc.header.hints.iter().position(|hint| {
+        partition
+            .iter()
+            .all(|&position| hint.may_contain(&hint_key, &vhc.blocks[position]))
+    });
+
+    let rewritten = blocks.len();
+    for (position, block) in positions.into_iter().zip(blocks) {
+        vhc.blocks[position] = block;
+    }
+    if let Some(index) = hinted {
+        let blocks: Vec<Vec<u8>> = partition.iter().map(|&p| vhc.blocks[p].clone()).collect();
+        vhc.header.hints[index] = BloomHint::build(secret, &blocks, &vhc.header)?;
+    }
+    vhc.header.bump_generation();
+    replace_vhc_file(path, &vhc)?;
+    // An index entry, perhaps left stale by a later reshuffle, gets the current positions
+    if lookup_partition(path, secret)?.is_some() {
+        record_partition(path, secret, &find_partition_blocks(&vhc.blocks, secret, &vhc.header))?;
+    }
+    metrics::counter(BYTES_ADDED, data.len() as u64);
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use crate::vhc::read_vhc_header;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+    use tempfile::tempdir;
+
+    fn extract(vhc: &Path, secret: &str) -> Vec<u8> {
+        let output = vhc.with_extension("out");
+        let options = ExtractOptions {
+            secret: secret.into(),
+            ..Default::default()
+        };
+        extract_from_vhc(vhc, &output, &options).unwrap();
+        std::fs::read(&output).unwrap()
+    }
+
+    #[test]
+    fn test_update_chunked_partition() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.bin");
+        let other = dir.path().join("other.txt");
+        let vhc = dir.path().join("vault.vhc");
+        let mut data = vec![0u8; 30_000];
+        StdRng::seed_from_u64(1).fill_bytes(&mut data);
+        std::fs::write(&input, &data).unwrap();
+        std::fs::write(&other, b"neighbour").unwrap();
+
+        // No KDF salt, so the key, and with it every chunk boundary, is the same each run
+        let options = AddOptions {
+            secret: "secret".into(),
+            chunked: true,
+            hint: true,
+            kdf: None,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+        let neighbour = AddOptions {
+            secret: "other".into(),
+            kdf: None,
+            ..Default::default()
+        };
+        add_partition(&other, &vhc, &neighbour).unwrap();
+        let header = read_vhc_header(&vhc).unwrap();
+        assert!(header.chunked);
+        let before = read_vhc_file(&vhc).unwrap().blocks;
+
+        // Change one byte near the end: only a chunk or two and the manifest move
+        data[29_000] ^= 0xFF;
+        std::fs::write(&input, &data).unwrap();
+        let update = UpdateOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        let rewritten = update_partition(&input, &vhc, &update).unwrap();
+        assert!(
+            rewritten < header.blocks_per_partition() / 2,
+            "{}",
+            rewritten
+        );
+
+        let after = read_vhc_file(&vhc).unwrap();
+        let changed = before
+            .iter()
+            .zip(&after.blocks)
+            .filter(|(a, b)| a != b)
+            .count();
+        assert_eq!(changed, rewritten);
+        assert_eq!(extract(&vhc, "secret"), data);
+        assert_eq!(extract(&vhc, "other"), b"neighbour");
+        assert_eq!(after.header.hints.len(), 1);
+    }
+
+    #[test]
+    fn test_update_plain_partition() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("vault.vhc");
+        std::fs::write(&input, b"first version").unwrap();
+        let options = AddOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &options).unwrap();
+
+        std::fs::write(&input, b"second version").unwrap();
+        let update = UpdateOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        let rewritten = update_partition(&input, &vhc, &update).unwrap();
+        assert_eq!(
+            rewritten,
+            read_vhc_header(&vhc).unwrap().blocks_per_partition()
+        );
+        assert_eq!(extract(&vhc, "secret"), b"second version");
+
+        let wrong = UpdateOptions {
+            secret: "wrong".into(),
+            ..Default::default()
+        };
+        assert!(update_partition(&input, &vhc, &wrong).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::error::{HypercubeError, Result};
+use crate::partition::{
+    block_generation, extract_partition_bounded, find_partition_blocks, SequenceGaps,
+};
+use crate::pipeline::{DEFAULT_MAX_DECOMPRESSED_SIZE, SEQUENCE_SIZE};
+use crate::vhc::{read_vhc_file, VhcReader};
+use std::path::Path;
+
+/// Options for the verify command
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    /// Partition secret; its blocks' generation tags are checked against the header
+    pub secret: Option<String>,
+    /// Generation last seen: the partition's with a secret, the header's without
+    pub min_generation: Option<u32>,
+}
+
+/// Outcome of `verify_freshness`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FreshnessReport {
+    /// Generation in the header (unauthenticated unless the container is signed)
+    pub header_generation: u32,
+    /// Newest generation among the partition's blocks; None without a secret, or for
+    /// blocks written before generation tags
+    pub partition_generation: Option<u32>,
+    /// Blocks the secret authenticated
+    pub blocks: usize,
+    /// Signs that the container was rolled back
+    pub problems: Vec<String>,
+}
+
+impl FreshnessReport {
+    pub fn is_fresh(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl std::fmt::Display for FreshnessReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Header generation: {}", self.header_generation)?;
+        if self.blocks > 0 {
+            match self.partition_generation {
+                Some(generation) => writeln!(
+                    f,
+                    "Partition generation: {} ({} blocks)",
+                    generation, self.blocks
+                )?,
+                None => writeln!(
+                    f,
+                    "Partition generation: none ({} blocks written before generation tags)",
+                    self.blocks
+                )?,
+            }
+        }
+        for problem in &self.problems {
+            writeln!(f, "Rollback: {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check a container for rollback to an older copy
+///
+/// Every write bumps the header generation, and the blocks it writes carry that
+/// generation in their sequence field, masked under the partition key and covered by
+/// the MAC. The header alone can be edited, so with a secret the partition's tags are
+/// what `min_generation` is checked against, and a header older than them is flagged.
+pub fn verify_freshness(path: &Path, options: &VerifyOptions) -> Result<FreshnessReport> {
+    let vhc = read_vhc_file(path)?;
+    let mut report = FreshnessReport {
+        header_generation: vhc.header.generation,
+        ..Default::default()
+    };
+    let Some(secret) = &options.secret else {
+        if let Some(min) = options
+            .min_generation
+            .filter(|&min| vhc.header.generation < min)
+        {
+            report.problems.push(format!(
+                "header generation {} is older than {}",
+                vhc.header.generation, min
+            ));
+        }
+        return Ok(report);
+    };
+
+    let key = vhc.header.partition_key(secret.as_bytes())?;
+    let positions = find_partition_blocks(&vhc.blocks, &key, &vhc.header);
+    if positions.is_empty() {
+        return Err(HypercubeError::NoAuthenticatedBlocks);
+    }
+    report.blocks = positions.len();
+    report.partition_generation = positions
+        .iter()
+        .filter_map(|&position| block_generation(&vhc.blocks[position], &key, &vhc.header))
+        .max();
+
+    if let Some(generation) = report.partition_generation {
+        if generation > vhc.header.generation {
+            report.problems.push(format!(
+                "header generation {} is older than the partition's blocks ({})",
+                vhc.header.generation, generation
+            ));
+        }
+    }
+    if let Some(min) = options.min_generation {
+        match report.partition_generation {
+            Some(generation) if generation >= min => {}
+            Some(generation) => report.problems.push(format!(
+                "partition generation {} is older than {}",
+                generation, min
+            )),
+            None => report.problems.push(format!(
+                "partition blocks carry no generation to compare with {}",
+                min
+            )),
+        }
+    }
+    Ok(report)
+}
+
+/// Outcome of `verify_partition`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartitionReport {
+    /// Blocks in the container
+    pub scanned: usize,
+    /// Blocks the secret authenticated
+    pub blocks: usize,
+    /// Runs of missing blocks as (index of the first, length), counted from the
+    /// partition's first block; chunked partitions number each chunk on its own and
+    /// report none
+    pub gaps: Vec<(u128, u128)>,
+    /// Indices held by more than one block
+    pub duplicates: Vec<u128>,
+    /// Blocks an erasure code can rebuild, when the container has one
+    pub restorable: bool,
+    /// Size of the original data, when the partition decoded
+    pub data_size: Option<usize>,
+    /// Why the partition cannot be extracted
+    pub problems: Vec<String>,
+}
+
+impl PartitionReport {
+    /// The partition extracts as it is
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl std::fmt::Display for PartitionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Authenticated blocks: {} of {}",
+            self.blocks, self.scanned
+        )?;
+        for &(start, length) in &self.gaps {
+            let restored = if self.restorable { " (erasure coded)" } else { "" };
+            writeln!(f, "Gap: {} blocks missing from block {}{}", length, start, restored)?;
+        }
+        for index in &self.duplicates {
+            writeln!(f, "Duplicate: block {} appears more than once", index)?;
+        }
+        if let Some(size) = self.data_size {
+            writeln!(f, "Data: {} bytes decoded", size)?;
+        }
+        for problem in &self.problems {
+            writeln!(f, "Problem: {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that a partition extracts, without writing it anywhere
+///
+/// Every block is authenticated as `extract` would, the sequence numbers of the
+/// partition's blocks are checked for gaps and duplicates, and the partition is
+/// decoded in memory: the AONT, metadata and decompressed size must all agree.
+pub fn verify_partition(path: &Path, secret: &str) -> Result<PartitionReport> {
+    let vhc = VhcReader::open(path)?;
+    let header = vhc.header();
+    let key = header.partition_key(secret.as_bytes())?;
+    let blocks: Vec<&[u8]> = (0..vhc.block_count()).filter_map(|i| vhc.block(i)).collect();
+    let own: Vec<&[u8]> = find_partition_blocks(&blocks, &key, header)
+        .into_iter()
+        .map(|position| blocks[position])
+        .collect();
+    if own.is_empty() {
+        return Err(HypercubeError::NoAuthenticatedBlocks);
+    }
+    let mut report = PartitionReport {
+        scanned: blocks.len(),
+        blocks: own.len(),
+        restorable: header.erasure.is_some(),
+        ..Default::default()
+    };
+
+    if !header.chunked {
+        let sequences = own
+            .iter()
+            .map(|block| u128::from_le_bytes(block[..SEQUENCE_SIZE].try_into().unwrap()))
+            .collect();
+        // An erasure-coded partition spans a whole aligned run, so lost end blocks show too
+        let run = header.erasure.map(|_| header.blocks_per_partition);
+        let holes = SequenceGaps::find(sequences, run);
+        report.gaps = holes.gaps;
+        report.duplicates = holes.duplicates;
+    }
+
+    match extract_partition_bounded(&own, &key, header, DEFAULT_MAX_DECOMPRESSED_SIZE) {
+        Ok(data) => report.data_size = Some(data.len()),
+        Err(e) => report.problems.push(e.to_string()),
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::update::{update_partition, UpdateOptions};
+    use crate::vhc::{read_vhc_header, rewrite_vhc_header};
+    use tempfile::tempdir;
+
+    /// Blocks of `secret`'s partition in the container, in sequence order
+    fn partition_positions(vhc: &crate::vhc::VhcFile, secret: &[u8]) -> Vec<usize> {
+        let mut positions = find_partition_blocks(&vhc.blocks, secret, &vhc.header);
+        positions.sort_by_key(|&position| {
+            u128::from_le_bytes(vhc.blocks[position][..SEQUENCE_SIZE].try_into().unwrap())
+        });
+        positions
+    }
+
+    #[test]
+    fn test_verify_partition() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc_path = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"data worth checking").unwrap();
+        let opts = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            kdf: None,
+            seal: true,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc_path, &opts).unwrap();
+
+        let report = verify_partition(&vhc_path, "secret").unwrap();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!((report.scanned, report.blocks), (64, 8));
+        assert!(report.gaps.is_empty() && report.duplicates.is_empty());
+        assert_eq!(report.data_size, Some(19));
+        assert!(verify_partition(&vhc_path, "wrong").is_err());
+
+        // A block in the middle of the partition rots
+        let mut vhc = read_vhc_file(&vhc_path).unwrap();
+        let positions = partition_positions(&vhc, b"secret");
+        vhc.blocks[positions[3]][40] ^= 0xff;
+        crate::vhc::write_vhc_file(&vhc_path, &vhc).unwrap();
+        let report = verify_partition(&vhc_path, "secret").unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.blocks, 7);
+        assert_eq!(report.gaps, vec![(3, 1)]);
+        assert!(report.to_string().contains("Gap: 1 blocks missing from block 3"));
+        assert_eq!(report.data_size, None);
+    }
+
+    #[test]
+    fn test_verify_erasure_coded_partition() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc_path = dir.path().join("cube.vhc");
+        std::fs::write(&input, crate::partition::generate_chaff(3000)).unwrap();
+        let opts = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            kdf: None,
+            erasure: Some("4+2".parse().unwrap()),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc_path, &opts).unwrap();
+
+        // Losing the last block leaves a gap only the erasure code's geometry can show
+        let mut vhc = read_vhc_file(&vhc_path).unwrap();
+        let positions = partition_positions(&vhc, b"secret");
+        vhc.blocks[positions[7]][40] ^= 0xff;
+        crate::vhc::write_vhc_file(&vhc_path, &vhc).unwrap();
+        let report = verify_partition(&vhc_path, "secret").unwrap();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.gaps, vec![(7, 1)]);
+        assert_eq!(report.data_size, Some(3000));
+    }
+
+    #[test]
+    fn test_verify_detects_rollback() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"first version").unwrap();
+        let opts = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &opts).unwrap();
+        let old = dir.path().join("old.vhc");
+        std::fs::copy(&vhc, &old).unwrap();
+
+        std::fs::write(&input, b"second version").unwrap();
+        let update = UpdateOptions {
+            secret: "secret".into(),
+            ..Default::default()
+        };
+        update_partition(&input, &vhc, &update).unwrap();
+
+        let options = VerifyOptions {
+            secret: Some("secret".into()),
+            min_generation: None,
+        };
+        let report = verify_freshness(&vhc, &options).unwrap();
+        assert!(report.is_fresh(), "{}", report);
+        let seen = report.partition_generation.unwrap();
+        assert_eq!(report.header_generation, 2);
+        assert_eq!(seen, 2);
+
+        // The old copy is caught even with its header generation pushed forward
+        let mut header = read_vhc_header(&old).unwrap();
+        header.generation = 10;
+        rewrite_vhc_header(&old, &header).unwrap();
+        let options = VerifyOptions {
+            secret: Some("secret".into()),
+            min_generation: Some(seen),
+        };
+        let report = verify_freshness(&old, &options).unwrap();
+        assert_eq!(report.partition_generation, Some(1));
+        assert!(!report.is_fresh());
+        assert!(report
+            .to_string()
+            .contains("partition generation 1 is older than 2"));
+        assert!(verify_freshness(&vhc, &options).unwrap().is_fresh());
+
+        // A header wound back behind the blocks it holds
+        let mut rolled = read_vhc_file(&vhc).unwrap();
+        rolled.header.generation = 0;
+        crate::vhc::write_vhc_file(&vhc, &rolled).unwrap();
+        let report = verify_freshness(&vhc, &options).unwrap();
+        assert!(report.to_string().contains("header generation 0 is older"));
+
+        // Without a secret only the header is compared
+        let header_only = VerifyOptions {
+            secret: None,
+            min_generation: Some(3),
+        };
+        assert!(!verify_freshness(&vhc, &header_only).unwrap().is_fresh());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::cli::seal::chaff_partition;
+use crate::error::{HypercubeError, Result};
+use crate::hint::HintKey;
+use crate::index::forget_partition;
+use crate::partition::{find_partition_blocks, generate_noise_blocks};
+use crate::vhc::{block_offset, read_vhc_file, replace_vhc_file, vhc_data_offset};
+use rand::{seq::SliceRandom, thread_rng};
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Options for the wipe command
+#[derive(Debug, Clone)]
+pub struct WipeOptions {
+    pub secret: String,
+    /// Overwrite passes; all but the last write random bytes, the last writes chaff
+    pub passes: usize,
+    /// Reshuffle the block table afterwards (default), so the chaff does not sit where
+    /// the partition was; without it every other block keeps its position
+    pub reshuffle: bool,
+}
+
+impl Default for WipeOptions {
+    fn default() -> Self {
+        Self {
+            secret: String::new(),
+            passes: 1,
+            reshuffle: true,
+        }
+    }
+}
+
+/// Remove the partition `secret` opens: one chaff pass, then a reshuffle
+/// Shorthand for `wipe_partition` with default options
+pub fn remove_partition(path: &Path, secret: &str) -> Result<usize> {
+    let options = WipeOptions {
+        secret: secret.into(),
+        ..Default::default()
+    };
+    wipe_partition(path, &options)
+}
+
+/// Destroy one partition: overwrite its blocks in place with fresh chaff,
+/// drop its bloom hint and index entry, then reshuffle the block table if asked
+/// Other partitions are untouched apart from their positions
+/// Returns the number of blocks wiped
+pub fn wipe_partition(path: &Path, options: &WipeOptions) -> Result<usize> {
+    if options.passes == 0 {
+        return Err(HypercubeError::InvalidFormat(
+            "At least one wipe pass is required".into(),
+        ));
+    }
+    let vhc = read_vhc_file(path)?;
+    vhc.header.check_unlocked()?;
+    vhc.header.check_mutable("wipe a partition")?;
+    let key = vhc.header.partition_key(options.secret.as_bytes())?;
+    let secret = key.as_slice();
+    let positions = find_partition_blocks(&vhc.blocks, secret, &vhc.header);
+    if positions.is_empty() {
+        return Err(HypercubeError::NoAuthenticatedBlocks);
+    }
+
+    // Replacement chaff looks like a whole partition of its own
+    let mut chaff = Vec::with_capacity(positions.len());
+    while chaff.len() < positions.len() {
+        chaff.extend(chaff_partition(&vhc.header)?);
+    }
+
+    let data_start = vhc_data_offset(path)?;
+    let block_size = vhc.header.total_block_size();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    for pass in 1..=options.passes {
+        let noise = if pass == options.passes {
+            Vec::new()
+        } else {
+            generate_noise_blocks(positions.len(), block_size)
+        };
+        for (i, &position) in positions.iter().enumerate() {
+            file.seek(SeekFrom::Start(block_offset(data_start, position, &vhc.header)?))?;
+            file.write_all(noise.get(i).unwrap_or(&chaff[i]))?;
+        }
+        file.sync_data()?;
+    }
+    drop(file);
+
+    // A leftover hint would still match the wiped blocks' old prefixes
+    let key = HintKey::new(secret, vhc.header.domains()?);
+    let mut wiped = read_vhc_file(path)?;
+    wiped.header.hints.retain(|hint| {
+        !positions
+            .iter()
+            .all(|&position| hint.may_contain(&key, &vhc.blocks[position]))
+    });
+    if options.reshuffle {
+        wiped.blocks.shuffle(&mut thread_rng());
+    }
+    wiped.header.bump_generation();
+    replace_vhc_file(path, &wiped)?;
+    forget_partition(path, secret)?;
+
+    Ok(positions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use crate::index::{lookup_partition, record_partition};
+    use crate::vhc::{get_block_count, read_vhc_header};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_wipe_destroys_only_target() {
+        let dir = tempdir().unwrap();
+        let keep = dir.path().join("keep.txt");
+        let drop = dir.path().join("drop.txt");
+        let vhc = dir.path().join("vault.vhc");
+        let output = dir.path().join("out.txt");
+        std::fs::write(&keep, b"keep this partition").unwrap();
+        std::fs::write(&drop, b"destroy this partition").unwrap();
+
+        let add = |input: &Path, secret: &str| {
+            let options = AddOptions {
+                secret: secret.into(),
+                hint: true,
+                index: true,
+                ..Default::default()
+            };
+            add_partition(input, &vhc, &options).unwrap();
+        };
+        add(&keep, "keep");
+        add(&drop, "drop");
+        let before = std::fs::read(&vhc).unwrap();
+        let blocks = get_block_count(&vhc).unwrap();
+
+        let options = WipeOptions {
+            secret: "drop".into(),
+            passes: 3,
+            ..Default::default()
+        };
+        let wiped = wipe_partition(&vhc, &options).unwrap();
+        assert_eq!(wiped, read_vhc_header(&vhc).unwrap().blocks_per_partition());
+        assert_eq!(get_block_count(&vhc).unwrap(), blocks);
+        assert_eq!(read_vhc_header(&vhc).unwrap().hints.len(), 1);
+        assert!(lookup_partition(&vhc, b"drop").unwrap().is_none());
+        assert_ne!(std::fs::read(&vhc).unwrap(), before);
+
+        let extract = |secret: &str| {
+            let options = ExtractOptions {
+                secret: secret.into(),
+                ..Default::default()
+            };
+            extract_from_vhc(&vhc, &output, &options)
+        };
+        assert!(extract("drop").is_err());
+        extract("keep").unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"keep this partition");
+
+        // Nothing left to wipe
+        assert!(wipe_partition(&vhc, &options).is_err());
+    }
+
+    #[test]
+    fn test_remove_without_reshuffle_keeps_positions() {
+        let dir = tempdir().unwrap();
+        let vhc = dir.path().join("vault.vhc");
+        for secret in ["keep", "drop"] {
+            let input = dir.path().join(secret);
+            std::fs::write(&input, secret.repeat(40)).unwrap();
+            let options = AddOptions {
+                secret: secret.into(),
+                dimension: 8,
+                index: true,
+                ..Default::default()
+            };
+            add_partition(&input, &vhc, &options).unwrap();
+        }
+        // Refresh the entry the second add's reshuffle left stale
+        let before = read_vhc_file(&vhc).unwrap();
+        let keep_key = before.header.partition_key(b"keep").unwrap();
+        let drop_key = before.header.partition_key(b"drop").unwrap();
+        let kept = find_partition_blocks(&before.blocks, &keep_key, &before.header);
+        record_partition(&vhc, &keep_key, &kept).unwrap();
+        let dropped = find_partition_blocks(&before.blocks, &drop_key, &before.header);
+
+        let options = WipeOptions {
+            secret: "drop".into(),
+            reshuffle: false,
+            ..Default::default()
+        };
+        assert_eq!(wipe_partition(&vhc, &options).unwrap(), dropped.len());
+
+        // Only the removed partition's blocks changed, so other index entries stay valid
+        let after = read_vhc_file(&vhc).unwrap();
+        for (position, (old, new)) in before.blocks.iter().zip(&after.blocks).enumerate() {
+            assert_eq!(old == new, !dropped.contains(&position), "block {}", position);
+        }
+        let keep = find_partition_blocks(&after.blocks, &keep_key, &after.header);
+        assert_eq!(lookup_partition(&vhc, &keep_key).unwrap(), Some(keep));
+        assert!(find_partition_blocks(&after.blocks, &drop_key, &after.header).is_empty());
+        assert_eq!(after.header.generation, before.header.generation + 1);
+
+        assert!(remove_partition(&vhc, "drop").is_err());
+        assert_eq!(remove_partition(&vhc, "keep").unwrap(), dropped.len());
+    }
+}
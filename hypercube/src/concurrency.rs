@@ -0,0 +1,205 @@
+//! How many threads an operation's per-block work may use.
+//!
+//! Checking MACs while scanning a container, computing a new partition's MACs and the
+//! Rivest AONT's per-fragment masks and hashes split their items into contiguous runs,
+//! one per worker on a scoped thread. Results come back in item order, so the blocks
+//! written and the data extracted never depend on the setting.
+//!
+//! The setting is process-wide, like the metrics recorder: call `set_concurrency` once at
+//! start-up. `Concurrency::Single` keeps all work on the calling thread, in order, for
+//! hosts that must not spawn threads and for reproducible profiles. Stage timings
+//! (`metrics::collect_timings`) are taken on the calling thread either way.
+
+use crate::error::{HypercubeError, Result};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Items each worker needs before another thread is worth starting
+pub const MIN_ITEMS_PER_THREAD: usize = 64;
+
+/// Threads available to per-block work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Concurrency {
+    /// One worker per available core
+    #[default]
+    Auto,
+    /// All work on the calling thread, in order
+    Single,
+    /// At most this many workers
+    Threads(NonZeroUsize),
+}
+
+impl Concurrency {
+    /// Workers the setting allows on this machine
+    pub fn threads(self) -> usize {
+        match self {
+            Self::Auto => std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            Self::Single => 1,
+            Self::Threads(threads) => threads.get(),
+        }
+    }
+}
+
+impl std::fmt::Display for Concurrency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Single => write!(f, "single"),
+            Self::Threads(threads) => write!(f, "{}", threads),
+        }
+    }
+}
+
+impl std::str::FromStr for Concurrency {
+    type Err = HypercubeError;
+    /// `auto`, `single`, or a thread count (1 is single)
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "single" | "1" => Ok(Self::Single),
+            other => other.parse().map(Self::Threads).map_err(|_| {
+                HypercubeError::InvalidFormat(format!(
+                    "Thread count {} (expected auto, single or a positive number)",
+                    s
+                ))
+            }),
+        }
+    }
+}
+
+/// 0 for `Auto`, otherwise the thread count (1 for `Single`)
+static CONCURRENCY: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the threads per-block work may use from now on, process-wide
+pub fn set_concurrency(concurrency: Concurrency) {
+    let threads = match concurrency {
+        Concurrency::Auto => 0,
+        Concurrency::Single => 1,
+        Concurrency::Threads(threads) => threads.get(),
+    };
+    CONCURRENCY.store(threads, Ordering::Relaxed);
+}
+
+/// The current setting
+pub fn concurrency() -> Concurrency {
+    match CONCURRENCY.load(Ordering::Relaxed) {
+        0 => Concurrency::Auto,
+        1 => Concurrency::Single,
+        threads => Concurrency::Threads(NonZeroUsize::new(threads).unwrap()),
+    }
+}
+
+/// Workers to split `items` items over
+fn workers(items: usize) -> usize {
+    concurrency()
+        .threads()
+        .min(items / MIN_ITEMS_PER_THREAD)
+        .max(1)
+}
+
+/// `f` applied to each item with its index, results in item order
+pub fn map<T: Sync, R: Send>(items: &[T], f: impl Fn(usize, &T) -> R + Sync) -> Vec<R> {
+    let workers = workers(items.len());
+    if workers == 1 {
+        return items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| f(i, item))
+            .collect();
+    }
+    let run = items.len().div_ceil(workers);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(run)
+            .enumerate()
+            .map(|(k, chunk)| {
+                scope.spawn(move || {
+                    let indexed = chunk.iter().enumerate();
+                    indexed
+                        .map(|(i, item)| f(k * run + i, item))
+                        .collect::<Vec<R>>()
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(join).collect()
+    })
+}
+
+/// `f` applied to each item in place, with its index
+pub fn for_each_mut<T: Send>(items: &mut [T], f: impl Fn(usize, &mut T) + Sync) {
+    let workers = workers(items.len());
+    if workers == 1 {
+        items
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, item)| f(i, item));
+        return;
+    }
+    let run = items.len().div_ceil(workers);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks_mut(run)
+            .enumerate()
+            .map(|(k, chunk)| {
+                scope.spawn(move || {
+                    let indexed = chunk.iter_mut().enumerate();
+                    indexed.for_each(|(i, item)| f(k * run + i, item))
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(join);
+    })
+}
+
+/// A worker's result, passing its panic on to the caller
+fn join<R>(handle: std::thread::ScopedJoinHandle<'_, R>) -> R {
+    handle
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_concurrency() {
+        assert_eq!("auto".parse::<Concurrency>().unwrap(), Concurrency::Auto);
+        assert_eq!(
+            "single".parse::<Concurrency>().unwrap(),
+            Concurrency::Single
+        );
+        assert_eq!("1".parse::<Concurrency>().unwrap(), Concurrency::Single);
+        let four = Concurrency::Threads(NonZeroUsize::new(4).unwrap());
+        assert_eq!("4".parse::<Concurrency>().unwrap(), four);
+        assert_eq!(four.to_string(), "4");
+        assert_eq!(Concurrency::Single.threads(), 1);
+        assert!(Concurrency::Auto.threads() >= 1);
+        for bad in ["", "0", "-2", "many"] {
+            assert!(bad.parse::<Concurrency>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_map_keeps_order() {
+        // Results never depend on the setting, so changing it cannot disturb other tests
+        set_concurrency(Concurrency::Threads(NonZeroUsize::new(4).unwrap()));
+
+        let items: Vec<u64> = (0..1000).collect();
+        let squares = map(&items, |i, &x| {
+            assert_eq!(i as u64, x);
+            x * x
+        });
+        assert_eq!(squares, items.iter().map(|&x| x * x).collect::<Vec<_>>());
+
+        let mut doubled = items.clone();
+        for_each_mut(&mut doubled, |i, x| {
+            assert_eq!(i as u64, *x);
+            *x *= 2;
+        });
+        assert_eq!(doubled, items.iter().map(|&x| x * 2).collect::<Vec<_>>());
+        set_concurrency(Concurrency::Auto);
+    }
+}
@@ -0,0 +1,118 @@
+//! User configuration file (JSON).
+//! Looked up at `$HYPERCUBE_CONFIG`, then `$XDG_CONFIG_HOME/hypercube/config.json`,
+//! then `~/.config/hypercube/config.json`. A missing default file means no settings.
+
+use crate::cube::{CubePreset, CubeRegistry};
+use crate::error::{HypercubeError, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming an explicit config file
+pub const CONFIG_ENV: &str = "HYPERCUBE_CONFIG";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Named cube presets, usable as `--cube NAME`
+    #[serde(default)]
+    pub presets: BTreeMap<String, CubePreset>,
+    /// Seal after every add unless a preset or `--no-seal` says otherwise
+    #[serde(default)]
+    pub seal: bool,
+    /// Secret helper used when a command gets no `--secret` (see `--secret-helper`)
+    #[serde(default)]
+    pub secret_helper: Option<String>,
+    /// Shell command run whenever a honeypot partition is opened (see `add --honeypot`)
+    #[serde(default)]
+    pub honeypot_alert: Option<String>,
+}
+
+impl Config {
+    /// Load a config file; the file must exist
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| {
+            HypercubeError::InvalidFormat(format!("Config {}: {}", path.display(), e))
+        })
+    }
+
+    /// Load the config from `$HYPERCUBE_CONFIG` or the default location
+    pub fn load_default() -> Result<Self> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return Self::load(Path::new(&path));
+        }
+        match default_config_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Built-in presets plus the ones defined here, which cannot be named by a number
+    pub fn cube_registry(&self) -> Result<CubeRegistry> {
+        let mut registry = CubeRegistry::builtin();
+        for (name, preset) in &self.presets {
+            registry.register(name, preset.clone()).map_err(|e| {
+                HypercubeError::InvalidFormat(format!("Config preset {}: {}", name, e))
+            })?;
+        }
+        Ok(registry)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/hypercube/config.json`, falling back to `~/.config`
+pub fn default_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("hypercube").join("config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Compression;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_config_presets() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        std::fs::write(
+            &path,
+            r#"{"presets": {"archive": {"dimension": 16, "block_size": 128, "compression": "brotli"}}}"#,
+        )
+        .unwrap();
+
+        let registry = Config::load(&path).unwrap().cube_registry().unwrap();
+        let archive = registry.resolve("archive").unwrap();
+        assert_eq!(archive.dimension, 16);
+        assert_eq!(archive.block_size, Some(128));
+        assert_eq!(archive.compression, Some(Compression::Brotli));
+        assert!(registry.get("1").is_some());
+
+        assert!(!Config::load(&path).unwrap().seal);
+
+        std::fs::write(
+            &path,
+            r#"{"seal": true, "presets": {"scratch": {"dimension": 8, "seal": false}}}"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert!(config.seal);
+        let scratch = config.cube_registry().unwrap().resolve("scratch").unwrap();
+        assert_eq!(scratch.seal, Some(false));
+
+        std::fs::write(&path, r#"{"presets": {"bad": {"dimension": 10}}}"#).unwrap();
+        let err = Config::load(&path).unwrap().cube_registry().unwrap_err();
+        assert!(err.to_string().contains("preset bad"));
+
+        // A config cannot redefine a built-in preset
+        std::fs::write(&path, r#"{"presets": {"2": {"dimension": 64}}}"#).unwrap();
+        let err = Config::load(&path).unwrap().cube_registry().unwrap_err();
+        assert!(err.to_string().contains("preset 2"));
+
+        std::fs::write(&path, r#"{"presets": {"typo": {"dimensions": 16}}}"#).unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+}
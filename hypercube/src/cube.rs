@@ -1,6 +1,9 @@
-use crate::error::Result;
-use crate::header::{PartitionMeta, Compression};
-use crate::pipeline::compress;
+use crate::error::{HypercubeError, Result};
+use crate::header::{Aont, Compression, HashAlgorithm, PartitionMeta};
+use crate::pipeline::registry::AontTransform;
+use crate::pipeline::{compress_framed, compressed_size_bound, CompressionCodec, SEQUENCE_SIZE};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Cube configuration describing partition/blocks layout
 /// For a hypercube, partitions == blocks_per_partition (N×N)
@@ -30,6 +33,233 @@ impl CubeConfig {
     }
 }
 
+/// A named container shape: geometry, block size and default algorithms
+/// Unset fields fall back to the command-line defaults
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CubePreset {
+    /// Hypercube dimension (N partitions × N blocks)
+    pub dimension: usize,
+    /// Fixed block payload size; sized to the first input when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac_bits: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aont: Option<Aont>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<HashAlgorithm>,
+    /// Seal after every add; overrides the config-wide default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seal: Option<bool>,
+    /// What the preset is for, shown when presets are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl CubePreset {
+    /// Preset with only a dimension
+    pub fn hypercube(dimension: usize) -> Self {
+        Self {
+            dimension,
+            block_size: None,
+            mac_bits: None,
+            compression: None,
+            aont: None,
+            hash: None,
+            seal: None,
+            description: None,
+        }
+    }
+
+    /// Compressed input bytes one partition holds (after its metadata), with the
+    /// preset's AONT; None when the block size is sized to the first input
+    pub fn partition_capacity(&self) -> Option<usize> {
+        let data_blocks = self
+            .dimension
+            .saturating_sub(self.aont.unwrap_or_default().overhead_blocks());
+        Some((self.block_size? * data_blocks).saturating_sub(PartitionMeta::HASHED_SIZE))
+    }
+
+    /// Bytes of blocks in a sealed container: N×N blocks, each with its sequence and MAC
+    pub fn sealed_bytes(&self) -> Option<u64> {
+        let block = self.block_size? + SEQUENCE_SIZE + self.mac_bits.unwrap_or(256) / 8;
+        Some((self.dimension * self.dimension) as u64 * block as u64)
+    }
+
+    /// Check the same limits `VhcHeader::new` enforces
+    pub fn validate(&self) -> Result<()> {
+        if self.dimension < 8 || !self.dimension.is_multiple_of(8) {
+            return Err(HypercubeError::InvalidDimension(self.dimension));
+        }
+        if let Some(block_size) = self.block_size {
+            if block_size < 32 || !block_size.is_multiple_of(2) {
+                return Err(HypercubeError::InvalidBlockSize(block_size));
+            }
+        }
+        if let Some(mac_bits) = self.mac_bits {
+            if ![128, 256, 512].contains(&mac_bits) {
+                return Err(HypercubeError::InvalidMacBits(mac_bits));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn cube(&self) -> CubeConfig {
+        CubeConfig::hypercube(self.dimension)
+    }
+}
+
+/// Built-in presets with a fixed block size: name, description, dimension and block size
+/// Partition capacities run from 7 KiB to 8 MiB; a sealed `5` is about 1 GiB
+pub const FIXED_PRESETS: [(&str, &str, usize, usize); 4] = [
+    ("2", "small", 16, 512),
+    ("3", "medium", 32, 4 * 1024),
+    ("4", "large", 64, 16 * 1024),
+    ("5", "archive", 128, 64 * 1024),
+];
+
+/// Named cube presets: the built-in ones plus any loaded from the config file
+#[derive(Debug, Clone)]
+pub struct CubeRegistry {
+    presets: BTreeMap<String, CubePreset>,
+}
+
+impl Default for CubeRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl CubeRegistry {
+    /// Built-in presets: `1` is the original 32×32 cube, with blocks sized to its first
+    /// input; `2` to `5` fix the block size, so their capacities are known up front
+    pub fn builtin() -> Self {
+        let mut presets = BTreeMap::new();
+        let default = CubePreset {
+            description: Some("default".to_string()),
+            ..CubePreset::hypercube(32)
+        };
+        presets.insert("1".to_string(), default);
+        for (name, description, dimension, block_size) in FIXED_PRESETS {
+            let preset = CubePreset {
+                block_size: Some(block_size),
+                description: Some(description.to_string()),
+                ..CubePreset::hypercube(dimension)
+            };
+            presets.insert(name.to_string(), preset);
+        }
+        Self { presets }
+    }
+
+    /// Add or replace a preset
+    /// Numbers are refused: `1` to `5` are the built-in presets, and any other number is
+    /// the plain cube of that dimension, so a preset named by one would shadow either
+    pub fn register(&mut self, name: &str, preset: CubePreset) -> Result<()> {
+        if name.parse::<usize>().is_ok() {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "Preset name {} is a number, which names a built-in preset or a dimension",
+                name
+            )));
+        }
+        preset.validate()?;
+        self.presets.insert(name.to_string(), preset);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CubePreset> {
+        self.presets.get(name)
+    }
+
+    /// Look up a preset by name
+    /// A number that is no preset is the plain cube of that dimension (`64` is 64×64 with
+    /// blocks sized to the input), and must be a valid one: a multiple of 8 from 8
+    pub fn resolve(&self, name: &str) -> Result<CubePreset> {
+        if let Some(preset) = self.get(name) {
+            return Ok(preset.clone());
+        }
+        let known = self.names().collect::<Vec<_>>().join(", ");
+        match name.parse::<usize>() {
+            Ok(dimension) => {
+                let preset = CubePreset::hypercube(dimension);
+                preset.validate().map_err(|_| {
+                    HypercubeError::UnknownPreset(format!(
+                        "{} (neither a preset nor a dimension, which is a multiple of 8; \
+                         known: {})",
+                        name, known
+                    ))
+                })?;
+                Ok(preset)
+            }
+            Err(_) => Err(HypercubeError::UnknownPreset(format!(
+                "{} (known: {})",
+                name, known
+            ))),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(String::as_str)
+    }
+
+    /// Presets with their names, in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CubePreset)> {
+        self.presets
+            .iter()
+            .map(|(name, preset)| (name.as_str(), preset))
+    }
+
+    /// Every geometry that can hold `payload_len` input bytes, smallest partition first
+    /// Registered presets keep their own block size, tag length and compression;
+    /// plain dimensions up to `MAX_RECOMMENDED_DIMENSION` use `compression` and 256-bit tags
+    pub fn candidate_geometries(
+        &self,
+        payload_len: usize,
+        compression: Compression,
+    ) -> Vec<GeometryCandidate> {
+        let named = self.presets.iter().map(|(name, preset)| {
+            let compression = preset.compression.unwrap_or(compression);
+            let mac_bits = preset.mac_bits.unwrap_or(256);
+            (
+                name.clone(),
+                preset.dimension,
+                preset.block_size,
+                compression,
+                mac_bits,
+            )
+        });
+        let plain = (8..=MAX_RECOMMENDED_DIMENSION)
+            .step_by(8)
+            .map(|dimension| (dimension.to_string(), dimension, None, compression, 256));
+
+        let mut candidates: Vec<GeometryCandidate> = named
+            .chain(plain)
+            .filter_map(|(preset, dimension, block_size, compression, mac_bits)| {
+                let payload_bytes =
+                    PartitionMeta::HASHED_SIZE + compressed_size_bound(payload_len, compression);
+                let layout = match block_size {
+                    Some(block_size) => {
+                        sized_layout(payload_bytes, dimension, mac_bits, block_size)?
+                    }
+                    None => block_layout(payload_bytes, dimension, mac_bits),
+                };
+                Some(GeometryCandidate {
+                    preset,
+                    compression,
+                    mac_bits,
+                    payload_bytes,
+                    layout,
+                })
+            })
+            .collect();
+        candidates
+            .sort_by_key(|candidate| (candidate.partition_bytes(), candidate.layout.dimension));
+        candidates
+    }
+}
+
 /// Summary of how a payload maps to a cube
 #[derive(Debug, Clone)]
 pub struct CubeAnalysis {
@@ -45,6 +275,19 @@ impl CubeAnalysis {
     pub fn headroom_bytes(&self) -> usize {
         self.capacity_bytes.saturating_sub(self.payload_bytes)
     }
+
+    /// The same analysis with `bytes` more payload per partition
+    pub fn with_extra_payload(self, bytes: usize) -> Self {
+        let payload_bytes = self.payload_bytes + bytes;
+        let data_blocks = self.cube.blocks_per_partition.saturating_sub(1).max(1);
+        let block_size_bytes = required_block_size(payload_bytes, data_blocks);
+        Self {
+            payload_bytes,
+            block_size_bytes,
+            capacity_bytes: block_size_bytes * data_blocks,
+            ..self
+        }
+    }
 }
 
 /// Analyze data for a specific cube & compression setting
@@ -54,8 +297,18 @@ pub fn analyze_data(
     compression: Compression,
     cube: CubeConfig,
 ) -> Result<CubeAnalysis> {
-    let compressed = compress(data, compression)?;
-    let payload_bytes = PartitionMeta::SIZE + compressed.len();
+    analyze_framed(data, &compression, 0, cube)
+}
+
+/// Analyze data compressed in frames of `frame_size` bytes (see `VhcHeader::frame_size`)
+pub fn analyze_framed(
+    data: &[u8],
+    compression: &dyn CompressionCodec,
+    frame_size: u32,
+    cube: CubeConfig,
+) -> Result<CubeAnalysis> {
+    let compressed = compress_framed(data, compression, frame_size)?;
+    let payload_bytes = PartitionMeta::HASHED_SIZE + compressed.len();
     // Reserve one block for AONT key
     let data_blocks = cube.blocks_per_partition.saturating_sub(1).max(1);
     let block_size_bytes = required_block_size(payload_bytes, data_blocks);
@@ -71,10 +324,110 @@ pub fn analyze_data(
     })
 }
 
+/// Largest dimension `recommend_block_size` considers
+pub const MAX_RECOMMENDED_DIMENSION: usize = 256;
+
+/// How one partition of a payload lays out on disk for a given dimension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLayout {
+    pub dimension: usize,
+    /// Block payload size, as `add` would pick it for this payload
+    pub block_size: usize,
+    /// Sequence and MAC of every block, plus the AONT key block
+    pub overhead_bytes: usize,
+    /// Unused space in the data blocks
+    pub padding_bytes: usize,
+}
+
+impl BlockLayout {
+    /// Size of one partition on disk
+    pub fn partition_bytes(&self, payload_bytes: usize) -> usize {
+        payload_bytes + self.overhead_bytes + self.padding_bytes
+    }
+}
+
+/// Lay a payload out in a cube of `dimension` (one Rivest key block per partition)
+pub fn block_layout(payload_bytes: usize, dimension: usize, mac_bits: usize) -> BlockLayout {
+    let data_blocks = dimension.saturating_sub(1).max(1);
+    let mut block_size = required_block_size(payload_bytes, data_blocks).max(32);
+    block_size += block_size % 2;
+    sized_layout(payload_bytes, dimension, mac_bits, block_size).unwrap()
+}
+
+/// Lay a payload out in fixed-size blocks; None when it does not fit
+fn sized_layout(
+    payload_bytes: usize,
+    dimension: usize,
+    mac_bits: usize,
+    block_size: usize,
+) -> Option<BlockLayout> {
+    let data_blocks = dimension.saturating_sub(1).max(1);
+    Some(BlockLayout {
+        dimension,
+        block_size,
+        overhead_bytes: dimension * (SEQUENCE_SIZE + mac_bits / 8) + block_size,
+        padding_bytes: (block_size * data_blocks).checked_sub(payload_bytes)?,
+    })
+}
+
+/// One way to store a payload, for frontends to offer as a choice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeometryCandidate {
+    /// Preset name, or the dimension for an unnamed cube; either is accepted by `resolve`
+    pub preset: String,
+    pub compression: Compression,
+    pub mac_bits: usize,
+    /// Worst-case partition payload: metadata plus the compression bound
+    pub payload_bytes: usize,
+    pub layout: BlockLayout,
+}
+
+impl GeometryCandidate {
+    /// Unused space in the data blocks
+    pub fn waste_bytes(&self) -> usize {
+        self.layout.padding_bytes
+    }
+
+    /// Sequence, MAC and AONT key block bytes
+    pub fn overhead_bytes(&self) -> usize {
+        self.layout.overhead_bytes
+    }
+
+    /// Size of one partition on disk
+    pub fn partition_bytes(&self) -> usize {
+        self.layout.partition_bytes(self.payload_bytes)
+    }
+
+    /// Size of the blocks of a full (sealed) container: `dimension` partitions
+    pub fn container_bytes(&self) -> usize {
+        self.layout.dimension * self.partition_bytes()
+    }
+}
+
+/// Every geometry the built-in presets and plain dimensions offer for `payload_len` bytes
+/// Sizes assume incompressible input, so they are upper bounds
+pub fn candidate_geometries(
+    payload_len: usize,
+    compression: Compression,
+) -> Vec<GeometryCandidate> {
+    CubeRegistry::builtin().candidate_geometries(payload_len, compression)
+}
+
+/// The block size (and dimension) that keeps one partition smallest on disk
+/// Bigger blocks waste more padding; more, smaller blocks each pay for a sequence and a MAC
+/// Ties go to the smaller dimension, since a sealed cube holds `dimension` partitions
+pub fn recommend_block_size(payload_bytes: usize, mac_bits: usize) -> BlockLayout {
+    (8..=MAX_RECOMMENDED_DIMENSION)
+        .step_by(8)
+        .map(|dimension| block_layout(payload_bytes, dimension, mac_bits))
+        .min_by_key(|layout| layout.partition_bytes(payload_bytes))
+        .unwrap()
+}
+
 /// Determine the minimal block size (bytes) needed to hold payload across given number of blocks
 pub fn required_block_size(payload_bytes: usize, blocks: usize) -> usize {
     let blocks = blocks.max(1);
-    let per_block = (payload_bytes + blocks - 1) / blocks;
+    let per_block = payload_bytes.div_ceil(blocks);
     per_block.max(1)
 }
 
@@ -98,6 +451,181 @@ mod tests {
         assert_eq!(cfg.total_blocks(), 64);
     }
 
+    #[test]
+    fn test_cube_registry() {
+        let mut registry = CubeRegistry::builtin();
+        assert_eq!(registry.resolve("1").unwrap().dimension, 32);
+        // Other numbers are plain cubes of that dimension, with blocks sized to the input
+        assert_eq!(registry.resolve("64").unwrap(), CubePreset::hypercube(64));
+        assert_eq!(registry.resolve("8").unwrap(), CubePreset::hypercube(8));
+        for bad in ["12", "6", "0"] {
+            let err = registry.resolve(bad).unwrap_err();
+            assert!(matches!(err, HypercubeError::UnknownPreset(_)), "{}", bad);
+            assert!(err.to_string().contains("known: 1, 2, 3, 4, 5"));
+        }
+
+        let archive = CubePreset {
+            block_size: Some(256),
+            compression: Some(Compression::Brotli),
+            ..CubePreset::hypercube(16)
+        };
+        registry.register("archive", archive.clone()).unwrap();
+        assert_eq!(registry.resolve("archive").unwrap(), archive);
+        // Registering a name again replaces the preset
+        let bigger = CubePreset {
+            dimension: 32,
+            ..archive.clone()
+        };
+        registry.register("archive", bigger.clone()).unwrap();
+        assert_eq!(registry.resolve("archive").unwrap(), bigger);
+
+        // Numbers cannot be registered: not the built-ins, nor a dimension
+        for name in ["1", "2", "5", "64", "007"] {
+            assert!(registry.register(name, archive.clone()).is_err(), "{}", name);
+        }
+        assert_eq!(registry.resolve("2").unwrap().dimension, 16);
+        assert_eq!(registry.resolve("64").unwrap(), CubePreset::hypercube(64));
+
+        let err = registry.resolve("missing").unwrap_err().to_string();
+        assert!(err.contains("known: 1, 2, 3, 4, 5, archive"));
+
+        let odd = CubePreset {
+            block_size: Some(33),
+            ..CubePreset::hypercube(16)
+        };
+        assert!(registry.register("odd", odd).is_err());
+    }
+
+    #[test]
+    fn test_builtin_presets() {
+        let registry = CubeRegistry::builtin();
+        let shapes: Vec<_> = registry
+            .iter()
+            .map(|(name, preset)| (name, preset.dimension, preset.block_size))
+            .collect();
+        assert_eq!(
+            shapes,
+            [
+                ("1", 32, None),
+                ("2", 16, Some(512)),
+                ("3", 32, Some(4096)),
+                ("4", 64, Some(16384)),
+                ("5", 128, Some(65536)),
+            ]
+        );
+        assert!(registry.iter().all(|(_, preset)| preset.validate().is_ok()));
+
+        // One of 16 blocks carries the Rivest key, and the metadata comes off the rest
+        let small = registry.resolve("2").unwrap();
+        assert_eq!(small.description.as_deref(), Some("small"));
+        assert_eq!(
+            small.partition_capacity(),
+            Some(15 * 512 - PartitionMeta::HASHED_SIZE)
+        );
+        assert_eq!(small.sealed_bytes(), Some(256 * (512 + 48)));
+        let oaep = CubePreset {
+            aont: Some(Aont::Oaep),
+            ..small
+        };
+        assert_eq!(
+            oaep.partition_capacity(),
+            Some(16 * 512 - PartitionMeta::HASHED_SIZE)
+        );
+
+        // The capacities and sealed sizes the README lists, for every fixed preset
+        let figures: Vec<_> = FIXED_PRESETS
+            .iter()
+            .map(|&(name, ..)| {
+                let preset = registry.resolve(name).unwrap();
+                (preset.partition_capacity(), preset.sealed_bytes())
+            })
+            .collect();
+        let meta = PartitionMeta::HASHED_SIZE;
+        assert_eq!(
+            figures,
+            [
+                (Some(15 * 512 - meta), Some(256 * (512 + 48))),
+                (Some(31 * 4096 - meta), Some(1024 * (4096 + 48))),
+                (Some(63 * 16384 - meta), Some(4096 * (16384 + 48))),
+                (Some(127 * 65536 - meta), Some(16384 * (65536 + 48))),
+            ]
+        );
+
+        // The default preset is sized to its input, so has no fixed capacity
+        let default = registry.resolve("1").unwrap();
+        assert_eq!(default.partition_capacity(), None);
+        assert_eq!(default.sealed_bytes(), None);
+    }
+
+    #[test]
+    fn test_block_layout() {
+        // 10 000 bytes over 31 data blocks: 323-byte blocks (rounded up to even: 324)
+        let layout = block_layout(10_000, 32, 256);
+        assert_eq!(layout.block_size, 324);
+        assert_eq!(layout.padding_bytes, 324 * 31 - 10_000);
+        assert_eq!(layout.overhead_bytes, 32 * 48 + 324);
+        assert_eq!(layout.partition_bytes(10_000), 32 * (16 + 324 + 32));
+
+        // Tiny payloads hit the 32-byte minimum and are all padding
+        assert_eq!(block_layout(10, 32, 256).block_size, 32);
+    }
+
+    #[test]
+    fn test_recommend_block_size() {
+        // A small payload wants few blocks; a large one spreads the fixed per-block cost
+        let small = recommend_block_size(200, 256);
+        assert_eq!(small.dimension, 8);
+        let large = recommend_block_size(1_000_000, 256);
+        assert!(large.dimension > 32);
+        for dimension in (8..=MAX_RECOMMENDED_DIMENSION).step_by(8) {
+            let other = block_layout(1_000_000, dimension, 256);
+            assert!(large.partition_bytes(1_000_000) <= other.partition_bytes(1_000_000));
+        }
+        // Bigger tags push towards fewer, larger blocks
+        assert!(recommend_block_size(1_000_000, 512).block_size >= large.block_size);
+    }
+
+    #[test]
+    fn test_candidate_geometries() {
+        let candidates = candidate_geometries(10_000, Compression::None);
+        // Built-in `1`, the fixed presets big enough (`3` to `5`), and every plain dimension
+        assert_eq!(candidates.len(), 4 + MAX_RECOMMENDED_DIMENSION / 8);
+        assert!(candidates.iter().all(|c| c.preset != "2"));
+        assert!(candidates
+            .iter()
+            .any(|c| c.preset == "1" && c.layout.dimension == 32));
+        let best = &candidates[0];
+        assert_eq!(best.payload_bytes, PartitionMeta::HASHED_SIZE + 10_000);
+        assert_eq!(best.layout, recommend_block_size(best.payload_bytes, 256));
+        assert!(candidates
+            .windows(2)
+            .all(|pair| pair[0].partition_bytes() <= pair[1].partition_bytes()));
+        assert_eq!(
+            best.partition_bytes(),
+            best.payload_bytes + best.waste_bytes() + best.overhead_bytes()
+        );
+
+        // Compression bounds grow the payload for incompressible input
+        let zstd = candidate_geometries(10_000, Compression::Zstd);
+        assert!(zstd[0].payload_bytes > best.payload_bytes);
+
+        // Presets with a fixed block size are dropped when the payload does not fit
+        let mut registry = CubeRegistry::builtin();
+        let fixed = CubePreset {
+            block_size: Some(64),
+            mac_bits: Some(128),
+            ..CubePreset::hypercube(8)
+        };
+        registry.register("tiny", fixed).unwrap();
+        let fits = registry.candidate_geometries(100, Compression::None);
+        let tiny = fits.iter().find(|c| c.preset == "tiny").unwrap();
+        assert_eq!(tiny.layout.block_size, 64);
+        assert_eq!(tiny.mac_bits, 128);
+        assert_eq!(tiny.container_bytes(), 8 * tiny.partition_bytes());
+        let too_big = registry.candidate_geometries(10_000, Compression::None);
+        assert!(too_big.iter().all(|c| c.preset != "tiny"));
+    }
+
     #[test]
     fn test_required_block_size() {
         // 31 data blocks (one reserved for AONT key)
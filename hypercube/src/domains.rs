@@ -0,0 +1,119 @@
+//! Domain-separation labels.
+//!
+//! Labels that shape a partition's blocks are versioned by the header's format
+//! `version`, so a later tweak can ship as a new label set while old containers
+//! keep reading with the labels they were written with:
+//!
+//! | Header version | Meaning                                   | Label set |
+//! |----------------|-------------------------------------------|-----------|
+//! | 1              | blocks only, expanded 512-bit tags        | `V1`      |
+//! | 2              | blocks + trailer, expanded 512-bit tags   | `V1`      |
+//! | 3              | blocks + trailer, native 512-bit tags     | `V1`      |
+//! | 4              | as 3, content hash in partition metadata  | `V1`      |
+//! | 5              | as 4, binary header                       | `V1`      |
+//!
+//! Container-level labels are used before a header can be read (or without one)
+//! and are fixed: changing one needs a new layout, not a new header version.
+
+use crate::error::{HypercubeError, Result};
+
+/// Per-version labels for the partition pipeline
+#[derive(Debug, PartialEq, Eq)]
+pub struct Domains {
+    /// Rivest AONT keystream PRF
+    pub rivest_prf: &'static [u8],
+    /// OAEP AONT half-hash
+    pub aont_half: &'static [u8],
+    /// Fragment shuffle key derivation (BLAKE3 context)
+    pub fragment_shuffle: &'static str,
+    /// Bloom hint probe key derivation (BLAKE3 context)
+    pub hint_key: &'static str,
+    /// Content-defined chunking gear table derivation (BLAKE3 context)
+    pub chunk_gear: &'static str,
+    /// Container-wide dedup key derivation for shared chunks (BLAKE3 context)
+    pub chunk_dedup: &'static str,
+    /// Generation tag in the sequence base (BLAKE3 context)
+    pub sequence_tag: &'static str,
+}
+
+/// Labels of header versions 1 to 5
+pub const V1: Domains = Domains {
+    rivest_prf: b"hypercube_rivest_prf",
+    aont_half: b"hypercube_aont_half",
+    fragment_shuffle: "hypercube fragment shuffle v1",
+    hint_key: "hypercube_hint_key_v1",
+    chunk_gear: "hypercube chunk gear v1",
+    chunk_dedup: "hypercube chunk dedup v1",
+    sequence_tag: "hypercube sequence tag v1",
+};
+
+/// Newest header version; new containers are written with it
+pub const LATEST_VERSION: u32 = 5;
+
+/// First header version whose 512-bit MACs use a 512-bit hash
+pub const NATIVE_MAC_VERSION: u32 = 3;
+
+/// First header version whose partition metadata records a hash of the original data
+pub const CONTENT_HASH_VERSION: u32 = 4;
+
+/// First header version written in the binary encoding rather than JSON
+pub const BINARY_HEADER_VERSION: u32 = 5;
+
+/// Label set for a header version
+pub fn for_version(version: u32) -> Result<&'static Domains> {
+    match version {
+        1..=5 => Ok(&V1),
+        _ => Err(HypercubeError::InvalidHeader(format!(
+            "Unsupported format version {} (this build reads 1-{})",
+            version, LATEST_VERSION
+        ))),
+    }
+}
+
+/// Keyed verifier replacing the magic of a magicless container
+pub const CONTAINER_VERIFY: &str = "hypercube_container_verify_v1";
+
+/// Index sidecar key derivation and AEAD associated data
+pub const INDEX_KEY: &str = "hypercube_index_key_v1";
+pub const INDEX_AAD: &[u8] = b"hypercube_vhcidx";
+
+/// Deniable envelope key derivation and AEAD associated data
+pub const ENVELOPE_KEY: &str = "hypercube_envelope_key_v1";
+pub const ENVELOPE_AAD: &[u8] = b"hypercube_envelope";
+
+/// Prefix of the message an Ed25519 container signature covers
+pub const SIGNATURE: &[u8] = b"hypercube_signature_v1";
+
+/// Append-only journal chain hash (BLAKE3 context)
+pub const JOURNAL: &str = "hypercube_journal_v1";
+
+/// Partition manifest MAC key derivation (BLAKE3 context)
+pub const MANIFEST: &str = "hypercube_manifest_v1";
+
+/// Attempt log entry names, hashed from container ids (BLAKE3 context)
+pub const ATTEMPT_LOG: &str = "hypercube_attempt_log_v1";
+
+/// Key file secret derivation (BLAKE3 context)
+pub const KEYFILE: &str = "hypercube_keyfile_v1";
+
+/// KMS secret derivation (BLAKE3 context)
+pub const KMS: &str = "hypercube_kms_v1";
+
+/// Message a KMS key MACs or signs to produce key material
+pub const KMS_MESSAGE: &str = "hypercube_kms_message_v1";
+
+/// AEAD associated data of in-memory `SecureCache` entries
+pub const RUNTIME_CACHE_AAD: &[u8] = b"hypercube_runtime_cache";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_matrix() {
+        assert_eq!(for_version(1).unwrap(), &V1);
+        assert_eq!(for_version(LATEST_VERSION).unwrap(), &V1);
+        assert!(for_version(0).is_err());
+        assert!(for_version(LATEST_VERSION + 1).is_err());
+    }
+}
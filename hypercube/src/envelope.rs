@@ -0,0 +1,217 @@
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::file_mode::{write_file, write_private_file};
+use crate::kdf::Kdf;
+use crate::vhc::{replace_file, scratch_dir};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{rngs::OsRng, RngCore};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Smallest envelope size; larger envelopes double until the container fits
+pub const MIN_BUCKET_SIZE: usize = 64 * 1024;
+
+/// Envelope field sizes in bytes
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const LENGTH_SIZE: usize = 8;
+
+/// Bytes an envelope adds around the container
+pub const ENVELOPE_OVERHEAD: usize = SALT_SIZE + NONCE_SIZE + LENGTH_SIZE + TAG_SIZE;
+
+/// Standard size of the envelope holding a container of `len` bytes
+pub fn bucket_size(len: usize) -> usize {
+    (len + ENVELOPE_OVERHEAD)
+        .max(MIN_BUCKET_SIZE)
+        .next_power_of_two()
+}
+
+/// Wrap a whole container in a deniable envelope
+/// Layout: [salt: 16][nonce: 12][AEAD(container_len: 8 || container || zero padding)]
+/// Every byte is salt, nonce or ciphertext, and the total is a bucket size,
+/// so without the passphrase the output is a random file of a standard size
+/// The key is the passphrase stretched with Argon2id at the container default's cost
+/// (`Kdf::argon2id`); the envelope has nowhere to record other parameters
+pub fn seal_envelope(container: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let size = bucket_size(container.len());
+    let mut plaintext = Vec::with_capacity(size - SALT_SIZE - NONCE_SIZE - TAG_SIZE);
+    plaintext.extend_from_slice(&(container.len() as u64).to_le_bytes());
+    plaintext.extend_from_slice(container);
+    plaintext.resize(size - SALT_SIZE - NONCE_SIZE - TAG_SIZE, 0);
+
+    let mut salt = [0u8; SALT_SIZE];
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = envelope_cipher(passphrase, &salt)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: domains::ENVELOPE_AAD,
+            },
+        )
+        .map_err(|_| HypercubeError::IntegrityError("Envelope encryption failed".into()))?;
+
+    let mut envelope = Vec::with_capacity(size);
+    envelope.extend_from_slice(&salt);
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Recover the container from an envelope
+pub fn open_envelope(envelope: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if envelope.len() < ENVELOPE_OVERHEAD {
+        return Err(HypercubeError::InvalidFormat("Envelope too short".into()));
+    }
+    let (salt, rest) = envelope.split_at(SALT_SIZE);
+    let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+
+    let payload = Payload {
+        msg: ciphertext,
+        aad: domains::ENVELOPE_AAD,
+    };
+    let plaintext = envelope_cipher(passphrase, salt)?
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map(Zeroizing::new)
+        .map_err(|_| {
+            HypercubeError::IntegrityError(
+                "Envelope passphrase does not match (or not an envelope)".into(),
+            )
+        })?;
+
+    let len = usize::try_from(u64::from_le_bytes(
+        plaintext[..LENGTH_SIZE].try_into().unwrap(),
+    ))
+    .ok()
+    .filter(|&len| len <= plaintext.len() - LENGTH_SIZE)
+    .ok_or_else(|| HypercubeError::InvalidFormat("Envelope length out of range".into()))?;
+    Ok(plaintext[LENGTH_SIZE..LENGTH_SIZE + len].to_vec())
+}
+
+/// Run `op` on the plain container inside the envelope at `path`
+/// The container is unpacked to a scratch file (absent if `path` does not exist) in an
+/// owner-only directory beside `path` (see `scratch_dir`), removed afterwards. With `reseal`, the scratch
+/// file is wrapped again and replaces `path` (see `replace_file`).
+pub fn with_envelope<T>(
+    path: &Path,
+    passphrase: &str,
+    reseal: bool,
+    op: impl FnOnce(&Path) -> Result<T>,
+) -> Result<T> {
+    let scratch_dir = scratch_dir(path)?;
+    let scratch = scratch_dir.path().join("container.vhc");
+    if path.exists() {
+        let container = open_envelope(&std::fs::read(path)?, passphrase)?;
+        write_private_file(&scratch, &container)?;
+    }
+
+    let result = op(&scratch)?;
+    if reseal {
+        let envelope = seal_envelope(&std::fs::read(&scratch)?, passphrase)?;
+        replace_file(path, |temp| write_file(temp, &envelope))?;
+    }
+    Ok(result)
+}
+
+/// Temporary container path, removed on drop
+pub struct ScratchFile(pub PathBuf);
+
+impl ScratchFile {
+    pub fn new() -> Self {
+        let mut name = [0u8; 8];
+        OsRng.fill_bytes(&mut name);
+        Self(std::env::temp_dir().join(format!("hypercube-{}.vhc", hex::encode(name))))
+    }
+}
+
+impl Default for ScratchFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn envelope_cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let stretched = Zeroizing::new(Kdf::argon2id().derive(passphrase.as_bytes(), salt)?);
+    let key = Zeroizing::new(blake3::derive_key(domains::ENVELOPE_KEY, &stretched[..]));
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key[..])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_bucket_sizes() {
+        assert_eq!(bucket_size(0), MIN_BUCKET_SIZE);
+        assert_eq!(
+            bucket_size(MIN_BUCKET_SIZE - ENVELOPE_OVERHEAD),
+            MIN_BUCKET_SIZE
+        );
+        assert_eq!(
+            bucket_size(MIN_BUCKET_SIZE - ENVELOPE_OVERHEAD + 1),
+            2 * MIN_BUCKET_SIZE
+        );
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let container = b"VHC\x01 not really a container".to_vec();
+        let envelope = seal_envelope(&container, "outer").unwrap();
+        assert_eq!(envelope.len(), MIN_BUCKET_SIZE);
+        assert_eq!(open_envelope(&envelope, "outer").unwrap(), container);
+        assert!(open_envelope(&envelope, "guess").is_err());
+
+        // Fresh salt and nonce every time
+        assert_ne!(seal_envelope(&container, "outer").unwrap(), envelope);
+    }
+
+    #[test]
+    fn test_tampered_envelope_rejected() {
+        let mut envelope = seal_envelope(b"payload", "outer").unwrap();
+        envelope[MIN_BUCKET_SIZE / 2] ^= 1;
+        assert!(open_envelope(&envelope, "outer").is_err());
+        assert!(open_envelope(&envelope[..ENVELOPE_OVERHEAD - 1], "outer").is_err());
+    }
+
+    #[test]
+    fn test_with_envelope_reseals() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("noise.bin");
+
+        with_envelope(&path, "outer", true, |inner| {
+            assert!(!inner.exists());
+            // Unpacked beside the envelope, in a directory only the owner can enter
+            let scratch_dir = inner.parent().unwrap();
+            assert_eq!(scratch_dir.parent(), Some(dir.path()));
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(scratch_dir)?.permissions().mode();
+                assert_eq!(mode & 0o777, 0o700);
+            }
+            Ok(std::fs::write(inner, b"first")?)
+        })
+        .unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        let contents =
+            with_envelope(&path, "outer", false, |inner| Ok(std::fs::read(inner)?)).unwrap();
+        assert_eq!(contents, b"first");
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            MIN_BUCKET_SIZE as u64
+        );
+        assert!(with_envelope(&path, "guess", false, |_| Ok(())).is_err());
+    }
+}
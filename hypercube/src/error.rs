@@ -20,6 +20,9 @@ pub enum HypercubeError {
     #[error("Invalid dimension: {0}. Must be a multiple of 8 (8, 16, 24, 32, ...)")]
     InvalidDimension(usize),
 
+    #[error("Invalid blocks per partition: {0}. Must be at least 8 and divisible by 8")]
+    InvalidBlocksPerPartition(usize),
+
     #[error("Invalid MAC bits: {0}. Must be 128, 256, or 512")]
     InvalidMacBits(usize),
 
@@ -41,6 +44,9 @@ pub enum HypercubeError {
     #[error("Invalid cube id: {0}")]
     InvalidCube(usize),
 
+    #[error("Unknown cube preset: {0}")]
+    UnknownPreset(String),
+
     #[error("MAC verification failed for partition {0}")]
     MacVerificationFailed(usize),
 
@@ -56,11 +62,64 @@ pub enum HypercubeError {
     #[error("Unsupported algorithm: {0}")]
     UnsupportedAlgorithm(String),
 
+    #[error("This container requires {0}, which this build lacks (see `hypercube capabilities`)")]
+    MissingCapability(String),
+
     #[error("Data integrity error: {0}")]
     IntegrityError(String),
 
+    /// The secret opens nothing here: no block's MAC checks out under it
+    #[error("No blocks authenticated with this secret")]
+    NoAuthenticatedBlocks,
+
+    #[error(
+        "Partition incomplete: {matched} of {expected} blocks authenticated{}",
+        describe_holes(.gaps, .duplicates)
+    )]
+    IncompletePartition {
+        /// Blocks the secret authenticated
+        matched: usize,
+        /// Blocks a partition of this container has
+        expected: usize,
+        /// Runs of missing blocks as (index of the first, length), from the first block
+        gaps: Vec<(u128, u128)>,
+        /// Indices held by more than one block
+        duplicates: Vec<u128>,
+    },
+
+    #[error("Container is append-only: {0}")]
+    AppendOnly(String),
+
+    #[error("Container is locked against modification")]
+    Locked,
+
+    #[error("Container is open read-only: {0}")]
+    ReadOnly(String),
+
+    #[error("Container must stay full: write would leave {blocks} of {capacity} blocks")]
+    NotFull { blocks: usize, capacity: usize },
+
     #[error("Secret required")]
     SecretRequired,
+
+    #[error("Too many failed attempts on this container ({failures}); try again in {retry_secs} s")]
+    AttemptBackoff { failures: u32, retry_secs: u64 },
+
+    #[error("No such command: {0} (and no hypercube-{0} plugin on PATH)")]
+    UnknownCommand(String),
 }
 
 pub type Result<T> = std::result::Result<T, HypercubeError>;
+
+/// `; missing 2 blocks from block 5; block 3 repeated`, or nothing without holes
+fn describe_holes(gaps: &[(u128, u128)], duplicates: &[u128]) -> String {
+    let mut text = String::new();
+    for &(start, length) in gaps {
+        let blocks = if length == 1 { "block" } else { "blocks" };
+        text.push_str(&format!("; missing {} {} from block {}", length, blocks, start));
+    }
+    for index in duplicates {
+        text.push_str(&format!("; block {} repeated", index));
+    }
+    text
+}
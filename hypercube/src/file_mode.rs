@@ -0,0 +1,150 @@
+//! Permissions for the files Hypercube creates.
+//!
+//! Files are opened through `OpenOptions` with their mode already set, so none is ever
+//! readable by others while it is being written. Decrypted output (extracted data and
+//! archives, scratch copies of unwrapped containers) defaults to owner-only; containers
+//! and their sidecars hold only ciphertext and default to what the umask allows.
+//!
+//! The setting is process-wide, like the thread count: call `set_file_mode` once at
+//! start-up to give every file created from then on that mode instead. The umask applies
+//! to new files only. An existing file that is overwritten is set to the mode too, so old
+//! output cannot stay readable by others, except containers and sidecars when no mode is
+//! set: those keep their permissions, and rewrites through `vhc::replace_file` copy them
+//! to the replacement.
+
+use crate::error::{HypercubeError, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Mode for decrypted output and other secret material when none is set
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Mode for containers when none is set, before the umask
+pub const DEFAULT_FILE_MODE: u32 = 0o666;
+
+/// Stands for no mode set; real modes never exceed 0o777
+const UNSET: u32 = u32::MAX;
+
+static FILE_MODE: AtomicU32 = AtomicU32::new(UNSET);
+
+/// Create every file from now on with `mode` (None restores the defaults), process-wide
+pub fn set_file_mode(mode: Option<u32>) {
+    FILE_MODE.store(mode.unwrap_or(UNSET), Ordering::Relaxed);
+}
+
+/// The current setting
+pub fn file_mode() -> Option<u32> {
+    match FILE_MODE.load(Ordering::Relaxed) {
+        UNSET => None,
+        mode => Some(mode),
+    }
+}
+
+/// Parse an octal mode such as `0600`, `600` or `0o600`
+pub fn parse_file_mode(s: &str) -> Result<u32> {
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o777 && !digits.is_empty() => Ok(mode),
+        _ => Err(HypercubeError::InvalidFormat(format!(
+            "File mode {} (expected octal permissions, 0 to 0777, e.g. 0600)",
+            s
+        ))),
+    }
+}
+
+/// Create or truncate a container or sidecar: the set mode, else the umask default
+/// An existing file is set to the mode only if one is set
+pub fn create_file(path: &Path) -> Result<File> {
+    match file_mode() {
+        Some(mode) => create_with_mode(path, mode),
+        None => open_with_mode(path, DEFAULT_FILE_MODE, false),
+    }
+}
+
+/// Create or truncate a file for decrypted data: the set mode, else owner-only
+pub fn create_private_file(path: &Path) -> Result<File> {
+    create_with_mode(path, file_mode().unwrap_or(PRIVATE_FILE_MODE))
+}
+
+/// `std::fs::write` through `create_file`
+pub fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    Ok(create_file(path)?.write_all(data)?)
+}
+
+/// `std::fs::write` through `create_private_file`
+pub fn write_private_file(path: &Path, data: &[u8]) -> Result<()> {
+    Ok(create_private_file(path)?.write_all(data)?)
+}
+
+/// Create or truncate a file with exactly `mode` (before the umask for a new file),
+/// whatever is set. The mode is ignored on platforms without Unix permissions
+pub fn create_with_mode(path: &Path, mode: u32) -> Result<File> {
+    open_with_mode(path, mode, true)
+}
+
+/// Create `path` with `mode`, or truncate it and, if `restrict`, set it to `mode`
+fn open_with_mode(path: &Path, mode: u32, restrict: bool) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+    match options.open(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        opened => return Ok(opened?),
+    }
+    let file = options.create_new(false).truncate(true).open(path)?;
+    #[cfg(unix)]
+    if restrict {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = restrict;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_file_mode() {
+        assert_eq!(parse_file_mode("0600").unwrap(), 0o600);
+        assert_eq!(parse_file_mode("644").unwrap(), 0o644);
+        assert_eq!(parse_file_mode("0o640").unwrap(), 0o640);
+        assert_eq!(parse_file_mode("0").unwrap(), 0);
+        for bad in ["", "0o", "0800", "rw-------", "1777", "-600"] {
+            assert!(parse_file_mode(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_with_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("secret.txt");
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        // The umask can only take bits away from owner-only
+        create_with_mode(&path, PRIVATE_FILE_MODE)
+            .unwrap()
+            .write_all(b"plaintext")
+            .unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // An existing file is truncated and loses what the mode does not grant
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        create_with_mode(&path, PRIVATE_FILE_MODE).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+        assert_eq!(mode(&path), 0o600);
+
+        // A container with no mode set keeps the permissions it has
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        open_with_mode(&path, DEFAULT_FILE_MODE, false).unwrap();
+        assert_eq!(mode(&path), 0o640);
+    }
+}
@@ -0,0 +1,85 @@
+//! Arithmetic in GF(2^8) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+//!
+//! Shared by secret sharing and erasure coding. There are no lookup tables and no
+//! branches on the operands, so timing does not depend on secret bytes.
+
+/// Product of two field elements
+pub fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse as a^254; zero maps to zero
+pub fn inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    for bit in 0..8 {
+        if 254u8 >> bit & 1 == 1 {
+            result = mul(result, power);
+        }
+        power = mul(power, power);
+    }
+    result
+}
+
+/// Inverse of a square matrix by Gauss-Jordan elimination, None if it is singular
+pub fn invert(mut matrix: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<u8>> = (0..n)
+        .map(|row| (0..n).map(|col| u8::from(row == col)).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n).find(|&row| matrix[row][col] != 0)?;
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+        let scale = inv(matrix[col][col]);
+        for value in matrix[col].iter_mut().chain(inverse[col].iter_mut()) {
+            *value = mul(*value, scale);
+        }
+        for row in (0..n).filter(|&row| row != col) {
+            let factor = matrix[row][col];
+            for k in 0..n {
+                matrix[row][k] ^= mul(factor, matrix[col][k]);
+                inverse[row][k] ^= mul(factor, inverse[col][k]);
+            }
+        }
+    }
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_arithmetic() {
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0x57, 0x13), 0xfe);
+        assert_eq!(inv(0), 0);
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inv(a)), 1, "{}", a);
+        }
+    }
+
+    #[test]
+    fn test_invert_matrix() {
+        let matrix = vec![vec![2, 3, 1], vec![1, 1, 1], vec![7, 0, 5]];
+        let inverse = invert(matrix.clone()).unwrap();
+        let product: Vec<Vec<u8>> = matrix
+            .iter()
+            .map(|row| {
+                (0..3)
+                    .map(|j| (0..3).fold(0, |acc, k| acc ^ mul(row[k], inverse[k][j])))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(product, [[1, 0, 0], [0, 1, 0], [0, 0, 1]]);
+        assert!(invert(vec![vec![1, 2], vec![1, 2]]).is_none());
+    }
+}
@@ -1,4 +1,9 @@
+use crate::domains::{self, Domains};
 use crate::error::{HypercubeError, Result};
+use crate::hint::BloomHint;
+use crate::kdf::{Kdf, KdfParams, KDF_SALT_SIZE};
+use crate::pipeline::{aont_for, AontTransform, ErasureCode, WideTag, SEQUENCE_NONCE_SIZE};
+use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 
 /// Compression algorithm options
@@ -12,6 +17,18 @@ pub enum Compression {
     None,
 }
 
+impl Compression {
+    /// Name in the header, and of the built-in codec in the stage registry
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Lz4 => "lz4",
+            Self::Brotli => "brotli",
+            Self::None => "none",
+        }
+    }
+}
+
 impl std::str::FromStr for Compression {
     type Err = HypercubeError;
     fn from_str(s: &str) -> Result<Self> {
@@ -37,6 +54,16 @@ pub enum Aont {
     Oaep,
 }
 
+impl Aont {
+    /// Name in the header, and of the built-in transform in the stage registry
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rivest => "rivest",
+            Self::Oaep => "oaep",
+        }
+    }
+}
+
 impl std::str::FromStr for Aont {
     type Err = HypercubeError;
     fn from_str(s: &str) -> Result<Self> {
@@ -58,6 +85,17 @@ pub enum HashAlgorithm {
     Sha256,
 }
 
+impl HashAlgorithm {
+    /// Name in the header, and of the built-in MAC in the stage registry
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha3 => "sha3",
+            Self::Blake3 => "blake3",
+            Self::Sha256 => "sha256",
+        }
+    }
+}
+
 impl std::str::FromStr for HashAlgorithm {
     type Err = HypercubeError;
     fn from_str(s: &str) -> Result<Self> {
@@ -70,40 +108,412 @@ impl std::str::FromStr for HashAlgorithm {
     }
 }
 
+/// Shuffle layers applied to a partition
+/// Blocks are always reshuffled across the container; `Fragment` also permutes
+/// fragments within the partition under a key derived from its secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Shuffle {
+    #[default]
+    Block,
+    Fragment,
+}
+
+impl Shuffle {
+    fn is_block(&self) -> bool {
+        *self == Self::Block
+    }
+}
+
+impl std::str::FromStr for Shuffle {
+    type Err = HypercubeError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "block" => Ok(Self::Block),
+            "fragment" => Ok(Self::Fragment),
+            _ => Err(HypercubeError::UnsupportedAlgorithm(format!("shuffle: {}", s))),
+        }
+    }
+}
+
+/// When appending blocks reshuffles the whole container block table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReshufflePolicy {
+    /// Every append randomly reorders all blocks
+    #[default]
+    Always,
+    /// New blocks take random slots, written in place; only the blocks they displace move
+    Never,
+    /// Like `Never`, but every Nth append reorders everything
+    Periodic(u32),
+}
+
+impl ReshufflePolicy {
+    fn is_always(&self) -> bool {
+        *self == Self::Always
+    }
+
+    /// Whether an append should reorder all blocks, given the appends since the last one
+    pub fn reshuffles(&self, pending_appends: u32) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Periodic(every) => pending_appends + 1 >= *every,
+        }
+    }
+}
+
+impl std::fmt::Display for ReshufflePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Always => write!(f, "always"),
+            Self::Never => write!(f, "never"),
+            Self::Periodic(every) => write!(f, "periodic:{}", every),
+        }
+    }
+}
+
+impl std::str::FromStr for ReshufflePolicy {
+    type Err = HypercubeError;
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => s
+                .strip_prefix("periodic:")
+                .and_then(|every| every.parse().ok())
+                .filter(|&every| every > 0)
+                .map(Self::Periodic)
+                .ok_or_else(|| {
+                    HypercubeError::UnsupportedAlgorithm(format!(
+                        "reshuffle: {} (expected always, never or periodic:N)",
+                        s
+                    ))
+                }),
+        }
+    }
+}
+
+/// Slows down repeated guessing at a container's secrets from this machine, as honored by
+/// the CLI's extraction commands; library callers and other tools are not bound by it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttemptPolicy {
+    /// Every key derivation takes at least this long; a faster KDF is padded out
+    pub min_kdf_ms: u32,
+    /// Wait after the first failed attempt, doubling with each further failure
+    pub backoff_ms: u32,
+}
+
+impl AttemptPolicy {
+    /// Longest wait between attempts, however many have failed
+    pub const MAX_BACKOFF_MS: u64 = 24 * 60 * 60 * 1000;
+
+    /// How long to wait after `failures` consecutive failed attempts
+    pub fn backoff(&self, failures: u32) -> std::time::Duration {
+        let ms = match failures {
+            0 => 0,
+            n => u64::from(self.backoff_ms).saturating_mul(1 << (n - 1).min(32)),
+        };
+        std::time::Duration::from_millis(ms.min(Self::MAX_BACKOFF_MS))
+    }
+}
+
+impl std::fmt::Display for AttemptPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.min_kdf_ms, self.backoff_ms)
+    }
+}
+
+impl std::str::FromStr for AttemptPolicy {
+    type Err = HypercubeError;
+    fn from_str(s: &str) -> Result<Self> {
+        s.split_once(':')
+            .and_then(|(min_kdf_ms, backoff_ms)| {
+                Some(Self {
+                    min_kdf_ms: min_kdf_ms.parse().ok()?,
+                    backoff_ms: backoff_ms.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| {
+                HypercubeError::InvalidFormat(format!(
+                    "attempt policy: {} (expected MIN_KDF_MS:BACKOFF_MS)",
+                    s
+                ))
+            })
+    }
+}
+
+/// A step of the partition pipeline, as listed in `PipelineOrder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineStage {
+    Compress,
+    Segment,
+    Fragment,
+    Whiten,
+    Aont,
+    /// The fragment shuffle; passes fragments through unchanged under `Shuffle::Block`
+    Shuffle,
+    Sequence,
+    Mac,
+}
+
+impl PipelineStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Compress => "compress",
+            Self::Segment => "segment",
+            Self::Fragment => "fragment",
+            Self::Whiten => "whiten",
+            Self::Aont => "aont",
+            Self::Shuffle => "shuffle",
+            Self::Sequence => "sequence",
+            Self::Mac => "mac",
+        }
+    }
+
+    /// Stages that work on the fragments and may be listed in any order
+    fn is_fragment_transform(&self) -> bool {
+        matches!(self, Self::Whiten | Self::Aont | Self::Shuffle)
+    }
+}
+
+impl std::str::FromStr for PipelineStage {
+    type Err = HypercubeError;
+    fn from_str(s: &str) -> Result<Self> {
+        PipelineOrder::STANDARD
+            .into_iter()
+            .find(|stage| stage.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| HypercubeError::UnsupportedAlgorithm(format!("pipeline stage: {}", s)))
+    }
+}
+
+/// The order a partition's stages run in when it is sealed; opening runs them backwards
+/// Compress, segment and fragment always come first and sequence and MAC last. The
+/// fragment transforms between them (whiten, aont, shuffle) may come in any order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineOrder(pub Vec<PipelineStage>);
+
+impl PipelineOrder {
+    /// The order every container used before orders were recorded
+    pub const STANDARD: [PipelineStage; 8] = [
+        PipelineStage::Compress,
+        PipelineStage::Segment,
+        PipelineStage::Fragment,
+        PipelineStage::Whiten,
+        PipelineStage::Aont,
+        PipelineStage::Shuffle,
+        PipelineStage::Sequence,
+        PipelineStage::Mac,
+    ];
+
+    pub fn is_standard(&self) -> bool {
+        self.0 == Self::STANDARD
+    }
+
+    /// The fragment transforms in the order they are applied
+    pub fn fragment_transforms(&self) -> impl DoubleEndedIterator<Item = PipelineStage> + '_ {
+        self.0.iter().copied().filter(PipelineStage::is_fragment_transform)
+    }
+
+    /// Refuse orders that drop, repeat or move a fixed stage
+    pub fn validate(&self) -> Result<()> {
+        let mut transforms: Vec<_> = self.fragment_transforms().collect();
+        transforms.sort_by_key(|stage| *stage as u8);
+        let ok = self.0.len() == Self::STANDARD.len()
+            && self.0[..3] == Self::STANDARD[..3]
+            && self.0[6..] == Self::STANDARD[6..]
+            && transforms == Self::STANDARD[3..6];
+        if ok {
+            Ok(())
+        } else {
+            Err(HypercubeError::InvalidHeader(format!(
+                "pipeline order {} (whiten, aont and shuffle go between fragment and sequence)",
+                self
+            )))
+        }
+    }
+}
+
+impl Default for PipelineOrder {
+    fn default() -> Self {
+        Self(Self::STANDARD.to_vec())
+    }
+}
+
+impl std::fmt::Display for PipelineOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<_> = self.0.iter().map(PipelineStage::as_str).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+impl std::str::FromStr for PipelineOrder {
+    type Err = HypercubeError;
+    fn from_str(s: &str) -> Result<Self> {
+        let order = Self(s.split(',').map(str::parse).collect::<Result<_>>()?);
+        order.validate()?;
+        Ok(order)
+    }
+}
+
+/// Stage implementations from the stage registry (`pipeline::registry`), by name
+/// A stage left unset uses the built-in chosen by the header's own field
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageIds {
+    /// Replaces `compression`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    /// Whitens fragments before the AONT; none when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub whitener: Option<String>,
+    /// Replaces `aont`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aont: Option<String>,
+    /// Replaces `hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+impl StageIds {
+    /// Every stage is a built-in chosen by the header's own fields
+    pub fn is_builtin(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Random identity given to a container when it is created, shown as a UUID
+/// It names the container to fleet tooling, logs and secret helpers whatever its path;
+/// copies made by `replicate`, `rekey` or a backup share it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct ContainerId(pub [u8; 16]);
+
+impl ContainerId {
+    /// A random (version 4) UUID
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Self(bytes)
+    }
+
+    /// First eight hex digits, enough to tell a handful of containers apart
+    pub fn short(&self) -> String {
+        hex::encode(&self.0[..4])
+    }
+}
+
+impl std::fmt::Display for ContainerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hex = hex::encode(self.0);
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl std::str::FromStr for ContainerId {
+    type Err = HypercubeError;
+    fn from_str(s: &str) -> Result<Self> {
+        let digits: String = s.chars().filter(|&c| c != '-').collect();
+        let groups: Vec<usize> = s.split('-').map(str::len).collect();
+        let mut bytes = [0u8; 16];
+        if groups != [8, 4, 4, 4, 12] || hex::decode_to_slice(&digits, &mut bytes).is_err() {
+            return Err(HypercubeError::InvalidHeader(format!(
+                "Container id {} is not a UUID",
+                s
+            )));
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl From<ContainerId> for String {
+    fn from(id: ContainerId) -> Self {
+        id.to_string()
+    }
+}
+
+impl TryFrom<String> for ContainerId {
+    type Error = HypercubeError;
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
 /// Partition metadata - stored at the START of compressed data
-/// Layout: [compressed_size: 8][original_size: 8][compressed data...]
+/// Layout: [compressed_size: 8][original_size: 8][content_hash: 32][compressed data...]
+/// (the hash from format version 4 on)
 #[derive(Debug, Clone)]
 pub struct PartitionMeta {
     /// Compressed size in bytes (excluding this metadata header)
     pub compressed_size: u64,
     /// Original (uncompressed) size in bytes
     pub original_size: u64,
+    /// BLAKE3 of the original data; None in containers older than version 4
+    pub content_hash: Option<[u8; 32]>,
 }
 
 impl PartitionMeta {
-    /// Metadata size: 8 bytes (compressed) + 8 bytes (original) = 16 bytes
+    /// Metadata size without the hash: 8 bytes (compressed) + 8 bytes (original) = 16 bytes
     pub const SIZE: usize = 16;
+    /// Metadata size with the hash, as new containers write it
+    pub const HASHED_SIZE: usize = Self::SIZE + 32;
 
     /// Serialize metadata to bytes
-    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
-        let mut buf = [0u8; Self::SIZE];
-        buf[0..8].copy_from_slice(&self.compressed_size.to_le_bytes());
-        buf[8..16].copy_from_slice(&self.original_size.to_le_bytes());
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::HASHED_SIZE);
+        buf.extend_from_slice(&self.compressed_size.to_le_bytes());
+        buf.extend_from_slice(&self.original_size.to_le_bytes());
+        if let Some(hash) = &self.content_hash {
+            buf.extend_from_slice(hash);
+        }
         buf
     }
 
-    /// Deserialize metadata from bytes
-    pub fn from_bytes(data: &[u8]) -> Result<Self> {
-        if data.len() < Self::SIZE {
+    /// Deserialize metadata from bytes, with a content hash when `hashed`
+    pub fn from_bytes(data: &[u8], hashed: bool) -> Result<Self> {
+        let size = if hashed { Self::HASHED_SIZE } else { Self::SIZE };
+        if data.len() < size {
             return Err(HypercubeError::InvalidFormat("Metadata too short".into()));
         }
         let compressed_size = u64::from_le_bytes(data[0..8].try_into().unwrap());
         let original_size = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let content_hash = hashed.then(|| data[Self::SIZE..size].try_into().unwrap());
         Ok(Self {
             compressed_size,
             original_size,
+            content_hash,
         })
     }
+
+    /// Check decompressed data against the recorded size and, if there is one, hash
+    pub fn check(&self, data: &[u8]) -> Result<()> {
+        if data.len() as u64 != self.original_size {
+            return Err(HypercubeError::IntegrityError(
+                "Original size mismatch after decompression".into(),
+            ));
+        }
+        // blake3::Hash compares in constant time
+        if let Some(hash) = self.content_hash {
+            if blake3::hash(data) != blake3::Hash::from(hash) {
+                return Err(HypercubeError::IntegrityError(
+                    "Content hash mismatch after decompression".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// VHC file header - plaintext, describes global parameters only
@@ -112,6 +522,9 @@ impl PartitionMeta {
 pub struct VhcHeader {
     /// Format version
     pub version: u32,
+    /// Identity given at creation; None for containers made before ids were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<ContainerId>,
     /// Cube identifier (maps to partition/block layout)
     pub cube_id: usize,
     /// Number of partitions (dimension along one axis)
@@ -130,6 +543,124 @@ pub struct VhcHeader {
     pub hash: HashAlgorithm,
     /// Fragment size in bytes
     pub fragment_size: usize,
+    /// Optional keyed bloom filters, one per hinted partition
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<BloomHint>,
+    /// Maintain a Merkle tree over the blocks in the v2 trailer
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub merkle: bool,
+    /// Shuffle layers applied when partitions are created
+    #[serde(default, skip_serializing_if = "Shuffle::is_block")]
+    pub shuffle: Shuffle,
+    /// When appends reorder the whole block table
+    #[serde(default, skip_serializing_if = "ReshufflePolicy::is_always")]
+    pub reshuffle: ReshufflePolicy,
+    /// Appends since the last full reshuffle (for `ReshufflePolicy::Periodic`)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub pending_appends: u32,
+    /// Hardening applied to every partition secret; raw secrets when None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<KdfParams>,
+    /// Blocks are only ever appended, each write is journaled in the trailer
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub append_only: bool,
+    /// Set by `hypercube lock`; no further blocks may be added or replaced
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// Every write must leave the cube at full capacity (topped up with chaff)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub always_full: bool,
+    /// Payloads are compressed in independent frames of this many bytes, for seeking;
+    /// one stream when 0
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub frame_size: u32,
+    /// Partitions are split into content-defined chunks, each sealed on its own,
+    /// so `update` only rewrites the chunks that changed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub chunked: bool,
+    /// Chunks are sealed under keys derived from their content and a container-wide
+    /// dedup secret, so identical chunks in different partitions are stored once
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dedup: bool,
+    /// Sequence bases are derived with HKDF from the partition key and a per-write nonce
+    /// (stored after the partition metadata) rather than drawn from the thread RNG
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub derived_sequence: bool,
+    /// Registered stage implementations used instead of the built-ins
+    #[serde(default, skip_serializing_if = "StageIds::is_builtin")]
+    pub stages: StageIds,
+    /// Order of the partition pipeline's stages; omitted when standard
+    #[serde(default, skip_serializing_if = "PipelineOrder::is_standard")]
+    pub pipeline_order: PipelineOrder,
+    /// Reed–Solomon parity added to every partition's transformed blocks, so a partition
+    /// survives losing a few of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub erasure: Option<ErasureCode>,
+    /// Mutation counter, bumped by every write; new blocks carry it in their sequence
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub generation: u32,
+    /// Minimum KDF time and backoff after failed extractions, honored by the CLI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempt_policy: Option<AttemptPolicy>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Bytes of a binary header before its stage ids and hints
+const BINARY_HEADER_FIXED_SIZE: usize =
+    4 + 16 + 5 * 4 + 2 + 6 + 4 * 4 + 2 + 1 + 3 * 4 + KDF_SALT_SIZE + PipelineOrder::STANDARD.len();
+
+/// Codes of the enum fields in a binary header: their positions in these lists
+const COMPRESSIONS: [Compression; 4] = [
+    Compression::Zstd,
+    Compression::Lz4,
+    Compression::Brotli,
+    Compression::None,
+];
+const AONTS: [Aont; 2] = [Aont::Rivest, Aont::Oaep];
+const HASHES: [HashAlgorithm; 3] = [
+    HashAlgorithm::Sha3,
+    HashAlgorithm::Blake3,
+    HashAlgorithm::Sha256,
+];
+const SHUFFLES: [Shuffle; 2] = [Shuffle::Block, Shuffle::Fragment];
+
+fn code_of<T: PartialEq>(all: &[T], value: &T) -> u8 {
+    all.iter().position(|known| known == value).unwrap() as u8
+}
+
+fn from_code<T: Copy>(all: &[T], code: u8, what: &str) -> Result<T> {
+    all.get(usize::from(code))
+        .copied()
+        .ok_or_else(|| HypercubeError::MissingCapability(format!("{} code {}", what, code)))
+}
+
+/// Reads a binary header front to back
+struct HeaderReader<'a>(&'a [u8]);
+
+impl<'a> HeaderReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.0.len() < length {
+            return Err(HypercubeError::InvalidHeader("Truncated binary header".into()));
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
 }
 
 impl Default for VhcHeader {
@@ -139,7 +670,8 @@ impl Default for VhcHeader {
         let blocks_per_partition = 32;
         let block_size = 32;
         Self {
-            version: 1,
+            version: domains::LATEST_VERSION,
+            uuid: None,
             cube_id,
             dimension: partitions,
             blocks_per_partition,
@@ -149,6 +681,24 @@ impl Default for VhcHeader {
             aont: Aont::default(),
             hash: HashAlgorithm::default(),
             fragment_size: Self::calculate_fragment_size(block_size),
+            hints: Vec::new(),
+            merkle: false,
+            shuffle: Shuffle::default(),
+            reshuffle: ReshufflePolicy::default(),
+            pending_appends: 0,
+            kdf: None,
+            append_only: false,
+            locked: false,
+            always_full: false,
+            frame_size: 0,
+            chunked: false,
+            dedup: false,
+            derived_sequence: false,
+            stages: StageIds::default(),
+            pipeline_order: PipelineOrder::default(),
+            erasure: None,
+            generation: 0,
+            attempt_policy: None,
         }
     }
 }
@@ -164,35 +714,71 @@ impl VhcHeader {
         block_size: usize,
         mac_bits: usize,
     ) -> Result<Self> {
+        let header = Self {
+            version: domains::LATEST_VERSION,
+            uuid: Some(ContainerId::generate()),
+            cube_id,
+            dimension: partitions,
+            blocks_per_partition,
+            block_size,
+            mac_bits,
+            fragment_size: Self::calculate_fragment_size(block_size),
+            ..Default::default()
+        };
+        header.validate_geometry()?;
+        Ok(header)
+    }
+
+    /// Check the geometry of a new or parsed header
+    /// Sizes are capped at 32 bits so 32- and 64-bit builds accept the same headers
+    fn validate_geometry(&self) -> Result<()> {
         // Dimension must be a multiple of 8
-        if partitions < 8 || partitions % 8 != 0 {
-            return Err(HypercubeError::InvalidDimension(partitions));
+        if self.dimension < 8 || !self.dimension.is_multiple_of(8) {
+            return Err(HypercubeError::InvalidDimension(self.dimension));
         }
-        if blocks_per_partition < 8 || blocks_per_partition % 8 != 0 {
-            return Err(HypercubeError::InvalidDimension(blocks_per_partition));
+        if self.blocks_per_partition < 8 || !self.blocks_per_partition.is_multiple_of(8) {
+            return Err(HypercubeError::InvalidBlocksPerPartition(self.blocks_per_partition));
         }
         // Block size must be even, positive, and at least 32 bytes (for AONT key)
-        if block_size < 32 || block_size % 2 != 0 {
-            return Err(HypercubeError::InvalidBlockSize(block_size));
+        if self.block_size < 32 || !self.block_size.is_multiple_of(2) {
+            return Err(HypercubeError::InvalidBlockSize(self.block_size));
         }
 
         // Validate MAC bits
-        if mac_bits != 128 && mac_bits != 256 && mac_bits != 512 {
-            return Err(HypercubeError::InvalidMacBits(mac_bits));
+        if self.mac_bits != 128 && self.mac_bits != 256 && self.mac_bits != 512 {
+            return Err(HypercubeError::InvalidMacBits(self.mac_bits));
         }
 
-        let fragment_size = Self::calculate_fragment_size(block_size);
-
-        Ok(Self {
-            version: 1,
-            cube_id,
-            dimension: partitions,
-            blocks_per_partition,
-            block_size,
-            mac_bits,
-            fragment_size,
-            ..Default::default()
-        })
+        if self.fragment_size == 0 || !self.block_size.is_multiple_of(self.fragment_size) {
+            return Err(HypercubeError::InvalidHeader(format!(
+                "Fragment size {} does not divide block size {}",
+                self.fragment_size, self.block_size
+            )));
+        }
+        // Stored blocks are capped too, so total_block_size cannot overflow a 32-bit usize,
+        // and a full cube's block region must be addressable as a u64 file offset
+        let stored_block = self.block_size as u64 + 16 + self.mac_bytes() as u64;
+        let sizes = [
+            self.cube_id as u64,
+            self.dimension as u64,
+            self.blocks_per_partition as u64,
+            stored_block,
+        ];
+        let full = self.cell_count().checked_mul(stored_block);
+        if full.is_none() || sizes.iter().any(|&size| size > u64::from(u32::MAX)) {
+            return Err(HypercubeError::InvalidHeader("Cube geometry too large".into()));
+        }
+        if let Some(code) = self.erasure {
+            ErasureCode::new(code.data, code.parity)?;
+            // Chunks are sealed on their own, each far shorter than a stripe
+            if self.chunked || code.data_blocks(self.blocks_per_partition) < 2 {
+                return Err(HypercubeError::InvalidHeader(format!(
+                    "Erasure code {}+{} does not fit {} blocks per partition",
+                    code.data, code.parity, self.blocks_per_partition
+                )));
+            }
+        }
+        Ok(())
     }
 
     /// Calculate fragment size for a given block size
@@ -209,20 +795,339 @@ impl VhcHeader {
         {
             frag_size *= 2;
         }
-        while frag_size > 1 && block_size % frag_size != 0 {
+        while frag_size > 1 && !block_size.is_multiple_of(frag_size) {
             frag_size /= 2;
         }
         frag_size
     }
 
-    /// Serialize header to JSON bytes
+    /// Change the block payload size, re-deriving the fragment size
+    pub fn set_block_size(&mut self, block_size: usize) -> Result<()> {
+        self.block_size = block_size;
+        self.fragment_size = Self::calculate_fragment_size(block_size);
+        self.validate_geometry()
+    }
+
+    /// Change the number of partitions (and the cube id, which follows it)
+    /// Existing blocks are unaffected: nothing they are keyed or laid out by changes
+    pub fn set_dimension(&mut self, dimension: usize) -> Result<()> {
+        self.dimension = dimension;
+        self.cube_id = dimension;
+        self.validate_geometry()
+    }
+
+    /// Change the erasure code, checking it fits the partition
+    pub fn set_erasure(&mut self, erasure: Option<ErasureCode>) -> Result<()> {
+        self.erasure = erasure;
+        self.validate_geometry()
+    }
+
+    /// Domain-separation labels for this header's format version
+    pub fn domains(&self) -> Result<&'static Domains> {
+        domains::for_version(self.version)
+    }
+
+    /// How this container's 512-bit MAC tags are computed
+    pub fn wide_tag(&self) -> WideTag {
+        if self.version >= domains::NATIVE_MAC_VERSION {
+            WideTag::Native
+        } else {
+            WideTag::Expanded
+        }
+    }
+
+    /// The key a partition secret stands for in this container
+    /// (stretched by the container KDF, if any)
+    pub fn partition_key(&self, secret: &[u8]) -> Result<Vec<u8>> {
+        match &self.kdf {
+            Some(kdf) => Ok(kdf.derive(secret)?.to_vec()),
+            None => Ok(secret.to_vec()),
+        }
+    }
+
+    /// Refuse an operation that would replace or reorder blocks of an append-only container
+    pub fn check_mutable(&self, operation: &str) -> Result<()> {
+        if self.append_only {
+            return Err(HypercubeError::AppendOnly(format!("cannot {}", operation)));
+        }
+        Ok(())
+    }
+
+    /// Refuse any write to a locked container
+    pub fn check_unlocked(&self) -> Result<()> {
+        if self.locked {
+            return Err(HypercubeError::Locked);
+        }
+        Ok(())
+    }
+
+    /// Serialize the header in the encoding of its version: JSON before
+    /// `domains::BINARY_HEADER_VERSION`, the binary layout from then on
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        if self.version >= domains::BINARY_HEADER_VERSION {
+            self.to_binary()
+        } else {
+            self.to_json()
+        }
+    }
+
+    /// Serialize header to JSON bytes
+    pub fn to_json(&self) -> Result<Vec<u8>> {
         Ok(serde_json::to_vec(self)?)
     }
 
-    /// Deserialize header from JSON bytes
+    /// Serialize header to the binary layout, which has no field names or other text
+    /// Layout (little-endian):
+    /// [version: 4][uuid: 16, zeros when none][cube_id: 4][dimension: 4]
+    /// [blocks_per_partition: 4][block_size: 4][fragment_size: 4][mac_bits: 2]
+    /// [compression: 1][aont: 1][hash: 1][shuffle: 1][flags: 1][reshuffle: 1][period: 4]
+    /// [pending_appends: 4][frame_size: 4][generation: 4][erasure data: 1][erasure parity: 1]
+    /// [kdf: 1][kdf costs: 3 x 4][kdf salt: 16][pipeline order: 8]
+    /// [4 x (stage id length: 1)(stage id)][hint count: 4][(hint length: 4)(hint) each]
+    /// [attempt policy, when set: (min_kdf_ms: 4)(backoff_ms: 4)]
+    pub fn to_binary(&self) -> Result<Vec<u8>> {
+        let field = |name: &str, value: usize| {
+            u32::try_from(value)
+                .map_err(|_| HypercubeError::InvalidHeader(format!("{} too large", name)))
+        };
+        let mut out = Vec::with_capacity(BINARY_HEADER_FIXED_SIZE);
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&self.uuid.map_or([0; 16], |id| id.0));
+        for (name, value) in [
+            ("cube_id", self.cube_id),
+            ("dimension", self.dimension),
+            ("blocks_per_partition", self.blocks_per_partition),
+            ("block_size", self.block_size),
+            ("fragment_size", self.fragment_size),
+        ] {
+            out.extend_from_slice(&field(name, value)?.to_le_bytes());
+        }
+        let mac_bits = u16::try_from(self.mac_bits)
+            .map_err(|_| HypercubeError::InvalidMacBits(self.mac_bits))?;
+        out.extend_from_slice(&mac_bits.to_le_bytes());
+        out.push(code_of(&COMPRESSIONS, &self.compression));
+        out.push(code_of(&AONTS, &self.aont));
+        out.push(code_of(&HASHES, &self.hash));
+        out.push(code_of(&SHUFFLES, &self.shuffle));
+        let flags = self.flags().iter().rev().fold(0u8, |bits, &set| bits << 1 | set as u8);
+        out.push(flags);
+        let (reshuffle, period) = match self.reshuffle {
+            ReshufflePolicy::Always => (0u8, 0),
+            ReshufflePolicy::Never => (1, 0),
+            ReshufflePolicy::Periodic(every) => (2, every),
+        };
+        out.push(reshuffle);
+        for value in [period, self.pending_appends, self.frame_size, self.generation] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        let erasure = self.erasure.map_or([0, 0], |code| [code.data, code.parity]);
+        out.extend_from_slice(&erasure);
+
+        let (kdf, costs) = match self.kdf.map(|params| params.kdf) {
+            None => (0u8, [0; 3]),
+            Some(Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            }) => (1, [memory_kib, iterations, parallelism]),
+            Some(Kdf::Scrypt { log_n, r, p }) => (2, [u32::from(log_n), r, p]),
+            Some(Kdf::Pbkdf2 { iterations }) => (3, [iterations, 0, 0]),
+        };
+        out.push(kdf);
+        for cost in costs {
+            out.extend_from_slice(&cost.to_le_bytes());
+        }
+        out.extend_from_slice(&self.kdf.map_or([0; KDF_SALT_SIZE], |params| params.salt));
+        self.pipeline_order.validate()?;
+        for stage in &self.pipeline_order.0 {
+            out.push(code_of(&PipelineOrder::STANDARD, stage));
+        }
+
+        let stages = &self.stages;
+        for id in [&stages.compression, &stages.whitener, &stages.aont, &stages.mac] {
+            let id = id.as_deref().unwrap_or_default();
+            let length = u8::try_from(id.len())
+                .map_err(|_| HypercubeError::InvalidHeader(format!("Stage id {} too long", id)))?;
+            out.push(length);
+            out.extend_from_slice(id.as_bytes());
+        }
+        out.extend_from_slice(&field("hint count", self.hints.len())?.to_le_bytes());
+        for hint in &self.hints {
+            out.extend_from_slice(&field("hint", hint.len())?.to_le_bytes());
+            out.extend_from_slice(hint.as_bytes());
+        }
+        if let Some(policy) = self.attempt_policy {
+            out.extend_from_slice(&policy.min_kdf_ms.to_le_bytes());
+            out.extend_from_slice(&policy.backoff_ms.to_le_bytes());
+        }
+        Ok(out)
+    }
+
+    /// Deserialize a binary header written by `to_binary`
+    fn from_binary(data: &[u8]) -> Result<Self> {
+        let mut reader = HeaderReader(data);
+        let version = reader.u32()?;
+        if version < domains::BINARY_HEADER_VERSION {
+            return Err(HypercubeError::InvalidHeader(format!(
+                "Version {} headers are JSON, not binary",
+                version
+            )));
+        }
+        let uuid: [u8; 16] = reader.take(16)?.try_into().unwrap();
+        let mut field = || reader.u32().map(|value| value as usize);
+        let (cube_id, dimension, blocks_per_partition, block_size, fragment_size) =
+            (field()?, field()?, field()?, field()?, field()?);
+        let mac_bits = usize::from(reader.u16()?);
+        let compression = from_code(&COMPRESSIONS, reader.u8()?, "compression")?;
+        let aont = from_code(&AONTS, reader.u8()?, "AONT")?;
+        let hash = from_code(&HASHES, reader.u8()?, "hash")?;
+        let shuffle = from_code(&SHUFFLES, reader.u8()?, "shuffle")?;
+        let flags = reader.u8()?;
+        if flags >> Self::FLAG_COUNT != 0 {
+            return Err(HypercubeError::InvalidHeader(format!(
+                "Unknown header flags {:#04x}",
+                flags
+            )));
+        }
+        let flag = |bit: usize| flags & (1 << bit) != 0;
+        let reshuffle = match (reader.u8()?, reader.u32()?) {
+            (0, 0) => ReshufflePolicy::Always,
+            (1, 0) => ReshufflePolicy::Never,
+            (2, every) if every > 0 => ReshufflePolicy::Periodic(every),
+            (kind, every) => {
+                return Err(HypercubeError::InvalidHeader(format!(
+                    "Unknown reshuffle policy {}:{}",
+                    kind, every
+                )))
+            }
+        };
+        let (pending_appends, frame_size, generation) =
+            (reader.u32()?, reader.u32()?, reader.u32()?);
+        let erasure = match (reader.u8()?, reader.u8()?) {
+            (0, 0) => None,
+            (data, parity) => Some(ErasureCode::new(data, parity)?),
+        };
+
+        let kdf = reader.u8()?;
+        let costs = [reader.u32()?, reader.u32()?, reader.u32()?];
+        let salt: [u8; KDF_SALT_SIZE] = reader.take(KDF_SALT_SIZE)?.try_into().unwrap();
+        let kdf = match (kdf, costs) {
+            (0, _) => None,
+            (1, [memory_kib, iterations, parallelism]) => Some(Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            }),
+            (2, [log_n, r, p]) => u8::try_from(log_n)
+                .ok()
+                .map(|log_n| Kdf::Scrypt { log_n, r, p }),
+            (3, [iterations, 0, 0]) => Some(Kdf::Pbkdf2 { iterations }),
+            _ => {
+                return Err(HypercubeError::InvalidHeader(format!(
+                    "Unknown KDF {} {:?}",
+                    kdf, costs
+                )))
+            }
+        };
+        let order = (0..PipelineOrder::STANDARD.len())
+            .map(|_| from_code(&PipelineOrder::STANDARD, reader.u8()?, "pipeline stage"))
+            .collect::<Result<_>>()?;
+
+        let mut stage_id = || -> Result<Option<String>> {
+            let length = usize::from(reader.u8()?);
+            let id = std::str::from_utf8(reader.take(length)?)
+                .map_err(|_| HypercubeError::InvalidHeader("Stage id is not UTF-8".into()))?;
+            Ok((!id.is_empty()).then(|| id.to_string()))
+        };
+        let stages = StageIds {
+            compression: stage_id()?,
+            whitener: stage_id()?,
+            aont: stage_id()?,
+            mac: stage_id()?,
+        };
+        let hints = (0..reader.u32()?)
+            .map(|_| {
+                let length = reader.u32()? as usize;
+                BloomHint::try_from(reader.take(length)?.to_vec())
+            })
+            .collect::<Result<_>>()?;
+        // Older builds stop at the hints and refuse a policy as trailing bytes
+        let attempt_policy = match reader.0.is_empty() {
+            true => None,
+            false => Some(AttemptPolicy {
+                min_kdf_ms: reader.u32()?,
+                backoff_ms: reader.u32()?,
+            }),
+        };
+        if !reader.0.is_empty() {
+            return Err(HypercubeError::InvalidHeader(
+                "Trailing bytes after binary header".into(),
+            ));
+        }
+
+        Ok(Self {
+            version,
+            uuid: (uuid != [0; 16]).then_some(ContainerId(uuid)),
+            cube_id,
+            dimension,
+            blocks_per_partition,
+            block_size,
+            mac_bits,
+            compression,
+            aont,
+            hash,
+            fragment_size,
+            hints,
+            merkle: flag(0),
+            shuffle,
+            reshuffle,
+            pending_appends,
+            kdf: kdf.map(|kdf| KdfParams { kdf, salt }),
+            append_only: flag(1),
+            locked: flag(2),
+            always_full: flag(3),
+            frame_size,
+            chunked: flag(4),
+            dedup: flag(5),
+            derived_sequence: flag(6),
+            stages,
+            pipeline_order: PipelineOrder(order),
+            erasure,
+            generation,
+            attempt_policy,
+        })
+    }
+
+    /// Boolean fields in the order of their bits in a binary header's flags
+    fn flags(&self) -> [bool; Self::FLAG_COUNT] {
+        [
+            self.merkle,
+            self.append_only,
+            self.locked,
+            self.always_full,
+            self.chunked,
+            self.dedup,
+            self.derived_sequence,
+        ]
+    }
+
+    const FLAG_COUNT: usize = 7;
+
+    /// Deserialize a header in either encoding
+    /// JSON always starts with `{`; a binary header starts with its version
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
-        Ok(serde_json::from_slice(data)?)
+        let header: Self = if data.first() == Some(&b'{') {
+            serde_json::from_slice(data)?
+        } else {
+            Self::from_binary(data)?
+        };
+        header.domains()?;
+        header.validate_geometry()?;
+        header.pipeline_order.validate()?;
+        if let Some(kdf) = &header.kdf {
+            kdf.kdf.validate()?;
+        }
+        Ok(header)
     }
 
     /// Get number of fragments per block
@@ -236,8 +1141,8 @@ impl VhcHeader {
     }
 
     /// Get block payload size in bits
-    pub fn block_bits(&self) -> usize {
-        self.block_size * 8
+    pub fn block_bits(&self) -> u64 {
+        self.block_size as u64 * 8
     }
 
     /// Cube size (also equals partitions and blocks per partition)
@@ -250,34 +1155,81 @@ impl VhcHeader {
         self.blocks_per_partition
     }
 
-    /// Effective data blocks per partition (accounting for AONT overhead)
+    /// Effective data blocks per partition (accounting for AONT overhead and parity)
     /// Rivest AONT adds one key block, so we have one less data block
     pub fn data_blocks_per_partition(&self) -> usize {
-        match self.aont {
-            Aont::Rivest => self.blocks_per_partition.saturating_sub(1),
-            Aont::Oaep => self.blocks_per_partition,
+        let blocks = self
+            .erasure
+            .map_or(self.blocks_per_partition, |code| code.data_blocks(self.blocks_per_partition));
+        let overhead = match &self.stages.aont {
+            // An unregistered transform fails once the partition is sealed or opened
+            Some(_) => aont_for(self).map_or(0, |aont| aont.overhead_blocks()),
+            None => self.aont.overhead_blocks(),
+        };
+        blocks.saturating_sub(overhead)
+    }
+
+    /// Whether partition metadata records a hash of the original data
+    pub fn hashed_meta(&self) -> bool {
+        self.version >= domains::CONTENT_HASH_VERSION
+    }
+
+    /// Bytes ahead of the compressed data in a partition's payload: the metadata, then
+    /// the sequence nonce when bases are derived
+    pub fn meta_size(&self) -> usize {
+        let meta = if self.hashed_meta() {
+            PartitionMeta::HASHED_SIZE
+        } else {
+            PartitionMeta::SIZE
+        };
+        if self.derived_sequence {
+            meta + SEQUENCE_NONCE_SIZE
+        } else {
+            meta
         }
     }
 
     /// Total blocks when the cube is full
+    /// Saturates on 32-bit builds for cubes too large to hold in memory anyway
     pub fn theoretical_block_count(&self) -> usize {
-        self.blocks_per_partition * self.dimension
+        usize::try_from(self.cell_count()).unwrap_or(usize::MAX)
+    }
+
+    /// Total blocks when the cube is full, without the platform's limit
+    pub fn cell_count(&self) -> u64 {
+        (self.dimension as u64).saturating_mul(self.blocks_per_partition as u64)
     }
 
     /// Maximum payload capacity (excluding MAC/sequence/header)
-    pub fn payload_capacity_bytes(&self) -> usize {
-        self.block_size * self.theoretical_block_count()
+    pub fn payload_capacity_bytes(&self) -> u64 {
+        self.cell_count().saturating_mul(self.block_size as u64)
+    }
+
+    /// Size of a full cube's block region (payload, sequence and MAC)
+    pub fn full_data_size(&self) -> u64 {
+        self.cell_count().saturating_mul(self.total_block_size() as u64)
     }
 
     /// Get total block size (data + sequence + MAC)
     pub fn total_block_size(&self) -> usize {
         self.block_size + 16 + self.mac_bytes()
     }
+
+    /// Count one more write
+    pub fn bump_generation(&mut self) {
+        self.generation = self.generation.saturating_add(1);
+    }
+
+    /// Generation that blocks encoded now are tagged with: the one their write creates
+    pub fn next_generation(&self) -> u32 {
+        self.generation.saturating_add(1)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kdf::Kdf;
 
     #[test]
     fn test_header_creation() {
@@ -309,6 +1261,37 @@ mod tests {
         assert!(VhcHeader::new(32, 32, 32, 0, 256).is_err());
     }
 
+    #[test]
+    fn test_large_geometry_sizes() {
+        // 2^32 blocks of 1 MiB: past 32-bit usize, well within u64
+        let header = VhcHeader::new(65536, 65536, 65536, 1 << 20, 512).unwrap();
+        assert_eq!(header.cell_count(), 1 << 32);
+        assert_eq!(header.payload_capacity_bytes(), 1 << 52);
+        assert_eq!(header.full_data_size(), (1u64 << 32) * ((1 << 20) + 16 + 64));
+        assert_eq!(header.block_bits(), 1 << 23);
+        let expected = usize::try_from(1u64 << 32).unwrap_or(usize::MAX);
+        assert_eq!(header.theoretical_block_count(), expected);
+
+        // A stored block (payload, sequence and MAC) must fit in 32 bits
+        let even_max = u32::MAX as usize - 1;
+        assert!(VhcHeader::new(8, 8, 8, even_max, 128).is_err());
+        // A full cube's block region must fit in a u64 file offset
+        let widest = u32::MAX as usize & !7;
+        assert!(VhcHeader::new(8, widest, widest, 1 << 20, 512).is_err());
+        assert!(VhcHeader::new(8, widest, 8, 32, 128).is_ok());
+    }
+
+    #[test]
+    fn test_reshuffle_policy_parse() {
+        assert_eq!("never".parse::<ReshufflePolicy>().unwrap(), ReshufflePolicy::Never);
+        let periodic: ReshufflePolicy = "periodic:4".parse().unwrap();
+        assert_eq!(periodic, ReshufflePolicy::Periodic(4));
+        assert_eq!(periodic.to_string(), "periodic:4");
+        assert!("periodic:0".parse::<ReshufflePolicy>().is_err());
+        assert!(!periodic.reshuffles(2));
+        assert!(periodic.reshuffles(3));
+    }
+
     #[test]
     fn test_serialization() {
         let header = VhcHeader::new(32, 32, 32, 128, 512).unwrap();
@@ -320,15 +1303,185 @@ mod tests {
         assert_eq!(header.mac_bits, restored.mac_bits);
     }
 
+    #[test]
+    fn test_container_id() {
+        let header = VhcHeader::new(32, 32, 32, 128, 512).unwrap();
+        let id = header.uuid.unwrap();
+        assert_ne!(Some(id), VhcHeader::new(32, 32, 32, 128, 512).unwrap().uuid);
+
+        let text = id.to_string();
+        assert_eq!(text.len(), 36);
+        assert_eq!(&text[14..15], "4");
+        assert!(text.starts_with(&id.short()));
+        assert_eq!(text.parse::<ContainerId>().unwrap(), id);
+        let restored = VhcHeader::from_bytes(&header.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.uuid, Some(id));
+
+        // Headers written before ids existed have none
+        let json = String::from_utf8(VhcHeader::default().to_json().unwrap()).unwrap();
+        assert!(!json.contains("uuid"));
+        assert_eq!(VhcHeader::from_bytes(json.as_bytes()).unwrap().uuid, None);
+
+        for bad in ["", "1234", "0123456789abcdef0123456789abcdef", &text[1..]] {
+            assert!(bad.parse::<ContainerId>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_header_without_hints_is_readable() {
+        // Headers written before hints existed have no `hints` key
+        let header = VhcHeader::default();
+        let json = String::from_utf8(header.to_json().unwrap()).unwrap();
+        assert!(!json.contains("hints"));
+        assert!(VhcHeader::from_bytes(json.as_bytes()).unwrap().hints.is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_order() {
+        let header = VhcHeader::default();
+        assert!(header.pipeline_order.is_standard());
+        assert!(!String::from_utf8(header.to_json().unwrap()).unwrap().contains("pipeline"));
+
+        let order: PipelineOrder = "compress,segment,fragment,aont,whiten,shuffle,sequence,mac"
+            .parse()
+            .unwrap();
+        let transforms: Vec<_> = order.fragment_transforms().collect();
+        assert_eq!(
+            transforms,
+            [PipelineStage::Aont, PipelineStage::Whiten, PipelineStage::Shuffle]
+        );
+        let mut reordered = header.clone();
+        reordered.pipeline_order = order.clone();
+        let bytes = reordered.to_json().unwrap();
+        assert!(String::from_utf8(bytes.clone()).unwrap().contains(r#""aont","whiten""#));
+        assert_eq!(VhcHeader::from_bytes(&bytes).unwrap().pipeline_order, order);
+        let bytes = reordered.to_binary().unwrap();
+        assert_eq!(VhcHeader::from_bytes(&bytes).unwrap().pipeline_order, order);
+
+        // Fixed stages stay in place, and every transform appears once
+        for bad in [
+            "segment,compress,fragment,whiten,aont,shuffle,sequence,mac",
+            "compress,segment,fragment,whiten,aont,sequence,shuffle,mac",
+            "compress,segment,fragment,aont,aont,shuffle,sequence,mac",
+            "compress,segment,fragment,whiten,aont,sequence,mac",
+            "compress,segment,fragment,whiten,aont,shuffle,sequence,mac,mac",
+            "compress,segment,fragment,whiten,aont,twirl,sequence,mac",
+        ] {
+            assert!(bad.parse::<PipelineOrder>().is_err(), "{}", bad);
+        }
+        reordered.pipeline_order.0.swap(0, 1);
+        assert!(VhcHeader::from_bytes(&reordered.to_json().unwrap()).is_err());
+        assert!(reordered.to_binary().is_err());
+    }
+
+    #[test]
+    fn test_binary_header() {
+        let mut header = VhcHeader::new(64, 64, 64, 128, 512).unwrap();
+        // Not JSON, which starts with '{'; random fields such as the id may hold that byte
+        let bytes = header.to_bytes().unwrap();
+        assert_ne!(bytes.first(), Some(&b'{'));
+        assert_eq!(bytes, header.to_binary().unwrap());
+        let restored = VhcHeader::from_binary(&bytes).unwrap();
+        assert_eq!(restored.to_json().unwrap(), header.to_json().unwrap());
+        header.hints = vec![BloomHint::try_from(vec![0xa5; 9]).unwrap()];
+        header.merkle = true;
+        header.locked = true;
+        header.derived_sequence = true;
+        header.shuffle = Shuffle::Fragment;
+        header.reshuffle = ReshufflePolicy::Periodic(5);
+        header.kdf = Some(KdfParams::new(Kdf::scrypt()));
+        header.frame_size = 4096;
+        header.generation = 7;
+        header.stages.whitener = Some("xor".into());
+        header.set_erasure(Some(ErasureCode::new(6, 2).unwrap())).unwrap();
+
+        let bytes = header.to_bytes().unwrap();
+        assert_eq!(bytes.len(), BINARY_HEADER_FIXED_SIZE + 4 + 3 + 4 + 4 + 9);
+        let restored = VhcHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.to_json().unwrap(), header.to_json().unwrap());
+
+        // JSON headers of older versions still read, and keep their encoding
+        let old = VhcHeader {
+            version: domains::BINARY_HEADER_VERSION - 1,
+            ..header.clone()
+        };
+        assert_eq!(old.to_bytes().unwrap(), old.to_json().unwrap());
+        assert_eq!(VhcHeader::from_bytes(&old.to_bytes().unwrap()).unwrap().kdf, header.kdf);
+        assert!(VhcHeader::from_binary(&old.to_binary().unwrap()).is_err());
+
+        // Truncated, padded and unknown-flag headers are refused
+        assert!(VhcHeader::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(VhcHeader::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+        let mut flags = bytes.clone();
+        flags[46] |= 0x80;
+        let error = VhcHeader::from_bytes(&flags).unwrap_err();
+        assert!(error.to_string().contains("Unknown header flags"), "{}", error);
+
+        // An attempt policy follows the hints
+        header.attempt_policy = Some("250:1000".parse().unwrap());
+        let with_policy = header.to_bytes().unwrap();
+        assert_eq!(with_policy.len(), bytes.len() + 8);
+        let restored = VhcHeader::from_bytes(&with_policy).unwrap();
+        assert_eq!(restored.attempt_policy, header.attempt_policy);
+        assert_eq!(header.attempt_policy.unwrap().to_string(), "250:1000");
+        assert!(VhcHeader::from_bytes(&with_policy[..with_policy.len() - 2]).is_err());
+        assert!("250".parse::<AttemptPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_kdf_section() {
+        let mut header = VhcHeader::default();
+        assert_eq!(header.partition_key(b"secret").unwrap(), b"secret");
+
+        header.kdf = Some(KdfParams::new(Kdf::pbkdf2(Some(10))));
+        let restored = VhcHeader::from_bytes(&header.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.kdf, header.kdf);
+        let key = restored.partition_key(b"secret").unwrap();
+        assert_eq!(key, header.partition_key(b"secret").unwrap());
+        assert_ne!(key, b"secret");
+
+        // Cost parameters are checked before anything is derived
+        let json = String::from_utf8(header.to_json().unwrap())
+            .unwrap()
+            .replace(r#""iterations":10"#, r#""iterations":4000000000"#);
+        let err = VhcHeader::from_bytes(json.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn test_unknown_version_rejected() {
+        let header = VhcHeader {
+            version: domains::LATEST_VERSION + 1,
+            ..Default::default()
+        };
+        let err = VhcHeader::from_bytes(&header.to_bytes().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("Unsupported format version"));
+    }
+
     #[test]
     fn test_partition_meta() {
         let meta = PartitionMeta {
             compressed_size: 1000,
             original_size: 12345,
+            content_hash: None,
         };
         let bytes = meta.to_bytes();
-        let restored = PartitionMeta::from_bytes(&bytes).unwrap();
+        assert_eq!(bytes.len(), PartitionMeta::SIZE);
+        let restored = PartitionMeta::from_bytes(&bytes, false).unwrap();
         assert_eq!(meta.compressed_size, restored.compressed_size);
         assert_eq!(meta.original_size, restored.original_size);
+        assert!(PartitionMeta::from_bytes(&bytes, true).is_err());
+
+        let data = b"hashed payload";
+        let meta = PartitionMeta {
+            compressed_size: 10,
+            original_size: data.len() as u64,
+            content_hash: Some(*blake3::hash(data).as_bytes()),
+        };
+        let restored = PartitionMeta::from_bytes(&meta.to_bytes(), true).unwrap();
+        assert_eq!(restored.content_hash, meta.content_hash);
+        restored.check(data).unwrap();
+        let error = restored.check(b"hashed paylaod").unwrap_err();
+        assert!(error.to_string().contains("Content hash mismatch"), "{}", error);
     }
 }
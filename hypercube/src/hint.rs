@@ -0,0 +1,201 @@
+use crate::domains::Domains;
+use crate::error::{HypercubeError, Result};
+use crate::header::VhcHeader;
+use crate::pipeline::SEQUENCE_SIZE;
+use serde::{Deserialize, Serialize};
+
+/// Bytes of the sequence field fed into the filter
+/// The low half carries the per-block counter, so every block differs
+pub const HINT_PREFIX_SIZE: usize = SEQUENCE_SIZE / 2;
+
+/// Filter bits allocated per block (≈1% false-positive rate with 7 probes)
+const BITS_PER_BLOCK: usize = 10;
+
+/// Number of probe positions per element
+const HINT_PROBES: u64 = 7;
+
+/// Keyed bloom filter over the sequence prefixes of one partition's blocks
+/// Probe positions come from a key derived from the partition secret, so
+/// without the secret the filter is an opaque bit string
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct BloomHint {
+    bits: Vec<u8>,
+}
+
+impl BloomHint {
+    /// Build a filter for the given serialized blocks of a container with `header`
+    /// Every filter in a container has the same size, sized for a full partition, so a
+    /// hint's length says nothing about how many blocks its partition holds
+    pub fn build(secret: &[u8], blocks: &[Vec<u8>], header: &VhcHeader) -> Result<Self> {
+        let key = HintKey::new(secret, header.domains()?);
+        let mut hint = Self {
+            bits: vec![0u8; Self::size_for(header)],
+        };
+        for block in blocks {
+            for bit in key.probes(block, hint.bit_len()) {
+                hint.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        Ok(hint)
+    }
+
+    /// Filter size in bytes for every hint of a container with `header`
+    pub fn size_for(header: &VhcHeader) -> usize {
+        (header.blocks_per_partition * BITS_PER_BLOCK).div_ceil(8)
+    }
+
+    /// Whether the block may belong to this filter (no false negatives)
+    pub fn may_contain(&self, key: &HintKey, block: &[u8]) -> bool {
+        key.probes(block, self.bit_len())
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Filter size in bytes
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// True for a zero-length filter
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    /// The filter's bits, as stored in a binary header
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    fn bit_len(&self) -> u64 {
+        self.bits.len() as u64 * 8
+    }
+}
+
+impl From<BloomHint> for String {
+    fn from(hint: BloomHint) -> Self {
+        hex::encode(hint.bits)
+    }
+}
+
+impl TryFrom<String> for BloomHint {
+    type Error = HypercubeError;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let bits = hex::decode(&value)
+            .map_err(|e| HypercubeError::InvalidHeader(format!("hint: {}", e)))?;
+        Self::try_from(bits)
+    }
+}
+
+impl TryFrom<Vec<u8>> for BloomHint {
+    type Error = HypercubeError;
+
+    fn try_from(bits: Vec<u8>) -> std::result::Result<Self, Self::Error> {
+        if bits.is_empty() {
+            return Err(HypercubeError::InvalidHeader("hint: empty filter".into()));
+        }
+        Ok(Self { bits })
+    }
+}
+
+/// Probe key derived from a partition secret
+pub struct HintKey([u8; 32]);
+
+impl HintKey {
+    pub fn new(secret: &[u8], domains: &Domains) -> Self {
+        Self(blake3::derive_key(domains.hint_key, secret))
+    }
+
+    /// Double-hashed probe positions for a block's sequence prefix
+    fn probes(&self, block: &[u8], bit_len: u64) -> impl Iterator<Item = usize> {
+        let prefix = &block[..HINT_PREFIX_SIZE.min(block.len())];
+        let hash = blake3::keyed_hash(&self.0, prefix);
+        let bytes = hash.as_bytes();
+        let h1 = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+        (0..HINT_PROBES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bit_len) as usize)
+    }
+}
+
+/// Select the blocks that match any filter under this secret
+pub fn candidate_blocks(
+    hints: &[BloomHint],
+    secret: &[u8],
+    all_blocks: &[impl AsRef<[u8]>],
+    domains: &Domains,
+) -> Vec<Vec<u8>> {
+    let key = HintKey::new(secret, domains);
+    all_blocks
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|block| hints.iter().any(|hint| hint.may_contain(&key, block)))
+        .map(<[u8]>::to_vec)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domains::V1;
+    use crate::partition::create_partition;
+
+    fn random_blocks(count: usize, size: usize) -> Vec<Vec<u8>> {
+        crate::partition::generate_noise_blocks(count, size)
+    }
+
+    fn header() -> VhcHeader {
+        VhcHeader::new(32, 32, 32, 64, 256).unwrap()
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        let blocks = random_blocks(32, 80);
+        let hint = BloomHint::build(b"secret", &blocks, &header()).unwrap();
+        let key = HintKey::new(b"secret", &V1);
+        assert_eq!(hint.len(), 40);
+        assert!(blocks.iter().all(|block| hint.may_contain(&key, block)));
+    }
+
+    #[test]
+    fn test_size_hides_partition_size() {
+        let header = header();
+        for count in [1, 8, 32, 40] {
+            let hint = BloomHint::build(b"secret", &random_blocks(count, 80), &header).unwrap();
+            assert_eq!(hint.len(), BloomHint::size_for(&header));
+        }
+    }
+
+    #[test]
+    fn test_filters_most_foreign_blocks() {
+        let header = header();
+        let ours = create_partition(b"ours", b"secret", &header, None).unwrap();
+        let hint = BloomHint::build(b"secret", &ours.blocks, &header).unwrap();
+
+        let foreign = random_blocks(2000, header.total_block_size());
+        let mut all_blocks = foreign.clone();
+        all_blocks.extend(ours.blocks.clone());
+
+        let candidates = candidate_blocks(&[hint], b"secret", &all_blocks, &V1);
+        assert!(ours.blocks.iter().all(|block| candidates.contains(block)));
+        // ~1% false-positive rate; allow generous slack
+        assert!(candidates.len() - ours.blocks.len() < 100);
+    }
+
+    #[test]
+    fn test_wrong_secret_does_not_match() {
+        let blocks = random_blocks(32, 80);
+        let hint = BloomHint::build(b"secret", &blocks, &header()).unwrap();
+        let key = HintKey::new(b"other", &V1);
+        let matched = blocks.iter().filter(|b| hint.may_contain(&key, b)).count();
+        assert!(matched < 8);
+    }
+
+    #[test]
+    fn test_hex_serialization() {
+        let hint = BloomHint::build(b"secret", &random_blocks(8, 80), &header()).unwrap();
+        let json = serde_json::to_string(&hint).unwrap();
+        let restored: BloomHint = serde_json::from_str(&json).unwrap();
+        assert_eq!(hint, restored);
+        assert!(serde_json::from_str::<BloomHint>("\"zz\"").is_err());
+    }
+}
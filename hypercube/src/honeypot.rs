@@ -0,0 +1,268 @@
+//! Honeypot partitions: decoys whose extraction raises an alert.
+//!
+//! A honeypot is an ordinary partition under a decoy secret, with a labelled record kept in
+//! its padding, after the compressed data. The record is sealed with the partition, so
+//! nothing shows it without the decoy secret, and opening the partition gives back exactly
+//! the decoy data; older readers simply skip the padding. When a partition with a record
+//! is opened (`extract`, `cat`, `verify`, anything that decodes it), the audit hook
+//! installed with `set_audit_hook` hears of it, on the thread that opened it.
+//!
+//! Only code that knows to look can tell: an adversary's own tooling, or a copy of the
+//! container opened where no hook is installed, raises nothing. Decoy data that is itself
+//! a canary (a credential or URL that reports its use) covers those cases too.
+
+use crate::error::{HypercubeError, Result};
+use crate::header::{ContainerId, VhcHeader};
+use std::cell::Cell;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+
+/// Starts a honeypot record; padding is otherwise all zeros
+const MAGIC: [u8; 16] = *b"\0hypercube-lure\x01";
+
+/// Longest label a record holds, in bytes
+pub const MAX_LABEL_LEN: usize = 255;
+
+/// Environment variable with the CLI's alert command, ahead of the config's `honeypot_alert`
+pub const HONEYPOT_ALERT_ENV: &str = "HYPERCUBE_HONEYPOT_ALERT";
+
+/// Environment variable giving `CommandAlert` the honeypot's label
+pub const LABEL_ENV: &str = "HYPERCUBE_HONEYPOT_LABEL";
+
+/// Environment variable giving `CommandAlert` the container id, when it has one
+pub const CONTAINER_ID_ENV: &str = "HYPERCUBE_CONTAINER_ID";
+
+/// A honeypot partition was opened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoneypotEvent {
+    /// The honeypot's label, as given when it was planted
+    pub label: String,
+    /// Id of the container it was opened in; None for containers made before ids
+    pub container_id: Option<ContainerId>,
+}
+
+/// Hears of every honeypot opened in the process
+/// Calls come from the thread that opened the partition, before its data is returned
+pub trait AuditHook: Send + Sync {
+    fn honeypot_opened(&self, event: &HoneypotEvent);
+}
+
+static AUDIT_HOOK: OnceLock<Arc<dyn AuditHook>> = OnceLock::new();
+
+thread_local! {
+    static SUPPRESSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install the process-wide audit hook; only the first call succeeds
+/// Returns the hook back if one is already installed
+pub fn set_audit_hook(hook: Arc<dyn AuditHook>) -> std::result::Result<(), Arc<dyn AuditHook>> {
+    AUDIT_HOOK.set(hook)
+}
+
+/// Run `f` without reporting honeypots it opens on this thread, for the owner's own
+/// reads (`add --verify` reading back the decoy it just planted)
+pub fn unobserved<T>(f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(SUPPRESSED.replace(true));
+    f()
+}
+
+/// Puts back the suppression in force before `unobserved`, even if `f` unwinds
+struct Restore(bool);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        SUPPRESSED.set(self.0);
+    }
+}
+
+/// The record planting a honeypot labelled `label`, to follow a partition's compressed data
+pub fn record(label: &str) -> Result<Vec<u8>> {
+    if label.is_empty() || label.len() > MAX_LABEL_LEN {
+        return Err(HypercubeError::InvalidFormat(format!(
+            "Honeypot label must be 1 to {} bytes",
+            MAX_LABEL_LEN
+        )));
+    }
+    let mut record = Vec::with_capacity(MAGIC.len() + 1 + label.len());
+    record.extend_from_slice(&MAGIC);
+    record.push(label.len() as u8);
+    record.extend_from_slice(label.as_bytes());
+    Ok(record)
+}
+
+/// The label of the record at the start of `padding`, if there is one
+pub fn read_record(padding: &[u8]) -> Option<String> {
+    let rest = padding.strip_prefix(&MAGIC)?;
+    let (&len, rest) = rest.split_first()?;
+    let label = rest.get(..len as usize)?;
+    Some(String::from_utf8_lossy(label).into_owned())
+}
+
+/// Report the partition just opened to the audit hook if `padding` (what follows its
+/// compressed data) holds a honeypot record
+pub fn check(padding: &[u8], header: &VhcHeader) {
+    let Some(hook) = AUDIT_HOOK.get() else {
+        return;
+    };
+    if SUPPRESSED.get() {
+        return;
+    }
+    if let Some(label) = read_record(padding) {
+        hook.honeypot_opened(&HoneypotEvent {
+            label,
+            container_id: header.uuid,
+        });
+    }
+}
+
+/// Audit hook running a command for each honeypot opened, as the CLI's alert does
+/// The command is a shell snippet, run with the label in `$HYPERCUBE_HONEYPOT_LABEL` and
+/// the container id in `$HYPERCUBE_CONTAINER_ID`. It is not waited for, and nothing about
+/// it (output, failure) reaches the process that opened the honeypot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandAlert {
+    command: String,
+}
+
+impl CommandAlert {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+        }
+    }
+}
+
+impl AuditHook for CommandAlert {
+    fn honeypot_opened(&self, event: &HoneypotEvent) {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .env(LABEL_ENV, &event.label)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(id) = event.container_id {
+            command.env(CONTAINER_ID_ENV, id.to_string());
+        }
+        let _ = command.spawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{read_from_vhc, ExtractOptions};
+    use crate::partition::ByteRange;
+    use crate::vhc::read_container_id;
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    /// Keeps every event; other tests may open honeypots too, so check by container
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<HoneypotEvent>>);
+
+    impl AuditHook for Recorded {
+        fn honeypot_opened(&self, event: &HoneypotEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    impl Recorded {
+        fn labels_for(&self, id: Option<ContainerId>) -> Vec<String> {
+            let events = self.0.lock().unwrap();
+            let ours = events.iter().filter(|event| event.container_id == id);
+            ours.map(|event| event.label.clone()).collect()
+        }
+    }
+
+    #[test]
+    fn test_unobserved_restores_after_panic() {
+        let outcome = std::panic::catch_unwind(|| unobserved(|| panic!("decoy read failed")));
+        assert!(outcome.is_err());
+        assert!(!SUPPRESSED.get());
+        assert!(unobserved(|| SUPPRESSED.get()));
+        assert!(!SUPPRESSED.get());
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let record = record("payroll decoy").unwrap();
+        let mut padding = record.clone();
+        padding.resize(record.len() + 64, 0);
+        assert_eq!(read_record(&padding).as_deref(), Some("payroll decoy"));
+
+        // Plain padding, or a cut-off record, is no honeypot
+        assert_eq!(read_record(&[0; 64]), None);
+        assert_eq!(read_record(&record[..record.len() - 1]), None);
+        assert_eq!(read_record(&[]), None);
+
+        assert!(super::record("").is_err());
+        assert!(super::record(&"x".repeat(MAX_LABEL_LEN)).is_ok());
+        assert!(super::record(&"x".repeat(MAX_LABEL_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_honeypot_reports_to_hook() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("decoy.txt");
+        let vhc = dir.path().join("cube.vhc");
+        std::fs::write(&input, b"decoy credentials").unwrap();
+        let recorded = Arc::new(Recorded::default());
+        assert!(set_audit_hook(recorded.clone()).is_ok());
+
+        let add = |secret: &str, honeypot: Option<&str>| {
+            let options = AddOptions {
+                secret: secret.into(),
+                dimension: 8,
+                kdf: None,
+                honeypot: honeypot.map(str::to_string),
+                verify: true,
+                ..Default::default()
+            };
+            add_partition(&input, &vhc, &options).unwrap();
+        };
+        add("decoy", Some("hr-share"));
+        add("real", None);
+        let id = read_container_id(&vhc);
+        assert!(id.is_some());
+        // Reading back what was just planted is not reported
+        assert!(recorded.labels_for(id).is_empty());
+
+        let read = |secret: &str, range: ByteRange| {
+            let options = ExtractOptions {
+                secret: secret.into(),
+                range,
+                ..Default::default()
+            };
+            read_from_vhc(&vhc, &options).unwrap()
+        };
+        assert_eq!(read("real", ByteRange::default()), b"decoy credentials");
+        assert!(recorded.labels_for(id).is_empty());
+
+        // The decoy reads back unchanged, whole or in part, and each read is reported
+        assert_eq!(read("decoy", ByteRange::default()), b"decoy credentials");
+        let range = ByteRange {
+            offset: 6,
+            length: Some(11),
+        };
+        assert_eq!(read("decoy", range), b"credentials");
+        assert_eq!(recorded.labels_for(id), ["hr-share", "hr-share"]);
+        unobserved(|| read("decoy", ByteRange::default()));
+        assert_eq!(recorded.labels_for(id).len(), 2);
+
+        // Chunked partitions have no padding to hold the record
+        let chunked = AddOptions {
+            secret: "decoy".into(),
+            dimension: 8,
+            kdf: None,
+            chunked: true,
+            honeypot: Some("x".into()),
+            ..Default::default()
+        };
+        let other = dir.path().join("chunked.vhc");
+        assert!(add_partition(&input, &other, &chunked).is_err());
+        assert!(!other.exists());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::file_mode::write_file;
+use crate::vhc::replace_file;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{rngs::OsRng, RngCore};
+use std::path::{Path, PathBuf};
+
+/// File extension of the optional index sidecar
+pub const INDEX_EXTENSION: &str = "vhcidx";
+
+/// ChaCha20-Poly1305 nonce size in bytes
+const NONCE_SIZE: usize = 12;
+
+/// One sidecar entry: the block indices of a single partition,
+/// AEAD-encrypted under a key derived from that partition's secret
+/// Layout: [nonce: 12][ciphertext_len: 4][ciphertext...]
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    pub nonce: [u8; NONCE_SIZE],
+    pub ciphertext: Vec<u8>,
+}
+
+impl IndexEntry {
+    /// Encrypt a list of block indices under the partition secret
+    pub fn seal(secret: &[u8], indices: &[usize]) -> Result<Self> {
+        let mut plaintext = Vec::with_capacity(indices.len() * 8);
+        for &index in indices {
+            plaintext.extend_from_slice(&(index as u64).to_le_bytes());
+        }
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = index_cipher(secret)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: domains::INDEX_AAD,
+                },
+            )
+            .map_err(|_| HypercubeError::IntegrityError("Index encryption failed".into()))?;
+
+        Ok(Self { nonce, ciphertext })
+    }
+
+    /// Decrypt the entry; None if it belongs to a different secret
+    pub fn open(&self, secret: &[u8]) -> Option<Vec<usize>> {
+        let plaintext = index_cipher(secret)
+            .decrypt(
+                Nonce::from_slice(&self.nonce),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: domains::INDEX_AAD,
+                },
+            )
+            .ok()?;
+
+        if plaintext.len() % 8 != 0 {
+            return None;
+        }
+        plaintext
+            .chunks_exact(8)
+            .map(|chunk| usize::try_from(u64::from_le_bytes(chunk.try_into().unwrap())).ok())
+            .collect()
+    }
+}
+
+/// Contents of a `.vhcidx` sidecar
+/// Entries carry no plaintext identifiers - each reader trial-decrypts them all
+#[derive(Debug, Clone, Default)]
+pub struct VhcIndex {
+    pub entries: Vec<IndexEntry>,
+}
+
+impl VhcIndex {
+    /// Serialize all entries back-to-back
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for entry in &self.entries {
+            buf.extend_from_slice(&entry.nonce);
+            buf.extend_from_slice(&(entry.ciphertext.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&entry.ciphertext);
+        }
+        buf
+    }
+
+    /// Parse entries from sidecar bytes
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+
+        while offset < data.len() {
+            if data.len() - offset < NONCE_SIZE + 4 {
+                return Err(HypercubeError::InvalidFormat("Truncated index entry".into()));
+            }
+            let mut nonce = [0u8; NONCE_SIZE];
+            nonce.copy_from_slice(&data[offset..offset + NONCE_SIZE]);
+            offset += NONCE_SIZE;
+
+            let len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            offset += 4;
+            if data.len() - offset < len {
+                return Err(HypercubeError::InvalidFormat("Truncated index entry".into()));
+            }
+            let ciphertext = data[offset..offset + len].to_vec();
+            offset += len;
+
+            entries.push(IndexEntry { nonce, ciphertext });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Find the entry owned by this secret, returning its position and block indices
+    pub fn find(&self, secret: &[u8]) -> Option<(usize, Vec<usize>)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .find_map(|(pos, entry)| entry.open(secret).map(|indices| (pos, indices)))
+    }
+
+    /// Insert or replace the entry owned by this secret
+    pub fn upsert(&mut self, secret: &[u8], indices: &[usize]) -> Result<()> {
+        let entry = IndexEntry::seal(secret, indices)?;
+        match self.find(secret) {
+            Some((pos, _)) => self.entries[pos] = entry,
+            None => self.entries.push(entry),
+        }
+        Ok(())
+    }
+
+    /// Drop the entry owned by this secret; true if there was one
+    pub fn remove(&mut self, secret: &[u8]) -> bool {
+        match self.find(secret) {
+            Some((pos, _)) => {
+                self.entries.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Sidecar path for a container (`vault.vhc` → `vault.vhcidx`)
+pub fn index_path(vhc_path: &Path) -> PathBuf {
+    vhc_path.with_extension(INDEX_EXTENSION)
+}
+
+/// Read the sidecar next to a container, if one exists
+pub fn read_index(vhc_path: &Path) -> Result<Option<VhcIndex>> {
+    let path = index_path(vhc_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = std::fs::read(path)?;
+    Ok(Some(VhcIndex::from_bytes(&data)?))
+}
+
+/// Write the sidecar next to a container, replacing an existing one whole
+pub fn write_index(vhc_path: &Path, index: &VhcIndex) -> Result<()> {
+    let bytes = index.to_bytes();
+    replace_file(&index_path(vhc_path), |temp| write_file(temp, &bytes))
+}
+
+/// Look up this secret's block indices in the sidecar
+/// Returns None when there is no sidecar or no entry decrypts
+pub fn lookup_partition(vhc_path: &Path, secret: &[u8]) -> Result<Option<Vec<usize>>> {
+    Ok(read_index(vhc_path)?.and_then(|index| index.find(secret).map(|(_, indices)| indices)))
+}
+
+/// Record (or refresh) this secret's block indices, creating the sidecar if needed
+pub fn record_partition(vhc_path: &Path, secret: &[u8], indices: &[usize]) -> Result<()> {
+    let mut index = read_index(vhc_path)?.unwrap_or_default();
+    index.upsert(secret, indices)?;
+    write_index(vhc_path, &index)
+}
+
+/// Remove this secret's entry, deleting the sidecar once it is empty
+pub fn forget_partition(vhc_path: &Path, secret: &[u8]) -> Result<bool> {
+    let Some(mut index) = read_index(vhc_path)? else {
+        return Ok(false);
+    };
+    if !index.remove(secret) {
+        return Ok(false);
+    }
+    if index.entries.is_empty() {
+        std::fs::remove_file(index_path(vhc_path))?;
+    } else {
+        write_index(vhc_path, &index)?;
+    }
+    Ok(true)
+}
+
+fn index_cipher(secret: &[u8]) -> ChaCha20Poly1305 {
+    let key = blake3::derive_key(domains::INDEX_KEY, secret);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_entry_roundtrip() {
+        let entry = IndexEntry::seal(b"secret", &[7, 3, 1024]).unwrap();
+        assert_eq!(entry.open(b"secret"), Some(vec![7, 3, 1024]));
+        assert_eq!(entry.open(b"other"), None);
+    }
+
+    #[test]
+    fn test_index_serialization() {
+        let mut index = VhcIndex::default();
+        index.upsert(b"alpha", &[1, 2, 3]).unwrap();
+        index.upsert(b"beta", &[9]).unwrap();
+
+        let restored = VhcIndex::from_bytes(&index.to_bytes()).unwrap();
+        assert_eq!(restored.entries.len(), 2);
+        assert_eq!(restored.find(b"alpha").unwrap().1, vec![1, 2, 3]);
+        assert_eq!(restored.find(b"beta").unwrap().1, vec![9]);
+        assert!(restored.find(b"gamma").is_none());
+    }
+
+    #[test]
+    fn test_upsert_replaces_own_entry() {
+        let mut index = VhcIndex::default();
+        index.upsert(b"alpha", &[1]).unwrap();
+        index.upsert(b"beta", &[2]).unwrap();
+        index.upsert(b"alpha", &[5, 6]).unwrap();
+
+        assert_eq!(index.entries.len(), 2);
+        assert_eq!(index.find(b"alpha").unwrap(), (0, vec![5, 6]));
+    }
+
+    #[test]
+    fn test_truncated_sidecar_rejected() {
+        let mut index = VhcIndex::default();
+        index.upsert(b"alpha", &[1, 2]).unwrap();
+        let bytes = index.to_bytes();
+        assert!(VhcIndex::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_sidecar_on_disk() {
+        let dir = tempdir().unwrap();
+        let vhc = dir.path().join("vault.vhc");
+
+        assert!(lookup_partition(&vhc, b"alpha").unwrap().is_none());
+        record_partition(&vhc, b"alpha", &[4, 8]).unwrap();
+        assert!(dir.path().join("vault.vhcidx").exists());
+        assert_eq!(lookup_partition(&vhc, b"alpha").unwrap(), Some(vec![4, 8]));
+        assert!(lookup_partition(&vhc, b"beta").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_forget_partition() {
+        let dir = tempdir().unwrap();
+        let vhc = dir.path().join("vault.vhc");
+
+        record_partition(&vhc, b"alpha", &[1]).unwrap();
+        record_partition(&vhc, b"beta", &[2]).unwrap();
+        assert!(forget_partition(&vhc, b"alpha").unwrap());
+        assert!(!forget_partition(&vhc, b"alpha").unwrap());
+        assert!(lookup_partition(&vhc, b"alpha").unwrap().is_none());
+
+        assert!(forget_partition(&vhc, b"beta").unwrap());
+        assert!(!index_path(&vhc).exists());
+    }
+}
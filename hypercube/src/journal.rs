@@ -0,0 +1,177 @@
+//! Append-only journal.
+//! An append-only container records each write as a journal entry in its trailer.
+//! Entries are hash-chained over the blocks they added, so replacing, reordering
+//! or dropping a journaled block breaks the chain from that generation on.
+//! Publishing or signing the newest chain hash pins the whole history.
+
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::merkle::Hash;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Serialized entry: generation, timestamp, first block, block count, chain hash
+pub const JOURNAL_ENTRY_SIZE: usize = 4 * 8 + 32;
+
+/// One append to an append-only container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Write counter, starting at 1
+    pub generation: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Index of the first appended block
+    pub first_block: u64,
+    pub block_count: u64,
+    /// Hash over the previous entry's chain, this entry's fields and its blocks
+    pub chain: Hash,
+}
+
+impl JournalEntry {
+    /// Journal the blocks from `first_block` to the end of `blocks`
+    pub fn record(journal: &mut Vec<JournalEntry>, blocks: &[Vec<u8>], first_block: usize) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let mut entry = Self {
+            generation: journal.len() as u64 + 1,
+            timestamp,
+            first_block: first_block as u64,
+            block_count: (blocks.len() - first_block) as u64,
+            chain: [0u8; 32],
+        };
+        entry.chain = entry.chain_hash(&previous_chain(journal), &blocks[first_block..]);
+        journal.push(entry);
+    }
+
+    fn chain_hash(&self, previous: &Hash, blocks: &[impl AsRef<[u8]>]) -> Hash {
+        let mut hasher = blake3::Hasher::new_derive_key(domains::JOURNAL);
+        hasher.update(previous);
+        hasher.update(&self.to_bytes()[..JOURNAL_ENTRY_SIZE - 32]);
+        for block in blocks {
+            hasher.update(block.as_ref());
+        }
+        *hasher.finalize().as_bytes()
+    }
+
+    pub fn to_bytes(&self) -> [u8; JOURNAL_ENTRY_SIZE] {
+        let mut bytes = [0u8; JOURNAL_ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.generation.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.first_block.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.block_count.to_le_bytes());
+        bytes[32..].copy_from_slice(&self.chain);
+        bytes
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() != JOURNAL_ENTRY_SIZE {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "Journal entry must be {} bytes, got {}",
+                JOURNAL_ENTRY_SIZE,
+                data.len()
+            )));
+        }
+        let u64_at = |i: usize| u64::from_le_bytes(data[i..i + 8].try_into().unwrap());
+        Ok(Self {
+            generation: u64_at(0),
+            timestamp: u64_at(8),
+            first_block: u64_at(16),
+            block_count: u64_at(24),
+            chain: data[32..].try_into().unwrap(),
+        })
+    }
+}
+
+/// Chain hash of the newest entry (zeros for an empty journal)
+pub fn previous_chain(journal: &[JournalEntry]) -> Hash {
+    journal.last().map_or([0u8; 32], |entry| entry.chain)
+}
+
+/// Check that the journal accounts for every block, in order, unchanged
+pub fn verify_journal(journal: &[JournalEntry], blocks: &[impl AsRef<[u8]>]) -> Result<()> {
+    let mut previous = [0u8; 32];
+    let mut next_block = 0usize;
+    for (i, entry) in journal.iter().enumerate() {
+        let range = usize::try_from(entry.first_block)
+            .ok()
+            .zip(usize::try_from(entry.block_count).ok())
+            .and_then(|(first, count)| Some(first..first.checked_add(count)?))
+            .filter(|range| range.start == next_block && range.end <= blocks.len());
+        let range = match range {
+            Some(range) if entry.generation == i as u64 + 1 => range,
+            _ => return Err(broken(entry.generation, "out of sequence")),
+        };
+        if entry.chain_hash(&previous, &blocks[range.clone()]) != entry.chain {
+            return Err(broken(entry.generation, "blocks or record altered"));
+        }
+        previous = entry.chain;
+        next_block = range.end;
+    }
+    if next_block != blocks.len() {
+        return Err(HypercubeError::IntegrityError(format!(
+            "Journal covers {} of {} blocks",
+            next_block,
+            blocks.len()
+        )));
+    }
+    Ok(())
+}
+
+fn broken(generation: u64, reason: &str) -> HypercubeError {
+    HypercubeError::IntegrityError(format!(
+        "Journal broken at generation {}: {}",
+        generation, reason
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journaled(appends: &[usize]) -> (Vec<JournalEntry>, Vec<Vec<u8>>) {
+        let mut journal = Vec::new();
+        let mut blocks = Vec::new();
+        for &count in appends {
+            let first = blocks.len();
+            blocks.extend((0..count).map(|i| vec![(first + i) as u8; 16]));
+            JournalEntry::record(&mut journal, &blocks, first);
+        }
+        (journal, blocks)
+    }
+
+    #[test]
+    fn test_journal_verifies() {
+        let (journal, blocks) = journaled(&[3, 1, 4]);
+        assert_eq!(journal.len(), 3);
+        assert_eq!(journal[2].generation, 3);
+        assert_eq!((journal[2].first_block, journal[2].block_count), (4, 4));
+        verify_journal(&journal, &blocks).unwrap();
+
+        let entry = journal[1];
+        assert_eq!(JournalEntry::from_bytes(&entry.to_bytes()).unwrap(), entry);
+        assert!(JournalEntry::from_bytes(&entry.to_bytes()[1..]).is_err());
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let (journal, blocks) = journaled(&[3, 1, 4]);
+
+        let mut replaced = blocks.clone();
+        replaced[5][0] ^= 1;
+        let err = verify_journal(&journal, &replaced).unwrap_err();
+        assert!(err.to_string().contains("generation 3"), "{}", err);
+
+        let mut reordered = blocks.clone();
+        reordered.swap(0, 4);
+        assert!(verify_journal(&journal, &reordered).is_err());
+
+        let mut extra = blocks.clone();
+        extra.push(vec![0u8; 16]);
+        assert!(verify_journal(&journal, &extra).is_err());
+
+        let mut rewritten = journal.clone();
+        rewritten[0].timestamp += 1;
+        assert!(verify_journal(&rewritten, &blocks).is_err());
+        assert!(verify_journal(&journal[1..], &blocks[3..]).is_err());
+    }
+}
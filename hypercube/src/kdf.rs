@@ -0,0 +1,284 @@
+//! Password hardening for partition secrets.
+//! When a container records KDF parameters, every secret is stretched with them
+//! (and the container's public salt) before it keys MACs, hints, the index or
+//! the fragment shuffle. Without parameters the secret is used as given.
+
+use crate::error::{HypercubeError, Result};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Container salt size in bytes
+pub const KDF_SALT_SIZE: usize = 16;
+
+/// Size of a derived partition key
+pub const KDF_KEY_SIZE: usize = 32;
+
+/// Most memory a header may ask a KDF to use (4 GiB)
+pub const MAX_KDF_MEMORY: u64 = 4 << 30;
+
+/// Most PBKDF2 iterations a header may ask for
+pub const MAX_PBKDF2_ITERATIONS: u32 = 100_000_000;
+
+/// Key derivation function and its cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum Kdf {
+    /// Argon2id (RFC 9106)
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+    /// scrypt (RFC 7914), for environments where Argon2 is not approved
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    /// PBKDF2-HMAC-SHA256 (SP 800-132), for FIPS-constrained deployments
+    Pbkdf2 { iterations: u32 },
+}
+
+impl Kdf {
+    /// Argon2id at 19 MiB, 2 passes, 1 lane
+    pub fn argon2id() -> Self {
+        Self::Argon2id {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+
+    /// scrypt at N = 2^17, r = 8, p = 1 (128 MiB)
+    pub fn scrypt() -> Self {
+        Self::Scrypt {
+            log_n: 17,
+            r: 8,
+            p: 1,
+        }
+    }
+
+    /// PBKDF2-HMAC-SHA256 at the given iteration count (600,000 when None)
+    pub fn pbkdf2(iterations: Option<u32>) -> Self {
+        Self::Pbkdf2 {
+            iterations: iterations.unwrap_or(600_000),
+        }
+    }
+
+    /// Reject cost parameters a hostile header could use to exhaust memory or time
+    pub fn validate(&self) -> Result<()> {
+        let ok = match *self {
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                (1..=16).contains(&parallelism)
+                    && (1..=64).contains(&iterations)
+                    && memory_kib >= 8 * parallelism
+                    && u64::from(memory_kib) * 1024 <= MAX_KDF_MEMORY
+            }
+            Self::Scrypt { log_n, r, p } => {
+                (1..=32).contains(&r)
+                    && (1..=16).contains(&p)
+                    && (1..32).contains(&log_n)
+                    && (128 * u64::from(r)) << log_n <= MAX_KDF_MEMORY
+            }
+            Self::Pbkdf2 { iterations } => (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations),
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(HypercubeError::InvalidHeader(format!(
+                "KDF parameters out of range: {}",
+                self
+            )))
+        }
+    }
+
+    /// Derive a partition key from a secret and the container salt
+    pub fn derive(&self, secret: &[u8], salt: &[u8]) -> Result<[u8; KDF_KEY_SIZE]> {
+        self.validate()?;
+        let mut key = [0u8; KDF_KEY_SIZE];
+        match *self {
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    argon2::Params::new(memory_kib, iterations, parallelism, Some(KDF_KEY_SIZE))
+                        .map_err(|e| HypercubeError::InvalidHeader(format!("argon2id: {}", e)))?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(secret, salt, &mut key)
+                    .map_err(|e| HypercubeError::InvalidHeader(format!("argon2id: {}", e)))?;
+            }
+            Self::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, KDF_KEY_SIZE)
+                    .map_err(|e| HypercubeError::InvalidHeader(format!("scrypt: {}", e)))?;
+                scrypt::scrypt(secret, salt, &params, &mut key)
+                    .map_err(|e| HypercubeError::InvalidHeader(format!("scrypt: {}", e)))?;
+            }
+            Self::Pbkdf2 { iterations } => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(secret, salt, iterations, &mut key);
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl std::fmt::Display for Kdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => write!(
+                f,
+                "argon2id ({} KiB, {} passes, {} lanes)",
+                memory_kib, iterations, parallelism
+            ),
+            Self::Scrypt { log_n, r, p } => write!(f, "scrypt (N=2^{}, r={}, p={})", log_n, r, p),
+            Self::Pbkdf2 { iterations } => {
+                write!(f, "pbkdf2-hmac-sha256 ({} iterations)", iterations)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Kdf {
+    type Err = HypercubeError;
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "argon2id" => Ok(Self::argon2id()),
+            "scrypt" => Ok(Self::scrypt()),
+            "pbkdf2" => Ok(Self::pbkdf2(None)),
+            _ => s
+                .strip_prefix("pbkdf2:")
+                .and_then(|iterations| iterations.parse().ok())
+                .map(|iterations| Self::pbkdf2(Some(iterations)))
+                .filter(|kdf| kdf.validate().is_ok())
+                .ok_or_else(|| HypercubeError::UnsupportedAlgorithm(format!("kdf: {}", s))),
+        }
+    }
+}
+
+/// KDF choice plus the container salt, as recorded in the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    #[serde(flatten)]
+    pub kdf: Kdf,
+    #[serde(with = "hex")]
+    pub salt: [u8; KDF_SALT_SIZE],
+}
+
+impl KdfParams {
+    /// Parameters for a new container with a fresh random salt
+    pub fn new(kdf: Kdf) -> Self {
+        let mut salt = [0u8; KDF_SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        Self { kdf, salt }
+    }
+
+    pub fn derive(&self, secret: &[u8]) -> Result<[u8; KDF_KEY_SIZE]> {
+        self.kdf.derive(secret, &self.salt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kdfs_are_deterministic_and_salted() {
+        let kdfs = [
+            Kdf::Argon2id {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            },
+            Kdf::Scrypt {
+                log_n: 4,
+                r: 8,
+                p: 1,
+            },
+            Kdf::pbkdf2(Some(10)),
+        ];
+        let salt = [1u8; KDF_SALT_SIZE];
+        let mut keys = Vec::new();
+        for kdf in kdfs {
+            let key = kdf.derive(b"passphrase", &salt).unwrap();
+            assert_eq!(key, kdf.derive(b"passphrase", &salt).unwrap());
+            assert_ne!(key, kdf.derive(b"passphrase", &[2u8; KDF_SALT_SIZE]).unwrap());
+            assert_ne!(key, kdf.derive(b"other", &salt).unwrap());
+            keys.push(key);
+        }
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[1], keys[2]);
+    }
+
+    #[test]
+    fn test_pbkdf2_vector() {
+        // RFC 7914 section 11 PBKDF2-HMAC-SHA256 vector, first 32 bytes
+        let key = Kdf::pbkdf2(Some(1)).derive(b"passwd", b"salt").unwrap();
+        assert_eq!(
+            hex::encode(key),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        assert!(Kdf::pbkdf2(Some(0)).derive(b"passwd", b"salt").is_err());
+    }
+
+    #[test]
+    fn test_hostile_parameters_rejected() {
+        for kdf in [Kdf::argon2id(), Kdf::scrypt(), Kdf::pbkdf2(None)] {
+            kdf.validate().unwrap();
+        }
+        let hostile = [
+            Kdf::Argon2id {
+                memory_kib: u32::MAX,
+                iterations: 1,
+                parallelism: 1,
+            },
+            Kdf::Argon2id {
+                memory_kib: 64,
+                iterations: 1_000_000,
+                parallelism: 1,
+            },
+            Kdf::Scrypt {
+                log_n: 30,
+                r: 8,
+                p: 1,
+            },
+            Kdf::Scrypt {
+                log_n: 4,
+                r: 8,
+                p: 1000,
+            },
+            Kdf::pbkdf2(Some(u32::MAX)),
+        ];
+        for kdf in hostile {
+            let err = kdf.derive(b"secret", &[0u8; KDF_SALT_SIZE]).unwrap_err();
+            assert!(err.to_string().contains("out of range"), "{}", kdf);
+        }
+    }
+
+    #[test]
+    fn test_params_serialization() {
+        let params = KdfParams {
+            kdf: Kdf::scrypt(),
+            salt: [0xab; KDF_SALT_SIZE],
+        };
+        let json = serde_json::to_string(&params).unwrap();
+        assert!(json.contains(r#""algorithm":"scrypt""#));
+        assert!(json.contains(&"ab".repeat(KDF_SALT_SIZE)));
+        assert_eq!(serde_json::from_str::<KdfParams>(&json).unwrap(), params);
+        assert!("pbkdf1".parse::<Kdf>().is_err());
+        assert_eq!("pbkdf2".parse::<Kdf>().unwrap(), Kdf::pbkdf2(None));
+        assert_eq!(
+            "PBKDF2:1000000".parse::<Kdf>().unwrap(),
+            Kdf::Pbkdf2 {
+                iterations: 1_000_000
+            }
+        );
+        assert!("pbkdf2:0".parse::<Kdf>().is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Key material from a cloud KMS key.
+//!
+//! Nothing is stored locally: the key MACs (or deterministically signs) a fixed message
+//! every time a secret is needed, and the result is hashed into the partition secret.
+//! Whoever the KMS policy lets use the key can open the partition, and revoking that
+//! permission locks them out. Each backend runs the vendor's command-line tool, which
+//! brings its own credentials, and is compiled in by a feature of its own:
+//! - `kms-aws`: `arn:aws:kms:...` names an HMAC_256 key, used with `aws kms generate-mac`
+//! - `kms-gcp`: `projects/.../cryptoKeyVersions/N` names an HMAC_SHA256 key version,
+//!   used with `gcloud kms mac-sign`
+//! - `kms-azure`: `https://<vault>.vault.azure.net/keys/<name>/<version>` names an RSA
+//!   key, used with `az keyvault key sign` and RS256, which is deterministic
+
+use crate::domains::KMS_MESSAGE;
+use crate::error::{HypercubeError, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+/// Which KMS a key lives in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KmsBackend {
+    Aws,
+    Gcp,
+    Azure,
+}
+
+impl KmsBackend {
+    /// Cargo feature that compiles the backend in
+    pub fn feature(self) -> &'static str {
+        match self {
+            Self::Aws => "kms-aws",
+            Self::Gcp => "kms-gcp",
+            Self::Azure => "kms-azure",
+        }
+    }
+}
+
+/// A KMS key named by its ARN, resource name or key identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KmsKey {
+    pub backend: KmsBackend,
+    pub id: String,
+}
+
+impl KmsKey {
+    /// Recognise the backend from the shape of the key name
+    pub fn parse(id: &str) -> Result<Self> {
+        let backend = if id.starts_with("arn:") && id.split(':').nth(2) == Some("kms") {
+            KmsBackend::Aws
+        } else if gcp_resource(id).is_some() {
+            KmsBackend::Gcp
+        } else if id.starts_with("https://") && id.contains(".vault.azure.") {
+            KmsBackend::Azure
+        } else {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "Unrecognised KMS key '{}' (expected an AWS ARN, a GCP key version or an \
+                 Azure Key Vault key URL)",
+                id
+            )));
+        };
+        Ok(Self {
+            backend,
+            id: id.into(),
+        })
+    }
+
+    /// The vendor command that MACs or signs the fixed message
+    pub fn command(&self) -> Result<Command> {
+        match self.backend {
+            #[cfg(feature = "kms-aws")]
+            KmsBackend::Aws => {
+                let mut command = Command::new("aws");
+                command.args(["kms", "generate-mac", "--key-id", &self.id]);
+                command.args(["--mac-algorithm", "HMAC_SHA_256", "--message", KMS_MESSAGE]);
+                command.args(["--cli-binary-format", "raw-in-base64-out"]);
+                command.args(["--query", "Mac", "--output", "text"]);
+                Ok(command)
+            }
+            #[cfg(feature = "kms-gcp")]
+            KmsBackend::Gcp => {
+                let [project, location, keyring, key, version] =
+                    gcp_resource(&self.id).expect("checked by parse");
+                let mut command = Command::new("gcloud");
+                command.args(["kms", "mac-sign", "--project", project]);
+                command.args(["--location", location, "--keyring", keyring]);
+                command.args(["--key", key, "--version", version]);
+                command.args(["--input-file", "-", "--signature-file", "-"]);
+                Ok(command)
+            }
+            #[cfg(feature = "kms-azure")]
+            KmsBackend::Azure => {
+                use base64::Engine;
+                use sha2::{Digest, Sha256};
+                let digest = Sha256::digest(KMS_MESSAGE.as_bytes());
+                let digest = base64::engine::general_purpose::STANDARD.encode(digest);
+                let mut command = Command::new("az");
+                command.args(["keyvault", "key", "sign", "--id", &self.id]);
+                command.args(["--algorithm", "RS256", "--digest", &digest]);
+                command.args(["--query", "signature", "--output", "tsv"]);
+                Ok(command)
+            }
+            #[allow(unreachable_patterns)]
+            backend => Err(HypercubeError::InvalidFormat(format!(
+                "KMS key '{}' needs hypercube built with the {} feature",
+                self.id,
+                backend.feature()
+            ))),
+        }
+    }
+
+    /// Ask the KMS for the key material; the same key always gives the same bytes
+    pub fn key_material(&self) -> Result<Zeroizing<Vec<u8>>> {
+        // Only gcloud reads the message from stdin; the others take it as an argument
+        let gcp = self.backend == KmsBackend::Gcp;
+        let mut child = self
+            .command()?
+            .stdin(if gcp { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(KMS_MESSAGE.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        let mut material = Zeroizing::new(output.stdout);
+        if !output.status.success() {
+            return Err(HypercubeError::Io(std::io::Error::other(format!(
+                "KMS command for '{}' failed ({})",
+                self.id, output.status
+            ))));
+        }
+        // The text outputs end in a newline; gcloud writes the raw MAC
+        if !gcp {
+            while material.last().is_some_and(u8::is_ascii_whitespace) {
+                material.pop();
+            }
+        }
+        if material.is_empty() {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "KMS key '{}' returned no key material",
+                self.id
+            )));
+        }
+        Ok(material)
+    }
+}
+
+/// Project, location, key ring, key and version of a GCP key version resource name
+fn gcp_resource(id: &str) -> Option<[&str; 5]> {
+    let mut parts = id.split('/');
+    let mut field = |label| {
+        (parts.next() == Some(label))
+            .then(|| parts.next())
+            .flatten()
+            .filter(|value| !value.is_empty())
+    };
+    let resource = [
+        field("projects")?,
+        field("locations")?,
+        field("keyRings")?,
+        field("cryptoKeys")?,
+        field("cryptoKeyVersions")?,
+    ];
+    parts.next().is_none().then_some(resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AWS: &str = "arn:aws:kms:eu-west-1:123456789012:key/1234abcd-12ab-34cd";
+    const GCP: &str = "projects/p/locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/1";
+    const AZURE: &str = "https://vault.vault.azure.net/keys/hypercube/0123456789abcdef";
+
+    #[test]
+    fn test_parse_kms_keys() {
+        assert_eq!(KmsKey::parse(AWS).unwrap().backend, KmsBackend::Aws);
+        assert_eq!(KmsKey::parse(GCP).unwrap().backend, KmsBackend::Gcp);
+        assert_eq!(KmsKey::parse(AZURE).unwrap().backend, KmsBackend::Azure);
+        for id in [
+            "",
+            "arn:aws:s3:::bucket",
+            "projects/p/locations/global/keyRings/r/cryptoKeys/k",
+            "projects//locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/1",
+            "projects/p/locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/1/x",
+            "https://example.com/keys/k",
+        ] {
+            assert!(KmsKey::parse(id).is_err(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_kms_commands() {
+        let args = |id: &str| -> Result<Vec<String>> {
+            let command = KmsKey::parse(id)?.command()?;
+            Ok(std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect())
+        };
+        for (id, enabled, program) in [
+            (AWS, cfg!(feature = "kms-aws"), "aws"),
+            (GCP, cfg!(feature = "kms-gcp"), "gcloud"),
+            (AZURE, cfg!(feature = "kms-azure"), "az"),
+        ] {
+            match args(id) {
+                Ok(args) => {
+                    assert!(enabled);
+                    assert_eq!(args[0], program);
+                }
+                Err(e) => {
+                    assert!(!enabled);
+                    assert!(e.to_string().contains("feature"), "{}", e);
+                }
+            }
+        }
+        #[cfg(feature = "kms-gcp")]
+        {
+            let args = args(GCP).unwrap();
+            let flag = |name: &str| &args[args.iter().position(|arg| arg == name).unwrap() + 1];
+            assert_eq!(
+                [flag("--project"), flag("--keyring"), flag("--version")],
+                ["p", "r", "1"]
+            );
+        }
+    }
+}
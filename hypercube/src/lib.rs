@@ -10,17 +10,21 @@
 //! Each partition's data goes through the following transforms:
 //!
 //! ```text
-//! Input → Compress → Segment → Fragment → Shuffle → Whiten → AONT → Sequence → AuthMAC → Output
+//! Input → Compress → Segment → Fragment → Whiten → AONT → Shuffle → Sequence → AuthMAC → Output
 //! ```
 //!
 //! - **Compress**: zstd (default), lz4, brotli, or none
 //! - **Segment**: Split into fixed-size blocks
 //! - **Fragment**: Split blocks into smaller pieces
-//! - **Shuffle**: Global Feistel shuffle of all fragments (random seed from CSPRNG)
-//! - **Whiten**: Keccak whitening (UNKEYED, deterministic)
+//! - **Whiten**: Optional; none built in, a registered `Whitener` (UNKEYED, deterministic)
 //! - **AONT**: All-or-Nothing Transform (KEYLESS)
+//! - **Shuffle**: Optional Feistel permutation of the partition's fragments, keyed
+//!   from the secret (`--shuffle fragment`); blocks are always reshuffled container-wide
 //! - **Sequence**: Add 128-bit sequence numbers
-//! - **AuthMAC**: HMAC authentication (KEYED - only step using secret)
+//! - **AuthMAC**: HMAC authentication (KEYED)
+//!
+//! Compression, whitening, the AONT and the MAC can be replaced by implementations
+//! registered with `pipeline::registry` and named in the header's `stages`.
 //!
 //! ## Example
 //!
@@ -42,6 +46,7 @@
 //! // Extract a partition
 //! let extract_opts = ExtractOptions {
 //!     secret: "my_secret".into(),
+//!     ..Default::default()
 //! };
 //! extract_from_vhc(
 //!     Path::new("output.vhc"),
@@ -50,12 +55,37 @@
 //! ).unwrap();
 //! ```
 
+pub mod archive;
+pub mod attempts;
+pub mod block;
+pub mod carrier;
+pub mod chunk;
 pub mod cli;
+pub mod concurrency;
+pub mod config;
 pub mod partition;
 pub mod cube;
+pub mod domains;
+pub mod envelope;
 pub mod error;
+pub mod file_mode;
+pub mod gf256;
 pub mod header;
+pub mod hint;
+pub mod honeypot;
+pub mod index;
+pub mod journal;
+pub mod kdf;
+pub mod kms;
+pub mod merkle;
+pub mod metrics;
 pub mod pipeline;
+pub mod progress;
+pub mod runtime;
+pub mod secret_helper;
+pub mod signature;
+pub mod threshold;
+pub mod trailer;
 pub mod vhc;
 
 pub use error::{HypercubeError, Result};
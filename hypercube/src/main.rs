@@ -1,10 +1,42 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use hypercube::archive::ArchiveOptions;
+use hypercube::attempts::default_attempt_log_path;
 use hypercube::cli::{
-    add_partition, extract_from_vhc, seal_file, show_info, AddOptions, ExtractOptions,
+    add_partition, check_manifest, check_sealed, diagnose, diff_containers, dig_container,
+    embed_container, estimate_extract, export_manifest, extract_from_vhc, list_archive,
+    lock_container, read_from_vhc, rekey_partition, replicate_container, run_daemon, run_plugin,
+    seal_file_with_progress, show_info, show_proof, sign_container, update_partition,
+    verify_container_signature, verify_freshness, verify_partition, verify_vectors,
+    wipe_partition, AddOptions, Capabilities, DaemonOptions, DiffOptions, EmbedOptions,
+    EstimateOptions, ExtractOptions, InfoOptions, ManifestOptions,
+    PartitionManifest, PluginEnv, ProofOptions, RekeyOptions, ReplicateOptions, SecretSource,
+    Severity, SignOptions, UpdateOptions, VerifyOptions, VerifySignatureOptions, WipeOptions,
+    DEFAULT_SAMPLE_BLOCKS, MODE_ENV, SECRET_HELPER_ENV, THREADS_ENV,
 };
-use hypercube::header::{Aont, Compression, HashAlgorithm};
-use std::path::PathBuf;
+use hypercube::concurrency::{set_concurrency, Concurrency};
+use hypercube::config::Config;
+use hypercube::file_mode::{parse_file_mode, set_file_mode};
+use hypercube::error::HypercubeError;
+use hypercube::honeypot::{set_audit_hook, CommandAlert, HONEYPOT_ALERT_ENV};
+use hypercube::header::{
+    Aont, AttemptPolicy, Compression, HashAlgorithm, PipelineOrder, ReshufflePolicy, Shuffle,
+};
+use hypercube::kdf::Kdf;
+use hypercube::metrics::collect_timings;
+use hypercube::partition::ByteRange;
+use hypercube::pipeline::ErasureCode;
+use hypercube::progress::{Progress, TerminalProgress};
+use hypercube::secret_helper::{SecretHelper, SecretRequest};
+use hypercube::signature::{generate_signing_key, key_fingerprint};
+use hypercube::threshold::{combine_shares, split_secret, Share};
+use hypercube::vhc::read_container_id;
+use std::ffi::OsString;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroizing;
 
 /// Version info from build.rs
 const VERSION: &str = env!("HYPERCUBE_VERSION");
@@ -12,6 +44,9 @@ const BUILD: &str = env!("HYPERCUBE_BUILD");
 const PROFILE: &str = env!("HYPERCUBE_PROFILE");
 const GIT_HASH: &str = env!("HYPERCUBE_GIT_HASH");
 
+/// Files at least this large get a progress bar on a terminal (64 MiB)
+const PROGRESS_MIN_BYTES: u64 = 64 << 20;
+
 /// Combined version string (compile-time concatenation not possible, so we build at runtime)
 fn get_version() -> &'static str {
     use std::sync::OnceLock;
@@ -27,6 +62,25 @@ struct Cli {
     #[arg(short = 'V', long)]
     version: bool,
 
+    /// Config file (defaults to $HYPERCUBE_CONFIG, then ~/.config/hypercube/config.json)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Program that supplies partition secrets not given with --secret, like a git
+    /// credential helper: `!shell snippet`, an absolute path, or NAME for hypercube-secret-NAME
+    #[arg(long, global = true)]
+    secret_helper: Option<String>,
+
+    /// Threads for checking and computing MACs and for the AONT: a number, `single` to
+    /// keep all work on one thread, or `auto` for one per core [default: auto]
+    #[arg(long, global = true, value_name = "N", value_parser = parse_threads)]
+    threads: Option<Concurrency>,
+
+    /// Permissions, in octal, for every file the command creates or overwrites, e.g. 0640
+    /// [default: 0600 for extracted data, the umask default for containers]
+    #[arg(long, global = true, value_name = "MODE", value_parser = parse_mode)]
+    mode: Option<u32>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -35,69 +89,813 @@ struct Cli {
 enum Commands {
     /// Add a partition to a VHC file
     #[command(alias = "a")]
-    Add {
-        /// Secret key for this partition
-        #[arg(long, required = true)]
-        secret: String,
+    Add(Box<AddArgs>),
+
+    /// Extract a partition from a VHC file
+    #[command(alias = "x")]
+    Extract {
+        /// Secret key for the partition (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// Container passphrase for magicless or deniable containers
+        #[arg(long, group = "passphrase_source")]
+        passphrase: Option<String>,
+
+        /// File of key material for the container passphrase, instead of --passphrase
+        #[arg(long, value_name = "PATH", group = "passphrase_source")]
+        passphrase_keyfile: Option<PathBuf>,
 
-        /// Input file to add
+        /// Input is a deniable envelope (requires --passphrase or --passphrase-keyfile)
+        #[arg(long, requires = "passphrase_source")]
+        deniable: bool,
+
+        /// The container's header is kept in this file (see `add --detached-header`)
+        #[arg(long, value_name = "PATH", conflicts_with = "deniable")]
+        detached_header: Option<PathBuf>,
+
+        /// Refuse partitions that decompress to more than this many bytes (default 1 GiB)
+        #[arg(long)]
+        max_size: Option<usize>,
+
+        /// Scan blocks in a random order rather than file order
+        #[arg(long)]
+        random_scan: bool,
+
+        /// Print how long each pipeline stage took
+        #[arg(long)]
+        timings: bool,
+
+        /// The partition was added with --archive: unpack it into the output directory
+        #[arg(long)]
+        archive: bool,
+
+        /// Restore the entries' extended attributes, other than SELinux labels and ACLs,
+        /// or their alternate data streams on Windows
+        #[arg(long, requires = "archive")]
+        xattrs: bool,
+
+        /// Restore the entries' SELinux labels
+        #[arg(long, requires = "archive")]
+        selinux: bool,
+
+        /// Restore the entries' ACLs, where the users and groups they name exist
+        #[arg(long, requires = "archive")]
+        acls: bool,
+
+        /// Input VHC file
         input: PathBuf,
 
-        /// Output VHC file (defaults to <INPUT>.vhc)
-        #[arg(value_name = "OUTPUT")]
-        output: Option<PathBuf>,
+        /// Output file (a directory with --archive)
+        output: PathBuf,
+    },
+
+    /// Write a byte range of a partition's original data to stdout
+    Cat {
+        /// Secret key for the partition (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
 
-        /// Hash algorithm for MAC
-        #[arg(long, default_value = "sha3", value_parser = parse_hash)]
-        hash: HashAlgorithm,
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
 
-        /// AONT algorithm
-        #[arg(long, default_value = "rivest", value_parser = parse_aont)]
-        aont: Aont,
+        /// First byte to write
+        #[arg(long, default_value = "0")]
+        offset: usize,
 
-        /// Compression algorithm
-        #[arg(long, default_value = "zstd", value_parser = parse_compression)]
-        compression: Compression,
+        /// Number of bytes to write (default: to the end)
+        #[arg(long)]
+        length: Option<usize>,
+
+        /// Container passphrase for magicless or deniable containers
+        #[arg(long, group = "passphrase_source")]
+        passphrase: Option<String>,
 
-        /// Hypercube dimension (N×N blocks, must be multiple of 8)
-        #[arg(long, default_value = "32")]
-        dimension: usize,
+        /// File of key material for the container passphrase, instead of --passphrase
+        #[arg(long, value_name = "PATH", group = "passphrase_source")]
+        passphrase_keyfile: Option<PathBuf>,
 
-        /// MAC size in bits (128, 256, or 512)
-        #[arg(long, default_value = "256")]
-        mac_bits: usize,
+        /// Input is a deniable envelope (requires --passphrase or --passphrase-keyfile)
+        #[arg(long, requires = "passphrase_source")]
+        deniable: bool,
 
-        /// Fill all remaining partitions with chaff
+        /// The container's header is kept in this file (see `add --detached-header`)
+        #[arg(long, value_name = "PATH", conflicts_with = "deniable")]
+        detached_header: Option<PathBuf>,
+
+        /// Refuse partitions that decompress to more than this many bytes (default 1 GiB)
         #[arg(long)]
-        seal: bool,
+        max_size: Option<usize>,
+
+        /// Scan blocks in a random order rather than file order
+        #[arg(long)]
+        random_scan: bool,
+
+        /// Input VHC file
+        input: PathBuf,
     },
 
-    /// Extract a partition from a VHC file
-    #[command(alias = "x")]
-    Extract {
-        /// Secret key for the partition
-        #[arg(long, required = true)]
-        secret: String,
+    /// List the entries of a partition added with --archive
+    Ls {
+        /// Secret key for the partition (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// Container passphrase for magicless or deniable containers
+        #[arg(long, group = "passphrase_source")]
+        passphrase: Option<String>,
+
+        /// File of key material for the container passphrase, instead of --passphrase
+        #[arg(long, value_name = "PATH", group = "passphrase_source")]
+        passphrase_keyfile: Option<PathBuf>,
+
+        /// Input is a deniable envelope (requires --passphrase or --passphrase-keyfile)
+        #[arg(long, requires = "passphrase_source")]
+        deniable: bool,
+
+        /// The container's header is kept in this file (see `add --detached-header`)
+        #[arg(long, value_name = "PATH", conflicts_with = "deniable")]
+        detached_header: Option<PathBuf>,
+
+        /// Refuse partitions that decompress to more than this many bytes (default 1 GiB)
+        #[arg(long)]
+        max_size: Option<usize>,
 
         /// Input VHC file
         input: PathBuf,
+    },
 
-        /// Output file
-        output: PathBuf,
+    /// Print a JSON manifest of a partition (sizes, content hash, blocks, algorithms),
+    /// MACed under its key, for checking extracted copies later with check-manifest
+    Manifest {
+        /// Secret key for the partition (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// Name to record for the partition
+        #[arg(long)]
+        label: Option<String>,
+
+        /// VHC file holding the partition
+        file: PathBuf,
+    },
+
+    /// Check an extracted copy against a manifest, reading only the container's header
+    CheckManifest {
+        /// Secret key for the partition (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// Manifest printed by `manifest`
+        #[arg(long)]
+        manifest: PathBuf,
+
+        /// VHC file the manifest was exported from
+        file: PathBuf,
+
+        /// Extracted copy to check
+        copy: PathBuf,
     },
 
     /// Show information about a VHC file
     #[command(alias = "i")]
     Info {
+        /// Show only format and algorithm details, safe to paste into a public bug report
+        #[arg(long)]
+        redacted: bool,
+
         /// VHC file to inspect
         file: PathBuf,
     },
 
+    /// Show the Merkle root, or a Merkle proof for one block
+    Proof {
+        /// Block to prove; without it every block is checked against the tree
+        #[arg(long)]
+        block: Option<usize>,
+
+        /// Verify against this root (hex), e.g. from a remote replica
+        #[arg(long)]
+        root: Option<String>,
+
+        /// VHC file created with --merkle
+        file: PathBuf,
+    },
+
+    /// Generate an Ed25519 signing key (public key written to <KEY>.pub)
+    Keygen {
+        /// Signing key file to create
+        key: PathBuf,
+    },
+
+    /// Sign a container with an Ed25519 key
+    Sign {
+        /// Signing key created by `keygen`
+        #[arg(long, required = true)]
+        signing_key: PathBuf,
+
+        /// Embed the signature in the container trailer instead of a detached file
+        #[arg(long, conflicts_with = "signature")]
+        embed: bool,
+
+        /// Detached signature file (defaults to <FILE>.sig)
+        #[arg(long)]
+        signature: Option<PathBuf>,
+
+        /// VHC file to sign
+        file: PathBuf,
+    },
+
+    /// Verify a container's Ed25519 signature
+    VerifySignature {
+        /// Trusted public key (<KEY>.pub from `keygen`)
+        #[arg(long, required = true)]
+        public_key: PathBuf,
+
+        /// Detached signature file (defaults to the embedded signature, then <FILE>.sig)
+        #[arg(long)]
+        signature: Option<PathBuf>,
+
+        /// VHC file to verify
+        file: PathBuf,
+    },
+
     /// Fill remaining capacity with random chaff blocks
     Seal {
         /// VHC file to seal
         file: PathBuf,
     },
+
+    /// Check that a container is a full cube of uniform blocks
+    CheckSealed {
+        /// VHC file to check
+        file: PathBuf,
+    },
+
+    /// Check a container for rollback to an older copy, using its generation counter, and
+    /// with --secret that the partition extracts intact (nothing is written)
+    Verify {
+        /// Partition secret: check its blocks' authenticated generation, not just the
+        /// header's, and decode the partition
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// Generation last seen (printed by an earlier verify); anything older is a rollback
+        #[arg(long)]
+        min_generation: Option<u32>,
+
+        /// VHC file to verify
+        file: PathBuf,
+    },
+
+    /// Compare two containers: header fields, blocks, and with --secret partition contents
+    /// (exits 1 when they differ)
+    Diff {
+        /// Secret of a partition to compare (repeat for each one)
+        #[arg(long = "secret", value_name = "SECRET")]
+        secrets: Vec<String>,
+
+        /// File of key material for a partition to compare (repeat for each one)
+        #[arg(long = "keyfile", value_name = "PATH")]
+        keyfiles: Vec<PathBuf>,
+
+        /// Older container
+        left: PathBuf,
+
+        /// Newer container
+        right: PathBuf,
+    },
+
+    /// Estimate how long a full extraction scan takes on this machine (no secret needed)
+    EstimateExtract {
+        /// Blocks to benchmark MAC checks on
+        #[arg(long, default_value_t = DEFAULT_SAMPLE_BLOCKS)]
+        sample: usize,
+
+        /// VHC file to estimate for
+        file: PathBuf,
+    },
+
+    /// List the format versions, algorithms and features this build supports
+    Capabilities {
+        /// Output format
+        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
+    },
+
+    /// Diagnose common container problems (truncation, trailer mismatch, weak chaff, ...)
+    Doctor {
+        /// VHC file to diagnose
+        file: PathBuf,
+    },
+
+    /// Known-answer test vectors from other implementations and versions
+    Kat {
+        #[command(subcommand)]
+        command: KatCommand,
+    },
+
+    /// Lock a container against further add, seal and wipe (cannot be undone)
+    Lock {
+        /// VHC file to lock
+        file: PathBuf,
+    },
+
+    /// Keep a container churning: periodically replace its chaff and reshuffle
+    Daemon {
+        /// Interval between rotations, e.g. 90s, 30m, 24h or 7d
+        #[arg(long, value_parser = parse_interval)]
+        rotate_chaff: Duration,
+
+        /// Secret the container's chaff is keyed under, as given to add (asked of
+        /// --secret-helper when neither this nor --chaff-keyfile is given)
+        #[arg(long, conflicts_with = "chaff_keyfile")]
+        chaff_secret: Option<String>,
+
+        /// File of key material for the chaff secret, as given to add
+        #[arg(long, value_name = "PATH")]
+        chaff_keyfile: Option<PathBuf>,
+
+        /// Exit after this many rotations instead of running until stopped
+        #[arg(long)]
+        rotations: Option<usize>,
+
+        /// VHC file to keep churning
+        file: PathBuf,
+    },
+
+    /// Replace a partition's data in place, keeping its block positions
+    Update {
+        /// Secret key of the partition to replace (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// Dedup secret of a deduplicating file
+        #[arg(long, conflicts_with = "dedup_keyfile")]
+        dedup_secret: Option<String>,
+
+        /// File of key material for the dedup secret, instead of --dedup-secret
+        #[arg(long, value_name = "PATH")]
+        dedup_keyfile: Option<PathBuf>,
+
+        /// File holding the new data
+        input: PathBuf,
+
+        /// VHC file to update
+        file: PathBuf,
+    },
+
+    /// Re-seal one partition under a new secret without re-adding its data
+    Rekey {
+        /// Current secret of the partition (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the current secret, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the current key material instead of a key file
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// New secret for the partition
+        #[arg(long)]
+        new_secret: Option<String>,
+
+        /// File of key material for the new secret, alone or combined with --new-secret
+        #[arg(long, value_name = "PATH")]
+        new_keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the new key material instead of a key file
+        #[arg(long, value_name = "KEY", conflicts_with = "new_keyfile")]
+        new_kms_key: Option<String>,
+
+        /// VHC file holding the partition
+        file: PathBuf,
+    },
+
+    /// Destroy one partition by overwriting its blocks with chaff
+    #[command(alias = "remove")]
+    Wipe {
+        /// Secret key of the partition to destroy (asked of --secret-helper when omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material for the partition, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+
+        /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+        #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+        kms_key: Option<String>,
+
+        /// Overwrite passes (random bytes first, fresh chaff last)
+        #[arg(long, default_value = "1")]
+        passes: usize,
+
+        /// Leave every other block where it is instead of reshuffling the block table
+        #[arg(long)]
+        no_reshuffle: bool,
+
+        /// VHC file to wipe from
+        file: PathBuf,
+    },
+
+    /// Mirror a container, copying only the blocks that differ at the destination
+    Replicate {
+        /// Hash every block on both sides instead of trusting Merkle trailers
+        #[arg(long)]
+        rehash: bool,
+
+        /// Report what would be copied without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Container to copy from
+        source: PathBuf,
+
+        /// Mirror to bring up to date (created if missing)
+        destination: PathBuf,
+    },
+
+    /// Split a partition secret into shares, any --threshold of which rebuild it
+    Share {
+        /// Shares needed to rebuild the secret
+        #[arg(long, short = 'k')]
+        threshold: u8,
+
+        /// Shares to write, one per custodian
+        #[arg(long, short = 'n')]
+        shares: u8,
+
+        /// Secret to split (read from stdin when neither this nor --keyfile is given)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// File of key material to split the secret of, alone or combined with --secret
+        #[arg(long, value_name = "PATH")]
+        keyfile: Option<PathBuf>,
+    },
+
+    /// Rebuild a partition secret from shares and print it
+    Combine {
+        /// Shares, as printed by `share` (read from stdin, one per line, when omitted)
+        shares: Vec<String>,
+    },
+
+    /// Hide a container inside a ZIP or PDF carrier file
+    Embed {
+        /// Carrier format (zip, pdf); detected from the carrier file by default
+        #[arg(long)]
+        carrier: Option<String>,
+
+        /// Container to hide
+        container: PathBuf,
+
+        /// Carrier file (left unmodified)
+        carrier_file: PathBuf,
+
+        /// Output polyglot file
+        output: PathBuf,
+    },
+
+    /// Recover a container embedded in a carrier file
+    Dig {
+        /// Carrier file produced by `embed`
+        input: PathBuf,
+
+        /// Output container file
+        output: PathBuf,
+    },
+
+    /// Any other subcommand runs `hypercube-<name>` from PATH, cargo-style
+    #[command(external_subcommand)]
+    External(Vec<OsString>),
+}
+
+/// Arguments of `add`, boxed in `Commands` as they outnumber every other command's
+#[derive(Args)]
+struct AddArgs {
+    /// Secret key for this partition (asked of --secret-helper when omitted)
+    #[arg(long)]
+    secret: Option<String>,
+
+    /// File of key material for the partition, alone or combined with --secret
+    #[arg(long, value_name = "PATH")]
+    keyfile: Option<PathBuf>,
+
+    /// KMS key that supplies the key material instead of a key file (see `hypercube::kms`)
+    #[arg(long, value_name = "KEY", conflicts_with = "keyfile")]
+    kms_key: Option<String>,
+
+    /// Input file to add (a directory with --archive)
+    input: PathBuf,
+
+    /// Output VHC file (defaults to <INPUT>.vhc)
+    #[arg(value_name = "OUTPUT")]
+    output: Option<PathBuf>,
+
+    /// Hash algorithm for MAC [default: sha3]
+    #[arg(long, value_parser = parse_hash)]
+    hash: Option<HashAlgorithm>,
+
+    /// AONT algorithm [default: rivest]
+    #[arg(long, value_parser = parse_aont)]
+    aont: Option<Aont>,
+
+    /// Compression algorithm [default: zstd]
+    #[arg(long, value_parser = parse_compression)]
+    compression: Option<Compression>,
+
+    /// Cube preset: built-in 1 (32×32, blocks sized to the input) or 2-5 (small to
+    /// archive, fixed capacities), one from the config file, or a dimension;
+    /// explicit flags override it
+    #[arg(long, conflicts_with = "dimension")]
+    cube: Option<String>,
+
+    /// Hypercube dimension (N×N blocks, must be multiple of 8) [default: 32]
+    #[arg(long)]
+    dimension: Option<usize>,
+
+    /// Partitions in a new file (multiple of 8) [default: the dimension]
+    #[arg(long, conflicts_with = "apply_recommendation")]
+    partitions: Option<u32>,
+
+    /// Blocks per partition in a new file (multiple of 8) [default: the dimension]
+    #[arg(long, conflicts_with = "apply_recommendation")]
+    blocks_per_partition: Option<u32>,
+
+    /// Block payload size in bytes for a new file (even, at least 32)
+    /// [default: sized to the input]
+    #[arg(long, conflicts_with = "apply_recommendation")]
+    block_size: Option<u32>,
+
+    /// MAC size in bits (128, 256, or 512) [default: 256]
+    #[arg(long)]
+    mac_bits: Option<usize>,
+
+    /// Fill all remaining partitions with chaff [default: from the config file]
+    #[arg(long, overrides_with = "no_seal")]
+    seal: bool,
+
+    /// Do not seal, even if the config file or cube preset asks for it
+    #[arg(long, overrides_with = "seal")]
+    no_seal: bool,
+
+    /// Record block positions in an encrypted .vhcidx sidecar for fast extraction
+    #[arg(long)]
+    index: bool,
+
+    /// Embed a keyed bloom filter so extraction can skip foreign blocks
+    #[arg(long)]
+    hint: bool,
+
+    /// Create the container without magic bytes (requires --passphrase or
+    /// --passphrase-keyfile)
+    #[arg(long, requires = "passphrase_source")]
+    magicless: bool,
+
+    /// Container passphrase for magicless or deniable containers
+    #[arg(long, group = "passphrase_source")]
+    passphrase: Option<String>,
+
+    /// File of key material for the container passphrase, instead of --passphrase
+    #[arg(long, value_name = "PATH", group = "passphrase_source")]
+    passphrase_keyfile: Option<PathBuf>,
+
+    /// Wrap the container in a random-looking envelope of a standard size (requires
+    /// --passphrase or --passphrase-keyfile)
+    #[arg(long, requires = "passphrase_source", conflicts_with_all = ["magicless", "index"])]
+    deniable: bool,
+
+    /// Keep the header in this file; the container then starts with its first block
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["deniable", "index"])]
+    detached_header: Option<PathBuf>,
+
+    /// Maintain a Merkle tree over the blocks for replica verification
+    #[arg(long)]
+    merkle: bool,
+
+    /// Shuffle layers for a new file: block, or fragment (also permutes fragments
+    /// within each partition) [default: block]
+    #[arg(long, value_parser = parse_shuffle)]
+    shuffle: Option<Shuffle>,
+
+    /// When appends reorder all blocks: always, never, or periodic:N (every Nth add);
+    /// stored in the file [default: always]
+    #[arg(long, value_parser = parse_reshuffle)]
+    reshuffle: Option<ReshufflePolicy>,
+
+    /// Harden secrets of a new file with a KDF: argon2id, scrypt, pbkdf2,
+    /// pbkdf2:ITERATIONS, or none to use secrets as given [default: argon2id]
+    #[arg(long, value_parser = parse_kdf)]
+    kdf: Option<KdfSetting>,
+
+    /// Slow down guessing at a new file's secrets from this machine: each key
+    /// derivation takes at least MIN_KDF_MS, and extract, cat and ls wait BACKOFF_MS
+    /// after a failed attempt, doubling with each further failure
+    #[arg(long, value_name = "MIN_KDF_MS:BACKOFF_MS", value_parser = parse_attempt_policy)]
+    attempt_policy: Option<AttemptPolicy>,
+
+    /// Create an append-only container: blocks are never replaced or reordered,
+    /// and every add is recorded in a hash-chained journal shown by `info`
+    #[arg(long, conflicts_with = "reshuffle")]
+    append_only: bool,
+
+    /// Create a container that every add leaves at full capacity, swapping keyed
+    /// chaff for data and topping up the rest (requires --chaff-secret or --chaff-keyfile)
+    #[arg(long, requires = "chaff_source", conflicts_with = "append_only")]
+    always_full: bool,
+
+    /// Secret keying an always-full container's chaff; needed for every add to it
+    #[arg(long, group = "chaff_source")]
+    chaff_secret: Option<String>,
+
+    /// File of key material for the chaff secret, instead of --chaff-secret
+    #[arg(long, value_name = "PATH", group = "chaff_source")]
+    chaff_keyfile: Option<PathBuf>,
+
+    /// Size a new file for this input: the dimension and block size that keep a
+    /// partition smallest on disk (see `codebreaker analyze`)
+    #[arg(long, conflicts_with_all = ["cube", "dimension"])]
+    apply_recommendation: bool,
+
+    /// Compress a new file's partitions in independent frames of this many bytes,
+    /// so `cat` only decompresses the frames it needs
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    frame_size: Option<u32>,
+
+    /// Split a new file's partitions into content-defined chunks, so `update`
+    /// only rewrites the chunks that changed
+    #[arg(long, conflicts_with = "frame_size")]
+    chunked: bool,
+
+    /// Store chunks that a new chunked file's partitions share only once
+    /// (requires --chunked and --dedup-secret or --dedup-keyfile)
+    #[arg(long, requires_all = ["chunked", "dedup_source"])]
+    dedup: bool,
+
+    /// Secret keying a deduplicating file's shared chunks; needed for every add to it
+    #[arg(long, group = "dedup_source")]
+    dedup_secret: Option<String>,
+
+    /// File of key material for the dedup secret, instead of --dedup-secret
+    #[arg(long, value_name = "PATH", group = "dedup_source")]
+    dedup_keyfile: Option<PathBuf>,
+
+    /// Derive a new file's sequence bases with HKDF from the partition key and a
+    /// per-write OS nonce, for platforms whose userspace RNG may be weakly seeded
+    #[arg(long, conflicts_with = "chunked")]
+    derived_sequence: bool,
+
+    /// Stage order for a new file, e.g.
+    /// compress,segment,fragment,aont,whiten,shuffle,sequence,mac to whiten after the
+    /// AONT; only whiten, aont and shuffle can move [default: standard order]
+    #[arg(long, value_parser = parse_pipeline_order)]
+    pipeline_order: Option<PipelineOrder>,
+
+    /// Add Reed–Solomon parity to a new file's partitions as DATA+PARITY, e.g. 6+2:
+    /// each stripe of DATA blocks gets PARITY more and survives losing any PARITY of them
+    #[arg(long, value_parser = parse_erasure, conflicts_with = "chunked")]
+    erasure: Option<ErasureCode>,
+
+    /// Store the input, a directory or a file, as an archive of named entries with
+    /// their permissions and modification times (list with `ls`)
+    #[arg(long)]
+    archive: bool,
+
+    /// Store the archive entries' extended attributes, other than SELinux labels and
+    /// ACLs, or their alternate data streams on Windows
+    #[arg(long, requires = "archive")]
+    xattrs: bool,
+
+    /// Store the archive entries' SELinux labels
+    #[arg(long, requires = "archive")]
+    selinux: bool,
+
+    /// Store the archive entries' POSIX ACLs, or their DACLs on Windows
+    #[arg(long, requires = "archive")]
+    acls: bool,
+
+    /// Read the partition back and compare it with the input before reporting success
+    /// (use before deleting the input)
+    #[arg(long)]
+    verify: bool,
+
+    /// Add partitions to an existing file that is too full for the input, instead
+    /// of failing; its blocks are left untouched
+    #[arg(long)]
+    grow: bool,
+
+    /// Plant the partition as a honeypot: its data reads back as given, but opening it
+    /// runs the alert command (`$HYPERCUBE_HONEYPOT_ALERT`, then the config's
+    /// `honeypot_alert`) with LABEL in $HYPERCUBE_HONEYPOT_LABEL
+    #[arg(long, value_name = "LABEL", conflicts_with = "chunked")]
+    honeypot: Option<String>,
+
+    /// Print how long each pipeline stage took
+    #[arg(long)]
+    timings: bool,
+}
+
+#[derive(Subcommand)]
+enum KatCommand {
+    /// Check that this build extracts every container in a vector file as expected
+    Verify {
+        /// Vector file in the hypercube-kat JSON format
+        #[arg(long)]
+        input: PathBuf,
+    },
+}
+
+impl Commands {
+    /// The container the command works on, named in its error messages
+    fn container(&self) -> Option<PathBuf> {
+        match self {
+            Commands::Add(args) => Some(
+                args.output
+                    .clone()
+                    .unwrap_or_else(|| default_output_path(&args.input)),
+            ),
+            Commands::Extract { input, .. }
+            | Commands::Cat { input, .. }
+            | Commands::Ls { input, .. } => Some(input.clone()),
+            Commands::Manifest { file, .. } | Commands::CheckManifest { file, .. } => {
+                Some(file.clone())
+            }
+            Commands::Info { file, .. }
+            | Commands::Proof { file, .. }
+            | Commands::Sign { file, .. }
+            | Commands::VerifySignature { file, .. }
+            | Commands::Seal { file, .. }
+            | Commands::CheckSealed { file, .. }
+            | Commands::Verify { file, .. }
+            | Commands::EstimateExtract { file, .. }
+            | Commands::Doctor { file, .. }
+            | Commands::Lock { file, .. }
+            | Commands::Daemon { file, .. }
+            | Commands::Update { file, .. }
+            | Commands::Rekey { file, .. }
+            | Commands::Wipe { file, .. } => Some(file.clone()),
+            Commands::Replicate { source, .. } => Some(source.clone()),
+            Commands::Embed { container, .. } => Some(container.clone()),
+            _ => None,
+        }
+    }
 }
 
 fn parse_hash(s: &str) -> Result<HashAlgorithm, String> {
@@ -108,18 +906,153 @@ fn parse_aont(s: &str) -> Result<Aont, String> {
     s.parse().map_err(|e| format!("{}", e))
 }
 
+fn parse_shuffle(s: &str) -> Result<Shuffle, String> {
+    s.parse().map_err(|e| format!("{}", e))
+}
+
+fn parse_reshuffle(s: &str) -> Result<ReshufflePolicy, String> {
+    s.parse().map_err(|e| format!("{}", e))
+}
+
+fn parse_attempt_policy(s: &str) -> Result<AttemptPolicy, String> {
+    s.parse().map_err(|e| format!("{}", e))
+}
+
+/// `--kdf` value: a KDF, or none
+#[derive(Clone, Copy)]
+struct KdfSetting(Option<Kdf>);
+
+fn parse_kdf(s: &str) -> Result<KdfSetting, String> {
+    if s.eq_ignore_ascii_case("none") {
+        return Ok(KdfSetting(None));
+    }
+    s.parse().map(|kdf| KdfSetting(Some(kdf))).map_err(|e| format!("{}", e))
+}
+
+fn parse_pipeline_order(s: &str) -> Result<PipelineOrder, String> {
+    s.parse().map_err(|e| format!("{}", e))
+}
+
+fn parse_threads(s: &str) -> Result<Concurrency, String> {
+    s.parse().map_err(|e| format!("{}", e))
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    parse_file_mode(s).map_err(|e| format!("{}", e))
+}
+
+fn parse_erasure(s: &str) -> Result<ErasureCode, String> {
+    s.parse().map_err(|e| format!("{}", e))
+}
+
 fn parse_compression(s: &str) -> Result<Compression, String> {
     s.parse().map_err(|e| format!("{}", e))
 }
 
-fn default_output_path(input: &PathBuf) -> PathBuf {
-    let mut os = input.as_os_str().to_os_string();
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    hypercube::cli::parse_interval(s).map_err(|e| format!("{}", e))
+}
+
+/// The secret source for --secret with --keyfile or --kms-key, None if neither is given
+fn secret_source(
+    secret: Option<String>,
+    keyfile: Option<PathBuf>,
+    kms_key: Option<String>,
+) -> Result<Option<SecretSource>, HypercubeError> {
+    match kms_key {
+        Some(key) => SecretSource::kms(&key, secret).map(Some),
+        None => Ok(SecretSource::new(secret, keyfile)),
+    }
+}
+
+/// The secret from an optional source, None if there is none
+fn resolve_secret(source: Option<SecretSource>) -> Result<Option<String>, HypercubeError> {
+    source.map(|source| source.resolve()).transpose()
+}
+
+/// The secret from a string option or a key file option, None if neither is given
+fn optional_secret(
+    secret: Option<String>,
+    keyfile: Option<PathBuf>,
+) -> Result<Option<String>, HypercubeError> {
+    resolve_secret(SecretSource::new(secret, keyfile))
+}
+
+/// Run `f` with the secret from --secret, --keyfile and --kms-key, or else with one from
+/// the secret helper (--secret-helper, `$HYPERCUBE_SECRET_HELPER`, then the config's
+/// `secret_helper`), which hears whether the secret worked
+fn with_secret<T>(
+    secret: Option<String>,
+    keyfile: Option<PathBuf>,
+    kms_key: Option<String>,
+    cli: &Cli,
+    container: &Path,
+    operation: &str,
+    f: impl FnOnce(String) -> Result<T, HypercubeError>,
+) -> Result<T, HypercubeError> {
+    if let Some(source) = secret_source(secret, keyfile, kms_key)? {
+        return f(source.resolve()?);
+    }
+    // A plugin calling back into hypercube passes the helper on in the environment
+    let helper = cli
+        .secret_helper
+        .clone()
+        .or_else(|| std::env::var(SECRET_HELPER_ENV).ok());
+    let command = match helper {
+        Some(command) => command,
+        None => load_config(cli.config.as_deref())?
+            .secret_helper
+            .ok_or(HypercubeError::SecretRequired)?,
+    };
+    SecretHelper::new(command).with_secret(&SecretRequest::new(container, operation), f)
+}
+
+/// Command run when a honeypot is opened: `$HYPERCUBE_HONEYPOT_ALERT`, then the config's
+/// `honeypot_alert`. A config that fails to load fails the command later, not here
+fn honeypot_alert(cli: &Cli) -> Option<String> {
+    std::env::var(HONEYPOT_ALERT_ENV)
+        .ok()
+        .or_else(|| load_config(cli.config.as_deref()).ok()?.honeypot_alert)
+}
+
+/// The config named by --config, or the default one
+fn load_config(path: Option<&Path>) -> Result<Config, HypercubeError> {
+    match path {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    }
+}
+
+/// One line of stdin without its line ending, for secrets kept off the command line
+fn read_stdin_line() -> Result<String, HypercubeError> {
+    let mut line = Zeroizing::new(String::new());
+    std::io::stdin().read_line(&mut line)?;
+    let secret = line.trim_end_matches(['\n', '\r']);
+    if secret.is_empty() {
+        return Err(HypercubeError::SecretRequired);
+    }
+    Ok(secret.to_string())
+}
+
+/// A progress bar for a file large enough to wait on, when stderr is a terminal
+fn progress_for(path: &Path) -> Progress {
+    let large = std::fs::metadata(path).is_ok_and(|meta| meta.len() >= PROGRESS_MIN_BYTES);
+    if large && std::io::stderr().is_terminal() {
+        Progress::new(TerminalProgress::new())
+    } else {
+        Progress::default()
+    }
+}
+
+fn default_output_path(input: &Path) -> PathBuf {
+    // `dir/` gives `dir.vhc` beside the directory, not a file inside it
+    let mut os = input.components().as_path().as_os_str().to_os_string();
     os.push(".vhc");
     PathBuf::from(os)
 }
 
 fn main() -> ExitCode {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
 
     // Handle --version flag
     if cli.version {
@@ -127,8 +1060,23 @@ fn main() -> ExitCode {
         return ExitCode::SUCCESS;
     }
 
+    // A plugin calling back into hypercube gets --threads and --mode in the environment
+    cli.threads = cli
+        .threads
+        .or_else(|| std::env::var(THREADS_ENV).ok()?.parse().ok());
+    if let Some(threads) = cli.threads {
+        set_concurrency(threads);
+    }
+    cli.mode = cli
+        .mode
+        .or_else(|| parse_file_mode(&std::env::var(MODE_ENV).ok()?).ok());
+    set_file_mode(cli.mode);
+    if let Some(command) = honeypot_alert(&cli) {
+        let _ = set_audit_hook(Arc::new(CommandAlert::new(command)));
+    }
+
     // Require a command if not showing version
-    let command = match cli.command {
+    let command = match cli.command.take() {
         Some(cmd) => cmd,
         None => {
             // Show help when no command provided
@@ -139,36 +1087,136 @@ fn main() -> ExitCode {
         }
     };
 
+    let container = command.container();
     let result = match command {
-        Commands::Add {
-            secret,
-            input,
-            output,
-            hash,
-            aont,
-            compression,
-            dimension,
-            mac_bits,
-            seal,
-        } => {
-            let options = AddOptions {
+        Commands::Add(args) => {
+            let AddArgs {
                 secret,
-                compression,
-                aont,
+                keyfile,
+                kms_key,
+                input,
+                output,
                 hash,
+                aont,
+                compression,
+                cube,
                 dimension,
+                partitions,
+                blocks_per_partition,
+                block_size,
                 mac_bits,
                 seal,
+                no_seal,
+                index,
+                hint,
+                magicless,
+                passphrase,
+                passphrase_keyfile,
+                deniable,
+                detached_header,
+                merkle,
+                shuffle,
+                reshuffle,
+                kdf,
+                attempt_policy,
+                append_only,
+                always_full,
+                chaff_secret,
+                chaff_keyfile,
+                apply_recommendation,
+                frame_size,
+                chunked,
+                dedup,
+                dedup_secret,
+                dedup_keyfile,
+                derived_sequence,
+                pipeline_order,
+                erasure,
+                archive,
+                xattrs,
+                selinux,
+                acls,
+                verify,
+                grow,
+                honeypot,
+                timings,
+            } = *args;
+            let mut options = AddOptions {
+                index,
+                hint,
+                magicless,
+                deniable,
+                detached_header,
+                merkle,
+                shuffle: shuffle.unwrap_or_default(),
+                reshuffle,
+                kdf: kdf.map_or(Some(Kdf::argon2id()), |KdfSetting(kdf)| kdf),
+                attempt_policy,
+                append_only,
+                always_full,
+                apply_recommendation,
+                frame_size,
+                chunked,
+                dedup,
+                derived_sequence,
+                pipeline_order: pipeline_order.unwrap_or_default(),
+                erasure,
+                archive,
+                archive_options: ArchiveOptions { xattrs, selinux, acls },
+                progress: progress_for(&input),
+                verify,
+                grow,
+                honeypot,
+                ..Default::default()
             };
+            let preset = load_config(cli.config.as_deref()).and_then(|config| {
+                options.seal = config.seal;
+                cube.map(|name| config.cube_registry()?.resolve(&name)).transpose()
+            });
 
             let output_path = output.unwrap_or_else(|| default_output_path(&input));
 
-            match add_partition(&input, &output_path, &options) {
-                Ok(block_count) => {
-                    println!("Added {} blocks to {}", block_count, output_path.display());
-                    if seal {
+            match preset.and_then(|preset| {
+                if let Some(preset) = preset {
+                    options = options.with_preset(&preset);
+                }
+                options.hash = hash.unwrap_or(options.hash);
+                options.aont = aont.unwrap_or(options.aont);
+                options.compression = compression.unwrap_or(options.compression);
+                // Header sizes are capped at 32 bits anyway
+                let given = |value: Option<u32>| value.map(|value| value as usize);
+                options.dimension = dimension.unwrap_or(options.dimension);
+                options.partitions = given(partitions).or(options.partitions);
+                options.blocks_per_partition =
+                    given(blocks_per_partition).or(options.blocks_per_partition);
+                options.block_size = given(block_size).or(options.block_size);
+                options.mac_bits = mac_bits.unwrap_or(options.mac_bits);
+                if seal || no_seal {
+                    options.seal = seal;
+                }
+                options.passphrase = optional_secret(passphrase, passphrase_keyfile)?;
+                options.chaff_secret = optional_secret(chaff_secret, chaff_keyfile)?;
+                options.dedup_secret = optional_secret(dedup_secret, dedup_keyfile)?;
+                with_secret(secret, keyfile, kms_key, &cli, &output_path, "add", |secret| {
+                    options.secret = secret;
+                    let (added, stages) =
+                        collect_timings(|| add_partition(&input, &output_path, &options));
+                    added.map(|count| (count, stages))
+                })
+                .map(|(count, stages)| (count, options.seal, stages))
+            }) {
+                Ok((report, sealed, stages)) => {
+                    println!("Added {} blocks to {}", report.blocks, output_path.display());
+                    print!("{}", report);
+                    if sealed {
                         println!("File sealed with chaff blocks");
                     }
+                    if verify {
+                        println!("Verified: the partition reads back identical to the input");
+                    }
+                    if timings {
+                        print!("{}", stages);
+                    }
                     Ok(())
                 }
                 Err(e) => Err(e),
@@ -177,21 +1225,175 @@ fn main() -> ExitCode {
 
         Commands::Extract {
             secret,
+            keyfile,
+            kms_key,
+            passphrase,
+            passphrase_keyfile,
+            deniable,
+            detached_header,
+            max_size,
+            random_scan,
+            timings,
+            archive,
+            xattrs,
+            selinux,
+            acls,
             input,
             output,
         } => {
-            let options = ExtractOptions { secret };
-
-            match extract_from_vhc(&input, &output, &options) {
-                Ok(_) => {
+            let passphrase = optional_secret(passphrase, passphrase_keyfile);
+            let extracted = passphrase.and_then(|passphrase| {
+                with_secret(secret, keyfile, kms_key, &cli, &input, "extract", |s| {
+                    let options = ExtractOptions {
+                        secret: s,
+                        passphrase,
+                        deniable,
+                        detached_header,
+                        max_size,
+                        attempt_log: default_attempt_log_path(),
+                        random_scan,
+                        archive,
+                        archive_options: ArchiveOptions { xattrs, selinux, acls },
+                        progress: progress_for(&input),
+                        ..Default::default()
+                    };
+                    let (extracted, stages) =
+                        collect_timings(|| extract_from_vhc(&input, &output, &options));
+                    extracted.map(|_| stages)
+                })
+            });
+            match extracted {
+                Ok(stages) => {
                     println!("Extracted to {}", output.display());
+                    if timings {
+                        print!("{}", stages);
+                    }
                     Ok(())
                 }
                 Err(e) => Err(e),
             }
         }
 
-        Commands::Info { file } => match show_info(&file) {
+        Commands::Cat {
+            secret,
+            keyfile,
+            kms_key,
+            offset,
+            length,
+            passphrase,
+            passphrase_keyfile,
+            deniable,
+            detached_header,
+            max_size,
+            random_scan,
+            input,
+        } => {
+            let passphrase = optional_secret(passphrase, passphrase_keyfile);
+            let data = passphrase.and_then(|passphrase| {
+                with_secret(secret, keyfile, kms_key, &cli, &input, "cat", |secret| {
+                    let options = ExtractOptions {
+                        secret,
+                        passphrase,
+                        deniable,
+                        detached_header,
+                        max_size,
+                        attempt_log: default_attempt_log_path(),
+                        range: ByteRange { offset, length },
+                        random_scan,
+                        ..Default::default()
+                    };
+                    read_from_vhc(&input, &options)
+                })
+            });
+            data.and_then(|data| {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+                Ok(())
+            })
+        }
+
+        Commands::Ls {
+            secret,
+            keyfile,
+            kms_key,
+            passphrase,
+            passphrase_keyfile,
+            deniable,
+            detached_header,
+            max_size,
+            input,
+        } => {
+            let passphrase = optional_secret(passphrase, passphrase_keyfile);
+            let listed = passphrase.and_then(|passphrase| {
+                with_secret(secret, keyfile, kms_key, &cli, &input, "ls", |secret| {
+                    let options = ExtractOptions {
+                        secret,
+                        passphrase,
+                        deniable,
+                        detached_header,
+                        max_size,
+                        attempt_log: default_attempt_log_path(),
+                        ..Default::default()
+                    };
+                    list_archive(&input, &options)
+                })
+            });
+            listed.map(|archive| {
+                for entry in &archive.entries {
+                    println!("{}", entry);
+                }
+            })
+        }
+
+        Commands::Manifest {
+            secret,
+            keyfile,
+            kms_key,
+            label,
+            file,
+        } => {
+            let manifest = with_secret(
+                secret,
+                keyfile,
+                kms_key,
+                &cli,
+                &file,
+                "manifest",
+                |secret| export_manifest(&file, &ManifestOptions { secret, label }),
+            );
+            manifest.and_then(|manifest| {
+                println!("{}", serde_json::to_string_pretty(&manifest)?);
+                Ok(())
+            })
+        }
+
+        Commands::CheckManifest {
+            secret,
+            keyfile,
+            kms_key,
+            manifest,
+            file,
+            copy,
+        } => {
+            let checked = std::fs::read(&manifest)
+                .map_err(HypercubeError::from)
+                .and_then(|json| Ok(serde_json::from_slice::<PartitionManifest>(&json)?))
+                .and_then(|manifest| {
+                    with_secret(
+                        secret,
+                        keyfile,
+                        kms_key,
+                        &cli,
+                        &file,
+                        "check-manifest",
+                        |s| check_manifest(&file, &manifest, &s, &copy),
+                    )
+                });
+            checked.map(|()| println!("{} matches the manifest", copy.display()))
+        }
+
+        Commands::Info { redacted, file } => match show_info(&file, &InfoOptions { redacted }) {
             Ok(info) => {
                 print!("{}", info);
                 Ok(())
@@ -199,7 +1401,69 @@ fn main() -> ExitCode {
             Err(e) => Err(e),
         },
 
-        Commands::Seal { file } => match seal_file(&file) {
+        Commands::Proof { block, root, file } => {
+            let options = ProofOptions { block, root };
+            match show_proof(&file, &options) {
+                Ok(report) => {
+                    print!("{}", report);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Keygen { key } => match generate_signing_key(&key) {
+            Ok(public) => {
+                println!(
+                    "Wrote signing key {} and public key {}",
+                    key.display(),
+                    public.display()
+                );
+                Ok(())
+            }
+            Err(e) => Err(e),
+        },
+
+        Commands::Sign {
+            signing_key,
+            embed,
+            signature,
+            file,
+        } => {
+            let options = SignOptions { embed, signature };
+            match sign_container(&file, &signing_key, &options) {
+                Ok(signature) => {
+                    println!(
+                        "Signed {} with key {}",
+                        file.display(),
+                        key_fingerprint(&signature.public_key)
+                    );
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::VerifySignature {
+            public_key,
+            signature,
+            file,
+        } => {
+            let options = VerifySignatureOptions { signature };
+            match verify_container_signature(&file, &public_key, &options) {
+                Ok(signature) => {
+                    println!(
+                        "Signature OK: {} signed by {}",
+                        file.display(),
+                        key_fingerprint(&signature.public_key)
+                    );
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Seal { file } => match seal_file_with_progress(&file, &progress_for(&file)) {
             Ok(0) => {
                 println!("{} is already full", file.display());
                 Ok(())
@@ -210,12 +1474,398 @@ fn main() -> ExitCode {
             }
             Err(e) => Err(e),
         },
+
+        Commands::CheckSealed { file } => match check_sealed(&file) {
+            Ok(check) if check.is_sealed() => {
+                println!("{} is sealed: {}", file.display(), check);
+                Ok(())
+            }
+            Ok(check) => Err(HypercubeError::IntegrityError(format!(
+                "{} is not sealed: {}",
+                file.display(),
+                check
+            ))),
+            Err(e) => Err(e),
+        },
+
+        Commands::Verify {
+            secret,
+            keyfile,
+            kms_key,
+            min_generation,
+            file,
+        } => secret_source(secret, keyfile, kms_key)
+            .and_then(resolve_secret)
+            .and_then(|secret| {
+                let options = VerifyOptions {
+                    secret,
+                    min_generation,
+                };
+                match verify_freshness(&file, &options) {
+                    Ok(report) if !report.is_fresh() => {
+                        print!("{}", report);
+                        Err(HypercubeError::IntegrityError(format!(
+                            "{} looks rolled back",
+                            file.display()
+                        )))
+                    }
+                    Ok(report) => {
+                        print!("{}", report);
+                        match options.secret.as_deref().map(|s| verify_partition(&file, s)) {
+                            Some(Ok(partition)) => {
+                                print!("{}", partition);
+                                if partition.is_ok() {
+                                    println!("Partition OK");
+                                    Ok(())
+                                } else {
+                                    Err(HypercubeError::IntegrityError(
+                                        "Partition does not extract intact".into(),
+                                    ))
+                                }
+                            }
+                            Some(Err(e)) => Err(e),
+                            None => Ok(()),
+                        }
+                    }
+                    Err(e) => Err(e),
+                }
+            }),
+
+        Commands::Diff {
+            secrets,
+            keyfiles,
+            left,
+            right,
+        } => {
+            let keyed: Result<Vec<_>, _> = keyfiles
+                .into_iter()
+                .map(|keyfile| SecretSource::Keyfile(keyfile).resolve())
+                .collect();
+            let diff = keyed.and_then(|keyed| {
+                let secrets = secrets.into_iter().chain(keyed).collect();
+                diff_containers(&left, &right, &DiffOptions { secrets })
+            });
+            match diff {
+                Ok(diff) => {
+                    print!("{}", diff);
+                    if !diff.is_empty() {
+                        return ExitCode::from(1);
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::EstimateExtract { sample, file } => {
+            let options = EstimateOptions {
+                sample: Some(sample),
+            };
+            match estimate_extract(&file, &options) {
+                Ok(estimate) => {
+                    print!("{}", estimate);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Capabilities { format } => {
+            let capabilities = Capabilities::current();
+            match format.as_str() {
+                "json" => serde_json::to_string_pretty(&capabilities)
+                    .map(|json| println!("{}", json))
+                    .map_err(HypercubeError::from),
+                _ => {
+                    print!("{}", capabilities);
+                    Ok(())
+                }
+            }
+        }
+
+        Commands::Doctor { file } => match diagnose(&file) {
+            Ok(report) => {
+                print!("{}", report);
+                let errors = report.count(Severity::Error);
+                if errors == 0 {
+                    println!("{}: no errors", file.display());
+                    Ok(())
+                } else {
+                    Err(HypercubeError::IntegrityError(format!(
+                        "{} problems found in {}",
+                        errors,
+                        file.display()
+                    )))
+                }
+            }
+            Err(e) => Err(e),
+        },
+
+        Commands::Kat {
+            command: KatCommand::Verify { input },
+        } => match verify_vectors(&input) {
+            Ok(report) => {
+                print!("{}", report);
+                let failures = report.failures();
+                if failures == 0 {
+                    println!("{} vectors passed", report.outcomes.len());
+                    Ok(())
+                } else {
+                    Err(HypercubeError::IntegrityError(format!(
+                        "{} of {} vectors failed",
+                        failures,
+                        report.outcomes.len()
+                    )))
+                }
+            }
+            Err(e) => Err(e),
+        },
+
+        Commands::Lock { file } => match lock_container(&file) {
+            Ok(true) => {
+                println!("Locked {}", file.display());
+                Ok(())
+            }
+            Ok(false) => {
+                println!("{} is already locked", file.display());
+                Ok(())
+            }
+            Err(e) => Err(e),
+        },
+
+        Commands::Daemon {
+            rotate_chaff,
+            chaff_secret,
+            chaff_keyfile,
+            rotations,
+            file,
+        } => {
+            let id = read_container_id(&file).map_or(String::new(), |id| format!(" ({})", id));
+            with_secret(chaff_secret, chaff_keyfile, None, &cli, &file, "daemon", |secret| {
+                let options = DaemonOptions {
+                    rotate_chaff,
+                    chaff_secret: secret,
+                    rotations,
+                };
+                run_daemon(&file, &options, |rotation| {
+                    println!(
+                        "Rotation {}: replaced {} chaff blocks in {}{}",
+                        rotation.number,
+                        rotation.replaced,
+                        file.display(),
+                        id
+                    )
+                })
+            })
+            .map(drop)
+        }
+
+        Commands::Update {
+            secret,
+            keyfile,
+            kms_key,
+            dedup_secret,
+            dedup_keyfile,
+            input,
+            file,
+        } => {
+            let dedup_secret = optional_secret(dedup_secret, dedup_keyfile);
+            let updated = dedup_secret.and_then(|dedup_secret| {
+                with_secret(secret, keyfile, kms_key, &cli, &file, "update", |secret| {
+                    let options = UpdateOptions {
+                        secret,
+                        dedup_secret,
+                    };
+                    update_partition(&input, &file, &options)
+                })
+            });
+            match updated {
+                Ok(count) => {
+                    println!("Rewrote {} blocks in {}", count, file.display());
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Rekey {
+            secret,
+            keyfile,
+            kms_key,
+            new_secret,
+            new_keyfile,
+            new_kms_key,
+            file,
+        } => {
+            let rekeyed = secret_source(new_secret, new_keyfile, new_kms_key)
+                .and_then(|source| source.ok_or(HypercubeError::SecretRequired)?.resolve())
+                .and_then(|new_secret| {
+                    with_secret(secret, keyfile, kms_key, &cli, &file, "rekey", |secret| {
+                        let options = RekeyOptions { secret, new_secret };
+                        rekey_partition(&file, &options)
+                    })
+                });
+            match rekeyed {
+                Ok(count) => {
+                    println!("Re-keyed {} blocks in {}", count, file.display());
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Wipe {
+            secret,
+            keyfile,
+            kms_key,
+            passes,
+            no_reshuffle,
+            file,
+        } => {
+            let wiped = with_secret(secret, keyfile, kms_key, &cli, &file, "wipe", |secret| {
+                let options = WipeOptions {
+                    secret,
+                    passes,
+                    reshuffle: !no_reshuffle,
+                };
+                wipe_partition(&file, &options)
+            });
+            match wiped {
+                Ok(count) => {
+                    println!("Wiped {} blocks from {}", count, file.display());
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Replicate {
+            rehash,
+            dry_run,
+            source,
+            destination,
+        } => {
+            let options = ReplicateOptions { rehash, dry_run };
+            match replicate_container(&source, &destination, &options) {
+                Ok(report) => {
+                    let verb = if dry_run { "Would copy" } else { "Copied" };
+                    if report.full_copy {
+                        let (blocks, to) = (report.blocks, destination.display());
+                        println!("{} all {} blocks to {}", verb, blocks, to);
+                    } else {
+                        println!(
+                            "{} {} of {} blocks to {}{}",
+                            verb,
+                            report.copied,
+                            report.blocks,
+                            destination.display(),
+                            if report.metadata { " (header and trailer too)" } else { "" }
+                        );
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Share {
+            threshold,
+            shares,
+            secret,
+            keyfile,
+        } => {
+            let secret = match SecretSource::new(secret, keyfile) {
+                Some(source) => source.resolve(),
+                None => read_stdin_line(),
+            };
+            secret
+                .map(Zeroizing::new)
+                .and_then(|secret| split_secret(secret.as_bytes(), threshold, shares))
+                .map(|shares| {
+                    for share in shares {
+                        println!("{}", share);
+                    }
+                })
+        }
+
+        Commands::Combine { shares } => {
+            let shares = if shares.is_empty() {
+                std::io::stdin()
+                    .lines()
+                    .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(HypercubeError::from)
+            } else {
+                Ok(shares)
+            };
+            shares
+                .and_then(|shares| shares.iter().map(|share| share.parse()).collect())
+                .and_then(|shares: Vec<Share>| combine_shares(&shares))
+                .and_then(|secret| {
+                    let secret = std::str::from_utf8(&secret).map_err(|_| {
+                        HypercubeError::InvalidFormat("Combined secret is not UTF-8".into())
+                    })?;
+                    println!("{}", secret);
+                    Ok(())
+                })
+        }
+
+        Commands::Embed {
+            carrier,
+            container,
+            carrier_file,
+            output,
+        } => {
+            let options = EmbedOptions { carrier };
+            match embed_container(&container, &carrier_file, &output, &options) {
+                Ok(name) => {
+                    println!(
+                        "Embedded {} in {} carrier {}",
+                        container.display(),
+                        name,
+                        output.display()
+                    );
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::External(args) => {
+            let env = PluginEnv {
+                config: cli.config.clone(),
+                secret_helper: cli.secret_helper.clone(),
+                threads: cli.threads,
+                mode: cli.mode,
+            };
+            match run_plugin(&args, &env) {
+                Ok(code) => return ExitCode::from(u8::try_from(code).unwrap_or(1)),
+                Err(e) => Err(e),
+            }
+        }
+
+        Commands::Dig { input, output } => match dig_container(&input, &output) {
+            Ok((name, size)) => {
+                println!(
+                    "Recovered {} bytes from {} carrier to {}",
+                    size,
+                    name,
+                    output.display()
+                );
+                Ok(())
+            }
+            Err(e) => Err(e),
+        },
     };
 
     match result {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            eprintln!("Error: {}", e);
+            // Name the container by id too, so the error can be traced whatever its path
+            match container.as_deref().and_then(read_container_id) {
+                Some(id) => eprintln!("Error: {} (container {})", e, id),
+                None => eprintln!("Error: {}", e),
+            }
             ExitCode::FAILURE
         }
     }
@@ -0,0 +1,176 @@
+/// Merkle tree over stored blocks
+/// Leaves and nodes are domain separated (RFC 6962 style) so a node can never
+/// be passed off as a block; an odd node at the end of a level is promoted as-is
+pub type Hash = [u8; 32];
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Hash of one stored block (sequence + data + MAC)
+pub fn leaf_hash(block: &[u8]) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(block);
+    *hasher.finalize().as_bytes()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// levels[0] are the leaves, the last level is the root
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    /// Build the tree from leaf hashes
+    pub fn from_leaves(leaves: Vec<Hash>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// Build the tree from stored blocks
+    pub fn from_blocks(blocks: &[Vec<u8>]) -> Self {
+        Self::from_leaves(blocks.iter().map(|block| leaf_hash(block)).collect())
+    }
+
+    /// Root hash; an empty tree has the hash of no data
+    pub fn root(&self) -> Hash {
+        match self.levels.last().and_then(|level| level.first()) {
+            Some(root) => *root,
+            None => *blake3::hash(&[]).as_bytes(),
+        }
+    }
+
+    pub fn leaves(&self) -> &[Hash] {
+        &self.levels[0]
+    }
+
+    /// Audit path for one leaf: O(log n) sibling hashes
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaves().len() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = position ^ 1;
+            if sibling < level.len() {
+                siblings.push(level[sibling]);
+            }
+            position /= 2;
+        }
+        Some(MerkleProof {
+            index,
+            leaf_count: self.leaves().len(),
+            siblings,
+        })
+    }
+}
+
+/// Proof that a block sits at `index` under a given root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub leaf_count: usize,
+    /// Sibling hashes from the leaf level upwards (promoted levels have none)
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Recompute the root from a block and compare
+    pub fn verify(&self, block: &[u8], root: &Hash) -> bool {
+        self.root_for(leaf_hash(block)).as_ref() == Some(root)
+    }
+
+    fn root_for(&self, leaf: Hash) -> Option<Hash> {
+        if self.index >= self.leaf_count {
+            return None;
+        }
+        let mut hash = leaf;
+        let mut position = self.index;
+        let mut width = self.leaf_count;
+        let mut siblings = self.siblings.iter();
+        while width > 1 {
+            let sibling = position ^ 1;
+            if sibling < width {
+                let other = siblings.next()?;
+                hash = if position.is_multiple_of(2) {
+                    node_hash(&hash, other)
+                } else {
+                    node_hash(other, &hash)
+                };
+            }
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+        siblings.next().is_none().then_some(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|i| vec![i as u8; 40]).collect()
+    }
+
+    #[test]
+    fn test_every_proof_verifies() {
+        for count in [1, 2, 3, 5, 8, 13] {
+            let blocks = blocks(count);
+            let tree = MerkleTree::from_blocks(&blocks);
+            let root = tree.root();
+            for (index, block) in blocks.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.siblings.len() <= (count as f64).log2().ceil() as usize);
+                assert!(
+                    proof.verify(block, &root),
+                    "count {} index {}",
+                    count,
+                    index
+                );
+                assert!(!proof.verify(&blocks[(index + 1) % count], &root) || count == 1);
+            }
+            assert!(tree.proof(count).is_none());
+        }
+    }
+
+    #[test]
+    fn test_root_changes_with_any_block() {
+        let mut blocks = blocks(8);
+        let root = MerkleTree::from_blocks(&blocks).root();
+        blocks[5][0] ^= 1;
+        assert_ne!(MerkleTree::from_blocks(&blocks).root(), root);
+    }
+
+    #[test]
+    fn test_tampered_proof_rejected() {
+        let blocks = blocks(6);
+        let tree = MerkleTree::from_blocks(&blocks);
+        let mut proof = tree.proof(2).unwrap();
+        proof.siblings[0][0] ^= 1;
+        assert!(!proof.verify(&blocks[2], &tree.root()));
+        proof.siblings.pop();
+        assert!(!proof.verify(&blocks[2], &tree.root()));
+    }
+}
@@ -0,0 +1,388 @@
+//! Metrics hooks for services that embed the crate.
+//!
+//! With the `metrics` feature, container operations report counters and stage durations to
+//! the recorder installed with `set_recorder`. `PrometheusRecorder` keeps them in memory and
+//! renders the Prometheus text format; any other backend implements `Recorder`. Without the
+//! feature counters compile to nothing.
+//!
+//! Independently of the feature, `collect_timings` sums the stage durations of one operation
+//! on the calling thread, for the CLI's `--timings` report. Outside it, and without a
+//! recorder, a timed stage costs one thread-local check.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Instant;
+#[cfg(feature = "metrics")]
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Blocks whose MAC was checked against a secret
+pub const BLOCKS_SCANNED: &str = "hypercube_blocks_scanned_total";
+/// Blocks whose MAC did not match (chaff and other partitions included)
+pub const MAC_FAILURES: &str = "hypercube_mac_failures_total";
+/// Input bytes written to partitions by `add` and `update`
+pub const BYTES_ADDED: &str = "hypercube_bytes_added_total";
+/// Original bytes read back by `extract` and `cat`
+pub const BYTES_EXTRACTED: &str = "hypercube_bytes_extracted_total";
+/// Histogram of pipeline stage durations, labelled by stage
+pub const STAGE_DURATION: &str = "hypercube_stage_duration_seconds";
+
+/// Pipeline stages timed under `STAGE_DURATION`
+/// `Seal` and `Open` span the finer stages from `Segment` to `Mac`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// Compressing a payload
+    Compress,
+    /// Splitting a payload into blocks, or joining them back
+    Segment,
+    /// Fragmenting blocks and reassembling them
+    Fragment,
+    /// Whitening, the all-or-nothing transform and the fragment shuffle, either way
+    Aont,
+    /// Numbering blocks, or checking and stripping the numbers
+    Sequence,
+    /// Computing block MACs, or checking every block's MAC against a secret
+    Mac,
+    /// Segment, fragment, AONT, shuffle, sequence and MAC
+    Seal,
+    /// The reverse of `Seal` for authenticated blocks
+    Open,
+    /// Decompressing a payload
+    Decompress,
+    /// Reading and writing container files
+    Io,
+}
+
+impl Stage {
+    /// Label value used for the stage
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Compress => "compress",
+            Stage::Segment => "segment",
+            Stage::Fragment => "fragment",
+            Stage::Aont => "aont",
+            Stage::Sequence => "sequence",
+            Stage::Mac => "mac",
+            Stage::Seal => "seal",
+            Stage::Open => "open",
+            Stage::Decompress => "decompress",
+            Stage::Io => "io",
+        }
+    }
+
+    /// Whether the stage only spans finer ones
+    pub fn is_composite(&self) -> bool {
+        matches!(self, Stage::Seal | Stage::Open)
+    }
+}
+
+/// Receives the crate's metrics; implementations must be cheap, as MAC checks count per block
+pub trait Recorder: Send + Sync {
+    /// Add `value` to the counter `name`
+    fn increment_counter(&self, name: &'static str, value: u64);
+    /// Record one run of `stage` that took `seconds`
+    fn record_duration(&self, stage: Stage, seconds: f64);
+}
+
+#[cfg(feature = "metrics")]
+static RECORDER: OnceLock<Arc<dyn Recorder>> = OnceLock::new();
+
+/// Install the process-wide recorder; only the first call succeeds
+/// Returns the recorder back if one is already installed
+#[cfg(feature = "metrics")]
+pub fn set_recorder(recorder: Arc<dyn Recorder>) -> Result<(), Arc<dyn Recorder>> {
+    RECORDER.set(recorder)
+}
+
+/// Add `value` to the counter `name`
+#[inline]
+pub fn counter(name: &'static str, value: u64) {
+    #[cfg(feature = "metrics")]
+    if let Some(recorder) = RECORDER.get() {
+        recorder.increment_counter(name, value);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (name, value);
+}
+
+/// Run `f`, recording how long it took as one run of `stage`
+#[inline]
+pub fn time<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "metrics")]
+    let recording = RECORDER.get().is_some();
+    #[cfg(not(feature = "metrics"))]
+    let recording = false;
+    if !recording && !COLLECTOR.with(|collector| collector.borrow().is_some()) {
+        return f();
+    }
+
+    let start = Instant::now();
+    let value = f();
+    let seconds = start.elapsed().as_secs_f64();
+    #[cfg(feature = "metrics")]
+    if let Some(recorder) = RECORDER.get() {
+        recorder.record_duration(stage, seconds);
+    }
+    COLLECTOR.with(|collector| {
+        if let Some(timings) = collector.borrow_mut().as_mut() {
+            let (total, runs) = timings.stages.entry(stage).or_default();
+            *total += seconds;
+            *runs += 1;
+        }
+    });
+    value
+}
+
+thread_local! {
+    static COLLECTOR: RefCell<Option<StageTimings>> = const { RefCell::new(None) };
+}
+
+/// Stage durations summed over one operation by `collect_timings`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StageTimings {
+    /// Total seconds and number of runs per stage
+    pub stages: BTreeMap<Stage, (f64, u64)>,
+    /// Wall time of the whole operation, in seconds
+    pub total: f64,
+}
+
+impl StageTimings {
+    /// Seconds spent outside the finer stages (key derivation, chaff, bookkeeping)
+    pub fn other(&self) -> f64 {
+        let staged: f64 = self
+            .stages
+            .iter()
+            .filter(|(stage, _)| !stage.is_composite())
+            .map(|(_, &(seconds, _))| seconds)
+            .sum();
+        (self.total - staged).max(0.0)
+    }
+}
+
+impl std::fmt::Display for StageTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let share = |seconds: f64| {
+            if self.total > 0.0 {
+                100.0 * seconds / self.total
+            } else {
+                0.0
+            }
+        };
+        writeln!(f, "Timings:")?;
+        for (stage, &(seconds, runs)) in &self.stages {
+            if stage.is_composite() {
+                continue;
+            }
+            writeln!(
+                f,
+                "  {:<10} {:>10.3} ms {:>5.1}%  ({} run{})",
+                stage.as_str(),
+                seconds * 1000.0,
+                share(seconds),
+                runs,
+                if runs == 1 { "" } else { "s" }
+            )?;
+        }
+        let other = self.other();
+        writeln!(f, "  {:<10} {:>10.3} ms {:>5.1}%", "other", other * 1000.0, share(other))?;
+        writeln!(f, "  {:<10} {:>10.3} ms", "total", self.total * 1000.0)
+    }
+}
+
+/// Run `f` and sum how long each stage took on this thread while it ran
+/// Works with or without the `metrics` feature, alongside any installed recorder
+pub fn collect_timings<T>(f: impl FnOnce() -> T) -> (T, StageTimings) {
+    let outer = COLLECTOR.with(|collector| collector.replace(Some(StageTimings::default())));
+    let start = Instant::now();
+    let value = f();
+    let total = start.elapsed().as_secs_f64();
+    let timings = COLLECTOR.with(|collector| collector.replace(outer));
+    let timings = StageTimings {
+        total,
+        ..timings.unwrap_or_default()
+    };
+    (value, timings)
+}
+
+/// Upper bounds of the duration histogram's buckets, in seconds
+#[cfg(feature = "metrics")]
+const BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// In-memory recorder that renders the Prometheus text exposition format
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct PrometheusRecorder {
+    counters: Mutex<BTreeMap<&'static str, u64>>,
+    durations: Mutex<BTreeMap<Stage, Histogram>>,
+}
+
+#[cfg(feature = "metrics")]
+impl PrometheusRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current value of a counter; 0 if it was never incremented
+    pub fn counter_value(&self, name: &str) -> u64 {
+        let counters = self.counters.lock().unwrap();
+        counters.get(name).copied().unwrap_or(0)
+    }
+
+    /// Every metric recorded so far, for a `/metrics` endpoint
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (name, value) in self.counters.lock().unwrap().iter() {
+            output.push_str(&format!("# TYPE {} counter\n{} {}\n", name, name, value));
+        }
+
+        let durations = self.durations.lock().unwrap();
+        if !durations.is_empty() {
+            output.push_str(&format!("# TYPE {} histogram\n", STAGE_DURATION));
+        }
+        for (stage, histogram) in durations.iter() {
+            let stage = stage.as_str();
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                output.push_str(&format!(
+                    "{}_bucket{{stage=\"{}\",le=\"{}\"}} {}\n",
+                    STAGE_DURATION, stage, bound, cumulative
+                ));
+            }
+            output.push_str(&format!(
+                "{}_bucket{{stage=\"{}\",le=\"+Inf\"}} {}\n",
+                STAGE_DURATION, stage, histogram.count
+            ));
+            output.push_str(&format!(
+                "{}_sum{{stage=\"{}\"}} {}\n",
+                STAGE_DURATION, stage, histogram.sum
+            ));
+            output.push_str(&format!(
+                "{}_count{{stage=\"{}\"}} {}\n",
+                STAGE_DURATION, stage, histogram.count
+            ));
+        }
+        output
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Recorder for PrometheusRecorder {
+    fn increment_counter(&self, name: &'static str, value: u64) {
+        *self.counters.lock().unwrap().entry(name).or_default() += value;
+    }
+
+    fn record_duration(&self, stage: Stage, seconds: f64) {
+        let mut durations = self.durations.lock().unwrap();
+        let histogram = durations.entry(stage).or_default();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| seconds <= bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::VhcHeader;
+    use crate::partition::{create_partition, extract_partition};
+
+    #[test]
+    fn test_collect_timings() {
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let ((), timings) = collect_timings(|| {
+            let blocks = create_partition(b"timed", b"secret", &header, Some(4))
+                .unwrap()
+                .blocks;
+            extract_partition(&blocks, b"secret", &header).unwrap();
+        });
+        for stage in [
+            Stage::Compress,
+            Stage::Segment,
+            Stage::Fragment,
+            Stage::Aont,
+            Stage::Sequence,
+            Stage::Mac,
+            Stage::Seal,
+            Stage::Open,
+            Stage::Decompress,
+        ] {
+            assert!(timings.stages.contains_key(&stage), "{}", stage.as_str());
+        }
+        assert_eq!(timings.stages[&Stage::Seal].1, 1);
+        assert_eq!(timings.stages[&Stage::Fragment].1, 4);
+        assert!(timings.other() <= timings.total);
+
+        let report = timings.to_string();
+        assert!(report.contains("  aont "), "{}", report);
+        assert!(report.contains("(1 run)"), "{}", report);
+        assert!(report.contains("  total "), "{}", report);
+        // Composite stages would count their finer stages twice
+        assert!(!report.contains("  seal "), "{}", report);
+
+        // Nothing is collected outside
+        let ((), empty) = collect_timings(|| {});
+        assert!(empty.stages.is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_prometheus_render() {
+        let recorder = PrometheusRecorder::new();
+        recorder.increment_counter(BLOCKS_SCANNED, 3);
+        recorder.increment_counter(BLOCKS_SCANNED, 2);
+        recorder.record_duration(Stage::Seal, 0.002);
+        recorder.record_duration(Stage::Seal, 10.0);
+        assert_eq!(recorder.counter_value(BLOCKS_SCANNED), 5);
+        assert_eq!(recorder.counter_value(MAC_FAILURES), 0);
+
+        let text = recorder.render();
+        assert!(text.contains("# TYPE hypercube_blocks_scanned_total counter\n"));
+        assert!(text.contains("hypercube_blocks_scanned_total 5\n"));
+        assert!(text
+            .contains("hypercube_stage_duration_seconds_bucket{stage=\"seal\",le=\"0.001\"} 0\n"));
+        assert!(text
+            .contains("hypercube_stage_duration_seconds_bucket{stage=\"seal\",le=\"0.005\"} 1\n"));
+        assert!(text
+            .contains("hypercube_stage_duration_seconds_bucket{stage=\"seal\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("hypercube_stage_duration_seconds_count{stage=\"seal\"} 2\n"));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_operations_report_metrics() {
+        let recorder = Arc::new(PrometheusRecorder::new());
+        assert!(set_recorder(recorder.clone()).is_ok());
+        assert!(set_recorder(Arc::new(PrometheusRecorder::new())).is_err());
+
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let mut blocks = create_partition(b"measured", b"secret", &header, Some(4))
+            .unwrap()
+            .blocks;
+        blocks.extend(
+            create_partition(b"other", b"other", &header, Some(4))
+                .unwrap()
+                .blocks,
+        );
+        extract_partition(&blocks, b"secret", &header).unwrap();
+
+        // Other tests in this process may report too, so only lower bounds hold
+        assert!(recorder.counter_value(BLOCKS_SCANNED) >= 8);
+        assert!(recorder.counter_value(MAC_FAILURES) >= 4);
+        let text = recorder.render();
+        for stage in ["compress", "seal", "open", "decompress"] {
+            let count = format!("{}_count{{stage=\"{}\"}}", STAGE_DURATION, stage);
+            assert!(text.contains(&count), "{}", stage);
+        }
+    }
+}
@@ -1,39 +1,90 @@
+use crate::chunk::{create_chunked_partition, extract_chunked_partition};
+use crate::concurrency;
+use crate::domains::V1;
 use crate::error::{HypercubeError, Result};
-use crate::header::{PartitionMeta, VhcHeader};
+use crate::header::{PartitionMeta, PipelineStage, VhcHeader};
+use crate::honeypot;
+use crate::metrics::{self, Stage, BLOCKS_SCANNED, MAC_FAILURES};
 use crate::pipeline::{
-    apply_aont, authenticate_blocks, compress, decompress, fragment_all, generate_sequence_base,
-    reverse_aont, segment, sequence_blocks, unfragment_all, unsequence_blocks, verify_mac,
-    AuthenticatedBlock, SequenceNumber, SequencedBlock, SEQUENCE_SIZE,
+    align_sequence_base, aont_for, apply_shuffle, codec_for, compress_framed,
+    decompress_framed_range, derived_sequence_base, fragment_all, generate_sequence_nonce,
+    mac_for, reverse_shuffle, segment, sequence_blocks, sequence_generation,
+    tagged_sequence_base, unfragment_all, unsequence_blocks, whitener_for, AuthenticatedBlock,
+    SequenceNumber, SequencedBlock, DEFAULT_MAX_DECOMPRESSED_SIZE, SEQUENCE_NONCE_SIZE,
+    SEQUENCE_SIZE,
 };
-use rand::{rngs::OsRng, RngCore};
+use rand::rngs::{adapter::ReseedingRng, OsRng};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Core;
+use std::cell::RefCell;
 
-/// Result of creating a partition - just the serialized blocks
+/// Result of creating a partition: the serialized blocks and what went into them
 pub struct CreatePartitionResult {
     /// Serialized blocks ready for storage (each = sequence + data + MAC)
     pub blocks: Vec<Vec<u8>>,
+    /// Input bytes
+    pub original_size: usize,
+    /// Compressed bytes the blocks carry; for chunked partitions, of the chunks sealed here
+    /// (chunks found shared or unchanged cost nothing)
+    pub compressed_size: usize,
 }
 
 /// Create a partition from input data
-/// Pipeline: Compress → Segment → Fragment → AONT → Sequence → MAC
+/// Pipeline: Compress → Segment → Fragment → Whiten → AONT → Shuffle → Sequence → MAC
 pub fn create_partition(
     data: &[u8],
     secret: &[u8],
     header: &VhcHeader,
     pad_to_blocks: Option<usize>,
 ) -> Result<CreatePartitionResult> {
+    create_partition_with_trailer(data, secret, header, pad_to_blocks, &[])
+}
+
+/// Create a partition whose padding starts with `trailer` (a honeypot record), right after
+/// the compressed data; readers that do not look for it see an ordinary partition
+pub fn create_partition_with_trailer(
+    data: &[u8],
+    secret: &[u8],
+    header: &VhcHeader,
+    pad_to_blocks: Option<usize>,
+    trailer: &[u8],
+) -> Result<CreatePartitionResult> {
+    if header.chunked {
+        if !trailer.is_empty() {
+            return Err(HypercubeError::InvalidFormat(
+                "Chunked partitions have no padding to hold a trailer".into(),
+            ));
+        }
+        // Chunked partitions always fill exactly blocks_per_partition blocks
+        return create_chunked_partition(data, secret, header);
+    }
+
     // Step 1: Compress
-    let compressed = compress(data, header.compression)?;
+    let codec = codec_for(header)?;
+    let compressed = metrics::time(Stage::Compress, || {
+        compress_framed(data, &*codec, header.frame_size)
+    })?;
 
-    // Step 2: Prepend metadata
+    // Step 2: Prepend metadata (and the nonce a derived sequence base comes from)
     let meta = PartitionMeta {
         compressed_size: compressed.len() as u64,
         original_size: data.len() as u64,
+        content_hash: header.hashed_meta().then(|| *blake3::hash(data).as_bytes()),
     };
-    let mut data_with_meta = Vec::with_capacity(PartitionMeta::SIZE + compressed.len());
+    let nonce = header.derived_sequence.then(generate_sequence_nonce);
+    let mut data_with_meta = Vec::with_capacity(header.meta_size() + compressed.len());
     data_with_meta.extend_from_slice(&meta.to_bytes());
+    if let Some(nonce) = &nonce {
+        data_with_meta.extend_from_slice(nonce);
+    }
     data_with_meta.extend_from_slice(&compressed);
+    data_with_meta.extend_from_slice(trailer);
 
-    // Pad if requested
+    // Pad if requested; parity is only computed over a whole partition
+    let pad_to_blocks = match header.erasure {
+        Some(_) => Some(header.data_blocks_per_partition()),
+        None => pad_to_blocks,
+    };
     if let Some(target_blocks) = pad_to_blocks {
         if target_blocks == 0 {
             return Err(HypercubeError::InvalidDimension(0));
@@ -45,152 +96,614 @@ pub fn create_partition(
         data_with_meta.resize(target_bytes, 0u8);
     }
 
-    // Step 3: Segment into blocks
-    let blocks = segment(&data_with_meta, header.block_size);
+    Ok(CreatePartitionResult {
+        blocks: encode_blocks(&data_with_meta, secret, header, nonce.as_ref())?,
+        original_size: data.len(),
+        compressed_size: compressed.len(),
+    })
+}
 
-    // Step 4: Fragment blocks
-    let (fragments, frags_per_block) = fragment_all(&blocks, header.fragment_size);
+/// Turn padded payload bytes into serialized blocks under a fresh sequence base,
+/// derived from `nonce` and the key when given, random otherwise
+/// Pipeline: Segment → Fragment → Whiten → AONT → Shuffle → Sequence → MAC
+pub fn encode_blocks(
+    data: &[u8],
+    secret: &[u8],
+    header: &VhcHeader,
+    nonce: Option<&[u8; SEQUENCE_NONCE_SIZE]>,
+) -> Result<Vec<Vec<u8>>> {
+    metrics::time(Stage::Seal, || {
+        let domains = header.domains()?;
+        let (whitener, aont, mac) = (whitener_for(header)?, aont_for(header)?, mac_for(header)?);
+        // Expanded legacy tags still verify, but no new block gets one
+        let max_mac_bits = mac.max_mac_bits(header.wide_tag());
+        if header.mac_bits > max_mac_bits {
+            return Err(HypercubeError::UnsupportedAlgorithm(format!(
+                "{}-bit tags: the {} MAC provides at most {} bits in a version {} container",
+                header.mac_bits,
+                header.stages.mac.as_deref().unwrap_or(header.hash.name()),
+                max_mac_bits,
+                header.version
+            )));
+        }
 
-    // Step 5: Apply AONT (randomized, adds key block)
-    let fragments = apply_aont(fragments, header.aont, frags_per_block);
+        // Step 3: Segment into blocks
+        let blocks = metrics::time(Stage::Segment, || segment(data, header.block_size));
 
-    // Step 6: Unfragment back to blocks
-    let transformed_blocks = unfragment_all(&fragments, frags_per_block);
+        // Step 4: Fragment blocks
+        let (mut fragments, frags_per_block) =
+            metrics::time(Stage::Fragment, || fragment_all(&blocks, header.fragment_size));
 
-    // Step 7: Add sequence numbers
-    let sequence_base = generate_sequence_base();
-    let sequenced = sequence_blocks(transformed_blocks, sequence_base);
+        // Steps 5-6: Whiten, apply the AONT (randomized, adds key block) and shuffle
+        // fragments across the partition, in the header's order (by default the shuffle
+        // comes last, so it spreads the AONT key block too)
+        let fragments = metrics::time(Stage::Aont, || {
+            for stage in header.pipeline_order.fragment_transforms() {
+                fragments = match stage {
+                    PipelineStage::Whiten => {
+                        whitener.whiten(&mut fragments, domains);
+                        fragments
+                    }
+                    PipelineStage::Aont => aont.apply(fragments, frags_per_block, domains),
+                    _ => apply_shuffle(fragments, header.shuffle, secret, domains),
+                };
+            }
+            fragments
+        });
 
-    // Step 8: Authenticate with MAC
-    let authenticated = authenticate_blocks(sequenced, secret, header.hash, header.mac_bits);
+        // Step 7: Unfragment back to blocks, interleaving parity if the header asks for it
+        let mut transformed_blocks =
+            metrics::time(Stage::Fragment, || unfragment_all(&fragments, frags_per_block));
+        if let Some(code) = header.erasure {
+            transformed_blocks = code.encode(transformed_blocks, header.blocks_per_partition)?;
+        }
 
-    // Step 9: Serialize blocks
-    let serialized: Vec<Vec<u8>> = authenticated
-        .iter()
-        .map(|block| {
-            let mut buf = Vec::with_capacity(SEQUENCE_SIZE + block.data.len() + block.mac.len());
-            buf.extend_from_slice(&block.sequence_bytes);
-            buf.extend_from_slice(&block.data);
-            buf.extend_from_slice(&block.mac);
-            buf
-        })
-        .collect();
+        // Step 8: Add sequence numbers, tagged with the generation of this write
+        let sequenced = metrics::time(Stage::Sequence, || {
+            let generation = header.next_generation();
+            let count = transformed_blocks.len();
+            let context = domains.sequence_tag;
+            let base = match nonce {
+                Some(nonce) => derived_sequence_base(secret, context, nonce, generation, count),
+                None => tagged_sequence_base(secret, context, generation, count),
+            };
+            match header.erasure {
+                Some(_) => sequence_blocks(transformed_blocks, align_sequence_base(base, count)),
+                None => sequence_blocks(transformed_blocks, base),
+            }
+        });
 
-    Ok(CreatePartitionResult { blocks: serialized })
+        // Steps 9-10: Authenticate with MAC and serialize blocks
+        let serialized: Vec<Vec<u8>> = metrics::time(Stage::Mac, || {
+            concurrency::map(&sequenced, |_, block| {
+                let sequence = block.sequence.as_bytes();
+                let parts = [&sequence[..], &block.data];
+                let tag = mac.compute(&parts, secret, header.mac_bits, header.wide_tag());
+                let mut buf = Vec::with_capacity(SEQUENCE_SIZE + block.data.len() + tag.len());
+                buf.extend_from_slice(sequence);
+                buf.extend_from_slice(&block.data);
+                buf.extend_from_slice(&tag);
+                buf
+            })
+        });
+
+        Ok(serialized)
+    })
 }
 
 /// Extract data from a VHC file by scanning ALL blocks and authenticating each
 pub fn extract_partition(
-    all_blocks: &[Vec<u8>],
+    all_blocks: &[impl AsRef<[u8]> + Sync],
     secret: &[u8],
     header: &VhcHeader,
 ) -> Result<Vec<u8>> {
-    let mac_bytes = header.mac_bytes();
-    let data_size = header.block_size;
-    let expected_block_size = SEQUENCE_SIZE + data_size + mac_bytes;
+    extract_partition_bounded(all_blocks, secret, header, DEFAULT_MAX_DECOMPRESSED_SIZE)
+}
+
+/// Extract a partition, refusing metadata that declares more than `max_size` bytes
+pub fn extract_partition_bounded(
+    all_blocks: &[impl AsRef<[u8]> + Sync],
+    secret: &[u8],
+    header: &VhcHeader,
+    max_size: usize,
+) -> Result<Vec<u8>> {
+    extract_partition_range(all_blocks, secret, header, max_size, ByteRange::default())
+}
+
+/// A byte range of a partition's original data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: usize,
+    /// Read to the end when None; clipped to the end of the data
+    pub length: Option<usize>,
+}
+
+impl ByteRange {
+    pub fn is_whole(&self) -> bool {
+        self.offset == 0 && self.length.is_none()
+    }
+
+    /// Length of the range within data of `size` bytes; an offset past the end is refused
+    pub fn clip(&self, size: usize) -> Result<usize> {
+        let remaining = size.checked_sub(self.offset).ok_or_else(|| {
+            HypercubeError::InvalidFormat(format!(
+                "Offset {} is past the end of the {} byte partition",
+                self.offset, size
+            ))
+        })?;
+        Ok(self.length.map_or(remaining, |length| length.min(remaining)))
+    }
+}
+
+/// Extract only `range` of a partition's original data
+/// Every block is still authenticated and un-transformed (the AONT needs all of them),
+/// but decompression stops at the end of the range (framed payloads skip to its first frame)
+pub fn extract_partition_range(
+    all_blocks: &[impl AsRef<[u8]> + Sync],
+    secret: &[u8],
+    header: &VhcHeader,
+    max_size: usize,
+    range: ByteRange,
+) -> Result<Vec<u8>> {
+    if header.chunked {
+        let mut data = extract_chunked_partition(all_blocks, secret, header, max_size)?;
+        let length = range.clip(data.len())?;
+        data.truncate(range.offset + length);
+        return Ok(data.split_off(range.offset));
+    }
+
+    // Fail on unregistered stages before scanning, rather than authenticating nothing
+    let codec = codec_for(header)?;
+    mac_for(header)?;
 
     // Step 1: Scan and authenticate blocks
-    let mut authenticated_blocks: Vec<AuthenticatedBlock> = Vec::new();
+    let authenticated_blocks: Vec<AuthenticatedBlock> = metrics::time(Stage::Mac, || {
+        concurrency::map(all_blocks, |_, block| {
+            authenticate_block(block.as_ref(), secret, header)
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    });
 
-    for block in all_blocks {
-        if block.len() != expected_block_size {
-            continue;
+    if authenticated_blocks.is_empty() {
+        return Err(HypercubeError::NoAuthenticatedBlocks);
+    }
+
+    // Steps 2-7: Order, un-transform and join the blocks
+    let count = authenticated_blocks.len();
+    let sequences: Vec<u128> = authenticated_blocks
+        .iter()
+        .map(|block| SequenceNumber::from_bytes(block.sequence_bytes).to_u128())
+        .collect();
+    let first_sequence = sequences.iter().copied().min();
+    // Blocks missing from the run fail the decode; say how many authenticated and where
+    // the holes are, so a damaged partition does not look like a wrong secret
+    let holes = SequenceGaps::find(sequences, header.erasure.map(|_| header.blocks_per_partition));
+    let incomplete = |holes: SequenceGaps| HypercubeError::IncompletePartition {
+        matched: count,
+        expected: header.blocks_per_partition,
+        gaps: holes.gaps,
+        duplicates: holes.duplicates,
+    };
+    // Only erasure coding can fill a hole
+    if header.erasure.is_none() && !holes.is_empty() {
+        return Err(incomplete(holes));
+    }
+    let all_data = match decode_blocks(authenticated_blocks, secret, header) {
+        Err(HypercubeError::IntegrityError(_)) if !holes.is_empty() => {
+            return Err(incomplete(holes))
         }
+        result => result?,
+    };
+
+    // Step 8: Extract metadata
+    if all_data.len() < header.meta_size() {
+        return Err(HypercubeError::IntegrityError(
+            "Data too short for metadata".into(),
+        ));
+    }
 
-        let mut sequence_bytes = [0u8; SEQUENCE_SIZE];
-        sequence_bytes.copy_from_slice(&block[..SEQUENCE_SIZE]);
-        let block_data = &block[SEQUENCE_SIZE..SEQUENCE_SIZE + data_size];
-        let mac = &block[SEQUENCE_SIZE + data_size..];
+    let meta = PartitionMeta::from_bytes(&all_data, header.hashed_meta())?;
+    if header.derived_sequence {
+        let nonce_start = header.meta_size() - SEQUENCE_NONCE_SIZE;
+        let nonce = all_data[nonce_start..header.meta_size()].try_into().unwrap();
+        // An erasure-coded partition may be missing blocks, but was sealed whole
+        let count = header.erasure.map_or(count, |_| header.blocks_per_partition);
+        check_derived_base(first_sequence, nonce, count, secret, header)?;
+    }
 
-        let auth_block = AuthenticatedBlock {
-            sequence_bytes,
-            data: block_data.to_vec(),
-            mac: mac.to_vec(),
-        };
+    // Step 9: Extract compressed data
+    let compressed_start = header.meta_size();
+    let compressed = usize::try_from(meta.compressed_size)
+        .ok()
+        .and_then(|size| all_data.get(compressed_start..compressed_start.checked_add(size)?))
+        .ok_or_else(|| {
+            HypercubeError::IntegrityError("Invalid compressed size in metadata".into())
+        })?;
+    honeypot::check(&all_data[compressed_start + compressed.len()..], header);
 
-        if verify_mac(&auth_block, secret, header.hash, header.mac_bits) {
-            authenticated_blocks.push(auth_block);
-        }
+    // Step 10: Decompress, never past the declared size (checked before allocating)
+    let original_size = usize::try_from(meta.original_size)
+        .ok()
+        .filter(|&size| size <= max_size)
+        .ok_or_else(|| {
+            HypercubeError::DecompressionError(format!(
+                "Declared size {} exceeds the {} byte limit",
+                meta.original_size, max_size
+            ))
+        })?;
+    // A range cannot be checked against the content hash, only the whole data
+    if !range.is_whole() {
+        let length = range.clip(original_size)?;
+        return metrics::time(Stage::Decompress, || {
+            decompress_framed_range(
+                compressed,
+                &*codec,
+                header.frame_size,
+                range.offset,
+                length,
+                original_size,
+            )
+        });
     }
+    let data = metrics::time(Stage::Decompress, || {
+        if header.frame_size != 0 {
+            decompress_framed_range(
+                compressed,
+                &*codec,
+                header.frame_size,
+                0,
+                original_size,
+                original_size,
+            )
+        } else {
+            codec.decompress(compressed, original_size)
+        }
+    })?;
 
-    if authenticated_blocks.is_empty() {
+    // Step 11: Check the size, and the content hash when the metadata has one
+    meta.check(&data)?;
+    Ok(data)
+}
+
+/// Check that a partition's sequence base is the one its stored nonce derives
+fn check_derived_base(
+    first_sequence: Option<u128>,
+    nonce: &[u8; SEQUENCE_NONCE_SIZE],
+    count: usize,
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Result<()> {
+    let context = header.domains()?.sequence_tag;
+    let aligned = |base| match header.erasure {
+        Some(_) => align_sequence_base(base, count),
+        None => base,
+    };
+    let derived = first_sequence.and_then(|base| {
+        let generation = sequence_generation(secret, context, base)?;
+        Some(aligned(derived_sequence_base(secret, context, nonce, generation, count)))
+    });
+    if derived.is_none() || derived != first_sequence.map(aligned) {
         return Err(HypercubeError::IntegrityError(
-            "No blocks authenticated with this secret".into(),
+            "Sequence base does not match its derivation".into(),
         ));
     }
+    Ok(())
+}
 
-    // Step 2: Extract sequenced blocks
-    let sequenced: Vec<SequencedBlock> = authenticated_blocks
-        .into_iter()
-        .map(|b| SequencedBlock {
-            sequence: SequenceNumber::from_bytes(b.sequence_bytes),
-            data: b.data,
-        })
-        .collect();
+/// Undo `encode_blocks` for one partition's authenticated blocks
+/// Blocks must carry consecutive sequence numbers; they are ordered here. Under an erasure
+/// code, up to its parity's worth of each stripe may be missing instead
+pub fn decode_blocks(
+    blocks: Vec<AuthenticatedBlock>,
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Result<Vec<u8>> {
+    metrics::time(Stage::Open, || {
+        let domains = header.domains()?;
+        let (whitener, aont) = (whitener_for(header)?, aont_for(header)?);
+
+        // Step 2: Extract sequenced blocks
+        let sequenced: Vec<SequencedBlock> = blocks
+            .into_iter()
+            .map(|b| SequencedBlock {
+                sequence: SequenceNumber::from_bytes(b.sequence_bytes),
+                data: b.data,
+            })
+            .collect();
 
-    // Step 3: Remove sequence numbers and verify order
-    let transformed_blocks = unsequence_blocks(sequenced)
-        .ok_or_else(|| HypercubeError::IntegrityError("Invalid sequence numbers".into()))?;
+        // Step 3: Remove sequence numbers and verify order, rebuilding lost blocks
+        let transformed_blocks = match header.erasure {
+            Some(code) => code.decode(erasure_slots(sequenced, header.blocks_per_partition)?)?,
+            None => metrics::time(Stage::Sequence, || unsequence_blocks(sequenced))
+                .ok_or_else(|| HypercubeError::IntegrityError("Invalid sequence numbers".into()))?,
+        };
+
+        // Step 4: Fragment the blocks again
+        let (mut fragments, frags_per_block) = metrics::time(Stage::Fragment, || {
+            fragment_all(&transformed_blocks, header.fragment_size)
+        });
 
-    // Step 4: Fragment for reverse AONT
-    let (fragments, frags_per_block) = fragment_all(&transformed_blocks, header.fragment_size);
+        // Step 5: Undo the fragment transforms, last applied first
+        let fragments = metrics::time(Stage::Aont, || {
+            for stage in header.pipeline_order.fragment_transforms().rev() {
+                fragments = match stage {
+                    PipelineStage::Whiten => {
+                        whitener.unwhiten(&mut fragments, domains);
+                        fragments
+                    }
+                    PipelineStage::Aont => aont.reverse(fragments, frags_per_block, domains),
+                    _ => reverse_shuffle(fragments, header.shuffle, secret, domains),
+                };
+            }
+            fragments
+        });
 
-    // Step 5: Reverse AONT
-    let fragments = reverse_aont(fragments, header.aont, frags_per_block);
+        // Step 6: Unfragment back to blocks
+        let blocks = metrics::time(Stage::Fragment, || unfragment_all(&fragments, frags_per_block));
 
-    // Step 6: Unfragment back to blocks
-    let blocks = unfragment_all(&fragments, frags_per_block);
+        // Step 7: Join all blocks
+        Ok(metrics::time(Stage::Segment, || blocks.concat()))
+    })
+}
 
-    // Step 7: Join all blocks
-    let mut all_data = Vec::new();
+/// Place an erasure-coded partition's blocks by the low bits of their sequence numbers,
+/// which must all share the aligned base of a run of `count`
+fn erasure_slots(blocks: Vec<SequencedBlock>, count: usize) -> Result<Vec<Option<Vec<u8>>>> {
+    let invalid = || HypercubeError::IntegrityError("Invalid sequence numbers".into());
+    let base = blocks.first().map(|block| align_sequence_base(block.sequence.to_u128(), count));
+    let mut slots = vec![None; count];
     for block in blocks {
-        all_data.extend_from_slice(&block);
+        let sequence = block.sequence.to_u128();
+        let index = usize::try_from(sequence - align_sequence_base(sequence, count))
+            .ok()
+            .filter(|&index| index < count)
+            .ok_or_else(invalid)?;
+        if Some(align_sequence_base(sequence, count)) != base || slots[index].is_some() {
+            return Err(invalid());
+        }
+        slots[index] = Some(block.data);
     }
+    Ok(slots)
+}
 
-    // Step 8: Extract metadata
-    if all_data.len() < PartitionMeta::SIZE {
-        return Err(HypercubeError::IntegrityError(
-            "Data too short for metadata".into(),
-        ));
+/// Holes in a partition's run of sequence numbers, counted from its first block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceGaps {
+    /// Runs of missing blocks as (index of the first, length)
+    pub gaps: Vec<(u128, u128)>,
+    /// Indices held by more than one block
+    pub duplicates: Vec<u128>,
+}
+
+impl SequenceGaps {
+    /// Find the holes among a partition's sequence numbers, given in any order
+    /// With `aligned_run`, the partition spans the whole aligned run of that many blocks
+    /// (as an erasure-coded one does), so blocks lost from either end show as well
+    pub fn find(mut sequences: Vec<u128>, aligned_run: Option<usize>) -> Self {
+        let mut holes = Self::default();
+        sequences.sort_unstable();
+        let (Some(&first), Some(&last)) = (sequences.first(), sequences.last()) else {
+            return holes;
+        };
+        let (base, end) = match aligned_run {
+            Some(count) => {
+                let base = align_sequence_base(first, count);
+                (base, base.wrapping_add(count as u128))
+            }
+            None => (first, last.wrapping_add(1)),
+        };
+        let mut next = base;
+        for &sequence in sequences.iter().chain([&end]) {
+            if sequence < next {
+                holes.duplicates.push(sequence.wrapping_sub(base));
+            } else if sequence > next {
+                holes.gaps.push((next.wrapping_sub(base), sequence - next));
+            }
+            next = sequence.wrapping_add(1);
+        }
+        holes
     }
 
-    let meta = PartitionMeta::from_bytes(&all_data)?;
+    pub fn is_empty(&self) -> bool {
+        self.gaps.is_empty() && self.duplicates.is_empty()
+    }
+}
 
-    // Step 9: Extract compressed data
-    let compressed_start = PartitionMeta::SIZE;
-    let compressed_end = compressed_start + meta.compressed_size as usize;
+/// Generation of the write that created a block of this partition, if its sequence carries one
+pub fn block_generation(block: &[u8], secret: &[u8], header: &VhcHeader) -> Option<u32> {
+    let sequence = u128::from_le_bytes(block.get(..SEQUENCE_SIZE)?.try_into().ok()?);
+    sequence_generation(secret, header.domains().ok()?.sequence_tag, sequence)
+}
 
-    if compressed_end > all_data.len() {
-        return Err(HypercubeError::IntegrityError(
-            "Invalid compressed size in metadata".into(),
-        ));
+/// Check a raw block's MAC in place, without copying it
+fn block_verifies(block: &[u8], secret: &[u8], header: &VhcHeader) -> bool {
+    let verifies = block.len() == header.total_block_size()
+        && mac_for(header)
+            .is_ok_and(|mac| mac.verify(block, secret, header.mac_bits, header.wide_tag()));
+    metrics::counter(BLOCKS_SCANNED, 1);
+    if !verifies {
+        metrics::counter(MAC_FAILURES, 1);
     }
+    verifies
+}
 
-    let compressed = &all_data[compressed_start..compressed_end];
+/// Parse a raw block and keep it only if its MAC verifies under the secret
+/// Foreign blocks are rejected before anything is copied
+pub fn authenticate_block(
+    block: &[u8],
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Option<AuthenticatedBlock> {
+    if !block_verifies(block, secret, header) {
+        return None;
+    }
 
-    // Step 10: Decompress
-    let data = decompress(compressed, header.compression)?;
+    let data_size = header.block_size;
+    let mut sequence_bytes = [0u8; SEQUENCE_SIZE];
+    sequence_bytes.copy_from_slice(&block[..SEQUENCE_SIZE]);
+    Some(AuthenticatedBlock {
+        sequence_bytes,
+        data: block[SEQUENCE_SIZE..SEQUENCE_SIZE + data_size].to_vec(),
+        mac: block[SEQUENCE_SIZE + data_size..].to_vec(),
+    })
+}
 
-    if data.len() != meta.original_size as usize {
-        return Err(HypercubeError::IntegrityError(
-            "Original size mismatch after decompression".into(),
+/// Re-seal a partition under a new secret, for the positions it holds now
+/// The data is decoded under `old_secret` and sealed afresh under `new_secret`. The MACs
+/// are not the only thing keyed by the secret: so are the masked generation tags, the
+/// fragment shuffle, derived sequence bases and chunk boundaries. New chunk boundaries
+/// can need more room than the old ones, so a nearly full chunked partition may not fit.
+/// Returns the partition's positions and as many blocks to write there
+pub fn rekey_partition(
+    all_blocks: &[impl AsRef<[u8]> + Sync],
+    old_secret: &[u8],
+    new_secret: &[u8],
+    header: &VhcHeader,
+) -> Result<(Vec<usize>, Vec<Vec<u8>>)> {
+    if header.dedup {
+        return Err(HypercubeError::InvalidFormat(
+            "A deduplicating container cannot re-key a partition".into(),
+        ));
+    }
+    let positions = find_partition_blocks(all_blocks, old_secret, header);
+    if positions.is_empty() {
+        return Err(HypercubeError::NoAuthenticatedBlocks);
+    }
+    if !find_partition_blocks(all_blocks, new_secret, header).is_empty() {
+        return Err(HypercubeError::InvalidFormat(
+            "The new secret already opens blocks in this container".into(),
         ));
     }
 
-    Ok(data)
+    let data = extract_partition(all_blocks, old_secret, header)?;
+    let pad = (!header.chunked).then(|| header.data_blocks_per_partition());
+    let blocks = create_partition(&data, new_secret, header, pad)?.blocks;
+    if blocks.len() != positions.len() {
+        return Err(HypercubeError::IntegrityError(format!(
+            "Partition holds {} blocks, expected {}",
+            positions.len(),
+            blocks.len()
+        )));
+    }
+    Ok((positions, blocks))
+}
+
+/// Positions of all blocks that authenticate under the secret
+pub fn find_partition_blocks(
+    all_blocks: &[impl AsRef<[u8]> + Sync],
+    secret: &[u8],
+    header: &VhcHeader,
+) -> Vec<usize> {
+    metrics::time(Stage::Mac, || {
+        concurrency::map(all_blocks, |i, block| {
+            block_verifies(block.as_ref(), secret, header).then_some(i)
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    })
+}
+
+/// Output after which a thread's chaff generator reseeds from the OS (1 MiB)
+const CHAFF_RESEED_BYTES: u64 = 1 << 20;
+
+thread_local! {
+    /// ChaCha20 DRBG seeded from the OS, so chaff costs no syscall per block
+    /// (also reseeded in a forked child)
+    static CHAFF_RNG: RefCell<ReseedingRng<ChaCha20Core, OsRng>> = RefCell::new(
+        ReseedingRng::new(
+            ChaCha20Core::from_rng(OsRng).expect("OS random source"),
+            CHAFF_RESEED_BYTES,
+            OsRng,
+        ),
+    );
 }
 
 /// Generate random chaff data for sealing
 pub fn generate_chaff(size: usize) -> Vec<u8> {
     let mut data = vec![0u8; size];
-    OsRng.fill_bytes(&mut data);
+    CHAFF_RNG.with(|rng| rng.borrow_mut().fill_bytes(&mut data));
     data
 }
 
+/// `count` unframed random blocks of `size` bytes, drawn from the generator in one fill
+pub fn generate_noise_blocks(count: usize, size: usize) -> Vec<Vec<u8>> {
+    if size == 0 {
+        return vec![Vec::new(); count];
+    }
+    generate_chaff(count * size)
+        .chunks_exact(size)
+        .map(<[u8]>::to_vec)
+        .collect()
+}
+
+/// `count` chaff blocks framed like real ones: each partition's worth counts up from a
+/// random sequence base, then random data and a MAC-sized random tail
+/// Chunked containers get chunked chaff, so its sequence runs take payload's shapes
+pub fn generate_chaff_blocks(header: &VhcHeader, count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut blocks = Vec::with_capacity(count);
+    while blocks.len() < count {
+        let partition = if header.chunked {
+            let mut secret = [0u8; 32];
+            OsRng.fill_bytes(&mut secret);
+            chunked_chaff_partition(header, &secret)?
+        } else {
+            framed_chaff_partition(header)
+        };
+        let take = partition.len().min(count - blocks.len());
+        blocks.extend(partition.into_iter().take(take));
+    }
+    Ok(blocks)
+}
+
+fn framed_chaff_partition(header: &VhcHeader) -> Vec<Vec<u8>> {
+    // Laid out like a payload base, under a key nobody holds
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    let count = header.blocks_per_partition();
+    let base = if header.derived_sequence {
+        derived_sequence_base(&key, V1.sequence_tag, &generate_sequence_nonce(), 0, count)
+    } else {
+        tagged_sequence_base(&key, V1.sequence_tag, 0, count)
+    };
+    let mut sequence = SequenceNumber::new(match header.erasure {
+        Some(_) => align_sequence_base(base, count),
+        None => base,
+    });
+    let tail = header.block_size + header.mac_bytes();
+    generate_noise_blocks(count, tail)
+        .into_iter()
+        .map(|random| {
+            let mut block = Vec::with_capacity(SEQUENCE_SIZE + tail);
+            block.extend_from_slice(sequence.as_bytes());
+            block.extend_from_slice(&random);
+            sequence.increment();
+            block
+        })
+        .collect()
+}
+
+/// A chunked partition of random data under `key`
+/// The chunking overhead depends on the boundaries, so the data shrinks until it fits
+pub fn chunked_chaff_partition(header: &VhcHeader, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let capacity = header.block_size * header.data_blocks_per_partition();
+    let mut size = capacity.saturating_sub(PartitionMeta::SIZE + 64).max(1);
+    loop {
+        match create_chunked_partition(&generate_chaff(size), key, header) {
+            Err(HypercubeError::DataTooLarge { .. }) if size > 1 => size = size * 3 / 4,
+            result => return result.map(|partition| partition.blocks),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::header::VhcHeader;
+    use crate::cli::doctor::check_randomness;
+    use crate::header::{Compression, Shuffle, VhcHeader};
+    use crate::pipeline::ErasureCode;
 
     #[test]
     fn test_create_extract_roundtrip() {
@@ -204,6 +717,155 @@ mod tests {
         assert_eq!(original_data.as_slice(), &extracted[..]);
     }
 
+    #[test]
+    fn test_derived_sequence_base() {
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.derived_sequence = true;
+        let secret = b"secret";
+        let data = b"sequence base from the key and a stored nonce";
+
+        let result = create_partition(data, secret, &header, Some(8)).unwrap();
+        assert_eq!(extract_partition(&result.blocks, secret, &header).unwrap(), data);
+
+        // Blocks whose base does not come from the nonce they carry are refused
+        let mut payload = PartitionMeta {
+            compressed_size: 0,
+            original_size: 0,
+            content_hash: Some([0; 32]),
+        }
+        .to_bytes()
+        .to_vec();
+        payload.extend_from_slice(&generate_sequence_nonce());
+        payload.resize(header.block_size * 4, 0);
+        let blocks = encode_blocks(&payload, secret, &header, Some(&[7; 16])).unwrap();
+        let error = extract_partition(&blocks, secret, &header).unwrap_err();
+        assert!(error.to_string().contains("does not match its derivation"), "{}", error);
+    }
+
+    #[test]
+    fn test_content_hash_checked() {
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.compression = Compression::None;
+        let secret = b"secret";
+        let data = b"stored payload";
+        let sealed = |header: &VhcHeader, content_hash| {
+            let mut payload = PartitionMeta {
+                compressed_size: data.len() as u64,
+                original_size: data.len() as u64,
+                content_hash,
+            }
+            .to_bytes();
+            payload.extend_from_slice(data);
+            payload.resize(header.block_size * 4, 0);
+            encode_blocks(&payload, secret, header, None).unwrap()
+        };
+
+        // Data of the right length that is not what was hashed is refused
+        let blocks = sealed(&header, Some(*blake3::hash(b"other payload!").as_bytes()));
+        let error = extract_partition(&blocks, secret, &header).unwrap_err();
+        assert!(error.to_string().contains("Content hash mismatch"), "{}", error);
+        let blocks = sealed(&header, Some(*blake3::hash(data).as_bytes()));
+        assert_eq!(extract_partition(&blocks, secret, &header).unwrap(), data);
+
+        // Version 3 metadata has no hash and still reads
+        header.version = 3;
+        let blocks = sealed(&header, None);
+        assert_eq!(extract_partition(&blocks, secret, &header).unwrap(), data);
+    }
+
+    #[test]
+    fn test_erasure_coded_partition() {
+        let mut header = VhcHeader::new(16, 16, 16, 64, 256).unwrap();
+        header.set_erasure(Some(ErasureCode::new(6, 2).unwrap())).unwrap();
+        assert_eq!(header.data_blocks_per_partition(), 11);
+        let secret = b"secret";
+        let data = generate_chaff(400);
+
+        for derived in [false, true] {
+            header.derived_sequence = derived;
+            let blocks = create_partition(&data, secret, &header, None).unwrap().blocks;
+            assert_eq!(blocks.len(), 16);
+            assert_eq!(extract_partition(&blocks, secret, &header).unwrap(), data);
+
+            // Lose two blocks of the first stripe and corrupt one of the second, in any order
+            let mut damaged: Vec<Vec<u8>> = blocks[2..].to_vec();
+            damaged[7][SEQUENCE_SIZE] ^= 1;
+            damaged.reverse();
+            assert_eq!(extract_partition(&damaged, secret, &header).unwrap(), data);
+
+            // A third loss in one stripe is more than its parity covers, and says where
+            let error = extract_partition(&blocks[3..], secret, &header).unwrap_err();
+            assert!(
+                matches!(&error, HypercubeError::IncompletePartition {
+                    matched: 13,
+                    expected: 16,
+                    gaps,
+                    ..
+                } if gaps == &[(0, 3)]),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_incomplete_partition_reports_holes() {
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let secret = b"secret";
+        let pad = Some(header.data_blocks_per_partition());
+        let blocks = create_partition(&generate_chaff(600), secret, &header, pad)
+            .unwrap()
+            .blocks;
+        assert_eq!(blocks.len(), 32);
+
+        // One block lost and another stored twice: the secret is right, the partition is not
+        let mut damaged = blocks.clone();
+        damaged.remove(7);
+        damaged.push(blocks[3].clone());
+        let error = extract_partition(&damaged, secret, &header).unwrap_err();
+        match &error {
+            HypercubeError::IncompletePartition {
+                matched,
+                expected,
+                gaps,
+                duplicates,
+            } => {
+                assert_eq!((*matched, *expected), (32, 32));
+                assert_eq!(gaps, &[(7, 1)]);
+                assert_eq!(duplicates, &[3]);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        let message = error.to_string();
+        assert!(message.contains("32 of 32 blocks authenticated"), "{}", message);
+        assert!(message.contains("missing 1 block from block 7; block 3 repeated"), "{}", message);
+
+        // A wrong secret still authenticates nothing
+        let error = extract_partition(&damaged, b"wrong", &header).unwrap_err();
+        assert!(matches!(error, HypercubeError::NoAuthenticatedBlocks), "{}", error);
+    }
+
+    #[test]
+    fn test_threaded_scan_matches_single() {
+        use crate::concurrency::{set_concurrency, Concurrency};
+        let header = VhcHeader::new(64, 64, 64, 32, 256).unwrap();
+        let data = generate_chaff(1500);
+
+        // Enough blocks for several workers; the results never depend on the setting
+        set_concurrency(Concurrency::Single);
+        let mut all_blocks = Vec::new();
+        let pad = Some(header.data_blocks_per_partition());
+        for secret in [b"first", b"other", b"third"] {
+            all_blocks.extend(create_partition(&data, secret, &header, pad).unwrap().blocks);
+        }
+        let single = find_partition_blocks(&all_blocks, b"other", &header);
+        set_concurrency(Concurrency::Threads(4.try_into().unwrap()));
+        assert_eq!(find_partition_blocks(&all_blocks, b"other", &header), single);
+        assert_eq!(single, (64..128).collect::<Vec<_>>());
+        assert_eq!(extract_partition(&all_blocks, b"third", &header).unwrap(), data);
+        set_concurrency(Concurrency::Auto);
+    }
+
     #[test]
     fn test_create_extract_large_data() {
         let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
@@ -216,6 +878,26 @@ mod tests {
         assert_eq!(original_data, extracted);
     }
 
+    #[test]
+    fn test_extract_range() {
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let secret = b"secret";
+        let original_data: Vec<u8> = (0..50000).map(|i| (i % 251) as u8).collect();
+        let result = create_partition(&original_data, secret, &header, None).unwrap();
+        let max = DEFAULT_MAX_DECOMPRESSED_SIZE;
+        let extract = |offset, length| {
+            let range = ByteRange { offset, length };
+            extract_partition_range(&result.blocks, secret, &header, max, range)
+        };
+
+        assert_eq!(extract(4096, Some(1024)).unwrap(), &original_data[4096..5120]);
+        assert_eq!(extract(49_000, None).unwrap(), &original_data[49_000..]);
+        // Lengths are clipped to the end; offsets past it are refused
+        assert_eq!(extract(49_990, Some(100)).unwrap(), &original_data[49_990..]);
+        assert!(extract(50_000, Some(1)).unwrap().is_empty());
+        assert!(extract(50_001, None).is_err());
+    }
+
     #[test]
     fn test_wrong_secret_fails() {
         let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
@@ -251,11 +933,78 @@ mod tests {
         assert_eq!(data2.as_slice(), &extracted2[..]);
     }
 
+    #[test]
+    fn test_find_partition_blocks() {
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let first = create_partition(b"first", b"secret1", &header, None).unwrap();
+        let second = create_partition(b"second", b"secret2", &header, None).unwrap();
+
+        let mut all_blocks = second.blocks.clone();
+        all_blocks.extend(first.blocks.clone());
+
+        let positions = find_partition_blocks(&all_blocks, b"secret1", &header);
+        let expected: Vec<usize> = (second.blocks.len()..all_blocks.len()).collect();
+        assert_eq!(positions, expected);
+        assert!(find_partition_blocks(&all_blocks, b"unknown", &header).is_empty());
+    }
+
     #[test]
     fn test_generate_chaff() {
         let chaff = generate_chaff(1000);
         assert_eq!(chaff.len(), 1000);
         assert!(chaff.iter().any(|&b| b != 0));
+
+        let blocks = generate_noise_blocks(5, 96);
+        assert_eq!(blocks.len(), 5);
+        assert!(blocks.iter().all(|block| block.len() == 96));
+        assert_ne!(blocks[0], blocks[1]);
+        assert_eq!(generate_noise_blocks(3, 0), vec![Vec::<u8>::new(); 3]);
+    }
+
+    #[test]
+    fn test_chaff_blocks_are_framed() {
+        let header = VhcHeader::new(32, 32, 8, 64, 256).unwrap();
+        let per_partition = header.blocks_per_partition();
+        let blocks = generate_chaff_blocks(&header, per_partition + 3).unwrap();
+        assert_eq!(blocks.len(), per_partition + 3);
+        assert!(blocks.iter().all(|b| b.len() == header.total_block_size()));
+
+        // Each partition's worth is one run of consecutive sequences
+        let sequences: Vec<u128> = blocks
+            .iter()
+            .map(|b| SequenceNumber::from_bytes(b[..SEQUENCE_SIZE].try_into().unwrap()).to_u128())
+            .collect();
+        let (first, second) = sequences.split_at(per_partition);
+        for run in [first, second] {
+            assert!(run.windows(2).all(|w| w[1] == w[0].wrapping_add(1)));
+        }
+        assert_ne!(first[0], second[0]);
+        assert!(generate_chaff_blocks(&header, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_chaff_passes_payload_battery() {
+        // Structured, compressible input, so the battery sees what the transforms did
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let data = b"0123456789abcdef".repeat(80);
+        let pad = Some(header.data_blocks_per_partition());
+        let payload = create_partition(&data, b"secret", &header, pad).unwrap().blocks;
+        let chaff = generate_chaff_blocks(&header, payload.len()).unwrap();
+
+        for (name, blocks) in [("payload", &payload), ("chaff", &chaff)] {
+            let check = check_randomness(blocks.iter().map(|block| &block[SEQUENCE_SIZE..]));
+            assert!(check.problems().is_empty(), "{}: {:?}", name, check);
+        }
+        // Many more chaff blocks than a partition holds, across a reseed
+        let many = generate_noise_blocks(16_384, 96);
+        let check = check_randomness(many.iter().map(Vec::as_slice));
+        assert!(check.problems().is_empty(), "{:?}", check);
+
+        let constant = vec![vec![7u8; 96]; 32];
+        let check = check_randomness(constant.iter().map(Vec::as_slice));
+        assert_eq!(check.constant, 32);
+        assert_eq!(check.duplicates, 31);
+        assert_eq!(check.problems().len(), 3);
     }
 
     #[test]
@@ -267,4 +1016,62 @@ mod tests {
         let result = create_partition(data, secret, &header, Some(target)).expect("partition");
         assert_eq!(result.blocks.len(), header.blocks_per_partition());
     }
+
+    #[test]
+    fn test_fragment_shuffle_roundtrip() {
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.shuffle = Shuffle::Fragment;
+        let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+
+        let shuffled = create_partition(&data, b"secret", &header, None).unwrap();
+        assert_eq!(extract_partition(&shuffled.blocks, b"secret", &header).unwrap(), data);
+
+        // Read back without the fragment layer, the payload comes out scrambled
+        let unshuffled = VhcHeader {
+            shuffle: Shuffle::Block,
+            ..header.clone()
+        };
+        assert!(extract_partition(&shuffled.blocks, b"secret", &unshuffled)
+            .map_or(true, |extracted| extracted != data));
+    }
+
+    #[test]
+    fn test_wide_macs_follow_header_version() {
+        let native = VhcHeader::new(32, 32, 32, 64, 512).unwrap();
+        let legacy = VhcHeader {
+            version: 2,
+            ..native.clone()
+        };
+        let data = b"512-bit tags";
+
+        let result = create_partition(data, b"secret", &native, None).unwrap();
+        assert_eq!(extract_partition(&result.blocks, b"secret", &native).unwrap(), data);
+        // Tags are only valid under the version they were written with
+        assert!(find_partition_blocks(&result.blocks, b"secret", &legacy).is_empty());
+
+        // Legacy 512-bit tags only stretch a 256-bit one, so no new ones are written
+        let err = create_partition(data, b"secret", &legacy, None).err().unwrap();
+        let expected = "the sha3 MAC provides at most 256 bits in a version 2 container";
+        assert!(err.to_string().contains(expected), "{}", err);
+        let legacy = VhcHeader {
+            mac_bits: 256,
+            ..legacy
+        };
+        let result = create_partition(data, b"secret", &legacy, None).unwrap();
+        assert_eq!(extract_partition(&result.blocks, b"secret", &legacy).unwrap(), data);
+    }
+
+    #[test]
+    fn test_extract_respects_size_limit() {
+        let header = VhcHeader::new(32, 32, 32, 32, 256).unwrap();
+        let secret = b"bomb";
+        // 1 MiB of zeros fits a tiny partition once compressed
+        let data = vec![0u8; 1 << 20];
+        let result = create_partition(&data, secret, &header, None).unwrap();
+
+        let err = extract_partition_bounded(&result.blocks, secret, &header, 1024).unwrap_err();
+        assert!(err.to_string().contains("exceeds the 1024 byte limit"));
+        let extracted = extract_partition_bounded(&result.blocks, secret, &header, 1 << 20);
+        assert_eq!(extracted.unwrap(), data);
+    }
 }
@@ -1,4 +1,7 @@
+use crate::concurrency;
+use crate::domains::Domains;
 use crate::header::Aont;
+use crate::pipeline::AontTransform;
 use rand::RngCore;
 use sha3::{Digest, Sha3_256};
 
@@ -7,19 +10,57 @@ const KEY_SIZE: usize = 32;
 /// Apply All-or-Nothing Transform to fragments
 /// Rivest AONT adds one block's worth of key fragments; OAEP keeps same count
 /// `frags_per_block` is needed for Rivest to maintain block alignment
-pub fn apply_aont(fragments: Vec<Vec<u8>>, algorithm: Aont, frags_per_block: usize) -> Vec<Vec<u8>> {
+pub fn apply_aont(
+    fragments: Vec<Vec<u8>>,
+    algorithm: Aont,
+    frags_per_block: usize,
+    domains: &Domains,
+) -> Vec<Vec<u8>> {
     match algorithm {
-        Aont::Rivest => rivest_aont_apply(fragments, frags_per_block),
-        Aont::Oaep => oaep_aont_apply(fragments),
+        Aont::Rivest => rivest_aont_apply(fragments, frags_per_block, domains),
+        Aont::Oaep => oaep_aont_apply(fragments, domains),
     }
 }
 
 /// Reverse All-or-Nothing Transform
 /// Rivest AONT removes one block's worth of key fragments; OAEP keeps same count
-pub fn reverse_aont(fragments: Vec<Vec<u8>>, algorithm: Aont, frags_per_block: usize) -> Vec<Vec<u8>> {
+pub fn reverse_aont(
+    fragments: Vec<Vec<u8>>,
+    algorithm: Aont,
+    frags_per_block: usize,
+    domains: &Domains,
+) -> Vec<Vec<u8>> {
     match algorithm {
-        Aont::Rivest => rivest_aont_reverse(fragments, frags_per_block),
-        Aont::Oaep => oaep_aont_reverse(fragments),
+        Aont::Rivest => rivest_aont_reverse(fragments, frags_per_block, domains),
+        Aont::Oaep => oaep_aont_reverse(fragments, domains),
+    }
+}
+
+/// The built-in transforms, registered under their header names
+impl AontTransform for Aont {
+    fn overhead_blocks(&self) -> usize {
+        match self {
+            Aont::Rivest => 1,
+            Aont::Oaep => 0,
+        }
+    }
+
+    fn apply(
+        &self,
+        fragments: Vec<Vec<u8>>,
+        frags_per_block: usize,
+        domains: &Domains,
+    ) -> Vec<Vec<u8>> {
+        apply_aont(fragments, *self, frags_per_block, domains)
+    }
+
+    fn reverse(
+        &self,
+        fragments: Vec<Vec<u8>>,
+        frags_per_block: usize,
+        domains: &Domains,
+    ) -> Vec<Vec<u8>> {
+        reverse_aont(fragments, *self, frags_per_block, domains)
     }
 }
 
@@ -30,8 +71,13 @@ pub fn reverse_aont(fragments: Vec<Vec<u8>>, algorithm: Aont, frags_per_block: u
 ///   Append key block as multiple fragments to maintain block alignment
 ///
 /// We add enough key fragments to form one complete block after unfragment.
-/// The key is stored in the first fragment; others are padding.
-fn rivest_aont_apply(fragments: Vec<Vec<u8>>, frags_per_block: usize) -> Vec<Vec<u8>> {
+/// The key is stored in the first fragment; the rest is random padding, so the key
+/// block looks like any other block.
+fn rivest_aont_apply(
+    fragments: Vec<Vec<u8>>,
+    frags_per_block: usize,
+    domains: &Domains,
+) -> Vec<Vec<u8>> {
     if fragments.is_empty() {
         return fragments;
     }
@@ -44,23 +90,23 @@ fn rivest_aont_apply(fragments: Vec<Vec<u8>>, frags_per_block: usize) -> Vec<Vec
     rand::thread_rng().fill_bytes(&mut key);
 
     // Transform all fragments with PRF
-    for (i, frag) in fragments.iter_mut().enumerate() {
-        let mask = prf(&key, i, frag.len());
+    concurrency::for_each_mut(&mut fragments, |i, frag| {
+        let mask = prf(domains, &key, i, frag.len());
         xor_in_place(frag, &mask);
-    }
+    });
 
     // Compute key block: K XOR H(0||m'[0]) XOR H(1||m'[1]) XOR ...
     let mut key_block = key;
-    for (i, frag) in fragments.iter().enumerate() {
-        let h = hash_indexed(i, frag);
+    for h in concurrency::map(&fragments, |i, frag| hash_indexed(i, frag)) {
         xor_in_place(&mut key_block, &h);
     }
 
     // Add frags_per_block fragments to form one complete key block
     // Spread the 32-byte key_block across the fragments
-    let key_frags_needed = (KEY_SIZE + frag_size - 1) / frag_size;
+    let key_frags_needed = KEY_SIZE.div_ceil(frag_size);
     for i in 0..frags_per_block {
         let mut key_frag = vec![0u8; frag_size];
+        rand::thread_rng().fill_bytes(&mut key_frag);
         if i < key_frags_needed {
             let start = i * frag_size;
             let end = (start + frag_size).min(KEY_SIZE);
@@ -76,7 +122,11 @@ fn rivest_aont_apply(fragments: Vec<Vec<u8>>, frags_per_block: usize) -> Vec<Vec
 }
 
 /// Reverse Rivest's package transform
-fn rivest_aont_reverse(fragments: Vec<Vec<u8>>, frags_per_block: usize) -> Vec<Vec<u8>> {
+fn rivest_aont_reverse(
+    fragments: Vec<Vec<u8>>,
+    frags_per_block: usize,
+    domains: &Domains,
+) -> Vec<Vec<u8>> {
     if fragments.len() < frags_per_block + 1 {
         return fragments;
     }
@@ -89,7 +139,7 @@ fn rivest_aont_reverse(fragments: Vec<Vec<u8>>, frags_per_block: usize) -> Vec<V
 
     // Reconstruct key_block from key fragments
     let mut key_block = [0u8; KEY_SIZE];
-    let key_frags_needed = (KEY_SIZE + frag_size - 1) / frag_size;
+    let key_frags_needed = KEY_SIZE.div_ceil(frag_size);
     for (i, frag) in key_frags.iter().enumerate().take(key_frags_needed) {
         let start = i * frag_size;
         let end = (start + frag_size).min(KEY_SIZE);
@@ -100,29 +150,29 @@ fn rivest_aont_reverse(fragments: Vec<Vec<u8>>, frags_per_block: usize) -> Vec<V
     }
 
     // Recover K: key_block XOR H(0||m'[0]) XOR H(1||m'[1]) XOR ...
-    for (i, frag) in fragments.iter().enumerate() {
-        let h = hash_indexed(i, frag);
+    for h in concurrency::map(&fragments, |i, frag| hash_indexed(i, frag)) {
         xor_in_place(&mut key_block, &h);
     }
 
     // Undo PRF on all fragments
-    for (i, frag) in fragments.iter_mut().enumerate() {
-        let mask = prf(&key_block, i, frag.len());
+    concurrency::for_each_mut(&mut fragments, |i, frag| {
+        let mask = prf(domains, &key_block, i, frag.len());
         xor_in_place(frag, &mask);
-    }
+    });
 
     fragments
 }
 
 /// PRF: SHA3(K || index) expanded to desired length
-fn prf(key: &[u8; KEY_SIZE], index: usize, length: usize) -> Vec<u8> {
+/// Indices are hashed as u64 so 32- and 64-bit builds agree
+fn prf(domains: &Domains, key: &[u8; KEY_SIZE], index: usize, length: usize) -> Vec<u8> {
     let mut result = Vec::with_capacity(length);
     let mut ctr = 0u64;
     while result.len() < length {
         let mut hasher = Sha3_256::new();
-        hasher.update(b"hypercube_rivest_prf");
+        hasher.update(domains.rivest_prf);
         hasher.update(key);
-        hasher.update(index.to_le_bytes());
+        hasher.update((index as u64).to_le_bytes());
         hasher.update(ctr.to_le_bytes());
         for b in hasher.finalize() {
             if result.len() >= length {
@@ -138,26 +188,26 @@ fn prf(key: &[u8; KEY_SIZE], index: usize, length: usize) -> Vec<u8> {
 /// Hash with index prefix: SHA3(index || data)
 fn hash_indexed(index: usize, data: &[u8]) -> [u8; KEY_SIZE] {
     let mut hasher = Sha3_256::new();
-    hasher.update(index.to_le_bytes());
+    hasher.update((index as u64).to_le_bytes());
     hasher.update(data);
     hasher.finalize().into()
 }
 
 /// OAEP-style AONT (2-round Feistel, deterministic, no size change)
-fn oaep_aont_apply(mut fragments: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+fn oaep_aont_apply(mut fragments: Vec<Vec<u8>>, domains: &Domains) -> Vec<Vec<u8>> {
     if fragments.len() < 2 {
         return fragments;
     }
 
     let mid = fragments.len() / 2;
 
-    let left_hash = compute_half_hash(&fragments[..mid]);
+    let left_hash = compute_half_hash(domains, &fragments[..mid]);
     for frag in fragments[mid..].iter_mut() {
         let mask = expand_hash(&left_hash, frag.len());
         xor_in_place(frag, &mask);
     }
 
-    let right_hash = compute_half_hash(&fragments[mid..]);
+    let right_hash = compute_half_hash(domains, &fragments[mid..]);
     for frag in fragments[..mid].iter_mut() {
         let mask = expand_hash(&right_hash, frag.len());
         xor_in_place(frag, &mask);
@@ -166,20 +216,20 @@ fn oaep_aont_apply(mut fragments: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
     fragments
 }
 
-fn oaep_aont_reverse(mut fragments: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+fn oaep_aont_reverse(mut fragments: Vec<Vec<u8>>, domains: &Domains) -> Vec<Vec<u8>> {
     if fragments.len() < 2 {
         return fragments;
     }
 
     let mid = fragments.len() / 2;
 
-    let right_hash = compute_half_hash(&fragments[mid..]);
+    let right_hash = compute_half_hash(domains, &fragments[mid..]);
     for frag in fragments[..mid].iter_mut() {
         let mask = expand_hash(&right_hash, frag.len());
         xor_in_place(frag, &mask);
     }
 
-    let left_hash = compute_half_hash(&fragments[..mid]);
+    let left_hash = compute_half_hash(domains, &fragments[..mid]);
     for frag in fragments[mid..].iter_mut() {
         let mask = expand_hash(&left_hash, frag.len());
         xor_in_place(frag, &mask);
@@ -188,9 +238,9 @@ fn oaep_aont_reverse(mut fragments: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
     fragments
 }
 
-fn compute_half_hash(fragments: &[Vec<u8>]) -> [u8; 32] {
+fn compute_half_hash(domains: &Domains, fragments: &[Vec<u8>]) -> [u8; 32] {
     let mut hasher = Sha3_256::new();
-    hasher.update(b"hypercube_aont_half");
+    hasher.update(domains.aont_half);
     for frag in fragments {
         hasher.update(frag);
     }
@@ -224,6 +274,7 @@ fn xor_in_place(data: &mut [u8], key: &[u8]) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domains::V1;
 
     const TEST_FRAGS_PER_BLOCK: usize = 4;
 
@@ -233,10 +284,10 @@ mod tests {
             .map(|i| vec![(i * 17) as u8; 32])
             .collect();
 
-        let transformed = apply_aont(original.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let transformed = apply_aont(original.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
         assert_eq!(transformed.len(), original.len() + TEST_FRAGS_PER_BLOCK); // one block added
 
-        let recovered = reverse_aont(transformed, Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let recovered = reverse_aont(transformed, Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
         assert_eq!(recovered, original);
     }
 
@@ -244,29 +295,38 @@ mod tests {
     fn test_rivest_aont_is_randomized() {
         let fragments: Vec<Vec<u8>> = (0..20).map(|i| vec![i as u8; 32]).collect();
 
-        let t1 = apply_aont(fragments.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK);
-        let t2 = apply_aont(fragments.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let t1 = apply_aont(fragments.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
+        let t2 = apply_aont(fragments.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
 
         assert_ne!(t1, t2);
     }
 
+    #[test]
+    fn test_rivest_key_block_is_padded_randomly() {
+        let fragments: Vec<Vec<u8>> = (0..8).map(|i| vec![i as u8; 32]).collect();
+        let t = apply_aont(fragments, Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
+        for padding in &t[9..] {
+            assert!(padding.iter().any(|&byte| byte != 0));
+        }
+    }
+
     #[test]
     fn test_oaep_aont_roundtrip() {
         let original: Vec<Vec<u8>> = (0..10)
             .map(|i| vec![(i * 17) as u8; 32])
             .collect();
 
-        let transformed = apply_aont(original.clone(), Aont::Oaep, TEST_FRAGS_PER_BLOCK);
+        let transformed = apply_aont(original.clone(), Aont::Oaep, TEST_FRAGS_PER_BLOCK, &V1);
         assert_eq!(transformed.len(), original.len());
 
-        let recovered = reverse_aont(transformed, Aont::Oaep, TEST_FRAGS_PER_BLOCK);
+        let recovered = reverse_aont(transformed, Aont::Oaep, TEST_FRAGS_PER_BLOCK, &V1);
         assert_eq!(recovered, original);
     }
 
     #[test]
     fn test_aont_empty() {
         let empty: Vec<Vec<u8>> = vec![];
-        let t = apply_aont(empty.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let t = apply_aont(empty.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
         assert!(t.is_empty());
     }
 
@@ -274,21 +334,21 @@ mod tests {
     fn test_aont_single_block() {
         // 4 fragments = 1 block
         let single_block: Vec<Vec<u8>> = (0..4).map(|i| vec![i as u8; 32]).collect();
-        let t = apply_aont(single_block.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let t = apply_aont(single_block.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
         assert_eq!(t.len(), 8); // original 4 + key block 4
-        let r = reverse_aont(t, Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let r = reverse_aont(t, Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
         assert_eq!(r, single_block);
     }
 
     #[test]
     fn test_rivest_all_fragments_needed() {
         let original: Vec<Vec<u8>> = (0..40).map(|i| vec![i as u8; 32]).collect();
-        let mut transformed = apply_aont(original.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let mut transformed = apply_aont(original.clone(), Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
 
         // Corrupt one fragment (not in key block)
         transformed[3][0] ^= 0xFF;
 
-        let recovered = reverse_aont(transformed, Aont::Rivest, TEST_FRAGS_PER_BLOCK);
+        let recovered = reverse_aont(transformed, Aont::Rivest, TEST_FRAGS_PER_BLOCK, &V1);
         assert_ne!(recovered, original);
     }
 }
@@ -1,5 +1,6 @@
 use crate::error::{HypercubeError, Result};
 use crate::header::Compression;
+use crate::pipeline::CompressionCodec;
 use std::io::{Read, Write};
 
 /// Compress data using the specified algorithm
@@ -12,29 +13,261 @@ pub fn compress(data: &[u8], algorithm: Compression) -> Result<Vec<u8>> {
     }
 }
 
-/// Decompress data using the specified algorithm
+/// Compress data in independent frames of `frame_size` input bytes; one stream when 0
+/// Layout: u32 frame count, a u32 compressed length per frame, then the frames
+pub fn compress_framed(
+    data: &[u8],
+    algorithm: &dyn CompressionCodec,
+    frame_size: u32,
+) -> Result<Vec<u8>> {
+    if frame_size == 0 {
+        return algorithm.compress(data);
+    }
+    let frames = data
+        .chunks(frame_size as usize)
+        .map(|frame| algorithm.compress(frame))
+        .collect::<Result<Vec<_>>>()?;
+    let too_large = || HypercubeError::CompressionError("too many frames".into());
+    let count = u32::try_from(frames.len()).map_err(|_| too_large())?;
+
+    let mut output = Vec::with_capacity(4 * (frames.len() + 1));
+    output.extend_from_slice(&count.to_le_bytes());
+    for frame in &frames {
+        let len = u32::try_from(frame.len()).map_err(|_| too_large())?;
+        output.extend_from_slice(&len.to_le_bytes());
+    }
+    for frame in &frames {
+        output.extend_from_slice(frame);
+    }
+    Ok(output)
+}
+
+/// Largest possible output of `compress` for an input of `len` bytes
+/// Incompressible input grows slightly: by framing and per-block headers
+pub fn compressed_size_bound(len: usize, algorithm: Compression) -> usize {
+    match algorithm {
+        // Frame header and checksum slack on top of the per-block bound
+        Compression::Zstd => zstd::zstd_safe::compress_bound(len) + 32,
+        Compression::Lz4 => 4 + lz4_flex::block::get_maximum_output_size(len),
+        Compression::Brotli => brotli::enc::BrotliEncoderMaxCompressedSize(len) + 16,
+        Compression::None => len,
+    }
+}
+
+/// Default ceiling on decompressed output (1 GiB)
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 1 << 30;
+
+/// Decompress data using the specified algorithm, up to the default ceiling
 pub fn decompress(data: &[u8], algorithm: Compression) -> Result<Vec<u8>> {
+    decompress_bounded(data, algorithm, DEFAULT_MAX_DECOMPRESSED_SIZE)
+}
+
+/// Decompress data, refusing to produce more than `max_size` bytes
+/// Output is streamed and checked as it grows, so a bomb is never fully inflated
+pub fn decompress_bounded(data: &[u8], algorithm: Compression, max_size: usize) -> Result<Vec<u8>> {
     match algorithm {
-        Compression::Zstd => decompress_zstd(data),
-        Compression::Lz4 => decompress_lz4(data),
-        Compression::Brotli => decompress_brotli(data),
-        Compression::None => Ok(data.to_vec()),
+        Compression::Zstd => decompress_zstd(data, max_size),
+        Compression::Lz4 => decompress_lz4(data, max_size),
+        Compression::Brotli => decompress_brotli(data, max_size),
+        Compression::None => {
+            check_limit(data.len(), max_size, "none")?;
+            Ok(data.to_vec())
+        }
+    }
+}
+
+/// Decompress only `length` bytes starting at `offset` of the original data
+/// Streaming decoders skip the prefix without keeping it; lz4 blocks are inflated whole
+pub fn decompress_range(
+    data: &[u8],
+    algorithm: Compression,
+    offset: usize,
+    length: usize,
+    max_size: usize,
+) -> Result<Vec<u8>> {
+    let end = offset
+        .checked_add(length)
+        .ok_or_else(|| HypercubeError::DecompressionError("range end overflows".into()))?;
+    match algorithm {
+        Compression::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(data)
+                .map_err(|e| HypercubeError::DecompressionError(format!("zstd: {}", e)))?;
+            read_range(decoder, offset, length, "zstd")
+        }
+        Compression::Brotli => read_range(
+            brotli::Decompressor::new(data, 4096),
+            offset,
+            length,
+            "brotli",
+        ),
+        Compression::Lz4 | Compression::None => {
+            let data = decompress_bounded(data, algorithm, max_size)?;
+            data.get(offset..end).map(<[u8]>::to_vec).ok_or_else(|| {
+                HypercubeError::DecompressionError("range past the end of the data".into())
+            })
+        }
+    }
+}
+
+/// Like `decompress_range`, for the output of `compress_framed`
+/// Only the frames overlapping the range are decompressed
+pub fn decompress_framed_range(
+    data: &[u8],
+    algorithm: &dyn CompressionCodec,
+    frame_size: u32,
+    offset: usize,
+    length: usize,
+    max_size: usize,
+) -> Result<Vec<u8>> {
+    if frame_size == 0 {
+        return algorithm.decompress_range(data, offset, length, max_size);
+    }
+    let corrupt = |what: &str| HypercubeError::DecompressionError(format!("frames: {}", what));
+    let read_u32 = |at: usize| {
+        data.get(at..at + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+            .ok_or_else(|| corrupt("truncated frame table"))
+    };
+
+    // Frame start offsets within `data`, from the length table
+    let count = read_u32(0)?;
+    let table_end = count
+        .checked_add(1)
+        .and_then(|entries| entries.checked_mul(4))
+        .filter(|&end| end <= data.len())
+        .ok_or_else(|| corrupt("truncated frame table"))?;
+    let mut starts = Vec::with_capacity(count + 1);
+    starts.push(table_end);
+    for i in 0..count {
+        let end = starts[i]
+            .checked_add(read_u32(4 + 4 * i)?)
+            .filter(|&end| end <= data.len())
+            .ok_or_else(|| corrupt("frame past the end of the data"))?;
+        starts.push(end);
+    }
+
+    let frame_size = frame_size as usize;
+    let end = offset
+        .checked_add(length)
+        .filter(|&end| end <= count.saturating_mul(frame_size))
+        .ok_or_else(|| corrupt("range past the end of the data"))?;
+    let mut output = Vec::with_capacity(length);
+    if length == 0 {
+        return Ok(output);
+    }
+    for index in offset / frame_size..end.div_ceil(frame_size) {
+        let frame = algorithm.decompress(&data[starts[index]..starts[index + 1]], frame_size)?;
+        // Every frame but the last holds exactly `frame_size` bytes
+        if frame.len() != frame_size && index + 1 != count {
+            return Err(corrupt("short frame"));
+        }
+        let frame_start = index * frame_size;
+        let from = offset.saturating_sub(frame_start);
+        let to = (end - frame_start).min(frame_size);
+        let bytes = frame
+            .get(from..to)
+            .ok_or_else(|| corrupt("range past the end of the data"))?;
+        output.extend_from_slice(bytes);
+    }
+    Ok(output)
+}
+
+/// The built-in codecs, registered under their header names
+impl CompressionCodec for Compression {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        compress(data, *self)
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        decompress_bounded(data, *self, max_size)
+    }
+
+    fn decompress_range(
+        &self,
+        data: &[u8],
+        offset: usize,
+        length: usize,
+        max_size: usize,
+    ) -> Result<Vec<u8>> {
+        decompress_range(data, *self, offset, length, max_size)
+    }
+
+    fn size_bound(&self, len: usize) -> usize {
+        compressed_size_bound(len, *self)
+    }
+}
+
+/// Discard `offset` bytes of a decompressor, then read exactly `length`
+fn read_range(
+    mut reader: impl Read,
+    offset: usize,
+    length: usize,
+    algorithm: &str,
+) -> Result<Vec<u8>> {
+    let error =
+        |e: std::io::Error| HypercubeError::DecompressionError(format!("{}: {}", algorithm, e));
+    let skipped = std::io::copy(&mut (&mut reader).take(offset as u64), &mut std::io::sink())
+        .map_err(error)?;
+    if skipped != offset as u64 {
+        return Err(HypercubeError::DecompressionError(format!(
+            "{}: range past the end of the data",
+            algorithm
+        )));
+    }
+    let mut output = vec![0u8; length];
+    reader.read_exact(&mut output).map_err(error)?;
+    Ok(output)
+}
+
+fn check_limit(size: usize, max_size: usize, algorithm: &str) -> Result<()> {
+    if size > max_size {
+        return Err(HypercubeError::DecompressionError(format!(
+            "{}: output exceeds the {} byte limit",
+            algorithm, max_size
+        )));
     }
+    Ok(())
+}
+
+/// Read a decompressor to the end, stopping one byte past the limit
+fn read_bounded(reader: impl Read, max_size: usize, algorithm: &str) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    reader
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| HypercubeError::DecompressionError(format!("{}: {}", algorithm, e)))?;
+    check_limit(output.len(), max_size, algorithm)?;
+    Ok(output)
 }
 
 fn compress_zstd(data: &[u8]) -> Result<Vec<u8>> {
     zstd::encode_all(data, 3).map_err(|e| HypercubeError::CompressionError(format!("zstd: {}", e)))
 }
 
-fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>> {
-    zstd::decode_all(data).map_err(|e| HypercubeError::DecompressionError(format!("zstd: {}", e)))
+fn decompress_zstd(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    let decoder = zstd::stream::read::Decoder::new(data)
+        .map_err(|e| HypercubeError::DecompressionError(format!("zstd: {}", e)))?;
+    read_bounded(decoder, max_size, "zstd")
 }
 
 fn compress_lz4(data: &[u8]) -> Result<Vec<u8>> {
+    // The size prefix is a u32, and lz4_flex sizes its output buffer as len * 110 / 100
+    if u32::try_from(data.len()).is_err() || data.len().checked_mul(110).is_none() {
+        return Err(HypercubeError::CompressionError(format!(
+            "lz4: {} bytes is too large for one block",
+            data.len()
+        )));
+    }
     Ok(lz4_flex::compress_prepend_size(data))
 }
 
-fn decompress_lz4(data: &[u8]) -> Result<Vec<u8>> {
+fn decompress_lz4(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    // The prepended size is checked before lz4_flex allocates it
+    let declared = data
+        .get(..4)
+        .map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize)
+        .ok_or_else(|| HypercubeError::DecompressionError("lz4: missing size".into()))?;
+    check_limit(declared, max_size, "lz4")?;
     lz4_flex::decompress_size_prepended(data)
         .map_err(|e| HypercubeError::DecompressionError(format!("lz4: {}", e)))
 }
@@ -49,13 +282,8 @@ fn compress_brotli(data: &[u8]) -> Result<Vec<u8>> {
     Ok(output)
 }
 
-fn decompress_brotli(data: &[u8]) -> Result<Vec<u8>> {
-    let mut output = Vec::new();
-    let mut reader = brotli::Decompressor::new(data, 4096);
-    reader
-        .read_to_end(&mut output)
-        .map_err(|e| HypercubeError::DecompressionError(format!("brotli: {}", e)))?;
-    Ok(output)
+fn decompress_brotli(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    read_bounded(brotli::Decompressor::new(data, 4096), max_size, "brotli")
 }
 
 #[cfg(test)]
@@ -124,4 +352,85 @@ mod tests {
             test_roundtrip(alg, &data);
         }
     }
+
+    #[test]
+    fn test_compressed_size_bound() {
+        let mut rng = rand::thread_rng();
+        for len in [0, 1, 100, 4095, 4096, 65536, 300_000] {
+            let mut data = vec![0u8; len];
+            rand::RngCore::fill_bytes(&mut rng, &mut data);
+            for alg in [
+                Compression::Zstd,
+                Compression::Lz4,
+                Compression::Brotli,
+                Compression::None,
+            ] {
+                let compressed = compress(&data, alg).unwrap();
+                let bound = compressed_size_bound(len, alg);
+                assert!(compressed.len() <= bound, "{:?} {}", alg, len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_range() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        for alg in [
+            Compression::Zstd,
+            Compression::Lz4,
+            Compression::Brotli,
+            Compression::None,
+        ] {
+            let compressed = compress(&data, alg).unwrap();
+            let max = DEFAULT_MAX_DECOMPRESSED_SIZE;
+            let range = decompress_range(&compressed, alg, 70_000, 1024, max).unwrap();
+            assert_eq!(range, &data[70_000..71_024], "{:?}", alg);
+            assert!(decompress_range(&compressed, alg, 0, 0, max)
+                .unwrap()
+                .is_empty());
+            assert!(decompress_range(&compressed, alg, 99_000, 1001, max).is_err());
+        }
+    }
+
+    #[test]
+    fn test_framed_roundtrip() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let max = DEFAULT_MAX_DECOMPRESSED_SIZE;
+        for alg in [Compression::Zstd, Compression::Lz4, Compression::None] {
+            let framed = compress_framed(&data, &alg, 4096).unwrap();
+            let whole = decompress_framed_range(&framed, &alg, 4096, 0, data.len(), max).unwrap();
+            assert_eq!(whole, data, "{:?}", alg);
+            // Ranges within one frame, across frames, and ending in the short last frame
+            for (offset, length) in [(100, 50), (4000, 200), (8192, 4096), (98_000, 2000)] {
+                let range =
+                    decompress_framed_range(&framed, &alg, 4096, offset, length, max).unwrap();
+                assert_eq!(range, &data[offset..offset + length], "{:?}", alg);
+            }
+            assert!(decompress_framed_range(&framed, &alg, 4096, 99_000, 2000, max).is_err());
+            assert!(decompress_framed_range(&framed[..20], &alg, 4096, 0, 10, max).is_err());
+        }
+
+        // Frame size 0 is the plain single stream
+        let plain = compress_framed(&data, &Compression::Zstd, 0).unwrap();
+        assert_eq!(decompress(&plain, Compression::Zstd).unwrap(), data);
+    }
+
+    #[test]
+    fn test_bomb_is_capped() {
+        // 16 MiB of zeros compresses to a few KiB (and stays lz4-sized on 32-bit targets)
+        let bomb = vec![0u8; 16 << 20];
+        for alg in [Compression::Zstd, Compression::Lz4, Compression::Brotli] {
+            let compressed = compress(&bomb, alg).unwrap();
+            let err = decompress_bounded(&compressed, alg, 1 << 20).unwrap_err();
+            assert!(err.to_string().contains("limit"), "{:?}: {}", alg, err);
+        }
+        assert!(decompress_bounded(&bomb[..100], Compression::None, 99).is_err());
+
+        // Exactly at the limit is fine
+        let data = vec![7u8; 4096];
+        for alg in [Compression::Zstd, Compression::Lz4, Compression::Brotli] {
+            let compressed = compress(&data, alg).unwrap();
+            assert_eq!(decompress_bounded(&compressed, alg, 4096).unwrap(), data);
+        }
+    }
 }
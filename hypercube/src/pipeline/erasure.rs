@@ -0,0 +1,245 @@
+use crate::error::{HypercubeError, Result};
+use crate::gf256::{inv, invert, mul};
+use serde::{Deserialize, Serialize};
+
+/// Systematic Reed–Solomon code over a partition's transformed blocks
+/// Blocks are grouped into stripes of `data` blocks followed by `parity` blocks, and a
+/// stripe survives the loss of any `parity` of its blocks. Parity rows come from a
+/// Cauchy matrix, so every square submatrix is invertible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErasureCode {
+    /// Data blocks per stripe
+    pub data: u8,
+    /// Parity blocks per stripe
+    pub parity: u8,
+}
+
+impl ErasureCode {
+    pub fn new(data: u8, parity: u8) -> Result<Self> {
+        // The last stripe may carry up to twice the parity, and Cauchy points must not collide
+        if data == 0 || parity == 0 || data as usize + 2 * parity as usize > 255 {
+            return Err(HypercubeError::InvalidHeader(format!(
+                "Erasure code {}+{} (need at least one data and one parity block, and \
+                 data + 2 * parity <= 255)",
+                data, parity
+            )));
+        }
+        Ok(Self { data, parity })
+    }
+
+    /// Data and parity block counts of each stripe of a partition of `blocks` blocks
+    /// Full stripes come first. A remainder longer than `parity` forms a short stripe;
+    /// a shorter one becomes extra parity for the last full stripe.
+    pub fn stripes(&self, blocks: usize) -> Vec<(usize, usize)> {
+        let (data, parity) = (self.data as usize, self.parity as usize);
+        let mut stripes = vec![(data, parity); blocks / (data + parity)];
+        match blocks % (data + parity) {
+            0 => {}
+            rest if rest > parity => stripes.push((rest - parity, parity)),
+            rest => match stripes.last_mut() {
+                Some(last) => last.1 += rest,
+                None => stripes.push((0, rest)),
+            },
+        }
+        stripes
+    }
+
+    /// Data blocks a partition of `blocks` blocks carries
+    pub fn data_blocks(&self, blocks: usize) -> usize {
+        self.stripes(blocks).iter().map(|&(data, _)| data).sum()
+    }
+
+    /// Interleave parity into `blocks`, to fill exactly `total` blocks
+    pub fn encode(&self, blocks: Vec<Vec<u8>>, total: usize) -> Result<Vec<Vec<u8>>> {
+        if blocks.len() != self.data_blocks(total) || blocks.is_empty() {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "An erasure-coded partition needs {} data blocks, got {}",
+                self.data_blocks(total),
+                blocks.len()
+            )));
+        }
+        let mut source = blocks.into_iter();
+        let mut encoded = Vec::with_capacity(total);
+        for (data, parity) in self.stripes(total) {
+            let stripe: Vec<Vec<u8>> = source.by_ref().take(data).collect();
+            let parity_blocks: Vec<Vec<u8>> = (0..parity)
+                .map(|row| combine(&self.parity_row(row, data), &stripe))
+                .collect();
+            encoded.extend(stripe);
+            encoded.extend(parity_blocks);
+        }
+        Ok(encoded)
+    }
+
+    /// Rebuild the data blocks from a partition's `slots`, None where a block was lost
+    pub fn decode(&self, mut slots: Vec<Option<Vec<u8>>>) -> Result<Vec<Vec<u8>>> {
+        let mut decoded = Vec::with_capacity(self.data_blocks(slots.len()));
+        let mut start = 0;
+        for (index, (data, parity)) in self.stripes(slots.len()).into_iter().enumerate() {
+            let stripe = &mut slots[start..start + data + parity];
+            start += data + parity;
+            let missing: Vec<usize> = (0..data).filter(|&i| stripe[i].is_none()).collect();
+            let rows: Vec<usize> = (0..parity)
+                .filter(|&row| stripe[data + row].is_some())
+                .take(missing.len())
+                .collect();
+            if rows.len() < missing.len() {
+                let lost = stripe.iter().filter(|block| block.is_none()).count();
+                return Err(HypercubeError::IntegrityError(format!(
+                    "Stripe {} lost {} of its {} blocks, more than its {} parity blocks restore",
+                    index,
+                    lost,
+                    data + parity,
+                    parity
+                )));
+            }
+            if !missing.is_empty() {
+                self.restore(stripe, data, &missing, &rows)?;
+            }
+            decoded.extend(stripe[..data].iter_mut().map(|block| block.take().unwrap()));
+        }
+        Ok(decoded)
+    }
+
+    /// Solve for the `missing` data blocks of a stripe from its parity `rows`
+    fn restore(
+        &self,
+        stripe: &mut [Option<Vec<u8>>],
+        data: usize,
+        missing: &[usize],
+        rows: &[usize],
+    ) -> Result<()> {
+        let size = stripe.iter().flatten().next().map_or(0, Vec::len);
+        // Each parity block less the present data blocks' share is a sum over the missing
+        let remainders: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|&row| {
+                let coefficients = self.parity_row(row, data);
+                let mut remainder = stripe[data + row].clone().unwrap();
+                for (i, block) in stripe[..data].iter().enumerate() {
+                    if let Some(block) = block {
+                        for (out, &byte) in remainder.iter_mut().zip(block) {
+                            *out ^= mul(coefficients[i], byte);
+                        }
+                    }
+                }
+                remainder
+            })
+            .collect();
+        let matrix = rows
+            .iter()
+            .map(|&row| {
+                let coefficients = self.parity_row(row, data);
+                missing.iter().map(|&i| coefficients[i]).collect()
+            })
+            .collect();
+        let inverse = invert(matrix)
+            .ok_or_else(|| HypercubeError::IntegrityError("Singular erasure matrix".into()))?;
+        for (k, &i) in missing.iter().enumerate() {
+            let mut block = combine(&inverse[k], &remainders);
+            block.resize(size, 0);
+            stripe[i] = Some(block);
+        }
+        Ok(())
+    }
+
+    /// Coefficients of parity block `row` over a stripe's `data` blocks
+    fn parity_row(&self, row: usize, data: usize) -> Vec<u8> {
+        let point = self.data as usize + row;
+        (0..data).map(|i| inv((point ^ i) as u8)).collect()
+    }
+}
+
+impl std::str::FromStr for ErasureCode {
+    type Err = HypercubeError;
+    /// `DATA+PARITY`, e.g. `6+2`
+    fn from_str(s: &str) -> Result<Self> {
+        let (data, parity) = s
+            .split_once('+')
+            .and_then(|(data, parity)| {
+                Some((data.trim().parse().ok()?, parity.trim().parse().ok()?))
+            })
+            .ok_or_else(|| {
+                HypercubeError::InvalidFormat(format!(
+                    "Erasure code {} (expected DATA+PARITY, e.g. 6+2)",
+                    s
+                ))
+            })?;
+        Self::new(data, parity)
+    }
+}
+
+/// Sum of `blocks` weighted by `coefficients`
+fn combine(coefficients: &[u8], blocks: &[Vec<u8>]) -> Vec<u8> {
+    let mut sum = vec![0u8; blocks.first().map_or(0, Vec::len)];
+    for (&coefficient, block) in coefficients.iter().zip(blocks) {
+        for (out, &byte) in sum.iter_mut().zip(block) {
+            *out ^= mul(coefficient, byte);
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(count: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| (0..24).map(|j| (i * 31 + j * 7) as u8).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_stripes() {
+        let code = ErasureCode::new(4, 1).unwrap();
+        assert_eq!(code.stripes(10), vec![(4, 1), (4, 1)]);
+        assert_eq!(code.stripes(13), vec![(4, 1), (4, 1), (2, 1)]);
+        assert_eq!(code.stripes(11), vec![(4, 1), (4, 2)]);
+        assert_eq!(code.data_blocks(64), 51);
+        assert!(ErasureCode::new(0, 1).is_err());
+        assert!(ErasureCode::new(4, 0).is_err());
+        assert!(ErasureCode::new(200, 28).is_err());
+        assert_eq!(
+            "6+2".parse::<ErasureCode>().unwrap(),
+            ErasureCode::new(6, 2).unwrap()
+        );
+        for bad in ["", "6", "6+", "6:2", "0+2", "300+2"] {
+            assert!(bad.parse::<ErasureCode>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_erasure_roundtrip() {
+        let code = ErasureCode::new(4, 2).unwrap();
+        for total in [12, 16, 17, 23] {
+            let source = blocks(code.data_blocks(total));
+            let encoded = code.encode(source.clone(), total).unwrap();
+            assert_eq!(encoded.len(), total);
+            let slots: Vec<_> = encoded.iter().cloned().map(Some).collect();
+            assert_eq!(code.decode(slots).unwrap(), source);
+
+            // Lose up to two blocks of every stripe, data or parity
+            for (a, b) in [(0, 1), (0, 4), (3, 5), (1, 7), (6, 11)] {
+                let mut slots: Vec<_> = encoded.iter().cloned().map(Some).collect();
+                slots[a] = None;
+                slots[b % total] = None;
+                assert_eq!(code.decode(slots).unwrap(), source, "{} {} {}", total, a, b);
+            }
+        }
+        assert!(code.encode(blocks(3), 12).is_err());
+    }
+
+    #[test]
+    fn test_erasure_too_many_lost() {
+        let code = ErasureCode::new(4, 1).unwrap();
+        let encoded = code.encode(blocks(8), 10).unwrap();
+        let mut slots: Vec<_> = encoded.into_iter().map(Some).collect();
+        slots[6] = None;
+        slots[9] = None;
+        assert!(matches!(
+            code.decode(slots),
+            Err(HypercubeError::IntegrityError(_))
+        ));
+    }
+}
@@ -2,7 +2,7 @@
 /// block_size must be evenly divisible by fragment_size (no remainders)
 pub fn fragment_block(block: &[u8], fragment_size: usize) -> Vec<Vec<u8>> {
     assert!(
-        block.len() % fragment_size == 0,
+        block.len().is_multiple_of(fragment_size),
         "Block size {} must be evenly divisible by fragment size {}",
         block.len(),
         fragment_size
@@ -50,7 +50,7 @@ pub fn unfragment_all(fragments: &[Vec<u8>], fragments_per_block: usize) -> Vec<
 
     fragments
         .chunks(fragments_per_block)
-        .map(|chunk| unfragment_block(chunk))
+        .map(unfragment_block)
         .collect()
 }
 
@@ -59,14 +59,14 @@ pub fn unfragment_all(fragments: &[Vec<u8>], fragments_per_block: usize) -> Vec<
 pub fn calculate_fragment_size(block_size: usize) -> usize {
     // Prefer these sizes in order
     for &size in &[64, 128, 32, 256, 16] {
-        if block_size % size == 0 {
+        if block_size.is_multiple_of(size) {
             return size;
         }
     }
     // Fallback: find any power of 2 that works
     let mut size = 64;
     while size > 1 {
-        if block_size % size == 0 {
+        if block_size.is_multiple_of(size) {
             return size;
         }
         size /= 2;
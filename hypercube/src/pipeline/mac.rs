@@ -1,12 +1,26 @@
 use crate::error::{HypercubeError, Result};
 use crate::header::HashAlgorithm;
 use crate::pipeline::sequence::{SequencedBlock, SEQUENCE_SIZE};
+use crate::pipeline::MacProvider;
+use hmac::digest::KeyInit;
 use hmac::{Hmac, Mac};
-use sha2::Sha256;
-use sha3::Sha3_256;
+use sha2::{Sha256, Sha512};
+use sha3::{Sha3_256, Sha3_512};
 
 type HmacSha3_256 = Hmac<Sha3_256>;
+type HmacSha3_512 = Hmac<Sha3_512>;
 type HmacSha256 = Hmac<Sha256>;
+type HmacSha512 = Hmac<Sha512>;
+
+/// How tags longer than 256 bits are produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WideTag {
+    /// Header versions 1-2: the 256-bit tag extended with BLAKE3 hashes of itself
+    /// (the extension is public, so security stays at 256 bits)
+    Expanded,
+    /// Header version 3+: HMAC-SHA3-512, HMAC-SHA-512 or 512 bits of keyed BLAKE3 output
+    Native,
+}
 
 /// A block with sequence, data, and MAC tag
 #[derive(Debug, Clone)]
@@ -54,41 +68,69 @@ pub fn compute_mac(
     secret: &[u8],
     algorithm: HashAlgorithm,
     mac_bits: usize,
+    wide: WideTag,
 ) -> Vec<u8> {
-    let message = block.to_bytes();
-    compute_mac_raw(&message, secret, algorithm, mac_bits)
+    let parts = [&block.sequence.as_bytes()[..], &block.data];
+    compute_mac_parts(&parts, secret, algorithm, mac_bits, wide)
 }
 
-/// Compute MAC for raw bytes
-fn compute_mac_raw(
-    data: &[u8],
+/// Compute MAC over the concatenation of `parts`, fed in place without copying them
+fn compute_mac_parts(
+    parts: &[&[u8]],
     secret: &[u8],
     algorithm: HashAlgorithm,
     mac_bits: usize,
+    wide: WideTag,
 ) -> Vec<u8> {
     let mac_bytes = mac_bits / 8;
+    if mac_bytes > 32 && wide == WideTag::Native {
+        return compute_wide_mac(parts, secret, algorithm, mac_bytes);
+    }
 
     match algorithm {
-        HashAlgorithm::Sha3 => {
-            let mut mac =
-                HmacSha3_256::new_from_slice(secret).expect("HMAC can take key of any size");
-            mac.update(data);
-            let result = mac.finalize().into_bytes();
-            truncate_mac(&result, mac_bytes)
-        }
+        HashAlgorithm::Sha3 => truncate_mac(&hmac::<HmacSha3_256>(parts, secret), mac_bytes),
         HashAlgorithm::Blake3 => {
-            let key = derive_blake3_key(secret);
-            let hash = blake3::keyed_hash(&key, data);
-            truncate_mac(hash.as_bytes(), mac_bytes)
+            let mut hasher = blake3::Hasher::new_keyed(&derive_blake3_key(secret));
+            for part in parts {
+                hasher.update(part);
+            }
+            truncate_mac(hasher.finalize().as_bytes(), mac_bytes)
         }
-        HashAlgorithm::Sha256 => {
-            let mut mac =
-                HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size");
-            mac.update(data);
-            let result = mac.finalize().into_bytes();
-            truncate_mac(&result, mac_bytes)
+        HashAlgorithm::Sha256 => truncate_mac(&hmac::<HmacSha256>(parts, secret), mac_bytes),
+    }
+}
+
+/// Tags longer than 256 bits from a hash with a matching output size
+fn compute_wide_mac(
+    parts: &[&[u8]],
+    secret: &[u8],
+    algorithm: HashAlgorithm,
+    mac_bytes: usize,
+) -> Vec<u8> {
+    let mut result = match algorithm {
+        HashAlgorithm::Sha3 => hmac::<HmacSha3_512>(parts, secret),
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new_keyed(&derive_blake3_key(secret));
+            for part in parts {
+                hasher.update(part);
+            }
+            let mut output = vec![0u8; mac_bytes];
+            hasher.finalize_xof().fill(&mut output);
+            output
         }
+        HashAlgorithm::Sha256 => hmac::<HmacSha512>(parts, secret),
+    };
+    result.truncate(mac_bytes);
+    result
+}
+
+/// Full-length HMAC tag over `parts`
+fn hmac<M: Mac + KeyInit>(parts: &[&[u8]], secret: &[u8]) -> Vec<u8> {
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC can take key of any size");
+    for part in parts {
+        mac.update(part);
     }
+    mac.finalize().into_bytes().to_vec()
 }
 
 /// Derive a 32-byte key for BLAKE3 from arbitrary secret
@@ -120,17 +162,34 @@ pub fn verify_mac(
     secret: &[u8],
     algorithm: HashAlgorithm,
     mac_bits: usize,
+    wide: WideTag,
 ) -> bool {
-    let mut message = Vec::with_capacity(SEQUENCE_SIZE + block.data.len());
-    message.extend_from_slice(&block.sequence_bytes);
-    message.extend_from_slice(&block.data);
-
-    let expected_mac = compute_mac_raw(&message, secret, algorithm, mac_bits);
+    let parts = [&block.sequence_bytes[..], &block.data];
+    let expected_mac = compute_mac_parts(&parts, secret, algorithm, mac_bits, wide);
     constant_time_compare(&expected_mac, &block.mac)
 }
 
+/// Verify the MAC of a serialized block (sequence || data || mac) in place
+pub fn verify_block_bytes(
+    block: &[u8],
+    secret: &[u8],
+    algorithm: HashAlgorithm,
+    mac_bits: usize,
+    wide: WideTag,
+) -> bool {
+    let Some(split) = block.len().checked_sub(mac_bits / 8) else {
+        return false;
+    };
+    let (message, mac) = block.split_at(split);
+    if message.len() < SEQUENCE_SIZE {
+        return false;
+    }
+    let expected_mac = compute_mac_parts(&[message], secret, algorithm, mac_bits, wide);
+    constant_time_compare(&expected_mac, mac)
+}
+
 /// Constant-time comparison to prevent timing attacks
-fn constant_time_compare(a: &[u8], b: &[u8]) -> bool {
+pub fn constant_time_compare(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
         return false;
     }
@@ -142,17 +201,37 @@ fn constant_time_compare(a: &[u8], b: &[u8]) -> bool {
     result == 0
 }
 
+/// The built-in MACs, registered under their header names
+impl MacProvider for HashAlgorithm {
+    fn compute(&self, parts: &[&[u8]], secret: &[u8], mac_bits: usize, wide: WideTag) -> Vec<u8> {
+        compute_mac_parts(parts, secret, *self, mac_bits, wide)
+    }
+
+    fn verify(&self, block: &[u8], secret: &[u8], mac_bits: usize, wide: WideTag) -> bool {
+        verify_block_bytes(block, secret, *self, mac_bits, wide)
+    }
+
+    /// Expanded tags stop being keyed after the 256-bit HMAC or BLAKE3 output
+    fn max_mac_bits(&self, wide: WideTag) -> usize {
+        match wide {
+            WideTag::Native => 512,
+            WideTag::Expanded => 256,
+        }
+    }
+}
+
 /// Authenticate sequenced blocks
 pub fn authenticate_blocks(
     blocks: Vec<SequencedBlock>,
     secret: &[u8],
     algorithm: HashAlgorithm,
     mac_bits: usize,
+    wide: WideTag,
 ) -> Vec<AuthenticatedBlock> {
     blocks
         .into_iter()
         .map(|block| {
-            let mac = compute_mac(&block, secret, algorithm, mac_bits);
+            let mac = compute_mac(&block, secret, algorithm, mac_bits, wide);
             AuthenticatedBlock {
                 sequence_bytes: *block.sequence.as_bytes(),
                 data: block.data,
@@ -168,13 +247,14 @@ pub fn verify_and_extract_blocks(
     secret: &[u8],
     algorithm: HashAlgorithm,
     mac_bits: usize,
+    wide: WideTag,
 ) -> Result<Vec<SequencedBlock>> {
     use crate::pipeline::sequence::SequenceNumber;
 
     let mut result = Vec::with_capacity(blocks.len());
 
     for (i, block) in blocks.into_iter().enumerate() {
-        if !verify_mac(&block, secret, algorithm, mac_bits) {
+        if !verify_mac(&block, secret, algorithm, mac_bits, wide) {
             return Err(HypercubeError::MacVerificationFailed(i));
         }
 
@@ -200,7 +280,7 @@ mod tests {
     fn test_compute_mac_sha3() {
         let block = test_block();
         let secret = b"secret key";
-        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256);
+        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded);
         assert_eq!(mac.len(), 32);
     }
 
@@ -208,7 +288,7 @@ mod tests {
     fn test_compute_mac_blake3() {
         let block = test_block();
         let secret = b"secret key";
-        let mac = compute_mac(&block, secret, HashAlgorithm::Blake3, 256);
+        let mac = compute_mac(&block, secret, HashAlgorithm::Blake3, 256, WideTag::Expanded);
         assert_eq!(mac.len(), 32);
     }
 
@@ -216,7 +296,7 @@ mod tests {
     fn test_compute_mac_sha256() {
         let block = test_block();
         let secret = b"secret key";
-        let mac = compute_mac(&block, secret, HashAlgorithm::Sha256, 256);
+        let mac = compute_mac(&block, secret, HashAlgorithm::Sha256, 256, WideTag::Expanded);
         assert_eq!(mac.len(), 32);
     }
 
@@ -225,20 +305,49 @@ mod tests {
         let block = test_block();
         let secret = b"secret key";
 
-        let mac128 = compute_mac(&block, secret, HashAlgorithm::Sha3, 128);
-        let mac256 = compute_mac(&block, secret, HashAlgorithm::Sha3, 256);
-        let mac512 = compute_mac(&block, secret, HashAlgorithm::Sha3, 512);
+        let mac128 = compute_mac(&block, secret, HashAlgorithm::Sha3, 128, WideTag::Expanded);
+        let mac256 = compute_mac(&block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded);
+        let mac512 = compute_mac(&block, secret, HashAlgorithm::Sha3, 512, WideTag::Expanded);
 
         assert_eq!(mac128.len(), 16);
         assert_eq!(mac256.len(), 32);
         assert_eq!(mac512.len(), 64);
     }
 
+    #[test]
+    fn test_native_wide_macs() {
+        let block = test_block();
+        let secret = b"secret key";
+        for algorithm in [HashAlgorithm::Sha3, HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            let expanded = compute_mac(&block, secret, algorithm, 512, WideTag::Expanded);
+            let native = compute_mac(&block, secret, algorithm, 512, WideTag::Native);
+            assert_eq!(native.len(), 64);
+            assert_ne!(native, expanded);
+            // The legacy tail is a public function of the head; the native one is not
+            assert_eq!(&expanded[32..], blake3::hash(&expanded[..32]).as_bytes());
+            assert_ne!(&native[32..], blake3::hash(&native[..32]).as_bytes());
+
+            let auth_block = AuthenticatedBlock {
+                sequence_bytes: *block.sequence.as_bytes(),
+                data: block.data.clone(),
+                mac: native,
+            };
+            assert!(verify_mac(&auth_block, secret, algorithm, 512, WideTag::Native));
+            assert!(!verify_mac(&auth_block, secret, algorithm, 512, WideTag::Expanded));
+        }
+
+        // Up to 256 bits both constructions agree
+        assert_eq!(
+            compute_mac(&block, secret, HashAlgorithm::Sha3, 256, WideTag::Native),
+            compute_mac(&block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded)
+        );
+    }
+
     #[test]
     fn test_verify_mac_valid() {
         let block = test_block();
         let secret = b"secret key";
-        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256);
+        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded);
 
         let auth_block = AuthenticatedBlock {
             sequence_bytes: *block.sequence.as_bytes(),
@@ -246,14 +355,14 @@ mod tests {
             mac,
         };
 
-        assert!(verify_mac(&auth_block, secret, HashAlgorithm::Sha3, 256));
+        assert!(verify_mac(&auth_block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded));
     }
 
     #[test]
     fn test_verify_mac_invalid_secret() {
         let block = test_block();
         let secret = b"secret key";
-        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256);
+        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded);
 
         let auth_block = AuthenticatedBlock {
             sequence_bytes: *block.sequence.as_bytes(),
@@ -265,7 +374,8 @@ mod tests {
             &auth_block,
             b"wrong key",
             HashAlgorithm::Sha3,
-            256
+            256,
+            WideTag::Expanded
         ));
     }
 
@@ -273,7 +383,7 @@ mod tests {
     fn test_verify_mac_tampered_data() {
         let block = test_block();
         let secret = b"secret key";
-        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256);
+        let mac = compute_mac(&block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded);
 
         let mut auth_block = AuthenticatedBlock {
             sequence_bytes: *block.sequence.as_bytes(),
@@ -284,7 +394,7 @@ mod tests {
         // Tamper with data
         auth_block.data[0] ^= 0xFF;
 
-        assert!(!verify_mac(&auth_block, secret, HashAlgorithm::Sha3, 256));
+        assert!(!verify_mac(&auth_block, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded));
     }
 
     #[test]
@@ -294,10 +404,10 @@ mod tests {
             .collect();
 
         let secret = b"my secret";
-        let authenticated = authenticate_blocks(blocks.clone(), secret, HashAlgorithm::Sha3, 256);
+        let authenticated = authenticate_blocks(blocks.clone(), secret, HashAlgorithm::Sha3, 256, WideTag::Expanded);
 
         let extracted =
-            verify_and_extract_blocks(authenticated, secret, HashAlgorithm::Sha3, 256).unwrap();
+            verify_and_extract_blocks(authenticated, secret, HashAlgorithm::Sha3, 256, WideTag::Expanded).unwrap();
 
         assert_eq!(extracted.len(), blocks.len());
         for (orig, ext) in blocks.iter().zip(extracted.iter()) {
@@ -322,6 +432,33 @@ mod tests {
         assert_eq!(auth_block.mac, restored.mac);
     }
 
+    #[test]
+    fn test_verify_block_bytes() {
+        let block = SequencedBlock::new(SequenceNumber::new(7), vec![0xA5; 4096]);
+        let secret = b"secret key";
+        for algorithm in [HashAlgorithm::Sha3, HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            let tags = [
+                (128, WideTag::Native),
+                (512, WideTag::Expanded),
+                (512, WideTag::Native),
+            ];
+            for (mac_bits, wide) in tags {
+                let mut bytes = AuthenticatedBlock {
+                    sequence_bytes: *block.sequence.as_bytes(),
+                    data: block.data.clone(),
+                    mac: compute_mac(&block, secret, algorithm, mac_bits, wide),
+                }
+                .to_bytes();
+                assert!(verify_block_bytes(&bytes, secret, algorithm, mac_bits, wide));
+                assert!(!verify_block_bytes(&bytes, b"wrong", algorithm, mac_bits, wide));
+                bytes[SEQUENCE_SIZE] ^= 1;
+                assert!(!verify_block_bytes(&bytes, secret, algorithm, mac_bits, wide));
+            }
+        }
+        let short = [0u8; 20];
+        assert!(!verify_block_bytes(&short, secret, HashAlgorithm::Sha3, 256, WideTag::Native));
+    }
+
     #[test]
     fn test_constant_time_compare() {
         assert!(constant_time_compare(&[1, 2, 3], &[1, 2, 3]));
@@ -1,13 +1,21 @@
 pub mod aont;
 pub mod compress;
+pub mod erasure;
 pub mod fragment;
 pub mod mac;
+pub mod registry;
 pub mod segment;
 pub mod sequence;
+pub mod shuffle;
+pub mod whiten;
 
 pub use aont::*;
 pub use compress::*;
+pub use erasure::*;
 pub use fragment::*;
 pub use mac::*;
+pub use registry::*;
 pub use segment::*;
 pub use sequence::*;
+pub use shuffle::*;
+pub use whiten::*;
@@ -0,0 +1,388 @@
+//! Extension points for the pipeline stages.
+//!
+//! Compression, whitening, the AONT and the block MAC each sit behind a trait. The built-in
+//! algorithms implement them and are registered under the names the header uses for them
+//! (`zstd`, `rivest`, `sha3`, ...). A downstream crate registers its own implementations
+//! under new names, then names them in a header's `stages` to use them for a container.
+//! Opening a container that names an implementation this process has not registered fails
+//! with `UnsupportedAlgorithm`.
+
+use crate::domains::Domains;
+use crate::error::{HypercubeError, Result};
+use crate::header::{Aont, Compression, HashAlgorithm, VhcHeader};
+use crate::pipeline::{constant_time_compare, NoWhitening, WideTag, SEQUENCE_SIZE};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Compresses a partition's payload (or each of its frames)
+pub trait CompressionCodec: Send + Sync {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decompress, refusing to produce more than `max_size` bytes
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>>;
+
+    /// Decompress only `length` bytes starting at `offset` of the original data
+    /// The default decompresses everything first
+    fn decompress_range(
+        &self,
+        data: &[u8],
+        offset: usize,
+        length: usize,
+        max_size: usize,
+    ) -> Result<Vec<u8>> {
+        let data = self.decompress(data, max_size)?;
+        offset
+            .checked_add(length)
+            .and_then(|end| data.get(offset..end))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| {
+                HypercubeError::DecompressionError("range past the end of the data".into())
+            })
+    }
+
+    /// Largest possible output of `compress` for an input of `len` bytes, for sizing cubes
+    fn size_bound(&self, len: usize) -> usize;
+}
+
+/// Unkeyed, reversible transform of a partition's fragments, applied before the AONT
+/// It must keep the number and length of the fragments
+pub trait Whitener: Send + Sync {
+    fn whiten(&self, fragments: &mut [Vec<u8>], domains: &Domains);
+    fn unwhiten(&self, fragments: &mut [Vec<u8>], domains: &Domains);
+}
+
+/// All-or-nothing transform over a partition's fragments
+pub trait AontTransform: Send + Sync {
+    /// Whole blocks `apply` adds to a partition (Rivest's key block is one)
+    fn overhead_blocks(&self) -> usize;
+
+    /// Transform the fragments; `frags_per_block` keeps added fragments block-aligned
+    fn apply(
+        &self,
+        fragments: Vec<Vec<u8>>,
+        frags_per_block: usize,
+        domains: &Domains,
+    ) -> Vec<Vec<u8>>;
+
+    fn reverse(
+        &self,
+        fragments: Vec<Vec<u8>>,
+        frags_per_block: usize,
+        domains: &Domains,
+    ) -> Vec<Vec<u8>>;
+}
+
+/// Keyed tag authenticating each block's sequence number and data
+pub trait MacProvider: Send + Sync {
+    /// A `mac_bits / 8` byte tag over the concatenation of `parts`
+    fn compute(&self, parts: &[&[u8]], secret: &[u8], mac_bits: usize, wide: WideTag) -> Vec<u8>;
+
+    /// Verify a serialized block (sequence || data || mac) in place
+    fn verify(&self, block: &[u8], secret: &[u8], mac_bits: usize, wide: WideTag) -> bool {
+        let Some(split) = block.len().checked_sub(mac_bits / 8) else {
+            return false;
+        };
+        let (message, mac) = block.split_at(split);
+        message.len() >= SEQUENCE_SIZE
+            && constant_time_compare(&self.compute(&[message], secret, mac_bits, wide), mac)
+    }
+
+    /// Longest tag, in bits, whose every bit is keyed output rather than an extension;
+    /// new blocks are refused wider tags. Override to offer more than 256 bits
+    fn max_mac_bits(&self, _wide: WideTag) -> usize {
+        256
+    }
+}
+
+/// Registered implementations of every stage, by name
+struct Registry {
+    codecs: HashMap<String, Arc<dyn CompressionCodec>>,
+    whiteners: HashMap<String, Arc<dyn Whitener>>,
+    aonts: HashMap<String, Arc<dyn AontTransform>>,
+    macs: HashMap<String, Arc<dyn MacProvider>>,
+}
+
+impl Registry {
+    fn builtin() -> Self {
+        let codecs = [
+            Compression::Zstd,
+            Compression::Lz4,
+            Compression::Brotli,
+            Compression::None,
+        ];
+        let macs = [
+            HashAlgorithm::Sha3,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Sha256,
+        ];
+        Self {
+            codecs: codecs
+                .into_iter()
+                .map(|codec| (codec.name().into(), Arc::new(codec) as _))
+                .collect(),
+            whiteners: [("none".into(), Arc::new(NoWhitening) as _)].into(),
+            aonts: [Aont::Rivest, Aont::Oaep]
+                .into_iter()
+                .map(|aont| (aont.name().into(), Arc::new(aont) as _))
+                .collect(),
+            macs: macs
+                .into_iter()
+                .map(|mac| (mac.name().into(), Arc::new(mac) as _))
+                .collect(),
+        }
+    }
+}
+
+static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+
+fn registry() -> &'static RwLock<Registry> {
+    REGISTRY.get_or_init(|| RwLock::new(Registry::builtin()))
+}
+
+/// Add `value` under a name not yet taken; built-ins cannot be replaced
+fn register<T: ?Sized>(
+    stages: &mut HashMap<String, Arc<T>>,
+    stage: &str,
+    name: &str,
+    value: Arc<T>,
+) -> Result<()> {
+    if stages.contains_key(name) {
+        return Err(HypercubeError::UnsupportedAlgorithm(format!(
+            "{}: {} is already registered",
+            stage, name
+        )));
+    }
+    stages.insert(name.into(), value);
+    Ok(())
+}
+
+fn lookup<T: ?Sized>(stages: &HashMap<String, Arc<T>>, stage: &str, name: &str) -> Result<Arc<T>> {
+    stages.get(name).cloned().ok_or_else(|| {
+        HypercubeError::UnsupportedAlgorithm(format!("{}: {} is not registered", stage, name))
+    })
+}
+
+pub fn register_codec(name: &str, codec: Arc<dyn CompressionCodec>) -> Result<()> {
+    let mut registry = registry().write().unwrap();
+    register(&mut registry.codecs, "compression", name, codec)
+}
+
+pub fn register_whitener(name: &str, whitener: Arc<dyn Whitener>) -> Result<()> {
+    let mut registry = registry().write().unwrap();
+    register(&mut registry.whiteners, "whitener", name, whitener)
+}
+
+pub fn register_aont(name: &str, aont: Arc<dyn AontTransform>) -> Result<()> {
+    let mut registry = registry().write().unwrap();
+    register(&mut registry.aonts, "aont", name, aont)
+}
+
+pub fn register_mac(name: &str, mac: Arc<dyn MacProvider>) -> Result<()> {
+    let mut registry = registry().write().unwrap();
+    register(&mut registry.macs, "mac", name, mac)
+}
+
+/// Names registered for each stage in this process, built-ins included, sorted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StageNames {
+    pub compression: Vec<String>,
+    pub whitener: Vec<String>,
+    pub aont: Vec<String>,
+    pub mac: Vec<String>,
+}
+
+pub fn registered_stages() -> StageNames {
+    fn names<T: ?Sized>(stages: &HashMap<String, Arc<T>>) -> Vec<String> {
+        let mut names: Vec<String> = stages.keys().cloned().collect();
+        names.sort();
+        names
+    }
+    let registry = registry().read().unwrap();
+    StageNames {
+        compression: names(&registry.codecs),
+        whitener: names(&registry.whiteners),
+        aont: names(&registry.aonts),
+        mac: names(&registry.macs),
+    }
+}
+
+/// The codec named `name`, or the built-in `fallback` when None
+pub fn codec_named(name: Option<&str>, fallback: Compression) -> Result<Arc<dyn CompressionCodec>> {
+    match name {
+        Some(name) => lookup(&registry().read().unwrap().codecs, "compression", name),
+        None => Ok(Arc::new(fallback)),
+    }
+}
+
+/// The header's compression codec
+pub fn codec_for(header: &VhcHeader) -> Result<Arc<dyn CompressionCodec>> {
+    codec_named(header.stages.compression.as_deref(), header.compression)
+}
+
+/// The header's whitener; one that changes nothing when unset
+pub fn whitener_for(header: &VhcHeader) -> Result<Arc<dyn Whitener>> {
+    match &header.stages.whitener {
+        Some(name) => lookup(&registry().read().unwrap().whiteners, "whitener", name),
+        None => Ok(Arc::new(NoWhitening)),
+    }
+}
+
+/// The header's all-or-nothing transform
+pub fn aont_for(header: &VhcHeader) -> Result<Arc<dyn AontTransform>> {
+    match &header.stages.aont {
+        Some(name) => lookup(&registry().read().unwrap().aonts, "aont", name),
+        None => Ok(Arc::new(header.aont)),
+    }
+}
+
+/// The header's block MAC
+pub fn mac_for(header: &VhcHeader) -> Result<Arc<dyn MacProvider>> {
+    match &header.stages.mac {
+        Some(name) => lookup(&registry().read().unwrap().macs, "mac", name),
+        None => Ok(Arc::new(header.hash)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partition::{create_partition, extract_partition};
+
+    /// Flips every bit, so whitened fragments differ from the plain ones
+    struct Invert;
+
+    impl Whitener for Invert {
+        fn whiten(&self, fragments: &mut [Vec<u8>], _domains: &Domains) {
+            for byte in fragments.iter_mut().flatten() {
+                *byte = !*byte;
+            }
+        }
+
+        fn unwhiten(&self, fragments: &mut [Vec<u8>], domains: &Domains) {
+            self.whiten(fragments, domains);
+        }
+    }
+
+    /// Stores data as is, behind a marker byte
+    struct Marked;
+
+    impl CompressionCodec for Marked {
+        fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+            Ok([&[0xA5], data].concat())
+        }
+
+        fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+            match data.split_first() {
+                Some((0xA5, rest)) if rest.len() <= max_size => Ok(rest.to_vec()),
+                _ => Err(HypercubeError::DecompressionError("not marked".into())),
+            }
+        }
+
+        fn size_bound(&self, len: usize) -> usize {
+            len + 1
+        }
+    }
+
+    /// The built-in SHA3 MAC with a fixed key appended to the secret
+    struct Peppered;
+
+    impl MacProvider for Peppered {
+        fn compute(
+            &self,
+            parts: &[&[u8]],
+            secret: &[u8],
+            mac_bits: usize,
+            wide: WideTag,
+        ) -> Vec<u8> {
+            let secret = [secret, b"pepper"].concat();
+            HashAlgorithm::Sha3.compute(parts, &secret, mac_bits, wide)
+        }
+    }
+
+    #[test]
+    fn test_builtins_are_registered() {
+        assert!(register_codec("zstd", Arc::new(Marked)).is_err());
+        assert!(register_aont("rivest", Arc::new(Aont::Oaep)).is_err());
+        let mut header = VhcHeader::default();
+        header.stages.compression = Some("lz4".into());
+        header.stages.mac = Some("blake3".into());
+        assert!(codec_for(&header).is_ok());
+        assert!(mac_for(&header).is_ok());
+        assert_eq!(mac_for(&header).unwrap().max_mac_bits(WideTag::Native), 512);
+        header.stages.aont = Some("missing".into());
+        assert!(matches!(
+            aont_for(&header),
+            Err(HypercubeError::UnsupportedAlgorithm(_))
+        ));
+    }
+
+    #[test]
+    fn test_registered_stages_roundtrip() {
+        register_codec("test-marked", Arc::new(Marked)).unwrap();
+        register_whitener("test-invert", Arc::new(Invert)).unwrap();
+        register_mac("test-peppered", Arc::new(Peppered)).unwrap();
+        assert!(register_whitener("test-invert", Arc::new(Invert)).is_err());
+
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.stages = crate::header::StageIds {
+            compression: Some("test-marked".into()),
+            whitener: Some("test-invert".into()),
+            aont: Some("oaep".into()),
+            mac: Some("test-peppered".into()),
+        };
+        assert_eq!(header.data_blocks_per_partition(), 32);
+        let header = VhcHeader::from_bytes(&header.to_bytes().unwrap()).unwrap();
+        let blocks = create_partition(b"registered stages", b"secret", &header, Some(4))
+            .unwrap()
+            .blocks;
+        assert_eq!(
+            extract_partition(&blocks, b"secret", &header).unwrap(),
+            b"registered stages"
+        );
+
+        // The built-in MAC does not accept blocks tagged by the registered one
+        let mut builtin = header.clone();
+        builtin.stages.mac = None;
+        assert!(extract_partition(&blocks, b"secret", &builtin).is_err());
+        let mut unknown = header;
+        unknown.stages.whitener = Some("test-unregistered".into());
+        assert!(matches!(
+            extract_partition(&blocks, b"secret", &unknown),
+            Err(HypercubeError::UnsupportedAlgorithm(_))
+        ));
+    }
+
+    #[test]
+    fn test_pipeline_orders_roundtrip() {
+        use crate::header::{PipelineOrder, Shuffle};
+        register_whitener("test-invert-ordered", Arc::new(Invert)).unwrap();
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.stages.whitener = Some("test-invert-ordered".into());
+        header.shuffle = Shuffle::Fragment;
+
+        let orders = [
+            "whiten,aont,shuffle",
+            "aont,whiten,shuffle",
+            "shuffle,whiten,aont",
+            "aont,shuffle,whiten",
+        ];
+        for transforms in orders {
+            let order = format!("compress,segment,fragment,{},sequence,mac", transforms);
+            header.pipeline_order = order.parse().unwrap();
+            let header = VhcHeader::from_bytes(&header.to_bytes().unwrap()).unwrap();
+            let blocks = create_partition(b"reordered", b"secret", &header, Some(4))
+                .unwrap()
+                .blocks;
+            assert_eq!(extract_partition(&blocks, b"secret", &header).unwrap(), b"reordered");
+
+            // Opening in another order does not give the data back
+            let mut standard = header.clone();
+            standard.pipeline_order = PipelineOrder::default();
+            if !header.pipeline_order.is_standard() {
+                let opened = extract_partition(&blocks, b"secret", &standard);
+                assert!(opened.map_or(true, |data| data != b"reordered"), "{}", order);
+            }
+        }
+    }
+}
@@ -53,7 +53,7 @@ pub fn block_count(data_size: usize, block_size: usize) -> usize {
     if data_size == 0 {
         1
     } else {
-        (data_size + block_size - 1) / block_size
+        data_size.div_ceil(block_size)
     }
 }
 
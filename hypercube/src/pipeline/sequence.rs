@@ -1,6 +1,13 @@
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+
 /// Sequence number size in bytes (128 bits = 16 bytes)
 pub const SEQUENCE_SIZE: usize = 16;
 
+/// Size of the per-write nonce a derived sequence base is stretched from
+pub const SEQUENCE_NONCE_SIZE: usize = 16;
+
 /// A 128-bit sequence number
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SequenceNumber([u8; SEQUENCE_SIZE]);
@@ -131,6 +138,96 @@ pub fn generate_sequence_base() -> u128 {
     rand::thread_rng().gen()
 }
 
+/// Bits of a tagged base below the nonce: the per-block counter
+const COUNTER_BITS: u32 = 32;
+
+/// Bits of the random nonce that the tag mask is derived from
+const NONCE_BITS: u32 = 48;
+
+/// Random sequence base carrying `generation`, readable only with the partition key
+/// As a u128: a counter start in bits 0-31, low enough that `blocks` blocks never carry
+/// out of them; a random nonce in bits 32-79; then the generation and 16 zero bits, masked
+/// by a keyed hash of the nonce. Without the key every bit looks random
+pub fn tagged_sequence_base(key: &[u8], context: &str, generation: u32, blocks: usize) -> u128 {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let blocks = u32::try_from(blocks).unwrap_or(u32::MAX);
+    let start = u128::from(rng.gen_range(0..=u32::MAX - blocks));
+    let nonce = u128::from(rng.gen::<u64>()) & ((1 << NONCE_BITS) - 1);
+    pack_base(key, context, generation, start, nonce)
+}
+
+/// Tagged sequence base whose counter start and nonce come from HKDF-SHA256 over the
+/// partition key, salted with a per-write nonce, instead of from the thread RNG
+/// Only the nonce has to be fresh: without the key the base is unpredictable even if a
+/// weakly seeded generator repeats it, and the nonce's holder can re-derive the base
+pub fn derived_sequence_base(
+    key: &[u8],
+    context: &str,
+    nonce: &[u8; SEQUENCE_NONCE_SIZE],
+    generation: u32,
+    blocks: usize,
+) -> u128 {
+    let mut info = Vec::with_capacity(context.len() + 17);
+    info.extend_from_slice(context.as_bytes());
+    info.extend_from_slice(b" base");
+    info.extend_from_slice(&generation.to_le_bytes());
+    info.extend_from_slice(&(blocks as u64).to_le_bytes());
+    let okm = hkdf_sha256(nonce, key, &info);
+
+    let blocks = u32::try_from(blocks).unwrap_or(u32::MAX);
+    let seed = u64::from_le_bytes(okm[..8].try_into().unwrap());
+    let start = u128::from(seed % (u64::from(u32::MAX - blocks) + 1));
+    let nonce = u128::from(u64::from_le_bytes(okm[8..16].try_into().unwrap()));
+    pack_base(key, context, generation, start, nonce & ((1 << NONCE_BITS) - 1))
+}
+
+/// A base lowered to a multiple of `blocks` rounded up to a power of two, so each
+/// block's index in the partition is the low bits of its sequence even when others are lost
+/// The counter start only moves down, so the run still fits below the nonce
+pub fn align_sequence_base(base: u128, blocks: usize) -> u128 {
+    base & !(blocks.next_power_of_two() as u128 - 1)
+}
+
+/// A nonce for `derived_sequence_base`, read from the OS rather than a userspace generator
+pub fn generate_sequence_nonce() -> [u8; SEQUENCE_NONCE_SIZE] {
+    let mut nonce = [0u8; SEQUENCE_NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+fn pack_base(key: &[u8], context: &str, generation: u32, start: u128, nonce: u128) -> u128 {
+    let tag = u128::from(generation) ^ tag_mask(key, context, nonce);
+    start | nonce << COUNTER_BITS | tag << (COUNTER_BITS + NONCE_BITS)
+}
+
+/// HKDF-SHA256 (RFC 5869), first 32 bytes of output
+fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut extract = Hmac::<Sha256>::new_from_slice(salt).expect("HMAC accepts any key size");
+    extract.update(ikm);
+    let prk = extract.finalize().into_bytes();
+    let mut expand = Hmac::<Sha256>::new_from_slice(&prk).expect("HMAC accepts any key size");
+    expand.update(info);
+    expand.update(&[1]);
+    expand.finalize().into_bytes().into()
+}
+
+/// Generation carried by a sequence of a `tagged_sequence_base` under the same key
+/// None for untagged sequences, except with probability 2^-16
+pub fn sequence_generation(key: &[u8], context: &str, sequence: u128) -> Option<u32> {
+    let nonce = (sequence >> COUNTER_BITS) & ((1 << NONCE_BITS) - 1);
+    let tag = (sequence >> (COUNTER_BITS + NONCE_BITS)) ^ tag_mask(key, context, nonce);
+    u32::try_from(tag).ok()
+}
+
+/// 48-bit mask for the tag of a nonce
+fn tag_mask(key: &[u8], context: &str, nonce: u128) -> u128 {
+    let hash = blake3::keyed_hash(&blake3::derive_key(context, key), &nonce.to_le_bytes());
+    let mut mask = [0u8; 16];
+    mask[..6].copy_from_slice(&hash.as_bytes()[..6]);
+    u128::from_le_bytes(mask)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,6 +242,47 @@ mod tests {
         assert_eq!(seq.to_u128(), 1);
     }
 
+    #[test]
+    fn test_derived_sequence_base() {
+        // RFC 5869 test case 1
+        let okm = hkdf_sha256(
+            &hex::decode("000102030405060708090a0b0c").unwrap(),
+            &[0x0b; 22],
+            &hex::decode("f0f1f2f3f4f5f6f7f8f9").unwrap(),
+        );
+        assert_eq!(
+            hex::encode(okm),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf"
+        );
+
+        let context = "test sequence tag";
+        let nonce = generate_sequence_nonce();
+        let base = derived_sequence_base(b"key", context, &nonce, 7, 64);
+        assert_eq!(derived_sequence_base(b"key", context, &nonce, 7, 64), base);
+        assert!((base & u128::from(u32::MAX)) + 64 <= u128::from(u32::MAX));
+        assert_eq!(sequence_generation(b"key", context, base + 63), Some(7));
+        assert_ne!(derived_sequence_base(b"other key", context, &nonce, 7, 64), base);
+        assert_ne!(derived_sequence_base(b"key", context, &[0; 16], 7, 64), base);
+        assert_ne!(derived_sequence_base(b"key", context, &nonce, 8, 64), base);
+    }
+
+    #[test]
+    fn test_tagged_sequence_base() {
+        let context = "test sequence tag";
+        let base = tagged_sequence_base(b"key", context, 42, 64);
+        assert!((base & u128::from(u32::MAX)) + 64 <= u128::from(u32::MAX));
+        // Every block of the run carries the tag
+        for i in 0..64 {
+            assert_eq!(sequence_generation(b"key", context, base + i), Some(42));
+        }
+        assert_ne!(sequence_generation(b"other key", context, base), Some(42));
+        assert_ne!(tagged_sequence_base(b"key", context, 42, 64), base);
+        let untagged = (0..64)
+            .filter(|_| sequence_generation(b"key", context, generate_sequence_base()).is_some())
+            .count();
+        assert_eq!(untagged, 0);
+    }
+
     #[test]
     fn test_sequence_number_overflow() {
         let mut seq = SequenceNumber::new(u128::MAX);
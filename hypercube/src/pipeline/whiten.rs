@@ -0,0 +1,13 @@
+use crate::domains::Domains;
+use crate::pipeline::Whitener;
+
+/// The built-in whitener, registered as `none`: fragments pass through unchanged
+/// Compression and the AONT already leave no plaintext structure in the blocks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoWhitening;
+
+impl Whitener for NoWhitening {
+    fn whiten(&self, _fragments: &mut [Vec<u8>], _domains: &Domains) {}
+
+    fn unwhiten(&self, _fragments: &mut [Vec<u8>], _domains: &Domains) {}
+}
@@ -0,0 +1,231 @@
+//! Progress callbacks for long container operations.
+//!
+//! `add_partition` and `extract_from_vhc` report to the sink in their options, and
+//! `seal_file_with_progress` to the one it is given: input bytes encoded, blocks written
+//! and blocks scanned against a secret. Every call carries the running count and the
+//! expected total, so a sink needs no state of its own to draw a bar. `TerminalProgress`
+//! is the bar the CLI shows for large containers.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Width of `TerminalProgress`'s bar, in characters
+const BAR_WIDTH: usize = 30;
+
+/// Receives progress from container operations; every method defaults to doing nothing
+/// Calls come from the thread running the operation, between units of work
+pub trait ProgressSink: Send + Sync {
+    /// `done` of `total` input bytes have been encoded, or output bytes decoded
+    fn bytes_processed(&self, done: u64, total: u64) {
+        let _ = (done, total);
+    }
+
+    /// `done` of `total` new blocks are ready to be written to the container
+    fn blocks_written(&self, done: u64, total: u64) {
+        let _ = (done, total);
+    }
+
+    /// `done` of `total` stored blocks have had their MAC checked against the secret
+    fn blocks_scanned(&self, done: u64, total: u64) {
+        let _ = (done, total);
+    }
+}
+
+/// An optional sink, as held by the options structs; reports nowhere by default
+#[derive(Clone, Default)]
+pub struct Progress(Option<Arc<dyn ProgressSink>>);
+
+impl Progress {
+    pub fn new(sink: impl ProgressSink + 'static) -> Self {
+        Self(Some(Arc::new(sink)))
+    }
+
+    /// Whether a sink is attached
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Progress(sink)"),
+            None => f.write_str("Progress(none)"),
+        }
+    }
+}
+
+impl ProgressSink for Progress {
+    fn bytes_processed(&self, done: u64, total: u64) {
+        if let Some(sink) = &self.0 {
+            sink.bytes_processed(done, total);
+        }
+    }
+
+    fn blocks_written(&self, done: u64, total: u64) {
+        if let Some(sink) = &self.0 {
+            sink.blocks_written(done, total);
+        }
+    }
+
+    fn blocks_scanned(&self, done: u64, total: u64) {
+        if let Some(sink) = &self.0 {
+            sink.blocks_scanned(done, total);
+        }
+    }
+}
+
+/// Progress bar on stderr, redrawn in place whenever another whole percent is done
+#[derive(Debug, Default)]
+pub struct TerminalProgress {
+    /// Label and percentage last drawn
+    last: Mutex<Option<(&'static str, u64)>>,
+}
+
+impl TerminalProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn draw(&self, label: &'static str, unit: &str, done: u64, total: u64) {
+        let percent = done
+            .saturating_mul(100)
+            .checked_div(total)
+            .unwrap_or(100)
+            .min(100);
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if *last == Some((label, percent)) {
+            return;
+        }
+        // A new label starts a new line, unless the last bar already ended its own
+        let mut stderr = std::io::stderr().lock();
+        if last.is_some_and(|(previous, percent)| previous != label && percent < 100) {
+            let _ = writeln!(stderr);
+        }
+        let _ = write!(
+            stderr,
+            "\r{:<8} {} {}/{} {}",
+            label,
+            render_bar(done, total),
+            done,
+            total,
+            unit
+        );
+        if percent == 100 {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+        *last = Some((label, percent));
+    }
+}
+
+impl ProgressSink for TerminalProgress {
+    fn bytes_processed(&self, done: u64, total: u64) {
+        self.draw("Encoding", "bytes", done, total);
+    }
+
+    fn blocks_written(&self, done: u64, total: u64) {
+        self.draw("Writing", "blocks", done, total);
+    }
+
+    fn blocks_scanned(&self, done: u64, total: u64) {
+        self.draw("Scanning", "blocks", done, total);
+    }
+}
+
+/// `[#####-----]  50%`; an empty total counts as done
+pub fn render_bar(done: u64, total: u64) -> String {
+    let fraction = match total {
+        0 => 1.0,
+        _ => (done as f64 / total as f64).min(1.0),
+    };
+    let filled = (fraction * BAR_WIDTH as f64) as usize;
+    format!(
+        "[{}{}] {:>3}%",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        (fraction * 100.0) as u64
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::add::{add_partition, AddOptions};
+    use crate::cli::extract::{extract_from_vhc, ExtractOptions};
+    use crate::cli::seal::seal_file_with_progress;
+    use tempfile::tempdir;
+
+    /// Keeps every report, tagged by kind
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<(&'static str, u64, u64)>>);
+
+    impl ProgressSink for Arc<Recorded> {
+        fn bytes_processed(&self, done: u64, total: u64) {
+            self.0.lock().unwrap().push(("bytes", done, total));
+        }
+
+        fn blocks_written(&self, done: u64, total: u64) {
+            self.0.lock().unwrap().push(("written", done, total));
+        }
+
+        fn blocks_scanned(&self, done: u64, total: u64) {
+            self.0.lock().unwrap().push(("scanned", done, total));
+        }
+    }
+
+    impl Recorded {
+        fn take(&self) -> Vec<(&'static str, u64, u64)> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_progress_reports() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.txt");
+        let vhc = dir.path().join("cube.vhc");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, b"progress").unwrap();
+        let recorded = Arc::new(Recorded::default());
+
+        let add = AddOptions {
+            secret: "secret".into(),
+            dimension: 8,
+            kdf: None,
+            progress: Progress::new(recorded.clone()),
+            ..Default::default()
+        };
+        add_partition(&input, &vhc, &add).unwrap();
+        assert_eq!(recorded.take(), [("bytes", 8, 8), ("written", 8, 8)]);
+
+        seal_file_with_progress(&vhc, &Progress::new(recorded.clone())).unwrap();
+        let sealed = recorded.take();
+        assert_eq!(sealed.len(), 7);
+        assert_eq!(sealed.last(), Some(&("written", 56, 56)));
+
+        let extract = ExtractOptions {
+            secret: "secret".into(),
+            progress: Progress::new(recorded.clone()),
+            ..Default::default()
+        };
+        extract_from_vhc(&vhc, &output, &extract).unwrap();
+        assert_eq!(recorded.take(), [("scanned", 64, 64), ("bytes", 8, 8)]);
+        assert_eq!(std::fs::read(&output).unwrap(), b"progress");
+
+        // Without a sink nothing is reported, and the options still print
+        assert!(!Progress::default().is_enabled());
+        assert_eq!(format!("{:?}", add.progress), "Progress(sink)");
+    }
+
+    #[test]
+    fn test_render_bar() {
+        assert_eq!(render_bar(0, 10), format!("[{}]   0%", "-".repeat(30)));
+        assert_eq!(
+            render_bar(5, 10),
+            format!("[{}{}]  50%", "#".repeat(15), "-".repeat(15))
+        );
+        assert_eq!(render_bar(12, 10), format!("[{}] 100%", "#".repeat(30)));
+        assert_eq!(render_bar(0, 0), render_bar(1, 1));
+    }
+}
@@ -0,0 +1,215 @@
+//! Runtime helpers for long-running services that embed the crate.
+//!
+//! `SecureCache` keeps parsed headers and block indices encrypted in memory under an
+//! ephemeral key drawn once per process, and decrypts an entry only while it is read.
+//! A heap dump or a swapped-out page then shows ciphertext instead of a partition's
+//! block positions. The key itself lives in the same process, so this narrows exposure
+//! rather than protecting against an attacker who can read all of its memory.
+
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::header::VhcHeader;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{rngs::OsRng, RngCore};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Mutex, OnceLock};
+use zeroize::Zeroizing;
+
+/// ChaCha20-Poly1305 nonce size in bytes
+const NONCE_SIZE: usize = 12;
+
+/// Ephemeral key for every cache in this process, never written anywhere
+static PROCESS_KEY: OnceLock<[u8; 32]> = OnceLock::new();
+
+fn process_cipher() -> ChaCha20Poly1305 {
+    let key = PROCESS_KEY.get_or_init(|| {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        key
+    });
+    ChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+/// A value a `SecureCache` can hold, encoded to bytes for encryption
+pub trait CacheValue: Sized {
+    fn to_cache_bytes(&self) -> Result<Vec<u8>>;
+    fn from_cache_bytes(data: &[u8]) -> Result<Self>;
+}
+
+impl CacheValue for VhcHeader {
+    fn to_cache_bytes(&self) -> Result<Vec<u8>> {
+        self.to_bytes()
+    }
+
+    fn from_cache_bytes(data: &[u8]) -> Result<Self> {
+        Self::from_bytes(data)
+    }
+}
+
+/// Block indices of a partition, as kept by the index sidecar
+impl CacheValue for Vec<usize> {
+    fn to_cache_bytes(&self) -> Result<Vec<u8>> {
+        Ok(self
+            .iter()
+            .flat_map(|&index| (index as u64).to_le_bytes())
+            .collect())
+    }
+
+    fn from_cache_bytes(data: &[u8]) -> Result<Self> {
+        if !data.len().is_multiple_of(8) {
+            return Err(HypercubeError::InvalidFormat(
+                "Cached block indices are truncated".into(),
+            ));
+        }
+        data.chunks_exact(8)
+            .map(|chunk| {
+                usize::try_from(u64::from_le_bytes(chunk.try_into().unwrap())).map_err(|_| {
+                    HypercubeError::InvalidFormat("Cached block index out of range".into())
+                })
+            })
+            .collect()
+    }
+}
+
+/// One encrypted entry: [nonce: 12][ciphertext...]
+struct Sealed {
+    nonce: [u8; NONCE_SIZE],
+    ciphertext: Vec<u8>,
+}
+
+/// Thread-safe map whose values stay encrypted under the process key
+/// Keys are kept in the clear, so they should not be secrets themselves
+pub struct SecureCache<K, V> {
+    entries: Mutex<HashMap<K, Sealed>>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<K: Eq + Hash, V: CacheValue> Default for SecureCache<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<K: Eq + Hash, V: CacheValue> SecureCache<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encrypt `value` and store it under `key`, replacing any previous entry
+    pub fn insert(&self, key: K, value: &V) -> Result<()> {
+        let plaintext = Zeroizing::new(value.to_cache_bytes()?);
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = process_cipher()
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: domains::RUNTIME_CACHE_AAD,
+                },
+            )
+            .map_err(|_| HypercubeError::IntegrityError("Cache encryption failed".into()))?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, Sealed { nonce, ciphertext });
+        Ok(())
+    }
+
+    /// Decrypt the entry under `key`, if there is one
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        let entries = self.entries.lock().unwrap();
+        let Some(sealed) = entries.get(key) else {
+            return Ok(None);
+        };
+        // The decrypted bytes are wiped once decoded
+        let plaintext = Zeroizing::new(
+            process_cipher()
+                .decrypt(
+                    Nonce::from_slice(&sealed.nonce),
+                    Payload {
+                        msg: &sealed.ciphertext,
+                        aad: domains::RUNTIME_CACHE_AAD,
+                    },
+                )
+                .map_err(|_| HypercubeError::IntegrityError("Cache entry corrupted".into()))?,
+        );
+        V::from_cache_bytes(&plaintext).map(Some)
+    }
+
+    /// Drop the entry under `key`; true if there was one
+    pub fn remove(&self, key: &K) -> bool {
+        self.entries.lock().unwrap().remove(key).is_some()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_secure_cache_roundtrip() {
+        let headers: SecureCache<PathBuf, VhcHeader> = SecureCache::new();
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        headers.insert("a.vhc".into(), &header).unwrap();
+        let cached = headers.get(&"a.vhc".into()).unwrap().unwrap();
+        assert_eq!(cached.to_bytes().unwrap(), header.to_bytes().unwrap());
+        assert!(headers.get(&"b.vhc".into()).unwrap().is_none());
+
+        let indices: SecureCache<u32, Vec<usize>> = SecureCache::new();
+        indices.insert(7, &vec![3, 1, 4, 1, 5]).unwrap();
+        assert_eq!(indices.get(&7).unwrap(), Some(vec![3, 1, 4, 1, 5]));
+        assert_eq!(indices.len(), 1);
+        assert!(indices.remove(&7));
+        assert!(!indices.remove(&7));
+        assert!(indices.is_empty());
+    }
+
+    #[test]
+    fn test_secure_cache_holds_ciphertext() {
+        let indices: SecureCache<u8, Vec<usize>> = SecureCache::new();
+        let value = vec![0x1122_3344_5566_7788];
+        indices.insert(0, &value).unwrap();
+        let plaintext = value.to_cache_bytes().unwrap();
+        {
+            let entries = indices.entries.lock().unwrap();
+            let sealed = &entries[&0];
+            assert!(!sealed
+                .ciphertext
+                .windows(plaintext.len())
+                .any(|window| window == plaintext));
+        }
+
+        // A tampered entry is refused rather than decoded
+        indices
+            .entries
+            .lock()
+            .unwrap()
+            .get_mut(&0)
+            .unwrap()
+            .ciphertext[0] ^= 1;
+        assert!(matches!(
+            indices.get(&0),
+            Err(HypercubeError::IntegrityError(_))
+        ));
+    }
+}
@@ -0,0 +1,249 @@
+//! Secrets from an external helper program, in the style of git credential helpers.
+//!
+//! A helper runs as `<helper> get`, `<helper> store` or `<helper> erase`, with `key=value`
+//! lines on stdin ending at a blank line or EOF. For `get` it answers in the same format,
+//! with a `secret=` line if it has a secret for the request. `store` follows a secret that
+//! worked and `erase` one that authenticated nothing, so a helper may cache secrets. Their
+//! output is ignored. Unknown keys are ignored in both directions. Requests name the
+//! container by absolute path and, once it exists, by its `container_id`.
+//!
+//! As with git, `!cmd args` is a shell snippet, an absolute path runs that program, and
+//! any other name runs `hypercube-secret-<name>` from `PATH`. Arguments after the name are
+//! kept, and the action is appended.
+
+use crate::error::{HypercubeError, Result};
+use crate::header::ContainerId;
+use crate::vhc::read_container_id;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Prefix of helper programs named without a path
+pub const HELPER_PREFIX: &str = "hypercube-secret-";
+
+/// What a secret is wanted for, as sent to the helper
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretRequest {
+    /// Container the secret opens
+    pub container: PathBuf,
+    /// The container's id, when it exists and has one, so a helper can key secrets by
+    /// container rather than by path
+    pub container_id: Option<ContainerId>,
+    /// Command asking for it: `add`, `extract`, `cat`, `ls`, `update` or `wipe`
+    pub operation: String,
+}
+
+impl SecretRequest {
+    pub fn new(container: &Path, operation: &str) -> Self {
+        Self {
+            // Absolute, so a helper caching by container sees the same key from any directory
+            container: std::path::absolute(container).unwrap_or_else(|_| container.into()),
+            container_id: read_container_id(container),
+            operation: operation.into(),
+        }
+    }
+
+    fn to_input(&self, secret: Option<&str>) -> Result<String> {
+        let container = self.container.to_string_lossy();
+        let id = self.container_id.map(|id| id.to_string());
+        let mut input = String::from("protocol=hypercube\n");
+        let fields = [("container", &*container)]
+            .into_iter()
+            .chain(id.as_deref().map(|id| ("container_id", id)))
+            .chain([("operation", &*self.operation)])
+            .chain(secret.map(|secret| ("secret", secret)));
+        for (key, value) in fields {
+            // The protocol is line-based, so a value cannot span lines
+            if value.contains(['\n', '\0']) {
+                return Err(HypercubeError::InvalidFormat(format!(
+                    "Secret helper {} contains a newline or NUL",
+                    key
+                )));
+            }
+            input.push_str(&format!("{}={}\n", key, value));
+        }
+        input.push('\n');
+        Ok(input)
+    }
+}
+
+/// An external secret helper
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretHelper {
+    command: String,
+}
+
+impl SecretHelper {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+        }
+    }
+
+    /// The helper's secret for the request, if it has one
+    pub fn get(&self, request: &SecretRequest) -> Result<Option<String>> {
+        let output = self.run("get", &request.to_input(None)?)?;
+        Ok(output
+            .lines()
+            .take_while(|line| !line.is_empty())
+            .find_map(|line| line.strip_prefix("secret="))
+            .map(str::to_string))
+    }
+
+    /// Tell the helper a secret worked for the request
+    pub fn store(&self, request: &SecretRequest, secret: &str) -> Result<()> {
+        self.run("store", &request.to_input(Some(secret))?)
+            .map(drop)
+    }
+
+    /// Tell the helper its secret for the request authenticated nothing
+    pub fn erase(&self, request: &SecretRequest, secret: &str) -> Result<()> {
+        self.run("erase", &request.to_input(Some(secret))?)
+            .map(drop)
+    }
+
+    /// Run `operation` with the helper's secret, then `store` it on success or `erase` it
+    /// when it authenticated nothing
+    pub fn with_secret<T>(
+        &self,
+        request: &SecretRequest,
+        operation: impl FnOnce(String) -> Result<T>,
+    ) -> Result<T> {
+        let secret = self.get(request)?.ok_or(HypercubeError::SecretRequired)?;
+        // As in git, a helper that fails to cache does not fail the command
+        match operation(secret.clone()) {
+            Ok(value) => {
+                let _ = self.store(request, &secret);
+                Ok(value)
+            }
+            Err(e @ HypercubeError::NoAuthenticatedBlocks) => {
+                let _ = self.erase(request, &secret);
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Shell command line for an action
+    fn command_line(&self, action: &str) -> String {
+        let command = self.command.trim();
+        match command.strip_prefix('!') {
+            Some(snippet) => format!("{} {}", snippet, action),
+            None if Path::new(command.split_whitespace().next().unwrap_or("")).is_absolute() => {
+                format!("{} {}", command, action)
+            }
+            None => format!("{}{} {}", HELPER_PREFIX, command, action),
+        }
+    }
+
+    fn run(&self, action: &str, input: &str) -> Result<String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(self.command_line(action))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        // A helper may exit without reading its input
+        let mut stdin = child.stdin.take().unwrap();
+        match stdin.write_all(input.as_bytes()) {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => drop(stdin),
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(HypercubeError::Io(std::io::Error::other(format!(
+                "Secret helper '{}' failed on {} ({})",
+                self.command, action, output.status
+            ))));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| HypercubeError::InvalidFormat("Secret helper output is not UTF-8".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_command_line() {
+        let helper = SecretHelper::new("cache --timeout 60");
+        assert_eq!(
+            helper.command_line("get"),
+            "hypercube-secret-cache --timeout 60 get"
+        );
+        assert_eq!(
+            SecretHelper::new("/usr/bin/pass-helper -q").command_line("store"),
+            "/usr/bin/pass-helper -q store"
+        );
+        assert_eq!(
+            SecretHelper::new("!f() { echo; }; f").command_line("erase"),
+            "f() { echo; }; f erase"
+        );
+    }
+
+    #[test]
+    fn test_helper_protocol() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("log");
+        // Answers `get` with a fixed secret and logs the input of `store` and `erase`
+        let helper = SecretHelper::new(format!(
+            "!f() {{ if [ \"$1\" = get ]; then cat >/dev/null; echo secret=hunter2; \
+             else echo \"$1\" >> {0}; cat >> {0}; fi; }}; f",
+            log.display()
+        ));
+        let request = SecretRequest::new(Path::new("/tmp/vault.vhc"), "extract");
+        assert_eq!(helper.get(&request).unwrap().as_deref(), Some("hunter2"));
+
+        let value = helper
+            .with_secret(&request, |secret| {
+                assert_eq!(secret, "hunter2");
+                Ok(7)
+            })
+            .unwrap();
+        assert_eq!(value, 7);
+        // Damage the secret did authenticate keeps it cached; only a secret that opens
+        // nothing is erased
+        let result: Result<()> = helper.with_secret(&request, |_| {
+            Err(HypercubeError::IntegrityError("Content hash mismatch".into()))
+        });
+        assert!(result.is_err());
+        let result: Result<()> =
+            helper.with_secret(&request, |_| Err(HypercubeError::NoAuthenticatedBlocks));
+        assert!(result.is_err());
+        let log = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(
+            log,
+            "store\nprotocol=hypercube\ncontainer=/tmp/vault.vhc\noperation=extract\n\
+             secret=hunter2\n\nerase\nprotocol=hypercube\ncontainer=/tmp/vault.vhc\n\
+             operation=extract\nsecret=hunter2\n\n"
+        );
+
+        // No secret, and a failing helper
+        let empty = SecretHelper::new("!true");
+        assert_eq!(empty.get(&request).unwrap(), None);
+        assert!(matches!(
+            empty.with_secret(&request, |_| Ok(())),
+            Err(HypercubeError::SecretRequired)
+        ));
+        assert!(SecretHelper::new("!false").get(&request).is_err());
+
+        let bad = SecretRequest::new(Path::new("/tmp/a\nb"), "add");
+        assert!(helper.get(&bad).is_err());
+    }
+
+    #[test]
+    fn test_request_names_container_id() {
+        let id = ContainerId([0x11; 16]);
+        let request = SecretRequest {
+            container_id: Some(id),
+            ..SecretRequest::new(Path::new("/tmp/vault.vhc"), "cat")
+        };
+        assert_eq!(
+            request.to_input(None).unwrap(),
+            "protocol=hypercube\ncontainer=/tmp/vault.vhc\n\
+             container_id=11111111-1111-1111-1111-111111111111\noperation=cat\n\n"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Ed25519 container signatures.
+//! The signed message is a domain tag followed by a BLAKE3 digest of everything
+//! before the trailer (layout prefix, header and block region). Key and signature
+//! files are hex text so they can be pasted between machines.
+
+use crate::domains;
+use crate::error::{HypercubeError, Result};
+use crate::file_mode::{create_file, create_with_mode, PRIVATE_FILE_MODE};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Public key followed by the signature
+pub const SIGNATURE_RECORD_SIZE: usize = 32 + 64;
+
+/// A signature together with the key that made it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerSignature {
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+impl ContainerSignature {
+    /// Sign the digest of a container's signed region
+    pub fn sign(key: &SigningKey, digest: &[u8; 32]) -> Self {
+        Self {
+            public_key: key.verifying_key().to_bytes(),
+            signature: key.sign(&signed_message(digest)).to_bytes(),
+        }
+    }
+
+    /// Check the signature against a digest and the expected signer
+    pub fn verify(&self, digest: &[u8; 32], signer: &VerifyingKey) -> Result<()> {
+        if self.public_key != signer.to_bytes() {
+            return Err(HypercubeError::IntegrityError(format!(
+                "Signed by a different key ({})",
+                key_fingerprint(&self.public_key)
+            )));
+        }
+        signer
+            .verify(
+                &signed_message(digest),
+                &Signature::from_bytes(&self.signature),
+            )
+            .map_err(|_| {
+                HypercubeError::IntegrityError("Signature does not match container".into())
+            })
+    }
+
+    pub fn to_bytes(&self) -> [u8; SIGNATURE_RECORD_SIZE] {
+        let mut bytes = [0u8; SIGNATURE_RECORD_SIZE];
+        bytes[..32].copy_from_slice(&self.public_key);
+        bytes[32..].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() != SIGNATURE_RECORD_SIZE {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "Signature record must be {} bytes, got {}",
+                SIGNATURE_RECORD_SIZE,
+                data.len()
+            )));
+        }
+        Ok(Self {
+            public_key: data[..32].try_into().unwrap(),
+            signature: data[32..].try_into().unwrap(),
+        })
+    }
+}
+
+fn signed_message(digest: &[u8; 32]) -> Vec<u8> {
+    [domains::SIGNATURE, digest.as_slice()].concat()
+}
+
+/// Short identifier for a public key
+pub fn key_fingerprint(public_key: &[u8; 32]) -> String {
+    hex::encode(&blake3::hash(public_key).as_bytes()[..8])
+}
+
+/// Generate a signing key and write it to `path`, with the public key in `<path>.pub`
+/// Returns the public key path
+pub fn generate_signing_key(path: &Path) -> Result<PathBuf> {
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    let key = SigningKey::from_bytes(&seed);
+
+    let public_path = public_key_path(path);
+    // Owner-only whatever mode is set; an existing key file is narrowed too
+    write_hex(create_with_mode(path, PRIVATE_FILE_MODE)?, &seed)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(PRIVATE_FILE_MODE))?;
+    }
+    write_hex(create_file(&public_path)?, key.verifying_key().as_bytes())?;
+    Ok(public_path)
+}
+
+/// Where `generate_signing_key` puts the public half
+pub fn public_key_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".pub");
+    PathBuf::from(name)
+}
+
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_hex(path)?))
+}
+
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&read_hex(path)?).map_err(|_| {
+        HypercubeError::InvalidFormat(format!("Invalid public key: {}", path.display()))
+    })
+}
+
+/// Write a detached signature file
+pub fn write_signature(path: &Path, signature: &ContainerSignature) -> Result<()> {
+    write_hex(create_file(path)?, &signature.to_bytes())
+}
+
+/// Read a detached signature file
+pub fn read_signature(path: &Path) -> Result<ContainerSignature> {
+    ContainerSignature::from_bytes(&read_hex::<SIGNATURE_RECORD_SIZE>(path)?)
+}
+
+fn write_hex(mut file: File, bytes: &[u8]) -> Result<()> {
+    file.write_all(format!("{}\n", hex::encode(bytes)).as_bytes())?;
+    Ok(())
+}
+
+fn read_hex<const N: usize>(path: &Path) -> Result<[u8; N]> {
+    let text = std::fs::read_to_string(path)?;
+    hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            HypercubeError::InvalidFormat(format!(
+                "Expected {} hex-encoded bytes in {}",
+                N,
+                path.display()
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let dir = tempdir().unwrap();
+        let key_path = dir.path().join("signer");
+        let public_path = generate_signing_key(&key_path).unwrap();
+        let key = read_signing_key(&key_path).unwrap();
+        let public = read_verifying_key(&public_path).unwrap();
+
+        let digest = *blake3::hash(b"container").as_bytes();
+        let signature = ContainerSignature::sign(&key, &digest);
+        signature.verify(&digest, &public).unwrap();
+
+        let sig_path = dir.path().join("vault.vhc.sig");
+        write_signature(&sig_path, &signature).unwrap();
+        assert_eq!(read_signature(&sig_path).unwrap(), signature);
+
+        let other = *blake3::hash(b"tampered").as_bytes();
+        assert!(signature.verify(&other, &public).is_err());
+
+        let stranger = SigningKey::from_bytes(&[7u8; 32]).verifying_key();
+        let err = signature.verify(&digest, &stranger).unwrap_err();
+        assert!(err.to_string().contains("different key"));
+    }
+}
@@ -0,0 +1,226 @@
+//! Shamir secret sharing of partition secrets over GF(256).
+//!
+//! `split_secret` turns a secret into N shares, any K of which rebuild it with
+//! `combine_shares`; fewer than K say nothing about it. Each byte of the secret is the
+//! constant term of its own random polynomial of degree K-1, and share `x` holds the
+//! polynomials evaluated at `x`, in the field of `gf256`.
+//!
+//! Shares are written as `hcs1-<set>-<k>-<x>-<hex>`. The set id is random per split, so
+//! shares from different splits are refused instead of combining into garbage. Nothing
+//! in a share checks the secret itself: K shares of the right set always combine, and a
+//! share whose data was altered yields a wrong secret that fails to open the partition.
+
+use crate::error::{HypercubeError, Result};
+use crate::gf256::{inv, mul};
+use rand::{rngs::OsRng, RngCore};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// Prefix and version of the share text format
+pub const SHARE_PREFIX: &str = "hcs1";
+
+/// One custodian's share of a secret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    /// Random id shared by every share of one split
+    pub set: [u8; 4],
+    /// Shares needed to rebuild the secret
+    pub threshold: u8,
+    /// Evaluation point, 1 to 255
+    pub x: u8,
+    /// One field element per secret byte
+    pub data: Zeroizing<Vec<u8>>,
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            SHARE_PREFIX,
+            hex::encode(self.set),
+            self.threshold,
+            self.x,
+            hex::encode(&*self.data)
+        )
+    }
+}
+
+impl FromStr for Share {
+    type Err = HypercubeError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = || HypercubeError::InvalidFormat(format!("Not a secret share: {}", text));
+        let fields: Vec<&str> = text.trim().split('-').collect();
+        let [prefix, set, threshold, x, data] = fields[..] else {
+            return Err(invalid());
+        };
+        let mut set_bytes = [0u8; 4];
+        hex::decode_to_slice(set, &mut set_bytes).map_err(|_| invalid())?;
+        let share = Self {
+            set: set_bytes,
+            threshold: threshold.parse().map_err(|_| invalid())?,
+            x: x.parse().map_err(|_| invalid())?,
+            data: Zeroizing::new(hex::decode(data).map_err(|_| invalid())?),
+        };
+        if prefix != SHARE_PREFIX || share.threshold < 2 || share.x == 0 || share.data.is_empty() {
+            return Err(invalid());
+        }
+        Ok(share)
+    }
+}
+
+/// Split `secret` into `shares` shares, any `threshold` of which rebuild it
+pub fn split_secret(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>> {
+    if threshold < 2 || threshold > shares {
+        return Err(HypercubeError::InvalidFormat(format!(
+            "Threshold must be between 2 and the number of shares, got {} of {}",
+            threshold, shares
+        )));
+    }
+    if secret.is_empty() {
+        return Err(HypercubeError::InvalidFormat(
+            "Cannot share an empty secret".into(),
+        ));
+    }
+    let mut set = [0u8; 4];
+    OsRng.fill_bytes(&mut set);
+
+    // Row i holds the coefficients of x^(i+1) for every secret byte
+    let mut coefficients = Zeroizing::new(vec![0u8; secret.len() * (threshold as usize - 1)]);
+    OsRng.fill_bytes(&mut coefficients);
+
+    Ok((1..=shares)
+        .map(|x| {
+            let data = secret
+                .iter()
+                .enumerate()
+                .map(|(i, &constant)| {
+                    // Horner's rule from the highest coefficient down to the secret byte
+                    let row = |degree: usize| coefficients[(degree - 1) * secret.len() + i];
+                    (1..threshold as usize)
+                        .rev()
+                        .map(row)
+                        .chain([constant])
+                        .fold(0, |acc, coefficient| mul(acc, x) ^ coefficient)
+                })
+                .collect();
+            Share {
+                set,
+                threshold,
+                x,
+                data: Zeroizing::new(data),
+            }
+        })
+        .collect())
+}
+
+/// Rebuild a secret from at least `threshold` shares of the same split
+pub fn combine_shares(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>> {
+    let first = shares
+        .first()
+        .ok_or_else(|| HypercubeError::InvalidFormat("No shares given".into()))?;
+    for (i, share) in shares.iter().enumerate() {
+        if (share.set, share.threshold, share.data.len())
+            != (first.set, first.threshold, first.data.len())
+        {
+            return Err(HypercubeError::InvalidFormat(
+                "Shares come from different splits".into(),
+            ));
+        }
+        if shares[..i].iter().any(|other| other.x == share.x) {
+            return Err(HypercubeError::InvalidFormat(format!(
+                "Share {} is given twice",
+                share.x
+            )));
+        }
+    }
+    let threshold = first.threshold as usize;
+    if shares.len() < threshold {
+        return Err(HypercubeError::InvalidFormat(format!(
+            "{} shares needed, {} given",
+            threshold,
+            shares.len()
+        )));
+    }
+
+    // Lagrange interpolation at zero over the first `threshold` shares
+    let shares = &shares[..threshold];
+    let mut secret = Zeroizing::new(vec![0u8; first.data.len()]);
+    for (j, share) in shares.iter().enumerate() {
+        let (numerator, denominator) = shares
+            .iter()
+            .enumerate()
+            .filter(|&(m, _)| m != j)
+            .fold((1, 1), |(num, den), (_, other)| {
+                (mul(num, other.x), mul(den, other.x ^ share.x))
+            });
+        let basis = mul(numerator, inv(denominator));
+        for (byte, &y) in secret.iter_mut().zip(share.data.iter()) {
+            *byte ^= mul(basis, y);
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_combine() {
+        let secret = b"correct horse battery staple";
+        let shares = split_secret(secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|share| share.set == shares[0].set));
+
+        // Every 3-share subset rebuilds the secret, in any order
+        for a in 0..5 {
+            for b in 0..5 {
+                for c in 0..5 {
+                    if a != b && b != c && a != c {
+                        let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                        assert_eq!(&*combine_shares(&subset).unwrap(), secret);
+                    }
+                }
+            }
+        }
+        assert_eq!(&*combine_shares(&shares).unwrap(), secret);
+        assert!(combine_shares(&shares[..2]).is_err());
+        assert!(
+            combine_shares(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err()
+        );
+
+        // Shares of another split of the same secret do not mix
+        let other = split_secret(secret, 3, 5).unwrap();
+        assert!(combine_shares(&[shares[0].clone(), shares[1].clone(), other[2].clone()]).is_err());
+
+        for (threshold, count) in [(1, 3), (4, 3), (0, 0)] {
+            assert!(split_secret(secret, threshold, count).is_err());
+        }
+        assert!(split_secret(b"", 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_share_text() {
+        let shares = split_secret(b"pass", 2, 3).unwrap();
+        let text = shares[1].to_string();
+        assert!(text.starts_with("hcs1-"));
+        assert_eq!(text.parse::<Share>().unwrap(), shares[1]);
+        assert_eq!(format!(" {}\n", text).parse::<Share>().unwrap(), shares[1]);
+
+        for bad in [
+            "",
+            "hcs1-00112233-2-1",
+            "hcs2-00112233-2-1-abcd",
+            "hcs1-0011223-2-1-abcd",
+            "hcs1-00112233-1-1-abcd",
+            "hcs1-00112233-2-0-abcd",
+            "hcs1-00112233-2-256-abcd",
+            "hcs1-00112233-2-1-xyz",
+        ] {
+            assert!(bad.parse::<Share>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Version 2 trailer: typed sections stored after the block region.
+//! A header with `version >= 2` promises a trailer; v1 files end with the last block.
+//! From version 3 every container carries one, with no sections when there is nothing to store.
+//! Layout: [kind: u8][length: u32 LE][body] ... [trailer length: u32 LE]
+
+use crate::error::{HypercubeError, Result};
+use crate::journal::{JournalEntry, JOURNAL_ENTRY_SIZE};
+use crate::merkle::{leaf_hash, Hash, MerkleTree};
+use crate::signature::ContainerSignature;
+use crate::vhc::VhcFile;
+
+/// Header version of containers that carry a trailer
+pub const TRAILER_VERSION: u32 = 2;
+
+/// Section holding one leaf hash per block, in block order
+const SECTION_MERKLE_LEAVES: u8 = 1;
+
+/// Section holding an embedded Ed25519 signature record
+const SECTION_SIGNATURE: u8 = 2;
+
+/// Section holding the append-only journal, oldest entry first
+const SECTION_JOURNAL: u8 = 3;
+
+/// Parsed trailer sections; unknown sections are skipped on read
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VhcTrailer {
+    /// Merkle leaf hashes, one per block
+    pub merkle_leaves: Option<Vec<Hash>>,
+    /// Signature over everything before the trailer
+    pub signature: Option<ContainerSignature>,
+    /// Append-only journal; empty unless the container is append-only
+    pub journal: Vec<JournalEntry>,
+}
+
+impl VhcTrailer {
+    /// The trailer a container should carry, or None if it needs no sections
+    pub fn for_file(vhc: &VhcFile) -> Option<Self> {
+        let trailer = Self {
+            merkle_leaves: vhc
+                .header
+                .merkle
+                .then(|| vhc.blocks.iter().map(|block| leaf_hash(block)).collect()),
+            signature: vhc.signature.clone(),
+            journal: vhc.journal.clone(),
+        };
+        (trailer != Self::default()).then_some(trailer)
+    }
+
+    /// Merkle tree over the stored leaves
+    pub fn merkle_tree(&self) -> Option<MerkleTree> {
+        self.merkle_leaves.clone().map(MerkleTree::from_leaves)
+    }
+
+    /// Serialize the sections (without the trailing length)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if let Some(leaves) = &self.merkle_leaves {
+            bytes.push(SECTION_MERKLE_LEAVES);
+            bytes.extend_from_slice(&((leaves.len() * 32) as u32).to_le_bytes());
+            for leaf in leaves {
+                bytes.extend_from_slice(leaf);
+            }
+        }
+        if let Some(signature) = &self.signature {
+            let record = signature.to_bytes();
+            bytes.push(SECTION_SIGNATURE);
+            bytes.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&record);
+        }
+        if !self.journal.is_empty() {
+            let len = self.journal.len() * JOURNAL_ENTRY_SIZE;
+            bytes.push(SECTION_JOURNAL);
+            bytes.extend_from_slice(&(len as u32).to_le_bytes());
+            for entry in &self.journal {
+                bytes.extend_from_slice(&entry.to_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Parse sections written by `to_bytes`
+    pub fn from_bytes(mut data: &[u8]) -> Result<Self> {
+        let mut trailer = Self::default();
+        while !data.is_empty() {
+            if data.len() < 5 {
+                return Err(truncated());
+            }
+            let kind = data[0];
+            let len = u32::from_le_bytes(data[1..5].try_into().unwrap()) as usize;
+            let body = data.get(5..5 + len).ok_or_else(truncated)?;
+            if kind == SECTION_MERKLE_LEAVES {
+                if !len.is_multiple_of(32) {
+                    return Err(HypercubeError::InvalidFormat(
+                        "Merkle section is not a whole number of hashes".into(),
+                    ));
+                }
+                let leaves = body
+                    .chunks_exact(32)
+                    .map(|leaf| leaf.try_into().unwrap())
+                    .collect();
+                trailer.merkle_leaves = Some(leaves);
+            } else if kind == SECTION_SIGNATURE {
+                trailer.signature = Some(ContainerSignature::from_bytes(body)?);
+            } else if kind == SECTION_JOURNAL {
+                if !len.is_multiple_of(JOURNAL_ENTRY_SIZE) {
+                    return Err(HypercubeError::InvalidFormat(
+                        "Journal section is not a whole number of entries".into(),
+                    ));
+                }
+                trailer.journal = body
+                    .chunks_exact(JOURNAL_ENTRY_SIZE)
+                    .map(JournalEntry::from_bytes)
+                    .collect::<Result<_>>()?;
+            }
+            data = &data[5 + len..];
+        }
+        Ok(trailer)
+    }
+}
+
+fn truncated() -> HypercubeError {
+    HypercubeError::InvalidFormat("Truncated trailer section".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::VhcHeader;
+
+    #[test]
+    fn test_trailer_roundtrip() {
+        let header = VhcHeader {
+            merkle: true,
+            ..Default::default()
+        };
+        let blocks = vec![vec![1u8; 8], vec![2u8; 8], vec![3u8; 8]];
+        let mut vhc = VhcFile::new(header);
+        vhc.add_blocks(blocks.clone());
+        vhc.signature = Some(ContainerSignature {
+            public_key: [1; 32],
+            signature: [2; 64],
+        });
+        JournalEntry::record(&mut vhc.journal, &blocks, 0);
+        let trailer = VhcTrailer::for_file(&vhc).unwrap();
+        assert_eq!(trailer.merkle_leaves.as_ref().unwrap().len(), 3);
+        assert_eq!(trailer.journal.len(), 1);
+        assert_eq!(
+            VhcTrailer::from_bytes(&trailer.to_bytes()).unwrap(),
+            trailer
+        );
+        assert_eq!(
+            trailer.merkle_tree().unwrap().root(),
+            MerkleTree::from_blocks(&blocks).root()
+        );
+
+        assert!(VhcTrailer::for_file(&VhcFile::new(VhcHeader::default())).is_none());
+    }
+
+    #[test]
+    fn test_trailer_skips_unknown_sections() {
+        let mut bytes = vec![0xEE, 3, 0, 0, 0, 9, 9, 9];
+        bytes.extend(VhcTrailer::default().to_bytes());
+        assert_eq!(
+            VhcTrailer::from_bytes(&bytes).unwrap(),
+            VhcTrailer::default()
+        );
+        assert!(VhcTrailer::from_bytes(&[SECTION_MERKLE_LEAVES, 64, 0, 0, 0, 1]).is_err());
+        assert!(VhcTrailer::from_bytes(&[SECTION_MERKLE_LEAVES, 3, 0, 0, 0, 1, 2, 3]).is_err());
+    }
+}
@@ -1,13 +1,215 @@
+use crate::block::BlockRef;
+use crate::domains::{self, NATIVE_MAC_VERSION};
 use crate::error::{HypercubeError, Result};
-use crate::header::VhcHeader;
-use rand::{seq::SliceRandom, thread_rng};
-use std::fs::File;
+use crate::file_mode::{create_file, create_private_file};
+use crate::header::{ContainerId, ReshufflePolicy, VhcHeader};
+use crate::journal::JournalEntry;
+use crate::kdf::Kdf;
+use crate::merkle::Hash;
+use crate::metrics::{self, Stage};
+use crate::signature::ContainerSignature;
+use crate::trailer::{VhcTrailer, TRAILER_VERSION};
+use memmap2::Mmap;
+use rand::{rngs::OsRng, seq::SliceRandom, thread_rng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::fs::{File, OpenOptions};
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use zeroize::Zeroizing;
 
 /// Magic bytes for VHC file format
 const VHC_MAGIC: &[u8; 4] = b"VHC\x01";
 
+/// Environment variable that, set to `0`, makes rewrites write over files in place
+/// instead of renaming a synced copy over them (for filesystems that cannot rename)
+pub const ATOMIC_ENV: &str = "HYPERCUBE_ATOMIC";
+
+/// `set_atomic_writes` settings; unset follows `$HYPERCUBE_ATOMIC`
+const ATOMIC_UNSET: u8 = 0;
+const ATOMIC_ON: u8 = 1;
+const ATOMIC_OFF: u8 = 2;
+
+static ATOMIC_WRITES: AtomicU8 = AtomicU8::new(ATOMIC_UNSET);
+
+/// Salt and tag sizes of the keyed verifier that replaces the magic
+const VERIFIER_SALT_SIZE: usize = 16;
+const VERIFIER_TAG_SIZE: usize = 16;
+
+/// How a container identifies itself at the start of the file
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum VhcLayout {
+    /// `VHC\x01` magic - trivially recognisable
+    #[default]
+    Magic,
+    /// Random salt + keyed tag, recognisable only with the container passphrase
+    /// Layout: [salt: 16][tag: 16]; the tag is keyed by the passphrase stretched with the
+    /// KDF recorded in the header that follows (see `verifier_kdf`)
+    Keyed {
+        salt: [u8; VERIFIER_SALT_SIZE],
+        tag: [u8; VERIFIER_TAG_SIZE],
+    },
+}
+
+impl VhcLayout {
+    /// Magicless layout keyed to a container passphrase (fresh random salt) for a
+    /// container with `header`
+    pub fn keyed(passphrase: &str, header: &VhcHeader) -> Result<Self> {
+        let mut salt = [0u8; VERIFIER_SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let tag = verifier_tag(passphrase, &salt, header)?;
+        Ok(Self::Keyed { salt, tag })
+    }
+
+    /// Whether the passphrase matches the stored verifier of a container with `header`
+    /// The magic layout has no verifier and never matches
+    pub fn verify(&self, passphrase: &str, header: &VhcHeader) -> Result<bool> {
+        match self {
+            Self::Magic => Ok(false),
+            Self::Keyed { salt, tag } => Ok(verifier_tag(passphrase, salt, header)? == *tag),
+        }
+    }
+
+    /// Bytes occupied before the header length field
+    pub fn prefix_len(&self) -> usize {
+        match self {
+            Self::Magic => VHC_MAGIC.len(),
+            Self::Keyed { .. } => VERIFIER_SALT_SIZE + VERIFIER_TAG_SIZE,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Magic => VHC_MAGIC.to_vec(),
+            Self::Keyed { salt, tag } => [&salt[..], &tag[..]].concat(),
+        }
+    }
+
+    /// Detect the layout from the leading bytes of a file
+    /// Anything without the magic is assumed to be keyed
+    fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic == VHC_MAGIC {
+            return Ok(Self::Magic);
+        }
+
+        let mut rest = [0u8; VERIFIER_SALT_SIZE + VERIFIER_TAG_SIZE - 4];
+        reader.read_exact(&mut rest)?;
+        let mut salt = [0u8; VERIFIER_SALT_SIZE];
+        let mut tag = [0u8; VERIFIER_TAG_SIZE];
+        salt[..4].copy_from_slice(&magic);
+        salt[4..].copy_from_slice(&rest[..VERIFIER_SALT_SIZE - 4]);
+        tag.copy_from_slice(&rest[VERIFIER_SALT_SIZE - 4..]);
+        Ok(Self::Keyed { salt, tag })
+    }
+}
+
+/// KDF stretching the passphrase of a keyed verifier: the one the header records, whose
+/// parameters are stored beside the verifier, else Argon2id at its default cost
+pub fn verifier_kdf(header: &VhcHeader) -> Kdf {
+    header.kdf.map_or_else(Kdf::argon2id, |params| params.kdf)
+}
+
+fn verifier_tag(
+    passphrase: &str,
+    salt: &[u8],
+    header: &VhcHeader,
+) -> Result<[u8; VERIFIER_TAG_SIZE]> {
+    let stretched = Zeroizing::new(verifier_kdf(header).derive(passphrase.as_bytes(), salt)?);
+    let key = Zeroizing::new(blake3::derive_key(domains::CONTAINER_VERIFY, &stretched[..]));
+    let hash = blake3::keyed_hash(&key, salt);
+    let mut tag = [0u8; VERIFIER_TAG_SIZE];
+    tag.copy_from_slice(&hash.as_bytes()[..VERIFIER_TAG_SIZE]);
+    Ok(tag)
+}
+
+/// Leading layout, parsed header and byte offset of the first block
+fn read_preamble(reader: &mut impl Read) -> Result<(VhcLayout, VhcHeader, u64)> {
+    let layout = VhcLayout::read_from(reader).map_err(not_a_vhc)?;
+    let keyed = matches!(layout, VhcLayout::Keyed { .. });
+
+    // Read header length (4 bytes, little-endian)
+    let mut header_len_bytes = [0u8; 4];
+    reader.read_exact(&mut header_len_bytes)?;
+    let header_len = u64::from(u32::from_le_bytes(header_len_bytes));
+
+    // Read header JSON; `take` avoids trusting the length of a non-VHC file
+    let mut header_bytes = Vec::new();
+    reader.take(header_len).read_to_end(&mut header_bytes)?;
+    let header = if header_bytes.len() as u64 == header_len {
+        VhcHeader::from_bytes(&header_bytes)
+    } else {
+        Err(HypercubeError::InvalidFormat("Truncated header".into()))
+    };
+    // Without the magic, a header that fails to parse means "not a container"
+    let header = header.map_err(|e| if keyed { not_a_vhc(e) } else { e })?;
+
+    let data_start = layout.prefix_len() as u64 + 4 + header_len;
+    Ok((layout, header, data_start))
+}
+
+/// Size of the v2 trailer including its length field (0 for v1 files)
+/// Leaves the reader positioned at the first block
+fn trailer_size(
+    reader: &mut (impl Read + Seek),
+    header: &VhcHeader,
+    data_start: u64,
+    file_len: u64,
+) -> Result<u64> {
+    if header.version < TRAILER_VERSION {
+        return Ok(0);
+    }
+    let available = file_len.saturating_sub(data_start);
+    if available < 4 {
+        return Err(HypercubeError::InvalidFormat("Missing trailer".into()));
+    }
+    reader.seek(SeekFrom::Start(file_len - 4))?;
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes)?;
+    reader.seek(SeekFrom::Start(data_start))?;
+
+    let size = u64::from(u32::from_le_bytes(len_bytes)) + 4;
+    if size > available {
+        return Err(HypercubeError::InvalidFormat(
+            "Trailer larger than data section".into(),
+        ));
+    }
+    Ok(size)
+}
+
+fn not_a_vhc(_: HypercubeError) -> HypercubeError {
+    HypercubeError::InvalidFormat("Not a VHC file (no magic or keyed header)".into())
+}
+
+fn file_len(file: &File) -> Result<u64> {
+    Ok(file.metadata()?.len())
+}
+
+/// Bytes between the first block and the trailer
+fn data_size(file_len: u64, data_start: u64, trailer: u64) -> Result<u64> {
+    file_len
+        .checked_sub(data_start)
+        .and_then(|len| len.checked_sub(trailer))
+        .ok_or_else(|| HypercubeError::InvalidFormat("Header runs past the trailer".into()))
+}
+
+/// A count or length that must fit in memory; past the address space it is refused,
+/// not truncated (only reachable on 32-bit builds)
+pub fn platform_size(value: u64) -> Result<usize> {
+    usize::try_from(value).map_err(|_| {
+        HypercubeError::InvalidFormat(format!("{} is too large for this platform", value))
+    })
+}
+
+/// Byte offset of block `index` in a file whose blocks start at `data_start`
+pub fn block_offset(data_start: u64, index: usize, header: &VhcHeader) -> Result<u64> {
+    (index as u64)
+        .checked_mul(header.total_block_size() as u64)
+        .and_then(|offset| offset.checked_add(data_start))
+        .ok_or_else(|| HypercubeError::InvalidFormat(format!("Block {} is out of range", index)))
+}
+
 /// A VHC file containing header and raw blocks
 /// Blocks are opaque - no tracking of which partition they belong to
 /// Security model: scan all blocks, authenticate each with your secret
@@ -16,6 +218,13 @@ pub struct VhcFile {
     pub header: VhcHeader,
     /// Raw block data (each block = sequence + data + MAC)
     pub blocks: Vec<Vec<u8>>,
+    /// Magic or keyed verifier at the start of the file
+    pub layout: VhcLayout,
+    /// Signature to embed in the trailer on write
+    /// Never loaded by `read_vhc_file`, so any rewrite drops a stale signature
+    pub signature: Option<ContainerSignature>,
+    /// Append-only journal, carried across rewrites
+    pub journal: Vec<JournalEntry>,
 }
 
 impl VhcFile {
@@ -24,6 +233,9 @@ impl VhcFile {
         Self {
             header,
             blocks: Vec::new(),
+            layout: VhcLayout::Magic,
+            signature: None,
+            journal: Vec::new(),
         }
     }
 
@@ -36,40 +248,241 @@ impl VhcFile {
     pub fn block_count(&self) -> usize {
         self.blocks.len()
     }
+
+    /// Load a container for inspection only
+    /// The result has no way to reach the file for writing: its mutating methods fail
+    /// with `ReadOnly`, and it derefs to the loaded `VhcFile` only immutably
+    pub fn open_read_only(path: &Path) -> Result<ReadOnlyVhc> {
+        Ok(ReadOnlyVhc {
+            vhc: read_vhc_file(path)?,
+            path: path.to_path_buf(),
+        })
+    }
 }
 
-/// Read a VHC file from disk
-pub fn read_vhc_file(path: &Path) -> Result<VhcFile> {
-    let file = File::open(path)?;
-    let file_len = file.metadata()?.len() as usize;
-    let mut reader = BufReader::new(file);
+/// A container opened with `VhcFile::open_read_only`, for audit tooling that must not
+/// alter what it inspects
+/// Every read goes through a fresh read-only file handle
+#[derive(Debug)]
+pub struct ReadOnlyVhc {
+    vhc: VhcFile,
+    path: PathBuf,
+}
 
-    // Read and verify magic
-    let mut magic = [0u8; 4];
-    reader.read_exact(&mut magic)?;
-    if &magic != VHC_MAGIC {
-        return Err(HypercubeError::InvalidFormat(
-            "Invalid VHC magic bytes".into(),
-        ));
+impl ReadOnlyVhc {
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    // Read header length (4 bytes, little-endian)
-    let mut header_len_bytes = [0u8; 4];
-    reader.read_exact(&mut header_len_bytes)?;
-    let header_len = u32::from_le_bytes(header_len_bytes) as usize;
+    /// The trailer as it is on disk, or None for a v1 file
+    pub fn trailer(&self) -> Result<Option<VhcTrailer>> {
+        read_vhc_trailer(&self.path)
+    }
+
+    /// BLAKE3 digest of the signed region, to compare against an evidence record
+    pub fn signed_region_digest(&self) -> Result<Hash> {
+        signed_region_digest(&self.path)
+    }
+
+    /// Re-read specific blocks from disk (None if any index is out of range)
+    pub fn read_blocks_at(&self, indices: &[usize]) -> Result<Option<Vec<Vec<u8>>>> {
+        read_vhc_blocks_at(&self.path, indices)
+    }
+
+    pub fn add_blocks(&mut self, _new_blocks: Vec<Vec<u8>>) -> Result<()> {
+        Err(read_only("add blocks"))
+    }
+
+    pub fn append_blocks(&mut self, _new_blocks: &[Vec<u8>]) -> Result<Vec<usize>> {
+        Err(read_only("append blocks"))
+    }
+
+    pub fn replace_blocks(
+        &mut self,
+        _evicted: &[usize],
+        _new_blocks: &[Vec<u8>],
+    ) -> Result<Vec<usize>> {
+        Err(read_only("replace blocks"))
+    }
+
+    pub fn rewrite_header(&mut self, _header: &VhcHeader) -> Result<()> {
+        Err(read_only("rewrite the header"))
+    }
+
+    pub fn write(&mut self) -> Result<()> {
+        Err(read_only("write the container"))
+    }
+}
+
+impl std::ops::Deref for ReadOnlyVhc {
+    type Target = VhcFile;
+
+    fn deref(&self) -> &VhcFile {
+        &self.vhc
+    }
+}
+
+fn read_only(operation: &str) -> HypercubeError {
+    HypercubeError::ReadOnly(format!("cannot {}", operation))
+}
+
+/// A VHC file in memory, with its blocks as slices of it
+/// `open` reads a private copy. `map` maps the file instead: nothing is copied, and the OS
+/// pages blocks in as they are touched and can drop them again, so a scan over a container
+/// larger than memory works, but the file must then stay unchanged while it is mapped.
+#[derive(Debug)]
+pub struct VhcReader {
+    bytes: Backing,
+    layout: VhcLayout,
+    header: VhcHeader,
+    data_start: usize,
+    block_count: usize,
+    trailer: usize,
+}
+
+/// Bytes a `VhcReader` reads: a copy of the file, or a mapping of it
+#[derive(Debug)]
+enum Backing {
+    Read(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl std::ops::Deref for Backing {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Read(bytes) => bytes,
+            Self::Mapped(map) => map,
+        }
+    }
+}
+
+impl VhcReader {
+    /// Read a VHC file (either layout) into memory and parse its header
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::parse(Backing::Read(std::fs::read(path)?))
+    }
+
+    /// Map a VHC file (either layout) into memory and parse its header
+    ///
+    /// # Safety
+    ///
+    /// Nothing, in this process or another, may write to or truncate the file until the
+    /// reader is dropped. This crate writes containers in place in places: `wipe`,
+    /// `replicate` onto an existing mirror, and every rewrite under `HYPERCUBE_ATOMIC=0`.
+    /// A write shows through the reader's slices, and a file truncated under the mapping
+    /// faults on the next touch of a lost page.
+    pub unsafe fn map(path: &Path) -> Result<Self> {
+        // Miri cannot map files; it runs the golden tests of other targets
+        if cfg!(miri) {
+            return Self::open(path);
+        }
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only; the caller keeps the file unchanged
+        let map = unsafe { Mmap::map(&file)? };
+        Self::parse(Backing::Mapped(map))
+    }
+
+    fn parse(bytes: Backing) -> Result<Self> {
+        let file_len = bytes.len() as u64;
+        let mut cursor = std::io::Cursor::new(&bytes[..]);
+
+        let (layout, header, data_start) = read_preamble(&mut cursor)?;
+        let trailer = trailer_size(&mut cursor, &header, data_start, file_len)?;
+        let data_size = data_size(file_len, data_start, trailer)?;
+        let block_count = platform_size(data_size / header.total_block_size() as u64)?;
+        Ok(Self {
+            layout,
+            header,
+            data_start: platform_size(data_start)?,
+            block_count,
+            trailer: platform_size(trailer)?,
+            bytes,
+        })
+    }
+
+    pub fn header(&self) -> &VhcHeader {
+        &self.header
+    }
+
+    /// Magic or keyed verifier at the start of the file
+    pub fn layout(&self) -> &VhcLayout {
+        &self.layout
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.block_count
+    }
+
+    /// Offset of the first block: the layout prefix, header length and header
+    pub fn data_start(&self) -> usize {
+        self.data_start
+    }
+
+    /// The whole file
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Block `index` (sequence + data + MAC), or None past the last block
+    pub fn block(&self, index: usize) -> Option<&[u8]> {
+        let size = self.header.total_block_size();
+        let start = self.data_start + index.checked_mul(size)?;
+        (index < self.block_count).then(|| &self.bytes[start..start + size])
+    }
+
+    /// Every block in file order
+    pub fn blocks(&self) -> std::slice::ChunksExact<'_, u8> {
+        let end = self.data_start + self.block_count * self.header.total_block_size();
+        self.bytes[self.data_start..end].chunks_exact(self.header.total_block_size())
+    }
+
+    /// Block `index` split into its fields, or None past the last block
+    pub fn block_ref(&self, index: usize) -> Option<BlockRef<'_>> {
+        let offset = (self.data_start + index * self.header.total_block_size()) as u64;
+        BlockRef::parse(self.block(index)?, index, offset, &self.header)
+    }
+
+    /// Every block split into its fields, in file order
+    pub fn block_refs(&self) -> impl ExactSizeIterator<Item = BlockRef<'_>> {
+        let size = self.header.total_block_size();
+        self.blocks().enumerate().map(move |(index, bytes)| {
+            let offset = (self.data_start + index * size) as u64;
+            BlockRef::parse(bytes, index, offset, &self.header).expect("whole blocks")
+        })
+    }
+
+    /// The v2 trailer, or None for a v1 file
+    pub fn trailer(&self) -> Result<Option<VhcTrailer>> {
+        if self.trailer == 0 {
+            return Ok(None);
+        }
+        let end = self.bytes.len() - 4;
+        let start = self.bytes.len() - self.trailer;
+        Ok(Some(VhcTrailer::from_bytes(&self.bytes[start..end])?))
+    }
+}
+
+/// Read a VHC file from disk (either layout)
+pub fn read_vhc_file(path: &Path) -> Result<VhcFile> {
+    metrics::time(Stage::Io, || read_vhc(path))
+}
 
-    // Read header JSON
-    let mut header_bytes = vec![0u8; header_len];
-    reader.read_exact(&mut header_bytes)?;
-    let header = VhcHeader::from_bytes(&header_bytes)?;
+fn read_vhc(path: &Path) -> Result<VhcFile> {
+    let file = File::open(path)?;
+    let file_len = file_len(&file)?;
+    let mut reader = BufReader::new(file);
+
+    let (layout, header, data_start) = read_preamble(&mut reader)?;
 
     // Calculate data section size
-    let data_start = 4 + 4 + header_len; // magic + header_len + header
-    let data_size = file_len - data_start;
+    let trailer = trailer_size(&mut reader, &header, data_start, file_len)?;
+    let data_size = data_size(file_len, data_start, trailer)?;
     let block_size = header.total_block_size();
 
     // Read all blocks
-    let num_blocks = data_size / block_size;
+    let num_blocks = platform_size(data_size / block_size as u64)?;
     let mut blocks = Vec::with_capacity(num_blocks);
 
     for _ in 0..num_blocks {
@@ -78,19 +491,220 @@ pub fn read_vhc_file(path: &Path) -> Result<VhcFile> {
         blocks.push(block);
     }
 
-    Ok(VhcFile { header, blocks })
+    let mut journal = Vec::new();
+    if trailer > 0 {
+        reader.seek(SeekFrom::Start(file_len - trailer))?;
+        let mut bytes = vec![0u8; platform_size(trailer - 4)?];
+        reader.read_exact(&mut bytes)?;
+        journal = VhcTrailer::from_bytes(&bytes)?.journal;
+    }
+
+    Ok(VhcFile {
+        header,
+        blocks,
+        layout,
+        signature: None,
+        journal,
+    })
 }
 
 /// Write a VHC file to disk (creates new file or overwrites)
 pub fn write_vhc_file(path: &Path, vhc: &VhcFile) -> Result<()> {
-    let file = File::create(path)?;
-    let mut writer = BufWriter::new(file);
+    metrics::time(Stage::Io, || write_vhc(path, vhc))
+}
+
+/// Make rewrites atomic or not, process-wide; None goes back to `$HYPERCUBE_ATOMIC`
+pub fn set_atomic_writes(atomic: Option<bool>) {
+    let setting = match atomic {
+        None => ATOMIC_UNSET,
+        Some(true) => ATOMIC_ON,
+        Some(false) => ATOMIC_OFF,
+    };
+    ATOMIC_WRITES.store(setting, Ordering::Relaxed);
+}
+
+/// Whether `replace_file` renames a copy into place: the setting, else true unless
+/// `$HYPERCUBE_ATOMIC` is `0`
+pub fn atomic_writes() -> bool {
+    match ATOMIC_WRITES.load(Ordering::Relaxed) {
+        ATOMIC_ON => true,
+        ATOMIC_OFF => false,
+        _ => std::env::var_os(ATOMIC_ENV).is_none_or(|value| value != "0"),
+    }
+}
+
+/// Write `path` through a sibling temp file that is synced and renamed into place, so a
+/// crash part way leaves the old file whole instead of a truncated one
+/// The temp file gets a fresh name, created exclusively and owner-only, then the old file's
+/// permissions. Without `atomic_writes`, or when `path` does not exist yet (there is nothing
+/// to keep whole, and `write` gives a new file its mode), `write` is given `path` itself.
+pub fn replace_file(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    replace_file_as(path, atomic_writes(), write)
+}
+
+/// `replace_file` with the setting given rather than read
+fn replace_file_as(
+    path: &Path,
+    atomic: bool,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    let staged = stage_replacement(path, atomic, write)?;
+    commit_replacement(path, staged)
+}
+
+/// The first half of `replace_file_as`: the synced temp file, not yet renamed over `path`
+/// None when `write` was given `path` itself
+fn stage_replacement(
+    path: &Path,
+    atomic: bool,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> Result<Option<tempfile::NamedTempFile>> {
+    let permissions = match std::fs::metadata(path) {
+        Ok(metadata) if atomic => metadata.permissions(),
+        _ => return write(path).map(|()| None),
+    };
+    // Removed on drop until persisted, so a failed write leaves nothing behind
+    let temp = tempfile::NamedTempFile::new_in(parent_dir(path))?;
+    write(temp.path())?;
+    let file = temp.as_file();
+    file.set_permissions(permissions)?;
+    file.sync_all()?;
+    Ok(Some(temp))
+}
+
+/// The second half of `replace_file_as`: rename a staged file over `path`
+fn commit_replacement(path: &Path, staged: Option<tempfile::NamedTempFile>) -> Result<()> {
+    let Some(temp) = staged else {
+        return Ok(());
+    };
+    temp.persist(path).map_err(|e| e.error)?;
+    // The rename is durable once the directory is synced; not every platform can open a
+    // directory for that, and the file itself is already on disk
+    if let Ok(dir) = File::open(parent_dir(path)) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Directory holding `path`, `.` for a bare file name
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Owner-only scratch directory beside `path`, removed on drop, so files unpacked from a
+/// container stay on its filesystem rather than in a shared temp directory
+pub fn scratch_dir(path: &Path) -> Result<tempfile::TempDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".");
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o700));
+    Ok(builder.tempdir_in(parent_dir(path))?)
+}
+
+/// Write a VHC file with `replace_file`, for rewrites of an existing container
+pub fn replace_vhc_file(path: &Path, vhc: &VhcFile) -> Result<()> {
+    replace_file(path, |temp| write_vhc_file(temp, vhc))
+}
+
+/// Write a container with its header detached: `header_path` gets the layout prefix,
+/// header length and header, and `path` only the blocks and trailer, so nothing at the
+/// start of `path` identifies it as a container
+pub fn write_vhc_file_detached(path: &Path, header_path: &Path, vhc: &VhcFile) -> Result<()> {
+    let scratch_dir = scratch_dir(path)?;
+    let scratch = scratch_dir.path().join("container.vhc");
+    write_vhc_file(&scratch, vhc)?;
+    split_detached(&scratch, path, header_path)
+}
+
+/// Read a container written by `write_vhc_file_detached`
+pub fn read_vhc_file_with_header(path: &Path, header_path: &Path) -> Result<VhcFile> {
+    with_detached_header(path, header_path, false, read_vhc_file)
+}
+
+/// Run `op` on the container joined from `path` and its detached header at `header_path`
+/// The container is joined in a scratch file (absent if neither file exists) in an
+/// owner-only directory beside `path` (see `scratch_dir`); with `split`, the scratch file
+/// is detached again and written back to both
+pub fn with_detached_header<T>(
+    path: &Path,
+    header_path: &Path,
+    split: bool,
+    op: impl FnOnce(&Path) -> Result<T>,
+) -> Result<T> {
+    let scratch_dir = scratch_dir(path)?;
+    let scratch = scratch_dir.path().join("container.vhc");
+    match (path.exists(), header_path.exists()) {
+        (true, true) => join_detached(path, header_path, &scratch)?,
+        (false, false) => {}
+        (exists, _) => {
+            let (found, missing) = match exists {
+                true => (path, header_path),
+                false => (header_path, path),
+            };
+            return Err(HypercubeError::InvalidFormat(format!(
+                "{} exists but {} does not; a detached container needs both",
+                found.display(),
+                missing.display()
+            )));
+        }
+    }
 
-    // Write magic
-    writer.write_all(VHC_MAGIC)?;
+    let result = op(&scratch)?;
+    if split {
+        split_detached(&scratch, path, header_path)?;
+    }
+    Ok(result)
+}
 
-    // Serialize header
-    let header_bytes = vhc.header.to_bytes()?;
+/// Concatenate a detached header and the blocks it describes into `joined`
+fn join_detached(path: &Path, header_path: &Path, joined: &Path) -> Result<()> {
+    // A detached header holds nothing after the header itself
+    let mut header = File::open(header_path)?;
+    let header_len = file_len(&header)?;
+    if read_preamble(&mut BufReader::new(&header))?.2 != header_len {
+        return Err(HypercubeError::InvalidFormat(format!(
+            "{} is not a detached header",
+            header_path.display()
+        )));
+    }
+    header.seek(SeekFrom::Start(0))?;
+    let mut writer = BufWriter::new(create_private_file(joined)?);
+    std::io::copy(&mut header, &mut writer)?;
+    std::io::copy(&mut File::open(path)?, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Split the container at `joined` into its header and the rest, each replaced whole
+/// Both files are written and synced before either is renamed, and the blocks go first.
+/// A crash between the renames can then only pair the new blocks with the old header,
+/// which has the same geometry, never a header with blocks that were not written.
+fn split_detached(joined: &Path, path: &Path, header_path: &Path) -> Result<()> {
+    let atomic = atomic_writes();
+    let data_start = vhc_data_offset(joined)?;
+    let mut reader = File::open(joined)?;
+    let header = stage_replacement(header_path, atomic, |temp| {
+        std::io::copy(&mut (&mut reader).take(data_start), &mut create_file(temp)?)?;
+        Ok(())
+    })?;
+    let blocks = stage_replacement(path, atomic, |temp| {
+        std::io::copy(&mut reader, &mut create_file(temp)?)?;
+        Ok(())
+    })?;
+    commit_replacement(path, blocks)?;
+    commit_replacement(header_path, header)
+}
+
+fn write_vhc(path: &Path, vhc: &VhcFile) -> Result<()> {
+    let (header_bytes, trailer_bytes) = encode_frame(vhc)?;
+    let file = create_file(path)?;
+    let mut writer = BufWriter::new(file);
+
+    // Write magic (or keyed verifier)
+    writer.write_all(&vhc.layout.to_bytes())?;
 
     // Write header length
     let header_len = header_bytes.len() as u32;
@@ -104,84 +718,517 @@ pub fn write_vhc_file(path: &Path, vhc: &VhcFile) -> Result<()> {
         writer.write_all(block)?;
     }
 
+    // Write trailer sections followed by their length
+    writer.write_all(&trailer_bytes)?;
+
     writer.flush()?;
     Ok(())
 }
 
-/// Append blocks to an existing VHC file and reshuffle the global block table
-pub fn append_blocks_to_vhc(path: &Path, new_blocks: &[Vec<u8>]) -> Result<()> {
-    if new_blocks.is_empty() {
-        return Ok(());
+/// Serialized header, and the trailer sections with their length (empty without a trailer)
+fn encode_frame(vhc: &VhcFile) -> Result<(Vec<u8>, Vec<u8>)> {
+    // Before native MACs the version recorded whether a trailer follows the
+    // blocks; later versions always carry one, possibly empty
+    let mut trailer = VhcTrailer::for_file(vhc);
+    let mut header = vhc.header.clone();
+    if header.version >= NATIVE_MAC_VERSION {
+        trailer.get_or_insert_with(VhcTrailer::default);
+    } else {
+        header.version = if trailer.is_some() { TRAILER_VERSION } else { 1 };
+    }
+
+    let mut trailer_bytes = Vec::new();
+    if let Some(trailer) = &trailer {
+        trailer_bytes = trailer.to_bytes();
+        let len = trailer_bytes.len() as u32;
+        trailer_bytes.extend_from_slice(&len.to_le_bytes());
+    }
+    Ok((header.to_bytes()?, trailer_bytes))
+}
+
+/// Write a scattered append in place: the header, the slots in `writes` (in that order)
+/// and the trailer. A header that changed size moves every block, so it takes a full rewrite
+fn write_scheduled(path: &Path, vhc: &VhcFile, writes: &[usize]) -> Result<()> {
+    let (header_bytes, trailer_bytes) = encode_frame(vhc)?;
+    let header_start = vhc.layout.to_bytes().len() as u64 + 4;
+    let data_start = header_start + header_bytes.len() as u64;
+    if vhc_data_offset(path)? != data_start {
+        return write_vhc_file(path, vhc);
+    }
+
+    metrics::time(Stage::Io, || {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(header_start))?;
+        file.write_all(&header_bytes)?;
+        for &position in writes {
+            file.seek(SeekFrom::Start(block_offset(data_start, position, &vhc.header)?))?;
+            file.write_all(&vhc.blocks[position])?;
+        }
+        let end = block_offset(data_start, vhc.blocks.len(), &vhc.header)?;
+        file.seek(SeekFrom::Start(end))?;
+        file.write_all(&trailer_bytes)?;
+        file.set_len(end + trailer_bytes.len() as u64)?;
+        Ok(())
+    })
+}
+
+/// Give each appended block (`existing..` in `order`) a random slot in the whole table,
+/// moving the block it displaces to the appended block's place at the end
+fn scatter(order: &mut [usize], existing: usize, rng: &mut impl Rng) {
+    for i in existing..order.len() {
+        order.swap(rng.gen_range(0..=i), i);
+    }
+}
+
+/// Slots a scattered append writes, in disk order: the new blocks in random order, each
+/// after the block it displaced has been copied to the end, so the appended data never
+/// sits together on disk even if the write stops part way
+fn write_schedule(order: &[usize], existing: usize, rng: &mut impl Rng) -> Vec<usize> {
+    let mut placed = vec![0usize; order.len()];
+    for (position, &source) in order.iter().enumerate() {
+        placed[source] = position;
+    }
+    let mut slots = placed[existing..].to_vec();
+    slots.shuffle(rng);
+    let mut writes = Vec::with_capacity(2 * slots.len());
+    for slot in slots {
+        if slot < existing {
+            writes.push(placed[slot]);
+        }
+        writes.push(slot);
+    }
+    writes
+}
+
+/// Append blocks to an existing VHC file, reshuffling the global block table
+/// as the header's reshuffle policy dictates, or scattering the new blocks in place
+/// An append-only container keeps existing blocks in place and journals the append
+/// Returns the final positions of the appended blocks (in the order given)
+///
+/// Scattered appends only write their slots in the file itself, in `write_schedule`
+/// order, when rewrites are not atomic (`HYPERCUBE_ATOMIC=0`). Otherwise the new file is
+/// renamed into place whole: an interrupted append leaves the old container, so no
+/// partial write can cluster the new blocks, and the copy has them scattered already.
+pub fn append_blocks_to_vhc(path: &Path, new_blocks: &[Vec<u8>]) -> Result<Vec<usize>> {
+    append_blocks(path, &[], new_blocks, false, atomic_writes())
+}
+
+/// Append blocks and reorder the whole block table regardless of policy
+/// (an append-only container still never moves existing blocks)
+/// Used for chaff, which would otherwise be recognisable by its position
+pub fn append_and_reshuffle_vhc(path: &Path, new_blocks: &[Vec<u8>]) -> Result<Vec<usize>> {
+    append_blocks(path, &[], new_blocks, true, atomic_writes())
+}
+
+/// Drop the blocks at `evicted` positions, append new ones and reorder the whole table
+/// Used to swap chaff for data in an always-full container
+pub fn replace_blocks_in_vhc(
+    path: &Path,
+    evicted: &[usize],
+    new_blocks: &[Vec<u8>],
+) -> Result<Vec<usize>> {
+    append_blocks(path, evicted, new_blocks, true, atomic_writes())
+}
+
+fn append_blocks(
+    path: &Path,
+    evicted: &[usize],
+    new_blocks: &[Vec<u8>],
+    force: bool,
+    atomic: bool,
+) -> Result<Vec<usize>> {
+    if evicted.is_empty() && new_blocks.is_empty() {
+        return Ok(Vec::new());
     }
 
     let mut vhc = read_vhc_file(path)?;
+    vhc.header.check_unlocked()?;
+    if !evicted.is_empty() {
+        vhc.header.check_mutable("replace blocks")?;
+        let mut evicted = evicted.to_vec();
+        evicted.sort_unstable();
+        evicted.dedup();
+        if evicted.last().is_some_and(|&position| position >= vhc.blocks.len()) {
+            return Err(HypercubeError::InvalidFormat("Evicted block out of range".into()));
+        }
+        for &position in evicted.iter().rev() {
+            vhc.blocks.remove(position);
+        }
+    }
+    let capacity = vhc.header.theoretical_block_count();
+    let total = vhc.blocks.len() + new_blocks.len();
+    if vhc.header.always_full && total != capacity {
+        return Err(HypercubeError::NotFull {
+            blocks: total,
+            capacity,
+        });
+    }
+    let existing = vhc.blocks.len();
     vhc.blocks.extend(new_blocks.iter().cloned());
+    vhc.header.bump_generation();
 
-    if vhc.blocks.len() > 1 {
-        let mut rng = thread_rng();
-        vhc.blocks.shuffle(&mut rng);
+    // Shuffle a permutation rather than the blocks so we can report where
+    // the new blocks ended up. Without a full reshuffle existing blocks keep
+    // their slots and only the new ones are shuffled among themselves
+    let mut rng = thread_rng();
+    let header = &mut vhc.header;
+    let mut order: Vec<usize> = (0..vhc.blocks.len()).collect();
+    let mut scattered = false;
+    if header.append_only {
+        // Existing blocks never move; the new ones land after them
+        order[existing..].shuffle(&mut rng);
+    } else if force || header.reshuffle.reshuffles(header.pending_appends) {
+        order.shuffle(&mut rng);
+        header.pending_appends = 0;
+    } else {
+        // Existing blocks keep their slots, except those the new ones displace
+        scatter(&mut order, existing, &mut rng);
+        scattered = true;
+        if let ReshufflePolicy::Periodic(_) = header.reshuffle {
+            header.pending_appends += 1;
+        }
     }
 
-    write_vhc_file(path, &vhc)
+    let mut positions = vec![0usize; new_blocks.len()];
+    let mut old_blocks: Vec<Option<Vec<u8>>> = vhc.blocks.drain(..).map(Some).collect();
+    for (position, &source) in order.iter().enumerate() {
+        if source >= existing {
+            positions[source - existing] = position;
+        }
+        vhc.blocks.push(old_blocks[source].take().expect("permutation visits each block once"));
+    }
+    if vhc.header.append_only {
+        JournalEntry::record(&mut vhc.journal, &vhc.blocks, existing);
+    }
+
+    // Slots are only written in place when rewrites need not be atomic
+    if scattered && !atomic {
+        write_scheduled(path, &vhc, &write_schedule(&order, existing, &mut rng))?;
+    } else {
+        replace_file_as(path, atomic, |temp| write_vhc_file(temp, &vhc))?;
+    }
+    Ok(positions)
 }
 
-/// Read just the header from a VHC file (without loading all blocks)
-pub fn read_vhc_header(path: &Path) -> Result<VhcHeader> {
+/// Replace the header of an existing VHC file, keeping its blocks in place
+/// Counts as a write: the generation moves past both the file's and `header`'s
+pub fn rewrite_vhc_header(path: &Path, header: &VhcHeader) -> Result<()> {
+    let mut vhc = read_vhc_file(path)?;
+    let generation = vhc.header.generation.max(header.generation);
+    vhc.header = header.clone();
+    vhc.header.generation = generation;
+    vhc.header.bump_generation();
+    replace_vhc_file(path, &vhc)
+}
+
+/// Read specific blocks by index without loading the whole block table
+/// Returns None if any index lies outside the block region
+pub fn read_vhc_blocks_at(path: &Path, indices: &[usize]) -> Result<Option<Vec<Vec<u8>>>> {
+    metrics::time(Stage::Io, || read_blocks_at(path, indices))
+}
+
+fn read_blocks_at(path: &Path, indices: &[usize]) -> Result<Option<Vec<Vec<u8>>>> {
     let file = File::open(path)?;
+    let file_len = file_len(&file)?;
     let mut reader = BufReader::new(file);
 
-    // Read and verify magic
-    let mut magic = [0u8; 4];
-    reader.read_exact(&mut magic)?;
-    if &magic != VHC_MAGIC {
-        return Err(HypercubeError::InvalidFormat(
-            "Invalid VHC magic bytes".into(),
-        ));
+    let (_, header, data_start) = read_preamble(&mut reader)?;
+    let trailer = trailer_size(&mut reader, &header, data_start, file_len)?;
+    let block_size = header.total_block_size();
+    let num_blocks = data_size(file_len, data_start, trailer)? / block_size as u64;
+
+    let mut blocks = Vec::with_capacity(indices.len());
+    for &index in indices {
+        if index as u64 >= num_blocks {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(block_offset(data_start, index, &header)?))?;
+        let mut block = vec![0u8; block_size];
+        reader.read_exact(&mut block)?;
+        blocks.push(block);
     }
 
-    // Read header length
-    let mut header_len_bytes = [0u8; 4];
-    reader.read_exact(&mut header_len_bytes)?;
-    let header_len = u32::from_le_bytes(header_len_bytes) as usize;
+    Ok(Some(blocks))
+}
 
-    // Read header JSON
-    let mut header_bytes = vec![0u8; header_len];
-    reader.read_exact(&mut header_bytes)?;
-    VhcHeader::from_bytes(&header_bytes)
+/// Blocks of a VHC file read one at a time, in file order
+pub struct VhcBlocks {
+    reader: BufReader<File>,
+    header: VhcHeader,
+    remaining: usize,
 }
 
-/// Get block count from file without loading blocks
-pub fn get_block_count(path: &Path) -> Result<usize> {
+impl VhcBlocks {
+    /// Header of the file the blocks come from
+    pub fn header(&self) -> &VhcHeader {
+        &self.header
+    }
+}
+
+impl Iterator for VhcBlocks {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut block = vec![0u8; self.header.total_block_size()];
+        Some(self.reader.read_exact(&mut block).map(|()| block).map_err(Into::into))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// Iterate over the blocks of a VHC file without loading the block table
+pub fn iter_vhc_blocks(path: &Path) -> Result<VhcBlocks> {
     let file = File::open(path)?;
-    let file_len = file.metadata()?.len() as usize;
+    let file_len = file_len(&file)?;
     let mut reader = BufReader::new(file);
 
-    // Skip magic
-    reader.seek(SeekFrom::Start(4))?;
+    let (_, header, data_start) = read_preamble(&mut reader)?;
+    let trailer = trailer_size(&mut reader, &header, data_start, file_len)?;
+    let data_size = data_size(file_len, data_start, trailer)?;
+    let remaining = platform_size(data_size / header.total_block_size() as u64)?;
+    reader.seek(SeekFrom::Start(data_start))?;
+    Ok(VhcBlocks {
+        reader,
+        header,
+        remaining,
+    })
+}
 
-    // Read header length
-    let mut header_len_bytes = [0u8; 4];
-    reader.read_exact(&mut header_len_bytes)?;
-    let header_len = u32::from_le_bytes(header_len_bytes) as usize;
+/// Pick `n` blocks uniformly at random, returned with their indices in index order
+/// (every block if there are fewer than `n`)
+///
+/// Reservoir sampling reads the file once and keeps at most `n` blocks in memory. The
+/// same `seed` picks the same blocks from the same file.
+pub fn sample_blocks(path: &Path, n: usize, seed: u64) -> Result<Vec<(usize, Vec<u8>)>> {
+    metrics::time(Stage::Io, || {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let mut reservoir = Vec::with_capacity(n);
+        for (index, block) in iter_vhc_blocks(path)?.enumerate() {
+            let block = block?;
+            if reservoir.len() < n {
+                reservoir.push((index, block));
+            } else {
+                let slot = rng.gen_range(0..=index);
+                if slot < n {
+                    reservoir[slot] = (index, block);
+                }
+            }
+        }
+        reservoir.sort_unstable_by_key(|&(index, _)| index);
+        Ok(reservoir)
+    })
+}
 
-    // Read header to get block size
-    let mut header_bytes = vec![0u8; header_len];
-    reader.read_exact(&mut header_bytes)?;
-    let header = VhcHeader::from_bytes(&header_bytes)?;
+/// Byte offset of the first block (magic + header length + header)
+pub fn vhc_data_offset(path: &Path) -> Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(read_preamble(&mut reader)?.2)
+}
 
-    // Calculate block count
-    let data_start = 4 + 4 + header_len;
-    let data_size = file_len - data_start;
-    let block_size = header.total_block_size();
+/// Read just the header from a VHC file (without loading all blocks)
+pub fn read_vhc_header(path: &Path) -> Result<VhcHeader> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(read_preamble(&mut reader)?.1)
+}
+
+/// The container's id, if the file is a readable container that has one
+pub fn read_container_id(path: &Path) -> Option<ContainerId> {
+    read_vhc_header(path).ok()?.uuid
+}
+
+/// Read just the layout (magic or keyed verifier) of a VHC file
+pub fn read_vhc_layout(path: &Path) -> Result<VhcLayout> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(read_preamble(&mut reader)?.0)
+}
+
+/// Read the v2 trailer, or None for a v1 file
+pub fn read_vhc_trailer(path: &Path) -> Result<Option<VhcTrailer>> {
+    let file = File::open(path)?;
+    let file_len = file_len(&file)?;
+    let mut reader = BufReader::new(file);
+
+    let (_, header, data_start) = read_preamble(&mut reader)?;
+    let size = trailer_size(&mut reader, &header, data_start, file_len)?;
+    if size == 0 {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(file_len - size))?;
+    let mut bytes = vec![0u8; platform_size(size - 4)?];
+    reader.read_exact(&mut bytes)?;
+    Ok(Some(VhcTrailer::from_bytes(&bytes)?))
+}
+
+/// BLAKE3 digest of everything before the trailer (layout, header, blocks)
+/// This is the region covered by container signatures
+pub fn signed_region_digest(path: &Path) -> Result<Hash> {
+    let file = File::open(path)?;
+    let file_len = file_len(&file)?;
+    let mut reader = BufReader::new(file);
+
+    let (_, header, data_start) = read_preamble(&mut reader)?;
+    let trailer = trailer_size(&mut reader, &header, data_start, file_len)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut reader.take(file_len - trailer), &mut hasher)?;
+    Ok(*hasher.finalize().as_bytes())
+}
 
-    Ok(data_size / block_size)
+/// Get block count from file without loading blocks
+pub fn get_block_count(path: &Path) -> Result<usize> {
+    let (header, _, data_size) = block_region(path)?;
+    platform_size(data_size / header.total_block_size() as u64)
+}
+
+/// Byte offset and length of the block region (between header and trailer)
+pub fn vhc_block_region(path: &Path) -> Result<(u64, u64)> {
+    let (_, data_start, data_size) = block_region(path)?;
+    Ok((data_start, data_size))
+}
+
+fn block_region(path: &Path) -> Result<(VhcHeader, u64, u64)> {
+    let file = File::open(path)?;
+    let file_len = file_len(&file)?;
+    let mut reader = BufReader::new(file);
+
+    let (_, header, data_start) = read_preamble(&mut reader)?;
+    let trailer = trailer_size(&mut reader, &header, data_start, file_len)?;
+    Ok((header, data_start, data_size(file_len, data_start, trailer)?))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kdf::KdfParams;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_vhc_reader() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("mapped.vhc");
+        let header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+        let mut vhc = VhcFile::new(header.clone());
+        vhc.add_blocks((0..6u8).map(|i| vec![i; header.total_block_size()]).collect());
+        write_vhc_file(&path, &vhc).unwrap();
+        append_blocks_to_vhc(&path, &[vec![7u8; header.total_block_size()]]).unwrap();
+        let vhc = read_vhc_file(&path).unwrap();
+
+        let reader = VhcReader::open(&path).unwrap();
+        assert_eq!(reader.header().generation, vhc.header.generation);
+        assert_eq!(reader.layout(), &vhc.layout);
+        assert_eq!(reader.block_count(), 7);
+        assert_eq!(reader.blocks().len(), 7);
+        assert!(reader.blocks().eq(vhc.blocks.iter().map(Vec::as_slice)));
+        assert_eq!(reader.block(6), Some(vhc.blocks[6].as_slice()));
+        assert_eq!(reader.block(7), None);
+        let block = reader.block_ref(6).unwrap();
+        assert_eq!((block.index, block.offset), (6, vhc_data_offset(&path).unwrap() + 6 * 112));
+        assert_eq!(block.payload, &vhc.blocks[6][16..80]);
+        assert_eq!(reader.block_refs().len(), 7);
+        assert!(reader
+            .block_refs()
+            .all(|block| block.sequence[..] == vhc.blocks[block.index][..16]));
+        assert_eq!(reader.block_ref(7), None);
+        assert_eq!(reader.block(usize::MAX), None);
+        assert_eq!(reader.trailer().unwrap(), read_vhc_trailer(&path).unwrap());
+        // SAFETY: nothing writes the file until the mapping is dropped
+        let mapped = unsafe { VhcReader::map(&path) }.unwrap();
+        assert_eq!(mapped.bytes(), reader.bytes());
+        assert_eq!(mapped.block_count(), 7);
+        drop(mapped);
+
+        std::fs::write(&path, b"not a container").unwrap();
+        assert!(VhcReader::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_sample_blocks() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sampled.vhc");
+        let header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+        let mut vhc = VhcFile::new(header.clone());
+        vhc.add_blocks((0..50u8).map(|i| vec![i; header.total_block_size()]).collect());
+        write_vhc_file(&path, &vhc).unwrap();
+
+        let blocks = iter_vhc_blocks(&path).unwrap();
+        assert_eq!(blocks.header().block_size, 64);
+        assert_eq!(blocks.size_hint(), (50, Some(50)));
+        assert_eq!(blocks.collect::<Result<Vec<_>>>().unwrap(), vhc.blocks);
+
+        let sample = sample_blocks(&path, 8, 7).unwrap();
+        assert_eq!(sample.len(), 8);
+        assert!(sample.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for (index, block) in &sample {
+            assert_eq!(block, &vhc.blocks[*index]);
+        }
+        assert_eq!(sample_blocks(&path, 8, 7).unwrap(), sample);
+        assert_ne!(sample_blocks(&path, 8, 8).unwrap(), sample);
+        assert_eq!(sample_blocks(&path, 100, 7).unwrap().len(), 50);
+        assert!(sample_blocks(&path, 0, 7).unwrap().is_empty());
+
+        // Every block is about equally likely to be picked
+        let mut picks = [0usize; 50];
+        for seed in 0..500 {
+            for (index, _) in sample_blocks(&path, 5, seed).unwrap() {
+                picks[index] += 1;
+            }
+        }
+        assert!(picks.iter().all(|&count| (20..=85).contains(&count)), "{:?}", picks);
+    }
+
+    #[test]
+    fn test_open_read_only() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("evidence.vhc");
+        let header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+        let mut vhc = VhcFile::new(header.clone());
+        vhc.add_blocks((0..4u8).map(|i| vec![i; header.total_block_size()]).collect());
+        write_vhc_file(&path, &vhc).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let mut evidence = VhcFile::open_read_only(&path).unwrap();
+        assert_eq!(evidence.path(), path.as_path());
+        assert_eq!(evidence.block_count(), 4);
+        assert_eq!(evidence.blocks, vhc.blocks);
+        assert_eq!(evidence.header.block_size, 64);
+        assert_eq!(
+            evidence.read_blocks_at(&[2]).unwrap().unwrap(),
+            vec![vhc.blocks[2].clone()]
+        );
+        assert_eq!(
+            evidence.signed_region_digest().unwrap(),
+            signed_region_digest(&path).unwrap()
+        );
+
+        let block = vec![9u8; header.total_block_size()];
+        assert!(matches!(
+            evidence.add_blocks(vec![block.clone()]),
+            Err(HypercubeError::ReadOnly(_))
+        ));
+        assert!(matches!(
+            evidence.append_blocks(std::slice::from_ref(&block)),
+            Err(HypercubeError::ReadOnly(_))
+        ));
+        assert!(matches!(
+            evidence.replace_blocks(&[0], &[block]),
+            Err(HypercubeError::ReadOnly(_))
+        ));
+        assert!(matches!(
+            evidence.rewrite_header(&header),
+            Err(HypercubeError::ReadOnly(_))
+        ));
+        assert!(matches!(evidence.write(), Err(HypercubeError::ReadOnly(_))));
+        assert_eq!(evidence.block_count(), 4);
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+    }
+
     #[test]
     fn test_vhc_file_roundtrip() {
         let dir = tempdir().unwrap();
@@ -220,6 +1267,53 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_keyed_layout_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("keyed.vhc");
+
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.kdf = Some(KdfParams::new(Kdf::pbkdf2(Some(1_000))));
+        let block_size = header.total_block_size();
+        let mut vhc = VhcFile::new(header.clone());
+        vhc.layout = VhcLayout::keyed("passphrase", &header).unwrap();
+        write_vhc_file(&path, &vhc).unwrap();
+        append_blocks_to_vhc(&path, &[vec![0xAA; block_size]]).unwrap();
+
+        let raw = std::fs::read(&path).unwrap();
+        assert_ne!(&raw[..4], VHC_MAGIC);
+        let loaded = read_vhc_file(&path).unwrap();
+        assert_eq!(loaded.layout, vhc.layout);
+        assert_eq!(loaded.blocks, vec![vec![0xAA; block_size]]);
+        // One block, then an empty trailer (just its length)
+        assert_eq!(vhc_data_offset(&path).unwrap(), (raw.len() - block_size - 4) as u64);
+
+        let layout = read_vhc_layout(&path).unwrap();
+        let verify = |layout: &VhcLayout, passphrase, header| layout.verify(passphrase, header);
+        assert!(verify(&layout, "passphrase", &loaded.header).unwrap());
+        assert!(!verify(&layout, "Passphrase", &loaded.header).unwrap());
+        assert!(!verify(&VhcLayout::Magic, "passphrase", &loaded.header).unwrap());
+        // The tag is stretched with the recorded KDF, so other parameters miss it
+        let mut other = loaded.header.clone();
+        other.kdf = Some(KdfParams::new(Kdf::pbkdf2(Some(1_001))));
+        assert!(!verify(&layout, "passphrase", &other).unwrap());
+    }
+
+    #[test]
+    fn test_random_bytes_are_not_a_container() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("random.bin");
+
+        let mut data = vec![0u8; 4096];
+        thread_rng().fill_bytes(&mut data);
+        // A huge header length must not be trusted
+        data[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+
+        let err = read_vhc_header(&path).unwrap_err();
+        assert!(err.to_string().contains("Not a VHC file"));
+    }
+
     #[test]
     fn test_append_blocks() {
         let dir = tempdir().unwrap();
@@ -238,11 +1332,13 @@ mod tests {
 
         // Append more blocks
         let block3: Vec<u8> = vec![0xCC; block_size];
-        append_blocks_to_vhc(&path, &[block3.clone()]).unwrap();
+        let positions = append_blocks_to_vhc(&path, std::slice::from_ref(&block3)).unwrap();
 
         // Read and verify
         let loaded = read_vhc_file(&path).unwrap();
         assert_eq!(loaded.blocks.len(), 3);
+        assert_eq!(positions.len(), 1);
+        assert_eq!(loaded.blocks[positions[0]], block3);
 
         let mut actual = loaded.blocks.clone();
         actual.sort();
@@ -251,6 +1347,145 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_reshuffle_policy() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("policy.vhc");
+
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.reshuffle = ReshufflePolicy::Periodic(3);
+        let block_size = header.total_block_size();
+        write_vhc_file(&path, &VhcFile::new(header)).unwrap();
+
+        let first: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; block_size]).collect();
+        append_blocks_to_vhc(&path, &first).unwrap();
+        let placed = read_vhc_file(&path).unwrap().blocks;
+
+        // Second append: the new block takes a random slot and the block it displaces
+        // moves to the end; every other block stays put
+        let positions = append_blocks_to_vhc(&path, &[vec![100u8; block_size]]).unwrap();
+        let loaded = read_vhc_file(&path).unwrap();
+        let mut expected = placed.clone();
+        expected.push(vec![100u8; block_size]);
+        expected.swap(positions[0], 16);
+        assert_eq!(loaded.blocks, expected);
+        assert_eq!(loaded.header.pending_appends, 2);
+
+        // Third append hits the period and resets the counter
+        append_blocks_to_vhc(&path, &[vec![101u8; block_size]]).unwrap();
+        let loaded = read_vhc_file(&path).unwrap();
+        assert_eq!(loaded.header.pending_appends, 0);
+        assert_eq!(loaded.blocks.len(), 18);
+
+        // Forced reshuffle ignores a `never` policy
+        let mut header = loaded.header.clone();
+        header.reshuffle = ReshufflePolicy::Never;
+        rewrite_vhc_header(&path, &header).unwrap();
+        let mut before = read_vhc_file(&path).unwrap().blocks;
+        let positions = append_blocks_to_vhc(&path, &[vec![102u8; block_size]]).unwrap();
+        before.push(vec![102u8; block_size]);
+        before.swap(positions[0], 18);
+        assert_eq!(read_vhc_file(&path).unwrap().blocks, before);
+        let chaff: Vec<Vec<u8>> = (0..64u8).map(|i| vec![i ^ 0x80; block_size]).collect();
+        append_and_reshuffle_vhc(&path, &chaff).unwrap();
+        assert_ne!(&read_vhc_file(&path).unwrap().blocks[..19], &before[..]);
+    }
+
+    #[test]
+    fn test_scattered_append_writes_in_place() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("scatter.vhc");
+
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.reshuffle = ReshufflePolicy::Never;
+        header.merkle = true;
+        let block_size = header.total_block_size();
+        write_vhc_file(&path, &VhcFile::new(header)).unwrap();
+        let first: Vec<Vec<u8>> = (0..64u8).map(|i| vec![i; block_size]).collect();
+        append_blocks_to_vhc(&path, &first).unwrap();
+
+        // Only without atomic rewrites
+        let new: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i | 0x80; block_size]).collect();
+        #[cfg(unix)]
+        let inode = || std::os::unix::fs::MetadataExt::ino(&std::fs::metadata(&path).unwrap());
+        #[cfg(unix)]
+        let before = inode();
+        let positions = append_blocks(&path, &[], &new, false, false).unwrap();
+        #[cfg(unix)]
+        assert_eq!(inode(), before);
+        let loaded = read_vhc_file(&path).unwrap();
+        for (block, &position) in new.iter().zip(&positions) {
+            assert_eq!(&loaded.blocks[position], block);
+        }
+        // The new blocks are spread over the table rather than all at the end
+        assert!(positions.iter().any(|&position| position < 64));
+        assert_eq!(loaded.blocks.len(), 80);
+        let mut sorted = loaded.blocks.clone();
+        sorted.sort();
+        let mut expected = [first, new].concat();
+        expected.sort();
+        assert_eq!(sorted, expected);
+
+        // Byte for byte what a full rewrite produces, Merkle trailer included
+        let rewritten = dir.path().join("rewritten.vhc");
+        write_vhc_file(&rewritten, &loaded).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), std::fs::read(&rewritten).unwrap());
+        assert!(crate::cli::proof::corrupted_blocks(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_schedule() {
+        let mut rng = thread_rng();
+        let mut order: Vec<usize> = (0..40).collect();
+        scatter(&mut order, 32, &mut rng);
+        let writes = write_schedule(&order, 32, &mut rng);
+
+        // Every slot holding a new block is written, and so is every end slot
+        let mut written = writes.clone();
+        written.sort_unstable();
+        written.dedup();
+        assert_eq!(written.len(), writes.len());
+        for (position, &source) in order.iter().enumerate() {
+            assert_eq!(written.contains(&position), source >= 32 || position >= 32);
+        }
+        // A displaced block is copied to the end before its slot is overwritten
+        for (i, &position) in writes.iter().enumerate() {
+            if position < 32 {
+                let copy = order.iter().position(|&source| source == position).unwrap();
+                assert!(writes[..i].contains(&copy));
+            }
+        }
+    }
+
+    #[test]
+    fn test_append_only_journal() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.vhc");
+
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.append_only = true;
+        let block_size = header.total_block_size();
+        write_vhc_file(&path, &VhcFile::new(header)).unwrap();
+
+        let first: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; block_size]).collect();
+        append_blocks_to_vhc(&path, &first).unwrap();
+        let placed = read_vhc_file(&path).unwrap().blocks;
+
+        // Even a forced reshuffle leaves journaled blocks where they are
+        let chaff: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i ^ 0x80; block_size]).collect();
+        let positions = append_and_reshuffle_vhc(&path, &chaff).unwrap();
+        assert!(positions.iter().all(|&position| position >= 16));
+        let loaded = read_vhc_file(&path).unwrap();
+        assert_eq!(&loaded.blocks[..16], &placed[..]);
+        assert_eq!(loaded.journal.len(), 2);
+        assert_eq!(loaded.journal[1].first_block, 16);
+        crate::journal::verify_journal(&loaded.journal, &loaded.blocks).unwrap();
+
+        // Header rewrites carry the journal along
+        rewrite_vhc_header(&path, &loaded.header).unwrap();
+        assert_eq!(read_vhc_file(&path).unwrap().journal, loaded.journal);
+    }
+
     #[test]
     fn test_read_header_only() {
         let dir = tempdir().unwrap();
@@ -271,5 +1506,190 @@ mod tests {
         // Get block count
         let count = get_block_count(&path).unwrap();
         assert_eq!(count, 100);
+
+        // Blocks fill the file from the data offset up to the empty trailer
+        let file_len = std::fs::metadata(&path).unwrap().len();
+        let data_start = vhc_data_offset(&path).unwrap();
+        assert_eq!(file_len - data_start, 100 * block_size as u64 + 4);
+    }
+
+    #[test]
+    fn test_detached_header() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let header_path = dir.path().join("data.hdr");
+        let header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+        let mut vhc = VhcFile::new(header.clone());
+        vhc.add_blocks((0..4u8).map(|i| vec![i; header.total_block_size()]).collect());
+        write_vhc_file_detached(&path, &header_path, &vhc).unwrap();
+
+        // The data file starts with the first block; the header file holds the rest
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(&data[..header.total_block_size()], &vhc.blocks[0][..]);
+        assert_eq!(data.len(), 4 * header.total_block_size() + 4);
+        let detached = read_vhc_header(&header_path).unwrap();
+        assert_eq!(detached.to_bytes().unwrap(), vhc.header.to_bytes().unwrap());
+        let read = read_vhc_file_with_header(&path, &header_path).unwrap();
+        assert_eq!(read.blocks, vhc.blocks);
+
+        // Changes made through the joined file land back in both halves; it is joined
+        // beside the data file, in a directory only the owner can enter, and removed
+        let scratch_dir = with_detached_header(&path, &header_path, true, |joined| {
+            let scratch_dir = joined.parent().unwrap();
+            assert_eq!(scratch_dir.parent(), Some(dir.path()));
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(scratch_dir)?.permissions().mode();
+                assert_eq!(mode & 0o777, 0o700);
+            }
+            append_blocks_to_vhc(joined, &[vec![9u8; header.total_block_size()]])?;
+            Ok(scratch_dir.to_path_buf())
+        })
+        .unwrap();
+        assert!(!scratch_dir.exists());
+        let read = read_vhc_file_with_header(&path, &header_path).unwrap();
+        assert_eq!(read.blocks.len(), 5);
+
+        // The header is renamed only once the blocks are in place: here they cannot be
+        // (a directory is in the way), so the old header stays, and no temp file is left
+        let joined = dir.path().join("joined.vhc");
+        write_vhc_file(&joined, &read).unwrap();
+        let blocked = dir.path().join("blocked");
+        std::fs::create_dir(&blocked).unwrap();
+        let before = std::fs::read(&header_path).unwrap();
+        assert!(split_detached(&joined, &blocked, &header_path).is_err());
+        assert_eq!(std::fs::read(&header_path).unwrap(), before);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 4);
+        std::fs::remove_dir(&blocked).unwrap();
+        std::fs::remove_file(&joined).unwrap();
+
+        // Either half alone is refused, as is a whole container in place of a header
+        assert!(read_vhc_file_with_header(&path, &dir.path().join("missing")).is_err());
+        assert!(read_vhc_file_with_header(&dir.path().join("missing"), &header_path).is_err());
+        let whole = dir.path().join("whole.vhc");
+        write_vhc_file(&whole, &vhc).unwrap();
+        assert!(read_vhc_file_with_header(&path, &whole).is_err());
+    }
+
+    #[test]
+    fn test_read_blocks_at() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("positions.vhc");
+
+        let header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        let block_size = header.total_block_size();
+        let mut vhc = VhcFile::new(header);
+        let blocks: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; block_size]).collect();
+        vhc.add_blocks(blocks.clone());
+        write_vhc_file(&path, &vhc).unwrap();
+
+        let picked = read_vhc_blocks_at(&path, &[3, 1]).unwrap().unwrap();
+        assert_eq!(picked, vec![blocks[3].clone(), blocks[1].clone()]);
+
+        // Out-of-range indices are reported rather than erroring
+        assert!(read_vhc_blocks_at(&path, &[5]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_offsets_past_4_gib() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("large.vhc");
+        let header = VhcHeader::new(8192, 8192, 8192, 1024, 256).unwrap();
+        let block_size = header.total_block_size();
+        write_vhc_file(&path, &VhcFile::new(header.clone())).unwrap();
+
+        // Grow the block region past 4 GiB as a sparse file, keeping the empty trailer
+        let data_start = vhc_data_offset(&path).unwrap();
+        let trailer = std::fs::read(&path).unwrap()[data_start as usize..].to_vec();
+        let blocks = platform_size((5u64 << 30) / block_size as u64).unwrap();
+        let last = block_offset(data_start, blocks - 1, &header).unwrap();
+        assert!(last > u64::from(u32::MAX));
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(last)).unwrap();
+        file.write_all(&vec![0x5A; block_size]).unwrap();
+        file.write_all(&trailer).unwrap();
+        drop(file);
+
+        let region = blocks as u64 * block_size as u64;
+        assert_eq!(vhc_block_region(&path).unwrap(), (data_start, region));
+        assert_eq!(get_block_count(&path).unwrap(), blocks);
+        let picked = read_vhc_blocks_at(&path, &[blocks - 1]).unwrap().unwrap();
+        assert_eq!(picked, vec![vec![0x5A; block_size]]);
+        assert!(read_vhc_blocks_at(&path, &[blocks]).unwrap().is_none());
+        assert!(read_vhc_trailer(&path).unwrap().is_some());
+
+        assert!(block_offset(u64::MAX - 10, 1, &header).is_err());
+    }
+
+    #[test]
+    fn test_legacy_versions_keep_their_layout() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("legacy.vhc");
+
+        let mut header = VhcHeader::new(32, 32, 32, 64, 512).unwrap();
+        header.version = 1;
+        let block_size = header.total_block_size();
+        let mut vhc = VhcFile::new(header);
+        vhc.add_blocks(vec![vec![7u8; block_size]; 3]);
+        write_vhc_file(&path, &vhc).unwrap();
+
+        // v1 files end with the last block and keep expanded wide tags
+        let file_len = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(file_len - vhc_data_offset(&path).unwrap(), 3 * block_size as u64);
+        let header = read_vhc_header(&path).unwrap();
+        assert_eq!(header.version, 1);
+        assert_eq!(header.wide_tag(), crate::pipeline::WideTag::Expanded);
+
+        // A trailer moves it to v2, never to the native-MAC version
+        let mut header = header;
+        header.merkle = true;
+        rewrite_vhc_header(&path, &header).unwrap();
+        assert_eq!(read_vhc_header(&path).unwrap().version, TRAILER_VERSION);
+        assert_eq!(get_block_count(&path).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_merkle_trailer() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("merkle.vhc");
+
+        let mut header = VhcHeader::new(32, 32, 32, 64, 256).unwrap();
+        header.merkle = true;
+        let block_size = header.total_block_size();
+        let mut vhc = VhcFile::new(header);
+        let blocks: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; block_size]).collect();
+        vhc.add_blocks(blocks.clone());
+        write_vhc_file(&path, &vhc).unwrap();
+
+        // The trailer is not mistaken for blocks
+        assert_eq!(read_vhc_header(&path).unwrap().version, domains::LATEST_VERSION);
+        assert_eq!(get_block_count(&path).unwrap(), 5);
+        assert_eq!(read_vhc_file(&path).unwrap().blocks, blocks);
+        assert!(read_vhc_blocks_at(&path, &[5]).unwrap().is_none());
+
+        let trailer = read_vhc_trailer(&path).unwrap().unwrap();
+        let leaves = trailer.merkle_leaves.unwrap();
+        assert_eq!(leaves.len(), 5);
+        assert_eq!(leaves[4], crate::merkle::leaf_hash(&blocks[4]));
+
+        // Appending keeps the leaves in step with the reshuffled blocks
+        append_blocks_to_vhc(&path, &[vec![9u8; block_size]]).unwrap();
+        let reloaded = read_vhc_file(&path).unwrap();
+        let trailer = read_vhc_trailer(&path).unwrap().unwrap();
+        assert_eq!(
+            trailer.merkle_tree().unwrap().root(),
+            crate::merkle::MerkleTree::from_blocks(&reloaded.blocks).root()
+        );
+
+        // Without a Merkle tree the trailer is empty; v1 files have none at all
+        write_vhc_file(&path, &VhcFile::new(VhcHeader::default())).unwrap();
+        assert_eq!(read_vhc_trailer(&path).unwrap(), Some(VhcTrailer::default()));
+        let legacy = VhcHeader {
+            version: 1,
+            ..Default::default()
+        };
+        write_vhc_file(&path, &VhcFile::new(legacy)).unwrap();
+        assert!(read_vhc_trailer(&path).unwrap().is_none());
     }
 }
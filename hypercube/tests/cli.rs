@@ -1,10 +1,20 @@
 use std::error::Error;
 use std::fs;
-use std::process::{Command, Output};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
 use tempfile::tempdir;
 
 fn hypercube_command() -> Command {
-    Command::new(env!("CARGO_BIN_EXE_hypercube"))
+    // Keep the developer's own config file (and its defaults) and attempt log out of the tests
+    let mut command = Command::new(env!("CARGO_BIN_EXE_hypercube"));
+    command
+        .env_remove("HYPERCUBE_CONFIG")
+        .env_remove("HYPERCUBE_HONEYPOT_ALERT")
+        .env_remove("HYPERCUBE_ATOMIC")
+        .env("XDG_CONFIG_HOME", env!("CARGO_TARGET_TMPDIR"))
+        .env("XDG_STATE_HOME", env!("CARGO_TARGET_TMPDIR"));
+    command
 }
 
 fn run(args: &[&str]) -> Result<Output, Box<dyn Error>> {
@@ -37,6 +47,10 @@ fn cli_end_to_end_flow() -> Result<(), Box<dyn Error>> {
         String::from_utf8(add.stdout.clone())?.contains("Added"),
         "add output missing confirmation"
     );
+    assert!(
+        String::from_utf8(add.stdout.clone())?.contains("Compressed size:"),
+        "add output missing size report"
+    );
 
     assert!(vault.exists(), "vault file should exist after add");
 
@@ -45,6 +59,12 @@ fn cli_end_to_end_flow() -> Result<(), Box<dyn Error>> {
     let info_stdout = String::from_utf8(info.stdout)?;
     assert!(info_stdout.contains("Cube id: 32")); // Cube id equals dimension
     assert!(info_stdout.contains("Blocks per partition: 32"));
+    assert!(info_stdout.contains("KDF: argon2id"), "{}", info_stdout);
+
+    let redacted = run(&["info", "--redacted", vault.to_str().unwrap()])?;
+    let redacted_stdout = String::from_utf8(redacted.stdout)?;
+    assert!(redacted_stdout.contains("Cube id: 32"));
+    assert!(!redacted_stdout.contains("Total blocks written"));
 
     // Extract partition
     let extract = run(&[
@@ -108,3 +128,1631 @@ fn add_defaults_output_extension() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn add_verify_reads_the_partition_back() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("data.bin");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"delete me once stored")?;
+    let (input, vault) = (input.to_str().unwrap(), vault.to_str().unwrap());
+
+    let add = run(&["add", "--secret", "s", "--kdf", "none", "--verify", input, vault])?;
+    let stdout = String::from_utf8(add.stdout)?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    assert!(stdout.contains("Verified: the partition reads back identical"), "{}", stdout);
+    Ok(())
+}
+
+#[test]
+fn add_grow_makes_room_in_a_sealed_container() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("data.bin");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"late arrival")?;
+    let (input, vault) = (input.to_str().unwrap(), vault.to_str().unwrap());
+
+    let add = run(&["add", "--secret", "a", "--kdf", "none", "--dimension", "8", "--seal", input, vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let full = run(&["add", "--secret", "b", input, vault])?;
+    assert!(String::from_utf8(full.stderr)?.contains("Cube is full"));
+    let grow = run(&["add", "--secret", "b", "--grow", input, vault])?;
+    assert!(grow.status.success(), "{}", String::from_utf8_lossy(&grow.stderr));
+    let info = String::from_utf8(run(&["info", vault])?.stdout)?;
+    assert!(info.contains("Partitions: 16"), "{}", info);
+    Ok(())
+}
+
+#[test]
+fn add_takes_an_explicit_geometry() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("data.bin");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"shaped by hand")?;
+    let (input, vault) = (input.to_str().unwrap(), vault.to_str().unwrap());
+
+    let add = run(&[
+        "add", "--secret", "s", "--kdf", "none", "--partitions", "16",
+        "--blocks-per-partition", "8", "--block-size", "64", input, vault,
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let info = String::from_utf8(run(&["info", vault])?.stdout)?;
+    assert!(info.contains("Partitions: 16"), "{}", info);
+    assert!(info.contains("Blocks per partition: 8"), "{}", info);
+    assert!(info.contains("Block payload: 64 bytes"), "{}", info);
+
+    // Sizes the header cannot hold are refused by name
+    let odd = dir.path().join("odd.vhc");
+    let odd = odd.to_str().unwrap();
+    let add = run(&["add", "--secret", "s", "--blocks-per-partition", "12", input, odd])?;
+    assert!(!add.status.success());
+    assert!(String::from_utf8(add.stderr)?.contains("Invalid blocks per partition: 12"));
+    Ok(())
+}
+
+#[test]
+fn container_id_in_info_and_errors() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("data.txt");
+    let vault = dir.path().join("vault.vhc");
+    let recovered = dir.path().join("recovered.txt");
+    fs::write(&input, b"payload")?;
+    let vault_arg = vault.to_str().unwrap();
+
+    let add = run(&["add", "--secret", "right", input.to_str().unwrap(), vault_arg])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let info = String::from_utf8(run(&["info", vault_arg])?.stdout)?;
+    let id = info
+        .lines()
+        .find_map(|line| line.strip_prefix("Container id: "))
+        .expect("info shows the container id")
+        .to_string();
+    assert_eq!(id.len(), 36, "{}", id);
+
+    // Errors about the container name it by id
+    let extract = run(&["extract", "--secret", "wrong", vault_arg, recovered.to_str().unwrap()])?;
+    assert!(!extract.status.success());
+    let stderr = String::from_utf8(extract.stderr)?;
+    assert!(stderr.contains(&format!("(container {})", id)), "{}", stderr);
+
+    // Writes keep the id
+    let seal = run(&["seal", vault_arg])?;
+    assert!(seal.status.success(), "{}", String::from_utf8_lossy(&seal.stderr));
+    let sealed = String::from_utf8(run(&["info", vault_arg])?.stdout)?;
+    assert!(sealed.contains(&id), "{}", sealed);
+
+    Ok(())
+}
+
+#[test]
+fn archive_add_ls_and_extract() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let tree = dir.path().join("photos");
+    fs::create_dir_all(tree.join("2026"))?;
+    fs::write(tree.join("index.txt"), b"two albums")?;
+    fs::write(tree.join("2026/beach.jpg"), b"not really a jpeg")?;
+
+    // `photos/` defaults to photos.vhc beside the directory
+    let tree_arg = format!("{}/", tree.display());
+    let add = run(&["add", "--secret", "s", "--kdf", "none", "--archive", &tree_arg])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let vault = dir.path().join("photos.vhc");
+    assert!(vault.is_file());
+
+    let ls = run(&["ls", "--secret", "s", vault.to_str().unwrap()])?;
+    assert!(ls.status.success(), "{}", String::from_utf8_lossy(&ls.stderr));
+    let listing = String::from_utf8(ls.stdout)?;
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", listing);
+    assert!(lines[0].starts_with('d') && lines[0].ends_with(" 2026"), "{}", listing);
+    assert!(lines[1].contains(" 17 "), "{}", listing);
+    assert!(lines[1].ends_with("2026/beach.jpg"), "{}", listing);
+    assert!(lines[2].ends_with("index.txt"), "{}", listing);
+
+    let out = dir.path().join("restored");
+    let extract = run(&[
+        "extract",
+        "--secret",
+        "s",
+        "--archive",
+        vault.to_str().unwrap(),
+        out.to_str().unwrap(),
+    ])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(out.join("2026/beach.jpg"))?, b"not really a jpeg");
+    assert_eq!(fs::read(out.join("index.txt"))?, b"two albums");
+
+    // Attributes belong to archive entries
+    let vault = vault.to_str().unwrap();
+    let plain = dir.path().join("plain.vhc");
+    let add = run(&["add", "--secret", "s", "--xattrs", vault, plain.to_str().unwrap()])?;
+    assert!(!add.status.success());
+    assert!(!run(&["extract", "--secret", "s", "--selinux", vault, out.to_str().unwrap()])?
+        .status
+        .success());
+    Ok(())
+}
+
+#[test]
+fn manifest_checks_an_extracted_copy() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("data.txt");
+    let vault = dir.path().join("vault.vhc");
+    let copy = dir.path().join("copy.txt");
+    fs::write(&input, b"manifested payload")?;
+    let (input, vault_arg) = (input.to_str().unwrap(), vault.to_str().unwrap());
+    let add = run(&["add", "--secret", "s", "--kdf", "none", input, vault_arg])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let manifest = run(&["manifest", "--secret", "s", "--label", "notes", vault_arg])?;
+    assert!(manifest.status.success(), "{}", String::from_utf8_lossy(&manifest.stderr));
+    let json = String::from_utf8(manifest.stdout)?;
+    assert!(json.contains("\"label\": \"notes\""), "{}", json);
+    assert!(json.contains("\"original_size\": 18"), "{}", json);
+    let manifest = dir.path().join("notes.json");
+    fs::write(&manifest, &json)?;
+    let manifest = manifest.to_str().unwrap();
+
+    fs::write(&copy, b"manifested payload")?;
+    let check = |secret: &str| {
+        let args = ["check-manifest", "--secret", secret, "--manifest", manifest];
+        run(&[&args[..], &[vault_arg, copy.to_str().unwrap()]].concat())
+    };
+    let ok = check("s")?;
+    assert!(ok.status.success(), "{}", String::from_utf8_lossy(&ok.stderr));
+    assert!(String::from_utf8(ok.stdout)?.contains("matches the manifest"));
+    assert!(!check("wrong")?.status.success());
+
+    fs::write(&copy, b"manifested payloaD")?;
+    assert!(!check("s")?.status.success());
+    Ok(())
+}
+
+#[test]
+fn capabilities_name_what_a_container_lacks() -> Result<(), Box<dyn Error>> {
+    let listed = run(&["capabilities", "--format", "json"])?;
+    assert!(listed.status.success());
+    let capabilities: serde_json::Value = serde_json::from_slice(&listed.stdout)?;
+    assert_eq!(capabilities["stages"]["aont"], serde_json::json!(["oaep", "rivest"]));
+    assert!(capabilities["format_versions"][1].as_u64() >= Some(5));
+
+    // A container naming a stage only another build registers
+    let dir = tempdir()?;
+    let input = dir.path().join("data.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"needs a plugin")?;
+    let (input, vault_arg) = (input.to_str().unwrap(), vault.to_str().unwrap());
+    let add = run(&["add", "--secret", "s", "--kdf", "none", input, vault_arg])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let mut file = hypercube::read_vhc_file(&vault)?;
+    file.header.stages.whitener = Some("rot13".into());
+    hypercube::write_vhc_file(&vault, &file)?;
+
+    let info = run(&["info", vault_arg])?;
+    assert!(info.status.success());
+    let info = String::from_utf8(info.stdout)?;
+    assert!(info.contains("Missing from this build: whitener stage `rot13`"), "{}", info);
+    let out = dir.path().join("out.txt");
+    let extract = run(&["extract", "--secret", "s", vault_arg, out.to_str().unwrap()])?;
+    assert!(!extract.status.success());
+    let stderr = String::from_utf8(extract.stderr)?;
+    assert!(stderr.contains("requires whitener stage `rot13`"), "{}", stderr);
+    Ok(())
+}
+
+#[test]
+fn add_and_extract_print_timings() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("data.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"timed payload")?;
+    let (input, vault, output) = (
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+        output.to_str().unwrap(),
+    );
+
+    let add = run(&["add", "--timings", "--secret", "s", input, vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let report = String::from_utf8(add.stdout)?;
+    for stage in ["compress", "aont", "mac", "io", "total"] {
+        assert!(report.contains(&format!("  {} ", stage)), "{}", report);
+    }
+
+    let extract = run(&["extract", "--timings", "--secret", "s", vault, output])?;
+    assert!(extract.status.success());
+    assert!(String::from_utf8(extract.stdout)?.contains("  decompress "));
+    let quiet = run(&["extract", "--secret", "s", vault, output])?;
+    assert!(!String::from_utf8(quiet.stdout)?.contains("Timings:"));
+    Ok(())
+}
+
+#[test]
+fn detached_header_keeps_the_container_headerless() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let (first, second) = (dir.path().join("first.txt"), dir.path().join("second.txt"));
+    let (data, header) = (dir.path().join("data.bin"), dir.path().join("key.hdr"));
+    fs::write(&first, b"first partition")?;
+    fs::write(&second, b"second partition")?;
+    let (data_arg, header_arg) = (data.to_str().unwrap(), header.to_str().unwrap());
+
+    for (secret, input) in [("a", &first), ("b", &second)] {
+        let args = ["add", "--secret", secret, "--kdf", "none", "--detached-header", header_arg];
+        let add = run(&[&args[..], &[input.to_str().unwrap(), data_arg]].concat())?;
+        assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    }
+    assert_ne!(&fs::read(&data)?[..4], b"VHC\x01");
+    assert_eq!(&fs::read(&header)?[..4], b"VHC\x01");
+
+    let cat = run(&["cat", "--secret", "b", "--detached-header", header_arg, data_arg])?;
+    assert!(cat.status.success(), "{}", String::from_utf8_lossy(&cat.stderr));
+    assert_eq!(cat.stdout, b"second partition");
+
+    let out = dir.path().join("out.txt");
+    let out_arg = out.to_str().unwrap();
+    let args = ["extract", "--secret", "a", "--detached-header", header_arg];
+    let extract = run(&[&args[..], &[data_arg, out_arg]].concat())?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(&out)?, b"first partition");
+
+    // Without its header the data file is not a container
+    assert!(!run(&["extract", "--secret", "a", data_arg, out_arg])?.status.success());
+    Ok(())
+}
+
+#[test]
+fn attempt_policy_backs_off_after_a_wrong_secret() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("data.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"guarded")?;
+    let (input, vault_arg) = (input.to_str().unwrap(), vault.to_str().unwrap());
+    let args = ["add", "--secret", "s", "--kdf", "none", "--attempt-policy", "0:60000"];
+    let add = run(&[&args[..], &[input, vault_arg]].concat())?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let info = String::from_utf8(run(&["info", vault_arg])?.stdout)?;
+    let policy = "Attempt policy: KDF at least 0 ms, backoff from 60000 ms";
+    assert!(info.contains(policy), "{}", info);
+
+    let wrong = run(&["cat", "--secret", "guess", vault_arg])?;
+    assert!(!wrong.status.success());
+    // Even the right secret has to wait out the backoff
+    let refused = run(&["cat", "--secret", "s", vault_arg])?;
+    assert!(!refused.status.success());
+    let stderr = String::from_utf8(refused.stderr)?;
+    assert!(stderr.contains("attempts on this container (1); try again in 60 s"), "{}", stderr);
+    Ok(())
+}
+
+#[test]
+fn magicless_container_requires_passphrase() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("secret.txt");
+    let vault = dir.path().join("vault.bin");
+    let extracted = dir.path().join("recovered.txt");
+    fs::write(&input, b"no magic here")?;
+
+    let missing = run(&["add", "--secret", "s", "--magicless", input.to_str().unwrap()])?;
+    assert!(!missing.status.success(), "--magicless without --passphrase");
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--magicless",
+        "--passphrase",
+        "outer",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(
+        add.status.success(),
+        "add command failed: {}",
+        String::from_utf8_lossy(&add.stderr)
+    );
+    assert_ne!(&fs::read(&vault)?[..4], b"VHC\x01");
+
+    let info = run(&["info", vault.to_str().unwrap()])?;
+    assert!(String::from_utf8(info.stdout)?.contains("Layout: magicless"));
+
+    let wrong = run(&[
+        "extract",
+        "--secret",
+        "s",
+        "--passphrase",
+        "guess",
+        vault.to_str().unwrap(),
+        extracted.to_str().unwrap(),
+    ])?;
+    assert!(!wrong.status.success(), "wrong passphrase must be rejected");
+
+    let extract = run(&[
+        "extract",
+        "--secret",
+        "s",
+        "--passphrase",
+        "outer",
+        vault.to_str().unwrap(),
+        extracted.to_str().unwrap(),
+    ])?;
+    assert!(
+        extract.status.success(),
+        "extract command failed: {}",
+        String::from_utf8_lossy(&extract.stderr)
+    );
+    assert_eq!(fs::read(&extracted)?, fs::read(&input)?);
+
+    Ok(())
+}
+
+#[test]
+fn embed_and_dig_roundtrip_through_pdf() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("secret.txt");
+    let vault = dir.path().join("vault.vhc");
+    let carrier = dir.path().join("doc.pdf");
+    let polyglot = dir.path().join("report.pdf");
+    let dug = dir.path().join("dug.vhc");
+    fs::write(&input, b"carried payload")?;
+    fs::write(
+        &carrier,
+        b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog >>\nendobj\nxref\n0 2\n\
+          0000000000 65535 f \n0000000009 00000 n \n\
+          trailer\n<< /Size 2 /Root 1 0 R >>\nstartxref\n45\n%%EOF\n",
+    )?;
+
+    let add = run(&["add", "--secret", "s", input.to_str().unwrap(), vault.to_str().unwrap()])?;
+    assert!(add.status.success());
+
+    let embed = run(&[
+        "embed",
+        vault.to_str().unwrap(),
+        carrier.to_str().unwrap(),
+        polyglot.to_str().unwrap(),
+    ])?;
+    assert!(
+        embed.status.success(),
+        "embed command failed: {}",
+        String::from_utf8_lossy(&embed.stderr)
+    );
+    assert!(fs::read(&polyglot)?.starts_with(&fs::read(&carrier)?));
+
+    let dig = run(&["dig", polyglot.to_str().unwrap(), dug.to_str().unwrap()])?;
+    assert!(
+        dig.status.success(),
+        "dig command failed: {}",
+        String::from_utf8_lossy(&dig.stderr)
+    );
+    assert_eq!(fs::read(&dug)?, fs::read(&vault)?);
+
+    let nothing = run(&["dig", carrier.to_str().unwrap(), dug.to_str().unwrap()])?;
+    assert!(!nothing.status.success(), "plain carrier has nothing to dig");
+
+    Ok(())
+}
+
+#[test]
+fn wipe_destroys_partition() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("secret.txt");
+    let vault = dir.path().join("vault.vhc");
+    let extracted = dir.path().join("recovered.txt");
+    fs::write(&input, b"short-lived secret")?;
+
+    let add = run(&["add", "--secret", "s", input.to_str().unwrap(), vault.to_str().unwrap()])?;
+    assert!(add.status.success());
+
+    let wipe = run(&["wipe", "--secret", "s", "--passes", "2", vault.to_str().unwrap()])?;
+    assert!(
+        wipe.status.success(),
+        "wipe command failed: {}",
+        String::from_utf8_lossy(&wipe.stderr)
+    );
+    assert!(String::from_utf8(wipe.stdout)?.contains("Wiped 32 blocks"));
+
+    let extract = run(&[
+        "extract",
+        "--secret",
+        "s",
+        vault.to_str().unwrap(),
+        extracted.to_str().unwrap(),
+    ])?;
+    assert!(!extract.status.success(), "wiped partition must not extract");
+
+    Ok(())
+}
+
+#[test]
+fn proof_verifies_against_replica_root() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("secret.txt");
+    let vault = dir.path().join("vault.vhc");
+    let replica = dir.path().join("replica.vhc");
+    fs::write(&input, b"replicated payload")?;
+
+    let vault_arg = vault.to_str().unwrap();
+    let add = run(&["add", "--secret", "s", "--merkle", input.to_str().unwrap(), vault_arg])?;
+    assert!(add.status.success());
+    fs::copy(&vault, &replica)?;
+
+    let summary = String::from_utf8(run(&["proof", vault_arg])?.stdout)?;
+    let root = summary
+        .lines()
+        .find_map(|line| line.strip_prefix("Merkle root: "))
+        .expect("proof prints the root")
+        .to_string();
+    assert!(summary.contains("Corrupted blocks: none"));
+
+    let replica_arg = replica.to_str().unwrap();
+    let proof = run(&["proof", "--block", "7", "--root", &root, replica_arg])?;
+    assert!(
+        proof.status.success(),
+        "proof command failed: {}",
+        String::from_utf8_lossy(&proof.stderr)
+    );
+    assert!(String::from_utf8(proof.stdout)?.contains("Proof verifies: yes"));
+
+    // Corrupt the last block of the replica (the trailer follows the blocks)
+    let mut data = fs::read(&replica)?;
+    let trailer_len = u32::from_le_bytes(data[data.len() - 4..].try_into()?) as usize + 4;
+    let last = data.len() - trailer_len - 1;
+    data[last] ^= 0xFF;
+    fs::write(&replica, data)?;
+
+    let blocks = summary
+        .lines()
+        .find_map(|line| line.strip_prefix("Blocks: "))
+        .expect("proof prints the block count")
+        .parse::<usize>()?;
+    let check = String::from_utf8(run(&["proof", replica_arg])?.stdout)?;
+    assert!(check.contains(&format!("Corrupted blocks: {}", blocks - 1)));
+    let block = (blocks - 1).to_string();
+    let proof = run(&["proof", "--block", &block, "--root", &root, replica_arg])?;
+    assert!(String::from_utf8(proof.stdout)?.contains("Proof verifies: no"));
+
+    Ok(())
+}
+
+#[test]
+fn sign_and_verify_signature() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("secret.txt");
+    let vault = dir.path().join("vault.vhc");
+    let key = dir.path().join("signer");
+    let public = dir.path().join("signer.pub");
+    fs::write(&input, b"provenance")?;
+
+    let vault_arg = vault.to_str().unwrap();
+    assert!(run(&["add", "--secret", "s", input.to_str().unwrap(), vault_arg])?.status.success());
+    assert!(run(&["keygen", key.to_str().unwrap()])?.status.success());
+    assert!(public.exists());
+
+    let sign = run(&["sign", "--signing-key", key.to_str().unwrap(), "--embed", vault_arg])?;
+    assert!(
+        sign.status.success(),
+        "sign command failed: {}",
+        String::from_utf8_lossy(&sign.stderr)
+    );
+    let info = String::from_utf8(run(&["info", vault_arg])?.stdout)?;
+    assert!(info.contains("Signature: embedded"));
+
+    let verify = run(&["verify-signature", "--public-key", public.to_str().unwrap(), vault_arg])?;
+    assert!(verify.status.success());
+    assert!(String::from_utf8(verify.stdout)?.contains("Signature OK"));
+
+    // Any change to the block region breaks the signature
+    let mut data = fs::read(&vault)?;
+    let offset = data.len() / 2;
+    data[offset] ^= 1;
+    fs::write(&vault, data)?;
+    let verify = run(&["verify-signature", "--public-key", public.to_str().unwrap(), vault_arg])?;
+    assert!(!verify.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn add_with_config_preset() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("secret.txt");
+    let vault = dir.path().join("vault.vhc");
+    let config = dir.path().join("config.json");
+    fs::write(&input, b"standard shape")?;
+    fs::write(
+        &config,
+        r#"{"presets": {"team": {"dimension": 16, "block_size": 64, "hash": "blake3"}}}"#,
+    )?;
+
+    let add = run(&[
+        "--config",
+        config.to_str().unwrap(),
+        "add",
+        "--secret",
+        "s",
+        "--cube",
+        "team",
+        "--mac-bits",
+        "512",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(
+        add.status.success(),
+        "add command failed: {}",
+        String::from_utf8_lossy(&add.stderr)
+    );
+
+    let info = String::from_utf8(run(&["info", vault.to_str().unwrap()])?.stdout)?;
+    assert!(info.contains("Partitions: 16"));
+    assert!(info.contains("Block payload: 64 bytes"));
+    assert!(info.contains("Hash: Blake3"));
+    assert!(info.contains("MAC bits: 512"), "explicit flags override the preset");
+
+    let unknown = run(&[
+        "--config",
+        config.to_str().unwrap(),
+        "add",
+        "--secret",
+        "s",
+        "--cube",
+        "nope",
+        input.to_str().unwrap(),
+        dir.path().join("other.vhc").to_str().unwrap(),
+    ])?;
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8(unknown.stderr)?.contains("Unknown cube preset"));
+
+    Ok(())
+}
+
+#[test]
+fn config_default_seal() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("secret.txt");
+    let config = dir.path().join("config.json");
+    fs::write(&input, b"sealed by policy")?;
+    fs::write(
+        &config,
+        r#"{"seal": true, "presets": {"scratch": {"dimension": 8, "seal": false}}}"#,
+    )?;
+
+    let cases: [(&str, &[&str], bool); 4] = [
+        ("policy.vhc", &[], true),
+        ("opt-out.vhc", &["--no-seal"], false),
+        ("preset.vhc", &["--cube", "scratch"], false),
+        ("preset-override.vhc", &["--cube", "scratch", "--seal"], true),
+    ];
+    for (name, flags, sealed) in cases {
+        let vault = dir.path().join(name);
+        let mut args = vec!["--config", config.to_str().unwrap(), "add", "--secret", "s"];
+        if !flags.contains(&"--cube") {
+            args.extend(["--dimension", "8"]);
+        }
+        args.extend(flags);
+        args.extend([input.to_str().unwrap(), vault.to_str().unwrap()]);
+        let add = run(&args)?;
+        assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+        let check = run(&["check-sealed", vault.to_str().unwrap()])?;
+        assert_eq!(check.status.success(), sealed, "{}", name);
+    }
+    Ok(())
+}
+
+#[test]
+fn pbkdf2_kdf_roundtrip() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, b"fips payload")?;
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "passphrase",
+        "--kdf",
+        "pbkdf2:1000",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let info = String::from_utf8(run(&["info", vault.to_str().unwrap()])?.stdout)?;
+    assert!(info.contains("KDF: pbkdf2-hmac-sha256 (1000 iterations)"), "{}", info);
+
+    let extract = run(&[
+        "extract",
+        "--secret",
+        "passphrase",
+        vault.to_str().unwrap(),
+        output.to_str().unwrap(),
+    ])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(&output)?, b"fips payload");
+
+    let bad = run(&["add", "--secret", "s", "--kdf", "pbkdf2:0", input.to_str().unwrap()])?;
+    assert!(!bad.status.success());
+
+    // Secrets are used as given only on request
+    let raw = dir.path().join("raw.vhc");
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--kdf",
+        "none",
+        input.to_str().unwrap(),
+        raw.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let info = String::from_utf8(run(&["info", raw.to_str().unwrap()])?.stdout)?;
+    assert!(!info.contains("KDF:"), "{}", info);
+    Ok(())
+}
+
+#[test]
+fn keyfile_secret_roundtrip() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("output.txt");
+    let keyfile = dir.path().join("key.bin");
+    fs::write(&input, b"keyed by a file")?;
+    fs::write(&keyfile, [0u8, 1, 2, 0xfe, 0xff])?;
+    let keyfile = keyfile.to_str().unwrap();
+    let vault = vault.to_str().unwrap();
+    let output = output.to_str().unwrap();
+
+    let input = input.to_str().unwrap();
+    let add = run(&["add", "--keyfile", keyfile, "--secret", "pin", input, vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    // Both halves are needed
+    assert!(!run(&["extract", "--keyfile", keyfile, vault, output])?.status.success());
+    assert!(!run(&["extract", "--secret", "pin", vault, output])?.status.success());
+
+    let extract = run(&["extract", "--keyfile", keyfile, "--secret", "pin", vault, output])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(output)?, b"keyed by a file");
+    Ok(())
+}
+
+#[test]
+fn every_secret_option_takes_a_keyfile() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.bin");
+    let output = dir.path().join("output.txt");
+    let (keyfile, outer, chaff) = (
+        dir.path().join("key.bin"),
+        dir.path().join("outer.bin"),
+        dir.path().join("chaff.bin"),
+    );
+    fs::write(&input, b"no secret on argv")?;
+    fs::write(&keyfile, [7u8; 32])?;
+    fs::write(&outer, [8u8; 32])?;
+    fs::write(&chaff, [9u8; 32])?;
+    let (input, vault, output) = (
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+        output.to_str().unwrap(),
+    );
+    let (keyfile, outer, chaff) = (
+        keyfile.to_str().unwrap(),
+        outer.to_str().unwrap(),
+        chaff.to_str().unwrap(),
+    );
+
+    let add = run(&[
+        "add",
+        "--keyfile",
+        keyfile,
+        "--kdf",
+        "none",
+        "--dimension",
+        "8",
+        "--magicless",
+        "--passphrase-keyfile",
+        outer,
+        "--always-full",
+        "--chaff-keyfile",
+        chaff,
+        input,
+        vault,
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let extract = |passphrase: &[&str]| {
+        let args = [&["extract", "--keyfile", keyfile][..], passphrase, &[vault, output]];
+        run(&args.concat())
+    };
+    let extracted = extract(&["--passphrase-keyfile", outer])?;
+    assert!(extracted.status.success(), "{}", String::from_utf8_lossy(&extracted.stderr));
+    assert_eq!(fs::read(output)?, b"no secret on argv");
+    // The key file is hashed, not used as the passphrase itself
+    let raw = String::from_utf8(vec![8u8; 32])?;
+    assert!(!extract(&["--passphrase", &raw])?.status.success());
+    assert!(!extract(&["--passphrase", "x", "--passphrase-keyfile", outer])?.status.success());
+
+    let daemon = ["daemon", "--rotate-chaff", "24h", "--rotations", "1"];
+    let rotated = run(&[&daemon[..], &["--chaff-keyfile", chaff, vault]].concat())?;
+    assert!(rotated.status.success(), "{}", String::from_utf8_lossy(&rotated.stderr));
+    assert!(!run(&[&daemon[..], &["--chaff-keyfile", outer, vault]].concat())?.status.success());
+
+    // A plain copy of the data, checked and compared by key file
+    let plain = dir.path().join("plain.vhc");
+    let plain = plain.to_str().unwrap();
+    let add = run(&["add", "--keyfile", keyfile, "--kdf", "none", input, plain])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let verify = run(&["verify", "--keyfile", keyfile, plain])?;
+    assert!(verify.status.success(), "{}", String::from_utf8_lossy(&verify.stderr));
+    assert!(String::from_utf8(verify.stdout)?.contains("Partition OK"));
+    let diff = run(&["diff", "--keyfile", keyfile, plain, plain])?;
+    assert!(diff.status.success(), "{}", String::from_utf8_lossy(&diff.stderr));
+    Ok(())
+}
+
+#[test]
+fn pipeline_order_roundtrip() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, b"aont before shuffle")?;
+
+    let order = "compress,segment,fragment,shuffle,aont,whiten,sequence,mac";
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--shuffle",
+        "fragment",
+        "--pipeline-order",
+        order,
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let info = String::from_utf8(run(&["info", vault.to_str().unwrap()])?.stdout)?;
+    assert!(info.contains(&format!("Pipeline order: {}", order)), "{}", info);
+
+    let extract = run(&[
+        "extract",
+        "--secret",
+        "s",
+        vault.to_str().unwrap(),
+        output.to_str().unwrap(),
+    ])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(&output)?, b"aont before shuffle");
+
+    let moved = "compress,segment,fragment,whiten,aont,sequence,shuffle,mac";
+    let bad = run(&["add", "--secret", "s", "--pipeline-order", moved, input.to_str().unwrap()])?;
+    assert!(!bad.status.success());
+    Ok(())
+}
+
+#[test]
+fn check_sealed_reports_fill_state() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"payload")?;
+    let vault_path = vault.to_str().unwrap();
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--dimension",
+        "8",
+        input.to_str().unwrap(),
+        vault_path,
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let open = run(&["check-sealed", vault_path])?;
+    assert!(!open.status.success());
+    assert!(String::from_utf8_lossy(&open.stderr).contains("not sealed: 8 / 64 blocks"));
+
+    assert!(run(&["seal", vault_path])?.status.success());
+    let sealed = run(&["check-sealed", vault_path])?;
+    assert!(sealed.status.success(), "{}", String::from_utf8_lossy(&sealed.stderr));
+    assert!(String::from_utf8(sealed.stdout)?.contains("is sealed: 64 / 64 blocks"));
+    Ok(())
+}
+
+#[test]
+fn verify_reports_generation_rollback() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"payload")?;
+    let vault_path = vault.to_str().unwrap();
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--dimension",
+        "8",
+        input.to_str().unwrap(),
+        vault_path,
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let old = dir.path().join("old.vhc");
+    fs::copy(&vault, &old)?;
+    assert!(run(&["seal", vault_path])?.status.success());
+
+    let fresh = run(&["verify", "--secret", "s", vault_path])?;
+    assert!(fresh.status.success(), "{}", String::from_utf8_lossy(&fresh.stderr));
+    let report = String::from_utf8(fresh.stdout)?;
+    assert!(report.contains("Header generation: 2"), "{}", report);
+    assert!(report.contains("Partition generation: 1 (8 blocks)"), "{}", report);
+    assert!(report.contains("Authenticated blocks: 8 of 64"), "{}", report);
+    assert!(report.contains("Data: 7 bytes decoded"), "{}", report);
+    assert!(report.contains("Partition OK"), "{}", report);
+
+    let stale = run(&["verify", "--min-generation", "2", old.to_str().unwrap()])?;
+    assert!(!stale.status.success());
+    assert!(String::from_utf8(stale.stdout)?.contains("Rollback: header generation 1 is older"));
+    assert!(String::from_utf8_lossy(&stale.stderr).contains("looks rolled back"));
+    Ok(())
+}
+
+#[test]
+fn locked_container_refuses_writes() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"final")?;
+    let vault_path = vault.to_str().unwrap();
+
+    assert!(run(&["add", "--secret", "a", input.to_str().unwrap(), vault_path])?.status.success());
+    let lock = run(&["lock", vault_path])?;
+    assert!(lock.status.success(), "{}", String::from_utf8_lossy(&lock.stderr));
+    assert!(String::from_utf8(run(&["info", vault_path])?.stdout)?.contains("Locked: yes"));
+
+    for args in [
+        vec!["add", "--secret", "b", input.to_str().unwrap(), vault_path],
+        vec!["seal", vault_path],
+        vec!["wipe", "--secret", "a", vault_path],
+    ] {
+        let refused = run(&args)?;
+        assert!(!refused.status.success(), "{:?} succeeded", args);
+        assert!(String::from_utf8_lossy(&refused.stderr).contains("locked"));
+    }
+    Ok(())
+}
+
+#[test]
+fn append_only_container_keeps_a_journal() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("evidence.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"exhibit A")?;
+
+    for secret in ["first", "second"] {
+        let add = run(&[
+            "add",
+            "--secret",
+            secret,
+            "--append-only",
+            input.to_str().unwrap(),
+            vault.to_str().unwrap(),
+        ])?;
+        assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    }
+
+    let info = String::from_utf8(run(&["info", vault.to_str().unwrap()])?.stdout)?;
+    assert!(info.contains("Reshuffle: never"), "{}", info);
+    assert!(info.contains("Status: verified (2 generations"), "{}", info);
+    assert!(info.contains("Generation 2: blocks 32..64"), "{}", info);
+
+    let wipe = run(&["wipe", "--secret", "first", vault.to_str().unwrap()])?;
+    assert!(!wipe.status.success());
+    assert!(String::from_utf8_lossy(&wipe.stderr).contains("append-only"));
+
+    let reshuffle = run(&[
+        "add",
+        "--secret",
+        "third",
+        "--reshuffle",
+        "always",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(!reshuffle.status.success());
+    Ok(())
+}
+
+#[test]
+fn always_full_container_stays_full() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("note.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"never half empty")?;
+
+    for secret in ["first", "second"] {
+        let add = run(&[
+            "add",
+            "--secret",
+            secret,
+            "--dimension",
+            "8",
+            "--always-full",
+            "--chaff-secret",
+            "chaff",
+            input.to_str().unwrap(),
+            vault.to_str().unwrap(),
+        ])?;
+        assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+        let check = run(&["check-sealed", vault.to_str().unwrap()])?;
+        assert!(check.status.success(), "{}", String::from_utf8_lossy(&check.stderr));
+    }
+
+    let info = String::from_utf8(run(&["info", vault.to_str().unwrap()])?.stdout)?;
+    assert!(info.contains("Always full: yes"), "{}", info);
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "third",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(!add.status.success());
+    assert!(String::from_utf8_lossy(&add.stderr).contains("chaff secret"));
+    Ok(())
+}
+
+#[test]
+fn daemon_rotates_keyed_chaff() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("note.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"quiet week")?;
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--dimension",
+        "8",
+        "--always-full",
+        "--chaff-secret",
+        "chaff",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let before = fs::read(&vault)?;
+
+    let daemon = |chaff_secret: &str| {
+        run(&[
+            "daemon",
+            "--rotate-chaff",
+            "24h",
+            "--chaff-secret",
+            chaff_secret,
+            "--rotations",
+            "1",
+            vault.to_str().unwrap(),
+        ])
+    };
+    let rotated = daemon("chaff")?;
+    assert!(rotated.status.success(), "{}", String::from_utf8_lossy(&rotated.stderr));
+    assert!(String::from_utf8(rotated.stdout)?.contains("replaced 56 chaff blocks"));
+    let after = fs::read(&vault)?;
+    assert_eq!(after.len(), before.len());
+    assert_ne!(after, before);
+
+    assert!(!daemon("wrong")?.status.success());
+    Ok(())
+}
+
+#[test]
+fn doctor_reports_problems() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("note.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"routine checkup")?;
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--dimension",
+        "8",
+        "--seal",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let healthy = run(&["doctor", vault.to_str().unwrap()])?;
+    let stdout = String::from_utf8(healthy.stdout)?;
+    assert!(healthy.status.success(), "{}", stdout);
+    assert!(stdout.contains("[ok] entropy"), "{}", stdout);
+    assert!(stdout.contains("no errors"), "{}", stdout);
+
+    // Chop off part of the last block and the trailer
+    let bytes = fs::read(&vault)?;
+    fs::write(&vault, &bytes[..bytes.len() - 7])?;
+    let truncated = run(&["doctor", vault.to_str().unwrap()])?;
+    assert!(!truncated.status.success());
+    assert!(String::from_utf8(truncated.stdout)?.contains("[error]"));
+    Ok(())
+}
+
+#[test]
+fn estimate_extract_needs_no_secret() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("note.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"how long to get this back?")?;
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--dimension",
+        "8",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let estimate = run(&["estimate-extract", "--sample", "8", vault.to_str().unwrap()])?;
+    let stdout = String::from_utf8(estimate.stdout)?;
+    assert!(estimate.status.success(), "{}", stdout);
+    assert!(stdout.contains("Blocks: 8 of 64"), "{}", stdout);
+    assert!(stdout.contains("over 8 blocks"), "{}", stdout);
+    assert!(stdout.contains("Estimated extraction: "), "{}", stdout);
+    Ok(())
+}
+
+#[test]
+fn diff_reports_partition_changes() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("note.txt");
+    let before = dir.path().join("before.vhc");
+    let after = dir.path().join("after.vhc");
+    fs::write(&input, b"reviewed contents")?;
+    let add = |secret: &str, vault: &Path| {
+        let args = ["add", "--secret", secret, "--kdf", "none", "--dimension", "8"];
+        run(&[&args[..], &[input.to_str().unwrap(), vault.to_str().unwrap()]].concat())
+    };
+    assert!(add("a", &before)?.status.success());
+    fs::copy(&before, &after)?;
+    let diff = || {
+        let args = ["diff", "--secret", "a", "--secret", "b"];
+        run(&[&args[..], &[before.to_str().unwrap(), after.to_str().unwrap()]].concat())
+    };
+
+    let same = diff()?;
+    assert!(same.status.success(), "{}", String::from_utf8_lossy(&same.stderr));
+    assert!(String::from_utf8(same.stdout)?.contains("No differences"));
+
+    assert!(add("b", &after)?.status.success());
+    let changed = diff()?;
+    assert_eq!(changed.status.code(), Some(1));
+    let stdout = String::from_utf8(changed.stdout)?;
+    assert!(stdout.contains("Blocks: 8 -> 16 (+8)"), "{}", stdout);
+    assert!(stdout.contains("Partition #1: unchanged"), "{}", stdout);
+    assert!(stdout.contains("Partition #2: added (17 bytes)"), "{}", stdout);
+    Ok(())
+}
+
+#[test]
+fn cat_writes_byte_range() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("log.txt");
+    let vault = dir.path().join("vault.vhc");
+    let data: Vec<u8> = (0..8192u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(&input, &data)?;
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let cat = |args: &[&str]| {
+        let mut full = vec!["cat", "--secret", "s"];
+        full.extend_from_slice(args);
+        full.push(vault.to_str().unwrap());
+        run(&full)
+    };
+    let range = cat(&["--offset", "4096", "--length", "1024"])?;
+    assert!(range.status.success(), "{}", String::from_utf8_lossy(&range.stderr));
+    assert_eq!(range.stdout, &data[4096..5120]);
+
+    let tail = cat(&["--offset", "8000"])?;
+    assert_eq!(tail.stdout, &data[8000..]);
+    assert_eq!(cat(&[])?.stdout, data);
+
+    assert!(!cat(&["--offset", "9000"])?.status.success());
+    Ok(())
+}
+
+#[test]
+fn update_rewrites_chunked_partition() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("notes.txt");
+    let output = dir.path().join("notes.out");
+    let vault = dir.path().join("vault.vhc");
+    let mut data: Vec<u8> = (0..6000u32).map(|i| (i * 31 % 253) as u8).collect();
+    fs::write(&input, &data)?;
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        "--chunked",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    data[5000] ^= 0xFF;
+    fs::write(&input, &data)?;
+    let update = run(&[
+        "update",
+        "--secret",
+        "s",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(update.status.success(), "{}", String::from_utf8_lossy(&update.stderr));
+    assert!(String::from_utf8(update.stdout)?.starts_with("Rewrote "));
+
+    let extract = run(&[
+        "extract",
+        "--secret",
+        "s",
+        vault.to_str().unwrap(),
+        output.to_str().unwrap(),
+    ])?;
+    assert!(extract.status.success());
+    assert_eq!(fs::read(&output)?, data);
+
+    let info = run(&["info", vault.to_str().unwrap()])?;
+    assert!(String::from_utf8(info.stdout)?.contains("Chunking: content-defined"));
+    Ok(())
+}
+
+#[test]
+fn secret_helper_supplies_missing_secret() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("note.txt");
+    let output = dir.path().join("note.out");
+    let vault = dir.path().join("vault.vhc");
+    let stored = dir.path().join("stored");
+    fs::write(&input, b"from a helper")?;
+
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let extract = |helper: &str| {
+        run(&[
+            "extract",
+            "--secret-helper",
+            helper,
+            vault.to_str().unwrap(),
+            output.to_str().unwrap(),
+        ])
+    };
+    let helper = format!(
+        "!f() {{ if [ \"$1\" = get ]; then echo secret=s; else cat > {}; fi; }}; f",
+        stored.display()
+    );
+    let helped = extract(&helper)?;
+    assert!(helped.status.success(), "{}", String::from_utf8_lossy(&helped.stderr));
+    assert_eq!(fs::read(&output)?, b"from a helper");
+    assert!(fs::read_to_string(&stored)?.contains("operation=extract\nsecret=s\n"));
+
+    // A helper without a secret, or no helper at all, leaves the secret missing
+    let empty = extract("!true")?;
+    assert!(String::from_utf8(empty.stderr)?.contains("Secret required"));
+    let config = dir.path().join("config.json");
+    fs::write(&config, r#"{"secret_helper": "!echo secret=s; true"}"#)?;
+    let configured = run(&[
+        "--config",
+        config.to_str().unwrap(),
+        "cat",
+        vault.to_str().unwrap(),
+    ])?;
+    assert_eq!(configured.stdout, b"from a helper");
+    let missing = run(&["cat", vault.to_str().unwrap()])?;
+    assert!(!missing.status.success());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn created_files_take_the_mode() -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir()?;
+    let input = dir.path().join("data.bin");
+    fs::write(&input, b"not for other users")?;
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+    let mode = |name: &str| -> Result<u32, Box<dyn Error>> {
+        Ok(fs::metadata(dir.path().join(name))?.permissions().mode() & 0o777)
+    };
+    let input = input.to_str().unwrap();
+
+    let add = run(&["add", "--secret", "s", "--kdf", "none", input, &path("vault.vhc")])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let extract = run(&["extract", "--secret", "s", &path("vault.vhc"), &path("out.bin")])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    // Extracted data is owner-only by default
+    assert_eq!(mode("out.bin")?, 0o600);
+
+    let extract = run(&[
+        "--mode", "0400", "extract", "--secret", "s", &path("vault.vhc"), &path("ro.bin"),
+    ])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(mode("ro.bin")?, 0o400);
+    let add = run(&[
+        "add", "--mode", "0600", "--secret", "s", "--kdf", "none", input, &path("own.vhc"),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    assert_eq!(mode("own.vhc")?, 0o600);
+
+    let bad = run(&["--mode", "rw", "info", &path("vault.vhc")])?;
+    assert!(!bad.status.success());
+    Ok(())
+}
+
+#[test]
+fn opening_a_honeypot_runs_the_alert() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("decoy.txt");
+    let vault = dir.path().join("vault.vhc");
+    let alert = dir.path().join("alert.log");
+    fs::write(&input, b"aws_secret_access_key=canary")?;
+    let vault_arg = vault.to_str().unwrap();
+    let alerting = |args: &[&str]| {
+        hypercube_command()
+            .env(
+                "HYPERCUBE_HONEYPOT_ALERT",
+                format!("echo \"$HYPERCUBE_HONEYPOT_LABEL\" >> {}", alert.display()),
+            )
+            .args(args)
+            .output()
+    };
+
+    let add = alerting(&[
+        "add", "--secret", "guessable", "--kdf", "none", "--honeypot", "backup-keys", "--verify",
+        input.to_str().unwrap(), vault_arg,
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let add = run(&[
+        "add", "--secret", "real", "--kdf", "none", "--seal", input.to_str().unwrap(), vault_arg,
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    // Planting it, and opening other partitions, raises nothing
+    let cat = alerting(&["cat", "--secret", "real", vault_arg])?;
+    assert!(cat.status.success());
+    assert!(!alert.exists());
+
+    // The decoy reads back as planted, and the alert runs alongside
+    let cat = alerting(&["cat", "--secret", "guessable", vault_arg])?;
+    assert!(cat.status.success(), "{}", String::from_utf8_lossy(&cat.stderr));
+    assert_eq!(cat.stdout, b"aws_secret_access_key=canary");
+    assert!(cat.stderr.is_empty());
+    // The alert is not waited for
+    for _ in 0..100 {
+        if alert.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert_eq!(fs::read_to_string(&alert)?.trim(), "backup-keys");
+
+    // A range is read as from any partition, and alerts as well
+    let range = alerting(&[
+        "cat", "--secret", "guessable", "--offset", "4", "--length", "6", vault_arg,
+    ])?;
+    assert_eq!(range.stdout, b"secret");
+
+    let chunked = run(&[
+        "add", "--secret", "s", "--chunked", "--honeypot", "x", input.to_str().unwrap(),
+        dir.path().join("chunked.vhc").to_str().unwrap(),
+    ])?;
+    assert!(!chunked.status.success());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn rewrites_rename_a_copy_unless_atomic_is_off() -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempdir()?;
+    let input = dir.path().join("data.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"kept whole")?;
+    let (input, vault_arg) = (input.to_str().unwrap(), vault.to_str().unwrap());
+    let add = |secret: &str, atomic: &str| {
+        hypercube_command()
+            .env("HYPERCUBE_ATOMIC", atomic)
+            .args(["add", "--secret", secret, "--kdf", "none", "--reshuffle", "never"])
+            .args([input, vault_arg])
+            .output()
+    };
+    let add_ok = |secret: &str, atomic: &str| -> Result<u64, Box<dyn Error>> {
+        let output = add(secret, atomic)?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        Ok(fs::metadata(&vault)?.ino())
+    };
+
+    let created = add_ok("first", "1")?;
+    // A synced copy replaces the file, and no temp file is left behind
+    let renamed = add_ok("second", "1")?;
+    assert_ne!(renamed, created);
+    let names: Vec<_> = fs::read_dir(dir.path())?.map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(names.len(), 2, "{:?}", names);
+    // Off, the new blocks are written into the file itself
+    let in_place = add_ok("third", "0")?;
+    assert_eq!(in_place, renamed);
+
+    for secret in ["first", "second", "third"] {
+        let cat = run(&["cat", "--secret", secret, vault_arg])?;
+        assert_eq!(cat.stdout, b"kept whole");
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn unknown_subcommand_runs_plugin() -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir()?;
+    let plugin = dir.path().join("hypercube-hello");
+    fs::write(
+        &plugin,
+        "#!/bin/sh\necho \"args=$*\"\necho \"config=$HYPERCUBE_CONFIG\"\n\
+         echo \"helper=$HYPERCUBE_SECRET_HELPER\"\necho \"threads=$HYPERCUBE_THREADS\"\n\
+         echo \"mode=$HYPERCUBE_MODE\"\n\
+         [ -x \"$HYPERCUBE\" ] && exit 3\n",
+    )?;
+    fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755))?;
+    let path = std::env::join_paths(
+        std::iter::once(dir.path().to_path_buf())
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default())),
+    )?;
+
+    let output = hypercube_command()
+        .env("PATH", &path)
+        .args(["--config", "conf.json", "--secret-helper", "!true", "--threads", "2"])
+        .args(["--mode", "640", "hello", "a", "--b"])
+        .output()?;
+    // The plugin's exit code is passed through
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "args=a --b\nconfig=conf.json\nhelper=!true\nthreads=2\nmode=0640\n"
+    );
+
+    let missing = hypercube_command().env("PATH", &path).arg("nope").output()?;
+    assert!(!missing.status.success());
+    assert!(String::from_utf8(missing.stderr)?.contains("No such command: nope"));
+    Ok(())
+}
+
+#[test]
+fn kat_verify_checks_vectors() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("note.txt");
+    let vault = dir.path().join("vault.vhc");
+    let vectors = dir.path().join("vectors.json");
+    fs::write(&input, b"interop")?;
+    let add = run(&[
+        "add",
+        "--secret",
+        "s",
+        input.to_str().unwrap(),
+        vault.to_str().unwrap(),
+    ])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let write_vectors = |plaintext: &[u8]| {
+        fs::write(
+            &vectors,
+            format!(
+                r#"{{"format": "hypercube-kat", "version": 1, "vectors": [
+                    {{"name": "cli", "container_file": "vault.vhc",
+                      "partitions": [{{"secret": "s", "plaintext": "{}"}}]}}]}}"#,
+                hex::encode(plaintext)
+            ),
+        )
+    };
+    let verify = || run(&["kat", "verify", "--input", vectors.to_str().unwrap()]);
+    write_vectors(b"interop")?;
+    let passed = verify()?;
+    assert!(passed.status.success(), "{}", String::from_utf8_lossy(&passed.stderr));
+    assert_eq!(String::from_utf8(passed.stdout)?, "[pass] cli\n1 vectors passed\n");
+
+    write_vectors(b"changed")?;
+    let failed = verify()?;
+    assert!(!failed.status.success());
+    assert!(String::from_utf8(failed.stdout)?.starts_with("[fail] cli: partition 1:"));
+    assert!(String::from_utf8(failed.stderr)?.contains("1 of 1 vectors failed"));
+    Ok(())
+}
+
+#[test]
+fn rekey_roundtrip() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, b"same data, new key")?;
+    let vault = vault.to_str().unwrap();
+    let output = output.to_str().unwrap();
+
+    let add = run(&["add", "--secret", "old", input.to_str().unwrap(), vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let rekey = run(&["rekey", "--secret", "old", "--new-secret", "new", vault])?;
+    assert!(rekey.status.success(), "{}", String::from_utf8_lossy(&rekey.stderr));
+    assert!(String::from_utf8(rekey.stdout)?.starts_with("Re-keyed "));
+
+    assert!(!run(&["extract", "--secret", "old", vault, output])?.status.success());
+    let extract = run(&["extract", "--secret", "new", vault, output])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(output)?, b"same data, new key");
+    Ok(())
+}
+
+#[test]
+fn replicate_mirror() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let mirror = dir.path().join("mirror.vhc");
+    fs::write(&input, b"mirrored")?;
+    let vault = vault.to_str().unwrap();
+    let mirror_path = mirror.to_str().unwrap();
+
+    let add = run(&["add", "--secret", "s", "--merkle", input.to_str().unwrap(), vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    let first = run(&["replicate", vault, mirror_path])?;
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    assert!(String::from_utf8(first.stdout)?.starts_with("Copied all "));
+    assert_eq!(fs::read(&mirror)?, fs::read(vault)?);
+
+    let second = run(&["replicate", vault, mirror_path])?;
+    assert!(second.status.success());
+    assert!(String::from_utf8(second.stdout)?.starts_with("Copied 0 of "));
+    Ok(())
+}
+
+#[test]
+fn kms_key_needs_backend() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    fs::write(&input, b"kms")?;
+    let input = input.to_str().unwrap();
+    let vault = vault.to_str().unwrap();
+
+    let unknown = run(&["add", "--kms-key", "not-a-key", input, vault])?;
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8(unknown.stderr)?.contains("Unrecognised KMS key"));
+
+    // Without the kms-aws feature the key is refused before anything runs
+    let arn = "arn:aws:kms:eu-west-1:123456789012:key/k";
+    if cfg!(not(feature = "kms-aws")) {
+        let aws = run(&["add", "--kms-key", arn, input, vault])?;
+        assert!(!aws.status.success());
+        assert!(String::from_utf8(aws.stderr)?.contains("kms-aws"));
+    }
+
+    let conflict = run(&["add", "--kms-key", arn, "--keyfile", input, input, vault])?;
+    assert!(!conflict.status.success());
+    Ok(())
+}
+
+#[test]
+fn share_and_combine_secret() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, b"two of three")?;
+    let vault = vault.to_str().unwrap();
+    let output = output.to_str().unwrap();
+
+    let add = run(&["add", "--secret", "custodians", input.to_str().unwrap(), vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+
+    // The secret comes in on stdin, not the command line
+    let mut share = hypercube_command()
+        .args(["share", "--threshold", "2", "--shares", "3"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    share.stdin.take().unwrap().write_all(b"custodians\n")?;
+    let share = share.wait_with_output()?;
+    assert!(share.status.success(), "{}", String::from_utf8_lossy(&share.stderr));
+    let shares: Vec<String> = String::from_utf8(share.stdout)?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    assert_eq!(shares.len(), 3);
+
+    assert!(!run(&["combine", &shares[0]])?.status.success());
+    let combine = run(&["combine", &shares[2], &shares[0]])?;
+    assert!(combine.status.success(), "{}", String::from_utf8_lossy(&combine.stderr));
+    let secret = String::from_utf8(combine.stdout)?;
+    assert_eq!(secret.trim_end(), "custodians");
+
+    let extract = run(&["extract", "--secret", secret.trim_end(), vault, output])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(output)?, b"two of three");
+    Ok(())
+}
+
+#[test]
+fn add_with_erasure_code() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, b"survives a few lost blocks")?;
+    let input = input.to_str().unwrap();
+    let vault = vault.to_str().unwrap();
+    let output = output.to_str().unwrap();
+
+    let bad = run(&["add", "--secret", "s", "--erasure", "6", input, vault])?;
+    assert!(!bad.status.success());
+
+    let add = run(&["add", "--secret", "s", "--erasure", "6+2", input, vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let info = String::from_utf8(run(&["info", vault])?.stdout)?;
+    assert!(info.contains("Erasure code: Reed–Solomon 6+2"), "{}", info);
+
+    let extract = run(&["extract", "--secret", "s", vault, output])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(output)?, b"survives a few lost blocks");
+    Ok(())
+}
+
+#[test]
+fn threads_flag_bounds_workers() -> Result<(), Box<dyn Error>> {
+    let dir = tempdir()?;
+    let input = dir.path().join("input.txt");
+    let vault = dir.path().join("vault.vhc");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, b"same result on any number of threads")?;
+    let vault = vault.to_str().unwrap();
+    let output = output.to_str().unwrap();
+
+    let input = input.to_str().unwrap();
+    let add = run(&["--threads", "single", "add", "--secret", "s", input, vault])?;
+    assert!(add.status.success(), "{}", String::from_utf8_lossy(&add.stderr));
+    let extract = run(&["extract", "--threads", "3", "--secret", "s", vault, output])?;
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read(output)?, b"same result on any number of threads");
+
+    assert!(!run(&["--threads", "0", "info", vault])?.status.success());
+    Ok(())
+}
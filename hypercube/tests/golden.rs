@@ -0,0 +1,406 @@
+//! Golden-file compatibility tests.
+//!
+//! `tests/golden/<target>/` holds containers written by builds for different
+//! targets (word size and byte order). Every build must read every set the same
+//! way: identical header fields, identical binary layout and identical payloads.
+//!
+//! To add a target's set, or the files of a new golden to every set, run on each
+//! target:
+//!
+//! ```text
+//! cargo test -p hypercube --test golden -- --ignored write_golden_set
+//! ```
+//!
+//! Files already recorded are kept. The big-endian set was written under Miri, which
+//! cannot map files, sync directories or run zstd: writes go in place, and the
+//! `s390x` target needs a C compiler only for the zstd build script, so any host one
+//! will do:
+//!
+//! ```text
+//! HYPERCUBE_ATOMIC=0 CC_s390x_unknown_linux_gnu=gcc MIRIFLAGS=-Zmiri-disable-isolation \
+//!     cargo +nightly miri test --target s390x-unknown-linux-gnu \
+//!     -p hypercube --test golden -- --ignored write_golden_set
+//! ```
+
+use hypercube::cli::add::{add_partition, AddOptions};
+use hypercube::cli::extract::{extract_from_vhc, ExtractOptions};
+use hypercube::cli::proof::corrupted_blocks;
+use hypercube::domains::{BINARY_HEADER_VERSION, LATEST_VERSION};
+use hypercube::header::{Aont, Compression, HashAlgorithm, ReshufflePolicy, Shuffle};
+use hypercube::kdf::{Kdf, KdfParams};
+use hypercube::vhc::{
+    get_block_count, read_vhc_header, read_vhc_layout, read_vhc_trailer, vhc_data_offset, VhcLayout,
+};
+use hypercube::{write_vhc_file, VhcFile, VhcHeader};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+/// Expected contents of one golden container
+struct Golden {
+    name: &'static str,
+    version: u32,
+    block_size: usize,
+    mac_bits: usize,
+    aont: Aont,
+    hash: HashAlgorithm,
+    compression: Compression,
+    shuffle: Shuffle,
+    reshuffle: ReshufflePolicy,
+    merkle: bool,
+    hints: usize,
+    kdf: Option<Kdf>,
+    passphrase: Option<&'static str>,
+    secrets: &'static [&'static str],
+    blocks: usize,
+    uuid: bool,
+    /// Writes counted in the header; None where builds from before the counter
+    /// recorded the file
+    generation: Option<u32>,
+}
+
+const GOLDEN: &[Golden] = &[
+    // Legacy layout: no trailer, expanded 512-bit tags
+    Golden {
+        name: "v1-mac512",
+        version: 1,
+        block_size: 64,
+        mac_bits: 512,
+        aont: Aont::Rivest,
+        hash: HashAlgorithm::Sha3,
+        compression: Compression::Zstd,
+        shuffle: Shuffle::Block,
+        reshuffle: ReshufflePolicy::Always,
+        merkle: false,
+        hints: 0,
+        kdf: None,
+        passphrase: None,
+        secrets: &["alpha", "beta"],
+        blocks: 16,
+        uuid: false,
+        generation: None,
+    },
+    // Legacy layout with a Merkle trailer
+    Golden {
+        name: "v2-merkle",
+        version: 2,
+        block_size: 48,
+        mac_bits: 256,
+        aont: Aont::Oaep,
+        hash: HashAlgorithm::Blake3,
+        compression: Compression::Lz4,
+        shuffle: Shuffle::Block,
+        reshuffle: ReshufflePolicy::Always,
+        merkle: true,
+        hints: 0,
+        kdf: None,
+        passphrase: None,
+        secrets: &["alpha"],
+        blocks: 8,
+        uuid: false,
+        generation: None,
+    },
+    // Indexed pipeline stages: Rivest AONT, fragment shuffle, hints, native tags
+    Golden {
+        name: "v3-fragment-kdf",
+        version: 3,
+        block_size: 32,
+        mac_bits: 512,
+        aont: Aont::Rivest,
+        hash: HashAlgorithm::Sha3,
+        compression: Compression::Brotli,
+        shuffle: Shuffle::Fragment,
+        reshuffle: ReshufflePolicy::Always,
+        merkle: false,
+        hints: 2,
+        kdf: Some(Kdf::Pbkdf2 { iterations: 1000 }),
+        passphrase: None,
+        secrets: &["alpha", "beta"],
+        blocks: 16,
+        uuid: false,
+        generation: None,
+    },
+    // Magicless layout keyed to a passphrase
+    Golden {
+        name: "v3-keyed",
+        version: 3,
+        block_size: 40,
+        mac_bits: 128,
+        aont: Aont::Rivest,
+        hash: HashAlgorithm::Sha256,
+        compression: Compression::None,
+        shuffle: Shuffle::Block,
+        reshuffle: ReshufflePolicy::Periodic(2),
+        merkle: false,
+        hints: 0,
+        kdf: Some(Kdf::Pbkdf2 { iterations: 1000 }),
+        passphrase: Some("container"),
+        secrets: &["alpha", "beta"],
+        blocks: 16,
+        uuid: false,
+        generation: None,
+    },
+    // JSON header with the container id and write counter
+    Golden {
+        name: "v4-uuid",
+        version: 4,
+        block_size: 48,
+        mac_bits: 256,
+        aont: Aont::Oaep,
+        hash: HashAlgorithm::Blake3,
+        compression: Compression::Lz4,
+        shuffle: Shuffle::Block,
+        reshuffle: ReshufflePolicy::Always,
+        merkle: true,
+        hints: 0,
+        kdf: None,
+        passphrase: None,
+        secrets: &["alpha", "beta"],
+        blocks: 16,
+        uuid: true,
+        generation: Some(2),
+    },
+    // Binary header, with hints, a KDF and a reshuffle period in its fixed fields
+    Golden {
+        name: "v5-binary",
+        version: 5,
+        block_size: 32,
+        mac_bits: 256,
+        aont: Aont::Rivest,
+        hash: HashAlgorithm::Sha3,
+        compression: Compression::Brotli,
+        shuffle: Shuffle::Fragment,
+        reshuffle: ReshufflePolicy::Periodic(2),
+        merkle: true,
+        hints: 2,
+        kdf: Some(Kdf::Pbkdf2 { iterations: 1000 }),
+        passphrase: None,
+        secrets: &["alpha", "beta"],
+        blocks: 16,
+        uuid: true,
+        generation: Some(4),
+    },
+];
+
+const DIMENSION: usize = 8;
+
+fn payload(secret: &str) -> Vec<u8> {
+    format!("{} golden payload\n", secret)
+        .repeat(12)
+        .into_bytes()
+}
+
+fn golden_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Directory name of this build's set, e.g. `x86_64-le`
+fn target_set() -> String {
+    let endian = if cfg!(target_endian = "little") {
+        "le"
+    } else {
+        "be"
+    };
+    format!("{}-{}", std::env::consts::ARCH, endian)
+}
+
+/// Whether a build can still write this golden; the others are only ever read
+fn writable(golden: &Golden) -> bool {
+    // Expanded 512-bit tags are no longer written
+    golden.version >= 3 || golden.mac_bits <= 256
+}
+
+/// An empty container of an older version, as the builds of that version created them
+/// Older versions are only ever extended now, never created
+fn legacy_container(golden: &Golden) -> Result<VhcFile, Box<dyn Error>> {
+    let mut header = VhcHeader::new(
+        DIMENSION,
+        DIMENSION,
+        DIMENSION,
+        golden.block_size,
+        golden.mac_bits,
+    )?;
+    header.version = golden.version;
+    if !golden.uuid {
+        header.uuid = None;
+    }
+    header.aont = golden.aont;
+    header.hash = golden.hash;
+    header.compression = golden.compression;
+    header.merkle = golden.merkle;
+    header.shuffle = golden.shuffle;
+    header.reshuffle = golden.reshuffle;
+    header.kdf = golden.kdf.map(KdfParams::new);
+    let mut vhc = VhcFile::new(header);
+    if let Some(passphrase) = golden.passphrase {
+        vhc.layout = VhcLayout::keyed(passphrase, &vhc.header)?;
+    }
+    Ok(vhc)
+}
+
+fn add_options(golden: &Golden, secret: &str) -> AddOptions {
+    AddOptions {
+        secret: secret.into(),
+        compression: golden.compression,
+        aont: golden.aont,
+        hash: golden.hash,
+        dimension: DIMENSION,
+        block_size: Some(golden.block_size),
+        mac_bits: golden.mac_bits,
+        hint: golden.hints > 0,
+        magicless: golden.passphrase.is_some(),
+        passphrase: golden.passphrase.map(String::from),
+        merkle: golden.merkle,
+        shuffle: golden.shuffle,
+        reshuffle: Some(golden.reshuffle),
+        kdf: golden.kdf,
+        ..Default::default()
+    }
+}
+
+fn check_golden(path: &Path, golden: &Golden) -> Result<(), Box<dyn Error>> {
+    let header = read_vhc_header(path)?;
+    assert_eq!(header.version, golden.version);
+    assert_eq!(header.cube_id, DIMENSION);
+    assert_eq!(header.dimension, DIMENSION);
+    assert_eq!(header.blocks_per_partition, DIMENSION);
+    assert_eq!(header.block_size, golden.block_size);
+    assert_eq!(header.mac_bits, golden.mac_bits);
+    assert_eq!(header.aont, golden.aont);
+    assert_eq!(header.hash, golden.hash);
+    assert_eq!(header.compression, golden.compression);
+    assert_eq!(header.shuffle, golden.shuffle);
+    assert_eq!(header.reshuffle, golden.reshuffle);
+    assert_eq!(header.merkle, golden.merkle);
+    assert_eq!(header.hints.len(), golden.hints);
+    assert_eq!(header.kdf.map(|params| params.kdf), golden.kdf);
+    assert_eq!(header.uuid.is_some(), golden.uuid);
+    if let Some(generation) = golden.generation {
+        assert_eq!(header.generation, generation);
+    }
+    assert_eq!(
+        matches!(read_vhc_layout(path)?, VhcLayout::Keyed { .. }),
+        golden.passphrase.is_some()
+    );
+
+    // The header re-serializes to the exact bytes on disk, length prefix included,
+    // in the encoding of its version
+    let bytes = fs::read(path)?;
+    let encoded = header.to_bytes()?;
+    let data_start = vhc_data_offset(path)? as usize;
+    let header_start = data_start - encoded.len();
+    assert_eq!(&bytes[header_start..data_start], &encoded[..]);
+    assert_eq!(
+        bytes[header_start - 4..header_start],
+        (encoded.len() as u32).to_le_bytes()
+    );
+    assert_eq!(
+        bytes[header_start] == b'{',
+        golden.version < BINARY_HEADER_VERSION
+    );
+
+    // Blocks and trailer account for every remaining byte
+    let blocks = get_block_count(path)?;
+    assert_eq!(blocks, golden.blocks);
+    let trailer = read_vhc_trailer(path)?;
+    assert_eq!(trailer.is_some(), golden.version >= 2);
+    let trailer_len = match trailer {
+        Some(_) => {
+            let total = u32::from_le_bytes(bytes[bytes.len() - 4..].try_into()?);
+            total as usize + 4
+        }
+        None => 0,
+    };
+    assert_eq!(
+        bytes.len(),
+        data_start + blocks * header.total_block_size() + trailer_len
+    );
+    if golden.merkle {
+        assert!(corrupted_blocks(path)?.is_empty());
+    }
+
+    // Every partition extracts to its payload; a foreign secret finds nothing
+    let dir = tempdir()?;
+    for secret in golden.secrets {
+        let output = dir.path().join(secret);
+        let options = ExtractOptions {
+            secret: secret.to_string(),
+            passphrase: golden.passphrase.map(String::from),
+            ..Default::default()
+        };
+        extract_from_vhc(path, &output, &options)?;
+        assert_eq!(fs::read(&output)?, payload(secret));
+    }
+    let options = ExtractOptions {
+        secret: "gamma".into(),
+        passphrase: golden.passphrase.map(String::from),
+        ..Default::default()
+    };
+    assert!(extract_from_vhc(path, &dir.path().join("gamma"), &options).is_err());
+    Ok(())
+}
+
+#[test]
+fn golden_sets_read_identically() -> Result<(), Box<dyn Error>> {
+    let mut sets = Vec::new();
+    for entry in fs::read_dir(golden_root())? {
+        let set = entry?.path();
+        for golden in GOLDEN {
+            let path = set.join(format!("{}.vhc", golden.name));
+            // Sets recorded since a format stopped being written lack its golden
+            if !writable(golden) && !path.exists() {
+                continue;
+            }
+            check_golden(&path, golden).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        sets.push(set);
+    }
+    // At least the 64- and 32-bit little-endian sets and a big-endian one are checked in
+    assert!(sets.len() >= 3, "missing golden sets: {:?}", sets);
+    assert!(
+        sets.iter().any(|set| set.to_string_lossy().ends_with("-be")),
+        "missing a big-endian golden set: {:?}",
+        sets
+    );
+    Ok(())
+}
+
+#[test]
+fn oversized_header_values_are_rejected() {
+    // Too wide for a 32-bit usize, and far too large for any block anyway
+    let header = VhcHeader::new(8, 8, 8, 64, 256).unwrap();
+    let json = String::from_utf8(header.to_json().unwrap()).unwrap();
+    let json = json.replace(r#""block_size":64"#, r#""block_size":1099511627776"#);
+    assert!(VhcHeader::from_bytes(json.as_bytes()).is_err());
+}
+
+/// Write this target's golden set; run on each new target and check in the result
+/// Files already recorded are only checked; delete one to record it again
+#[test]
+#[ignore]
+fn write_golden_set() -> Result<(), Box<dyn Error>> {
+    let set = golden_root().join(target_set());
+    fs::create_dir_all(&set)?;
+    let dir = tempdir()?;
+    for golden in GOLDEN {
+        let path = set.join(format!("{}.vhc", golden.name));
+        if path.exists() {
+            check_golden(&path, golden)?;
+            continue;
+        }
+        if !writable(golden) {
+            continue;
+        }
+        if golden.version < LATEST_VERSION {
+            write_vhc_file(&path, &legacy_container(golden)?)?;
+        }
+        for secret in golden.secrets {
+            let input = dir.path().join(secret);
+            fs::write(&input, payload(secret))?;
+            add_partition(&input, &path, &add_options(golden, secret))?;
+        }
+        check_golden(&path, golden)?;
+    }
+    Ok(())
+}